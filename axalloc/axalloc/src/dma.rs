@@ -0,0 +1,85 @@
+//! Memory for DMA.
+//!
+//! Coherent memory is shared by the CPU and a device for as long as it is
+//! allocated, as descriptor rings are: [`dma_alloc_coherent`] returns
+//! zeroed, physically contiguous pages aligned to the power of two of
+//! their size, with the address the CPU uses and the one the device does.
+//! Other buffers are lent to a device for one transfer with
+//! [`dma_map_single`] and [`dma_unmap_single`].
+//!
+//! The platforms supported snoop DMA into the caches, so the linear
+//! mapping serves for coherent memory, and syncing a buffer only has to
+//! order the accesses of the CPU against those of the device.
+
+use core::sync::atomic::{fence, Ordering};
+use axerrno::AxResult;
+use axhal::mem::virt_to_phys;
+use memory_addr::{PhysAddr, VirtAddr};
+
+use crate::page::alloc_err_to_ax_err;
+use crate::{global_allocator, PAGE_SIZE};
+
+/// Which way data moves in a transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// From memory to the device.
+    ToDevice,
+    /// From the device to memory.
+    FromDevice,
+    Bidirectional,
+}
+
+fn dma_pages(size: usize) -> usize {
+    size.div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Allocates `size` bytes of memory coherent between the CPU and devices.
+/// Returns its virtual address, for the CPU, and its physical one, for
+/// devices.
+pub fn dma_alloc_coherent(size: usize) -> AxResult<(VirtAddr, PhysAddr)> {
+    let num_pages = dma_pages(size);
+    let align = num_pages.next_power_of_two() * PAGE_SIZE;
+    let vaddr = global_allocator()
+        .alloc_pages(num_pages, align)
+        .map_err(alloc_err_to_ax_err)?;
+    unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, num_pages * PAGE_SIZE) };
+    let vaddr = VirtAddr::from(vaddr);
+    Ok((vaddr, virt_to_phys(vaddr)))
+}
+
+/// Frees memory from [`dma_alloc_coherent`] of the same `size`.
+///
+/// # Safety
+///
+/// No device may access it any more.
+pub unsafe fn dma_free_coherent(vaddr: VirtAddr, size: usize) {
+    global_allocator().dealloc_pages(vaddr.as_usize(), dma_pages(size));
+}
+
+/// Lends the buffer at `vaddr` to a device for a transfer, and returns the
+/// address the device sees it at. The buffer must be in the linear
+/// mapping, as the heap is, and the CPU must not touch it until
+/// [`dma_unmap_single`].
+pub fn dma_map_single(vaddr: VirtAddr, size: usize, dir: DmaDirection) -> PhysAddr {
+    dma_sync_for_device(vaddr, size, dir);
+    virt_to_phys(vaddr)
+}
+
+/// Takes back a buffer from [`dma_map_single`] once the transfer is done.
+pub fn dma_unmap_single(vaddr: VirtAddr, size: usize, dir: DmaDirection) {
+    dma_sync_for_cpu(vaddr, size, dir);
+}
+
+/// Makes what the CPU wrote to the buffer visible to the device.
+pub fn dma_sync_for_device(_vaddr: VirtAddr, _size: usize, dir: DmaDirection) {
+    if dir != DmaDirection::FromDevice {
+        fence(Ordering::SeqCst);
+    }
+}
+
+/// Makes what the device wrote to the buffer visible to the CPU.
+pub fn dma_sync_for_cpu(_vaddr: VirtAddr, _size: usize, dir: DmaDirection) {
+    if dir != DmaDirection::ToDevice {
+        fence(Ordering::SeqCst);
+    }
+}
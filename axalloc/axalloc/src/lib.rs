@@ -11,6 +11,7 @@
 extern crate log;
 extern crate alloc;
 
+mod dma;
 mod page;
 
 use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
@@ -22,6 +23,8 @@ use axhal::mem::{MemRegionFlags, memory_regions, phys_to_virt};
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+pub use dma::{dma_alloc_coherent, dma_free_coherent, DmaDirection};
+pub use dma::{dma_map_single, dma_sync_for_cpu, dma_sync_for_device, dma_unmap_single};
 pub use page::GlobalPage;
 
 cfg_if::cfg_if! {
@@ -98,7 +98,7 @@ impl Drop for GlobalPage {
     }
 }
 
-const fn alloc_err_to_ax_err(e: AllocError) -> AxError {
+pub(crate) const fn alloc_err_to_ax_err(e: AllocError) -> AxError {
     match e {
         AllocError::InvalidParam | AllocError::MemoryOverlap | AllocError::NotAllocated => {
             AxError::InvalidInput
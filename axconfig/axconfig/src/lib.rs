@@ -15,6 +15,8 @@ mod config {
 
 pub use config::*;
 
+pub mod runtime;
+
 /// End address of the whole physical memory.
 pub const PHYS_MEMORY_END: usize = PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE;
 
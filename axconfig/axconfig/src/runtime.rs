@@ -0,0 +1,81 @@
+//! The values of the config that the machine booted on overrides, as its
+//! device tree and the command line tell them, set once at boot before the
+//! memory allocator is up.
+//!
+//! The memory and the CPUs can only be fewer than the config has: the
+//! per-CPU arrays are sized by [`SMP`], and the kernel space above the
+//! linear mapping of the memory, as the module area, is laid out after
+//! [`PHYS_MEMORY_END`]. The kernel base is linked in, so it has no override.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{PHYS_MEMORY_BASE, PHYS_MEMORY_END, PHYS_MEMORY_SIZE, SMP};
+
+/// Zero while not overridden.
+static MEMORY_BASE: AtomicUsize = AtomicUsize::new(0);
+static MEMORY_SIZE: AtomicUsize = AtomicUsize::new(0);
+static NR_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+static FROZEN: AtomicBool = AtomicBool::new(false);
+
+/// Base address of the whole physical memory.
+#[inline]
+pub fn phys_memory_base() -> usize {
+    match MEMORY_BASE.load(Ordering::Acquire) {
+        0 => PHYS_MEMORY_BASE,
+        base => base,
+    }
+}
+
+/// Size of the whole physical memory.
+#[inline]
+pub fn phys_memory_size() -> usize {
+    match MEMORY_SIZE.load(Ordering::Acquire) {
+        0 => PHYS_MEMORY_SIZE,
+        size => size,
+    }
+}
+
+/// End address of the whole physical memory.
+#[inline]
+pub fn phys_memory_end() -> usize {
+    phys_memory_base() + phys_memory_size()
+}
+
+/// Number of CPUs that are brought up, at most [`SMP`].
+#[inline]
+pub fn nr_cpus() -> usize {
+    match NR_CPUS.load(Ordering::Acquire) {
+        0 => SMP,
+        n => n,
+    }
+}
+
+/// Sets the physical memory to `[base, base + size)`, cut at
+/// [`PHYS_MEMORY_END`]. Returns false if it is too late, or if nothing of
+/// it is left.
+pub fn set_phys_memory(base: usize, size: usize) -> bool {
+    let end = base.saturating_add(size).min(PHYS_MEMORY_END);
+    if FROZEN.load(Ordering::Acquire) || base >= end {
+        return false;
+    }
+    MEMORY_BASE.store(base, Ordering::Release);
+    MEMORY_SIZE.store(end - base, Ordering::Release);
+    true
+}
+
+/// Sets the number of CPUs, cut at [`SMP`]. Returns false if it is too
+/// late, or if it is 0.
+pub fn set_nr_cpus(n: usize) -> bool {
+    if FROZEN.load(Ordering::Acquire) || n == 0 {
+        return false;
+    }
+    NR_CPUS.store(n.min(SMP), Ordering::Release);
+    true
+}
+
+/// Keeps the values as they are from now on, once the memory allocator
+/// has taken the memory.
+pub fn freeze() {
+    FROZEN.store(true, Ordering::Release);
+}
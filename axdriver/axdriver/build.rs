@@ -1,6 +1,7 @@
 const NET_DEV_FEATURES: &[&str] = &["ixgbe", "virtio-net"];
 const BLOCK_DEV_FEATURES: &[&str] = &["ramdisk", "bcm2835-sdhci", "virtio-blk"];
 const DISPLAY_DEV_FEATURES: &[&str] = &["virtio-gpu"];
+const CHAR_DEV_FEATURES: &[&str] = &["virtio-console"];
 
 fn make_cfg_values(str_list: &[&str]) -> String {
     str_list
@@ -23,9 +24,11 @@ fn enable_cfg(key: &str, value: &str) {
 }
 
 fn main() {
+    // Both buses may be enabled; without either, PCI is.
     if has_feature("bus-mmio") {
         enable_cfg("bus", "mmio");
-    } else {
+    }
+    if has_feature("bus-pci") || !has_feature("bus-mmio") {
         enable_cfg("bus", "pci");
     }
 
@@ -36,6 +39,7 @@ fn main() {
         ("net", NET_DEV_FEATURES),
         ("block", BLOCK_DEV_FEATURES),
         ("display", DISPLAY_DEV_FEATURES),
+        ("char", CHAR_DEV_FEATURES),
     ] {
         if !has_feature(dev_kind) {
             continue;
@@ -72,4 +76,8 @@ fn main() {
         "cargo::rustc-check-cfg=cfg(display_dev, values({}, \"dummy\"))",
         make_cfg_values(DISPLAY_DEV_FEATURES)
     );
+    println!(
+        "cargo::rustc-check-cfg=cfg(char_dev, values({}, \"dummy\"))",
+        make_cfg_values(CHAR_DEV_FEATURES)
+    );
 }
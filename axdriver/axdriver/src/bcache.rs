@@ -0,0 +1,423 @@
+//! The buffer cache of block devices.
+//!
+//! Blocks read through a [`Partition`](crate::partition::Partition) are
+//! kept here by (disk, block), and writes only go to the cache and mark
+//! the block dirty. Dirty blocks are written back by the flusher thread
+//! once they have been dirty for [`DIRTY_EXPIRE`], by [`sync`], or when
+//! the cache is full and they are the least recently used. The blocks of
+//! a write-back are submitted together, so the request queue merges
+//! those that are contiguous.
+//!
+//! Readahead reads blocks into the cache without waiting for them, so
+//! that they are there by the time they are read.
+//!
+//! Direct I/O, for O_DIRECT, goes to the request queue instead, and only
+//! keeps the cache coherent: the dirty blocks it reads are written back
+//! first, and the blocks it writes are dropped from the cache.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::time::Duration;
+use axhal::time::TimeValue;
+use spinbase::SpinNoIrq;
+
+use crate::prelude::*;
+use crate::queue::{Bio, BioOp, RequestQueue};
+
+/// Blocks cached at most.
+const MAX_BUFFERS: usize = 8192;
+/// How long a block may stay dirty before the flusher writes it back.
+const DIRTY_EXPIRE: Duration = Duration::from_secs(30);
+/// How often the flusher wakes up.
+const WRITEBACK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A disk, by its request queue, and a block on it.
+type BufferKey = (usize, u64);
+
+fn disk_id(queue: &Arc<RequestQueue>) -> usize {
+    Arc::as_ptr(queue) as usize
+}
+
+struct Buffer {
+    queue: Arc<RequestQueue>,
+    data: Vec<u8>,
+    /// When it was dirtied, if it is dirty.
+    dirty_since: Option<TimeValue>,
+    /// Key in the LRU list.
+    last_used: u64,
+}
+
+struct BufferCache {
+    buffers: BTreeMap<BufferKey, Buffer>,
+    /// Keys by when they were last used.
+    lru: BTreeMap<u64, BufferKey>,
+    clock: u64,
+}
+
+impl BufferCache {
+    const fn new() -> Self {
+        Self {
+            buffers: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn touch(&mut self, key: BufferKey) -> Option<&mut Buffer> {
+        let buf = self.buffers.get_mut(&key)?;
+        self.lru.remove(&buf.last_used);
+        self.clock += 1;
+        buf.last_used = self.clock;
+        self.lru.insert(self.clock, key);
+        Some(buf)
+    }
+
+    fn insert(&mut self, key: BufferKey, buf: Buffer) {
+        self.clock += 1;
+        self.lru.insert(self.clock, key);
+        self.buffers.insert(key, Buffer {
+            last_used: self.clock,
+            ..buf
+        });
+    }
+
+    /// Takes the least recently used clean buffer, or else the least
+    /// recently used one, which the caller has to write back.
+    fn evict(&mut self) -> Option<(BufferKey, Buffer)> {
+        let key = self.lru_clean()
+            .or_else(|| self.lru.values().next().copied())?;
+        Some((key, self.remove(key)))
+    }
+
+    /// The least recently used clean buffer.
+    fn lru_clean(&self) -> Option<BufferKey> {
+        self.lru.values()
+            .find(|key| self.buffers[*key].dirty_since.is_none())
+            .copied()
+    }
+
+    fn remove(&mut self, key: BufferKey) -> Buffer {
+        let buf = self.buffers.remove(&key).unwrap();
+        self.lru.remove(&buf.last_used);
+        buf
+    }
+}
+
+static BCACHE: SpinNoIrq<BufferCache> = SpinNoIrq::new(BufferCache::new());
+
+/// Caches `data` of `block` on `queue`, unless it is cached already.
+fn insert(queue: &Arc<RequestQueue>, block: u64, data: Vec<u8>, dirty: Option<TimeValue>) {
+    let key = (disk_id(queue), block);
+    let victim = {
+        let mut cache = BCACHE.lock();
+        if let Some(buf) = cache.touch(key) {
+            if let Some(now) = dirty {
+                buf.data = data;
+                buf.dirty_since.get_or_insert(now);
+            }
+            return;
+        }
+        let victim = if cache.buffers.len() >= MAX_BUFFERS {
+            cache.evict()
+        } else {
+            None
+        };
+        cache.insert(key, Buffer {
+            queue: queue.clone(),
+            data,
+            dirty_since: dirty,
+            last_used: 0,
+        });
+        victim
+    };
+    if let Some(((_, block), victim)) = victim {
+        if victim.dirty_since.is_some() {
+            if let Err(e) = victim.queue.submit_bio_wait(BioOp::Write, block, victim.data).0 {
+                error!("bcache: lost block {} on write-back: {:?}", block, e);
+            }
+        }
+    }
+}
+
+/// Caches `data` of `block` on `queue` as it was read ahead, unless it is
+/// cached already. As it may be in the interrupt handler, it doesn't write
+/// back a dirty block to make room, and drops `data` rather.
+fn insert_readahead(queue: &Arc<RequestQueue>, block: u64, data: Vec<u8>) {
+    let key = (disk_id(queue), block);
+    let mut cache = BCACHE.lock();
+    if cache.buffers.contains_key(&key) {
+        return;
+    }
+    if cache.buffers.len() >= MAX_BUFFERS {
+        let Some(victim) = cache.lru_clean() else {
+            return;
+        };
+        cache.remove(victim);
+    }
+    cache.insert(key, Buffer {
+        queue: queue.clone(),
+        data,
+        dirty_since: None,
+        last_used: 0,
+    });
+}
+
+/// Starts reading the `count` blocks from `block` on `queue` into the
+/// cache, and returns without waiting for them. Those cached already are
+/// skipped, and the others are read in runs of contiguous blocks.
+pub fn readahead(queue: &Arc<RequestQueue>, block: u64, count: u64) {
+    let id = disk_id(queue);
+    let end = min(block + count, queue.num_blocks());
+    let mut runs: Vec<(u64, u64)> = Vec::new();
+    {
+        let cache = BCACHE.lock();
+        for b in block..end {
+            if cache.buffers.contains_key(&(id, b)) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == b => *len += 1,
+                _ => runs.push((b, 1)),
+            }
+        }
+    }
+    if runs.is_empty() {
+        return;
+    }
+    trace!("bcache: readahead {} blocks from {}", end - block, block);
+
+    let bs = queue.block_size();
+    let bios = runs.into_iter().map(|(start, len)| {
+        let q = queue.clone();
+        Bio::read(start, len as usize, bs, Box::new(move |result, data: Vec<u8>| {
+            if result.is_err() {
+                // Only a hint: the blocks are read again when they are needed.
+                return;
+            }
+            for (i, chunk) in data.chunks(bs).enumerate() {
+                insert_readahead(&q, start + i as u64, chunk.into());
+            }
+        }))
+    });
+    queue.submit_bios(bios);
+}
+
+/// Reads blocks from `block` on `queue` into `buf`, a whole number of
+/// blocks, from the cache as far as it has them. Those it has not are
+/// read at once, from the first to the last.
+pub fn read(queue: &Arc<RequestQueue>, block: u64, buf: &mut [u8]) -> DevResult {
+    let bs = queue.block_size();
+    if buf.len() % bs != 0 {
+        return Err(DevError::InvalidParam);
+    }
+    let id = disk_id(queue);
+    let mut misses = Vec::new();
+    {
+        let mut cache = BCACHE.lock();
+        for (i, chunk) in buf.chunks_mut(bs).enumerate() {
+            match cache.touch((id, block + i as u64)) {
+                Some(cached) => chunk.copy_from_slice(&cached.data),
+                None => misses.push(i),
+            }
+        }
+    }
+    let (Some(&first), Some(&last)) = (misses.first(), misses.last()) else {
+        return Ok(());
+    };
+
+    let len = (last - first + 1) * bs;
+    let (result, data) = queue.submit_bio_wait(BioOp::Read, block + first as u64, vec![0; len]);
+    result?;
+    for i in misses {
+        let chunk = &data[(i - first) * bs..(i - first + 1) * bs];
+        let key = (id, block + i as u64);
+        // Written meanwhile, which is newer than what was read.
+        if let Some(cached) = BCACHE.lock().touch(key) {
+            buf[i * bs..(i + 1) * bs].copy_from_slice(&cached.data);
+            continue;
+        }
+        buf[i * bs..(i + 1) * bs].copy_from_slice(chunk);
+        insert(queue, key.1, chunk.into(), None);
+    }
+    Ok(())
+}
+
+/// Writes `buf`, a whole number of blocks, to `block` on `queue`, in the
+/// cache only.
+pub fn write(queue: &Arc<RequestQueue>, block: u64, buf: &[u8]) -> DevResult {
+    let bs = queue.block_size();
+    if buf.len() % bs != 0 {
+        return Err(DevError::InvalidParam);
+    }
+    let now = axhal::time::current_time();
+    for (i, chunk) in buf.chunks(bs).enumerate() {
+        insert(queue, block + i as u64, chunk.into(), Some(now));
+    }
+    Ok(())
+}
+
+/// Reads blocks from `block` on `queue` into `buf`, a whole number of
+/// blocks, from the disk and not the cache, which is left as it is.
+pub fn read_direct(queue: &Arc<RequestQueue>, block: u64, buf: &mut [u8]) -> DevResult {
+    let bs = queue.block_size();
+    if buf.len() % bs != 0 {
+        return Err(DevError::InvalidParam);
+    }
+    writeback_range(queue, block, (buf.len() / bs) as u64)?;
+    let (result, data) = queue.submit_bio_wait(BioOp::Read, block, vec![0; buf.len()]);
+    result?;
+    buf.copy_from_slice(&data);
+    Ok(())
+}
+
+/// Writes `buf`, a whole number of blocks, to `block` on `queue`, on the
+/// disk at once. The cached copies of the blocks are dropped, dirty or not,
+/// as they are older.
+pub fn write_direct(queue: &Arc<RequestQueue>, block: u64, buf: &[u8]) -> DevResult {
+    let bs = queue.block_size();
+    if buf.len() % bs != 0 {
+        return Err(DevError::InvalidParam);
+    }
+    queue.submit_bio_wait(BioOp::Write, block, buf.into()).0?;
+    let id = disk_id(queue);
+    let mut cache = BCACHE.lock();
+    for i in 0..(buf.len() / bs) as u64 {
+        if let Some(cached) = cache.buffers.remove(&(id, block + i)) {
+            cache.lru.remove(&cached.last_used);
+        }
+    }
+    Ok(())
+}
+
+/// Writes back the dirty blocks of `queue` among the `count` ones from
+/// `block`, for a direct read of them to get what was written.
+fn writeback_range(queue: &Arc<RequestQueue>, block: u64, count: u64) -> DevResult {
+    let id = disk_id(queue);
+    let bios: Vec<(BioOp, u64, Vec<u8>)> = {
+        let mut cache = BCACHE.lock();
+        cache.buffers.range_mut((id, block)..(id, block + count))
+            .filter(|(_, buf)| buf.dirty_since.is_some())
+            .map(|(key, buf)| {
+                buf.dirty_since = None;
+                (BioOp::Write, key.1, buf.data.clone())
+            })
+            .collect()
+    };
+    if bios.is_empty() {
+        return Ok(());
+    }
+    let now = axhal::time::current_time();
+    let mut ret = Ok(());
+    let blocks: Vec<u64> = bios.iter().map(|(_, block, _)| *block).collect();
+    for (block, (result, _)) in blocks.into_iter().zip(queue.submit_bios_wait(bios)) {
+        if let Err(e) = result {
+            error!("bcache: write-back of block {} failed: {:?}", block, e);
+            if let Some(buf) = BCACHE.lock().buffers.get_mut(&(id, block)) {
+                buf.dirty_since.get_or_insert(now);
+            }
+            ret = Err(e);
+        }
+    }
+    ret
+}
+
+/// Writes back the blocks dirtied before `before`, those on `queue` only
+/// if it is given.
+fn writeback(queue: Option<&Arc<RequestQueue>>, before: TimeValue) -> DevResult {
+    let mut dirty: Vec<(BufferKey, Arc<RequestQueue>, Vec<u8>)> = Vec::new();
+    {
+        let mut cache = BCACHE.lock();
+        for (key, buf) in cache.buffers.iter_mut() {
+            if queue.map_or(false, |q| disk_id(q) != key.0) {
+                continue;
+            }
+            if buf.dirty_since.map_or(false, |t| t <= before) {
+                // Dirtied again while it is written, it is written again.
+                buf.dirty_since = None;
+                dirty.push((*key, buf.queue.clone(), buf.data.clone()));
+            }
+        }
+    }
+    if dirty.is_empty() {
+        return Ok(());
+    }
+    debug!("bcache: writing back {} blocks", dirty.len());
+
+    let mut ret = Ok(());
+    let mut queues: Vec<Arc<RequestQueue>> = Vec::new();
+    for (_, q, _) in dirty.iter() {
+        if !queues.iter().any(|known| Arc::ptr_eq(known, q)) {
+            queues.push(q.clone());
+        }
+    }
+    for q in queues {
+        let (keys, bios): (Vec<_>, Vec<_>) = dirty.iter()
+            .filter(|(_, bq, _)| Arc::ptr_eq(bq, &q))
+            .map(|(key, _, data)| (*key, (BioOp::Write, key.1, data.clone())))
+            .unzip();
+        for (key, (result, _)) in keys.into_iter().zip(q.submit_bios_wait(bios)) {
+            if let Err(e) = result {
+                error!("bcache: write-back of block {} failed: {:?}", key.1, e);
+                // Keep it dirty for the next try.
+                if let Some(buf) = BCACHE.lock().buffers.get_mut(&key) {
+                    buf.dirty_since.get_or_insert(before);
+                }
+                ret = Err(e);
+            }
+        }
+    }
+    ret
+}
+
+/// Writes back all dirty blocks of `queue`, and flushes the disk.
+pub fn sync_disk(queue: &Arc<RequestQueue>) -> DevResult {
+    writeback(Some(queue), axhal::time::current_time())?;
+    queue.flush()
+}
+
+/// Writes back all dirty blocks, and flushes the disks.
+pub fn sync() -> DevResult {
+    writeback(None, axhal::time::current_time())?;
+    let mut queues: Vec<Arc<RequestQueue>> = Vec::new();
+    for buf in BCACHE.lock().buffers.values() {
+        if !queues.iter().any(|known| Arc::ptr_eq(known, &buf.queue)) {
+            queues.push(buf.queue.clone());
+        }
+    }
+    queues.iter().try_for_each(|q| q.flush())
+}
+
+/// Drops the clean blocks of `queue` from the cache, so they are read
+/// again, after the disk has changed under it.
+pub fn invalidate(queue: &Arc<RequestQueue>) {
+    let id = disk_id(queue);
+    let mut cache = BCACHE.lock();
+    let keys: Vec<_> = cache.buffers.iter()
+        .filter(|(key, buf)| key.0 == id && buf.dirty_since.is_none())
+        .map(|(key, buf)| (*key, buf.last_used))
+        .collect();
+    for (key, last_used) in keys {
+        cache.buffers.remove(&key);
+        cache.lru.remove(&last_used);
+    }
+}
+
+/// The flusher thread: every [`WRITEBACK_INTERVAL`], writes back the
+/// blocks which have been dirty for [`DIRTY_EXPIRE`].
+pub fn flusher_loop() -> ! {
+    info!("bcache: flusher started");
+    loop {
+        run_queue::sleep(WRITEBACK_INTERVAL);
+        let now = axhal::time::current_time();
+        if now < DIRTY_EXPIRE {
+            continue;
+        }
+        if let Err(e) = writeback(None, now - DIRTY_EXPIRE) {
+            warn!("bcache: periodic write-back: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! RAM disks on memory that the boot loader or the firmware set aside.
+//!
+//! The device tree tells the regions: the initrd image the boot loader
+//! loaded, by the `linux,initrd-start` and `linux,initrd-end` properties of
+//! `/chosen`, and the children of `/reserved-memory` compatible with
+//! "brd". Each is registered by [`add_disks`] as `ram0`, `ram1`... with its
+//! partitions, so a filesystem can be mounted from it when no disk is
+//! attached.
+//!
+//! A region must be in the linear mapping, and is skipped if it overlaps
+//! the free memory, which the page allocator hands out.
+
+use alloc::format;
+use alloc::vec::Vec;
+use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
+use driver_block::brd::Brd;
+
+/// Registers a RAM disk on each region the device tree at `dtb_pa` sets
+/// aside for one. Returns how many there are.
+pub fn add_disks(dtb_pa: usize) -> usize {
+    let mut index = 0;
+    for (start, size) in find_regions(dtb_pa) {
+        if size == 0 {
+            continue;
+        }
+        if overlaps_free_memory(start, size) {
+            warn!("brd: [{:#x}, {:#x}) is free memory, skipped", start, start + size);
+            continue;
+        }
+        while crate::partition::lookup_partition(&format!("ram{}", index)).is_some() {
+            index += 1;
+        }
+        let name = format!("ram{}", index);
+        info!("brd: {} on [{:#x}, {:#x})", name, start, start + size);
+        let base: usize = phys_to_virt(start.into()).into();
+        let dev = unsafe { Brd::new(base as *mut u8, size) };
+        crate::partition::add_disk(&name, dev, None);
+        index += 1;
+    }
+    index
+}
+
+fn overlaps_free_memory(start: usize, size: usize) -> bool {
+    memory_regions()
+        .filter(|r| r.flags.contains(MemRegionFlags::FREE))
+        .any(|r| start < r.paddr.as_usize() + r.size && r.paddr.as_usize() < start + size)
+}
+
+/// Returns (start, size) of the regions for RAM disks.
+#[cfg(target_arch = "riscv64")]
+fn find_regions(dtb_pa: usize) -> Vec<(usize, usize)> {
+    use alloc::string::String;
+    use axdtb::read_cells;
+
+    let mut regions = Vec::new();
+    let mut cb = |name: String,
+                  addr_cells: usize,
+                  size_cells: usize,
+                  props: Vec<(String, Vec<u8>)>| {
+        let prop = |key: &str| props.iter().find(|p| p.0 == key).map(|p| p.1.as_slice());
+        if name == "chosen" {
+            // Either one or two cells, as the boot loader chose.
+            let cell = |key| prop(key).and_then(|v| read_cells(v, 0, v.len() / 4));
+            if let (Some(start), Some(end)) = (cell("linux,initrd-start"), cell("linux,initrd-end")) {
+                if end > start {
+                    regions.push((start, end - start));
+                }
+            }
+            return;
+        }
+        let Some(compatible) = prop("compatible") else {
+            return;
+        };
+        if !compatible.split(|c| *c == 0).any(|s| s == b"brd") {
+            return;
+        }
+        if let Some(reg) = prop("reg") {
+            if let (Some(start), Some(size)) = (
+                read_cells(reg, 0, addr_cells),
+                read_cells(reg, addr_cells, size_cells),
+            ) {
+                regions.push((start, size));
+            }
+        }
+    };
+    axdtb::parse(phys_to_virt(dtb_pa.into()).into(), &mut cb);
+    regions
+}
+
+#[cfg(not(target_arch = "riscv64"))]
+fn find_regions(_dtb_pa: usize) -> Vec<(usize, usize)> {
+    Vec::new()
+}
@@ -1,23 +1,88 @@
-#[allow(unused_imports)]
-use crate::{prelude::*, AllDevices};
+use alloc::vec::Vec;
+use page_table::ioremap::IoMem;
+use spinbase::SpinNoIrq;
+
+use crate::AllDevices;
+#[cfg(feature = "virtio")]
+use crate::{dtb::DtbNode, prelude::*, AxDeviceEnum};
+#[cfg(feature = "virtio")]
+use page_table::ioremap::{ioremap, iounmap};
+
+/// The VirtIO MMIO regions with a device, which are reset at shutdown.
+static VIRTIO_MMIO_REGIONS: SpinNoIrq<Vec<IoMem>> = SpinNoIrq::new(Vec::new());
+
+/// Takes a VirtIO MMIO slot of the device tree, if it has a device.
+#[cfg(feature = "virtio")]
+pub(crate) fn probe_virtio_mmio(node: &DtbNode) -> DevResult<Option<AxDeviceEnum>> {
+    let (base, size) = node.reg().ok_or(DevError::InvalidParam)?;
+    let mmio = ioremap(base, size).map_err(|_| DevError::NoMemory)?;
+    for_each_drivers!(type Driver, {
+        if let Some(dev) = Driver::probe_mmio(&mmio) {
+            VIRTIO_MMIO_REGIONS.lock().push(mmio);
+            return Ok(Some(dev));
+        }
+    });
+    // An empty slot, or a device without a driver.
+    iounmap(mmio);
+    Err(DevError::Unsupported)
+}
+
+/// The IRQ line of the `i`-th VirtIO MMIO slot, as QEMU wires them up.
+#[cfg(feature = "virtio")]
+fn virtio_mmio_irq(i: usize) -> Option<usize> {
+    if cfg!(target_arch = "riscv64") {
+        Some(1 + i)
+    } else if cfg!(target_arch = "aarch64") {
+        // From SPI 16.
+        Some(32 + 16 + i)
+    } else {
+        None
+    }
+}
 
 impl AllDevices {
-    pub(crate) fn probe_bus_devices(&mut self) {
-        // TODO: parse device tree
+    /// Probes the VirtIO MMIO slots of the platform config, for the boards
+    /// without a device tree. Those of a device tree matched "virtio,mmio".
+    pub(crate) fn probe_mmio_devices(&mut self, dtb_walked: bool) {
+        if dtb_walked {
+            return;
+        }
         #[cfg(feature = "virtio")]
-        for reg in axconfig::VIRTIO_MMIO_REGIONS {
+        for (i, reg) in axconfig::VIRTIO_MMIO_REGIONS.iter().enumerate() {
+            let Ok(mmio) = ioremap(reg.0, reg.1) else {
+                warn!("cannot map VirtIO MMIO slot at {:#x}", reg.0);
+                continue;
+            };
             for_each_drivers!(type Driver, {
-                if let Some(dev) = Driver::probe_mmio(reg.0, reg.1) {
+                if let Some(dev) = Driver::probe_mmio(&mmio) {
                     info!(
                         "registered a new {:?} device at [PA:{:#x}, PA:{:#x}): {:?}",
                         dev.device_type(),
                         reg.0, reg.0 + reg.1,
                         dev.device_name(),
                     );
+                    VIRTIO_MMIO_REGIONS.lock().push(mmio);
+                    self.set_irq(&dev, virtio_mmio_irq(i));
                     self.add_device(dev);
                     continue; // skip to the next device
                 }
             });
+            iounmap(mmio);
+        }
+    }
+}
+
+/// Resets the VirtIO devices of the MMIO slots found at probe, which stops
+/// their DMA and their interrupts, whoever owns them.
+pub(crate) fn shutdown() {
+    const MAGIC: u32 = 0x7472_6976; // "virt"
+    const REG_MAGIC: usize = 0x00;
+    const REG_DEVICE_ID: usize = 0x08;
+    const REG_STATUS: usize = 0x70;
+    for mmio in VIRTIO_MMIO_REGIONS.lock().iter() {
+        let reg = |off: usize| mmio.reg::<u32>(off);
+        if reg(REG_MAGIC).read() == MAGIC && reg(REG_DEVICE_ID).read() != 0 {
+            reg(REG_STATUS).write(0);
         }
     }
 }
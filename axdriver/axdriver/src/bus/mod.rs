@@ -1,4 +1,10 @@
 #[cfg(bus = "mmio")]
-mod mmio;
+pub(crate) mod mmio;
 #[cfg(bus = "pci")]
-mod pci;
+pub(crate) mod pci;
+
+#[cfg(bus = "mmio")]
+pub(crate) use mmio::shutdown;
+
+#[cfg(not(bus = "mmio"))]
+pub(crate) fn shutdown() {}
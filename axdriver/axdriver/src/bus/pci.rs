@@ -1,11 +1,142 @@
-use crate::{prelude::*, AllDevices};
-use axhal::mem::phys_to_virt;
+//! The PCI bus, behind the host bridges of the device tree compatible with
+//! "pci-host-ecam-generic", or else the one of the platform config.
+//!
+//! Each function of the buses is enabled, with its memory BARs assigned
+//! from the 32-bit memory window of its bridge, and probed by the drivers.
+//! Its interrupt is an MSI on the interrupt controllers that take them,
+//! or else its INTx line, routed by the `interrupt-map` of the bridge.
+
+use alloc::vec::Vec;
+use spinbase::SpinNoIrq;
+
+use crate::dtb::{self, DtbNode};
+use crate::{prelude::*, AllDevices, AxDeviceEnum};
+use page_table::ioremap::ioremap;
 use driver_pci::{
     BarInfo, Cam, Command, DeviceFunction, HeaderType, MemoryBarType, PciRangeAllocator, PciRoot,
 };
 
 const PCI_BAR_NUM: u8 = 6;
 
+/// Offset in the config space of the dword with the interrupt pin.
+const PCI_INTERRUPT_LINE: u8 = 0x3c;
+
+/// A PCI host bridge.
+struct PciHost {
+    /// Physical address of the ECAM space of the first bus.
+    ecam_base: usize,
+    bus_start: u8,
+    bus_end: u8,
+    /// (base, size) of the 32-bit memory window.
+    mem32: Option<(usize, usize)>,
+    /// The `interrupt-map` and `interrupt-map-mask` cells.
+    interrupt_map: Vec<u32>,
+    interrupt_map_mask: [u32; 4],
+}
+
+static PCI_HOSTS: SpinNoIrq<Vec<PciHost>> = SpinNoIrq::new(Vec::new());
+
+fn cells(buf: &[u8]) -> Vec<u32> {
+    buf.chunks_exact(4)
+        .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+        .collect()
+}
+
+fn join_cells(cells: &[u32]) -> usize {
+    cells.iter().fold(0, |acc, cell| (acc << 32) | *cell as usize)
+}
+
+/// Takes a host bridge of the device tree, whose buses are probed after the
+/// walk of the tree.
+pub(crate) fn probe_host(node: &DtbNode) -> DevResult<Option<AxDeviceEnum>> {
+    let (ecam_base, _) = node.reg().ok_or(DevError::InvalidParam)?;
+    let (bus_start, bus_end) = match node.prop("bus-range").map(cells).as_deref() {
+        Some(&[start, end]) => (start as u8, end as u8),
+        _ => (0, 0xff),
+    };
+
+    // A range is the PCI address, phys.hi first, the CPU address, and the
+    // size. Bits 24-25 of phys.hi tell the space, 2 for 32-bit memory.
+    let child_cells = node.prop_u32("#address-cells").unwrap_or(3) as usize;
+    let size_cells = node.prop_u32("#size-cells").unwrap_or(2) as usize;
+    let entry = child_cells + node.addr_cells + size_cells;
+    let mut mem32 = None;
+    for range in node.prop("ranges").map(cells).unwrap_or_default().chunks_exact(entry) {
+        if (range[0] >> 24) & 0x3 == 2 {
+            let base = join_cells(&range[child_cells..child_cells + node.addr_cells]);
+            let size = join_cells(&range[child_cells + node.addr_cells..]);
+            mem32 = Some((base, size));
+        }
+    }
+
+    let mut interrupt_map_mask = [0; 4];
+    if let Some(mask) = node.prop("interrupt-map-mask") {
+        for (i, cell) in cells(mask).into_iter().take(4).enumerate() {
+            interrupt_map_mask[i] = cell;
+        }
+    }
+    info!(
+        "PCI host {}: ECAM at {:#x}, buses {}-{}, memory {:#x?}",
+        node.name, ecam_base, bus_start, bus_end, mem32
+    );
+    PCI_HOSTS.lock().push(PciHost {
+        ecam_base,
+        bus_start,
+        bus_end,
+        mem32,
+        interrupt_map: node.prop("interrupt-map").map(cells).unwrap_or_default(),
+        interrupt_map_mask,
+    });
+    Ok(None)
+}
+
+/// The host bridge of the platform config, for the boards without a
+/// device tree.
+fn config_host() -> Option<PciHost> {
+    if axconfig::PCI_ECAM_BASE == 0 {
+        return None;
+    }
+    Some(PciHost {
+        ecam_base: axconfig::PCI_ECAM_BASE,
+        bus_start: 0,
+        bus_end: axconfig::PCI_BUS_END as u8,
+        mem32: axconfig::PCI_RANGES.get(1).copied(),
+        interrupt_map: Vec::new(),
+        interrupt_map_mask: [0; 4],
+    })
+}
+
+impl PciHost {
+    /// The IRQ that the INTx line of the function at `bdf` is routed to.
+    fn intx_irq(&self, root: &PciRoot, bdf: DeviceFunction) -> Option<usize> {
+        let pin = (root.config_read_word(bdf, PCI_INTERRUPT_LINE) >> 8) & 0xff;
+        if pin == 0 {
+            return None;
+        }
+        let child = [
+            (bdf.bus as u32) << 16 | (bdf.device as u32) << 11 | (bdf.function as u32) << 8,
+            0,
+            0,
+            pin,
+        ];
+        let mask = self.interrupt_map_mask;
+
+        // Each entry is the child unit address and pin, the phandle of the
+        // interrupt controller, and its parent unit address and interrupt.
+        let mut map = &self.interrupt_map[..];
+        while map.len() > 5 {
+            let (parent_addr_cells, parent_irq_cells) = dtb::interrupt_cells(map[4])?;
+            let len = 5 + parent_addr_cells + parent_irq_cells;
+            let entry = map.get(..len)?;
+            if (0..4).all(|i| child[i] & mask[i] == entry[i] & mask[i]) {
+                return Some(dtb::parent_irq(&entry[5 + parent_addr_cells..]));
+            }
+            map = &map[len..];
+        }
+        None
+    }
+}
+
 fn config_pci_device(
     root: &mut PciRoot,
     bdf: DeviceFunction,
@@ -82,17 +213,49 @@ fn config_pci_device(
     Ok(())
 }
 
+/// Sets up the interrupt of the function at `bdf`: an MSI if it and the
+/// interrupt controller can, or else its INTx line.
+fn setup_irq(root: &mut PciRoot, bdf: DeviceFunction, host: &PciHost) -> Option<usize> {
+    if driver_pci::has_msi(root, bdf) {
+        if let Some(msg) = axirq::alloc_msi() {
+            if driver_pci::enable_msi(root, bdf, msg.address, msg.data as u16) {
+                return Some(msg.irq);
+            }
+        }
+    }
+    host.intx_irq(root, bdf)
+}
+
 impl AllDevices {
-    pub(crate) fn probe_bus_devices(&mut self) {
-        let base_vaddr = phys_to_virt(axconfig::PCI_ECAM_BASE.into());
-        let mut root = unsafe { PciRoot::new(base_vaddr.as_mut_ptr(), Cam::Ecam) };
+    pub(crate) fn probe_pci_devices(&mut self) {
+        let mut hosts = core::mem::take(&mut *PCI_HOSTS.lock());
+        if hosts.is_empty() {
+            hosts.extend(config_host());
+        }
+        for host in hosts.iter() {
+            self.probe_pci_host(host);
+        }
+    }
+
+    fn probe_pci_host(&mut self, host: &PciHost) {
+        // The ECAM space of bus n is at n MiB from that of bus 0. It stays
+        // mapped, for the drivers to access the config spaces.
+        let buses = host.bus_end as usize - host.bus_start as usize + 1;
+        let ecam = match ioremap(host.ecam_base, buses << 20) {
+            Ok(ecam) => ecam,
+            Err(e) => {
+                warn!("PCI: cannot map ECAM at {:#x}: {:?}", host.ecam_base, e);
+                return;
+            }
+        };
+        let bus0_vaddr = ecam.vaddr().as_usize() - ((host.bus_start as usize) << 20);
+        let mut root = unsafe { PciRoot::new(bus0_vaddr as *mut u8, Cam::Ecam) };
 
-        // PCI 32-bit MMIO space
-        let mut allocator = axconfig::PCI_RANGES
-            .get(1)
+        let mut allocator = host
+            .mem32
             .map(|range| PciRangeAllocator::new(range.0 as u64, range.1 as u64));
 
-        for bus in 0..=axconfig::PCI_BUS_END as u8 {
+        for bus in host.bus_start..=host.bus_end {
             for (bdf, dev_info) in root.enumerate_bus(bus) {
                 debug!("PCI {}: {}", bdf, dev_info);
                 if dev_info.header_type != HeaderType::Standard {
@@ -101,12 +264,15 @@ impl AllDevices {
                 match config_pci_device(&mut root, bdf, &mut allocator) {
                     Ok(_) => for_each_drivers!(type Driver, {
                         if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info) {
+                            let irq = setup_irq(&mut root, bdf, host);
                             info!(
-                                "registered a new {:?} device at {}: {:?}",
+                                "registered a new {:?} device at {}, irq {:?}: {:?}",
                                 dev.device_type(),
                                 bdf,
+                                irq,
                                 dev.device_name(),
                             );
+                            self.set_irq(&dev, irq);
                             self.add_device(dev);
                             continue; // skip to the next device
                         }
@@ -1,17 +1,25 @@
 use crate::prelude::*;
+use crate::partition::Partition;
+use axerrno::{ax_err, ax_err_type, AxError};
+use axio::{Read, Seek, SeekFrom, Write};
 
 const BLOCK_SIZE: usize = 512;
 
-/// A disk device with a cursor.
+/// A disk device, or a partition of one, with a cursor.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    dev: Partition,
 }
 
 impl Disk {
     /// Create a new disk.
     pub fn new(dev: AxBlockDevice) -> Self {
+        Self::from_partition(Partition::whole("", dev, None))
+    }
+
+    /// Create a disk on a partition, whose blocks start from 0.
+    pub fn from_partition(dev: Partition) -> Self {
         assert_eq!(BLOCK_SIZE, dev.block_size());
         Self {
             block_id: 0,
@@ -36,6 +44,34 @@ impl Disk {
         self.offset = pos as usize % BLOCK_SIZE;
     }
 
+    /// Start reading the `len` bytes from `offset` on into the buffer
+    /// cache, without waiting. The cursor is left as it is.
+    pub fn readahead(&self, offset: u64, len: u64) {
+        let start = offset / BLOCK_SIZE as u64;
+        let end = (offset + len).div_ceil(BLOCK_SIZE as u64);
+        self.dev.readahead(start, end - start);
+    }
+
+    /// Read whole blocks from `offset`, a multiple of the block size, on,
+    /// from the disk rather than the buffer cache, for direct I/O. The
+    /// cursor is left as it is.
+    pub fn read_direct(&self, offset: u64, buf: &mut [u8]) -> DevResult {
+        if offset % BLOCK_SIZE as u64 != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        self.dev.read_direct(offset / BLOCK_SIZE as u64, buf)
+    }
+
+    /// Write whole blocks from `offset`, a multiple of the block size, on,
+    /// on the disk at once rather than in the buffer cache, for direct I/O.
+    /// The cursor is left as it is.
+    pub fn write_direct(&self, offset: u64, buf: &[u8]) -> DevResult {
+        if offset % BLOCK_SIZE as u64 != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        self.dev.write_direct(offset / BLOCK_SIZE as u64, buf)
+    }
+
     /// Read within one block, returns the number of bytes read.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
@@ -90,3 +126,51 @@ impl Disk {
         Ok(write_size)
     }
 }
+
+/// A disk reads up to the end of the buffer or of the disk, which makes it
+/// usable as any other reader, e.g. with a [`axio::BufReader`] over it.
+impl Read for Disk {
+    fn read(&mut self, mut buf: &mut [u8]) -> axio::Result<usize> {
+        let mut read_len = 0;
+        while !buf.is_empty() && self.position() < self.size() {
+            let n = self.read_one(buf).map_err(|_| AxError::Io)?;
+            let tmp = buf;
+            buf = &mut tmp[n..];
+            read_len += n;
+        }
+        Ok(read_len)
+    }
+}
+
+impl Write for Disk {
+    fn write(&mut self, mut buf: &[u8]) -> axio::Result<usize> {
+        let mut write_len = 0;
+        while !buf.is_empty() && self.position() < self.size() {
+            let n = self.write_one(buf).map_err(|_| AxError::Io)?;
+            buf = &buf[n..];
+            write_len += n;
+        }
+        Ok(write_len)
+    }
+
+    fn flush(&mut self) -> axio::Result {
+        Ok(())
+    }
+}
+
+impl Seek for Disk {
+    fn seek(&mut self, pos: SeekFrom) -> axio::Result<u64> {
+        let size = self.size();
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(off) => self.position().checked_add_signed(off),
+            SeekFrom::End(off) => size.checked_add_signed(off),
+        }
+        .ok_or_else(|| ax_err_type!(InvalidInput))?;
+        if new_pos > size {
+            return ax_err!(InvalidInput, "seek beyond the end of the disk");
+        }
+        self.set_position(new_pos);
+        Ok(new_pos)
+    }
+}
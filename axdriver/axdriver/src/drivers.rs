@@ -4,6 +4,7 @@
 
 use crate::AxDeviceEnum;
 use driver_common::DeviceType;
+use page_table::ioremap::IoMem;
 
 #[cfg(feature = "virtio")]
 use crate::virtio::{self, VirtIoDevMeta};
@@ -19,7 +20,7 @@ pub trait DriverProbe {
     }
 
     #[cfg(bus = "mmio")]
-    fn probe_mmio(_mmio_base: usize, _mmio_size: usize) -> Option<AxDeviceEnum> {
+    fn probe_mmio(_mmio: &IoMem) -> Option<AxDeviceEnum> {
         None
     }
 
@@ -51,6 +52,12 @@ register_display_driver!(
     <virtio::VirtIoGpu as VirtIoDevMeta>::Device
 );
 
+#[cfg(char_dev = "virtio-console")]
+register_char_driver!(
+    <virtio::VirtIoConsole as VirtIoDevMeta>::Driver,
+    <virtio::VirtIoConsole as VirtIoDevMeta>::Device
+);
+
 cfg_if::cfg_if! {
     if #[cfg(block_dev = "ramdisk")] {
         pub struct RamDiskDriver;
@@ -0,0 +1,204 @@
+//! Probing of the devices that the device tree describes.
+//!
+//! Drivers match nodes by their `compatible` strings: the built-in ones
+//! are in [`MATCH_TABLE`], and those of other crates are added by
+//! [`register_driver`] before [`init_drivers2`](crate::init_drivers2).
+//! Each enabled node is handed, with its IRQs, to the probe function of the first entry that matches one of its strings,
+//! in the order the node lists them, the most specific first.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use axdtb::DtbNodeRef;
+use core::ops::Deref;
+use spinbase::SpinNoIrq;
+
+use crate::{prelude::*, AllDevices, AxDeviceEnum};
+
+/// Probes a node that matched. `Ok(Some(dev))` adds `dev` to the devices,
+/// `Ok(None)` tells that the driver took the node for itself, as a console
+/// or a clock, and an error lets the next entry that matches try.
+pub type DtbProbeFn = fn(&DtbNode) -> DevResult<Option<AxDeviceEnum>>;
+
+/// An entry of the match table.
+#[derive(Clone, Copy)]
+pub struct DtbDriver {
+    pub compatible: &'static str,
+    pub probe: DtbProbeFn,
+}
+
+/// A node of the device tree, as a probe function gets it, which derefs
+/// to the node of axdtb for its properties and its `reg`.
+pub struct DtbNode<'a> {
+    pub name: &'a str,
+    /// Its IRQ lines, of its `interrupts` property, as the interrupt
+    /// controller numbers them: a cell each for the PLIC, three for the GIC.
+    pub irqs: Vec<usize>,
+    node: DtbNodeRef<'a>,
+}
+
+impl<'a> DtbNode<'a> {
+    fn new(name: &'a str, node: DtbNodeRef<'a>) -> Self {
+        let irqs = match node.prop("interrupts") {
+            Some(irqs) => {
+                let cells: Vec<u32> = irqs.chunks_exact(4)
+                    .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+                    .collect();
+                let irq_cells = if cfg!(target_arch = "aarch64") { 3 } else { 1 };
+                cells.chunks_exact(irq_cells).map(parent_irq).collect()
+            }
+            None => Vec::new(),
+        };
+        Self { name, irqs, node }
+    }
+
+    /// Whether its `status` lets it be used, which it does if it has none.
+    pub fn is_enabled(&self) -> bool {
+        matches!(self.prop_str("status"), None | Some("okay") | Some("ok"))
+    }
+}
+
+impl<'a> Deref for DtbNode<'a> {
+    type Target = DtbNodeRef<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.node
+    }
+}
+
+/// The drivers built in this crate.
+static MATCH_TABLE: &[DtbDriver] = &[
+    #[cfg(all(bus = "mmio", feature = "virtio"))]
+    DtbDriver {
+        compatible: "virtio,mmio",
+        probe: crate::bus::mmio::probe_virtio_mmio,
+    },
+    #[cfg(bus = "pci")]
+    DtbDriver {
+        compatible: "pci-host-ecam-generic",
+        probe: crate::bus::pci::probe_host,
+    },
+    #[cfg(feature = "goldfish-rtc")]
+    DtbDriver {
+        compatible: "google,goldfish-rtc",
+        probe: crate::rtc::probe_goldfish,
+    },
+];
+
+/// The drivers of other crates.
+static DRIVERS: SpinNoIrq<Vec<DtbDriver>> = SpinNoIrq::new(Vec::new());
+
+/// Adds a driver for the nodes compatible with `compatible`, which is tried
+/// after the built-in ones.
+pub fn register_driver(compatible: &'static str, probe: DtbProbeFn) {
+    DRIVERS.lock().push(DtbDriver { compatible, probe });
+}
+
+/// `#address-cells` and `#interrupt-cells` of the interrupt controllers,
+/// by phandle, for the interrupt maps.
+static INTERRUPT_CELLS: SpinNoIrq<BTreeMap<u32, (usize, usize)>> = SpinNoIrq::new(BTreeMap::new());
+
+/// `#address-cells` and `#interrupt-cells` of the interrupt controller of
+/// `phandle`, as the device tree walked at probe has them.
+pub(crate) fn interrupt_cells(phandle: u32) -> Option<(usize, usize)> {
+    INTERRUPT_CELLS.lock().get(&phandle).copied()
+}
+
+/// The IRQ of an interrupt specifier: a PLIC has the number only, a GIC has
+/// the type, the number and flags, with the SPIs from 32 and the PPIs
+/// from 16.
+pub(crate) fn parent_irq(cells: &[u32]) -> usize {
+    match cells {
+        [0, num, _] => *num as usize + 32,
+        [1, num, _] => *num as usize + 16,
+        [num, ..] => *num as usize,
+        [] => 0,
+    }
+}
+
+impl AllDevices {
+    /// Probes the enabled nodes of the device tree at `dtb_pa` against the
+    /// match table. Returns whether there is a device tree to walk.
+    pub(crate) fn probe_dtb_devices(&mut self, dtb_pa: usize) -> bool {
+        if dtb_pa == 0 {
+            return false;
+        }
+        let dt = match axdtb::DeviceTree::init(axhal::mem::phys_to_virt(dtb_pa.into()).into()) {
+            Ok(dt) => dt,
+            Err(e) => {
+                warn!("dtb: no device tree at {:#x}: {:?}", dtb_pa, e);
+                return false;
+            }
+        };
+        let drivers: Vec<DtbDriver> = MATCH_TABLE.iter()
+            .copied()
+            .chain(DRIVERS.lock().iter().copied())
+            .collect();
+
+        match dt.find_node("/") {
+            Some(root) => self.probe_dtb_tree("", root, &drivers),
+            None => warn!("dtb: bad device tree at {:#x}", dtb_pa),
+        }
+        true
+    }
+
+    /// Probes `node` and then its children, which are in the order of the
+    /// tree, so that an interrupt controller is known before its devices.
+    fn probe_dtb_tree(&mut self, name: &str, node: DtbNodeRef, drivers: &[DtbDriver]) {
+        if node.prop("interrupt-controller").is_some() {
+            if let Some(phandle) = node.prop_u32("phandle") {
+                let cells = (
+                    node.prop_u32("#address-cells").unwrap_or(0) as usize,
+                    node.prop_u32("#interrupt-cells").unwrap_or(1) as usize,
+                );
+                INTERRUPT_CELLS.lock().insert(phandle, cells);
+            }
+        }
+        let node = DtbNode::new(name, node);
+        if node.prop("compatible").is_some() && node.is_enabled() {
+            self.probe_dtb_node(&node, drivers);
+        }
+        for (name, child) in node.children() {
+            self.probe_dtb_tree(name, child, drivers);
+        }
+    }
+
+    fn probe_dtb_node(&mut self, node: &DtbNode, drivers: &[DtbDriver]) {
+        for compatible in node.compatible() {
+            for drv in drivers.iter().filter(|drv| drv.compatible == compatible) {
+                match (drv.probe)(node) {
+                    Ok(Some(dev)) => {
+                        info!(
+                            "registered a new {:?} device {} ({}): {:?}",
+                            dev.device_type(),
+                            node.name,
+                            compatible,
+                            dev.device_name(),
+                        );
+                        self.set_irq(&dev, node.irqs.first().copied());
+                        self.add_device(dev);
+                        return;
+                    }
+                    Ok(None) => {
+                        info!("dtb: {} ({}) probed", node.name, compatible);
+                        return;
+                    }
+                    Err(DevError::Unsupported) => {}
+                    Err(e) => warn!("dtb: failed to probe {} ({}): {:?}", node.name, compatible, e),
+                }
+            }
+        }
+    }
+
+    /// Takes `irq` for the device if it is the first of its category.
+    #[allow(unused_variables)]
+    pub(crate) fn set_irq(&mut self, dev: &AxDeviceEnum, irq: Option<usize>) {
+        #[cfg(feature = "net")]
+        if dev.device_type() == DeviceType::Net && self.net.is_empty() {
+            self.net_irq = irq;
+        }
+        #[cfg(feature = "block")]
+        if dev.device_type() == DeviceType::Block && self.block.is_empty() {
+            self.block_irq = irq;
+        }
+    }
+}
@@ -100,3 +100,32 @@ cfg_if! {
         }
     }
 }
+
+cfg_if! {
+    if #[cfg(char_dev = "dummy")] {
+        pub struct DummyCharDev;
+        pub struct DummyCharDriver;
+        register_char_driver!(DummyCharDriver, DummyCharDev);
+
+        impl BaseDriverOps for DummyCharDev {
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Char
+            }
+            fn device_name(&self) -> &str {
+                "dummy-char"
+            }
+        }
+
+        impl CharDriverOps for DummyCharDev {
+            fn write_bytes(&mut self, _: &[u8]) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+            fn getchar(&mut self) -> DevResult<Option<u8>> {
+                Err(DevError::Unsupported)
+            }
+            fn ack_interrupt(&mut self) -> bool {
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! The consoles of the character devices, `hvc0`, `hvc1`... as Linux
+//! names the consoles of hypervisors. The ttys of `/dev/hvcN` write to
+//! them and poll them for input.
+
+use alloc::vec::Vec;
+use spinbase::SpinNoIrq;
+
+use crate::prelude::*;
+use crate::AxDeviceContainer;
+
+static HVC: SpinNoIrq<Vec<AxCharDevice>> = SpinNoIrq::new(Vec::new());
+
+/// Takes the character devices that were probed as the consoles, in the
+/// order they were found.
+pub fn init(mut devs: AxDeviceContainer<AxCharDevice>) {
+    let mut hvc = HVC.lock();
+    while let Some(dev) = devs.take_one() {
+        info!("hvc{}: {}", hvc.len(), dev.device_name());
+        hvc.push(dev);
+    }
+}
+
+/// How many consoles there are.
+pub fn count() -> usize {
+    HVC.lock().len()
+}
+
+/// Writes `buf` to console `index`.
+pub fn write_bytes(index: usize, buf: &[u8]) -> DevResult {
+    HVC.lock()
+        .get_mut(index)
+        .ok_or(DevError::InvalidParam)?
+        .write_bytes(buf)
+}
+
+/// Takes the next byte that console `index` received, if any.
+pub fn getchar(index: usize) -> Option<u8> {
+    HVC.lock().get_mut(index)?.getchar().ok().flatten()
+}
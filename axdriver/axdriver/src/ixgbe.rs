@@ -1,25 +1,20 @@
-use axalloc::global_allocator;
+use axalloc::{dma_alloc_coherent, dma_free_coherent};
 use axhal::mem::{phys_to_virt, virt_to_phys};
-use core::{alloc::Layout, ptr::NonNull};
+use core::ptr::NonNull;
 use driver_net::ixgbe::{IxgbeHal, PhysAddr as IxgbePhysAddr};
 
 pub struct IxgbeHalImpl;
 
 unsafe impl IxgbeHal for IxgbeHalImpl {
     fn dma_alloc(size: usize) -> (IxgbePhysAddr, NonNull<u8>) {
-        let layout = Layout::from_size_align(size, 8).unwrap();
-        let vaddr = if let Ok(vaddr) = global_allocator().alloc(layout) {
-            vaddr
-        } else {
+        let Ok((vaddr, paddr)) = dma_alloc_coherent(size) else {
             return (0, NonNull::dangling());
         };
-        let paddr = virt_to_phys((vaddr.as_ptr() as usize).into());
-        (paddr.as_usize(), vaddr)
+        (paddr.as_usize(), NonNull::new(vaddr.as_mut_ptr()).unwrap())
     }
 
     unsafe fn dma_dealloc(_paddr: IxgbePhysAddr, vaddr: NonNull<u8>, size: usize) -> i32 {
-        let layout = Layout::from_size_align(size, 8).unwrap();
-        global_allocator().dealloc(vaddr, layout);
+        dma_free_coherent((vaddr.as_ptr() as usize).into(), size);
         0
     }
 
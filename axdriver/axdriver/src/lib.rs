@@ -3,7 +3,7 @@
 //! # Usage
 //!
 //! All detected devices are composed into a large struct [`AllDevices`]
-//! and returned by the [`init_drivers`] function. The upperlayer subsystems
+//! and returned by the [`init_drivers2`] function. The upperlayer subsystems
 //! (e.g., the network stack) may unpack the struct to get the specified device
 //! driver they want.
 //!
@@ -31,16 +31,19 @@
 //! | Device Category | Cargo Feature | Description |
 //! |-|-|-|
 //! | Block | `ramdisk` | A RAM disk that stores data in a vector |
+//! | Block | - | RAM disks on reserved memory, see [`brd`] |
 //! | Block | `virtio-blk` | VirtIO block device |
 //! | Network | `virtio-net` | VirtIO network device |
 //! | Display | `virtio-gpu` | VirtIO graphics device |
+//! | Char | `virtio-console` | VirtIO console device, see [`hvc`] |
 //!
 //! # Other Cargo Features
 //!
 //! - `dyn`: use the dynamic device model (see above).
-//! - `bus-mmio`: use device tree to probe all MMIO devices. This feature is
-//!    enabeld by default.
-//! - `bus-pci`: use PCI bus to probe all PCI devices.
+//! - `bus-mmio`: use device tree to probe all MMIO devices, see [`dtb`]. This
+//!    feature is enabeld by default.
+//! - `bus-pci`: use PCI bus to probe all PCI devices. Both buses can be
+//!    probed, with VirtIO devices on either.
 //! - `virtio`: use VirtIO devices. This is enabled if any of `virtio-blk`,
 //!   `virtio-net` or `virtio-gpu` is enabled.
 //! - `net`: use network devices. This is enabled if any feature of network
@@ -48,6 +51,7 @@
 //!    features, a dummy struct is used for [`AxNetDevice`].
 //! - `block`: use block storage devices. Similar to the `net` feature.
 //! - `display`: use graphics display devices. Similar to the `net` feature.
+//! - `char`: use character devices. Similar to the `net` feature.
 //!
 //! [`VirtioNetDev`]: driver_virtio::VirtIoNetDev
 //! [`Box<dyn NetDriverOps>`]: driver_net::NetDriverOps
@@ -61,7 +65,6 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "dyn")]
 extern crate alloc;
 
 #[macro_use]
@@ -72,6 +75,14 @@ mod drivers;
 mod dummy;
 mod structs;
 mod disk;
+pub mod bcache;
+pub mod dtb;
+#[cfg(feature = "char")]
+pub mod hvc;
+pub mod brd;
+pub mod partition;
+pub mod queue;
+pub mod rtc;
 pub use disk::Disk;
 
 #[cfg(feature = "virtio")]
@@ -92,6 +103,8 @@ pub use self::structs::AxBlockDevice;
 pub use self::structs::AxDisplayDevice;
 #[cfg(feature = "net")]
 pub use self::structs::AxNetDevice;
+#[cfg(feature = "char")]
+pub use self::structs::AxCharDevice;
 
 /// A structure that contains all device drivers, organized by their category.
 #[derive(Default)]
@@ -99,12 +112,23 @@ pub struct AllDevices {
     /// All network device drivers.
     #[cfg(feature = "net")]
     pub net: AxDeviceContainer<AxNetDevice>,
+    /// IRQ line of the first network device, if it is known. Without one
+    /// the device only gets polled.
+    #[cfg(feature = "net")]
+    pub net_irq: Option<usize>,
     /// All block device drivers.
     #[cfg(feature = "block")]
     pub block: AxDeviceContainer<AxBlockDevice>,
+    /// IRQ line of the first block device, if it is known. Without one
+    /// its requests get polled.
+    #[cfg(feature = "block")]
+    pub block_irq: Option<usize>,
     /// All graphics device drivers.
     #[cfg(feature = "display")]
     pub display: AxDeviceContainer<AxDisplayDevice>,
+    /// All character device drivers.
+    #[cfg(feature = "char")]
+    pub char: AxDeviceContainer<AxCharDevice>,
 }
 
 impl AllDevices {
@@ -119,8 +143,9 @@ impl AllDevices {
         }
     }
 
-    /// Probes all supported devices.
-    fn probe(&mut self) {
+    /// Probes all supported devices, those of the device tree at `dtb_pa`
+    /// among them.
+    fn probe(&mut self, dtb_pa: usize) {
         for_each_drivers!(type Driver, {
             if let Some(dev) = Driver::probe_global() {
                 info!(
@@ -132,7 +157,12 @@ impl AllDevices {
             }
         });
 
-        self.probe_bus_devices();
+        #[allow(unused_variables)]
+        let dtb_walked = self.probe_dtb_devices(dtb_pa);
+        #[cfg(bus = "mmio")]
+        self.probe_mmio_devices(dtb_walked);
+        #[cfg(bus = "pci")]
+        self.probe_pci_devices();
     }
 
     /// Adds one device into the corresponding container, according to its device category.
@@ -145,17 +175,28 @@ impl AllDevices {
             AxDeviceEnum::Block(dev) => self.block.push(dev),
             #[cfg(feature = "display")]
             AxDeviceEnum::Display(dev) => self.display.push(dev),
+            #[cfg(feature = "char")]
+            AxDeviceEnum::Char(dev) => self.char.push(dev),
         }
     }
 }
 
+/// Quiesces the devices before the kernel is left, as by kexec: they stop
+/// their DMA and their interrupts.
+pub fn shutdown_devices() {
+    info!("Shut down device drivers...");
+    bus::shutdown();
+}
+
 /// Probes and initializes all device drivers, returns the [`AllDevices`] struct.
-pub fn init_drivers2() -> AllDevices {
+/// The MMIO devices are those of the device tree at `dtb_pa`, or of the
+/// platform config if there is none.
+pub fn init_drivers2(dtb_pa: usize) -> AllDevices {
     info!("Initialize device drivers...");
     info!("  device model: {}", AllDevices::device_model());
 
     let mut all_devs = AllDevices::default();
-    all_devs.probe();
+    all_devs.probe(dtb_pa);
 
     #[cfg(feature = "net")]
     {
@@ -181,6 +222,14 @@ pub fn init_drivers2() -> AllDevices {
             debug!("  graphics device {}: {:?}", i, dev.device_name());
         }
     }
+    #[cfg(feature = "char")]
+    {
+        debug!("number of character devices: {}", all_devs.char.len());
+        for (i, dev) in all_devs.char.iter().enumerate() {
+            assert_eq!(dev.device_type(), DeviceType::Char);
+            debug!("  character device {}: {:?}", i, dev.device_name());
+        }
+    }
 
     all_devs
 }
@@ -26,6 +26,14 @@ macro_rules! register_display_driver {
     };
 }
 
+macro_rules! register_char_driver {
+    ($driver_type:ty, $device_type:ty) => {
+        /// The unified type of the character devices.
+        #[cfg(not(feature = "dyn"))]
+        pub type AxCharDevice = $device_type;
+    };
+}
+
 macro_rules! for_each_drivers {
     (type $drv_type:ident, $code:block) => {{
         #[allow(unused_imports)]
@@ -49,6 +57,11 @@ macro_rules! for_each_drivers {
             type $drv_type = <virtio::VirtIoGpu as VirtIoDevMeta>::Driver;
             $code
         }
+        #[cfg(char_dev = "virtio-console")]
+        {
+            type $drv_type = <virtio::VirtIoConsole as VirtIoDevMeta>::Driver;
+            $code
+        }
         #[cfg(block_dev = "ramdisk")]
         {
             type $drv_type = crate::drivers::RamDiskDriver;
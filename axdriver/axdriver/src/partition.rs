@@ -0,0 +1,373 @@
+//! Partitions of block devices.
+//!
+//! Each disk added is scanned for a GPT, or else an MBR with the logical
+//! partitions of its extended one, and registered with its partitions by
+//! names like Linux gives them: `vda`, `vda1`, `vda2`... A [`Partition`]
+//! reads and writes its own blocks, from 0, translated to those of the
+//! disk, through the request queue all partitions of it share.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spinbase::SpinNoIrq;
+use core::cmp::min;
+
+use crate::prelude::*;
+use crate::bcache;
+use crate::queue::{Bio, RequestQueue};
+
+/// A disk, shared by its partitions.
+pub type SharedBlockDevice = Arc<RequestQueue>;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRIES: usize = 4;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// Logical partitions are numbered after the 4 primary ones.
+const FIRST_LOGICAL_PARTNO: usize = 5;
+/// Bound on the chain of EBRs, against loops in it.
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_MIN_SIZE: usize = 92;
+const GPT_ENTRY_MIN_SIZE: usize = 128;
+const GPT_MAX_ENTRIES: usize = 1024;
+
+/// Blocks `[start, start + num_blocks)` of a disk, or all of it.
+#[derive(Clone)]
+pub struct Partition {
+    name: String,
+    dev: SharedBlockDevice,
+    /// 0 for the whole disk.
+    partno: usize,
+    start: u64,
+    num_blocks: u64,
+}
+
+impl Partition {
+    /// The whole of `dev`, whose IRQ line is `irq` if it is known.
+    pub fn whole<D: BlockDriverOps + Send + 'static>(name: &str, dev: D, irq: Option<usize>) -> Self {
+        let num_blocks = dev.num_blocks();
+        Self {
+            name: name.into(),
+            dev: RequestQueue::new(dev, irq),
+            partno: 0,
+            start: 0,
+            num_blocks,
+        }
+    }
+
+    fn part(&self, partno: usize, start: u64, num_blocks: u64) -> Self {
+        // "mmcblk0" has "mmcblk0p1", but "vda" has "vda1".
+        let name = if self.name.ends_with(|c: char| c.is_ascii_digit()) {
+            format!("{}p{}", self.name, partno)
+        } else {
+            format!("{}{}", self.name, partno)
+        };
+        Self {
+            name,
+            dev: self.dev.clone(),
+            partno,
+            start,
+            num_blocks,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of the partition, or 0 for the whole disk.
+    pub fn partno(&self) -> usize {
+        self.partno
+    }
+
+    /// The first block on the disk.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    /// The request queue of the disk.
+    pub fn queue(&self) -> &SharedBlockDevice {
+        &self.dev
+    }
+
+    fn translate(&self, block_id: u64, len: usize) -> DevResult<u64> {
+        let count = len.div_ceil(self.block_size()) as u64;
+        if block_id.checked_add(count).map_or(true, |end| end > self.num_blocks) {
+            return Err(DevError::InvalidParam);
+        }
+        Ok(self.start + block_id)
+    }
+
+    /// Reads from block `block_id` of the partition, through the buffer
+    /// cache.
+    pub fn read_block(&self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        bcache::read(&self.dev, block_id, buf)
+    }
+
+    /// Writes to block `block_id` of the partition, in the buffer cache,
+    /// to be written back later.
+    pub fn write_block(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        bcache::write(&self.dev, block_id, buf)
+    }
+
+    /// Starts reading `count` blocks from block `block_id` of the partition
+    /// into the buffer cache, without waiting. Those past its end are not.
+    pub fn readahead(&self, block_id: u64, count: u64) {
+        let count = min(count, self.num_blocks.saturating_sub(block_id));
+        if count > 0 {
+            bcache::readahead(&self.dev, self.start + block_id, count);
+        }
+    }
+
+    /// Reads from block `block_id` of the partition, from the disk rather
+    /// than the buffer cache, for direct I/O.
+    pub fn read_direct(&self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        bcache::read_direct(&self.dev, block_id, buf)
+    }
+
+    /// Writes to block `block_id` of the partition, on the disk at once
+    /// rather than in the buffer cache, for direct I/O.
+    pub fn write_direct(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        bcache::write_direct(&self.dev, block_id, buf)
+    }
+
+    /// Writes to block `block_id` of the partition at once, bypassing the
+    /// queue and the buffer cache, for the crash dump.
+    pub fn panic_write(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        let block_id = self.translate(block_id, buf.len())?;
+        self.dev.panic_write(block_id, buf)
+    }
+
+    /// Submits a bio on blocks of the partition, without waiting for it
+    /// and bypassing the buffer cache. Its block is translated to that of
+    /// the disk.
+    pub fn submit_bio(&self, mut bio: Bio) {
+        match self.translate(bio.block_id(), bio.len()) {
+            Ok(block_id) => bio.set_block_id(block_id),
+            Err(e) => return bio.end_io(Err(e)),
+        }
+        self.dev.submit_bio(bio);
+    }
+
+    /// Writes back the dirty blocks of the disk, and flushes it.
+    pub fn flush(&self) -> DevResult {
+        bcache::sync_disk(&self.dev)
+    }
+}
+
+static PARTITIONS: SpinNoIrq<Vec<Partition>> = SpinNoIrq::new(Vec::new());
+
+/// Registers the disk `dev` as `name`, and the partitions found on it.
+/// Returns how many there are.
+pub fn add_disk<D: BlockDriverOps + Send + 'static>(name: &str, mut dev: D, irq: Option<usize>) -> usize {
+    let parts = scan_partitions(&mut dev).unwrap_or_else(|e| {
+        warn!("{}: bad partition table: {:?}", name, e);
+        Vec::new()
+    });
+    let disk = Partition::whole(name, dev, irq);
+    let mut partitions = PARTITIONS.lock();
+    for (partno, start, num_blocks) in parts.iter().copied() {
+        let part = disk.part(partno, start, num_blocks);
+        info!("  {}: start {} blocks {}", part.name(), start, num_blocks);
+        partitions.push(part);
+    }
+    partitions.push(disk);
+    parts.len()
+}
+
+/// Finds a disk or partition by its name.
+pub fn lookup_partition(name: &str) -> Option<Partition> {
+    PARTITIONS.lock().iter().find(|p| p.name == name).cloned()
+}
+
+/// All disks and partitions, each disk after its partitions.
+pub fn partitions() -> Vec<Partition> {
+    PARTITIONS.lock().clone()
+}
+
+/// Returns (partno, start, num_blocks) of the partitions on `dev`.
+fn scan_partitions(dev: &mut dyn BlockDriverOps) -> DevResult<Vec<(usize, u64, u64)>> {
+    let mut mbr = vec![0u8; dev.block_size()];
+    dev.read_block(0, &mut mbr)?;
+    if mbr[510..512] != MBR_SIGNATURE {
+        return Ok(Vec::new());
+    }
+    let entries = mbr_entries(&mbr);
+    if entries.iter().any(|e| e.ty == MBR_TYPE_GPT_PROTECTIVE) {
+        if let Some(parts) = scan_gpt(dev)? {
+            return Ok(parts);
+        }
+        warn!("no valid GPT behind a protective MBR");
+        return Ok(Vec::new());
+    }
+
+    let num_blocks = dev.num_blocks();
+    let mut parts = Vec::new();
+    for (i, e) in entries.iter().enumerate() {
+        if e.ty == MBR_TYPE_EMPTY || e.num_blocks == 0 {
+            continue;
+        }
+        if e.start + e.num_blocks > num_blocks {
+            warn!("partition {} beyond the end of the disk", i + 1);
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&e.ty) {
+            scan_extended(dev, e.start, e.num_blocks, &mut parts)?;
+        } else {
+            parts.push((i + 1, e.start, e.num_blocks));
+        }
+    }
+    parts.sort_by_key(|p| p.0);
+    Ok(parts)
+}
+
+#[derive(Clone, Copy)]
+struct MbrEntry {
+    ty: u8,
+    start: u64,
+    num_blocks: u64,
+}
+
+fn mbr_entries(sector: &[u8]) -> [MbrEntry; MBR_ENTRIES] {
+    core::array::from_fn(|i| {
+        let e = &sector[MBR_TABLE_OFFSET + i * 16..MBR_TABLE_OFFSET + (i + 1) * 16];
+        MbrEntry {
+            ty: e[4],
+            start: u32::from_le_bytes(e[8..12].try_into().unwrap()) as u64,
+            num_blocks: u32::from_le_bytes(e[12..16].try_into().unwrap()) as u64,
+        }
+    })
+}
+
+/// Follows the chain of EBRs of the extended partition at `ext_start`.
+/// Each EBR tells a logical partition relative to itself, and the next EBR
+/// relative to the extended partition.
+fn scan_extended(
+    dev: &mut dyn BlockDriverOps,
+    ext_start: u64,
+    ext_blocks: u64,
+    parts: &mut Vec<(usize, u64, u64)>,
+) -> DevResult {
+    let mut sector = vec![0u8; dev.block_size()];
+    let mut ebr = ext_start;
+    let mut partno = FIRST_LOGICAL_PARTNO;
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        dev.read_block(ebr, &mut sector)?;
+        if sector[510..512] != MBR_SIGNATURE {
+            break;
+        }
+        let entries = mbr_entries(&sector);
+        let logical = entries[0];
+        if logical.ty != MBR_TYPE_EMPTY && logical.num_blocks != 0 {
+            let start = ebr + logical.start;
+            if start + logical.num_blocks <= ext_start + ext_blocks {
+                parts.push((partno, start, logical.num_blocks));
+            }
+            partno += 1;
+        }
+        let next = entries[1];
+        if !MBR_TYPES_EXTENDED.contains(&next.ty) || next.start == 0 || next.start >= ext_blocks {
+            break;
+        }
+        ebr = ext_start + next.start;
+    }
+    Ok(())
+}
+
+/// Reads the primary GPT, or the backup one at the end of the disk if the
+/// primary is corrupt.
+fn scan_gpt(dev: &mut dyn BlockDriverOps) -> DevResult<Option<Vec<(usize, u64, u64)>>> {
+    let last_lba = dev.num_blocks() - 1;
+    if let Some(parts) = read_gpt(dev, 1)? {
+        return Ok(Some(parts));
+    }
+    warn!("primary GPT is corrupt, trying the backup");
+    read_gpt(dev, last_lba)
+}
+
+fn read_gpt(dev: &mut dyn BlockDriverOps, lba: u64) -> DevResult<Option<Vec<(usize, u64, u64)>>> {
+    let bs = dev.block_size();
+    let mut header = vec![0u8; bs];
+    dev.read_block(lba, &mut header)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Ok(None);
+    }
+    let le32 = |b: &[u8], off: usize| u32::from_le_bytes(b[off..off + 4].try_into().unwrap());
+    let le64 = |b: &[u8], off: usize| u64::from_le_bytes(b[off..off + 8].try_into().unwrap());
+
+    let header_size = le32(&header, 12) as usize;
+    if header_size < GPT_HEADER_MIN_SIZE || header_size > bs {
+        return Ok(None);
+    }
+    let header_crc = le32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc || le64(&header, 24) != lba {
+        return Ok(None);
+    }
+    let first_usable = le64(&header, 40);
+    let last_usable = le64(&header, 48);
+    let entries_lba = le64(&header, 72);
+    let num_entries = le32(&header, 80) as usize;
+    let entry_size = le32(&header, 84) as usize;
+    let entries_crc = le32(&header, 88);
+    if num_entries > GPT_MAX_ENTRIES
+        || entry_size < GPT_ENTRY_MIN_SIZE
+        || !entry_size.is_power_of_two()
+    {
+        return Ok(None);
+    }
+
+    let len = num_entries * entry_size;
+    let mut entries = vec![0u8; len.div_ceil(bs) * bs];
+    dev.read_block(entries_lba, &mut entries)?;
+    if crc32(&entries[..len]) != entries_crc {
+        return Ok(None);
+    }
+
+    let mut parts = Vec::new();
+    for (i, e) in entries[..len].chunks(entry_size).enumerate() {
+        // An unused entry has a null type GUID.
+        if e[0..16].iter().all(|b| *b == 0) {
+            continue;
+        }
+        let first = le64(e, 32);
+        let last = le64(e, 40);
+        if first < first_usable || last > last_usable || first > last {
+            warn!("GPT entry {} out of the usable blocks", i + 1);
+            continue;
+        }
+        parts.push((i + 1, first, last - first + 1));
+    }
+    Ok(Some(parts))
+}
+
+/// The CRC32 of GPT (IEEE 802.3, reflected).
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -8,3 +8,5 @@ pub use {crate::structs::AxBlockDevice, driver_block::BlockDriverOps};
 pub use {crate::structs::AxDisplayDevice, driver_display::DisplayDriverOps};
 #[cfg(feature = "net")]
 pub use {crate::structs::AxNetDevice, driver_net::NetDriverOps};
+#[cfg(feature = "char")]
+pub use {crate::structs::AxCharDevice, driver_common::CharDriverOps};
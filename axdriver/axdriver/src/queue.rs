@@ -0,0 +1,665 @@
+//! Request queues of block devices.
+//!
+//! I/O is submitted to the queue of a disk as [`Bio`]s, each a run of
+//! blocks to read or write with a callback for its completion. A bio
+//! contiguous with a queued request of the same direction is merged into
+//! it, so a request may stand for many bios and is issued to the device in
+//! one call. Which request goes next is up to a deadline elevator: reads
+//! before writes, in ascending block order from where the disk was left,
+//! unless the oldest request of a direction has waited past its deadline.
+//!
+//! There is no I/O thread. A device that takes requests asynchronously,
+//! as virtio-blk does, gets as many posted as it has room for, and each
+//! completion from its interrupt posts more; while interrupts are off, as
+//! at boot, or if its IRQ line can't be had, waiters poll it for
+//! completions instead. With any other device, whoever submits to an idle
+//! queue dispatches it synchronously, including whatever others queue
+//! meanwhile, until it is empty. Others wait for their bios in
+//! [`RequestQueue::submit_bio_wait`].
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use axhal::time::TimeValue;
+use axirq::{IrqFlags, IrqReturn};
+use spinbase::SpinNoIrq;
+use taskctx::{TaskState, Tid};
+
+use crate::prelude::*;
+
+/// How long a read may wait before it goes ahead of everything else.
+const READ_EXPIRE: Duration = Duration::from_millis(500);
+/// How long a write may wait before it goes ahead of everything else.
+const WRITE_EXPIRE: Duration = Duration::from_secs(5);
+/// Requests dispatched in a row in one direction.
+const FIFO_BATCH: usize = 16;
+/// Batches of reads dispatched while writes wait, before one of writes.
+const WRITES_STARVED: usize = 2;
+/// Bound on the blocks of a merged request.
+const MAX_REQUEST_BLOCKS: u64 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BioOp {
+    Read,
+    Write,
+}
+
+impl BioOp {
+    fn index(self) -> usize {
+        match self {
+            BioOp::Read => 0,
+            BioOp::Write => 1,
+        }
+    }
+
+    fn expire(self) -> Duration {
+        match self {
+            BioOp::Read => READ_EXPIRE,
+            BioOp::Write => WRITE_EXPIRE,
+        }
+    }
+}
+
+/// Called when a bio completes, with the result and the data: what was
+/// read, or what was to be written.
+pub type BioEndIo = Box<dyn FnOnce(DevResult, Vec<u8>) + Send>;
+
+/// Blocks `[block_id, block_id + data.len() / block size)` of a disk to
+/// read into or write from `data`.
+pub struct Bio {
+    op: BioOp,
+    block_id: u64,
+    data: Vec<u8>,
+    end_io: BioEndIo,
+}
+
+impl Bio {
+    /// A bio reading `num_blocks` blocks of `block_size` from `block_id`.
+    pub fn read(block_id: u64, num_blocks: usize, block_size: usize, end_io: BioEndIo) -> Self {
+        Self {
+            op: BioOp::Read,
+            block_id,
+            data: vec![0; num_blocks * block_size],
+            end_io,
+        }
+    }
+
+    /// A bio writing `data`, a whole number of blocks, from `block_id`.
+    pub fn write(block_id: u64, data: Vec<u8>, end_io: BioEndIo) -> Self {
+        Self {
+            op: BioOp::Write,
+            block_id,
+            data,
+            end_io,
+        }
+    }
+
+    pub fn op(&self) -> BioOp {
+        self.op
+    }
+
+    pub fn block_id(&self) -> u64 {
+        self.block_id
+    }
+
+    pub(crate) fn set_block_id(&mut self, block_id: u64) {
+        self.block_id = block_id;
+    }
+
+    /// In bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Completes the bio without issuing it.
+    pub(crate) fn end_io(self, result: DevResult) {
+        (self.end_io)(result, self.data)
+    }
+}
+
+/// Contiguous bios of one direction, issued to the device at once.
+struct Request {
+    op: BioOp,
+    start: u64,
+    num_blocks: u64,
+    /// In block order.
+    bios: VecDeque<Bio>,
+    /// Of the oldest bio in it.
+    deadline: TimeValue,
+}
+
+impl Request {
+    fn end(&self) -> u64 {
+        self.start + self.num_blocks
+    }
+
+    /// The buffer for the device: what its bios write, or room for what
+    /// they read. That of a single bio is its own.
+    fn gather(&mut self, block_size: usize) -> Vec<u8> {
+        if self.bios.len() == 1 {
+            return core::mem::take(&mut self.bios[0].data);
+        }
+        match self.op {
+            BioOp::Read => vec![0; self.num_blocks as usize * block_size],
+            BioOp::Write => {
+                let mut buf = Vec::with_capacity(self.num_blocks as usize * block_size);
+                for bio in self.bios.iter() {
+                    buf.extend_from_slice(&bio.data);
+                }
+                buf
+            },
+        }
+    }
+
+    /// Completes its bios, with what was read in `buf` scattered into them.
+    fn complete(mut self, buf: Vec<u8>, result: DevResult) {
+        ktrace::trace_event!(
+            block_rq_complete,
+            write = self.op == BioOp::Write,
+            sector = self.start,
+            nr = self.num_blocks,
+            error = result.is_err(),
+        );
+        if self.bios.len() == 1 {
+            let mut bio = self.bios.pop_front().unwrap();
+            bio.data = buf;
+            return bio.end_io(result);
+        }
+        let mut offset = 0;
+        for mut bio in self.bios {
+            let len = bio.data.len();
+            if self.op == BioOp::Read && result.is_ok() {
+                bio.data.copy_from_slice(&buf[offset..offset + len]);
+            }
+            offset += len;
+            bio.end_io(result);
+        }
+    }
+}
+
+/// The pending requests of a direction, by start block.
+#[derive(Default)]
+struct Direction {
+    sorted: Vec<Request>,
+}
+
+impl Direction {
+    fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+
+    /// Merges `bio` into a request it adjoins, or queues a new one.
+    fn add(&mut self, bio: Bio, num_blocks: u64, now: TimeValue) {
+        let start = bio.block_id;
+        let end = start + num_blocks;
+        let pos = self.sorted.partition_point(|r| r.start < start);
+        // Back merge into the request ending where the bio starts.
+        if pos > 0 {
+            let prev = &mut self.sorted[pos - 1];
+            if prev.end() == start && prev.num_blocks + num_blocks <= MAX_REQUEST_BLOCKS {
+                prev.num_blocks += num_blocks;
+                prev.bios.push_back(bio);
+                // Which may now adjoin the next one.
+                if pos < self.sorted.len() {
+                    let (prev, next) = (&self.sorted[pos - 1], &self.sorted[pos]);
+                    if prev.end() == next.start
+                        && prev.num_blocks + next.num_blocks <= MAX_REQUEST_BLOCKS
+                    {
+                        let next = self.sorted.remove(pos);
+                        let prev = &mut self.sorted[pos - 1];
+                        prev.num_blocks += next.num_blocks;
+                        prev.bios.extend(next.bios);
+                        prev.deadline = prev.deadline.min(next.deadline);
+                    }
+                }
+                return;
+            }
+        }
+        // Front merge into the request starting where the bio ends.
+        if pos < self.sorted.len() {
+            let next = &mut self.sorted[pos];
+            if next.start == end && next.num_blocks + num_blocks <= MAX_REQUEST_BLOCKS {
+                next.start = start;
+                next.num_blocks += num_blocks;
+                next.bios.push_front(bio);
+                return;
+            }
+        }
+        let op = bio.op;
+        self.sorted.insert(pos, Request {
+            op,
+            start,
+            num_blocks,
+            bios: VecDeque::from([bio]),
+            deadline: now + op.expire(),
+        });
+    }
+
+    /// Takes the request waiting longest, if it is past its deadline.
+    fn take_expired(&mut self, now: TimeValue) -> Option<Request> {
+        let (i, r) = self.sorted.iter().enumerate().min_by_key(|(_, r)| r.deadline)?;
+        if r.deadline > now {
+            return None;
+        }
+        Some(self.sorted.remove(i))
+    }
+
+    /// Takes the first request from `head` on, or else the first one.
+    fn take_next(&mut self, head: u64) -> Option<Request> {
+        if self.sorted.is_empty() {
+            return None;
+        }
+        let pos = self.sorted.partition_point(|r| r.start < head);
+        let pos = if pos < self.sorted.len() { pos } else { 0 };
+        Some(self.sorted.remove(pos))
+    }
+}
+
+/// A deadline elevator.
+#[derive(Default)]
+struct Elevator {
+    dirs: [Direction; 2],
+    /// Where the last request dispatched ended.
+    head: u64,
+    /// Direction of the current batch, and how many are left in it.
+    batch_op: Option<BioOp>,
+    batch_left: usize,
+    /// Batches of reads dispatched while writes wait.
+    starved: usize,
+}
+
+impl Elevator {
+    fn is_empty(&self) -> bool {
+        self.dirs.iter().all(Direction::is_empty)
+    }
+
+    fn add(&mut self, bio: Bio, num_blocks: u64, now: TimeValue) {
+        self.dirs[bio.op.index()].add(bio, num_blocks, now);
+    }
+
+    fn dispatch(&mut self, now: TimeValue) -> Option<Request> {
+        // Go on with the batch in sector order while it lasts.
+        if let Some(op) = self.batch_op {
+            if self.batch_left > 0 && !self.dirs[op.index()].is_empty() {
+                let req = self.dirs[op.index()].take_expired(now)
+                    .or_else(|| self.dirs[op.index()].take_next(self.head));
+                return req.map(|req| self.dispatched(req));
+            }
+        }
+
+        let reads = !self.dirs[BioOp::Read.index()].is_empty();
+        let writes = !self.dirs[BioOp::Write.index()].is_empty();
+        let op = if reads && (!writes || self.starved < WRITES_STARVED) {
+            if writes {
+                self.starved += 1;
+            }
+            BioOp::Read
+        } else if writes {
+            self.starved = 0;
+            BioOp::Write
+        } else {
+            return None;
+        };
+        self.batch_op = Some(op);
+        self.batch_left = FIFO_BATCH;
+        let dir = &mut self.dirs[op.index()];
+        let req = dir.take_expired(now).or_else(|| dir.take_next(self.head));
+        req.map(|req| self.dispatched(req))
+    }
+
+    fn dispatched(&mut self, req: Request) -> Request {
+        self.batch_left = self.batch_left.saturating_sub(1);
+        self.head = req.end();
+        req
+    }
+}
+
+struct QueueInner {
+    elevator: Elevator,
+    /// Whether some task is dispatching, synchronously.
+    dispatching: bool,
+    /// Requests posted to an asynchronous device, with their buffers, by
+    /// token.
+    inflight: BTreeMap<u16, (Request, Vec<u8>)>,
+}
+
+/// The request queue of a disk, which owns it.
+pub struct RequestQueue {
+    /// Boxed, as disks of other types than [`AxBlockDevice`], such as RAM
+    /// disks, have queues too.
+    dev: SpinNoIrq<Box<dyn BlockDriverOps + Send>>,
+    block_size: usize,
+    num_blocks: u64,
+    /// Requests the device takes at once, or 0 if it is synchronous.
+    depth: usize,
+    /// Its IRQ line, if it raises interrupts.
+    irq: Option<usize>,
+    inner: SpinNoIrq<QueueInner>,
+}
+
+/// Queues of asynchronous devices with an IRQ line, which [`handle_irq`]
+/// completes requests of.
+static IRQ_QUEUES: SpinNoIrq<Vec<Arc<RequestQueue>>> = SpinNoIrq::new(Vec::new());
+
+/// The interrupt handler of disks: completes what those on `irq` are done
+/// with.
+fn handle_irq(irq: usize, _dev_id: usize) -> IrqReturn {
+    let mut ret = IrqReturn::None;
+    for queue in IRQ_QUEUES.lock().iter().filter(|q| q.irq == Some(irq)) {
+        if queue.dev.lock().ack_interrupt() {
+            queue.complete();
+            ret = IrqReturn::Handled;
+        }
+    }
+    ret
+}
+
+/// Requests the line `irq` for the disks on it, unless one of them has
+/// already. Returns whether it is theirs.
+fn request_disk_irq(irq: usize) -> bool {
+    let queues = IRQ_QUEUES.lock();
+    if queues.iter().any(|q| q.irq == Some(irq)) {
+        return true;
+    }
+    axirq::request_irq(irq, handle_irq, IrqFlags::SHARED, "disk", 0).is_ok()
+}
+
+impl RequestQueue {
+    /// The queue of `dev`, whose IRQ line is `irq` if it is known.
+    pub fn new<D: BlockDriverOps + Send + 'static>(dev: D, irq: Option<usize>) -> Arc<Self> {
+        let depth = dev.queue_depth();
+        // Polled, if the line can't be had.
+        let irq = irq.filter(|&irq| depth > 0 && request_disk_irq(irq));
+        let queue = Arc::new(Self {
+            block_size: dev.block_size(),
+            num_blocks: dev.num_blocks(),
+            depth,
+            irq,
+            dev: SpinNoIrq::new(Box::new(dev)),
+            inner: SpinNoIrq::new(QueueInner {
+                elevator: Elevator::default(),
+                dispatching: false,
+                inflight: BTreeMap::new(),
+            }),
+        });
+        if irq.is_some() {
+            IRQ_QUEUES.lock().push(queue.clone());
+        }
+        queue
+    }
+
+    /// Whether waiters can sleep until an interrupt completes their
+    /// requests.
+    fn irq_driven(&self) -> bool {
+        self.irq.is_some() && axhal::arch::irqs_enabled()
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// Queues `bio`, and dispatches the queue if no one else is.
+    /// Its completion may be called before this returns.
+    pub fn submit_bio(&self, bio: Bio) {
+        self.submit_bios([bio]);
+    }
+
+    /// Queues all of `bios` before dispatching, so that those contiguous
+    /// are merged.
+    pub fn submit_bios(&self, bios: impl IntoIterator<Item = Bio>) {
+        let now = axhal::time::current_time();
+        {
+            let mut inner = self.inner.lock();
+            for bio in bios {
+                let num_blocks = bio.data.len().div_ceil(self.block_size) as u64;
+                let valid = !bio.data.is_empty()
+                    && bio.data.len() % self.block_size == 0
+                    && bio.block_id.checked_add(num_blocks).map_or(false, |end| end <= self.num_blocks);
+                if !valid {
+                    bio.end_io(Err(DevError::InvalidParam));
+                    continue;
+                }
+                ktrace::trace_event!(
+                    block_bio_queue,
+                    write = bio.op == BioOp::Write,
+                    sector = bio.block_id,
+                    nr = num_blocks,
+                );
+                inner.elevator.add(bio, num_blocks, now);
+            }
+        }
+        self.run_queue();
+    }
+
+    /// Submits a bio of `data` to read into or write from, and waits for it to complete.
+    pub fn submit_bio_wait(&self, op: BioOp, block_id: u64, data: Vec<u8>) -> (DevResult, Vec<u8>) {
+        self.submit_bios_wait(vec![(op, block_id, data)]).pop().unwrap()
+    }
+
+    /// Submits bios of (op, block_id, data) at once, and waits for all of
+    /// them. Returns their results in the same order.
+    pub fn submit_bios_wait(&self, bios: Vec<(BioOp, u64, Vec<u8>)>) -> Vec<(DevResult, Vec<u8>)> {
+        let waits: Vec<_> = bios.iter().map(|_| Arc::new(BioWait::new())).collect();
+        self.submit_bios(bios.into_iter().zip(waits.iter()).map(|((op, block_id, data), wait)| {
+            let wait = wait.clone();
+            Bio {
+                op,
+                block_id,
+                data,
+                end_io: Box::new(move |result, data| wait.complete(result, data)),
+            }
+        }));
+        waits.iter().map(|wait| self.wait(wait)).collect()
+    }
+
+    fn wait(&self, wait: &BioWait) -> (DevResult, Vec<u8>) {
+        if self.depth > 0 && !self.irq_driven() {
+            while !wait.is_done() {
+                self.complete();
+                core::hint::spin_loop();
+            }
+        }
+        wait.wait()
+    }
+
+    /// Dispatches what it can.
+    fn run_queue(&self) {
+        if self.depth > 0 {
+            self.post_requests();
+        } else {
+            self.dispatch_sync();
+        }
+    }
+
+    /// Posts requests to an asynchronous device while it has room, without
+    /// waiting for them.
+    fn post_requests(&self) {
+        let mut failed = Vec::new();
+        {
+            let mut inner = self.inner.lock();
+            let now = axhal::time::current_time();
+            while inner.inflight.len() < self.depth {
+                let Some(mut req) = inner.elevator.dispatch(now) else {
+                    break;
+                };
+                trace!("blk: post {:?} blocks {}..{}", req.op, req.start, req.end());
+                ktrace::trace_event!(
+                    block_rq_issue,
+                    write = req.op == BioOp::Write,
+                    sector = req.start,
+                    nr = req.num_blocks,
+                );
+                let mut buf = req.gather(self.block_size);
+                // Safety: the buffer stays in `inflight` until it completes.
+                let token = unsafe {
+                    match req.op {
+                        BioOp::Read => self.dev.lock().submit_read(req.start, buf.as_mut_ptr(), buf.len()),
+                        BioOp::Write => self.dev.lock().submit_write(req.start, buf.as_ptr(), buf.len()),
+                    }
+                };
+                match token {
+                    Ok(token) => {
+                        inner.inflight.insert(token, (req, buf));
+                    },
+                    Err(e) => failed.push((req, buf, e)),
+                }
+            }
+        }
+        for (req, buf, e) in failed {
+            req.complete(buf, Err(e));
+        }
+    }
+
+    /// Completes the requests an asynchronous device is done with, and
+    /// posts more. Called from the interrupt handler, or by waiters polling
+    /// while there is none.
+    pub fn complete(&self) {
+        let done: Vec<_> = {
+            let mut dev = self.dev.lock();
+            core::iter::from_fn(|| dev.poll_completion()).collect()
+        };
+        if done.is_empty() {
+            return;
+        }
+        // Posted under the lock, so all of them are in `inflight` by now.
+        let reqs: Vec<_> = {
+            let mut inner = self.inner.lock();
+            done.into_iter()
+                .filter_map(|(token, result)| {
+                    inner.inflight.remove(&token).map(|(req, buf)| (req, buf, result))
+                })
+                .collect()
+        };
+        for (req, buf, result) in reqs {
+            req.complete(buf, result);
+        }
+        self.post_requests();
+    }
+
+    /// Dispatches a synchronous device until the queue is empty, unless
+    /// someone else is at it.
+    fn dispatch_sync(&self) {
+        {
+            let mut inner = self.inner.lock();
+            if inner.dispatching {
+                return;
+            }
+            inner.dispatching = true;
+        }
+        loop {
+            let req = {
+                let mut inner = self.inner.lock();
+                match inner.elevator.dispatch(axhal::time::current_time()) {
+                    Some(req) => req,
+                    None => {
+                        inner.dispatching = false;
+                        return;
+                    },
+                }
+            };
+            self.issue(req);
+        }
+    }
+
+    /// Issues a request to the device in one call, and completes its bios.
+    fn issue(&self, mut req: Request) {
+        trace!("blk: {:?} blocks {}..{}", req.op, req.start, req.end());
+        ktrace::trace_event!(
+            block_rq_issue,
+            write = req.op == BioOp::Write,
+            sector = req.start,
+            nr = req.num_blocks,
+        );
+        let mut buf = req.gather(self.block_size);
+        let result = match req.op {
+            BioOp::Read => self.dev.lock().read_block(req.start, &mut buf),
+            BioOp::Write => self.dev.lock().write_block(req.start, &buf),
+        };
+        req.complete(buf, result);
+    }
+
+    /// Waits for all queued requests, and flushes the device.
+    pub fn flush(&self) -> DevResult {
+        self.run_queue();
+        while !self.is_idle() {
+            if self.depth > 0 && !self.irq_driven() {
+                self.complete();
+            }
+            run_queue::yield_now();
+        }
+        self.dev.lock().flush()
+    }
+
+    /// Writes `buf` to the device at once and flushes it, bypassing the
+    /// queue, for the crash dump. Gives up with `ResourceBusy` rather than
+    /// wait for the device, which the panicking CPU may hold.
+    pub fn panic_write(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        let Some(mut dev) = self.dev.try_lock() else {
+            return Err(DevError::ResourceBusy);
+        };
+        dev.write_block(block_id, buf)?;
+        dev.flush()
+    }
+
+    fn is_idle(&self) -> bool {
+        let inner = self.inner.lock();
+        !inner.dispatching && inner.elevator.is_empty() && inner.inflight.is_empty()
+    }
+}
+
+/// The completion a task waits on in [`RequestQueue::submit_bio_wait`].
+struct BioWait {
+    done: AtomicBool,
+    result: SpinNoIrq<Option<(DevResult, Vec<u8>)>>,
+    waiter: SpinNoIrq<Option<Tid>>,
+}
+
+impl BioWait {
+    fn new() -> Self {
+        Self {
+            done: AtomicBool::new(false),
+            result: SpinNoIrq::new(None),
+            waiter: SpinNoIrq::new(None),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    fn complete(&self, result: DevResult, data: Vec<u8>) {
+        *self.result.lock() = Some((result, data));
+        self.done.store(true, Ordering::Release);
+        if let Some(tid) = self.waiter.lock().take() {
+            run_queue::wake_up_task(tid);
+        }
+    }
+
+    /// Done already when the submitter dispatched or polled it itself;
+    /// else the task dispatching or the interrupt completes it.
+    fn wait(&self) -> (DevResult, Vec<u8>) {
+        if !self.done.load(Ordering::Acquire) {
+            let tid = taskctx::current_ctx().tid();
+            while !self.done.load(Ordering::Acquire) {
+                *self.waiter.lock() = Some(tid);
+                // Recheck after queueing, or a wakeup in between is missed.
+                if !self.done.load(Ordering::Acquire) {
+                    run_queue::block_current(TaskState::Uninterruptible);
+                }
+            }
+        }
+        self.result.lock().take().unwrap()
+    }
+}
@@ -0,0 +1,54 @@
+//! The real-time clock, which the wall clock is read from at boot and
+//! written to when it is set.
+//!
+//! The goldfish RTC of the QEMU virt machines counts the nanoseconds since
+//! the Epoch in two 32-bit registers. Reading the low half latches the
+//! high half, and writing the low half sets the whole count, so the
+//! halves are read low first and written high first.
+
+use page_table::ioremap::IoMem;
+use spinbase::SpinNoIrq;
+
+use crate::prelude::*;
+#[cfg(feature = "goldfish-rtc")]
+use crate::{dtb::DtbNode, AxDeviceEnum};
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// The registers of the RTC, if there is one.
+static GOLDFISH: SpinNoIrq<Option<IoMem>> = SpinNoIrq::new(None);
+
+/// Takes the goldfish RTC of a device tree node.
+#[cfg(feature = "goldfish-rtc")]
+pub(crate) fn probe_goldfish(node: &DtbNode) -> DevResult<Option<AxDeviceEnum>> {
+    let (base, size) = node.reg().ok_or(DevError::InvalidParam)?;
+    let mut rtc = GOLDFISH.lock();
+    if rtc.is_some() {
+        // Only the first is used.
+        return Err(DevError::AlreadyExists);
+    }
+    let mmio = page_table::ioremap::ioremap(base, size).map_err(|_| DevError::NoMemory)?;
+    info!("rtc: goldfish at {:#x}", base);
+    *rtc = Some(mmio);
+    Ok(None)
+}
+
+/// The time of the RTC, in nanoseconds since the Epoch, or None if there
+/// is none.
+pub fn read_time() -> Option<u64> {
+    let rtc = GOLDFISH.lock();
+    let mmio = rtc.as_ref()?;
+    let low = mmio.reg::<u32>(TIME_LOW).read();
+    let high = mmio.reg::<u32>(TIME_HIGH).read();
+    Some(((high as u64) << 32) | low as u64)
+}
+
+/// Sets the RTC to `nanos` since the Epoch.
+pub fn set_time(nanos: u64) -> DevResult {
+    let rtc = GOLDFISH.lock();
+    let mmio = rtc.as_ref().ok_or(DevError::Unsupported)?;
+    mmio.reg::<u32>(TIME_HIGH).write((nanos >> 32) as u32);
+    mmio.reg::<u32>(TIME_LOW).write(nanos as u32);
+    Ok(())
+}
@@ -12,6 +12,9 @@ pub type AxBlockDevice = Box<dyn BlockDriverOps>;
 /// The unified type of the graphics display devices.
 #[cfg(feature = "display")]
 pub type AxDisplayDevice = Box<dyn DisplayDriverOps>;
+/// The unified type of the character devices.
+#[cfg(feature = "char")]
+pub type AxCharDevice = Box<dyn CharDriverOps>;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -31,6 +34,12 @@ impl super::AxDeviceEnum {
     pub fn from_display(dev: impl DisplayDriverOps + 'static) -> Self {
         Self::Display(Box::new(dev))
     }
+
+    /// Constructs a character device.
+    #[cfg(feature = "char")]
+    pub fn from_char(dev: impl CharDriverOps + 'static) -> Self {
+        Self::Char(Box::new(dev))
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
@@ -4,6 +4,8 @@ pub use crate::drivers::AxBlockDevice;
 pub use crate::drivers::AxDisplayDevice;
 #[cfg(feature = "net")]
 pub use crate::drivers::AxNetDevice;
+#[cfg(feature = "char")]
+pub use crate::drivers::AxCharDevice;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -23,6 +25,12 @@ impl super::AxDeviceEnum {
     pub const fn from_display(dev: AxDisplayDevice) -> Self {
         Self::Display(dev)
     }
+
+    /// Constructs a character device.
+    #[cfg(feature = "char")]
+    pub const fn from_char(dev: AxCharDevice) -> Self {
+        Self::Char(dev)
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
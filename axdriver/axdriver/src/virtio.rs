@@ -1,22 +1,18 @@
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 
-use axalloc::global_allocator;
-use axhal::mem::{phys_to_virt, virt_to_phys};
+use axalloc::{dma_alloc_coherent, dma_free_coherent, dma_map_single, dma_unmap_single, DmaDirection};
+use axhal::mem::PAGE_SIZE_4K;
 use cfg_if::cfg_if;
 use driver_common::{BaseDriverOps, DevResult, DeviceType};
 use driver_virtio::{BufferDirection, PhysAddr, VirtIoHal};
+use page_table::ioremap::{ioremap, IoMem};
 
 use crate::{drivers::DriverProbe, AxDeviceEnum};
 
-cfg_if! {
-    if #[cfg(bus = "pci")] {
-        use driver_pci::{PciRoot, DeviceFunction, DeviceFunctionInfo};
-        type VirtIoTransport = driver_virtio::PciTransport;
-    } else if #[cfg(bus = "mmio")] {
-        type VirtIoTransport = driver_virtio::MmioTransport;
-    } 
-}
+#[cfg(bus = "pci")]
+use driver_pci::{PciRoot, DeviceFunction, DeviceFunctionInfo};
+use driver_virtio::VirtIoTransport;
 
 /// A trait for VirtIO device meta information.
 pub trait VirtIoDevMeta {
@@ -73,24 +69,38 @@ cfg_if! {
     }
 }
 
+cfg_if! {
+    if #[cfg(char_dev = "virtio-console")] {
+        pub struct VirtIoConsole;
+
+        impl VirtIoDevMeta for VirtIoConsole {
+            const DEVICE_TYPE: DeviceType = DeviceType::Char;
+            type Device = driver_virtio::VirtIoConsoleDev<VirtIoHalImpl, VirtIoTransport>;
+
+            fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
+                Ok(AxDeviceEnum::from_char(Self::Device::try_new(transport)?))
+            }
+        }
+    }
+}
+
 /// A common driver for all VirtIO devices that implements [`DriverProbe`].
 pub struct VirtIoDriver<D: VirtIoDevMeta + ?Sized>(PhantomData<D>);
 
 impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
     #[cfg(bus = "mmio")]
-    fn probe_mmio(mmio_base: usize, mmio_size: usize) -> Option<AxDeviceEnum> {
-        let base_vaddr = phys_to_virt(mmio_base.into());
+    fn probe_mmio(mmio: &IoMem) -> Option<AxDeviceEnum> {
         if let Some((ty, transport)) =
-            driver_virtio::probe_mmio_device(base_vaddr.as_mut_ptr(), mmio_size)
+            driver_virtio::probe_mmio_device(mmio.as_mut_ptr(), mmio.size())
         {
             if ty == D::DEVICE_TYPE {
-                match D::try_new(transport) {
+                match D::try_new(VirtIoTransport::Mmio(transport)) {
                     Ok(dev) => return Some(dev),
                     Err(e) => {
                         warn!(
                             "failed to initialize MMIO device at [PA:{:#x}, PA:{:#x}): {:?}",
-                            mmio_base,
-                            mmio_base + mmio_size,
+                            mmio.paddr(),
+                            mmio.paddr() + mmio.size(),
                             e
                         );
                         return None;
@@ -114,6 +124,7 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
             (DeviceType::Net, 0x1000) | (DeviceType::Net, 0x1040) => {}
             (DeviceType::Block, 0x1001) | (DeviceType::Block, 0x1041) => {}
             (DeviceType::Display, 0x1050) => {}
+            (DeviceType::Char, 0x1003) | (DeviceType::Char, 0x1043) => {}
             _ => return None,
         }
 
@@ -121,7 +132,7 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
             driver_virtio::probe_pci_device::<VirtIoHalImpl>(root, bdf, dev_info)
         {
             if ty == D::DEVICE_TYPE {
-                match D::try_new(transport) {
+                match D::try_new(VirtIoTransport::Pci(transport)) {
                     Ok(dev) => return Some(dev),
                     Err(e) => {
                         warn!(
@@ -141,32 +152,40 @@ pub struct VirtIoHalImpl;
 
 unsafe impl VirtIoHal for VirtIoHalImpl {
     fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
-        let vaddr = if let Ok(vaddr) = global_allocator().alloc_pages(pages, 0x1000) {
-            vaddr
-        } else {
+        let Ok((vaddr, paddr)) = dma_alloc_coherent(pages * PAGE_SIZE_4K) else {
             return (0, NonNull::dangling());
         };
-        let paddr = virt_to_phys(vaddr.into());
-        let ptr = NonNull::new(vaddr as _).unwrap();
-        (paddr.as_usize(), ptr)
+        (paddr.as_usize(), NonNull::new(vaddr.as_mut_ptr()).unwrap())
     }
 
     unsafe fn dma_dealloc(_paddr: PhysAddr, vaddr: NonNull<u8>, pages: usize) -> i32 {
-        global_allocator().dealloc_pages(vaddr.as_ptr() as usize, pages);
+        dma_free_coherent((vaddr.as_ptr() as usize).into(), pages * PAGE_SIZE_4K);
         0
     }
 
-    #[inline]
-    unsafe fn mmio_phys_to_virt(paddr: PhysAddr, _size: usize) -> NonNull<u8> {
-        NonNull::new(phys_to_virt(paddr.into()).as_mut_ptr()).unwrap()
+    /// Maps a BAR of a PCI function, for as long as the device lives.
+    unsafe fn mmio_phys_to_virt(paddr: PhysAddr, size: usize) -> NonNull<u8> {
+        let mem = ioremap(paddr, size).expect("virtio: cannot map BAR");
+        NonNull::new(mem.as_mut_ptr()).unwrap()
     }
 
     #[inline]
-    unsafe fn share(buffer: NonNull<[u8]>, _direction: BufferDirection) -> PhysAddr {
+    unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> PhysAddr {
         let vaddr = buffer.as_ptr() as *mut u8 as usize;
-        virt_to_phys(vaddr.into()).into()
+        dma_map_single(vaddr.into(), buffer.len(), dma_direction(direction)).into()
     }
 
     #[inline]
-    unsafe fn unshare(_paddr: PhysAddr, _buffer: NonNull<[u8]>, _direction: BufferDirection) {}
+    unsafe fn unshare(_paddr: PhysAddr, buffer: NonNull<[u8]>, direction: BufferDirection) {
+        let vaddr = buffer.as_ptr() as *mut u8 as usize;
+        dma_unmap_single(vaddr.into(), buffer.len(), dma_direction(direction));
+    }
+}
+
+fn dma_direction(direction: BufferDirection) -> DmaDirection {
+    match direction {
+        BufferDirection::DriverToDevice => DmaDirection::ToDevice,
+        BufferDirection::DeviceToDriver => DmaDirection::FromDevice,
+        BufferDirection::Both => DmaDirection::Bidirectional,
+    }
 }
@@ -0,0 +1,257 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use axdriver::bcache;
+use axdriver::queue::{Bio, BioOp, RequestQueue};
+use driver_block::BlockDriverOps;
+use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
+use spinbase::SpinNoIrq;
+
+const BLOCK_SIZE: usize = 512;
+const NUM_BLOCKS: u64 = 512;
+/// Reads of it fail.
+const BAD_BLOCK: u64 = 100;
+
+/// Requests issued to the [`MockDisk`]s, as (op, start, blocks).
+static ISSUED: SpinNoIrq<Vec<(BioOp, u64, u64)>> = SpinNoIrq::new(Vec::new());
+
+fn take_issued() -> Vec<(BioOp, u64, u64)> {
+    core::mem::take(&mut *ISSUED.lock())
+}
+
+/// A synchronous disk, whose blocks are first filled with their number,
+/// and which logs the requests it gets.
+struct MockDisk {
+    data: Vec<u8>,
+}
+
+impl MockDisk {
+    fn new() -> Self {
+        let mut data = vec![0; NUM_BLOCKS as usize * BLOCK_SIZE];
+        for (i, block) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+            block.fill(i as u8);
+        }
+        Self { data }
+    }
+
+    fn range(&self, block_id: u64, len: usize) -> core::ops::Range<usize> {
+        let start = block_id as usize * BLOCK_SIZE;
+        start..start + len
+    }
+}
+
+impl BaseDriverOps for MockDisk {
+    fn device_name(&self) -> &str {
+        "mock-disk"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+}
+
+impl BlockDriverOps for MockDisk {
+    fn num_blocks(&self) -> u64 {
+        NUM_BLOCKS
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let nr = (buf.len() / BLOCK_SIZE) as u64;
+        ISSUED.lock().push((BioOp::Read, block_id, nr));
+        if (block_id..block_id + nr).contains(&BAD_BLOCK) {
+            return Err(DevError::Io);
+        }
+        buf.copy_from_slice(&self.data[self.range(block_id, buf.len())]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        ISSUED.lock().push((BioOp::Write, block_id, (buf.len() / BLOCK_SIZE) as u64));
+        let range = self.range(block_id, buf.len());
+        self.data[range].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DevResult {
+        Ok(())
+    }
+}
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    assert_eq!(cpu_id, 0);
+
+    axlog2::init("info");
+    info!("[rt_axdriver]: ...");
+
+    axhal::arch_init_early(cpu_id);
+    axalloc::init();
+    page_table::init();
+    run_queue::init(cpu_id, dtb_pa);
+
+    test_merge_and_elevator();
+    test_max_request();
+    test_end_io();
+    test_bcache();
+
+    info!("[rt_axdriver]: ok!");
+    axhal::misc::terminate();
+}
+
+fn block(n: u8) -> Vec<u8> {
+    vec![n; BLOCK_SIZE]
+}
+
+/// Bios submitted together are merged with their neighbours, front or
+/// back, and the elevator issues reads before writes, in block order from
+/// where the disk was left.
+fn test_merge_and_elevator() {
+    let queue = RequestQueue::new(MockDisk::new(), None);
+    let results = queue.submit_bios_wait(vec![
+        (BioOp::Write, 5, block(0xa5)),
+        (BioOp::Read, 9, block(0)),
+        (BioOp::Write, 4, block(0xa4)),
+        (BioOp::Read, 20, block(0)),
+        (BioOp::Read, 8, block(0)),
+        (BioOp::Write, 10, block(0xaa)),
+    ]);
+    assert_eq!(take_issued(), [
+        (BioOp::Read, 8, 2),
+        (BioOp::Read, 20, 1),
+        (BioOp::Write, 4, 2),
+        (BioOp::Write, 10, 1),
+    ]);
+    // In the order submitted, each with its own part of the request.
+    let expected = [0xa5, 9, 0xa4, 20, 8, 0xaa];
+    for ((result, data), n) in results.into_iter().zip(expected) {
+        assert!(result.is_ok());
+        assert_eq!(data, block(n));
+    }
+
+    let (result, data) = queue.submit_bio_wait(BioOp::Read, 4, vec![0; 2 * BLOCK_SIZE]);
+    assert!(result.is_ok());
+    assert_eq!(data[..BLOCK_SIZE], block(0xa4)[..]);
+    assert_eq!(data[BLOCK_SIZE..], block(0xa5)[..]);
+    assert_eq!(take_issued(), [(BioOp::Read, 4, 2)]);
+
+    // A failed request fails all the bios merged in it.
+    let results = queue.submit_bios_wait(vec![
+        (BioOp::Read, BAD_BLOCK - 1, block(0)),
+        (BioOp::Read, BAD_BLOCK, block(0)),
+        (BioOp::Read, BAD_BLOCK + 1, block(0)),
+    ]);
+    assert!(results.iter().all(|(result, _)| matches!(result, Err(DevError::Io))));
+    assert_eq!(take_issued(), [(BioOp::Read, BAD_BLOCK - 1, 3)]);
+    info!("[rt_axdriver]: merging and elevator ok!");
+}
+
+/// Merging stops at the bound on the blocks of a request.
+fn test_max_request() {
+    let queue = RequestQueue::new(MockDisk::new(), None);
+    let bios = (0..300).map(|i| (BioOp::Write, i, block(i as u8))).collect();
+    let results = queue.submit_bios_wait(bios);
+    assert!(results.iter().all(|(result, _)| result.is_ok()));
+    assert_eq!(take_issued(), [(BioOp::Write, 0, 256), (BioOp::Write, 256, 44)]);
+    info!("[rt_axdriver]: request bound ok!");
+}
+
+static READ_DONE: AtomicBool = AtomicBool::new(false);
+static INVALID_DONE: AtomicBool = AtomicBool::new(false);
+
+/// The completion of a bio gets its result and data. That of a bio out
+/// of the disk, or not of whole blocks, is called without issuing it.
+fn test_end_io() {
+    let queue = RequestQueue::new(MockDisk::new(), None);
+    queue.submit_bio(Bio::read(7, 1, BLOCK_SIZE, Box::new(|result, data| {
+        assert!(result.is_ok());
+        assert_eq!(data, block(7));
+        READ_DONE.store(true, Ordering::Release);
+    })));
+    // A synchronous disk is done before the submitter returns.
+    assert!(READ_DONE.load(Ordering::Acquire));
+    assert_eq!(take_issued(), [(BioOp::Read, 7, 1)]);
+
+    queue.submit_bio(Bio::read(NUM_BLOCKS - 1, 2, BLOCK_SIZE, Box::new(|result, _| {
+        assert!(matches!(result, Err(DevError::InvalidParam)));
+        INVALID_DONE.store(true, Ordering::Release);
+    })));
+    assert!(INVALID_DONE.load(Ordering::Acquire));
+    let (result, _) = queue.submit_bio_wait(BioOp::Write, 0, vec![0; BLOCK_SIZE + 1]);
+    assert!(matches!(result, Err(DevError::InvalidParam)));
+    assert!(take_issued().is_empty());
+    info!("[rt_axdriver]: completions ok!");
+}
+
+/// Blocks are read once into the cache, and writes stay there till they
+/// are written back, by a sync or before a direct read of them.
+fn test_bcache() {
+    let queue = RequestQueue::new(MockDisk::new(), None);
+    let mut buf = vec![0; 2 * BLOCK_SIZE];
+    assert!(bcache::read(&queue, 3, &mut buf).is_ok());
+    assert_eq!(buf[..BLOCK_SIZE], block(3)[..]);
+    assert_eq!(buf[BLOCK_SIZE..], block(4)[..]);
+    assert_eq!(take_issued(), [(BioOp::Read, 3, 2)]);
+    assert!(bcache::read(&queue, 3, &mut buf).is_ok());
+    assert!(take_issued().is_empty());
+
+    // The misses are read at once, the hits among them too.
+    let mut buf = vec![0; 4 * BLOCK_SIZE];
+    assert!(bcache::read(&queue, 2, &mut buf).is_ok());
+    assert!(buf.chunks(BLOCK_SIZE).zip(2..).all(|(chunk, n)| chunk == &block(n)[..]));
+    assert_eq!(take_issued(), [(BioOp::Read, 2, 4)]);
+    assert!(matches!(bcache::read(&queue, 2, &mut buf[1..]), Err(DevError::InvalidParam)));
+
+    let mut data = block(0xb0);
+    data.extend(block(0xb1));
+    assert!(bcache::write(&queue, 10, &data).is_ok());
+    let mut buf = vec![0; 2 * BLOCK_SIZE];
+    assert!(bcache::read(&queue, 10, &mut buf).is_ok());
+    assert_eq!(buf, data);
+    assert!(take_issued().is_empty());
+
+    // A direct read gets what was written, as the dirty blocks it reads
+    // are written back first.
+    let mut buf = block(0);
+    assert!(bcache::read_direct(&queue, 10, &mut buf).is_ok());
+    assert_eq!(buf, block(0xb0));
+    assert_eq!(take_issued(), [(BioOp::Write, 10, 1), (BioOp::Read, 10, 1)]);
+    assert!(bcache::sync_disk(&queue).is_ok());
+    assert_eq!(take_issued(), [(BioOp::Write, 11, 1)]);
+    assert!(bcache::sync_disk(&queue).is_ok());
+    assert!(take_issued().is_empty());
+
+    // A direct write drops the cached block, and the next read gets it
+    // from the disk.
+    assert!(bcache::write_direct(&queue, 3, &block(0xc3)).is_ok());
+    assert_eq!(take_issued(), [(BioOp::Write, 3, 1)]);
+    assert!(bcache::read(&queue, 3, &mut buf).is_ok());
+    assert_eq!(buf, block(0xc3));
+    assert_eq!(take_issued(), [(BioOp::Read, 3, 1)]);
+
+    // Invalidated, the clean blocks are read again.
+    bcache::invalidate(&queue);
+    assert!(bcache::read(&queue, 4, &mut buf).is_ok());
+    assert_eq!(buf, block(4));
+    assert_eq!(take_issued(), [(BioOp::Read, 4, 1)]);
+    info!("[rt_axdriver]: buffer cache ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
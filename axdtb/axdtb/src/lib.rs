@@ -10,7 +10,9 @@
 use core::str;
 use axtype::align_up;
 
+mod node;
 mod util;
+pub use crate::node::{read_cells, Children, DtbNodeRef};
 pub use crate::util::SliceRead;
 
 extern crate alloc;
@@ -21,6 +23,7 @@ const SUPPORTED_VERSION: u32 = 17;
 const OF_DT_BEGIN_NODE : u32 = 0x00000001;
 const OF_DT_END_NODE   : u32 = 0x00000002;
 const OF_DT_PROP       : u32 = 0x00000003;
+const OF_DT_NOP        : u32 = 0x00000004;
 
 /// Represents possible errors that can occur during DTB parsing.
 #[derive(Debug)]
@@ -70,10 +73,14 @@ impl DeviceTree {
 
 impl DeviceTree {
     /// Parse the device tree structure and invoke a callback for each node.
+    ///
+    /// The callback gets the `#address-cells` and `#size-cells` of the
+    /// parent of the node, which its `reg` is in, and its children get its
+    /// own.
     pub fn parse(
         &self, mut pos: usize,
-        mut addr_cells: usize,
-        mut size_cells: usize,
+        addr_cells: usize,
+        size_cells: usize,
         cb: &mut dyn FnMut(String, usize, usize, Vec<(String, Vec<u8>)>)
     ) -> DeviceTreeResult<usize> {
         let buf = unsafe {
@@ -91,6 +98,8 @@ impl DeviceTree {
 
         // First, read all the props.
         let mut props = Vec::new();
+        let mut child_addr_cells = addr_cells;
+        let mut child_size_cells = size_cells;
         while buf.read_be_u32(pos)? == OF_DT_PROP {
             let val_size = buf.read_be_u32(pos+4)? as usize;
             let name_offset = buf.read_be_u32(pos+8)? as usize;
@@ -105,9 +114,9 @@ impl DeviceTree {
 
             let prop_name = str::from_utf8(prop_name)?.to_owned();
             if prop_name == "#address-cells" {
-                addr_cells = val.read_be_u32(0)? as usize;
+                child_addr_cells = val.read_be_u32(0)? as usize;
             } else if prop_name == "#size-cells" {
-                size_cells = val.read_be_u32(0)? as usize;
+                child_size_cells = val.read_be_u32(0)? as usize;
             }
 
             props.push((prop_name, val.to_owned()));
@@ -121,7 +130,7 @@ impl DeviceTree {
 
         // Then, parse all its children.
         while buf.read_be_u32(pos)? == OF_DT_BEGIN_NODE {
-            pos = self.parse(pos, addr_cells, size_cells, cb)?;
+            pos = self.parse(pos, child_addr_cells, child_size_cells, cb)?;
         }
 
         if buf.read_be_u32(pos)? != OF_DT_END_NODE {
@@ -0,0 +1,226 @@
+//! Lookups of single nodes by their paths, which allocate nothing, so that
+//! they can be done at early boot, before the heap is up.
+
+use core::str;
+
+use crate::util::SliceRead;
+use crate::{DeviceTree, DeviceTreeError, DeviceTreeResult, OF_DT_BEGIN_NODE, OF_DT_END_NODE, OF_DT_NOP, OF_DT_PROP};
+use axtype::align_up;
+
+/// A node found by [`DeviceTree::find_node`].
+pub struct DtbNodeRef<'a> {
+    dt: &'a DeviceTree,
+    /// The start of its properties.
+    props: usize,
+    /// The end of its properties, where its children start.
+    children: usize,
+    /// `#address-cells` and `#size-cells` of its parent, which its `reg` is in.
+    pub addr_cells: usize,
+    pub size_cells: usize,
+}
+
+impl<'a> DtbNodeRef<'a> {
+    /// The value of property `name`.
+    pub fn prop(&self, name: &str) -> Option<&'a [u8]> {
+        let buf = self.dt.buf();
+        let mut pos = self.props;
+        while pos < self.children {
+            match buf.read_be_u32(pos).ok()? {
+                OF_DT_PROP => {
+                    let len = buf.read_be_u32(pos + 4).ok()? as usize;
+                    let name_offset = buf.read_be_u32(pos + 8).ok()? as usize;
+                    let prop_name = buf.read_bstring0(self.dt.off_strings + name_offset).ok()?;
+                    if prop_name == name.as_bytes() {
+                        return buf.get(pos + 12..pos + 12 + len);
+                    }
+                    pos = align_up(pos + 12 + len, 4);
+                }
+                _ => pos += 4,
+            }
+        }
+        None
+    }
+
+    /// The value of property `name`, a single cell.
+    pub fn prop_u32(&self, name: &str) -> Option<u32> {
+        self.prop(name)?.read_be_u32(0).ok()
+    }
+
+    /// The value of property `name`, a string, without its NUL.
+    pub fn prop_str(&self, name: &str) -> Option<&'a str> {
+        let val = self.prop(name)?;
+        let end = val.iter().position(|c| *c == 0).unwrap_or(val.len());
+        str::from_utf8(&val[..end]).ok()
+    }
+
+    /// The strings of the `compatible` property.
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.prop("compatible")
+            .unwrap_or_default()
+            .split(|c| *c == 0)
+            .filter_map(|s| str::from_utf8(s).ok())
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Its children, with their names.
+    pub fn children(&self) -> Children<'a> {
+        Children {
+            dt: self.dt,
+            pos: self.children,
+            addr_cells: self.prop_u32("#address-cells").map_or(2, |n| n as usize),
+            size_cells: self.prop_u32("#size-cells").map_or(1, |n| n as usize),
+        }
+    }
+
+    /// (base, size) of the regions of its `reg` property.
+    pub fn regs(&self) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let reg = self.prop("reg").unwrap_or_default();
+        let (addr_cells, size_cells) = (self.addr_cells, self.size_cells);
+        let cells = addr_cells + size_cells;
+        (0..(reg.len() / 4).checked_div(cells).unwrap_or(0)).map_while(move |i| Some((
+            read_cells(reg, i * cells, addr_cells)?,
+            read_cells(reg, i * cells + addr_cells, size_cells)?,
+        )))
+    }
+
+    /// (base, size) of the first region of its `reg` property.
+    pub fn reg(&self) -> Option<(usize, usize)> {
+        self.regs().next()
+    }
+}
+
+/// Reads the big-endian number of `cells` cells at cell `pos` of `buf`,
+/// as the addresses and sizes of `reg` and `ranges` are.
+pub fn read_cells(buf: &[u8], pos: usize, cells: usize) -> Option<usize> {
+    (0..cells).try_fold(0usize, |acc, i| {
+        Some((acc << 32) | buf.read_be_u32((pos + i) * 4).ok()? as usize)
+    })
+}
+
+/// The iterator of [`DtbNodeRef::children`].
+pub struct Children<'a> {
+    dt: &'a DeviceTree,
+    pos: usize,
+    addr_cells: usize,
+    size_cells: usize,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = (&'a str, DtbNodeRef<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.dt.buf().read_be_u32(self.pos).ok()? {
+                OF_DT_BEGIN_NODE => {
+                    let (name, child) = self.dt.node_at(self.pos, self.addr_cells, self.size_cells).ok()?;
+                    self.pos = self.dt.skip_node(self.pos).ok()?;
+                    return Some((str::from_utf8(name).ok()?, child));
+                }
+                OF_DT_NOP => self.pos += 4,
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Whether a node named `name` is the `comp` of a path, which may leave
+/// out the unit address.
+fn name_matches(name: &str, comp: &str) -> bool {
+    if comp.contains('@') {
+        name == comp
+    } else {
+        name.split('@').next() == Some(comp)
+    }
+}
+
+impl DeviceTree {
+    pub(crate) fn buf(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr as *const u8, self.totalsize) }
+    }
+
+    /// The node at `pos`, its BEGIN_NODE token, with its name.
+    fn node_at(&self, pos: usize, addr_cells: usize, size_cells: usize) -> DeviceTreeResult<(&[u8], DtbNodeRef<'_>)> {
+        let buf = self.buf();
+        if buf.read_be_u32(pos)? != OF_DT_BEGIN_NODE {
+            return Err(DeviceTreeError::ParseError(pos));
+        }
+        let name = buf.get(pos + 4..).ok_or(DeviceTreeError::SliceReadError)?;
+        let len = name.iter().position(|c| *c == 0).ok_or(DeviceTreeError::SliceReadError)?;
+        let name = &name[..len];
+        let props = align_up(pos + 4 + name.len() + 1, 4);
+        let mut end = props;
+        loop {
+            match buf.read_be_u32(end)? {
+                OF_DT_PROP => end = align_up(end + 12 + buf.read_be_u32(end + 4)? as usize, 4),
+                OF_DT_NOP => end += 4,
+                _ => break,
+            }
+        }
+        let node = DtbNodeRef {
+            dt: self,
+            props,
+            children: end,
+            addr_cells,
+            size_cells,
+        };
+        Ok((name, node))
+    }
+
+    /// The position past the end of the node at `pos`, with its children.
+    fn skip_node(&self, mut pos: usize) -> DeviceTreeResult<usize> {
+        let buf = self.buf();
+        let mut depth = 0;
+        loop {
+            match buf.read_be_u32(pos)? {
+                OF_DT_BEGIN_NODE => {
+                    depth += 1;
+                    pos = align_up(pos + 4 + buf.read_bstring0(pos + 4)?.len() + 1, 4);
+                }
+                OF_DT_END_NODE => {
+                    depth -= 1;
+                    pos += 4;
+                    if depth == 0 {
+                        return Ok(pos);
+                    }
+                }
+                OF_DT_PROP => pos = align_up(pos + 12 + buf.read_be_u32(pos + 4)? as usize, 4),
+                OF_DT_NOP => pos += 4,
+                _ => return Err(DeviceTreeError::ParseError(pos)),
+            }
+        }
+    }
+
+    /// The node of the absolute `path`, as "/soc/serial@10000000", whose
+    /// components may leave out the unit addresses.
+    pub fn find_node(&self, path: &str) -> Option<DtbNodeRef<'_>> {
+        // The defaults of the spec for the children of the root.
+        let (_, mut node) = self.node_at(self.off_struct, 2, 1).ok()?;
+        for comp in path.split('/').filter(|c| !c.is_empty()) {
+            node = node.children().find(|(name, _)| name_matches(name, comp))?.1;
+        }
+        Some(node)
+    }
+
+    /// The path that the alias `name` of /aliases stands for.
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.find_node("/aliases")?.prop_str(name)
+    }
+
+    /// The path of the node of the console, of the `stdout-path` of
+    /// /chosen, without its options after ':', and with its alias resolved.
+    pub fn stdout_path(&self) -> Option<&str> {
+        let chosen = self.find_node("/chosen")?;
+        let path = chosen.prop_str("stdout-path").or_else(|| chosen.prop_str("linux,stdout-path"))?;
+        let path = path.split(':').next()?;
+        if path.starts_with('/') {
+            Some(path)
+        } else {
+            self.alias(path)
+        }
+    }
+
+    /// The node of the console, of the `stdout-path` of /chosen.
+    pub fn stdout_node(&self) -> Option<DtbNodeRef<'_>> {
+        self.find_node(self.stdout_path()?)
+    }
+}
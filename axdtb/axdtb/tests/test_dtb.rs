@@ -11,8 +11,9 @@ fn test_dtb() {
     let mut cb = |name: String, addr_cells: usize, size_cells: usize, props: Vec<(String, Vec<u8>)>| {
         match name.as_str() {
             "" => {
-                assert_eq!(addr_cells, 2);
-                assert_eq!(size_cells, 2);
+                // The root has no parent, it gets what the caller passed.
+                assert_eq!(addr_cells, 0);
+                assert_eq!(size_cells, 0);
                 for prop in &props {
                     if prop.0.as_str() == "compatible" {
                         assert_eq!(str::from_utf8(&(prop.1)), Ok("riscv-virtio\0"));
@@ -48,3 +49,56 @@ fn test_dtb() {
     let dt = axdtb::DeviceTree::init(buf.as_slice().as_ptr() as usize).unwrap();
     assert_eq!(dt.parse(dt.off_struct, 0, 0, &mut cb).unwrap(), 396);
 }
+
+#[test]
+fn test_stdout_path() {
+    let mut input = std::fs::File::open("tests/chosen.dtb").unwrap();
+    let mut buf = Vec::new();
+    input.read_to_end(&mut buf).unwrap();
+
+    let dt = axdtb::DeviceTree::init(buf.as_slice().as_ptr() as usize).unwrap();
+    assert_eq!(dt.alias("serial0"), Some("/soc/serial@10000000"));
+    assert_eq!(dt.stdout_path(), Some("/soc/serial@10000000"));
+
+    let uart = dt.stdout_node().unwrap();
+    assert_eq!(uart.compatible().collect::<Vec<_>>(), ["ns16550a"]);
+    assert_eq!(uart.reg(), Some((0x10000000, 0x100)));
+    assert_eq!(uart.prop_u32("reg-shift"), Some(0));
+
+    let virtio = dt.find_node("/soc/virtio_mmio").unwrap();
+    assert_eq!(virtio.reg(), Some((0x10001000, 0x1000)));
+    assert!(dt.find_node("/soc/serial@10001000").is_none());
+    assert!(dt.find_node("/memory").is_none());
+}
+
+#[test]
+fn test_children() {
+    let mut input = std::fs::File::open("tests/chosen.dtb").unwrap();
+    let mut buf = Vec::new();
+    input.read_to_end(&mut buf).unwrap();
+
+    let dt = axdtb::DeviceTree::init(buf.as_slice().as_ptr() as usize).unwrap();
+    let soc = dt.find_node("/soc").unwrap();
+    let names: Vec<&str> = soc.children().map(|(name, _)| name).collect();
+    assert_eq!(names, ["virtio_mmio@10001000", "serial@10000000"]);
+    let root: Vec<&str> = dt.find_node("/").unwrap().children().map(|(name, _)| name).collect();
+    assert_eq!(root, ["chosen", "aliases", "soc"]);
+}
+
+#[test]
+fn test_read_cells() {
+    let buf = [0, 0, 0, 0x1, 0x80, 0, 0, 0, 0, 0, 0x10, 0];
+    assert_eq!(axdtb::read_cells(&buf, 0, 2), Some(0x1_8000_0000));
+    assert_eq!(axdtb::read_cells(&buf, 2, 1), Some(0x1000));
+    assert_eq!(axdtb::read_cells(&buf, 0, 0), Some(0));
+    assert_eq!(axdtb::read_cells(&buf, 2, 2), None);
+
+    let mut input = std::fs::File::open("tests/chosen.dtb").unwrap();
+    let mut dtb = Vec::new();
+    input.read_to_end(&mut dtb).unwrap();
+
+    let dt = axdtb::DeviceTree::init(dtb.as_slice().as_ptr() as usize).unwrap();
+    let uart = dt.find_node("/soc/serial").unwrap();
+    assert_eq!(uart.regs().collect::<Vec<_>>(), [(0x10000000, 0x100)]);
+    assert_eq!(dt.find_node("/chosen").unwrap().regs().count(), 0);
+}
@@ -95,6 +95,14 @@ pub enum AxError {
     PermDenied,
     /// Too many symbolic links encountered
     TooManyLinks,
+    /// No data available, e.g. a missing extended attribute
+    NoData,
+    /// Result too large for the supplied buffer
+    OutOfRange,
+    /// The operation was interrupted by a signal
+    Interrupted,
+    /// Inappropriate ioctl for the device, e.g. a terminal request to a file
+    NotATty,
 }
 
 /// A specialized [`Result`] type with [`AxError`] as the error type.
@@ -239,6 +247,10 @@ impl AxError {
             NoPermission => "Operation not permitted",
             PermDenied => "Permission denied",
             TooManyLinks => "Too many symbolic links encountered",
+            NoData => "No data available",
+            OutOfRange => "Result out of range",
+            Interrupted => "Interrupted system call",
+            NotATty => "Inappropriate ioctl for device",
         }
     }
 
@@ -272,6 +284,15 @@ impl From<LinuxError> for AxError {
         use AxError::*;
         match e {
             LinuxError::ENOENT => NotFound,
+            LinuxError::EEXIST => AlreadyExists,
+            LinuxError::EINVAL => InvalidInput,
+            LinuxError::EIO => Io,
+            LinuxError::ENOSPC => StorageFull,
+            LinuxError::EACCES => PermDenied,
+            LinuxError::ENODATA => NoData,
+            LinuxError::ERANGE => OutOfRange,
+            LinuxError::EOPNOTSUPP => Unsupported,
+            LinuxError::EINTR => Interrupted,
             _ => todo!("{:?}", e),
         }
     }
@@ -305,6 +326,10 @@ impl From<AxError> for LinuxError {
             NoPermission => LinuxError::EPERM,
             PermDenied => LinuxError::EACCES,
             TooManyLinks => LinuxError::ELOOP,
+            NoData => LinuxError::ENODATA,
+            OutOfRange => LinuxError::ERANGE,
+            Interrupted => LinuxError::EINTR,
+            NotATty => LinuxError::ENOTTY,
         }
     }
 }
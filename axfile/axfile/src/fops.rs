@@ -1,13 +1,19 @@
 //! Low-level filesystem operations.
 
 use axerrno::{ax_err, ax_err_type, AxResult};
-use axfs_vfs::{VfsError, VfsNodeRef, VfsNodeType};
-use axio::SeekFrom;
+use axfs_vfs::{VfsError, VfsNodeRef, VfsNodeType, LinuxDirent64};
+use axio::{Interest, PollState, Read, Seek, SeekFrom, Write};
 use capability::{Cap, WithCap};
 use core::fmt;
 use fstree::FsStruct;
 use alloc::collections::BTreeMap;
-use axtype::{O_DIRECTORY, O_NOATIME, O_PATH};
+use axtype::{O_DIRECTORY, O_NOATIME, O_PATH, PAGE_SIZE};
+use axtype::{O_ACCMODE, O_RDONLY, O_WRONLY, O_RDWR, O_APPEND, O_NONBLOCK, O_ASYNC, O_DIRECT};
+use axtype::{O_CREAT, O_EXCL, O_NOCTTY, O_TRUNC, O_CLOEXEC};
+use axtype::Cred;
+use axtype::{POSIX_FADV_NORMAL, POSIX_FADV_RANDOM, POSIX_FADV_SEQUENTIAL};
+use axtype::{POSIX_FADV_WILLNEED, POSIX_FADV_DONTNEED, POSIX_FADV_NOREUSE};
+use crate::readahead::ReadAhead;
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
@@ -29,10 +35,22 @@ pub type FilePerm = axfs_vfs::VfsNodePerm;
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
+    /// The file status flags (O_APPEND, O_NONBLOCK...) it was opened with,
+    /// or that fcntl set.
+    status_flags: i32,
+    /// The node itself fails with `WouldBlock` for O_NONBLOCK, rather than
+    /// the file polling it first.
+    node_nonblock: bool,
     offset: u64,
+    ra: ReadAhead,
     pub shared_map: BTreeMap<usize, usize>,
 }
 
+/// Flags that only take effect at open, and are not kept as status flags.
+const OPEN_ONLY_FLAGS: i32 = O_CREAT | O_EXCL | O_NOCTTY | O_TRUNC | O_CLOEXEC;
+/// Status flags that F_SETFL may change.
+const SETFL_MASK: i32 = O_APPEND | O_NONBLOCK | O_ASYNC | O_DIRECT | O_NOATIME;
+
 /*
 type OpenOp = fn(u32) -> u32;
 type ReadOp = fn(u32, u32) -> u32;
@@ -218,7 +236,10 @@ impl File {
         Self {
             node: WithCap::new(node, cap),
             is_append: false,
+            status_flags: 0,
+            node_nonblock: false,
             offset: 0,
+            ra: ReadAhead::new(),
             shared_map: BTreeMap::new(),
         }
     }
@@ -227,7 +248,7 @@ impl File {
         self.node.access(Cap::empty()).unwrap().get_ino()
     }
 
-    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions, fs: &FsStruct, uid: u32, gid: u32) -> AxResult<Self> {
+    fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions, fs: &FsStruct, cred: &Cred) -> AxResult<Self> {
         info!("open file: {} {:?} flags {:#o}", path, opts, opts._custom_flags);
         if !opts.is_valid() {
             return ax_err!(InvalidInput);
@@ -245,7 +266,7 @@ impl File {
                     node
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => fs.create_file(dir, path, VfsNodeType::File, uid, gid, opts._mode)?,
+                Err(VfsError::NotFound) => fs.create_file(dir, path, VfsNodeType::File, cred.fsuid, cred.fsgid, opts._mode)?,
                 Err(e) => return Err(e),
             }
         } else {
@@ -258,7 +279,7 @@ impl File {
         let attr = node.get_attr()?;
 
         if (opts._custom_flags & O_NOATIME) != 0 {
-            if attr.uid() != uid {
+            if attr.uid() != cred.fsuid && !cred.may_override() {
                 return ax_err!(NoPermission);
             }
         }
@@ -277,9 +298,10 @@ impl File {
         if opts.create || opts.create_new {
             mask = 0;
         }
-        Self::may_open(mask, uid, gid, attr)?;
+        Self::may_open(mask, cred, attr)?;
 
         node.open(opts._custom_flags)?;
+        let node = node.open_clone(opts._custom_flags)?.unwrap_or(node);
         if opts.truncate {
             node.truncate(0)?;
         }
@@ -290,12 +312,19 @@ impl File {
             access_cap
         };
 
-        Ok(Self {
+        let mut file = Self {
             node: WithCap::new(node, cap),
             is_append: opts.append,
+            status_flags: opts._custom_flags & !(O_ACCMODE | OPEN_ONLY_FLAGS),
+            node_nonblock: false,
             offset: 0,
+            ra: ReadAhead::new(),
             shared_map: BTreeMap::new(),
-        })
+        };
+        if file.is_nonblocking() {
+            file.sync_nonblock();
+        }
+        Ok(file)
     }
 
     fn cap_to_linux_mask(cap: Cap) -> u32 {
@@ -312,19 +341,30 @@ impl File {
         ret
     }
 
-    fn may_open(mask: u32, uid: u32, gid: u32, attr: FileAttr) -> AxResult {
+    /// Checks the rwx bits in `mask` on a node with `attr` against `cred`,
+    /// with the owner, group and other classes as usual.
+    pub fn may_open(mask: u32, cred: &Cred, attr: FileAttr) -> AxResult {
         let fsuid = attr.uid();
         let fsgid = attr.gid();
         let mut mode = attr.perm().mode();
         info!("may_open: mask {:#o} uid {:#x}, gid {:#x}, fsuid {:#x} fsgid {:#x} mode {:#o}",
-            mask, uid, gid, fsuid, fsgid, mode);
+            mask, cred.fsuid, cred.fsgid, fsuid, fsgid, mode);
 
         if attr.is_symlink() {
             return ax_err!(TooManyLinks);
         }
 
+        // Root may read and write anything, but execute only what
+        // someone can.
+        if cred.may_override() {
+            if (mask & 0o1) != 0 && !attr.is_dir() && (mode & 0o111) == 0 {
+                return ax_err!(PermDenied);
+            }
+            return Ok(());
+        }
+
         // Are we the owner? If so, ACL's don't matter.
-        if uid == fsuid {
+        if cred.fsuid == fsuid {
             mode >>= 6;
             if (mask & !mode) != 0 {
                 return ax_err!(PermDenied);
@@ -337,7 +377,7 @@ impl File {
         // the other permissions in the bits we care
         // about? Need to check group ownership if so.
         //
-        if (mask & (mode ^ (mode >> 3))) != 0 {
+        if (mask & (mode ^ (mode >> 3))) != 0 && cred.in_group(fsgid) {
             mode >>= 3;
         }
 
@@ -349,9 +389,9 @@ impl File {
     }
 
     /// Opens a file at the path relative to the current directory. Returns a
-    /// [`File`] object.
-    pub fn open(path: &str, opts: &OpenOptions, fs: &FsStruct, uid: u32, gid: u32) -> AxResult<Self> {
-        Self::_open_at(None, path, opts, fs, uid, gid)
+    /// [`File`] object. Access is checked against `cred`.
+    pub fn open(path: &str, opts: &OpenOptions, fs: &FsStruct, cred: &Cred) -> AxResult<Self> {
+        Self::_open_at(None, path, opts, fs, cred)
     }
 
     /// Truncates the file to the specified size.
@@ -367,7 +407,14 @@ impl File {
             return ax_err!(NotADirectory);
         }
         let read_len = node.getdents(self.offset, buf)?;
-        self.offset += read_len as u64;
+        // Resume at `d_off` of the last record, it's up to the filesystem
+        // what the offset means.
+        let mut pos = 0;
+        while pos < read_len {
+            let dirent = unsafe { &*(buf.as_ptr().add(pos) as *const LinuxDirent64) };
+            self.offset = dirent.d_off as u64;
+            pos += dirent.d_reclen as usize;
+        }
         Ok(read_len)
     }
 
@@ -380,7 +427,14 @@ impl File {
         if node.get_attr()?.is_dir() {
             return ax_err!(IsADirectory);
         }
-        let read_len = node.read_at(self.offset, buf)?;
+        if self.would_block(Interest::READABLE)? {
+            return ax_err!(WouldBlock);
+        }
+        let direct = self.is_direct(self.offset, buf.len());
+        if !direct {
+            self.ra.on_read(node, self.offset, buf.len());
+        }
+        let read_len = node_read(node, self.offset, buf, direct)?;
         self.offset += read_len as u64;
         Ok(read_len)
     }
@@ -393,7 +447,7 @@ impl File {
         if node.get_attr()?.is_dir() {
             return ax_err!(IsADirectory);
         }
-        node.read_at(offset, buf)
+        node_read(node, offset, buf, self.is_direct(offset, buf.len()))
     }
 
     /// Writes the file at the current position. Returns the number of bytes
@@ -403,21 +457,34 @@ impl File {
     /// written.
     pub fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
         let node = self.node.access(Cap::WRITE)?;
+        if self.would_block(Interest::WRITABLE)? {
+            return ax_err!(WouldBlock);
+        }
         if self.is_append {
             self.offset = self.get_attr()?.size();
         };
-        let write_len = node.write_at(self.offset, buf)?;
+        let write_len = node_write(node, self.offset, buf, self.is_direct(self.offset, buf.len()))?;
         self.offset += write_len as u64;
         Ok(write_len)
     }
 
+    /// Returns where the next [`write`](File::write) starts, which is the end
+    /// of the file when opened for appending.
+    pub fn write_pos(&self) -> AxResult<u64> {
+        if self.is_append {
+            Ok(self.get_attr()?.size())
+        } else {
+            Ok(self.offset)
+        }
+    }
+
     /// Writes the file at the given position. Returns the number of bytes
     /// written.
     ///
     /// It does not update the file cursor.
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
         let node = self.node.access(Cap::WRITE)?;
-        let write_len = node.write_at(offset, buf)?;
+        let write_len = node_write(node, offset, buf, self.is_direct(offset, buf.len()))?;
         Ok(write_len)
     }
 
@@ -444,6 +511,73 @@ impl File {
         Ok(new_offset)
     }
 
+    /// Advises how `len` bytes of the file from `offset` are about to be
+    /// accessed, as posix_fadvise does, all of it from `offset` on if `len`
+    /// is 0. Only the readahead heeds it.
+    pub fn fadvise(&mut self, offset: u64, len: u64, advice: usize) -> AxResult {
+        let node = self.node.access(Cap::empty())?;
+        match advice {
+            POSIX_FADV_NORMAL | POSIX_FADV_RANDOM | POSIX_FADV_SEQUENTIAL => {
+                self.ra.set_advice(advice);
+            }
+            POSIX_FADV_WILLNEED => {
+                let len = if len == 0 { usize::MAX } else { len as usize };
+                node.readahead(offset, len)?;
+            }
+            POSIX_FADV_DONTNEED | POSIX_FADV_NOREUSE => {}
+            _ => return ax_err!(InvalidInput),
+        }
+        Ok(())
+    }
+
+    /// Polls the file for readiness.
+    pub fn poll(&self) -> AxResult<PollState> {
+        self.node.access(Cap::empty())?.poll()
+    }
+
+    /// Whether the file is ready for any of what `interest` asks for. The
+    /// nodes that can't be polled, such as regular files, are always ready.
+    pub fn poll_ready(&self, interest: Interest) -> AxResult<bool> {
+        match self.poll() {
+            Ok(state) => Ok(state.is_ready(interest)),
+            Err(VfsError::Unsupported) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the file is in nonblocking mode, for O_NONBLOCK.
+    pub fn is_nonblocking(&self) -> bool {
+        (self.status_flags & O_NONBLOCK) != 0
+    }
+
+    /// Whether a read or write, as `interest` tells, would have to wait in
+    /// nonblocking mode, for the nodes that don't fail on their own then.
+    fn would_block(&self, interest: Interest) -> AxResult<bool> {
+        if !self.is_nonblocking() || self.node_nonblock {
+            return Ok(false);
+        }
+        Ok(!self.poll_ready(interest)?)
+    }
+
+    /// Whether a read or write of `len` bytes at `offset` goes to the device
+    /// directly, for O_DIRECT. Only whole pages at page offsets do, others
+    /// go through the caches.
+    fn is_direct(&self, offset: u64, len: usize) -> bool {
+        (self.status_flags & O_DIRECT) != 0
+            && len != 0
+            && offset % PAGE_SIZE as u64 == 0
+            && len % PAGE_SIZE == 0
+    }
+
+    /// Switches the node to the mode of the file, if it has one of its own.
+    fn sync_nonblock(&mut self) {
+        let nonblock = self.is_nonblocking();
+        self.node_nonblock = match self.node.access(Cap::empty()) {
+            Ok(node) => node.set_nonblock(nonblock).is_ok(),
+            Err(_) => false,
+        };
+    }
+
     /// Gets the file attributes.
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.node.access(Cap::empty())?.get_attr()
@@ -454,6 +588,28 @@ impl File {
         self.node.access(Cap::SET_STAT)?.set_attr(attr, valid)
     }
 
+    /// The access mode and the status flags, as F_GETFL gets them.
+    pub fn flags(&self) -> i32 {
+        let cap = self.node.cap();
+        let mode = match (cap.contains(Cap::READ), cap.contains(Cap::WRITE)) {
+            (true, true) => O_RDWR,
+            (false, true) => O_WRONLY,
+            _ => O_RDONLY,
+        };
+        mode | self.status_flags
+    }
+
+    /// Sets the status flags that may change after open, as F_SETFL does.
+    /// The others in `flags` are ignored.
+    pub fn set_status_flags(&mut self, flags: i32) {
+        let old = self.status_flags;
+        self.status_flags = (self.status_flags & !SETFL_MASK) | (flags & SETFL_MASK);
+        self.is_append = (self.status_flags & O_APPEND) != 0;
+        if ((old ^ self.status_flags) & O_NONBLOCK) != 0 {
+            self.sync_nonblock();
+        }
+    }
+
     /// Gets the file cap.
     pub fn get_cap(&self) -> Cap {
         self.node.cap()
@@ -464,6 +620,54 @@ impl File {
     }
 }
 
+/// The kernel reads its own files, e.g. /etc-style config files, through
+/// the [`axio`] traits, with a [`axio::BufReader`] to iterate over lines.
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
+        File::read(self, buf)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
+        File::write(self, buf)
+    }
+
+    fn flush(&mut self) -> AxResult {
+        File::flush(self)
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> AxResult<u64> {
+        File::seek(self, pos)
+    }
+}
+
+/// Reads `node` at `offset`, from the device if `direct` and the node can,
+/// or else through the caches.
+fn node_read(node: &VfsNodeRef, offset: u64, buf: &mut [u8], direct: bool) -> AxResult<usize> {
+    if direct {
+        match node.read_direct(offset, buf) {
+            Err(VfsError::Unsupported) => {}
+            ret => return ret,
+        }
+    }
+    node.read_at(offset, buf)
+}
+
+/// Writes `node` at `offset`, to the device if `direct` and the node can,
+/// or else through the caches.
+fn node_write(node: &VfsNodeRef, offset: u64, buf: &[u8], direct: bool) -> AxResult<usize> {
+    if direct {
+        match node.write_direct(offset, buf) {
+            Err(VfsError::Unsupported) => {}
+            ret => return ret,
+        }
+    }
+    node.write_at(offset, buf)
+}
+
 impl Directory {
     fn _open_dir_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions, fs: &FsStruct) -> AxResult<Self> {
         debug!("open dir: {}", path);
@@ -513,18 +717,18 @@ impl Directory {
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
-    pub fn open_file_at(&self, path: &str, opts: &OpenOptions, fs: &FsStruct, uid: u32, gid: u32) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts, fs, uid, gid)
+    pub fn open_file_at(&self, path: &str, opts: &OpenOptions, fs: &FsStruct, cred: &Cred) -> AxResult<File> {
+        File::_open_at(self.access_at(path)?, path, opts, fs, cred)
     }
 
     /// Creates an empty file at the path relative to this directory.
-    pub fn create_file(&self, path: &str, fs: &FsStruct, uid: u32, gid: u32, mode: i32) -> AxResult<VfsNodeRef> {
-        fs.create_file(self.access_at(path)?, path, VfsNodeType::File, uid, gid, mode)
+    pub fn create_file(&self, path: &str, fs: &FsStruct, cred: &Cred, mode: i32) -> AxResult<VfsNodeRef> {
+        fs.create_file(self.access_at(path)?, path, VfsNodeType::File, cred.fsuid, cred.fsgid, mode)
     }
 
     /// Creates an empty directory at the path relative to this directory.
-    pub fn create_dir(&self, path: &str, fs: &FsStruct, uid: u32, gid: u32) -> AxResult {
-        fs.create_dir(self.access_at(path)?, path, uid, gid, 0o777)
+    pub fn create_dir(&self, path: &str, fs: &FsStruct, cred: &Cred) -> AxResult {
+        fs.create_dir(self.access_at(path)?, path, cred.fsuid, cred.fsgid, 0o777)
     }
 
     /// Removes a file at the path relative to this directory.
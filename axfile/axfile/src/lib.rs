@@ -5,3 +5,4 @@ extern crate log;
 extern crate alloc;
 
 pub mod fops;
+mod readahead;
@@ -0,0 +1,83 @@
+//! Readahead of the files that are read sequentially.
+//!
+//! A read that starts where the last one ended is sequential. The first
+//! one starts a window past it that the node reads into its caches without
+//! waiting, and each read that reaches a window starts the next one, twice
+//! as large up to a limit, so that the blocks are there before they are
+//! read. A read elsewhere ends the run, and the next window starts over
+//! small.
+
+use core::cmp::{max, min};
+use axfs_vfs::VfsNodeRef;
+use axtype::{PAGE_SIZE, POSIX_FADV_NORMAL, POSIX_FADV_RANDOM, POSIX_FADV_SEQUENTIAL};
+
+/// Bounds of the size of a window.
+const RA_MIN: u64 = 4 * PAGE_SIZE as u64;
+const RA_MAX: u64 = 32 * PAGE_SIZE as u64;
+
+/// The readahead state of an open file, as the file_ra_state of Linux.
+pub(crate) struct ReadAhead {
+    /// Where the last read ended.
+    prev_end: u64,
+    /// The window read ahead last, `size` bytes from `start`, none if 0.
+    start: u64,
+    size: u64,
+    /// The posix_fadvise advice on the access pattern.
+    advice: usize,
+}
+
+impl ReadAhead {
+    pub(crate) const fn new() -> Self {
+        Self {
+            prev_end: 0,
+            start: 0,
+            size: 0,
+            advice: POSIX_FADV_NORMAL,
+        }
+    }
+
+    /// Takes POSIX_FADV_NORMAL, POSIX_FADV_RANDOM, which stops readahead,
+    /// or POSIX_FADV_SEQUENTIAL, which allows larger windows.
+    pub(crate) fn set_advice(&mut self, advice: usize) {
+        self.advice = advice;
+        self.size = 0;
+    }
+
+    fn max_size(&self) -> u64 {
+        if self.advice == POSIX_FADV_SEQUENTIAL {
+            2 * RA_MAX
+        } else {
+            RA_MAX
+        }
+    }
+
+    /// Reads ahead of a read of `len` bytes of `node` at `offset`, if the
+    /// file is read sequentially, before the read itself.
+    pub(crate) fn on_read(&mut self, node: &VfsNodeRef, offset: u64, len: usize) {
+        if self.advice == POSIX_FADV_RANDOM || len == 0 {
+            return;
+        }
+        let end = offset.saturating_add(len as u64);
+        let sequential = offset == self.prev_end;
+        self.prev_end = end;
+        if !sequential {
+            self.size = 0;
+            return;
+        }
+
+        if self.size == 0 {
+            let size = (4 * len as u64).clamp(RA_MIN, self.max_size());
+            self.submit(node, end, size);
+        } else if end > self.start {
+            let size = min(2 * self.size, self.max_size());
+            self.submit(node, max(self.start + self.size, end), size);
+        }
+    }
+
+    fn submit(&mut self, node: &VfsNodeRef, start: u64, size: u64) {
+        // Only a hint, a failure shows at the read of the blocks if at all.
+        let _ = node.readahead(start, size as usize);
+        self.start = start;
+        self.size = size;
+    }
+}
@@ -0,0 +1,50 @@
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axio::PollState;
+use axtype::MAJOR_MEM;
+
+/// A full device behaves like `/dev/full`.
+///
+/// It reads like `/dev/zero`, but every write fails with `ENOSPC`, as if
+/// the disk were full.
+pub struct FullDev;
+
+impl VfsNodeOps for FullDev {
+    fn get_ino(&self) -> usize {
+        0
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(MAJOR_MEM, 7);
+        Ok(attr)
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::StorageFull)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
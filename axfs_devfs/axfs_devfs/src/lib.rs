@@ -11,6 +11,7 @@ extern crate log;
 mod dir;
 mod null;
 mod zero;
+mod full;
 mod console;
 
 #[cfg(test)]
@@ -19,6 +20,7 @@ mod tests;
 pub use self::dir::DirNode;
 pub use self::null::NullDev;
 pub use self::zero::ZeroDev;
+pub use self::full::FullDev;
 pub use self::console::ConsoleDev;
 
 use alloc::sync::Arc;
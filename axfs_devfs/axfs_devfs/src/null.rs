@@ -1,4 +1,6 @@
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axio::PollState;
+use axtype::MAJOR_MEM;
 
 /// A null device behaves like `/dev/null`.
 ///
@@ -11,14 +13,16 @@ impl VfsNodeOps for NullDev {
     }
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new(
+        let mut attr = VfsNodeAttr::new(
             VfsNodePerm::default_file(),
             VfsNodeType::CharDevice,
             0,
             0,
             0,
             0,
-        ))
+        );
+        attr.set_rdev(MAJOR_MEM, 3);
+        Ok(attr)
     }
 
     fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
@@ -33,5 +37,12 @@ impl VfsNodeOps for NullDev {
         Ok(())
     }
 
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
     axfs_vfs::impl_vfs_non_dir_default! {}
 }
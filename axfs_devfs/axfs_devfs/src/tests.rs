@@ -37,6 +37,13 @@ fn test_devfs_ops(devfs: &DeviceFileSystem) -> VfsResult {
     assert_eq!(buf, [0; N]);
     assert_eq!(node.write_at(0, &buf)?, N);
 
+    let node = devfs.root_dir().lookup("full")?;
+    assert_eq!(node.get_attr()?.file_type(), VfsNodeType::CharDevice);
+    buf.fill(1);
+    assert_eq!(node.read_at(0, &mut buf)?, N);
+    assert_eq!(buf, [0; N]);
+    assert_eq!(node.write_at(0, &buf).err(), Some(VfsError::StorageFull));
+
     let foo = devfs.root_dir().lookup(".///.//././/.////foo")?;
     assert!(foo.get_attr()?.is_dir());
     assert_eq!(
@@ -98,12 +105,14 @@ fn test_devfs() {
     // │   ├── bar
     // │   │   └── f1 (null)
     // │   └── f2 (zero)
+    // ├── full
     // ├── null
     // └── zero
 
     let devfs = DeviceFileSystem::new();
     devfs.add("null", Arc::new(NullDev));
     devfs.add("zero", Arc::new(ZeroDev));
+    devfs.add("full", Arc::new(FullDev));
 
     let dir_foo = devfs.mkdir("foo");
     dir_foo.add("f2", Arc::new(ZeroDev));
@@ -1,4 +1,6 @@
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axio::PollState;
+use axtype::MAJOR_MEM;
 
 /// A zero device behaves like `/dev/zero`.
 ///
@@ -11,14 +13,16 @@ impl VfsNodeOps for ZeroDev {
     }
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new(
+        let mut attr = VfsNodeAttr::new(
             VfsNodePerm::default_file(),
             VfsNodeType::CharDevice,
             0,
             0,
             0,
             0,
-        ))
+        );
+        attr.set_rdev(MAJOR_MEM, 5);
+        Ok(attr)
     }
 
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
@@ -34,5 +38,12 @@ impl VfsNodeOps for ZeroDev {
         Ok(())
     }
 
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
     axfs_vfs::impl_vfs_non_dir_default! {}
 }
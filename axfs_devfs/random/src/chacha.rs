@@ -0,0 +1,61 @@
+//! The ChaCha20 block function, in its original form with a 64-bit block
+//! counter and a 64-bit nonce.
+
+pub(crate) const KEY_WORDS: usize = 8;
+pub(crate) const BLOCK_SIZE: usize = 64;
+
+pub(crate) type Key = [u32; KEY_WORDS];
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+#[inline(always)]
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// Returns block `counter` of the keystream of `key` and `nonce`.
+pub(crate) fn chacha20_block(key: &Key, counter: u64, nonce: u64) -> [u8; BLOCK_SIZE] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; BLOCK_SIZE];
+    for (i, word) in x.iter().enumerate() {
+        let word = word.wrapping_add(state[i]);
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// Reads a key from the first 32 bytes of `bytes`.
+pub(crate) fn key_from_bytes(bytes: &[u8]) -> Key {
+    let mut key = [0u32; KEY_WORDS];
+    for (i, word) in key.iter_mut().enumerate() {
+        *word = u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+    key
+}
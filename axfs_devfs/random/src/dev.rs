@@ -0,0 +1,79 @@
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axio::PollState;
+use axtype::MAJOR_MEM;
+
+/// _IOR('R', 0x00, int)
+const RNDGETENTCNT: usize = 0x8004_5200;
+
+/// `/dev/random`, which waits until the generator is ready, or
+/// `/dev/urandom`, which does not.
+///
+/// Writes are mixed into the generator without any entropy credit.
+pub struct RandomDev {
+    blocking: bool,
+}
+
+impl RandomDev {
+    pub const fn new(blocking: bool) -> Self {
+        Self { blocking }
+    }
+}
+
+impl VfsNodeOps for RandomDev {
+    fn get_ino(&self) -> usize {
+        0
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::set_mode(0o666),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(MAJOR_MEM, if self.blocking { 8 } else { 9 });
+        Ok(attr)
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.blocking {
+            crate::wait_for_random_bytes().map_err(|_| AxError::Interrupted)?;
+        }
+        crate::get_random_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        crate::add_device_randomness(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: !self.blocking || crate::crng_ready(),
+            writable: true,
+        })
+    }
+
+    fn ioctl(&self, req: usize, data: usize) -> VfsResult<usize> {
+        match req {
+            RNDGETENTCNT => {
+                if data == 0 {
+                    return Err(AxError::BadAddress);
+                }
+                unsafe { *(data as *mut i32) = crate::entropy_count() as i32 };
+                Ok(0)
+            },
+            _ => Err(AxError::Unsupported),
+        }
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
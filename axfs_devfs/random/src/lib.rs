@@ -0,0 +1,241 @@
+//! The kernel random number generator, and /dev/random and /dev/urandom.
+//!
+//! Inputs are mixed into a ChaCha20 key, which only ever moves forward
+//! through the block function, so output does not tell about inputs nor
+//! about earlier output. Each request takes a fresh key for its stream
+//! and replaces the base key at once ("fast key erasure"). The key is
+//! seeded at boot from timer jitter and a seed the bootloader may pass
+//! in the device tree, and keeps taking in the timing of interrupts.
+//! Until the inputs are credited with enough entropy, /dev/random and
+//! getrandom() wait, while /dev/urandom does not.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod chacha;
+mod dev;
+
+pub use self::dev::RandomDev;
+
+use core::cmp::min;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use axerrno::{LinuxError, LinuxResult};
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+use self::chacha::{chacha20_block, key_from_bytes, Key, BLOCK_SIZE, KEY_WORDS};
+
+/// Entropy the generator needs before it is ready.
+const CRNG_INIT_BITS: usize = 256;
+/// Timer samples taken at boot at most.
+const JITTER_SAMPLES: usize = 4096;
+/// Iterations of the busy loop each timer sample measures.
+const JITTER_LOOP: usize = 64;
+/// Most bits a timer sample is credited with.
+const JITTER_MAX_BITS: usize = 11;
+/// Interrupts whose timing is pooled before it's mixed into the key.
+const FAST_POOL_IRQS: usize = 64;
+
+/* getrandom() flags */
+pub const GRND_NONBLOCK: usize = 0x0001;
+pub const GRND_RANDOM: usize = 0x0002;
+pub const GRND_INSECURE: usize = 0x0004;
+
+/// Nonces that keep the block uses apart.
+const NONCE_MIX: u64 = 0;
+const NONCE_KEY: u64 = 1;
+
+struct Crng {
+    key: Key,
+    /// Entropy credited so far, up to [`CRNG_INIT_BITS`].
+    entropy_bits: usize,
+    generation: u64,
+}
+
+impl Crng {
+    const fn new() -> Self {
+        Self {
+            key: [0; KEY_WORDS],
+            entropy_bits: 0,
+            generation: 0,
+        }
+    }
+
+    /// Mixes `input` into the key: each 32-byte chunk is xored in and the
+    /// key is replaced by a block of the result.
+    fn mix(&mut self, input: &[u8]) {
+        for chunk in input.chunks(KEY_WORDS * 4) {
+            let mut bytes = [0u8; KEY_WORDS * 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            let chunk = key_from_bytes(&bytes);
+            for (word, input) in self.key.iter_mut().zip(chunk) {
+                *word ^= input;
+            }
+            self.generation += 1;
+            let block = chacha20_block(&self.key, self.generation, NONCE_MIX);
+            self.key = key_from_bytes(&block);
+        }
+    }
+
+    /// Replaces the key, and returns another one for a single request.
+    fn fast_key_erasure(&mut self) -> Key {
+        self.generation += 1;
+        let block = chacha20_block(&self.key, self.generation, NONCE_KEY);
+        self.key = key_from_bytes(&block[..32]);
+        key_from_bytes(&block[32..])
+    }
+}
+
+static CRNG: SpinNoIrq<Crng> = SpinNoIrq::new(Crng::new());
+static CRNG_READY: AtomicBool = AtomicBool::new(false);
+/// Interrupt timings not mixed in yet, and how many.
+static FAST_POOL: AtomicU64 = AtomicU64::new(0);
+static FAST_POOL_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Readers waiting for the generator to be ready.
+static WAITERS: WaitQueue = WaitQueue::new();
+
+/// Whether enough entropy has been credited.
+pub fn crng_ready() -> bool {
+    CRNG_READY.load(Ordering::Acquire)
+}
+
+/// Entropy credited so far, in bits, as RNDGETENTCNT tells.
+pub fn entropy_count() -> usize {
+    CRNG.lock().entropy_bits
+}
+
+fn credit_entropy_bits(bits: usize) {
+    let mut crng = CRNG.lock();
+    crng.entropy_bits = min(crng.entropy_bits + bits, CRNG_INIT_BITS);
+    if crng.entropy_bits < CRNG_INIT_BITS || crng_ready() {
+        return;
+    }
+    drop(crng);
+    CRNG_READY.store(true, Ordering::Release);
+    info!("random: crng init done");
+    WAITERS.notify_all(false);
+}
+
+/// Mixes in data that differs between machines or boots, but may be
+/// known to others, so it is credited with nothing.
+pub fn add_device_randomness(buf: &[u8]) {
+    let now = axhal::time::current_ticks();
+    let mut crng = CRNG.lock();
+    crng.mix(&now.to_ne_bytes());
+    crng.mix(buf);
+}
+
+/// Mixes in a seed passed by the bootloader, which is trusted to be
+/// random, as the "rng-seed" of /chosen in the device tree.
+pub fn add_bootloader_randomness(buf: &[u8]) {
+    CRNG.lock().mix(buf);
+    credit_entropy_bits(buf.len() * 8);
+}
+
+/// Mixes in the time of an interrupt, without credit. Timings are folded
+/// into a pool, which is mixed into the key every [`FAST_POOL_IRQS`]
+/// interrupts, so the IRQ path stays cheap. Called on each IRQ.
+pub fn add_interrupt_randomness(irq: usize) {
+    let now = axhal::time::current_ticks();
+    // Racing CPUs may lose a sample of each other, which is harmless.
+    let pool = FAST_POOL.load(Ordering::Relaxed).rotate_left(7) ^ now ^ ((irq as u64) << 48);
+    FAST_POOL.store(pool, Ordering::Relaxed);
+    if FAST_POOL_COUNT.fetch_add(1, Ordering::Relaxed) + 1 < FAST_POOL_IRQS {
+        return;
+    }
+    FAST_POOL_COUNT.store(0, Ordering::Relaxed);
+    CRNG.lock().mix(&pool.to_ne_bytes());
+}
+
+/// Seeds from the jitter of the timer against a fixed busy loop, whose
+/// duration varies with caches, pipelines and the host. Like timer events
+/// on Linux, a sample is credited with the bits of the smallest of its
+/// first, second and third order deltas, so a loop whose duration stays
+/// the same, or changes steadily, earns nothing.
+fn seed_from_jitter() {
+    let mut last_delta: i64 = 0;
+    let mut last_delta2: i64 = 0;
+    let mut credited = 0;
+    let mut spin = 0usize;
+    for i in 0..JITTER_SAMPLES {
+        if credited >= CRNG_INIT_BITS {
+            break;
+        }
+        let start = axhal::time::current_ticks();
+        for _ in 0..JITTER_LOOP {
+            spin = core::hint::black_box(spin.wrapping_mul(31).wrapping_add(i));
+        }
+        let end = axhal::time::current_ticks();
+        let mut sample = [0u8; 16];
+        sample[..8].copy_from_slice(&end.to_ne_bytes());
+        sample[8..].copy_from_slice(&(spin as u64).to_ne_bytes());
+        CRNG.lock().mix(&sample);
+
+        let delta = end.wrapping_sub(start) as i64;
+        let delta2 = delta.wrapping_sub(last_delta);
+        let delta3 = delta2.wrapping_sub(last_delta2);
+        last_delta = delta;
+        last_delta2 = delta2;
+        // The first samples have no history to be compared with.
+        if i >= 2 {
+            let min_delta = delta.unsigned_abs().min(delta2.unsigned_abs()).min(delta3.unsigned_abs());
+            let bits = (u64::BITS - (min_delta >> 1).leading_zeros()) as usize;
+            credited += min(bits, JITTER_MAX_BITS);
+        }
+    }
+    debug!("random: {} bits of timer jitter", credited);
+    credit_entropy_bits(credited);
+}
+
+/// Seeds the generator at boot.
+pub fn init() {
+    info!("Initialize random ...");
+    add_device_randomness(&axhal::time::current_time_nanos().to_ne_bytes());
+    seed_from_jitter();
+    if !crng_ready() {
+        warn!("random: crng not ready after boot seeding");
+    }
+}
+
+/// Fills `buf` with output of the generator, whether it is ready or not.
+pub fn get_random_bytes(buf: &mut [u8]) {
+    let key = CRNG.lock().fast_key_erasure();
+    for (counter, chunk) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+        let block = chacha20_block(&key, counter as u64, 0);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
+
+/// Waits until the generator is ready, or a signal comes.
+pub fn wait_for_random_bytes() -> LinuxResult {
+    WAITERS.wait_until_interruptible(crng_ready)
+}
+
+/// Fills `len` bytes at `buf` in user space.
+pub fn getrandom(buf: usize, len: usize, flags: usize) -> LinuxResult<usize> {
+    if (flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    // GRND_INSECURE means not to wait, which GRND_RANDOM contradicts.
+    if (flags & (GRND_INSECURE | GRND_RANDOM)) == (GRND_INSECURE | GRND_RANDOM) {
+        return Err(LinuxError::EINVAL);
+    }
+    if !crng_ready() && (flags & GRND_INSECURE) == 0 {
+        if (flags & GRND_NONBLOCK) != 0 {
+            return Err(LinuxError::EAGAIN);
+        }
+        wait_for_random_bytes()?;
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    if buf == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    let ubuf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+    get_random_bytes(ubuf);
+    Ok(len)
+}
@@ -0,0 +1,187 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+extern crate alloc;
+
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeOps, VfsNodeRef};
+use axtype::O_NOCTTY;
+use tty::{PtmxDev, PtsDir, Termios, WinSize};
+
+const TCGETS:     usize = 0x5401;
+const TCSETS:     usize = 0x5402;
+const TIOCGWINSZ: usize = 0x5413;
+const TIOCSWINSZ: usize = 0x5414;
+const FIONREAD:   usize = 0x541B;
+const TIOCGPTN:   usize = 0x80045430;
+const TIOCSPTLCK: usize = 0x40045431;
+
+const ICANON: u32 = 0o000002;
+const ECHO:   u32 = 0o000010;
+const VMIN:   usize = 6;
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_tty]: ...");
+
+    fork::init(cpu_id, dtb_pa);
+
+    let (master, slave) = open_pty();
+    test_canonical(&master, &slave);
+    test_raw(&master, &slave);
+    test_blocking_read(&master, &slave);
+    test_hangup(master, slave);
+
+    info!("[rt_tty]: ok!");
+    axhal::misc::terminate();
+}
+
+/// Reads all the master has, which is what the slave put out.
+fn read_output(master: &VfsNodeRef, buf: &mut [u8]) -> usize {
+    if !master.poll().unwrap().readable {
+        return 0;
+    }
+    master.read_at(0, buf).unwrap()
+}
+
+fn fionread(node: &VfsNodeRef) -> i32 {
+    let mut size = 0i32;
+    node.ioctl(FIONREAD, &mut size as *mut _ as usize).unwrap();
+    size
+}
+
+/// A new pty, whose slave can only be opened once it's unlocked.
+fn open_pty() -> (VfsNodeRef, VfsNodeRef) {
+    let master = PtmxDev.open_clone(0).unwrap().unwrap();
+    let mut index = u32::MAX;
+    master.ioctl(TIOCGPTN, &mut index as *mut _ as usize).unwrap();
+    let (slave, _) = Arc::new(PtsDir::new()).lookup(&index.to_string(), 0).unwrap();
+
+    assert_eq!(slave.open(O_NOCTTY), Err(AxError::Io));
+    let lock = 0i32;
+    master.ioctl(TIOCSPTLCK, &lock as *const _ as usize).unwrap();
+    assert_eq!(slave.open(O_NOCTTY), Ok(()));
+    info!("[rt_tty]: pty {} opened ok!", index);
+    (master, slave)
+}
+
+/// In canonical mode, input is edited a line at a time with echo, and the
+/// slave reads whole lines, with '\n' put out as "\r\n".
+fn test_canonical(master: &VfsNodeRef, slave: &VfsNodeRef) {
+    let mut buf = [0u8; 64];
+    master.write_at(0, b"ab\x7fc").unwrap();
+    // Not a line yet.
+    assert_eq!(fionread(slave), 0);
+    master.write_at(0, b"\r").unwrap();
+    assert_eq!(fionread(slave), 3);
+    assert_eq!(slave.read_at(0, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"ac\n");
+    let n = read_output(master, &mut buf);
+    assert_eq!(&buf[..n], b"ab\x08 \x08c\r\n");
+
+    // VKILL erases the line, and VEOF pushes it out without an end.
+    master.write_at(0, b"xy\x15q\x04").unwrap();
+    assert_eq!(slave.read_at(0, &mut buf), Ok(1));
+    assert_eq!(buf[0], b'q');
+    let n = read_output(master, &mut buf);
+    assert_eq!(&buf[..n], b"xy\x08 \x08\x08 \x08q");
+    // And on an empty line, it's an end of file.
+    master.write_at(0, b"\x04").unwrap();
+    assert_eq!(slave.read_at(0, &mut buf), Ok(0));
+
+    // A line is read in parts by short reads, and never with the next.
+    master.write_at(0, b"hello\nworld\n").unwrap();
+    assert_eq!(slave.read_at(0, &mut buf[..3]), Ok(3));
+    assert_eq!(&buf[..3], b"hel");
+    assert_eq!(slave.read_at(0, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"lo\n");
+    assert_eq!(slave.read_at(0, &mut buf), Ok(6));
+    assert_eq!(&buf[..6], b"world\n");
+    read_output(master, &mut buf);
+
+    assert_eq!(slave.write_at(0, b"out\n"), Ok(4));
+    let n = read_output(master, &mut buf);
+    assert_eq!(&buf[..n], b"out\r\n");
+    info!("[rt_tty]: canonical mode ok!");
+}
+
+/// Without ICANON and ECHO, input is read as it comes, control
+/// characters too, and nothing is echoed. The ioctls of termios and the
+/// window size go to the slave through the master too.
+fn test_raw(master: &VfsNodeRef, slave: &VfsNodeRef) {
+    let mut termios = Termios::default();
+    slave.ioctl(TCGETS, &mut termios as *mut _ as usize).unwrap();
+    let cooked = termios;
+    termios.c_lflag &= !(ICANON | ECHO);
+    termios.c_cc[VMIN] = 1;
+    master.ioctl(TCSETS, &termios as *const _ as usize).unwrap();
+
+    let mut buf = [0u8; 8];
+    master.write_at(0, b"a\x7f").unwrap();
+    assert_eq!(fionread(slave), 2);
+    assert_eq!(slave.read_at(0, &mut buf), Ok(2));
+    assert_eq!(&buf[..2], b"a\x7f");
+    assert!(!master.poll().unwrap().readable);
+
+    // Switching back takes what's pending into the line being edited.
+    master.write_at(0, b"ab").unwrap();
+    slave.ioctl(TCSETS, &cooked as *const _ as usize).unwrap();
+    assert_eq!(fionread(slave), 0);
+    master.write_at(0, b"c\n").unwrap();
+    assert_eq!(slave.read_at(0, &mut buf), Ok(4));
+    assert_eq!(&buf[..4], b"abc\n");
+    read_output(master, &mut buf);
+
+    let ws = WinSize { ws_row: 24, ws_col: 80, ..Default::default() };
+    master.ioctl(TIOCSWINSZ, &ws as *const _ as usize).unwrap();
+    let mut got = WinSize::default();
+    slave.ioctl(TIOCGWINSZ, &mut got as *mut _ as usize).unwrap();
+    assert_eq!((got.ws_row, got.ws_col), (24, 80));
+    info!("[rt_tty]: raw mode ok!");
+}
+
+/// A read of the slave sleeps till a line comes, here from another task.
+fn test_blocking_read(master: &VfsNodeRef, slave: &VfsNodeRef) {
+    let writer = master.clone();
+    fork::kernel_thread(move || {
+        writer.write_at(0, b"late\n").unwrap();
+        run_queue::exit_current(0);
+    }, None);
+
+    let mut buf = [0u8; 8];
+    assert_eq!(slave.read_at(0, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"late\n");
+    read_output(master, &mut buf);
+    info!("[rt_tty]: blocking read ok!");
+}
+
+/// Reading the master fails once the slave is closed, and the slave hangs
+/// up once the master is closed: reads see an end of file, and writes
+/// fail.
+fn test_hangup(master: VfsNodeRef, slave: VfsNodeRef) {
+    let mut index = u32::MAX;
+    master.ioctl(TIOCGPTN, &mut index as *mut _ as usize).unwrap();
+    let mut buf = [0u8; 8];
+    slave.release().unwrap();
+    assert_eq!(master.read_at(0, &mut buf), Err(AxError::Io));
+
+    master.release().unwrap();
+    assert_eq!(slave.read_at(0, &mut buf), Ok(0));
+    assert_eq!(slave.write_at(0, b"x"), Err(AxError::Io));
+    let pts = Arc::new(PtsDir::new());
+    assert!(pts.lookup(&index.to_string(), 0).is_err());
+    info!("[rt_tty]: hangup ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
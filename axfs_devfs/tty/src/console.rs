@@ -0,0 +1,78 @@
+//! The console as a tty.
+//!
+//! It is on the UART of axhal, or on the hypervisor console `hvcN` with
+//! `console=hvcN` on the command line, for the platforms whose UART is
+//! unreliable.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use axerrno::{LinuxError, LinuxResult};
+use axtype::MAJOR_TTYAUX;
+use spinbase::SpinNoIrq;
+
+use crate::{Tty, TtyDriver};
+
+static CONSOLE: SpinNoIrq<Option<Arc<Tty>>> = SpinNoIrq::new(None);
+
+/// Index of the hypervisor console the console is on, or `usize::MAX` for
+/// the UART.
+static CONSOLE_HVC: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn console_setup(value: &str) -> LinuxResult {
+    // The others, as ttyS0, are the UART.
+    if let Some(index) = value.strip_prefix("hvc") {
+        let index = index.parse().map_err(|_| LinuxError::EINVAL)?;
+        CONSOLE_HVC.store(index, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+cmdline::kernel_param!("console", console_setup);
+
+/// The hypervisor console the console is on, if it is there.
+fn console_hvc() -> Option<usize> {
+    let index = CONSOLE_HVC.load(Ordering::Relaxed);
+    (index < axdriver::hvc::count()).then_some(index)
+}
+
+/// Writes to the console of axhal or the hypervisor console, and the
+/// framebuffer with the `fbcon` feature, and polls it for input, which has
+/// no interrupt yet.
+struct ConsoleDriver;
+
+impl TtyDriver for ConsoleDriver {
+    fn write(&self, _tty: &Tty, buf: &[u8]) {
+        match console_hvc() {
+            Some(index) => {
+                let _ = axdriver::hvc::write_bytes(index, buf);
+            }
+            None => axhal::console::write_bytes(buf),
+        }
+        #[cfg(feature = "fbcon")]
+        fbdev::fbcon::write_bytes(buf);
+    }
+
+    fn poll(&self, tty: &Tty) -> bool {
+        match console_hvc() {
+            Some(index) => {
+                while let Some(c) = axdriver::hvc::getchar(index) {
+                    tty.receive_buf(&[c]);
+                }
+            }
+            None => {
+                while let Some(c) = axhal::console::getchar() {
+                    tty.receive_buf(&[c]);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// The tty of `/dev/console`, made on first use.
+pub fn console() -> Arc<Tty> {
+    CONSOLE.lock()
+        .get_or_insert_with(|| Tty::new(MAJOR_TTYAUX, 1, Box::new(ConsoleDriver)))
+        .clone()
+}
@@ -0,0 +1,40 @@
+//! The ttys of the hypervisor consoles, `/dev/hvc0`... on the character
+//! devices of [`axdriver::hvc`].
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axtype::MAJOR_HVC;
+use spinbase::SpinNoIrq;
+
+use crate::{Tty, TtyDriver};
+
+static HVC_TTYS: SpinNoIrq<Vec<Arc<Tty>>> = SpinNoIrq::new(Vec::new());
+
+/// Drives console `index`, polled for input, as the consoles have no
+/// interrupt handler yet.
+struct HvcDriver {
+    index: usize,
+}
+
+impl TtyDriver for HvcDriver {
+    fn write(&self, _tty: &Tty, buf: &[u8]) {
+        let _ = axdriver::hvc::write_bytes(self.index, buf);
+    }
+
+    fn poll(&self, tty: &Tty) -> bool {
+        while let Some(c) = axdriver::hvc::getchar(self.index) {
+            tty.receive_buf(&[c]);
+        }
+        true
+    }
+}
+
+/// The ttys of all the consoles, by index, made on first use.
+pub fn hvc_ttys() -> Vec<Arc<Tty>> {
+    let mut ttys = HVC_TTYS.lock();
+    for index in ttys.len()..axdriver::hvc::count() {
+        ttys.push(Tty::new(MAJOR_HVC, index as u32, Box::new(HvcDriver { index })));
+    }
+    ttys.clone()
+}
@@ -0,0 +1,212 @@
+//! N_TTY, the default line discipline.
+//!
+//! In canonical mode input is edited a line at a time and readers only get
+//! whole lines; otherwise it is passed on as it comes. It also echoes the
+//! input and turns the INTR, QUIT and SUSP characters into signals.
+
+use core::cmp::min;
+use core::mem;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use task::{SIGINT, SIGQUIT, SIGTSTP};
+
+use crate::termios::*;
+
+/// Longest line in canonical mode, with its end.
+const N_TTY_BUF_SIZE: usize = 4096;
+
+/// What received input asks the tty to do once its locks are dropped.
+#[derive(Default)]
+pub(crate) struct Input {
+    pub echo: Vec<u8>,
+    pub signals: Vec<usize>,
+}
+
+pub(crate) struct NTty {
+    /// Lines completed in canonical mode. An empty one is an end of file.
+    lines: VecDeque<Vec<u8>>,
+    /// The line being edited.
+    line: Vec<u8>,
+    /// Input in non-canonical mode.
+    raw: VecDeque<u8>,
+}
+
+impl NTty {
+    pub const fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            line: Vec::new(),
+            raw: VecDeque::new(),
+        }
+    }
+
+    pub fn receive(&mut self, termios: &Termios, mut c: u8, input: &mut Input) {
+        if termios.iflag(ISTRIP) {
+            c &= 0x7f;
+        }
+        if c == b'\r' {
+            if termios.iflag(IGNCR) {
+                return;
+            }
+            if termios.iflag(ICRNL) {
+                c = b'\n';
+            }
+        } else if c == b'\n' && termios.iflag(INLCR) {
+            c = b'\r';
+        }
+
+        if termios.lflag(ISIG) {
+            let signal = if termios.is_cc(c, VINTR) {
+                Some(SIGINT)
+            } else if termios.is_cc(c, VQUIT) {
+                Some(SIGQUIT)
+            } else if termios.is_cc(c, VSUSP) {
+                Some(SIGTSTP)
+            } else {
+                None
+            };
+            if let Some(signal) = signal {
+                if !termios.lflag(NOFLSH) {
+                    self.flush();
+                }
+                echo_char(termios, c, input);
+                input.signals.push(signal);
+                return;
+            }
+        }
+
+        if !termios.lflag(ICANON) {
+            self.raw.push_back(c);
+            echo_char(termios, c, input);
+            return;
+        }
+
+        if termios.is_cc(c, VERASE) {
+            self.erase(termios, input);
+        } else if termios.is_cc(c, VWERASE) && termios.lflag(IEXTEN) {
+            while self.line.last().is_some_and(|c| c.is_ascii_whitespace()) {
+                self.erase(termios, input);
+            }
+            while self.line.last().is_some_and(|c| !c.is_ascii_whitespace()) {
+                self.erase(termios, input);
+            }
+        } else if termios.is_cc(c, VKILL) {
+            if termios.lflag(ECHOKE) {
+                while !self.line.is_empty() {
+                    self.erase(termios, input);
+                }
+            } else {
+                self.line.clear();
+                echo_char(termios, c, input);
+                if termios.lflag(ECHOK) {
+                    input.echo.push(b'\n');
+                }
+            }
+        } else if termios.is_cc(c, VEOF) {
+            // Pushes the line out as it is, or an end of file if empty.
+            self.lines.push_back(mem::take(&mut self.line));
+        } else if c == b'\n' || termios.is_cc(c, VEOL) || termios.is_cc(c, VEOL2) {
+            self.line.push(c);
+            if c == b'\n' && termios.lflag(ECHONL) && !termios.lflag(ECHO) {
+                input.echo.push(c);
+            } else {
+                echo_char(termios, c, input);
+            }
+            self.lines.push_back(mem::take(&mut self.line));
+        } else if self.line.len() < N_TTY_BUF_SIZE - 1 {
+            self.line.push(c);
+            echo_char(termios, c, input);
+        }
+    }
+
+    fn erase(&mut self, termios: &Termios, input: &mut Input) {
+        let Some(c) = self.line.pop() else {
+            return;
+        };
+        if termios.lflag(ECHO) && termios.lflag(ECHOE) {
+            let width = if is_ctl(c) && termios.lflag(ECHOCTL) { 2 } else { 1 };
+            for _ in 0..width {
+                input.echo.extend_from_slice(b"\x08 \x08");
+            }
+        }
+    }
+
+    /// Drops all the input not read yet.
+    pub fn flush(&mut self) {
+        self.lines.clear();
+        self.line.clear();
+        self.raw.clear();
+    }
+
+    /// Moves pending input over when ICANON is switched.
+    pub fn set_canonical(&mut self, canonical: bool) {
+        if canonical {
+            self.line.extend(self.raw.drain(..));
+        } else {
+            for line in self.lines.drain(..) {
+                self.raw.extend(line);
+            }
+            self.raw.extend(self.line.drain(..));
+        }
+    }
+
+    /// Bytes ready for readers.
+    pub fn available(&self, termios: &Termios) -> usize {
+        if termios.lflag(ICANON) {
+            self.lines.iter().map(|line| line.len()).sum()
+        } else {
+            self.raw.len()
+        }
+    }
+
+    /// Whether a read of `want` bytes would return now: there has to be a
+    /// line in canonical mode, or VMIN bytes otherwise. VTIME is not
+    /// supported, so with VMIN 0 a read never waits.
+    pub fn readable(&self, termios: &Termios, want: usize) -> bool {
+        if termios.lflag(ICANON) {
+            !self.lines.is_empty()
+        } else {
+            self.raw.len() >= min(termios.c_cc[VMIN] as usize, want)
+        }
+    }
+
+    /// Takes input for a read into `buf`, no more than a line in canonical
+    /// mode, or returns `None` if it has to wait.
+    pub fn read(&mut self, termios: &Termios, buf: &mut [u8]) -> Option<usize> {
+        if !self.readable(termios, buf.len()) {
+            return None;
+        }
+        if termios.lflag(ICANON) {
+            let line = self.lines.front_mut()?;
+            let size = min(buf.len(), line.len());
+            buf[..size].copy_from_slice(&line[..size]);
+            line.drain(..size);
+            if line.is_empty() {
+                self.lines.pop_front();
+            }
+            return Some(size);
+        }
+        let size = min(buf.len(), self.raw.len());
+        for (dst, c) in buf.iter_mut().zip(self.raw.drain(..size)) {
+            *dst = c;
+        }
+        Some(size)
+    }
+}
+
+fn is_ctl(c: u8) -> bool {
+    (c < 0x20 && c != b'\t' && c != b'\n') || c == 0x7f
+}
+
+/// Echoes `c`, with control characters as '^C' for ECHOCTL.
+fn echo_char(termios: &Termios, c: u8, input: &mut Input) {
+    if !termios.lflag(ECHO) {
+        return;
+    }
+    if termios.lflag(ECHOCTL) && is_ctl(c) {
+        input.echo.push(b'^');
+        input.echo.push(c ^ 0x40);
+    } else {
+        input.echo.push(c);
+    }
+}
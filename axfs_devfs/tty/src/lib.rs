@@ -0,0 +1,496 @@
+//! TTY layer.
+//!
+//! A [`Tty`] runs input from its driver through the N_TTY line discipline
+//! and output through the output processing of its termios. The console
+//! is one, each hypervisor console is one, and each pseudo-terminal pair has one for the slave side, which
+//! its master drives.
+//!
+//! A session leader which opens a tty without O_NOCTTY makes it its
+//! controlling tty, the one `/dev/tty` opens and whose foreground group
+//! gets the signals typed.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod termios;
+mod ldisc;
+mod console;
+mod hvc;
+mod pty;
+
+pub use self::termios::{Termios, WinSize};
+pub use self::console::console;
+pub use self::hvc::hvc_ttys;
+pub use self::pty::{PtmxDev, PtsDir};
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use axfs_vfs::alloc_ino;
+use axio::PollState;
+use axtype::{O_NOCTTY, MAJOR_TTYAUX};
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+use task::{SIGHUP, SIGWINCH};
+
+use crate::ldisc::{Input, NTty};
+use crate::termios::*;
+
+// IOCTL
+const TCGETS:     usize = 0x5401;
+const TCSETS:     usize = 0x5402;
+const TCSETSW:    usize = 0x5403;
+const TCSETSF:    usize = 0x5404;
+const TCFLSH:     usize = 0x540B;
+const TIOCSCTTY:  usize = 0x540E;
+const TIOCGPGRP:  usize = 0x540F;
+const TIOCSPGRP:  usize = 0x5410;
+const TIOCOUTQ:   usize = 0x5411;
+const TIOCGWINSZ: usize = 0x5413;
+const TIOCSWINSZ: usize = 0x5414;
+const FIONREAD:   usize = 0x541B;
+const TIOCNOTTY:  usize = 0x5422;
+const TIOCGSID:   usize = 0x5429;
+
+const TCIFLUSH:   usize = 0;
+const TCIOFLUSH:  usize = 2;
+
+/// Ttys by their ids, which `TaskStruct::tty` refers to.
+static TTYS: SpinNoIrq<BTreeMap<usize, Weak<Tty>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// Returns the tty `id`, if it is still there.
+pub fn get_tty(id: usize) -> Option<Arc<Tty>> {
+    TTYS.lock().get(&id).and_then(|tty| tty.upgrade())
+}
+
+/// The device side of a tty.
+pub trait TtyDriver: Send + Sync {
+    /// Puts out what the tty writes, after output processing.
+    fn write(&self, tty: &Tty, buf: &[u8]);
+
+    /// Feeds `tty` with the input got so far, for devices without an
+    /// interrupt for it. Returns false if input comes without polling.
+    fn poll(&self, _tty: &Tty) -> bool {
+        false
+    }
+
+    /// Called as the tty is opened.
+    fn open(&self) -> VfsResult {
+        Ok(())
+    }
+
+    /// Called as the last opened file of the tty is closed.
+    fn close(&self) {}
+}
+
+/// A terminal with its line discipline.
+pub struct Tty {
+    major: u32,
+    minor: u32,
+    termios: SpinNoIrq<Termios>,
+    winsize: SpinNoIrq<WinSize>,
+    ldisc: SpinNoIrq<NTty>,
+    driver: Box<dyn TtyDriver>,
+    /// Session it is the controlling tty of, and its foreground group, or
+    /// 0 for none.
+    session: AtomicUsize,
+    pgrp: AtomicUsize,
+    /// Opened files of it.
+    count: AtomicUsize,
+    hung_up: AtomicBool,
+    read_wait: WaitQueue,
+}
+
+impl Tty {
+    pub fn new(major: u32, minor: u32, driver: Box<dyn TtyDriver>) -> Arc<Self> {
+        let tty = Arc::new(Self {
+            major,
+            minor,
+            termios: SpinNoIrq::new(Termios::new()),
+            winsize: SpinNoIrq::new(WinSize::default()),
+            ldisc: SpinNoIrq::new(NTty::new()),
+            driver,
+            session: AtomicUsize::new(0),
+            pgrp: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            hung_up: AtomicBool::new(false),
+            read_wait: WaitQueue::new(),
+        });
+        TTYS.lock().insert(tty.id(), Arc::downgrade(&tty));
+        tty
+    }
+
+    /// Id of the tty, never 0, which is no tty for a task.
+    pub fn id(&self) -> usize {
+        ((self.major as usize) << 20) | self.minor as usize
+    }
+
+    pub fn foreground(&self) -> usize {
+        self.pgrp.load(Ordering::Relaxed)
+    }
+
+    fn signal_foreground(&self, sig: usize) {
+        let pgrp = self.foreground();
+        if pgrp != 0 {
+            let _ = signal::kill_pgrp(pgrp, sig);
+        }
+    }
+
+    /// Takes input from the driver.
+    pub fn receive_buf(&self, buf: &[u8]) {
+        let termios = *self.termios.lock();
+        let mut input = Input::default();
+        {
+            let mut ldisc = self.ldisc.lock();
+            for &c in buf {
+                ldisc.receive(&termios, c, &mut input);
+            }
+        }
+        if !input.echo.is_empty() {
+            self.output(&termios, &input.echo);
+        }
+        for sig in input.signals {
+            self.signal_foreground(sig);
+        }
+        self.read_wait.notify_all(false);
+    }
+
+    /// Writes to the driver with '\n' as "\r\n" for ONLCR.
+    fn output(&self, termios: &Termios, buf: &[u8]) {
+        if !termios.oflag(OPOST) {
+            return self.driver.write(self, buf);
+        }
+        let mut out = Vec::with_capacity(buf.len());
+        for &c in buf {
+            match c {
+                b'\n' if termios.oflag(ONLCR) => out.extend_from_slice(b"\r\n"),
+                b'\r' if termios.oflag(OCRNL) => out.push(b'\n'),
+                _ => out.push(c),
+            }
+        }
+        self.driver.write(self, &out);
+    }
+
+    pub fn read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        loop {
+            let polled = self.driver.poll(self);
+            let termios = *self.termios.lock();
+            if let Some(size) = self.ldisc.lock().read(&termios, buf) {
+                return Ok(size);
+            }
+            if self.hung_up.load(Ordering::Relaxed) {
+                return Ok(0);
+            }
+            if polled {
+                if taskctx::signal_pending(&taskctx::current_ctx()) {
+                    return Err(AxError::Interrupted);
+                }
+                run_queue::yield_now();
+                continue;
+            }
+            self.read_wait.wait_until_interruptible(|| {
+                let termios = *self.termios.lock();
+                self.ldisc.lock().readable(&termios, buf.len())
+                    || self.hung_up.load(Ordering::Relaxed)
+            })?;
+        }
+    }
+
+    /// Readable once a read would not wait, and always writable, as the
+    /// output goes to the driver at once.
+    pub fn poll(&self) -> PollState {
+        self.driver.poll(self);
+        let termios = *self.termios.lock();
+        PollState {
+            readable: self.ldisc.lock().readable(&termios, 1) || self.hung_up.load(Ordering::Relaxed),
+            writable: true,
+        }
+    }
+
+    pub fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+        if self.hung_up.load(Ordering::Relaxed) {
+            return Err(AxError::Io);
+        }
+        let termios = *self.termios.lock();
+        self.output(&termios, buf);
+        Ok(buf.len())
+    }
+
+    /// Cuts the tty off, as its pty master is closed: the foreground gets
+    /// SIGHUP, reads then see end of file and writes fail.
+    pub fn hangup(&self) {
+        self.hung_up.store(true, Ordering::Relaxed);
+        self.signal_foreground(SIGHUP);
+        self.session.store(0, Ordering::Relaxed);
+        self.pgrp.store(0, Ordering::Relaxed);
+        self.read_wait.notify_all(false);
+    }
+
+    pub fn open(&self, flags: i32) -> VfsResult {
+        self.driver.open()?;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        // As on Linux, '/dev/console' never becomes a controlling tty.
+        let is_console = self.major == MAJOR_TTYAUX && self.minor == 1;
+        if (flags & O_NOCTTY) == 0 && !is_console {
+            self.set_ctty(false);
+        }
+        Ok(())
+    }
+
+    pub fn release(&self) {
+        if self.count.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.driver.close();
+        }
+    }
+
+    /// Whether some process of the session it belongs to still has it.
+    fn owned(&self) -> bool {
+        let sid = self.session.load(Ordering::Relaxed);
+        sid != 0 && task::all_tasks().iter().any(|t| {
+            t.sid() == sid && t.tty.load(Ordering::Relaxed) == self.id()
+        })
+    }
+
+    /// Makes it the controlling tty of the session the current task leads,
+    /// unless it has one or the tty belongs to another, which `steal` lets
+    /// a privileged task take it from.
+    fn set_ctty(&self, steal: bool) -> bool {
+        let current = task::current();
+        if !current.is_session_leader() || current.tty.load(Ordering::Relaxed) != 0 {
+            return false;
+        }
+        if self.owned() && !(steal && current.cred().is_privileged()) {
+            return false;
+        }
+        let old = self.session.swap(current.sid(), Ordering::Relaxed);
+        if old != 0 {
+            for t in task::all_tasks() {
+                if t.sid() == old {
+                    let _ = t.tty.compare_exchange(self.id(), 0, Ordering::Relaxed, Ordering::Relaxed);
+                }
+            }
+        }
+        self.pgrp.store(current.pgid(), Ordering::Relaxed);
+        for tid in current.thread_group() {
+            if let Some(t) = task::get_task(tid) {
+                t.tty.store(self.id(), Ordering::Relaxed);
+            }
+        }
+        info!("tty {:#x} controls session {}", self.id(), current.sid());
+        true
+    }
+
+    /// Whether it is the controlling tty of the current task.
+    fn is_ctty(&self) -> bool {
+        task::current().tty.load(Ordering::Relaxed) == self.id()
+    }
+
+    fn set_termios(&self, new: Termios) {
+        let mut termios = self.termios.lock();
+        let canonical = new.lflag(ICANON);
+        if termios.lflag(ICANON) != canonical {
+            self.ldisc.lock().set_canonical(canonical);
+        }
+        *termios = new;
+        drop(termios);
+        self.read_wait.notify_all(false);
+    }
+
+    pub fn ioctl(&self, req: usize, data: usize) -> VfsResult<usize> {
+        debug!("tty ioctl: req {:#x} data {:#x}", req, data);
+        match req {
+            TCGETS => {
+                unsafe { *(data as *mut Termios) = *self.termios.lock(); }
+            },
+            TCSETS | TCSETSW | TCSETSF => {
+                if req == TCSETSF {
+                    self.ldisc.lock().flush();
+                }
+                self.set_termios(unsafe { *(data as *const Termios) });
+            },
+            TCFLSH => {
+                if data == TCIFLUSH || data == TCIOFLUSH {
+                    self.ldisc.lock().flush();
+                }
+            },
+            TIOCGWINSZ => {
+                unsafe { *(data as *mut WinSize) = *self.winsize.lock(); }
+            },
+            TIOCSWINSZ => {
+                let new = unsafe { *(data as *const WinSize) };
+                let old = core::mem::replace(&mut *self.winsize.lock(), new);
+                if (old.ws_row, old.ws_col) != (new.ws_row, new.ws_col) {
+                    self.signal_foreground(SIGWINCH);
+                }
+            },
+            FIONREAD => {
+                let termios = *self.termios.lock();
+                let size = self.ldisc.lock().available(&termios);
+                unsafe { *(data as *mut i32) = size as i32; }
+            },
+            TIOCOUTQ => {
+                unsafe { *(data as *mut i32) = 0; }
+            },
+            TIOCSCTTY => {
+                if task::current().tty.load(Ordering::Relaxed) == self.id() {
+                    return Ok(0);
+                }
+                if !self.set_ctty(data == 1) {
+                    return Err(AxError::NoPermission);
+                }
+            },
+            TIOCNOTTY => {
+                if !self.is_ctty() {
+                    return Err(AxError::NotATty);
+                }
+                let current = task::current();
+                if current.is_session_leader() {
+                    self.signal_foreground(SIGHUP);
+                    self.session.store(0, Ordering::Relaxed);
+                    self.pgrp.store(0, Ordering::Relaxed);
+                }
+                for tid in current.thread_group() {
+                    if let Some(t) = task::get_task(tid) {
+                        t.tty.store(0, Ordering::Relaxed);
+                    }
+                }
+            },
+            TIOCGPGRP | TIOCGSID => {
+                if !self.is_ctty() {
+                    return Err(AxError::NotATty);
+                }
+                let id = if req == TIOCGPGRP { &self.pgrp } else { &self.session };
+                let id = task::pid_vnr(id.load(Ordering::Relaxed));
+                unsafe { *(data as *mut i32) = id as i32; }
+            },
+            TIOCSPGRP => {
+                if !self.is_ctty() {
+                    return Err(AxError::NotATty);
+                }
+                let pgrp = unsafe { *(data as *const i32) };
+                if pgrp < 0 {
+                    return Err(AxError::InvalidInput);
+                }
+                let Some(pgrp) = task::find_vpid(pgrp as usize) else {
+                    return Err(AxError::NoPermission);
+                };
+                if !sys::pgrp_in_session(pgrp, self.session.load(Ordering::Relaxed)) {
+                    return Err(AxError::NoPermission);
+                }
+                self.pgrp.store(pgrp, Ordering::Relaxed);
+            },
+            _ => {
+                warn!("tty: unknown ioctl {:#x}", req);
+                return Err(AxError::NotATty);
+            },
+        }
+        Ok(0)
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        TTYS.lock().remove(&self.id());
+    }
+}
+
+/// A device node of a tty, e.g. `/dev/console` or `/dev/pts/0`.
+pub struct TtyNode {
+    tty: Arc<Tty>,
+    ino: usize,
+}
+
+impl TtyNode {
+    pub fn new(tty: Arc<Tty>) -> Self {
+        Self { tty, ino: alloc_ino() }
+    }
+}
+
+impl VfsNodeOps for TtyNode {
+    fn open(&self, flags: i32) -> VfsResult {
+        self.tty.open(flags)
+    }
+
+    fn release(&self) -> VfsResult {
+        self.tty.release();
+        Ok(())
+    }
+
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(self.tty.major, self.tty.minor);
+        Ok(attr)
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.tty.read(buf)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.tty.write(buf)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    fn ioctl(&self, req: usize, data: usize) -> VfsResult<usize> {
+        self.tty.ioctl(req, data)
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(self.tty.poll())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// `/dev/tty`, which opens the controlling tty of the opener.
+pub struct CurrentTtyDev;
+
+impl VfsNodeOps for CurrentTtyDev {
+    fn open_clone(&self, flags: i32) -> VfsResult<Option<VfsNodeRef>> {
+        let id = task::current().tty.load(Ordering::Relaxed);
+        let tty = get_tty(id).ok_or(AxError::NoDevOrAddr)?;
+        let node = TtyNode::new(tty);
+        node.open(flags | O_NOCTTY)?;
+        Ok(Some(Arc::new(node)))
+    }
+
+    fn get_ino(&self) -> usize {
+        0
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(MAJOR_TTYAUX, 0);
+        Ok(attr)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
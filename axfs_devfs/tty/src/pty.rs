@@ -0,0 +1,263 @@
+//! Pseudo-terminals.
+//!
+//! Each open of `/dev/ptmx` makes a pair: a master, which the opener gets,
+//! and a slave tty at `/dev/pts/<n>`. What is written to the master is the
+//! input of the slave, and what the slave outputs is read from the master.
+//! The slave can be opened once the master unlocks it, and hangs up when
+//! the master is closed.
+
+use core::cmp::min;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use axerrno::AxError;
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult};
+use axfs_vfs::alloc_ino;
+use axio::PollState;
+use axtype::{MAJOR_PTS, MAJOR_TTYAUX};
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+use crate::{Tty, TtyDriver, TtyNode};
+
+const TIOCGPTN:   usize = 0x80045430;
+const TIOCSPTLCK: usize = 0x40045431;
+
+/// Most ptys at a time.
+const MAX_PTYS: usize = 256;
+
+/// Slaves of the ptys, by their numbers.
+static PTYS: SpinNoIrq<BTreeMap<usize, Weak<Tty>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// What both sides of a pty share.
+struct PtyLink {
+    index: usize,
+    /// Output of the slave for the master to read.
+    buf: SpinNoIrq<VecDeque<u8>>,
+    read_wait: WaitQueue,
+    locked: AtomicBool,
+    /// Whether all files of the slave were closed.
+    slave_closed: AtomicBool,
+}
+
+struct PtySlaveDriver {
+    link: Arc<PtyLink>,
+}
+
+impl TtyDriver for PtySlaveDriver {
+    fn write(&self, _tty: &Tty, buf: &[u8]) {
+        self.link.buf.lock().extend(buf);
+        self.link.read_wait.notify_all(false);
+    }
+
+    fn open(&self) -> VfsResult {
+        if self.link.locked.load(Ordering::Relaxed) {
+            return Err(AxError::Io);
+        }
+        self.link.slave_closed.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn close(&self) {
+        self.link.slave_closed.store(true, Ordering::Relaxed);
+        self.link.read_wait.notify_all(false);
+    }
+}
+
+/// The master side of a pty, which is what an open of `/dev/ptmx` gets.
+pub struct PtyMaster {
+    link: Arc<PtyLink>,
+    slave: Arc<Tty>,
+    ino: usize,
+}
+
+impl PtyMaster {
+    fn new() -> VfsResult<Self> {
+        let mut ptys = PTYS.lock();
+        let index = (0..MAX_PTYS)
+            .find(|i| !ptys.contains_key(i))
+            .ok_or(AxError::NoDevOrAddr)?;
+        let link = Arc::new(PtyLink {
+            index,
+            buf: SpinNoIrq::new(VecDeque::new()),
+            read_wait: WaitQueue::new(),
+            locked: AtomicBool::new(true),
+            slave_closed: AtomicBool::new(false),
+        });
+        let driver = PtySlaveDriver { link: link.clone() };
+        let slave = Tty::new(MAJOR_PTS, index as u32, Box::new(driver));
+        ptys.insert(index, Arc::downgrade(&slave));
+        info!("pty {} created", index);
+        Ok(Self { link, slave, ino: alloc_ino() })
+    }
+}
+
+impl VfsNodeOps for PtyMaster {
+    fn release(&self) -> VfsResult {
+        info!("pty {} master closed", self.link.index);
+        PTYS.lock().remove(&self.link.index);
+        self.slave.hangup();
+        Ok(())
+    }
+
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(MAJOR_TTYAUX, 2);
+        Ok(attr)
+    }
+
+    /// Reads what the slave put out. Once the slave is closed, it fails
+    /// with EIO instead of waiting.
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        loop {
+            {
+                let mut out = self.link.buf.lock();
+                if !out.is_empty() {
+                    let size = min(buf.len(), out.len());
+                    for (dst, c) in buf.iter_mut().zip(out.drain(..size)) {
+                        *dst = c;
+                    }
+                    return Ok(size);
+                }
+            }
+            if self.link.slave_closed.load(Ordering::Relaxed) {
+                return Err(AxError::Io);
+            }
+            self.link.read_wait.wait_until_interruptible(|| {
+                !self.link.buf.lock().is_empty() || self.link.slave_closed.load(Ordering::Relaxed)
+            })?;
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.slave.receive_buf(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    fn ioctl(&self, req: usize, data: usize) -> VfsResult<usize> {
+        match req {
+            TIOCGPTN => {
+                unsafe { *(data as *mut u32) = self.link.index as u32; }
+                Ok(0)
+            },
+            TIOCSPTLCK => {
+                let lock = unsafe { *(data as *const i32) } != 0;
+                self.link.locked.store(lock, Ordering::Relaxed);
+                Ok(0)
+            },
+            // The rest, like termios and window size, are of the slave.
+            _ => self.slave.ioctl(req, data),
+        }
+    }
+
+    /// Readable with output of the slave, or once it is closed, when a read
+    /// fails at once.
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: !self.link.buf.lock().is_empty() || self.link.slave_closed.load(Ordering::Relaxed),
+            writable: true,
+        })
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// `/dev/ptmx`, which makes a new pty at each open.
+pub struct PtmxDev;
+
+impl VfsNodeOps for PtmxDev {
+    fn open_clone(&self, _flags: i32) -> VfsResult<Option<VfsNodeRef>> {
+        Ok(Some(Arc::new(PtyMaster::new()?)))
+    }
+
+    fn get_ino(&self) -> usize {
+        0
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(MAJOR_TTYAUX, 2);
+        Ok(attr)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// `/dev/pts`, with the slaves of the ptys there are.
+pub struct PtsDir {
+    ino: usize,
+}
+
+impl PtsDir {
+    pub fn new() -> Self {
+        Self { ino: alloc_ino() }
+    }
+}
+
+impl VfsNodeOps for PtsDir {
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(4096, 0, 0, 0, 0o755))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str, _flags: i32) -> VfsResult<(VfsNodeRef, String)> {
+        let name = path.trim_matches('/');
+        if name.is_empty() || name == "." {
+            return Ok((self.clone() as VfsNodeRef, String::new()));
+        }
+        let index = name.parse::<usize>().map_err(|_| VfsError::NotFound)?;
+        let slave = PTYS.lock().get(&index)
+            .and_then(|slave| slave.upgrade())
+            .ok_or(VfsError::NotFound)?;
+        Ok((Arc::new(TtyNode::new(slave)), String::new()))
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let ptys = PTYS.lock();
+        let mut indices = ptys.keys().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some(index) = indices.next() {
+                        *ent = VfsDirEntry::new(&index.to_string(), VfsNodeType::CharDevice);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
@@ -0,0 +1,95 @@
+//! Terminal attributes, as in "include/uapi/asm-generic/termbits.h".
+
+pub const NCCS: usize = 19;
+
+/* c_cc characters */
+pub const VINTR:    usize = 0;
+pub const VQUIT:    usize = 1;
+pub const VERASE:   usize = 2;
+pub const VKILL:    usize = 3;
+pub const VEOF:     usize = 4;
+pub const VTIME:    usize = 5;
+pub const VMIN:     usize = 6;
+pub const VSUSP:    usize = 10;
+pub const VEOL:     usize = 11;
+pub const VWERASE:  usize = 14;
+pub const VEOL2:    usize = 16;
+
+/* c_iflag bits */
+pub const ISTRIP:   u32 = 0o000040;
+pub const INLCR:    u32 = 0o000100;
+pub const IGNCR:    u32 = 0o000200;
+pub const ICRNL:    u32 = 0o000400;
+pub const IXON:     u32 = 0o002000;
+
+/* c_oflag bits */
+pub const OPOST:    u32 = 0o000001;
+pub const ONLCR:    u32 = 0o000004;
+pub const OCRNL:    u32 = 0o000010;
+
+/* c_lflag bits */
+pub const ISIG:     u32 = 0o000001;
+pub const ICANON:   u32 = 0o000002;
+pub const ECHO:     u32 = 0o000010;
+pub const ECHOE:    u32 = 0o000020;
+pub const ECHOK:    u32 = 0o000040;
+pub const ECHONL:   u32 = 0o000100;
+pub const NOFLSH:   u32 = 0o000200;
+pub const ECHOCTL:  u32 = 0o001000;
+pub const ECHOKE:   u32 = 0o004000;
+pub const IEXTEN:   u32 = 0o100000;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Termios {
+    pub c_iflag: u32,     /* input mode flags */
+    pub c_oflag: u32,     /* output mode flags */
+    pub c_cflag: u32,     /* control mode flags */
+    pub c_lflag: u32,     /* local mode flags */
+    pub c_line: u8,       /* line discipline */
+    pub c_cc: [u8; NCCS], /* control characters */
+}
+
+impl Termios {
+    /// Cooked mode with echo, as a terminal starts in.
+    pub const fn new() -> Self {
+        Self {
+            c_iflag: ICRNL | IXON,
+            c_oflag: OPOST | ONLCR,
+            c_cflag: 0xcbd,
+            c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            c_line: 0,
+            c_cc: [
+                0x3, 0x1c, 0x7f, 0x15, 0x4, 0x0, 0x1, 0x0, 0x11, 0x13, 0x1a, 0x0, 0x12, 0xf, 0x17,
+                0x16, 0x0, 0x0, 0x0,
+            ],
+        }
+    }
+
+    pub fn iflag(&self, flag: u32) -> bool {
+        (self.c_iflag & flag) != 0
+    }
+
+    pub fn oflag(&self, flag: u32) -> bool {
+        (self.c_oflag & flag) != 0
+    }
+
+    pub fn lflag(&self, flag: u32) -> bool {
+        (self.c_lflag & flag) != 0
+    }
+
+    /// Whether `c` is the control character `index`, which is disabled
+    /// when set to 0.
+    pub fn is_cc(&self, c: u8, index: usize) -> bool {
+        c != 0 && self.c_cc[index] == c
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
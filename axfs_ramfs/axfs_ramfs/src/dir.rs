@@ -1,7 +1,6 @@
 use core::mem;
 use core::mem::transmute;
 use core::ptr::copy_nonoverlapping;
-use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
 use alloc::borrow::ToOwned;
@@ -15,6 +14,10 @@ use axfs_vfs::VfsNodeAttrValid;
 use spin::RwLock;
 
 use crate::file::{FileNode, SymLinkNode};
+use crate::RamUsage;
+use crate::entries::{DirEntries, FIRST_COOKIE};
+use crate::times::NodeTimes;
+use crate::xattr::{Xattrs, impl_xattr_ops};
 use pipefs::PipeNode;
 
 /// The directory node in the RAM filesystem.
@@ -23,24 +26,34 @@ use pipefs::PipeNode;
 pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
-    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    children: RwLock<DirEntries>,
     ino: usize,
     uid: RwLock<u32>,
     gid: RwLock<u32>,
     mode: RwLock<i32>,
+    times: NodeTimes,
+    xattrs: Xattrs,
+    usage: Arc<RamUsage>,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>, uid: u32, gid: u32, mode: i32) -> Arc<Self> {
-        Arc::new_cyclic(|this| Self {
+    pub(super) fn new(
+        parent: Option<Weak<dyn VfsNodeOps>>, uid: u32, gid: u32, mode: i32,
+        usage: Arc<RamUsage>
+    ) -> VfsResult<Arc<Self>> {
+        usage.charge_inode()?;
+        Ok(Arc::new_cyclic(|this| Self {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
-            children: RwLock::new(BTreeMap::new()),
+            children: RwLock::new(DirEntries::new()),
             ino: alloc_ino(),
             uid: RwLock::new(uid),
             gid: RwLock::new(gid),
             mode: RwLock::new(mode),
-        })
+            times: NodeTimes::new(),
+            xattrs: Xattrs::new(),
+            usage,
+        }))
     }
 
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
@@ -59,7 +72,8 @@ impl DirNode {
 
     /// Creates a new node with the given name and type in this directory.
     pub fn create_node(&self, name: &str, ty: VfsNodeType, uid: u32, mut gid: u32, mode: i32) -> VfsResult<VfsNodeRef> {
-        if self.exist(name) {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
             log::error!("AlreadyExists {}", name);
             return Err(VfsError::AlreadyExists);
         }
@@ -69,24 +83,34 @@ impl DirNode {
             gid = *self.gid.read();
         }
         let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new(uid, gid, mode)),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone()), uid, gid, mode),
+            VfsNodeType::File => Arc::new(FileNode::new(uid, gid, mode, self.usage.clone())?),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), uid, gid, mode, self.usage.clone())?,
             VfsNodeType::Fifo => Arc::new(PipeNode::new(uid, gid)),
-            VfsNodeType::SymLink => Arc::new(SymLinkNode::new(uid, gid)),
+            VfsNodeType::SymLink => Arc::new(SymLinkNode::new(uid, gid, self.usage.clone())?),
             VfsNodeType::CharDevice => Arc::new(ConsoleDev),
             _ => return Err(VfsError::Unsupported),
         };
-        self.children.write().insert(name.into(), node.clone());
+        children.insert(name.into(), node.clone());
+        drop(children);
+        self.times.touch_mtime();
         Ok(node)
     }
 
     /// Fill a existed node with the given name into this directory.
+    ///
+    /// Files become hard links, directories can't be linked twice.
     pub fn fill_node(&self, name: &str, node: VfsNodeRef) -> VfsResult {
-        if self.exist(name) {
+        if node.as_any().is::<DirNode>() {
+            return Err(VfsError::NoPermission);
+        }
+        let mut children = self.children.write();
+        if children.contains_key(name) {
             log::error!("AlreadyExists {}", name);
             return Err(VfsError::AlreadyExists);
         }
-        self.children.write().insert(name.into(), node.clone());
+        children.insert(name.into(), node);
+        drop(children);
+        self.times.touch_mtime();
         info!("fill_node with name: {}", name);
         Ok(())
     }
@@ -102,6 +126,91 @@ impl DirNode {
             }
         }
         children.remove(name);
+        self.times.touch_mtime();
+        Ok(())
+    }
+
+    /// Resolves the directory which contains the last component of
+    /// `path`, and returns it with that component.
+    fn lookup_parent<'a>(&self, path: &'a str) -> VfsResult<(Arc<DirNode>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = match path.rfind('/') {
+            Some(n) => (&path[..n], &path[n + 1..]),
+            None => ("", path),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let (node, _) = this.lookup(parent, 0)?;
+        let dir = node.as_any().downcast_ref::<DirNode>()
+            .ok_or(VfsError::NotADirectory)?;
+        Ok((dir.this.upgrade().ok_or(VfsError::NotFound)?, name))
+    }
+
+    /// Checks whether `self` is `dir` or one of its descendants.
+    fn is_descendant_of(&self, dir: &DirNode) -> bool {
+        let mut curr = self.this.upgrade().map(|d| d as VfsNodeRef);
+        while let Some(node) = curr {
+            match node.as_any().downcast_ref::<DirNode>() {
+                Some(d) if core::ptr::eq(d, dir) => return true,
+                Some(d) => curr = d.parent(),
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Moves child `src_name` of `src_dir` to `dst_dir` as `dst_name`.
+    ///
+    /// The node itself is kept, so open handles remain valid.
+    /// An existing destination is replaced following rename(2) rules.
+    fn move_node(src_dir: &Arc<DirNode>, src_name: &str, dst_dir: &Arc<DirNode>, dst_name: &str) -> VfsResult {
+        let node = src_dir.children.read().get(src_name).cloned().ok_or(VfsError::NotFound)?;
+        let src_is_dir = node.get_attr()?.is_dir();
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            if dst_dir.is_descendant_of(dir) {
+                return Err(VfsError::InvalidInput);
+            }
+        }
+
+        if let Some(old) = dst_dir.children.read().get(dst_name) {
+            if Arc::ptr_eq(old, &node) {
+                return Ok(());
+            }
+            match (src_is_dir, old.as_any().downcast_ref::<DirNode>()) {
+                (true, Some(d)) if !d.children.read().is_empty() => {
+                    return Err(VfsError::DirectoryNotEmpty);
+                }
+                (true, None) => return Err(VfsError::NotADirectory),
+                (false, Some(_)) => return Err(VfsError::IsADirectory),
+                _ => {}
+            }
+        }
+
+        if Arc::ptr_eq(src_dir, dst_dir) {
+            let mut children = src_dir.children.write();
+            let node = children.remove(src_name).ok_or(VfsError::NotFound)?;
+            children.insert(dst_name.into(), node);
+            src_dir.times.touch_mtime();
+            return Ok(());
+        }
+
+        // Always lock the two directories in inode order to avoid deadlock.
+        let (mut src_children, mut dst_children) = if src_dir.ino < dst_dir.ino {
+            let src = src_dir.children.write();
+            (src, dst_dir.children.write())
+        } else {
+            let dst = dst_dir.children.write();
+            (src_dir.children.write(), dst)
+        };
+        let node = src_children.remove(src_name).ok_or(VfsError::NotFound)?;
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            *dir.parent.write() = Arc::downgrade(dst_dir) as Weak<dyn VfsNodeOps>;
+        }
+        dst_children.insert(dst_name.into(), node);
+        src_dir.times.touch_mtime();
+        dst_dir.times.touch_mtime();
         Ok(())
     }
 
@@ -145,6 +254,10 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    fn link_child(&self, fname: &str, node: VfsNodeRef) -> VfsResult {
+        self.fill_node(fname, node)
+    }
+
     fn symlink(&self, path: &str, target: &str, uid: u32, gid: u32, mode: i32) -> VfsResult {
         let (name, rest) = split_path(path);
         if let Some(rest) = rest {
@@ -175,9 +288,12 @@ impl VfsNodeOps for DirNode {
     }
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(
+        let mut attr = VfsNodeAttr::new_dir(
             4096, 0, *self.uid.read(), *self.gid.read(), *self.mode.read()
-        ))
+        );
+        attr.set_nlink(2 + self.children.read().nr_subdirs() as u32);
+        self.times.fill(&mut attr);
+        Ok(attr)
     }
 
     fn set_attr(&self, attr: &VfsNodeAttr, valid: &VfsNodeAttrValid) -> VfsResult {
@@ -190,6 +306,7 @@ impl VfsNodeOps for DirNode {
         if valid.contains(VfsNodeAttrValid::ATTR_GID) {
             *self.gid.write() = attr.gid();
         }
+        self.times.set(attr, valid);
         Ok(())
     }
 
@@ -312,56 +429,58 @@ impl VfsNodeOps for DirNode {
         }
     }
 
-    fn getdents(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        if offset != 0 {
-            warn!("NOTICE! todo: check offset[{}] and real length of directory!", offset);
-            return Ok(0);
-        }
-
-        static mut INO_SEQ: u64 = 0;
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::info!("rename at ramfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.lookup_parent(src_path)?;
+        let (dst_dir, dst_name) = self.lookup_parent(dst_path)?;
+        Self::move_node(&src_dir, src_name, &dst_dir, dst_name)
+    }
 
+    /// Fills `buf` with entries from stream position `offset` on.
+    ///
+    /// The offset is a cookie rather than a byte count: `0` and `1` are
+    /// "." and "..", children are located by the cookie they were given on
+    /// insertion. `d_off` of every record is where to resume after it.
+    fn getdents(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let children = self.children.read();
-        let mut children = children.iter().skip((offset.max(2) - 2) as usize);
+        let parent_ino = self.parent().map_or(self.ino, |p| p.get_ino());
+        let dots = [(0, ".", self.ino), (1, "..", parent_ino)];
+        let dots = dots
+            .into_iter()
+            .filter(|(cookie, _, _)| *cookie >= offset)
+            .map(|(cookie, name, ino)| (cookie, String::from(name), ino, DT_::DIR as u8));
+        let entries = children
+            .iter_from(offset.max(FIRST_COOKIE))
+            .map(|(cookie, name, node)| {
+                let ty = match node.get_attr().unwrap().file_type() {
+                    VfsNodeType::File => DT_::REG as u8,
+                    VfsNodeType::Dir => DT_::DIR as u8,
+                    VfsNodeType::CharDevice => DT_::CHR as u8,
+                    VfsNodeType::BlockDevice => DT_::BLK as u8,
+                    VfsNodeType::Fifo => DT_::FIFO as u8,
+                    VfsNodeType::Socket => DT_::SOCK as u8,
+                    VfsNodeType::SymLink => DT_::LNK as u8,
+                };
+                (cookie, name.clone(), node.get_ino(), ty)
+            });
 
         let mut count = 0;
-        for i in offset.. {
-            let (mut name, ty) = match i + offset {
-                0 => (String::from("."), DT_::DIR as u8),
-                1 => (String::from(".."), DT_::DIR as u8),
-                _ => {
-                    if let Some((name, node)) = children.next() {
-                        let ty = match node.get_attr().unwrap().file_type() {
-                            VfsNodeType::File => DT_::REG as u8,
-                            VfsNodeType::Dir => DT_::DIR as u8,
-                            VfsNodeType::CharDevice => DT_::CHR as u8,
-                            VfsNodeType::BlockDevice => DT_::BLK as u8,
-                            VfsNodeType::Fifo => DT_::FIFO as u8,
-                            VfsNodeType::Socket => DT_::SOCK as u8,
-                            VfsNodeType::SymLink => DT_::LNK as u8,
-                        };
-                        (name.clone(), ty)
-                    } else {
-                        return Ok(count as usize);
-                    }
-                }
-            };
+        for (cookie, mut name, ino, ty) in dots.chain(entries) {
             name.push('\0');
             let name_len = name.len();
-            log::info!("[{}] name:{:?} [{}] {}", i, name.as_bytes(), name_len, name.len());
+            debug!("[{}] name:{:?} [{}]", cookie, name, name_len);
 
             let entry_size = mem::size_of::<LinuxDirent64>() + name_len;
-            log::info!("entry_size : {}", entry_size);
-
             if count + entry_size > buf.len() {
-                log::error!("buf for dirents overflow!");
-                return Ok(count as usize);
+                debug!("buf for dirents is full, resume at {}", cookie);
+                break;
             }
 
             let dirent: &mut LinuxDirent64 = unsafe {
                 transmute(buf.as_mut_ptr().offset(count as isize))
             };
-            dirent.d_ino = unsafe { INO_SEQ += 1; INO_SEQ };
-            dirent.d_off = (count + entry_size) as i64;
+            dirent.d_ino = ino as u64;
+            dirent.d_off = (cookie + 1) as i64;
             dirent.d_reclen = entry_size as u16;
             dirent.d_type = ty;
 
@@ -375,12 +494,20 @@ impl VfsNodeOps for DirNode {
 
             count += entry_size;
         }
-        Ok(0)
+        Ok(count)
     }
 
+    impl_xattr_ops! {}
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
+impl Drop for DirNode {
+    fn drop(&mut self) {
+        self.usage.uncharge_inode();
+    }
+}
+
 fn split_path(path: &str) -> (&str, Option<&str>) {
     let trimmed_path = path.trim_start_matches('/');
     trimmed_path.find('/').map_or((trimmed_path, None), |n| {
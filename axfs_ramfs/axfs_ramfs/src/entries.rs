@@ -0,0 +1,113 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use axfs_vfs::VfsNodeRef;
+use hashbrown::HashMap;
+use crate::{DirNode, FileNode};
+
+/// Position of the first child in a directory stream,
+/// `0` and `1` are taken by "." and "..".
+pub(crate) const FIRST_COOKIE: u64 = 2;
+
+/// Children of a ramfs directory.
+///
+/// Names are hashed, so lookup, insertion and removal stay O(1) even for
+/// directories with tens of thousands of entries.
+///
+/// Every entry gets a cookie when it is inserted. Cookies grow
+/// monotonically and are never reused, so the directory can be iterated
+/// by cookie and resumed from any offset handed out before, no matter
+/// how many entries were added or removed in between.
+///
+/// Inserting and removing an entry also maintains the link count of
+/// the file it references and the number of subdirectories.
+pub(crate) struct DirEntries {
+    by_name: HashMap<String, (u64, VfsNodeRef)>,
+    by_cookie: BTreeMap<u64, String>,
+    next_cookie: u64,
+    nr_subdirs: usize,
+}
+
+impl DirEntries {
+    pub(crate) fn new() -> Self {
+        Self {
+            by_name: HashMap::new(),
+            by_cookie: BTreeMap::new(),
+            next_cookie: FIRST_COOKIE,
+            nr_subdirs: 0,
+        }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&VfsNodeRef> {
+        self.by_name.get(name).map(|(_, node)| node)
+    }
+
+    pub(crate) fn contains_key(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// Number of subdirectories, which contribute to the link count of
+    /// this directory by their "..".
+    pub(crate) fn nr_subdirs(&self) -> usize {
+        self.nr_subdirs
+    }
+
+    /// Inserts or replaces the entry `name`, which is placed at the end
+    /// of the directory stream.
+    pub(crate) fn insert(&mut self, name: String, node: VfsNodeRef) -> Option<VfsNodeRef> {
+        self.account(&node, true);
+        let cookie = self.next_cookie;
+        self.next_cookie += 1;
+        self.by_cookie.insert(cookie, name.clone());
+        let old = self.by_name.insert(name, (cookie, node));
+        old.map(|(cookie, node)| {
+            self.by_cookie.remove(&cookie);
+            self.account(&node, false);
+            node
+        })
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> Option<VfsNodeRef> {
+        let (cookie, node) = self.by_name.remove(name)?;
+        self.by_cookie.remove(&cookie);
+        self.account(&node, false);
+        Some(node)
+    }
+
+    fn account(&mut self, node: &VfsNodeRef, linked: bool) {
+        let any = node.as_any();
+        if let Some(file) = any.downcast_ref::<FileNode>() {
+            if linked {
+                file.inc_nlink();
+            } else {
+                file.dec_nlink();
+            }
+        } else if any.is::<DirNode>() {
+            if linked {
+                self.nr_subdirs += 1;
+            } else {
+                self.nr_subdirs -= 1;
+            }
+        }
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &String> {
+        self.by_cookie.values()
+    }
+
+    /// Iterates entries in stream order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &VfsNodeRef)> {
+        self.iter_from(FIRST_COOKIE).map(|(_, name, node)| (name, node))
+    }
+
+    /// Iterates entries whose cookie is not less than `cookie`,
+    /// yielding `(cookie, name, node)`.
+    pub(crate) fn iter_from(&self, cookie: u64) -> impl Iterator<Item = (u64, &String, &VfsNodeRef)> {
+        self.by_cookie.range(cookie..).map(|(cookie, name)| {
+            (*cookie, name, &self.by_name[name].1)
+        })
+    }
+}
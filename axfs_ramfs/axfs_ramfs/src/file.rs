@@ -1,13 +1,19 @@
-use core::ops::Bound;
 use core::cmp::min;
-use core::sync::atomic::{AtomicUsize, Ordering};
-use alloc::{vec, vec::Vec};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use alloc::collections::BTreeMap;
 use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
 use axfs_vfs::VfsNodeAttrValid;
+use axio::PollState;
 use spin::RwLock;
 use axtype::{PAGE_SIZE, PAGE_SHIFT};
 use axfs_vfs::alloc_ino;
+use crate::RamUsage;
+use crate::times::NodeTimes;
+use crate::xattr::{Xattrs, impl_xattr_ops};
 
 /// The symlink node in the RAM filesystem.
 pub struct SymLinkNode {
@@ -15,16 +21,29 @@ pub struct SymLinkNode {
     ino: usize,
     uid: u32,
     gid: u32,
+    times: NodeTimes,
+    xattrs: Xattrs,
+    usage: Arc<RamUsage>,
 }
 
 impl SymLinkNode {
-    pub fn new(uid: u32, gid: u32) -> Self {
-        Self {
+    pub(super) fn new(uid: u32, gid: u32, usage: Arc<RamUsage>) -> VfsResult<Self> {
+        usage.charge_inode()?;
+        Ok(Self {
             buf: RwLock::new(Vec::new()),
             ino: alloc_ino(),
             uid,
             gid,
-        }
+            times: NodeTimes::new(),
+            xattrs: Xattrs::new(),
+            usage,
+        })
+    }
+}
+
+impl Drop for SymLinkNode {
+    fn drop(&mut self) {
+        self.usage.uncharge_inode();
     }
 }
 
@@ -34,7 +53,9 @@ impl VfsNodeOps for SymLinkNode {
     }
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_symlink(0, 0, self.uid, self.gid))
+        let mut attr = VfsNodeAttr::new_symlink(self.buf.read().len() as u64, 0, self.uid, self.gid);
+        self.times.fill(&mut attr);
+        Ok(attr)
     }
 
     fn write_at(&self, pos: u64, buf: &[u8]) -> VfsResult<usize> {
@@ -57,48 +78,106 @@ impl VfsNodeOps for SymLinkNode {
         Ok(rbuf.len())
     }
 
+    impl_xattr_ops! {}
+
     impl_vfs_non_dir_default! {}
 }
 
+/// Page-aligned buffer, so that a page can be mapped into user space.
+#[repr(C, align(4096))]
+struct PageBuf([u8; PAGE_SIZE]);
+
+impl Deref for PageBuf {
+    type Target = [u8; PAGE_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for PageBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// A single page of file content.
+type Page = Box<PageBuf>;
+
+fn new_page() -> Page {
+    Box::new(PageBuf([0u8; PAGE_SIZE]))
+}
+
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 /// Content stores pages in btreemap:
 /// {page_index => content_in_page}
+///
+/// Pages are boxed so that inserting into the map never moves page data,
+/// and a missing page is a hole which reads as zeros.
+///
+/// `nlink` counts directory entries referring to the node. Content is
+/// released when the node itself is dropped, i.e. after the last link
+/// and the last open handle are gone.
+///
+/// Once a page has been handed out by `get_page` (e.g. for a MAP_SHARED
+/// mapping of a /dev/shm file), the file is `mapped`: truncation then
+/// zeroes pages beyond the end instead of freeing them, because they may
+/// still be mapped by some process.
 pub struct FileNode {
-    content: RwLock<BTreeMap::<usize, [u8; PAGE_SIZE]>>,
-    index: AtomicUsize,
-    offset: AtomicUsize,
+    content: RwLock<BTreeMap<usize, Page>>,
+    size: AtomicUsize,
+    mapped: AtomicBool,
+    nlink: AtomicU32,
     ino: usize,
     uid: RwLock<u32>,
     gid: RwLock<u32>,
-    mode: i32,
+    mode: RwLock<i32>,
+    times: NodeTimes,
+    xattrs: Xattrs,
+    usage: Arc<RamUsage>,
 }
 
 impl FileNode {
-    pub(super) fn new(uid: u32, gid: u32, mode: i32) -> Self {
-        Self {
+    pub(super) fn new(uid: u32, gid: u32, mode: i32, usage: Arc<RamUsage>) -> VfsResult<Self> {
+        usage.charge_inode()?;
+        Ok(Self {
             content: RwLock::new(BTreeMap::new()),
-            index: AtomicUsize::new(0),
-            offset: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            mapped: AtomicBool::new(false),
+            nlink: AtomicU32::new(0),
             ino: alloc_ino(),
             uid: RwLock::new(uid),
             gid: RwLock::new(gid),
-            mode,
-        }
+            mode: RwLock::new(mode),
+            times: NodeTimes::new(),
+            xattrs: Xattrs::new(),
+            usage,
+        })
     }
 
     fn size(&self) -> usize {
-        let index = self.index.load(Ordering::Relaxed);
-        let offset = self.offset.load(Ordering::Relaxed);
-        (index << PAGE_SHIFT) + offset
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Number of directory entries referring to this file.
+    pub fn nlink(&self) -> u32 {
+        self.nlink.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn inc_nlink(&self) {
+        self.nlink.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dec_nlink(&self) {
+        self.nlink.fetch_sub(1, Ordering::Relaxed);
+        self.times.touch_ctime();
     }
 
-    fn set_size(&self, newsize: usize) {
-        let index = newsize >> PAGE_SHIFT;
-        let offset = newsize % PAGE_SIZE;
-        self.index.store(index, Ordering::Relaxed);
-        self.offset.store(offset, Ordering::Relaxed);
+    /// Number of pages which are really backed by memory.
+    pub fn nr_pages(&self) -> usize {
+        self.content.read().len()
     }
 }
 
@@ -108,62 +187,75 @@ impl VfsNodeOps for FileNode {
     }
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.size() as u64, 0,
-            *self.uid.read(), *self.gid.read(), self.mode))
+        let blocks = (self.nr_pages() * PAGE_SIZE / 512) as u64;
+        let mut attr = VfsNodeAttr::new_file(self.size() as u64, blocks,
+            *self.uid.read(), *self.gid.read(), *self.mode.read());
+        attr.set_nlink(self.nlink());
+        self.times.fill(&mut attr);
+        Ok(attr)
     }
 
     fn set_attr(&self, attr: &VfsNodeAttr, valid: &VfsNodeAttrValid) -> VfsResult {
+        if valid.contains(VfsNodeAttrValid::ATTR_SIZE) {
+            self.truncate(attr.size())?;
+        }
+        if valid.contains(VfsNodeAttrValid::ATTR_MODE) {
+            *self.mode.write() = attr.mode();
+        }
         if valid.contains(VfsNodeAttrValid::ATTR_UID) {
             *self.uid.write() = attr.uid();
         }
         if valid.contains(VfsNodeAttrValid::ATTR_GID) {
             *self.gid.write() = attr.gid();
         }
+        self.times.set(attr, valid);
         Ok(())
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let size = size as usize;
-        let index = size >> PAGE_SHIFT;
-        let offset = size % PAGE_SIZE;
+        let mut content = self.content.write();
         if size < self.size() {
             debug!("truncate size: {} < {}", size, self.size());
-            let mut remove_keys = vec![];
-            {
-                let content = self.content.read();
-                let mut lower = content.lower_bound(Bound::Excluded(&index));
-                loop {
-                    if let Some(index) = lower.key() {
-                        remove_keys.push(*index);
-                    } else {
-                        break;
-                    }
-                    lower.move_next();
-                }
+            // Drop all pages beyond the new end of file,
+            // or just clear them if they may be mapped.
+            let first = (size + PAGE_SIZE - 1) >> PAGE_SHIFT;
+            if self.mapped.load(Ordering::Relaxed) {
+                content.range_mut(first..).for_each(|(_, page)| page.fill(0));
+            } else {
+                let dropped = content.split_off(&first);
+                self.usage.uncharge_pages(dropped.len());
             }
-            for key in remove_keys {
-                let mut content = self.content.write();
-                content.remove(&key);
+            // Zero the tail of the last partial page, so that
+            // extending the file later reads back zeros.
+            let offset = size % PAGE_SIZE;
+            if offset != 0 {
+                if let Some(page) = content.get_mut(&(size >> PAGE_SHIFT)) {
+                    page[offset..].fill(0);
+                }
             }
         }
-
-        self.index.store(index, Ordering::Relaxed);
-        self.offset.store(offset, Ordering::Relaxed);
+        self.size.store(size, Ordering::Relaxed);
+        self.times.touch_mtime();
         Ok(())
     }
 
     fn read_at(&self, pos: u64, buf: &mut [u8]) -> VfsResult<usize> {
         info!("read_at pos {}, buf.len {}, total: {}", pos, buf.len(), self.size());
+        let content = self.content.read();
         let mut pos = pos as usize;
-        let start = pos as usize;
+        let start = pos;
         let end = min(pos + buf.len(), self.size());
+        if start >= end {
+            return Ok(0);
+        }
 
         let mut buf_pos = 0;
         while pos < end {
             let index = pos >> PAGE_SHIFT;
             let offset = pos % PAGE_SIZE;
             let size = min(PAGE_SIZE - offset, end - pos);
-            if let Some(page) = self.content.read().get(&index) {
+            if let Some(page) = content.get(&index) {
                 let src = &page[offset..offset+size];
                 buf[buf_pos..buf_pos+size].copy_from_slice(src);
             } else {
@@ -172,6 +264,7 @@ impl VfsNodeOps for FileNode {
             pos += size;
             buf_pos += size;
         }
+        self.times.touch_atime();
         Ok(end - start)
     }
 
@@ -180,29 +273,63 @@ impl VfsNodeOps for FileNode {
         let end = pos + buf.len();
         debug!("write_at pos {}, buf.len {} end {}...", pos, buf.len(), end);
 
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Charge all missing pages up front, so a write either fits
+        // into the limit as a whole or fails without side effects.
+        let mut content = self.content.write();
+        let missing = ((pos >> PAGE_SHIFT)..=((end - 1) >> PAGE_SHIFT))
+            .filter(|index| !content.contains_key(index))
+            .count();
+        self.usage.charge_pages(missing)?;
+
         let mut buf_pos = 0;
         while pos < end {
             let index = pos >> PAGE_SHIFT;
             let offset = pos % PAGE_SIZE;
             let size = min(PAGE_SIZE - offset, end - pos);
 
-            if self.content.read().get(&index).is_none() {
-                self.content.write().insert(index, [0u8; PAGE_SIZE]);
-            }
-
-            let mut content = self.content.write();
-            let page = content.get_mut(&index).unwrap();
+            let page = content.entry(index).or_insert_with(new_page);
             page[offset..offset+size].copy_from_slice(&buf[buf_pos..buf_pos+size]);
             pos += size;
             buf_pos += size;
-
-            if pos > self.size() {
-                self.set_size(pos);
-            }
         }
+        if end > self.size() {
+            self.size.store(end, Ordering::Relaxed);
+        }
+        self.times.touch_mtime();
         debug!("write_at: ret {} ok!", buf.len());
         Ok(buf.len())
     }
 
+    fn get_page(&self, offset: u64) -> VfsResult<usize> {
+        let index = offset as usize >> PAGE_SHIFT;
+        let mut content = self.content.write();
+        if !content.contains_key(&index) {
+            self.usage.charge_pages(1)?;
+            content.insert(index, new_page());
+        }
+        self.mapped.store(true, Ordering::Relaxed);
+        Ok(content[&index].as_ptr() as usize)
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    impl_xattr_ops! {}
+
     impl_vfs_non_dir_default! {}
 }
+
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        self.usage.uncharge_pages(self.content.get_mut().len());
+        self.usage.uncharge_inode();
+    }
+}
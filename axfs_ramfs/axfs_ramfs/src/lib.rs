@@ -3,20 +3,24 @@
 //! The implementation is based on [`axfs_vfs`].
 
 #![cfg_attr(not(test), no_std)]
-#![feature(btree_cursors)]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
 mod dir;
+mod entries;
 mod file;
+mod times;
+mod usage;
+mod xattr;
 
 #[cfg(test)]
 mod tests;
 
 pub use self::dir::DirNode;
 pub use self::file::FileNode;
+pub use self::usage::RamUsage;
 
 use alloc::sync::Arc;
 use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult, FileSystemInfo};
@@ -30,17 +34,33 @@ const RAMFS_MAGIC: u64 = 0x858458f6;
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
     root: Arc<DirNode>,
+    usage: Arc<RamUsage>,
 }
 
 impl RamFileSystem {
     /// Create a new instance.
     pub fn new(uid: u32, gid: u32, mode: i32) -> Self {
+        Self::with_limits(uid, gid, mode, usize::MAX, usize::MAX)
+    }
+
+    /// Create a new instance which holds at most `max_bytes` of file
+    /// content and `max_inodes` nodes (including the root directory).
+    pub fn with_limits(uid: u32, gid: u32, mode: i32, max_bytes: usize, max_inodes: usize) -> Self {
+        let usage = RamUsage::new(max_bytes, max_inodes);
+        let root = DirNode::new(None, uid, gid, mode, usage.clone())
+            .expect("ramfs: no inode left for root");
         Self {
             parent: Once::new(),
-            root: DirNode::new(None, uid, gid, mode),
+            root,
+            usage,
         }
     }
 
+    /// Returns the memory accounting of this instance.
+    pub fn usage(&self) -> &RamUsage {
+        &self.usage
+    }
+
     /// Returns the root directory node in [`Arc<DirNode>`](DirNode).
     pub fn root_dir_node(&self) -> Arc<DirNode> {
         self.root.clone()
@@ -62,17 +82,30 @@ impl VfsOps for RamFileSystem {
     }
 
     fn statfs(&self) -> VfsResult<FileSystemInfo> {
-        let info = FileSystemInfo {
+        let mut info = FileSystemInfo {
             f_type: RAMFS_MAGIC,
             f_bsize: PAGE_SIZE as u64,
+            f_frsize: PAGE_SIZE as u64,
+            f_namelen: 255,
             ..Default::default()
         };
+        // Like tmpfs, an unlimited instance reports zero sizes.
+        let usage = &self.usage;
+        if usage.max_bytes() != usize::MAX {
+            info.f_blocks = usage.max_pages() as u64;
+            info.f_bfree = usage.max_pages().saturating_sub(usage.nr_pages()) as u64;
+            info.f_bavail = info.f_bfree;
+        }
+        if usage.max_inodes() != usize::MAX {
+            info.f_files = usage.max_inodes() as u64;
+            info.f_ffree = usage.max_inodes().saturating_sub(usage.inodes()) as u64;
+        }
         Ok(info)
     }
 
     fn alloc_inode(&self, ty: VfsNodeType, uid: u32, gid: u32, mode: i32) -> VfsResult<VfsNodeRef> {
         match ty {
-            VfsNodeType::File => Ok(Arc::new(FileNode::new(uid, gid, mode))),
+            VfsNodeType::File => Ok(Arc::new(FileNode::new(uid, gid, mode, self.usage.clone())?)),
             _ => return Err(VfsError::Unsupported),
         }
     }
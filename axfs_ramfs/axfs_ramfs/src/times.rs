@@ -0,0 +1,68 @@
+use core::time::Duration;
+use axfs_vfs::{VfsNodeAttr, VfsNodeAttrValid};
+use spin::RwLock;
+
+/// Timestamps of a ramfs node.
+pub(crate) struct NodeTimes {
+    inner: RwLock<Times>,
+}
+
+#[derive(Clone, Copy)]
+struct Times {
+    atime: Duration,
+    mtime: Duration,
+    ctime: Duration,
+}
+
+fn now() -> Duration {
+    timekeeping::ktime_get_real_coarse()
+}
+
+impl NodeTimes {
+    pub(crate) fn new() -> Self {
+        let now = now();
+        Self {
+            inner: RwLock::new(Times { atime: now, mtime: now, ctime: now }),
+        }
+    }
+
+    /// Content has been read.
+    pub(crate) fn touch_atime(&self) {
+        self.inner.write().atime = now();
+    }
+
+    /// Content has been modified.
+    pub(crate) fn touch_mtime(&self) {
+        let now = now();
+        let mut times = self.inner.write();
+        times.mtime = now;
+        times.ctime = now;
+    }
+
+    /// Attributes have been changed.
+    pub(crate) fn touch_ctime(&self) {
+        self.inner.write().ctime = now();
+    }
+
+    /// Applies the time fields of a `set_attr` request.
+    pub(crate) fn set(&self, attr: &VfsNodeAttr, valid: &VfsNodeAttrValid) {
+        let mut times = self.inner.write();
+        if valid.contains(VfsNodeAttrValid::ATTR_ATIME) {
+            times.atime = attr.atime();
+        }
+        if valid.contains(VfsNodeAttrValid::ATTR_MTIME) {
+            times.mtime = attr.mtime();
+        }
+        times.ctime = if valid.contains(VfsNodeAttrValid::ATTR_CTIME) {
+            attr.ctime()
+        } else {
+            now()
+        };
+    }
+
+    /// Fills timestamps into `attr`.
+    pub(crate) fn fill(&self, attr: &mut VfsNodeAttr) {
+        let times = self.inner.read();
+        attr.set_times(times.atime, times.mtime, times.ctime);
+    }
+}
@@ -0,0 +1,81 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::sync::Arc;
+use axfs_vfs::{VfsError, VfsResult};
+use axtype::PAGE_SIZE;
+
+/// Memory accounting of one [`RamFileSystem`](crate::RamFileSystem) instance.
+///
+/// It is shared by all nodes of the filesystem. Page and inode allocations
+/// beyond the limits fail with [`VfsError::StorageFull`] (ENOSPC).
+pub struct RamUsage {
+    pages: AtomicUsize,
+    inodes: AtomicUsize,
+    max_bytes: usize,
+    max_pages: usize,
+    max_inodes: usize,
+}
+
+impl RamUsage {
+    pub(crate) fn new(max_bytes: usize, max_inodes: usize) -> Arc<Self> {
+        Arc::new(Self {
+            pages: AtomicUsize::new(0),
+            inodes: AtomicUsize::new(0),
+            max_bytes,
+            max_pages: max_bytes / PAGE_SIZE,
+            max_inodes,
+        })
+    }
+
+    /// Bytes of file content held by the filesystem.
+    pub fn bytes(&self) -> usize {
+        self.pages.load(Ordering::Relaxed) * PAGE_SIZE
+    }
+
+    /// Number of live inodes in the filesystem.
+    pub fn inodes(&self) -> usize {
+        self.inodes.load(Ordering::Relaxed)
+    }
+
+    /// Limit of content bytes, `usize::MAX` means unlimited.
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Limit of inodes, `usize::MAX` means unlimited.
+    pub fn max_inodes(&self) -> usize {
+        self.max_inodes
+    }
+
+    pub(crate) fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    pub(crate) fn nr_pages(&self) -> usize {
+        self.pages.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn charge_pages(&self, count: usize) -> VfsResult {
+        Self::charge(&self.pages, count, self.max_pages)
+    }
+
+    pub(crate) fn uncharge_pages(&self, count: usize) {
+        self.pages.fetch_sub(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn charge_inode(&self) -> VfsResult {
+        Self::charge(&self.inodes, 1, self.max_inodes)
+    }
+
+    pub(crate) fn uncharge_inode(&self) {
+        self.inodes.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn charge(counter: &AtomicUsize, count: usize, max: usize) -> VfsResult {
+        counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(count).filter(|&total| total <= max)
+            })
+            .map(|_| ())
+            .map_err(|_| VfsError::StorageFull)
+    }
+}
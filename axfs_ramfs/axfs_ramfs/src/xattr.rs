@@ -0,0 +1,109 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use axfs_vfs::{VfsError, VfsResult};
+use axtype::{XATTR_CREATE, XATTR_REPLACE, XATTR_NAME_MAX, XATTR_SIZE_MAX};
+use spin::RwLock;
+
+/// Namespaces accepted by ramfs, like tmpfs.
+const XATTR_PREFIXES: [&str; 3] = ["user.", "trusted.", "security."];
+
+/// Extended attributes of a ramfs node.
+pub(crate) struct Xattrs {
+    map: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl Xattrs {
+    pub(crate) const fn new() -> Self {
+        Self { map: RwLock::new(BTreeMap::new()) }
+    }
+
+    pub(crate) fn get(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        check_name(name)?;
+        let map = self.map.read();
+        let value = map.get(name).ok_or(VfsError::NoData)?;
+        copy_out(value, buf)
+    }
+
+    pub(crate) fn set(&self, name: &str, value: &[u8], flags: usize) -> VfsResult {
+        check_name(name)?;
+        if value.len() > XATTR_SIZE_MAX {
+            return Err(VfsError::OutOfRange);
+        }
+        let mut map = self.map.write();
+        let exists = map.contains_key(name);
+        if (flags & XATTR_CREATE) != 0 && exists {
+            return Err(VfsError::AlreadyExists);
+        }
+        if (flags & XATTR_REPLACE) != 0 && !exists {
+            return Err(VfsError::NoData);
+        }
+        map.insert(name.into(), value.into());
+        Ok(())
+    }
+
+    pub(crate) fn list(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let map = self.map.read();
+        let mut names = Vec::new();
+        for name in map.keys() {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        copy_out(&names, buf)
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> VfsResult {
+        check_name(name)?;
+        self.map.write().remove(name).map(|_| ()).ok_or(VfsError::NoData)
+    }
+}
+
+fn check_name(name: &str) -> VfsResult {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(VfsError::OutOfRange);
+    }
+    if !XATTR_PREFIXES.iter().any(|prefix| name.len() > prefix.len() && name.starts_with(prefix)) {
+        return Err(VfsError::Unsupported);
+    }
+    Ok(())
+}
+
+/// Copies `data` into `buf`, or just reports its size if `buf` is empty.
+fn copy_out(data: &[u8], buf: &mut [u8]) -> VfsResult<usize> {
+    if buf.is_empty() {
+        return Ok(data.len());
+    }
+    if buf.len() < data.len() {
+        return Err(VfsError::OutOfRange);
+    }
+    buf[..data.len()].copy_from_slice(data);
+    Ok(data.len())
+}
+
+/// Implements the xattr operations of [`axfs_vfs::VfsNodeOps`] by
+/// forwarding them to the `xattrs` field of the node.
+macro_rules! impl_xattr_ops {
+    () => {
+        fn getxattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+            self.xattrs.get(name, buf)
+        }
+
+        fn setxattr(&self, name: &str, value: &[u8], flags: usize) -> VfsResult {
+            self.xattrs.set(name, value, flags)?;
+            self.times.touch_ctime();
+            Ok(())
+        }
+
+        fn listxattr(&self, buf: &mut [u8]) -> VfsResult<usize> {
+            self.xattrs.list(buf)
+        }
+
+        fn removexattr(&self, name: &str) -> VfsResult {
+            self.xattrs.remove(name)?;
+            self.times.touch_ctime();
+            Ok(())
+        }
+    };
+}
+
+pub(crate) use impl_xattr_ops;
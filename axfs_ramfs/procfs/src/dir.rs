@@ -1,3 +1,6 @@
+use core::mem;
+use core::mem::transmute;
+use core::ptr::copy_nonoverlapping;
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
@@ -5,12 +8,13 @@ use alloc::borrow::ToOwned;
 use axfs_vfs::alloc_ino;
 use axtype::{O_NOFOLLOW, S_ISGID};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
-use axfs_vfs::{VfsError, VfsResult};
+use axfs_vfs::{VfsError, VfsResult, DT_, LinuxDirent64};
 use spin::RwLock;
 
 use crate::file::{FileNode, SymLinkNode};
 
 pub type LookupOp = fn(&str, i32) -> VfsResult<VfsNodeRef>;
+pub type ListOp = fn() -> Vec<(String, VfsNodeRef)>;
 
 /// The directory node in the Proc filesystem.
 ///
@@ -24,6 +28,10 @@ pub struct DirNode {
     gid: RwLock<u32>,
     mode: RwLock<i32>,
     lookup_op: Option<LookupOp>,
+    /// Looks up the names which are not children, for entries made on demand.
+    miss_op: RwLock<Option<LookupOp>>,
+    /// Lists the entries made on demand.
+    list_op: RwLock<Option<ListOp>>,
 }
 
 impl DirNode {
@@ -39,9 +47,17 @@ impl DirNode {
             gid: RwLock::new(gid),
             mode: RwLock::new(mode),
             lookup_op,
+            miss_op: RwLock::new(None),
+            list_op: RwLock::new(None),
         })
     }
 
+    /// Adds entries made on demand besides the children.
+    pub(super) fn set_dynamic(&self, miss_op: LookupOp, list_op: ListOp) {
+        *self.miss_op.write() = Some(miss_op);
+        *self.list_op.write() = Some(list_op);
+    }
+
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
@@ -207,12 +223,14 @@ impl VfsNodeOps for DirNode {
             let node = match name.as_str() {
                 "" | "." => Ok(self.clone() as VfsNodeRef),
                 ".." => self.parent().ok_or(VfsError::NotFound),
-                _ => self
-                    .children
-                    .read()
-                    .get(name.as_str())
-                    .cloned()
-                    .ok_or(VfsError::NotFound),
+                _ => {
+                    let child = self.children.read().get(name.as_str()).cloned();
+                    match (child, *self.miss_op.read()) {
+                        (Some(child), _) => Ok(child),
+                        (None, Some(miss_op)) => miss_op(name.as_str(), flags),
+                        (None, None) => Err(VfsError::NotFound),
+                    }
+                }
             }?;
             debug!("name {} rest {:?} {} flags {:#o}", name, rest, node.get_attr()?.is_symlink(), flags);
             if let Some(linkname) = self.handle_symlink(node.clone(), flags, rest.is_none()) {
@@ -379,9 +397,70 @@ impl VfsNodeOps for DirNode {
     }
     */
 
+    fn getdents(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let parent_ino = self.parent().map_or(self.ino, |p| p.get_ino());
+        let mut entries = alloc::vec![
+            (String::from("."), self.ino, DT_::DIR as u8),
+            (String::from(".."), parent_ino, DT_::DIR as u8),
+        ];
+        let list_op = *self.list_op.read();
+        let dynamic = list_op.map_or(Vec::new(), |list_op| list_op());
+        let children = self.children.read();
+        for (name, node) in children.iter().map(|(name, node)| (name.clone(), node.clone())).chain(dynamic) {
+            entries.push((name, node.get_ino(), dirent_type(&node)));
+        }
+        fill_dirents(offset, buf, entries)
+    }
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
+pub(crate) fn dirent_type(node: &VfsNodeRef) -> u8 {
+    match node.get_attr().map(|attr| attr.file_type()) {
+        Ok(VfsNodeType::Dir) => DT_::DIR as u8,
+        Ok(VfsNodeType::CharDevice) => DT_::CHR as u8,
+        Ok(VfsNodeType::BlockDevice) => DT_::BLK as u8,
+        Ok(VfsNodeType::Fifo) => DT_::FIFO as u8,
+        Ok(VfsNodeType::Socket) => DT_::SOCK as u8,
+        Ok(VfsNodeType::SymLink) => DT_::LNK as u8,
+        _ => DT_::REG as u8,
+    }
+}
+
+/// Packs `entries` from index `offset` on as dirents into `buf`, with the
+/// index of the next entry as the offset of each, and returns the length.
+pub(crate) fn fill_dirents(offset: u64, buf: &mut [u8], entries: Vec<(String, usize, u8)>) -> VfsResult<usize> {
+    let mut count = 0;
+    for (index, (mut name, ino, ty)) in entries.into_iter().enumerate().skip(offset as usize) {
+        name.push('\0');
+        let name_len = name.len();
+        let entry_size = mem::size_of::<LinuxDirent64>() + name_len;
+        if count + entry_size > buf.len() {
+            debug!("buf for dirents is full, resume at {}", index);
+            break;
+        }
+
+        let dirent: &mut LinuxDirent64 = unsafe {
+            transmute(buf.as_mut_ptr().offset(count as isize))
+        };
+        dirent.d_ino = ino as u64;
+        dirent.d_off = (index + 1) as i64;
+        dirent.d_reclen = entry_size as u16;
+        dirent.d_type = ty;
+
+        unsafe {
+            copy_nonoverlapping(
+                name.as_ptr(),
+                dirent.d_name.as_mut_ptr(),
+                name_len
+            )
+        };
+
+        count += entry_size;
+    }
+    Ok(count)
+}
+
 fn split_path(path: &str) -> (&str, Option<&str>) {
     let trimmed_path = path.trim_start_matches('/');
     trimmed_path.find('/').map_or((trimmed_path, None), |n| {
@@ -11,22 +11,23 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod pid;
 
 pub use self::dir::DirNode;
 pub use self::file::{FileNode, SymLinkNode};
+pub use self::pid::{ProcProvider, PidDirNode, PidFileNode, FdDirNode};
 
-use core::cmp::min;
 use alloc::format;
-use alloc::sync::Arc;
 use alloc::string::String;
+use alloc::sync::Arc;
 use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult, FileSystemInfo};
 use axfs_vfs::{VfsError, VfsNodeType, VfsNodeOps};
 use spin::once::Once;
 use axtype::PAGE_SIZE;
-use mm::{VM_READ, VM_WRITE, VM_EXEC, VM_MAYSHARE};
 use axfile::fops::File;
 use axfile::fops::OpenOptions;
 use axerrno::AxError::NotConnected;
+use pid::{Maps, Status};
 
 const PROC_SUPER_MAGIC: u64 = 0x9fa0;
 
@@ -90,6 +91,7 @@ impl Default for ProcFileSystem {
 
 pub fn init_procfs(uid: u32, gid: u32, mode: i32) -> VfsResult<Arc<ProcFileSystem>> {
     let fs = ProcFileSystem::new(uid, gid, mode);
+    fs.root.set_dynamic(pid::lookup_pid, pid::list_pids);
     let root = fs.root_dir();
     let _ = root.create_child("sys", VfsNodeType::Dir, uid, gid, mode)?;
 
@@ -123,6 +125,18 @@ pub fn init_procfs(uid: u32, gid: u32, mode: i32) -> VfsResult<Arc<ProcFileSyste
     let f_meminfo = FileNode::new(Some(read_meminfo), uid, gid, mode);
     root.link_child("meminfo", Arc::new(f_meminfo))?;
 
+    // Group /proc/kmsg
+    let f_kmsg = FileNode::new(Some(read_kmsg), uid, gid, 0o400);
+    root.link_child("kmsg", Arc::new(f_kmsg))?;
+
+    // Group /proc/cmdline
+    let f_cmdline = FileNode::new(Some(read_cmdline), uid, gid, 0o444);
+    root.link_child("cmdline", Arc::new(f_cmdline))?;
+
+    // Group /proc/interrupts
+    let f_interrupts = FileNode::new(Some(read_interrupts), uid, gid, 0o444);
+    root.link_child("interrupts", Arc::new(f_interrupts))?;
+
     Ok(Arc::new(fs))
 }
 
@@ -153,45 +167,50 @@ fn read_meminfo(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
     Ok(buf.len())
 }
 
-fn read_status(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
-    let mm = task::current().mm();
-    let locked_mm = mm.lock();
-    let src = format!("VmLck:\t       {} kB\n\0",
-        locked_mm.locked_vm << 2);
+/// The command line the kernel was booted with.
+fn read_cmdline(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    let src = format!("{}\n", cmdline::saved_command_line());
     let src = src.as_bytes();
-    let src: &[u8] = &src[offset..];
-    buf[..src.len()].copy_from_slice(src);
-    Ok(buf.len())
+    if offset >= src.len() {
+        return Ok(0);
+    }
+    let size = core::cmp::min(src.len() - offset, buf.len());
+    buf[..size].copy_from_slice(&src[offset..offset + size]);
+    Ok(size)
 }
 
-fn read_maps(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
-    let mm = task::current().mm();
-    let locked_mm = mm.lock();
-
-    let mut src = String::from("");
-    locked_mm.vmas.values().for_each(|vma| {
-        let r = if (vma.vm_flags & VM_READ) != 0 { "r" } else { "-" };
-        let w = if (vma.vm_flags & VM_WRITE) != 0 { "w" } else { "-" };
-        let x = if (vma.vm_flags & VM_EXEC) != 0 { "x" } else { "-" };
-        let s = if (vma.vm_flags & VM_MAYSHARE) != 0 { "s" } else { "p" };
-        let flags = format!("{}{}{}{}", r, w, x, s);
-
-        src += format!("{:x}-{:x} {} {:x} 00:00 0 []\n",
-                vma.vm_start, vma.vm_end,
-                flags,
-                vma.vm_pgoff,
-            ).as_str();
-    });
+/// How many times each IRQ line has fired on each CPU.
+fn read_interrupts(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    let src = axirq::show_interrupts();
+    let src = src.as_bytes();
+    if offset >= src.len() {
+        return Ok(0);
+    }
+    let size = core::cmp::min(src.len() - offset, buf.len());
+    buf[..size].copy_from_slice(&src[offset..offset + size]);
+    Ok(size)
+}
 
+/// The kernel log still in the buffer, as dmesg prints it. Unlike that of
+/// Linux, reading it does not consume the records, so it can be read with
+/// an offset as a regular file.
+fn read_kmsg(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    let src: String = axlog2::kmsg::read_log(0).iter().map(|r| r.to_line()).collect();
     let src = src.as_bytes();
     if offset >= src.len() {
         return Ok(0);
     }
-    let src = &src[offset..];
-    error!("offset: {} {}", offset, buf.len());
-    let min_size = min(src.len(), buf.len());
-    buf[..min_size].copy_from_slice(&src[..min_size]);
-    return Ok(buf.len());
+    let size = core::cmp::min(src.len() - offset, buf.len());
+    buf[..size].copy_from_slice(&src[offset..offset + size]);
+    Ok(size)
+}
+
+fn read_status(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    pid::read_generated(&Status, task::current().as_task_ref(), offset, buf)
+}
+
+fn read_maps(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    pid::read_generated(&Maps, task::current().as_task_ref(), offset, buf)
 }
 
 fn read_pagemap(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
@@ -199,8 +218,8 @@ fn read_pagemap(offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
     let va = (offset >> 3) << 12;
 
     let mm = task::current().mm();
-    let locked_mm = mm.lock();
-    if locked_mm.mapped.get(&va).is_some() {
+    let locked_mm = mm.read();
+    if locked_mm.mapped.get(va).is_some() {
         // Todo: fill pagemap with:
         // Bits 0-54  page frame number (PFN) if present
         // Bits 0-4   swap type if swapped
@@ -232,8 +251,6 @@ fn kernel_read(filename: &str, buf: &mut [u8]) -> VfsResult<usize> {
 
     let current = task::current();
     let fs = current.fs.lock();
-    let fsuid = current.fsuid();
-    let fsgid = current.fsgid();
-    let mut file = File::open(filename, &opts, &fs, fsuid, fsgid)?;
+    let mut file = File::open(filename, &opts, &fs, &current.cred())?;
     file.read(buf)
 }
@@ -0,0 +1,400 @@
+//! Per-process directories '/proc/<pid>'.
+//!
+//! Nothing is kept for a process here: the directories are made when they
+//! are looked up, and the content of their files is generated from the
+//! live task, its mm and its fd table on each read.
+
+use core::cmp::min;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axfs_vfs::{alloc_ino, impl_vfs_non_dir_default};
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsResult, DT_};
+use axerrno::AxError::NotConnected;
+use mm::{MmStruct, VM_READ, VM_WRITE, VM_EXEC, VM_MAYSHARE, MMF_DUMP_FILTER_MASK};
+use taskctx::{rt_policy, TaskState};
+use task::{TaskRef, Tid};
+
+use crate::dir::{dirent_type, fill_dirents};
+use crate::file::SymLinkNode;
+
+/// Clock ticks per second in the times of 'stat', as AT_CLKTCK.
+const USER_HZ: u64 = 100;
+const NSEC_PER_TICK: u64 = 1_000_000_000 / USER_HZ;
+
+/// Longest name of a task, as TASK_COMM_LEN without the nul.
+const COMM_LEN: usize = 15;
+
+/// Generates the content of a per-process file from the task it belongs to.
+pub trait ProcProvider: Sync {
+    fn generate(&self, task: &TaskRef) -> VfsResult<String>;
+
+    /// Takes what is written to the file, which is read-only unless this
+    /// is implemented and [`writable`](Self::writable).
+    fn store(&self, _task: &TaskRef, _buf: &[u8]) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+}
+
+/// '/proc/<pid>/stat': one line for ps and top.
+pub struct Stat;
+/// '/proc/<pid>/status': the same in a readable form.
+pub struct Status;
+/// '/proc/<pid>/maps': the memory regions.
+pub struct Maps;
+/// '/proc/<pid>/cmdline': the arguments, each ended by a nul.
+pub struct Cmdline;
+/// '/proc/<pid>/coredump_filter': the areas a core dump has, in hex.
+pub struct CoredumpFilter;
+
+const ENTRIES: [(&str, &dyn ProcProvider); 5] = [
+    ("stat", &Stat),
+    ("status", &Status),
+    ("maps", &Maps),
+    ("cmdline", &Cmdline),
+    ("coredump_filter", &CoredumpFilter),
+];
+
+/// Looks up '/proc/<pid>' for the root of procfs.
+pub(crate) fn lookup_pid(name: &str, _flags: i32) -> VfsResult<VfsNodeRef> {
+    let tid = name.parse::<Tid>().map_err(|_| VfsError::NotFound)?;
+    task::get_task(tid).ok_or(VfsError::NotFound)?;
+    Ok(PidDirNode::new(tid))
+}
+
+/// Lists a directory for each process, but not for the other threads.
+pub(crate) fn list_pids() -> Vec<(String, VfsNodeRef)> {
+    task::all_tasks()
+        .iter()
+        .filter(|task| task.is_group_leader())
+        .map(|task| (task.tid().to_string(), PidDirNode::new(task.tid()) as VfsNodeRef))
+        .collect()
+}
+
+/// Reads from content generated for `task` by `provider` at `offset`.
+pub(crate) fn read_generated(
+    provider: &dyn ProcProvider, task: &TaskRef, offset: usize, buf: &mut [u8]
+) -> VfsResult<usize> {
+    let src = provider.generate(task)?;
+    let src = src.as_bytes();
+    if offset >= src.len() {
+        return Ok(0);
+    }
+    let size = min(src.len() - offset, buf.len());
+    buf[..size].copy_from_slice(&src[offset..offset + size]);
+    Ok(size)
+}
+
+fn get_task(tid: Tid) -> VfsResult<TaskRef> {
+    task::get_task(tid).ok_or(VfsError::NotFound)
+}
+
+/// The directory '/proc/<pid>'.
+pub struct PidDirNode {
+    tid: Tid,
+    ino: usize,
+}
+
+impl PidDirNode {
+    fn new(tid: Tid) -> Arc<Self> {
+        Arc::new(Self { tid, ino: alloc_ino() })
+    }
+
+    fn child(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        if let Some((_, provider)) = ENTRIES.iter().find(|(entry, _)| *entry == name) {
+            return Ok(Arc::new(PidFileNode::new(self.tid, *provider)));
+        }
+        match name {
+            "fd" => Ok(Arc::new(FdDirNode::new(self.tid, true))),
+            "_fd" => Ok(Arc::new(FdDirNode::new(self.tid, false))),
+            _ => Err(VfsError::NotFound),
+        }
+    }
+}
+
+impl VfsNodeOps for PidDirNode {
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let cred = get_task(self.tid)?.cred();
+        Ok(VfsNodeAttr::new_dir(4096, 0, cred.euid, cred.egid, 0o555))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str, flags: i32) -> VfsResult<(VfsNodeRef, String)> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = path.split_once('/').map_or((path, None), |(n, r)| (n, Some(r)));
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            _ => self.child(name)?,
+        };
+        match rest {
+            Some(rest) if !rest.is_empty() => node.lookup(rest, flags),
+            _ => Ok((node, String::new())),
+        }
+    }
+
+    fn getdents(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut entries = alloc::vec![
+            (String::from("."), self.ino, DT_::DIR as u8),
+            (String::from(".."), self.ino, DT_::DIR as u8),
+        ];
+        for name in ENTRIES.iter().map(|(name, _)| *name).chain(["fd"]) {
+            let node = self.child(name)?;
+            entries.push((String::from(name), node.get_ino(), dirent_type(&node)));
+        }
+        fill_dirents(offset, buf, entries)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+/// A file in '/proc/<pid>' generated by a [`ProcProvider`].
+pub struct PidFileNode {
+    tid: Tid,
+    provider: &'static dyn ProcProvider,
+    ino: usize,
+}
+
+impl PidFileNode {
+    fn new(tid: Tid, provider: &'static dyn ProcProvider) -> Self {
+        Self { tid, provider, ino: alloc_ino() }
+    }
+}
+
+impl VfsNodeOps for PidFileNode {
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let cred = get_task(self.tid)?.cred();
+        let mode = if self.provider.writable() { 0o644 } else { 0o444 };
+        Ok(VfsNodeAttr::new_file(0, 0, cred.euid, cred.egid, mode))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        read_generated(self.provider, &get_task(self.tid)?, offset as usize, buf)
+    }
+
+    /// The whole of `buf` is taken at once, wherever it is written.
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.provider.store(&get_task(self.tid)?, buf)?;
+        Ok(buf.len())
+    }
+
+    /// Nothing to truncate, but a writable file is opened with O_TRUNC by
+    /// the shell.
+    fn truncate(&self, _size: u64) -> VfsResult {
+        if self.provider.writable() {
+            Ok(())
+        } else {
+            Err(VfsError::PermissionDenied)
+        }
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// '/proc/<pid>/fd' with a link for each open fd, or '/proc/<pid>/_fd'
+/// which the links point into and which gives the opened nodes.
+pub struct FdDirNode {
+    tid: Tid,
+    links: bool,
+    ino: usize,
+}
+
+impl FdDirNode {
+    fn new(tid: Tid, links: bool) -> Self {
+        Self { tid, links, ino: alloc_ino() }
+    }
+}
+
+impl VfsNodeOps for FdDirNode {
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let cred = get_task(self.tid)?.cred();
+        Ok(VfsNodeAttr::new_dir(4096, 0, cred.euid, cred.egid, 0o500))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str, _flags: i32) -> VfsResult<(VfsNodeRef, String)> {
+        let fd = path.parse::<usize>().map_err(|_| NotConnected)?;
+        let task = get_task(self.tid)?;
+        let file = task.filetable.lock().get_file(fd).ok_or(NotConnected)?;
+        if self.links {
+            let node = SymLinkNode::new(0, 0);
+            let linkto = format!("/proc/{}/_fd/{}", self.tid, fd);
+            node.write_at(0, linkto.as_bytes())?;
+            return Ok((Arc::new(node), String::new()));
+        }
+        let node = file.lock().get_node()?;
+        Ok((node, String::new()))
+    }
+
+    fn getdents(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let task = get_task(self.tid)?;
+        let mut entries = alloc::vec![
+            (String::from("."), self.ino, DT_::DIR as u8),
+            (String::from(".."), self.ino, DT_::DIR as u8),
+        ];
+        let filetable = task.filetable.lock();
+        for fd in 0..filetable.slots_len() {
+            if filetable.get_file(fd).is_some() {
+                entries.push((fd.to_string(), alloc_ino(), DT_::LNK as u8));
+            }
+        }
+        fill_dirents(offset, buf, entries)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+/// The state letter of ps.
+fn state_char(task: &TaskRef) -> char {
+    match task.sched_info.state() {
+        TaskState::Running | TaskState::Runnable => 'R',
+        TaskState::Interruptible => 'S',
+        TaskState::Uninterruptible => 'D',
+        TaskState::Zombie => 'Z',
+    }
+}
+
+fn state_name(task: &TaskRef) -> &'static str {
+    match state_char(task) {
+        'R' => "R (running)",
+        'S' => "S (sleeping)",
+        'D' => "D (disk sleep)",
+        _ => "Z (zombie)",
+    }
+}
+
+fn ppid(task: &TaskRef) -> usize {
+    task.parent().map_or(0, |parent| parent.tgid())
+}
+
+/// The arguments as they are on the initial stack, each ended by a nul.
+fn cmdline(mm: &MmStruct) -> Vec<u8> {
+    let mut args = alloc::vec![0u8; mm.arg_end - mm.arg_start];
+    let size = mm.access_remote(mm.arg_start, &mut args);
+    args.truncate(size);
+    args
+}
+
+/// The name of the program, which is the base name of argv[0] for now.
+fn comm(task: &TaskRef) -> String {
+    let args = task.try_mm().map_or(Vec::new(), |mm| cmdline(&mm.read()));
+    let arg0 = args.split(|&c| c == 0).next().unwrap_or(&[]);
+    let name = arg0.rsplit(|&c| c == b'/').next().unwrap_or(&[]);
+    if name.is_empty() {
+        return String::from("kthread");
+    }
+    String::from_utf8_lossy(&name[..min(name.len(), COMM_LEN)]).into_owned()
+}
+
+/// Sizes in pages of the address space and of what is resident.
+fn vm_pages(task: &TaskRef) -> (usize, usize, usize) {
+    task.try_mm().map_or((0, 0, 0), |mm| {
+        let mm = mm.read();
+        (mm.total_vm() >> 12, mm.mapped.len(), mm.locked_vm)
+    })
+}
+
+impl ProcProvider for Stat {
+    fn generate(&self, task: &TaskRef) -> VfsResult<String> {
+        let info = &task.sched_info;
+        let (prio, nice) = if rt_policy(info.policy()) {
+            (-1 - info.priority(), 0)
+        } else {
+            (20 + info.priority(), info.priority())
+        };
+        let (total_vm, rss, _) = vm_pages(task);
+        Ok(format!(
+            "{} ({}) {} {} {} {} 0 -1 0 0 0 0 0 {} {} 0 0 {} {} {} 0 0 {} {}\n",
+            task.tid(), comm(task), state_char(task),
+            ppid(task), task.pgid(), task.sid(),
+            info.utime_ns() / NSEC_PER_TICK, info.stime_ns() / NSEC_PER_TICK,
+            prio, nice, task.thread_group().len(),
+            total_vm << 12, rss,
+        ))
+    }
+}
+
+impl ProcProvider for Status {
+    fn generate(&self, task: &TaskRef) -> VfsResult<String> {
+        let cred = task.cred();
+        let groups: Vec<String> = cred.groups.iter().map(|gid| gid.to_string()).collect();
+        let (total_vm, rss, locked_vm) = vm_pages(task);
+        Ok(format!(
+            "Name:\t{}\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\n\
+             Uid:\t{}\t{}\t{}\t{}\nGid:\t{}\t{}\t{}\t{}\nGroups:\t{}\n\
+             VmSize:\t{:>8} kB\nVmLck:\t{:>8} kB\nVmRSS:\t{:>8} kB\nThreads:\t{}\n",
+            comm(task), state_name(task), task.tgid(), task.tid(), ppid(task),
+            cred.uid, cred.euid, cred.suid, cred.fsuid,
+            cred.gid, cred.egid, cred.sgid, cred.fsgid,
+            groups.join(" "),
+            total_vm << 2, locked_vm << 2, rss << 2,
+            task.thread_group().len(),
+        ))
+    }
+}
+
+impl ProcProvider for Maps {
+    fn generate(&self, task: &TaskRef) -> VfsResult<String> {
+        let mut src = String::new();
+        let Some(mm) = task.try_mm() else {
+            return Ok(src);
+        };
+        let locked_mm = mm.read();
+        locked_mm.vmas.values().for_each(|vma| {
+            let r = if (vma.vm_flags & VM_READ) != 0 { "r" } else { "-" };
+            let w = if (vma.vm_flags & VM_WRITE) != 0 { "w" } else { "-" };
+            let x = if (vma.vm_flags & VM_EXEC) != 0 { "x" } else { "-" };
+            let s = if (vma.vm_flags & VM_MAYSHARE) != 0 { "s" } else { "p" };
+            let flags = format!("{}{}{}{}", r, w, x, s);
+
+            src += format!("{:x}-{:x} {} {:x} 00:00 0 []\n",
+                    vma.vm_start, vma.vm_end,
+                    flags,
+                    vma.vm_pgoff,
+                ).as_str();
+        });
+        Ok(src)
+    }
+}
+
+impl ProcProvider for Cmdline {
+    fn generate(&self, task: &TaskRef) -> VfsResult<String> {
+        let args = task.try_mm().map_or(Vec::new(), |mm| cmdline(&mm.read()));
+        Ok(String::from_utf8_lossy(&args).into_owned())
+    }
+}
+
+impl ProcProvider for CoredumpFilter {
+    fn generate(&self, task: &TaskRef) -> VfsResult<String> {
+        let mm = task.try_mm().ok_or(VfsError::NotFound)?;
+        let filter = mm.read().coredump_filter;
+        Ok(format!("{:08x}\n", filter))
+    }
+
+    fn store(&self, task: &TaskRef, buf: &[u8]) -> VfsResult {
+        let value = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidInput)?.trim();
+        let value = value.trim_start_matches("0x");
+        let filter = usize::from_str_radix(value, 16).map_err(|_| VfsError::InvalidInput)?;
+        let mm = task.try_mm().ok_or(VfsError::NotFound)?;
+        mm.write().coredump_filter = filter & MMF_DUMP_FILTER_MASK;
+        Ok(())
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+}
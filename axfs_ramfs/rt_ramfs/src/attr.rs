@@ -0,0 +1,40 @@
+use core::time::Duration;
+use axfs_vfs::{VfsNodeAttr, VfsNodeAttrValid, VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+
+pub fn test_attr() -> VfsResult {
+    info!("==============> attr test ...");
+
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File, 0, 0, 0o644)?;
+    let (node, _) = root.lookup("f1", 0)?;
+
+    let mut attr = VfsNodeAttr::default();
+    attr.set_mode(0o600);
+    attr.set_uid(1000);
+    attr.set_gid(100);
+    attr.set_atime(Duration::from_nanos(1));
+    attr.set_mtime(Duration::from_nanos(2));
+    let valid = VfsNodeAttrValid::ATTR_MODE | VfsNodeAttrValid::ATTR_UID
+        | VfsNodeAttrValid::ATTR_GID | VfsNodeAttrValid::ATTR_ATIME
+        | VfsNodeAttrValid::ATTR_MTIME;
+    node.set_attr(&attr, &valid)?;
+
+    let attr = node.get_attr()?;
+    assert_eq!(attr.mode(), 0o600);
+    assert_eq!(attr.uid(), 1000);
+    assert_eq!(attr.gid(), 100);
+    assert_eq!(attr.atime(), Duration::from_nanos(1));
+    assert_eq!(attr.mtime(), Duration::from_nanos(2));
+
+    // Writing moves mtime forward, reading moves atime forward.
+    node.write_at(0, b"abc")?;
+    assert!(node.get_attr()?.mtime() > Duration::from_nanos(2));
+    let mut buf = [0u8; 3];
+    node.read_at(0, &mut buf)?;
+    assert!(node.get_attr()?.atime() > Duration::from_nanos(1));
+
+    info!("==============> attr test ok!");
+    Ok(())
+}
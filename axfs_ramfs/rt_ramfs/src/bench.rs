@@ -1,7 +1,11 @@
+use alloc::format;
 use axfs_vfs::{VfsNodeType, VfsResult, VfsOps};
 use axfs_ramfs::RamFileSystem;
+use axhal::time::current_time;
 
 const BUFSIZ: usize = 2 * axtype::PAGE_SIZE;
+const NR_FILES: usize = 20000;
+const BATCH: usize = 5000;
 
 pub fn test_write() {
     info!("==============> cycle write test ...");
@@ -27,3 +31,29 @@ fn cycle_write() -> VfsResult {
     }
     Ok(())
 }
+
+/// Creates many files in one directory, like untarring into /tmp.
+/// Cost per batch must stay flat as the directory grows.
+pub fn test_create_many() {
+    info!("==============> create many test ...");
+    create_many().unwrap();
+    info!("==============> create many test ok!");
+}
+
+fn create_many() -> VfsResult {
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir_node();
+    for batch in 0..NR_FILES / BATCH {
+        let start = current_time();
+        for i in batch * BATCH..(batch + 1) * BATCH {
+            let name = format!("file{}", i);
+            root.create_node(&name, VfsNodeType::File, 0, 0, 0o644)?;
+            assert!(root.exist(&name));
+        }
+        let elapsed = current_time() - start;
+        info!("[{}..{}]: {} ns per create+lookup",
+            batch * BATCH, (batch + 1) * BATCH, elapsed.as_nanos() / BATCH as u128);
+    }
+    assert_eq!(root.get_entries().len(), NR_FILES);
+    Ok(())
+}
@@ -1,7 +1,7 @@
 extern crate alloc;
 use alloc::sync::Arc;
 use axfs_vfs::{VfsNodeType, VfsResult, VfsOps, VfsNodeOps};
-use axfs_ramfs::RamFileSystem;
+use axfs_ramfs::{RamFileSystem, FileNode};
 use axtype::PAGE_SIZE;
 
 const BUF_SIZE: usize = 32;
@@ -36,6 +36,12 @@ pub fn test_boundary() -> VfsResult {
 
     read_buf(1, 1, node.clone())?;
 
+    // Regrow after truncate: the old tail must read back as zeros.
+    node.truncate(4 * PAGE_SIZE as u64)?;
+    read_buf(3, 0, node.clone())?;
+
+    test_sparse(&ramfs)?;
+
     info!("==============> boundary test ok!");
     Ok(())
 }
@@ -54,3 +60,21 @@ fn read_buf(index: usize, expected: usize, node: Arc<dyn VfsNodeOps>) -> VfsResu
     assert_eq!(rbuf, [expected as u8; BUF_SIZE]);
     Ok(())
 }
+
+fn test_sparse(ramfs: &RamFileSystem) -> VfsResult {
+    let root = ramfs.root_dir();
+    root.create("sparse", VfsNodeType::File, 0, 0, 0o777)?;
+    let (node, _) = root.lookup("sparse", 0)?;
+
+    // Write across one page boundary at ~100M: only those two pages
+    // are allocated, everything before them is a hole.
+    let index = (100 << 20) / PAGE_SIZE + 7;
+    write_buf(index, node.clone())?;
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.nr_pages(), 2);
+    assert_eq!(node.get_attr()?.size(), (index*PAGE_SIZE + BUF_SIZE/2) as u64);
+
+    read_buf(1, 0, node.clone())?;
+    read_buf(index, index & 0xff, node.clone())?;
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use axfs_vfs::{LinuxDirent64, VfsNodeRef, VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+
+const NR_FILES: usize = 100;
+
+pub fn test_getdents() -> VfsResult {
+    info!("==============> getdents test ...");
+
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir();
+    for i in 0..NR_FILES {
+        root.create(&format!("file{}", i), VfsNodeType::File, 0, 0, 0o777)?;
+    }
+
+    // A small buffer forces many resumptions.
+    let mut names = Vec::new();
+    let mut offset = 0;
+    loop {
+        let (batch, next) = read_batch(&root, offset)?;
+        if batch.is_empty() {
+            break;
+        }
+        names.extend(batch);
+        offset = next;
+
+        // Mutate the directory while iterating: removed entries vanish,
+        // new entries show up later, nothing is seen twice.
+        if names.len() == 10 {
+            root.remove("file50")?;
+            root.create("late", VfsNodeType::File, 0, 0, 0o777)?;
+        }
+    }
+
+    assert_eq!(&names[..2], [".", ".."]);
+    assert_eq!(names.len(), 2 + NR_FILES);
+    assert!(!names.iter().any(|n| n == "file50"));
+    assert_eq!(names.last().unwrap(), "late");
+    let mut sorted = names.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted.len(), names.len());
+
+    info!("==============> getdents test ok!");
+    Ok(())
+}
+
+fn read_batch(dir: &VfsNodeRef, offset: u64) -> VfsResult<(Vec<String>, u64)> {
+    let mut buf = [0u8; 128];
+    let len = dir.getdents(offset, &mut buf)?;
+    let mut names = Vec::new();
+    let mut next = offset;
+    let mut pos = 0;
+    while pos < len {
+        let dirent = unsafe { &*(buf.as_ptr().add(pos) as *const LinuxDirent64) };
+        let reclen = dirent.d_reclen as usize;
+        let name = &buf[pos + core::mem::size_of::<LinuxDirent64>()..pos + reclen];
+        let name = core::str::from_utf8(name).unwrap().trim_end_matches('\0');
+        names.push(String::from(name));
+        next = dirent.d_off as u64;
+        pos += reclen;
+    }
+    Ok((names, next))
+}
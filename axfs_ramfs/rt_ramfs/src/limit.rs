@@ -0,0 +1,49 @@
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+use axtype::PAGE_SIZE;
+
+pub fn test_limit() -> VfsResult {
+    info!("==============> limit test ...");
+
+    // Root + two nodes, four pages of content.
+    let ramfs = RamFileSystem::with_limits(0, 0, 0o777, 4 * PAGE_SIZE, 3);
+    let root = ramfs.root_dir();
+    assert_eq!(ramfs.usage().inodes(), 1);
+
+    root.create("f1", VfsNodeType::File, 0, 0, 0o777)?;
+    root.create("f2", VfsNodeType::File, 0, 0, 0o777)?;
+    assert_eq!(
+        root.create("f3", VfsNodeType::File, 0, 0, 0o777).err(),
+        Some(VfsError::StorageFull)
+    );
+
+    let (f1, _) = root.clone().lookup("f1", 0)?;
+    let buf = [1u8; PAGE_SIZE];
+    for i in 0..4 {
+        assert_eq!(f1.write_at((i * PAGE_SIZE) as u64, &buf)?, PAGE_SIZE);
+    }
+    assert_eq!(ramfs.usage().bytes(), 4 * PAGE_SIZE);
+    assert_eq!(
+        f1.write_at((4 * PAGE_SIZE) as u64, &buf).err(),
+        Some(VfsError::StorageFull)
+    );
+    // Overwriting existing pages needs no more space.
+    assert_eq!(f1.write_at(0, &buf)?, PAGE_SIZE);
+
+    let info = ramfs.statfs()?;
+    assert_eq!(info.f_blocks, 4);
+    assert_eq!(info.f_bfree, 0);
+    assert_eq!(info.f_ffree, 0);
+
+    f1.truncate(PAGE_SIZE as u64)?;
+    assert_eq!(ramfs.usage().bytes(), PAGE_SIZE);
+
+    drop(f1);
+    root.remove("f1")?;
+    assert_eq!(ramfs.usage().bytes(), 0);
+    assert_eq!(ramfs.usage().inodes(), 2);
+    root.create("f3", VfsNodeType::File, 0, 0, 0o777)?;
+
+    info!("==============> limit test ok!");
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use alloc::sync::Arc;
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+
+pub fn test_link() -> VfsResult {
+    info!("==============> link test ...");
+
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir();
+    root.create("d", VfsNodeType::Dir, 0, 0, 0o777)?;
+    root.create("f1", VfsNodeType::File, 0, 0, 0o777)?;
+    assert_eq!(root.get_attr()?.nlink(), 3);
+
+    let (f1, _) = root.clone().lookup("f1", 0)?;
+    f1.write_at(0, b"data")?;
+    assert_eq!(f1.get_attr()?.nlink(), 1);
+
+    root.link("d/f2", f1.clone())?;
+    assert_eq!(f1.get_attr()?.nlink(), 2);
+    let (f2, _) = root.clone().lookup("d/f2", 0)?;
+    assert!(Arc::ptr_eq(&f1, &f2));
+    assert_eq!(root.link("f1", f1.clone()).err(), Some(VfsError::AlreadyExists));
+
+    let (d, _) = root.clone().lookup("d", 0)?;
+    assert_eq!(root.link("d2", d).err(), Some(VfsError::NoPermission));
+
+    // Data stays alive as long as any link or handle exists.
+    root.remove("f1")?;
+    assert_eq!(f1.get_attr()?.nlink(), 1);
+    root.remove("d/f2")?;
+    assert_eq!(f1.get_attr()?.nlink(), 0);
+    let mut buf = [0u8; 4];
+    assert_eq!(f1.read_at(0, &mut buf)?, 4);
+    assert_eq!(&buf, b"data");
+
+    drop(f2);
+    drop(f1);
+    assert_eq!(ramfs.usage().bytes(), 0);
+
+    info!("==============> link test ok!");
+    Ok(())
+}
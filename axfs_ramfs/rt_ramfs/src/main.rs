@@ -6,9 +6,16 @@ extern crate axlog2;
 extern crate alloc;
 use core::panic::PanicInfo;
 
+mod attr;
 mod basic;
 mod bench;
 mod boundary;
+mod dents;
+mod limit;
+mod link;
+mod rename;
+mod shm;
+mod xattr;
 
 /// Entry
 #[no_mangle]
@@ -20,7 +27,15 @@ pub extern "Rust" fn runtime_main(_cpu_id: usize, _dtb_pa: usize) {
 
     basic::test_basic();
     bench::test_write();
+    bench::test_create_many();
     boundary::test_boundary().unwrap();
+    limit::test_limit().unwrap();
+    rename::test_rename().unwrap();
+    attr::test_attr().unwrap();
+    dents::test_getdents().unwrap();
+    link::test_link().unwrap();
+    xattr::test_xattr().unwrap();
+    shm::test_shm().unwrap();
 
     info!("[rt_ramfs]: ok!");
     axhal::misc::terminate();
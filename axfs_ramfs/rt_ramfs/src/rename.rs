@@ -0,0 +1,43 @@
+use alloc::sync::Arc;
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+
+pub fn test_rename() -> VfsResult {
+    info!("==============> rename test ...");
+
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir();
+    root.create("a", VfsNodeType::Dir, 0, 0, 0o777)?;
+    root.create("b", VfsNodeType::Dir, 0, 0, 0o777)?;
+    root.create("a/f1", VfsNodeType::File, 0, 0, 0o777)?;
+    root.create("a/f2", VfsNodeType::File, 0, 0, 0o777)?;
+
+    // Rename inside one directory keeps the node.
+    let (f1, _) = root.clone().lookup("a/f1", 0)?;
+    f1.write_at(0, b"hello")?;
+    root.rename("a/f1", "a/g1")?;
+    assert_eq!(root.clone().lookup("a/f1", 0).err(), Some(VfsError::NotFound));
+    let (g1, _) = root.clone().lookup("a/g1", 0)?;
+    assert!(Arc::ptr_eq(&f1, &g1));
+
+    // Move across directories, replacing an existing file.
+    root.create("b/g1", VfsNodeType::File, 0, 0, 0o777)?;
+    root.rename("a/g1", "b/g1")?;
+    let (g1, _) = root.clone().lookup("b/g1", 0)?;
+    assert!(Arc::ptr_eq(&f1, &g1));
+    let mut buf = [0u8; 5];
+    assert_eq!(f1.read_at(0, &mut buf)?, 5);
+    assert_eq!(&buf, b"hello");
+
+    // Move a directory and check its parent link.
+    root.rename("a", "b/a")?;
+    let (f2, _) = root.clone().lookup("b/a/../a/f2", 0)?;
+    assert_eq!(f2.get_attr()?.file_type(), VfsNodeType::File);
+
+    assert_eq!(root.rename("b", "b/a/b").err(), Some(VfsError::InvalidInput));
+    assert_eq!(root.rename("b/g1", "b/a").err(), Some(VfsError::IsADirectory));
+    assert_eq!(root.rename("b/a", "b/g1").err(), Some(VfsError::NotADirectory));
+
+    info!("==============> rename test ok!");
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use axfs_vfs::{VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+use axtype::PAGE_SIZE;
+
+pub fn test_shm() -> VfsResult {
+    info!("==============> shm test ...");
+
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir();
+    root.create("shm", VfsNodeType::File, 0, 0, 0o666)?;
+    let (f, _) = root.clone().lookup("shm", 0)?;
+    f.truncate(2 * PAGE_SIZE as u64)?;
+    assert_eq!(f.get_attr()?.size(), 2 * PAGE_SIZE as u64);
+
+    // Every mapping gets the same page, and it sees file writes.
+    let page = f.get_page(PAGE_SIZE as u64 + 8)?;
+    assert_eq!(page % PAGE_SIZE, 0);
+    assert_eq!(f.get_page(PAGE_SIZE as u64)?, page);
+    f.write_at(PAGE_SIZE as u64, b"shm")?;
+    let mapped = unsafe { core::slice::from_raw_parts_mut(page as *mut u8, PAGE_SIZE) };
+    assert_eq!(&mapped[..3], b"shm");
+
+    // And file reads see stores through the mapping.
+    mapped[3] = b'!';
+    let mut buf = [0u8; 4];
+    f.read_at(PAGE_SIZE as u64, &mut buf)?;
+    assert_eq!(&buf, b"shm!");
+
+    // Shrinking keeps mapped pages alive but clears them.
+    f.truncate(0)?;
+    assert_eq!(mapped[0], 0);
+    f.truncate(2 * PAGE_SIZE as u64)?;
+    f.read_at(PAGE_SIZE as u64, &mut buf)?;
+    assert_eq!(buf, [0u8; 4]);
+
+    let state = f.poll()?;
+    assert!(state.readable && state.writable);
+
+    info!("==============> shm test ok!");
+    Ok(())
+}
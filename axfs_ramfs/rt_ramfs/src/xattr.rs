@@ -0,0 +1,35 @@
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult, VfsOps};
+use axfs_ramfs::RamFileSystem;
+use axtype::{XATTR_CREATE, XATTR_REPLACE};
+
+pub fn test_xattr() -> VfsResult {
+    info!("==============> xattr test ...");
+
+    let ramfs = RamFileSystem::new(0, 0, 0o777);
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File, 0, 0, 0o777)?;
+    let (node, _) = root.lookup("f1", 0)?;
+
+    node.setxattr("user.tag", b"red", 0)?;
+    node.setxattr("trusted.overlay.whiteout", b"y", XATTR_CREATE)?;
+    assert_eq!(node.setxattr("user.tag", b"blue", XATTR_CREATE).err(), Some(VfsError::AlreadyExists));
+    assert_eq!(node.setxattr("user.none", b"x", XATTR_REPLACE).err(), Some(VfsError::NoData));
+    assert_eq!(node.setxattr("system.foo", b"x", 0).err(), Some(VfsError::Unsupported));
+
+    let mut buf = [0u8; 16];
+    assert_eq!(node.getxattr("user.tag", &mut [])?, 3);
+    assert_eq!(node.getxattr("user.tag", &mut buf)?, 3);
+    assert_eq!(&buf[..3], b"red");
+    assert_eq!(node.getxattr("user.tag", &mut buf[..2]).err(), Some(VfsError::OutOfRange));
+
+    let mut list = [0u8; 64];
+    let len = node.listxattr(&mut list)?;
+    assert_eq!(&list[..len], b"trusted.overlay.whiteout\0user.tag\0");
+
+    node.removexattr("user.tag")?;
+    assert_eq!(node.getxattr("user.tag", &mut buf).err(), Some(VfsError::NoData));
+    assert_eq!(node.removexattr("user.tag").err(), Some(VfsError::NoData));
+
+    info!("==============> xattr test ok!");
+    Ok(())
+}
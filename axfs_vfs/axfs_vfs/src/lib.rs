@@ -12,6 +12,8 @@
 //! - [`umount()`](VfsOps::umount): Do something when the filesystem is unmounted.
 //! - [`format()`](VfsOps::format): Format the filesystem.
 //! - [`statfs()`](VfsOps::statfs): Get the attributes of the filesystem.
+//! - [`sync()`](VfsOps::sync): Write back what the filesystem holds in memory.
+//! - [`remount()`](VfsOps::remount): Change the mount flags of the filesystem.
 //! - [`root_dir()`](VfsOps::root_dir): Get root directory of the filesystem.
 //!
 //! The [`VfsNodeOps`] trait provides the following operations on a file or a
@@ -42,15 +44,18 @@ extern crate alloc;
 
 mod macros;
 mod structs;
+mod mount_tree;
 
 pub mod path;
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::sync::Arc;
 use alloc::string::String;
 use crate::alloc::borrow::ToOwned;
 use axerrno::{ax_err, AxError, AxResult};
+use axio::PollState;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use spin::RwLock;
+use spin::{Mutex, RwLock};
+use mount_tree::MountTree;
 
 pub use self::structs::{VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType};
 pub use self::structs::{VfsNodeAttrValid, FileSystemInfo, DT_, LinuxDirent64};
@@ -97,6 +102,18 @@ pub trait VfsOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Write back the data and metadata that the filesystem holds in
+    /// memory, down to the buffer cache of its disk.
+    fn sync(&self) -> VfsResult {
+        Ok(())
+    }
+
+    /// Change the mount flags (`MS_*`) of the filesystem. The flags are
+    /// kept by the mount, so a filesystem only has to act on them.
+    fn remount(&self, _flags: usize) -> VfsResult {
+        Ok(())
+    }
+
     /// Get the root directory of the filesystem.
     fn root_dir(&self) -> VfsNodeRef;
 
@@ -118,6 +135,12 @@ pub trait VfsNodeOps: Send + Sync {
         Ok(())
     }
 
+    /// Gives a node of its own to each open, e.g. a new pty master for
+    /// `/dev/ptmx`, or `None` to open the node itself.
+    fn open_clone(&self, _mode: i32) -> VfsResult<Option<VfsNodeRef>> {
+        Ok(None)
+    }
+
     /// Get the attributes of the node.
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
         ax_err!(Unsupported)
@@ -145,6 +168,27 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(InvalidInput)
     }
 
+    /// Start reading `len` bytes of the file from the given offset into the
+    /// caches, without waiting for them, as they are about to be read. Only
+    /// a hint, which the nodes without caches ignore.
+    fn readahead(&self, _offset: u64, _len: usize) -> VfsResult {
+        Ok(())
+    }
+
+    /// Read data from the file at the given offset, from the device rather
+    /// than the caches, for O_DIRECT. The offset and the length of `buf`
+    /// are multiples of the page size.
+    fn read_direct(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        ax_err!(Unsupported)
+    }
+
+    /// Write data to the file at the given offset, to the device rather
+    /// than the caches, for O_DIRECT. The offset and the length of `buf`
+    /// are multiples of the page size.
+    fn write_direct(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        ax_err!(Unsupported)
+    }
+
     /// Flush the file, synchronize the data to disk.
     fn fsync(&self) -> VfsResult {
         ax_err!(InvalidInput)
@@ -155,6 +199,32 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(InvalidInput)
     }
 
+    /// Get the page which backs `offset` of the file, so that it can be
+    /// mapped shared. A hole is filled with a new zeroed page.
+    ///
+    /// Return the kernel virtual address of the page.
+    fn get_page(&self, _offset: u64) -> VfsResult<usize> {
+        ax_err!(Unsupported)
+    }
+
+    /// Poll the node for readiness.
+    fn poll(&self) -> VfsResult<PollState> {
+        ax_err!(Unsupported)
+    }
+
+    /// Switch the node between blocking and nonblocking mode, as O_NONBLOCK
+    /// of the file opened on it. In nonblocking mode, a read or write that
+    /// would wait fails with `WouldBlock` instead.
+    ///
+    /// For the nodes that are made per open file, such as the ends of a
+    /// pipe. For others, the file finds out through [`poll`] whether to
+    /// wait.
+    ///
+    /// [`poll`]: VfsNodeOps::poll
+    fn set_nonblock(&self, _nonblock: bool) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     // directory operations:
 
     /// Get the parent directory of this directory.
@@ -228,6 +298,32 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Get the value of extended attribute `name` into `buf`.
+    ///
+    /// Return the size of the value. An empty `buf` only queries the size.
+    fn getxattr(&self, _name: &str, _buf: &mut [u8]) -> VfsResult<usize> {
+        ax_err!(Unsupported)
+    }
+
+    /// Set extended attribute `name` to `value`.
+    ///
+    /// `flags` may contain `XATTR_CREATE` or `XATTR_REPLACE`.
+    fn setxattr(&self, _name: &str, _value: &[u8], _flags: usize) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// List names of extended attributes into `buf`, each terminated by '\0'.
+    ///
+    /// Return the size of the list. An empty `buf` only queries the size.
+    fn listxattr(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+        ax_err!(Unsupported)
+    }
+
+    /// Remove extended attribute `name`.
+    fn removexattr(&self, _name: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Convert `&self` to [`&dyn Any`][1] that can use
     /// [`Any::downcast_ref`][2].
     ///
@@ -250,23 +346,32 @@ pub mod __priv {
 pub struct MountPoint {
     path: String,
     fs: Arc<dyn VfsOps>,
+    /// Mount flags (`MS_*`) that remount may change.
+    flags: AtomicUsize,
 }
 
 impl MountPoint {
     pub fn new(path: &str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path: String::from(path), fs }
+        Self { path: String::from(path), fs, flags: AtomicUsize::new(0) }
     }
 }
 
 impl Drop for MountPoint {
     fn drop(&mut self) {
+        debug!("umount {}", self.path);
         self.fs.umount().ok();
     }
 }
 
 pub struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
-    mounts: RwLock<Vec<MountPoint>>,
+    /// Mount flags of the main filesystem.
+    main_flags: AtomicUsize,
+    mounts: RwLock<MountTree>,
+    /// The mount point that the last path was resolved to, as its path
+    /// relative to the root and its filesystem. Only one with nothing
+    /// mounted below is kept, as it holds any path under its own.
+    last_hit: Mutex<Option<(String, VfsRef)>>,
 }
 
 impl VfsNodeOps for RootDirectory {
@@ -331,11 +436,14 @@ impl VfsNodeOps for RootDirectory {
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        let (dst_fs, dst_rest) = self.lookup_fs(dst_path)?;
         self.lookup_mounted_fs(src_path, |fs, rest_path| {
-            if rest_path.is_empty() {
+            if rest_path.is_empty() || dst_rest.trim_matches('/').is_empty() {
                 ax_err!(PermissionDenied) // cannot rename mount points
+            } else if !Arc::ptr_eq(&fs, &dst_fs) {
+                ax_err!(Unsupported) // cannot rename across filesystems
             } else {
-                fs.root_dir().rename(rest_path, dst_path)
+                fs.root_dir().rename(rest_path, &dst_rest)
             }
         })
     }
@@ -349,7 +457,9 @@ impl RootDirectory {
     pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
         Self {
             main_fs,
-            mounts: RwLock::new(Vec::new()),
+            main_flags: AtomicUsize::new(0),
+            mounts: RwLock::new(MountTree::new()),
+            last_hit: Mutex::new(None),
         }
     }
 
@@ -368,21 +478,71 @@ impl RootDirectory {
         self.main_fs.root_dir().create(path, FileType::Dir, uid, gid, 0o777)?;
         let (mnt_point, _) = self.main_fs.root_dir().lookup(path, 0)?;
         fs.mount(path, mnt_point)?;
-        self.mounts.write().push(MountPoint::new(path, fs));
+        let mut mounts = self.mounts.write();
+        if mounts.insert(path, MountPoint::new(path, fs)).is_err() {
+            return ax_err!(AlreadyExists, "mount point already exists");
+        }
+        // The last hit may have a mount below it now.
+        *self.last_hit.lock() = None;
         Ok(())
     }
 
     pub fn _umount(&self, path: &str) {
-        self.mounts.write().retain(|mp| mp.path != path);
+        let mut mounts = self.mounts.write();
+        *self.last_hit.lock() = None;
+        mounts.remove(path);
     }
 
     pub fn contains(&self, path: &str) -> bool {
-        self.mounts.read().iter().any(|mp| mp.path == path)
+        self.mounts.read().get(path).is_some()
     }
 
     pub fn statfs(&self, path: &str) -> AxResult<FileSystemInfo> {
         let (fs, _) = self.lookup_fs(path)?;
-        fs.statfs()
+        let mut info = fs.statfs()?;
+        info.f_flags = self.mount_flags(&fs) as u64;
+        Ok(info)
+    }
+
+    /// Writes back all the filesystems, the main one first. All of them
+    /// are tried, and the first error is returned.
+    pub fn sync(&self) -> AxResult {
+        let mut ret = self.main_fs.sync();
+        self.mounts.read().for_each(|mp| {
+            let r = mp.fs.sync();
+            if ret.is_ok() {
+                ret = r;
+            }
+        });
+        ret
+    }
+
+    /// Changes the mount flags of the filesystem mounted at `path`, which
+    /// has to be a mount point, or "/" for the main filesystem.
+    pub fn remount(&self, path: &str, flags: usize) -> AxResult {
+        if path.trim_matches('/').is_empty() {
+            self.main_fs.remount(flags)?;
+            self.main_flags.store(flags, Ordering::Relaxed);
+            return Ok(());
+        }
+        let mounts = self.mounts.read();
+        let Some(mp) = mounts.get(path) else {
+            return ax_err!(InvalidInput, "not a mount point");
+        };
+        mp.fs.remount(flags)?;
+        mp.flags.store(flags, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Mount flags of `fs`, which is the main filesystem or a mounted one.
+    fn mount_flags(&self, fs: &VfsRef) -> usize {
+        let mut flags = self.main_flags.load(Ordering::Relaxed);
+        self.mounts.read().for_each(|mp| {
+            if Arc::ptr_eq(&mp.fs, fs) {
+                flags = mp.flags.load(Ordering::Relaxed);
+            }
+        });
+        flags
     }
 
     pub fn lookup_fs(&self, path: &str) -> AxResult<(VfsRef, String)> {
@@ -392,27 +552,8 @@ impl RootDirectory {
             return self.lookup_fs(rest);
         }
 
-        let mut idx = 0;
-        let mut max_len = 0;
-
-        let mounts = self.mounts.read();
-
-        // Find the filesystem that has the longest mounted path match
-        // TODO: more efficient, e.g. trie
-        for (i, mp) in mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
-        }
-
-        if max_len == 0 {
-            Ok((self.main_fs.clone(), path.to_owned()))        // not matched any mount point
-        } else {
-            let ret = String::from(&path[max_len..]);
-            Ok((mounts[idx].fs.clone(), ret)) // matched at `idx`
-        }
+        let (fs, rest) = self.resolve(path);
+        Ok((fs, rest.to_owned()))
     }
 
     // Deprecated: use lookup_fs to replace it.
@@ -426,25 +567,32 @@ impl RootDirectory {
             return self.lookup_mounted_fs(rest, f);
         }
 
-        let mut idx = 0;
-        let mut max_len = 0;
-
-        let mounts = self.mounts.read();
+        let (fs, rest) = self.resolve(path);
+        f(fs, rest)
+    }
 
-        // Find the filesystem that has the longest mounted path match
-        // TODO: more efficient, e.g. trie
-        for (i, mp) in mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
+    /// Finds the filesystem that holds `path`, which is relative to the
+    /// root: the one mounted at the longest leading components of it, or
+    /// else the main one. Returns it with the rest of the path.
+    fn resolve<'a>(&self, path: &'a str) -> (VfsRef, &'a str) {
+        if let Some(Some((prefix, fs))) = self.last_hit.try_lock().as_deref() {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                if rest.is_empty() || rest.starts_with('/') {
+                    return (fs.clone(), rest);
+                }
             }
         }
 
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+        let mounts = self.mounts.read();
+        let Some(hit) = mounts.resolve(path) else {
+            return (self.main_fs.clone(), path); // not matched any mount point
+        };
+        if hit.leaf {
+            // Under the read lock, so that no umount comes in between.
+            if let Some(mut last_hit) = self.last_hit.try_lock() {
+                *last_hit = Some((String::from(&path[..hit.len]), hit.mp.fs.clone()));
+            }
         }
+        (hit.mp.fs.clone(), &path[hit.len..])
     }
 }
@@ -0,0 +1,133 @@
+//! The mount points, in a tree by path component.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+use crate::MountPoint;
+
+/// A path component of the tree: the mount point there, if any, and the
+/// components below it.
+#[derive(Default)]
+struct MountNode {
+    mount: Option<MountPoint>,
+    children: BTreeMap<String, MountNode>,
+}
+
+impl MountNode {
+    const fn new() -> Self {
+        Self {
+            mount: None,
+            children: BTreeMap::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.mount.is_none() && self.children.is_empty()
+    }
+
+    fn remove<'a>(&mut self, mut components: impl Iterator<Item = &'a str>) -> Option<MountPoint> {
+        let Some(name) = components.next() else {
+            return self.mount.take();
+        };
+        let child = self.children.get_mut(name)?;
+        let mp = child.remove(components);
+        if child.is_empty() {
+            self.children.remove(name);
+        }
+        mp
+    }
+
+    fn for_each<'a>(&'a self, f: &mut impl FnMut(&'a MountPoint)) {
+        if let Some(mp) = &self.mount {
+            f(mp);
+        }
+        for child in self.children.values() {
+            child.for_each(f);
+        }
+    }
+}
+
+/// The mount points by path, resolved component by component, so that
+/// finding the one that holds a path takes as long as the path, however
+/// many there are.
+pub(crate) struct MountTree {
+    root: MountNode,
+}
+
+/// The mount point that holds a path, as [`MountTree::resolve`] finds it.
+pub(crate) struct Resolved<'a> {
+    pub mp: &'a MountPoint,
+    /// Length of the part of the path that leads to the mount point.
+    pub len: usize,
+    /// Nothing is mounted below it.
+    pub leaf: bool,
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|c| !c.is_empty())
+}
+
+impl MountTree {
+    pub const fn new() -> Self {
+        Self {
+            root: MountNode::new(),
+        }
+    }
+
+    /// Adds `mp` at `path`. Fails, giving it back, if there is one already.
+    pub fn insert(&mut self, path: &str, mp: MountPoint) -> Result<(), MountPoint> {
+        let mut node = &mut self.root;
+        for name in components(path) {
+            node = node.children.entry(String::from(name)).or_default();
+        }
+        if node.mount.is_some() {
+            return Err(mp);
+        }
+        node.mount = Some(mp);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<MountPoint> {
+        self.root.remove(components(path))
+    }
+
+    /// The mount point at `path` exactly.
+    pub fn get(&self, path: &str) -> Option<&MountPoint> {
+        let mut node = &self.root;
+        for name in components(path) {
+            node = node.children.get(name)?;
+        }
+        node.mount.as_ref()
+    }
+
+    /// The deepest mount point on the way of `path`, which is relative to
+    /// the root.
+    pub fn resolve(&self, path: &str) -> Option<Resolved> {
+        let mut node = &self.root;
+        let mut found = None;
+        let mut start = 0;
+        for name in path.split('/') {
+            let end = start + name.len();
+            if !name.is_empty() {
+                let Some(child) = node.children.get(name) else {
+                    break;
+                };
+                node = child;
+                if let Some(mp) = &node.mount {
+                    found = Some(Resolved {
+                        mp,
+                        len: end,
+                        leaf: node.children.is_empty(),
+                    });
+                }
+            }
+            start = end + 1;
+        }
+        found
+    }
+
+    /// Calls `f` on each mount point, parents before children.
+    pub fn for_each<'a>(&'a self, mut f: impl FnMut(&'a MountPoint)) {
+        self.root.for_each(&mut f);
+    }
+}
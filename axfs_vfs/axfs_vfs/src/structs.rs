@@ -1,3 +1,5 @@
+use core::time::Duration;
+
 /// Filesystem attributes.
 #[derive(Default, Clone, Copy)]
 #[repr(C)]
@@ -33,10 +35,6 @@ pub struct KernelFsid {
     _val: [i32; 2],
 }
 
-// #define ATTR_SIZE   (1 << 3)
-// #define ATTR_ATIME  (1 << 4)
-// #define ATTR_MTIME  (1 << 5)
-// #define ATTR_CTIME  (1 << 6)
 // #define ATTR_ATIME_SET  (1 << 7)
 // #define ATTR_MTIME_SET  (1 << 8)
 // #define ATTR_FORCE  (1 << 9) /* Not a change, but a change it */
@@ -55,6 +53,10 @@ bitflags::bitflags! {
         const ATTR_MODE = (1 << 0);
         const ATTR_UID  = (1 << 1);
         const ATTR_GID  = (1 << 2);
+        const ATTR_SIZE  = (1 << 3);
+        const ATTR_ATIME = (1 << 4);
+        const ATTR_MTIME = (1 << 5);
+        const ATTR_CTIME = (1 << 6);
     }
 }
 
@@ -75,6 +77,14 @@ pub struct VfsNodeAttr {
     /// gid
     gid: u32,
     rdev: u32,
+    /// Number of hard links.
+    nlink: u32,
+    /// Time of last access.
+    atime: Duration,
+    /// Time of last modification.
+    mtime: Duration,
+    /// Time of last status change.
+    ctime: Duration,
 }
 
 bitflags::bitflags! {
@@ -293,6 +303,10 @@ impl VfsNodeAttr {
             uid,
             gid,
             rdev: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
@@ -326,6 +340,54 @@ impl VfsNodeAttr {
         self.gid = gid;
     }
 
+    #[inline]
+    pub const fn nlink(&self) -> u32 {
+        self.nlink
+    }
+
+    #[inline]
+    pub fn set_nlink(&mut self, nlink: u32) {
+        self.nlink = nlink;
+    }
+
+    #[inline]
+    pub const fn atime(&self) -> Duration {
+        self.atime
+    }
+
+    #[inline]
+    pub const fn mtime(&self) -> Duration {
+        self.mtime
+    }
+
+    #[inline]
+    pub const fn ctime(&self) -> Duration {
+        self.ctime
+    }
+
+    /// Sets access, modification and status change time at once.
+    #[inline]
+    pub fn set_times(&mut self, atime: Duration, mtime: Duration, ctime: Duration) {
+        self.atime = atime;
+        self.mtime = mtime;
+        self.ctime = ctime;
+    }
+
+    #[inline]
+    pub fn set_atime(&mut self, atime: Duration) {
+        self.atime = atime;
+    }
+
+    #[inline]
+    pub fn set_mtime(&mut self, mtime: Duration) {
+        self.mtime = mtime;
+    }
+
+    #[inline]
+    pub fn set_size(&mut self, size: u64) {
+        self.size = size;
+    }
+
     #[inline]
     pub fn set_mode(&mut self, mode: i32) {
         self.mode = VfsNodePerm::set_mode(mode as u16);
@@ -346,6 +408,10 @@ impl VfsNodeAttr {
             uid,
             gid,
             rdev: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
@@ -359,6 +425,10 @@ impl VfsNodeAttr {
             uid,
             gid,
             rdev: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
@@ -372,6 +442,10 @@ impl VfsNodeAttr {
             uid,
             gid,
             rdev: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
@@ -386,6 +460,10 @@ impl VfsNodeAttr {
             uid,
             gid,
             rdev: 0,
+            nlink: 1,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
         }
     }
 
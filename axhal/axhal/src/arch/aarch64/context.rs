@@ -1,9 +1,7 @@
+use crate::arch::PSR_MODE_EL0T;
 use core::arch::asm;
 use memory_addr::VirtAddr;
 
-/// PSR bits
-const PSR_MODE_EL0t: usize = 0x00000000;
-
 /// Saved registers when a trap (exception) occurs.
 #[repr(C)]
 #[derive(Debug, Default, Clone, Copy)]
@@ -35,6 +33,10 @@ impl FpState {
     fn switch_to(&mut self, next_fpstate: &FpState) {
         unsafe { fpstate_switch(self, next_fpstate) }
     }
+
+    fn save(&mut self) {
+        unsafe { fpstate_save(self) }
+    }
 }
 
 /// Saved hardware states of a task.
@@ -84,6 +86,18 @@ impl TaskContext {
         self.tpidr_el0 = tls_area.as_usize() as u64;
     }
 
+    /// The frame pointer of the task switched out, for its backtrace.
+    pub fn frame_pointer(&self) -> usize {
+        self.r29 as usize
+    }
+
+    /// Takes the FP/SIMD state of the current task, which is in the
+    /// registers, for this task that is forked from it.
+    pub fn inherit_fp_state(&mut self) {
+        #[cfg(feature = "fp_simd")]
+        self.fp_state.save();
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -181,14 +195,41 @@ unsafe extern "C" fn fpstate_switch(_current_fpstate: &mut FpState, _next_fpstat
     )
 }
 
+#[naked]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn fpstate_save(_fpstate: &mut FpState) {
+    asm!(
+        "
+        // save fp/neon context, as fpstate_switch does
+        mrs     x9, fpcr
+        mrs     x10, fpsr
+        stp     q0, q1, [x0, 0 * 16]
+        stp     q2, q3, [x0, 2 * 16]
+        stp     q4, q5, [x0, 4 * 16]
+        stp     q6, q7, [x0, 6 * 16]
+        stp     q8, q9, [x0, 8 * 16]
+        stp     q10, q11, [x0, 10 * 16]
+        stp     q12, q13, [x0, 12 * 16]
+        stp     q14, q15, [x0, 14 * 16]
+        stp     q16, q17, [x0, 16 * 16]
+        stp     q18, q19, [x0, 18 * 16]
+        stp     q20, q21, [x0, 20 * 16]
+        stp     q22, q23, [x0, 22 * 16]
+        stp     q24, q25, [x0, 24 * 16]
+        stp     q26, q27, [x0, 26 * 16]
+        stp     q28, q29, [x0, 28 * 16]
+        stp     q30, q31, [x0, 30 * 16]
+        str     x9, [x0, 64 *  8]
+        str     x10, [x0, 65 * 8]
+        ret",
+        options(noreturn),
+    )
+}
+
 pub fn start_thread(regs: usize, pc: usize, sp: usize) {
-    let mut regs = unsafe {
-        core::slice::from_raw_parts_mut(
-            regs as *mut TrapFrame, 1
-        )
-    };
-    error!("{:#X} {:#X}", pc, sp);
+    let regs = unsafe { core::slice::from_raw_parts_mut(regs as *mut TrapFrame, 1) };
     regs[0].elr = pc as u64;
-    regs[0].spsr = PSR_MODE_EL0t as u64;
+    // EL0 with SP_EL0, and no exception masked
+    regs[0].spsr = PSR_MODE_EL0T as u64;
     regs[0].usp = sp as u64;
 }
@@ -1,18 +1,26 @@
+#[macro_use]
+mod macros;
+
 mod context;
-pub(crate) mod trap;
+mod trap;
+pub use trap::ret_from_fork;
+/// The syscall numbers of asm-generic, which AArch64 has as RISC-V does.
+#[path = "../riscv/sysno.rs"]
+pub mod sysno;
 
 use core::arch::asm;
 
-use aarch64_cpu::registers::{DAIF, TPIDR_EL0, TTBR0_EL1, TTBR1_EL1, VBAR_EL1};
+use aarch64_cpu::registers::{DAIF, SPSR_EL1, TPIDR_EL0, TTBR0_EL1, TTBR1_EL1, VBAR_EL1};
 use memory_addr::{PhysAddr, VirtAddr};
 use tock_registers::interfaces::{Readable, Writeable};
+use axerrno::{LinuxError, linux_err};
 
 pub use self::context::{start_thread, FpState, TaskContext, TrapFrame};
 use crate::mem::PAGE_SIZE_4K;
-pub use trap::syscall;
 
 pub const TASK_SIZE: usize = 0x40_0000_0000;
 pub const STACK_SIZE: usize = 32 * PAGE_SIZE_4K;
+pub const STACK_TOP: usize = TASK_SIZE;
 
 /*
  * This is the location that an ET_DYN program is loaded if exec'ed.
@@ -29,10 +37,17 @@ pub const ELF_ET_DYN_BASE: usize = (TASK_SIZE / 3) * 2;
  */
 pub const TASK_UNMAPPED_BASE: usize = (TASK_SIZE / 3) & !(PAGE_SIZE_4K - 1);
 
-/// Status register flags
-pub const SR_SPIE: usize = 0x00000020; /* Previous Supervisor IE */
-pub const SR_FS_INITIAL: usize = 0x00002000;
-pub const SR_UXL_64: usize = 0x200000000; /* XLEN = 64 for U-mode */
+/// Saved program status register: modes and exception masks
+pub const PSR_MODE_EL0T: usize = 0x00000000;
+pub const PSR_MODE_EL1H: usize = 0x00000005;
+pub const PSR_MODE_MASK: usize = 0x0000000f;
+pub const PSR_I_BIT: usize = 0x00000080; /* IRQs masked */
+
+/// Whether the exception being handled is from user mode.
+#[inline]
+pub fn user_mode() -> bool {
+    (SPSR_EL1.get() as usize & PSR_MODE_MASK) == PSR_MODE_EL0T
+}
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -60,6 +75,16 @@ pub fn wait_for_irqs() {
     aarch64_cpu::asm::wfi();
 }
 
+/// Waits for interrupts with interrupts disabled, then enables them.
+///
+/// An interrupt raised right before still wakes the CPU up, so it can't be
+/// missed between checking for work and sleeping.
+#[inline]
+pub fn wait_for_irqs_and_enable() {
+    aarch64_cpu::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -89,13 +114,11 @@ pub fn read_page_table_root0() -> PhysAddr {
 /// This function is unsafe as it changes the virtual memory address space.
 pub unsafe fn write_page_table_root(root_paddr: PhysAddr) {
     let old_root = read_page_table_root();
-    debug!("set page table root: {:#x} => {:#x}", old_root, root_paddr);
+    trace!("set page table root: {:#x} => {:#x}", old_root, root_paddr);
     if old_root != root_paddr {
         // kernel space page table use TTBR1 (0xffff_0000_0000_0000..0xffff_ffff_ffff_ffff)
         TTBR1_EL1.set(root_paddr.as_usize() as _);
-        debug!("set page table root: 2");
         flush_tlb(None);
-        debug!("set page table root: 3");
     }
 }
 
@@ -117,7 +140,8 @@ pub unsafe fn write_page_table_root0(root_paddr: PhysAddr) {
 pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     unsafe {
         if let Some(vaddr) = vaddr {
-            asm!("tlbi vaae1is, {}; dsb sy; isb", in(reg) vaddr.as_usize())
+            // The operand is the page number, VA[55:12].
+            asm!("tlbi vaae1is, {}; dsb sy; isb", in(reg) vaddr.as_usize() >> 12)
         } else {
             // flush the entire TLB
             asm!("tlbi vmalle1; dsb sy; isb")
@@ -125,10 +149,24 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
-/// Flushes the entire instruction cache.
+/// Flushes the TLB entries of all CPUs for the kernel range of `size`
+/// bytes at `vaddr`, after its mappings are changed.
+pub fn flush_tlb_kernel_range(vaddr: VirtAddr, size: usize) {
+    for addr in (vaddr.as_usize()..vaddr.as_usize() + size).step_by(PAGE_SIZE_4K) {
+        flush_tlb(Some(addr.into()));
+    }
+}
+
+#[inline]
+pub fn local_flush_icache_all() {
+    unsafe { asm!("ic iallu; dsb nsh; isb") };
+}
+
+/// Flushes the instruction caches of all CPUs, after code is modified.
+/// The inner shareable invalidation is broadcast to them.
 #[inline]
 pub fn flush_icache_all() {
-    unsafe { asm!("ic iallu; dsb sy; isb") };
+    unsafe { asm!("dsb ish; ic ialluis; dsb ish; isb") };
 }
 
 /// Sets the base address of the exception vector (writes `VBAR_EL1`).
@@ -163,4 +201,108 @@ pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
     TPIDR_EL0.set(tpidr_el0 as _)
 }
 
+/// The kernel space is under TTBR1_EL1 for all the tasks, and the page
+/// tables of the processes, under TTBR0_EL1, have the user space only.
 pub fn sync_kernel_mappings(_src: PhysAddr, _dst: PhysAddr) {}
+
+#[inline]
+pub fn __get_user_asm(ptr: usize) -> (u8, usize) {
+    let mut x: u8;
+    let mut err: usize = 0;
+    unsafe { core::arch::asm!(
+        "1:",
+        "   ldtrb {x:w}, [{ptr}]",
+        "2:",
+        "   .section .fixup,\"ax\"",
+        "   .balign 4",
+        "3:",
+        "   mov {err}, #{err_val}",
+        "   mov {x:w}, #0",
+        "   b 2b",
+        "   .previous",
+        "   .section __ex_table,\"a\"",
+        "   .balign 8",
+        "   .dword 1b, 3b",
+        "   .previous",
+        err = inout(reg) err,
+        x = out(reg) x,
+        ptr = in(reg) ptr,
+        err_val = const (-(LinuxError::EFAULT as isize)),
+    )}
+    (x, err)
+}
+
+#[inline]
+pub fn __put_user_asm(x: u8, ptr: usize) -> usize {
+    let mut err: usize = 0;
+    unsafe { core::arch::asm!(
+        "1:",
+        "   sttrb {x:w}, [{ptr}]",
+        "2:",
+        "   .section .fixup,\"ax\"",
+        "   .balign 4",
+        "3:",
+        "   mov {err}, #{err_val}",
+        "   b 2b",
+        "   .previous",
+        "   .section __ex_table,\"a\"",
+        "   .balign 8",
+        "   .dword 1b, 3b",
+        "   .previous",
+        err = inout(reg) err,
+        x = in(reg) x,
+        ptr = in(reg) ptr,
+        err_val = const (-(LinuxError::EFAULT as isize)),
+    )}
+    err
+}
+
+/// Checks if a user space pointer may be valid, that is, whether the block
+/// of `size` bytes at `addr` is in the user space range. The access may
+/// still fail with EFAULT.
+#[inline]
+pub fn access_ok(addr: usize, size: usize) -> bool {
+    size <= TASK_SIZE && addr <= TASK_SIZE - size
+}
+
+#[inline]
+pub fn fault_in_readable(addr: usize, size: usize) -> usize {
+    if !access_ok(addr, size) {
+        return linux_err!(EFAULT);
+    }
+
+    let (_, err) = __get_user_asm(addr);
+    if err != 0 {
+        error!("__get_user_asm: err = {:#x}", err);
+        return err;
+    }
+    0
+}
+
+#[inline]
+pub fn fault_in_writeable(addr: usize, size: usize) -> usize {
+    if !access_ok(addr, size) {
+        return linux_err!(EFAULT);
+    }
+
+    let err = __put_user_asm(0u8, addr);
+    if err != 0 {
+        error!("__put_user_asm: err = {:#x}", err);
+        return err;
+    }
+    0
+}
+
+/// The causes of page faults, as the trap handler passes them on: the
+/// exception class of ESR_EL1 for an instruction or a data abort from
+/// EL0, and the latter with bit 6 set, WnR of its syndrome, for a write.
+pub const EXC_INST_PAGE_FAULT: usize = 0x20;
+pub const EXC_LOAD_PAGE_FAULT: usize = 0x24;
+pub const EXC_STORE_PAGE_FAULT: usize = 0x64;
+
+/// Drops the identity mapping of the boot from TTBR0_EL1, for the user
+/// page tables to take it, and stores the frequency of the timer.
+pub fn early_init() {
+    unsafe { write_page_table_root0(0.into()) };
+    crate::platform::time::init_early();
+}
@@ -1,4 +1,8 @@
 use crate::arch::{SR_FS_INITIAL, SR_SPIE, SR_SUM, SR_UXL_64};
+#[cfg(feature = "fp_simd")]
+use crate::arch::{SR_FS, SR_FS_CLEAN, SR_FS_DIRTY, SR_FS_OFF};
+#[cfg(feature = "rvv")]
+use crate::arch::{SR_VS, SR_VS_CLEAN, SR_VS_DIRTY, SR_VS_OFF};
 use core::arch::asm;
 use memory_addr::VirtAddr;
 
@@ -7,7 +11,7 @@ include_asm_marcos!();
 /// General registers of RISC-V.
 #[allow(missing_docs)]
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct GeneralRegisters {
     pub ra: usize,
     pub sp: usize,
@@ -44,7 +48,7 @@ pub struct GeneralRegisters {
 
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct TrapFrame {
     /// All general registers.
     pub regs: GeneralRegisters,
@@ -54,6 +58,48 @@ pub struct TrapFrame {
     pub sstatus: usize,
 }
 
+/// FP registers of RISC-V (the D extension).
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FpState {
+    /// f0..f31
+    pub f: [u64; 32],
+    /// Floating-point Control and Status Register
+    pub fcsr: usize,
+}
+
+/// The largest `vlenb` the vector state has room for, a VLEN of 256 bits.
+#[cfg(feature = "rvv")]
+pub const MAX_VLENB: usize = 32;
+
+/// Vector registers of RISC-V (the V extension).
+#[cfg(feature = "rvv")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VState {
+    pub vstart: usize,
+    pub vtype: usize,
+    pub vl: usize,
+    pub vcsr: usize,
+    /// v0..v31, `vlenb` bytes each.
+    pub data: [u8; 32 * MAX_VLENB],
+}
+
+#[cfg(feature = "rvv")]
+impl Default for VState {
+    fn default() -> Self {
+        unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    }
+}
+
+/// Checks that the vector registers fit in [`VState`].
+#[cfg(feature = "rvv")]
+pub(crate) fn init_vstate() {
+    let vlenb = unsafe { read_vlenb() };
+    info!("rvv: vlenb {}", vlenb);
+    assert!(vlenb <= MAX_VLENB, "rvv: vlenb {} > {}", vlenb, MAX_VLENB);
+}
+
 /// Saved hardware states of a task.
 ///
 /// The context usually includes:
@@ -87,7 +133,19 @@ pub struct TaskContext {
     pub s11: usize,
 
     pub tp: usize,
-    // TODO: FP states
+
+    /// The trap frame at the top of the kernel stack, whose `sstatus` has
+    /// the FS and VS fields of the task in user mode. Zero for the tasks
+    /// that have none, as the boot task.
+    pub regs: usize,
+    /// Only saved and restored for the tasks that use the FPU, as the FS
+    /// field tells: the state is saved when it is Dirty, and restored
+    /// when it is not Off, which leaves it Clean.
+    #[cfg(feature = "fp_simd")]
+    pub fp_state: FpState,
+    /// As the FP state, with the VS field.
+    #[cfg(feature = "rvv")]
+    pub v_state: VState,
 }
 
 impl TaskContext {
@@ -102,6 +160,64 @@ impl TaskContext {
         self.sp = kstack_top.as_usize();
         self.ra = entry;
         self.tp = tls_area.as_usize();
+        self.regs = kstack_top.as_usize();
+    }
+
+    /// Takes the FP and vector states of the current task, which are in
+    /// the registers, for this task that is forked from it and has a copy
+    /// of its trap frame.
+    pub fn inherit_fp_state(&mut self) {
+        #[cfg(feature = "fp_simd")]
+        if let Some(tf) = self.trap_frame() {
+            if tf.sstatus & SR_FS != SR_FS_OFF {
+                unsafe { fstate_save(&mut self.fp_state) };
+            }
+            #[cfg(feature = "rvv")]
+            if tf.sstatus & SR_VS != SR_VS_OFF {
+                unsafe { vstate_save(&mut self.v_state) };
+            }
+        }
+    }
+
+    #[cfg(feature = "fp_simd")]
+    fn trap_frame(&self) -> Option<&'static mut TrapFrame> {
+        if self.regs == 0 {
+            return None;
+        }
+        Some(unsafe { &mut *(self.regs as *mut TrapFrame) })
+    }
+
+    /// Saves the FP and vector states of this task if it changed them since
+    /// they were restored, and restores those of `next_ctx` if it has them.
+    #[cfg(feature = "fp_simd")]
+    fn switch_fp_state(&mut self, next_ctx: &Self) {
+        if let Some(tf) = self.trap_frame() {
+            if tf.sstatus & SR_FS == SR_FS_DIRTY {
+                unsafe { fstate_save(&mut self.fp_state) };
+                tf.sstatus = (tf.sstatus & !SR_FS) | SR_FS_CLEAN;
+            }
+            #[cfg(feature = "rvv")]
+            if tf.sstatus & SR_VS == SR_VS_DIRTY {
+                unsafe { vstate_save(&mut self.v_state) };
+                tf.sstatus = (tf.sstatus & !SR_VS) | SR_VS_CLEAN;
+            }
+        }
+        if let Some(tf) = next_ctx.trap_frame() {
+            if tf.sstatus & SR_FS != SR_FS_OFF {
+                unsafe { fstate_restore(&next_ctx.fp_state) };
+                tf.sstatus = (tf.sstatus & !SR_FS) | SR_FS_CLEAN;
+            }
+            #[cfg(feature = "rvv")]
+            if tf.sstatus & SR_VS != SR_VS_OFF {
+                unsafe { vstate_restore(&next_ctx.v_state) };
+                tf.sstatus = (tf.sstatus & !SR_VS) | SR_VS_CLEAN;
+            }
+        }
+    }
+
+    /// The frame pointer of the task switched out, for its backtrace.
+    pub fn frame_pointer(&self) -> usize {
+        self.s0
     }
 
     /// Switches to another task.
@@ -114,10 +230,9 @@ impl TaskContext {
             self.tp = super::read_thread_pointer();
             unsafe { super::write_thread_pointer(next_ctx.tp) };
         }
-        unsafe {
-            // TODO: switch FP states
-            context_switch(self, next_ctx)
-        }
+        #[cfg(feature = "fp_simd")]
+        self.switch_fp_state(next_ctx);
+        unsafe { context_switch(self, next_ctx) }
     }
 }
 
@@ -162,10 +277,201 @@ unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task:
     )
 }
 
+/// Saves the FP registers, with the FPU on only for the time it takes.
+#[naked]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn fstate_save(_fp_state: &mut FpState) {
+    asm!(
+        "
+        li      t0, {sr_fs}
+        csrrs   t2, sstatus, t0
+        frcsr   t1
+        fsd     f0, 0(a0)
+        fsd     f1, 8(a0)
+        fsd     f2, 16(a0)
+        fsd     f3, 24(a0)
+        fsd     f4, 32(a0)
+        fsd     f5, 40(a0)
+        fsd     f6, 48(a0)
+        fsd     f7, 56(a0)
+        fsd     f8, 64(a0)
+        fsd     f9, 72(a0)
+        fsd     f10, 80(a0)
+        fsd     f11, 88(a0)
+        fsd     f12, 96(a0)
+        fsd     f13, 104(a0)
+        fsd     f14, 112(a0)
+        fsd     f15, 120(a0)
+        fsd     f16, 128(a0)
+        fsd     f17, 136(a0)
+        fsd     f18, 144(a0)
+        fsd     f19, 152(a0)
+        fsd     f20, 160(a0)
+        fsd     f21, 168(a0)
+        fsd     f22, 176(a0)
+        fsd     f23, 184(a0)
+        fsd     f24, 192(a0)
+        fsd     f25, 200(a0)
+        fsd     f26, 208(a0)
+        fsd     f27, 216(a0)
+        fsd     f28, 224(a0)
+        fsd     f29, 232(a0)
+        fsd     f30, 240(a0)
+        fsd     f31, 248(a0)
+        sd      t1, 32 * 8(a0)
+        csrw    sstatus, t2
+        ret",
+        sr_fs = const SR_FS,
+        options(noreturn),
+    )
+}
+
+#[naked]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn fstate_restore(_fp_state: &FpState) {
+    asm!(
+        "
+        li      t0, {sr_fs}
+        csrrs   t2, sstatus, t0
+        ld      t1, 32 * 8(a0)
+        fld     f0, 0(a0)
+        fld     f1, 8(a0)
+        fld     f2, 16(a0)
+        fld     f3, 24(a0)
+        fld     f4, 32(a0)
+        fld     f5, 40(a0)
+        fld     f6, 48(a0)
+        fld     f7, 56(a0)
+        fld     f8, 64(a0)
+        fld     f9, 72(a0)
+        fld     f10, 80(a0)
+        fld     f11, 88(a0)
+        fld     f12, 96(a0)
+        fld     f13, 104(a0)
+        fld     f14, 112(a0)
+        fld     f15, 120(a0)
+        fld     f16, 128(a0)
+        fld     f17, 136(a0)
+        fld     f18, 144(a0)
+        fld     f19, 152(a0)
+        fld     f20, 160(a0)
+        fld     f21, 168(a0)
+        fld     f22, 176(a0)
+        fld     f23, 184(a0)
+        fld     f24, 192(a0)
+        fld     f25, 200(a0)
+        fld     f26, 208(a0)
+        fld     f27, 216(a0)
+        fld     f28, 224(a0)
+        fld     f29, 232(a0)
+        fld     f30, 240(a0)
+        fld     f31, 248(a0)
+        fscsr   t1
+        csrw    sstatus, t2
+        ret",
+        sr_fs = const SR_FS,
+        options(noreturn),
+    )
+}
+
+/// Saves the vector registers, by groups of eight with LMUL=8.
+#[naked]
+#[cfg(feature = "rvv")]
+unsafe extern "C" fn vstate_save(_v_state: &mut VState) {
+    asm!(
+        "
+        .option push
+        .option arch, +v
+        li      t0, {sr_vs}
+        csrrs   t2, sstatus, t0
+        csrr    t1, vstart
+        sd      t1, 0(a0)
+        csrr    t1, vtype
+        sd      t1, 8(a0)
+        csrr    t1, vl
+        sd      t1, 16(a0)
+        csrr    t1, vcsr
+        sd      t1, 24(a0)
+        addi    a0, a0, 32
+        vsetvli t1, x0, e8, m8, ta, ma
+        vse8.v  v0, (a0)
+        add     a0, a0, t1
+        vse8.v  v8, (a0)
+        add     a0, a0, t1
+        vse8.v  v16, (a0)
+        add     a0, a0, t1
+        vse8.v  v24, (a0)
+        csrw    sstatus, t2
+        .option pop
+        ret",
+        sr_vs = const SR_VS,
+        options(noreturn),
+    )
+}
+
+#[naked]
+#[cfg(feature = "rvv")]
+unsafe extern "C" fn vstate_restore(_v_state: &VState) {
+    asm!(
+        "
+        .option push
+        .option arch, +v
+        li      t0, {sr_vs}
+        csrrs   t2, sstatus, t0
+        addi    t3, a0, 32
+        vsetvli t1, x0, e8, m8, ta, ma
+        vle8.v  v0, (t3)
+        add     t3, t3, t1
+        vle8.v  v8, (t3)
+        add     t3, t3, t1
+        vle8.v  v16, (t3)
+        add     t3, t3, t1
+        vle8.v  v24, (t3)
+        // vl is at most VLMAX of vtype, so it is set back as it was.
+        ld      t1, 16(a0)
+        ld      t3, 8(a0)
+        vsetvl  x0, t1, t3
+        ld      t1, 0(a0)
+        csrw    vstart, t1
+        ld      t1, 24(a0)
+        csrw    vcsr, t1
+        csrw    sstatus, t2
+        .option pop
+        ret",
+        sr_vs = const SR_VS,
+        options(noreturn),
+    )
+}
+
+/// VLEN in bytes, which can only be read with the vector unit on.
+#[cfg(feature = "rvv")]
+unsafe fn read_vlenb() -> usize {
+    let vlenb;
+    asm!(
+        "
+        .option push
+        .option arch, +v
+        li      t0, {sr_vs}
+        csrrs   t2, sstatus, t0
+        csrr    {0}, vlenb
+        csrw    sstatus, t2
+        .option pop",
+        out(reg) vlenb,
+        sr_vs = const SR_VS,
+        out("t0") _,
+        out("t2") _,
+    );
+    vlenb
+}
+
 pub fn start_thread(regs: usize, pc: usize, sp: usize) {
     let regs = unsafe { core::slice::from_raw_parts_mut(regs as *mut TrapFrame, 1) };
     regs[0].sepc = pc;
     // default to open the sum bit
     regs[0].sstatus = SR_SPIE | SR_FS_INITIAL | SR_UXL_64 | SR_SUM;
+    #[cfg(feature = "rvv")]
+    {
+        regs[0].sstatus |= crate::arch::SR_VS_INITIAL;
+    }
     regs[0].regs.sp = sp;
 }
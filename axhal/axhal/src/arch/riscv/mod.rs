@@ -12,7 +12,9 @@ use riscv::asm;
 use riscv::register::{satp, sstatus, stvec};
 use axerrno::{LinuxError, linux_err};
 
-pub use self::context::{start_thread, GeneralRegisters, TaskContext, TrapFrame};
+pub use self::context::{start_thread, FpState, GeneralRegisters, TaskContext, TrapFrame};
+#[cfg(feature = "rvv")]
+pub use self::context::VState;
 
 pub const TASK_SIZE: usize = 0x40_0000_0000;
 pub const STACK_SIZE: usize = 32 * PAGE_SIZE_4K;
@@ -36,7 +38,16 @@ pub const TASK_UNMAPPED_BASE: usize = (TASK_SIZE / 3) & !(PAGE_SIZE_4K - 1);
 /// Status register flags
 pub const SR_SPIE:  usize = 0x00000020; /* Previous Supervisor IE */
 pub const SR_SPP:   usize = 0x00000100; /* Previously Supervisor */
+pub const SR_VS:         usize = 0x00000600; /* Vector Status */
+pub const SR_VS_OFF:     usize = 0x00000000;
+pub const SR_VS_INITIAL: usize = 0x00000200;
+pub const SR_VS_CLEAN:   usize = 0x00000400;
+pub const SR_VS_DIRTY:   usize = 0x00000600;
+pub const SR_FS:         usize = 0x00006000; /* Floating-point Status */
+pub const SR_FS_OFF:     usize = 0x00000000;
 pub const SR_FS_INITIAL: usize = 0x00002000;
+pub const SR_FS_CLEAN:   usize = 0x00004000;
+pub const SR_FS_DIRTY:   usize = 0x00006000;
 pub const SR_UXL_64: usize = 0x200000000; /* XLEN = 64 for U-mode */
 pub const SR_SUM: usize = 0x00040000; /* Supervisor User Memory access */
 
@@ -76,6 +87,16 @@ pub fn wait_for_irqs() {
     unsafe { riscv::asm::wfi() }
 }
 
+/// Waits for interrupts with interrupts disabled, then enables them.
+///
+/// An interrupt raised right before still wakes the CPU up, so it can't be
+/// missed between checking for work and sleeping.
+#[inline]
+pub fn wait_for_irqs_and_enable() {
+    unsafe { riscv::asm::wfi() }
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -123,11 +144,28 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the TLB entries of all harts for the kernel range of `size`
+/// bytes at `vaddr`, after its mappings are changed.
+pub fn flush_tlb_kernel_range(vaddr: VirtAddr, size: usize) {
+    for addr in (vaddr.as_usize()..vaddr.as_usize() + size).step_by(PAGE_SIZE_4K) {
+        flush_tlb(Some(addr.into()));
+    }
+    sbi_rt::remote_sfence_vma(0, usize::MAX, vaddr.as_usize(), size);
+}
+
 #[inline]
 pub fn local_flush_icache_all() {
     unsafe { core::arch::asm!("fence.i") };
 }
 
+/// Flushes the instruction caches of all harts, after code is modified.
+#[inline]
+pub fn flush_icache_all() {
+    local_flush_icache_all();
+    // A mask base of -1 selects all harts.
+    sbi_rt::remote_fence_i(0, usize::MAX);
+}
+
 /// Writes Supervisor Trap Vector Base Address Register (`stvec`).
 #[inline]
 pub fn set_trap_vector_base(stvec: usize) {
@@ -274,4 +312,6 @@ pub const EXC_LOAD_PAGE_FAULT: usize = 13;
 pub const EXC_STORE_PAGE_FAULT: usize = 15;
 
 pub fn early_init() {
+    #[cfg(feature = "rvv")]
+    context::init_vstate();
 }
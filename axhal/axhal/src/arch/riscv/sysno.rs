@@ -2,8 +2,20 @@
 /// Linux syscall
 ///
 
+pub const LINUX_SYSCALL_SETXATTR: usize = 0x5;
+pub const LINUX_SYSCALL_LSETXATTR: usize = 0x6;
+pub const LINUX_SYSCALL_FSETXATTR: usize = 0x7;
+pub const LINUX_SYSCALL_GETXATTR: usize = 0x8;
+pub const LINUX_SYSCALL_LGETXATTR: usize = 0x9;
 pub const LINUX_SYSCALL_FGETXATTR: usize = 0xa;
+pub const LINUX_SYSCALL_LISTXATTR: usize = 0xb;
+pub const LINUX_SYSCALL_LLISTXATTR: usize = 0xc;
+pub const LINUX_SYSCALL_FLISTXATTR: usize = 0xd;
+pub const LINUX_SYSCALL_REMOVEXATTR: usize = 0xe;
+pub const LINUX_SYSCALL_LREMOVEXATTR: usize = 0xf;
+pub const LINUX_SYSCALL_FREMOVEXATTR: usize = 0x10;
 pub const LINUX_SYSCALL_GETCWD: usize = 0x11;
+pub const LINUX_SYSCALL_EVENTFD2: usize = 0x13;
 pub const LINUX_SYSCALL_DUP: usize = 0x17;
 pub const LINUX_SYSCALL_DUP3: usize = 0x18;
 pub const LINUX_SYSCALL_FCNTL: usize = 0x19;
@@ -36,21 +48,47 @@ pub const LINUX_SYSCALL_PREAD64: usize = 0x43;
 pub const LINUX_SYSCALL_SENDFILE: usize = 0x47;
 pub const LINUX_SYSCALL_READLINKAT: usize = 0x4e;
 pub const LINUX_SYSCALL_FSTATAT: usize = 0x4f;
+pub const LINUX_SYSCALL_SYNC: usize = 0x51;
+pub const LINUX_SYSCALL_FSYNC: usize = 0x52;
+pub const LINUX_SYSCALL_FDATASYNC: usize = 0x53;
+pub const LINUX_SYSCALL_TIMERFD_CREATE: usize = 0x55;
+pub const LINUX_SYSCALL_TIMERFD_SETTIME: usize = 0x56;
+pub const LINUX_SYSCALL_TIMERFD_GETTIME: usize = 0x57;
 pub const LINUX_SYSCALL_UTIMENSAT: usize = 0x58;
 pub const LINUX_SYSCALL_CAPGET: usize = 0x5a;
 pub const LINUX_SYSCALL_EXIT: usize = 0x5d;
 pub const LINUX_SYSCALL_EXIT_GROUP: usize = 0x5e;
 pub const LINUX_SYSCALL_FUTEX: usize = 0x62;
 pub const LINUX_SYSCALL_SETITIMER: usize = 0x67;
+pub const LINUX_SYSCALL_KEXEC_LOAD: usize = 0x68;
+pub const LINUX_SYSCALL_INIT_MODULE: usize = 0x69;
+pub const LINUX_SYSCALL_DELETE_MODULE: usize = 0x6a;
 pub const LINUX_SYSCALL_TGKILL: usize = 0x83;
 pub const LINUX_SYSCALL_RT_SIGRETURN: usize = 0x8b;
+pub const LINUX_SYSCALL_REBOOT: usize = 0x8e;
+pub const LINUX_SYSCALL_SETREGID: usize = 0x8f;
 pub const LINUX_SYSCALL_SETGID:usize = 0x90;
 pub const LINUX_SYSCALL_SETREUID: usize = 0x91;
 pub const LINUX_SYSCALL_SETUID: usize = 0x92;
 pub const LINUX_SYSCALL_SETRESUID: usize = 0x93;
+pub const LINUX_SYSCALL_GETRESUID: usize = 0x94;
+pub const LINUX_SYSCALL_SETRESGID: usize = 0x95;
+pub const LINUX_SYSCALL_GETRESGID: usize = 0x96;
 pub const LINUX_SYSCALL_SETPGID: usize = 0x9a;
+pub const LINUX_SYSCALL_GETPGID: usize = 0x9b;
+pub const LINUX_SYSCALL_GETSID: usize = 0x9c;
+pub const LINUX_SYSCALL_SETSID: usize = 0x9d;
+pub const LINUX_SYSCALL_GETGROUPS: usize = 0x9e;
+pub const LINUX_SYSCALL_SETGROUPS: usize = 0x9f;
 pub const LINUX_SYSCALL_UNAME: usize = 0xa0;
+pub const LINUX_SYSCALL_SETHOSTNAME: usize = 0xa1;
+pub const LINUX_SYSCALL_SETDOMAINNAME: usize = 0xa2;
+pub const LINUX_SYSCALL_GETRLIMIT: usize = 0xa3;
+pub const LINUX_SYSCALL_SETRLIMIT: usize = 0xa4;
 pub const LINUX_SYSCALL_UMASK: usize = 0xa6;
+pub const LINUX_SYSCALL_GETCPU: usize = 0xa8;
+pub const LINUX_SYSCALL_GETTIMEOFDAY: usize = 0xa9;
+pub const LINUX_SYSCALL_SETTIMEOFDAY: usize = 0xaa;
 pub const LINUX_SYSCALL_GETPID: usize = 0xac;
 pub const LINUX_SYSCALL_GETPPID: usize = 0xad;
 pub const LINUX_SYSCALL_GETUID: usize = 0xae;
@@ -58,26 +96,200 @@ pub const LINUX_SYSCALL_GETEUID: usize = 0xaf;
 pub const LINUX_SYSCALL_GETGID: usize = 0xb0;
 pub const LINUX_SYSCALL_GETEGID: usize = 0xb1;
 pub const LINUX_SYSCALL_GETTID: usize = 0xb2;
+pub const LINUX_SYSCALL_SHMGET: usize = 0xc2;
+pub const LINUX_SYSCALL_SHMCTL: usize = 0xc3;
+pub const LINUX_SYSCALL_SHMAT: usize = 0xc4;
+pub const LINUX_SYSCALL_SHMDT: usize = 0xc5;
 pub const LINUX_SYSCALL_SOCKET: usize = 0xc6;
+pub const LINUX_SYSCALL_SOCKETPAIR: usize = 0xc7;
+pub const LINUX_SYSCALL_BIND: usize = 0xc8;
+pub const LINUX_SYSCALL_LISTEN: usize = 0xc9;
+pub const LINUX_SYSCALL_ACCEPT: usize = 0xca;
+pub const LINUX_SYSCALL_CONNECT: usize = 0xcb;
+pub const LINUX_SYSCALL_GETSOCKNAME: usize = 0xcc;
+pub const LINUX_SYSCALL_GETPEERNAME: usize = 0xcd;
+pub const LINUX_SYSCALL_SENDTO: usize = 0xce;
+pub const LINUX_SYSCALL_RECVFROM: usize = 0xcf;
+pub const LINUX_SYSCALL_SETSOCKOPT: usize = 0xd0;
+pub const LINUX_SYSCALL_GETSOCKOPT: usize = 0xd1;
+pub const LINUX_SYSCALL_SHUTDOWN: usize = 0xd2;
+pub const LINUX_SYSCALL_SENDMSG: usize = 0xd3;
+pub const LINUX_SYSCALL_RECVMSG: usize = 0xd4;
 pub const LINUX_SYSCALL_BRK: usize = 0xd6;
 pub const LINUX_SYSCALL_MUNMAP: usize = 0xd7;
 pub const LINUX_SYSCALL_MREMAP: usize = 0xd8;
 pub const LINUX_SYSCALL_CLONE: usize = 0xdc;
 pub const LINUX_SYSCALL_EXECVE: usize = 0xdd;
 pub const LINUX_SYSCALL_MMAP: usize = 0xde;
+pub const LINUX_SYSCALL_FADVISE64: usize = 0xdf;
 pub const LINUX_SYSCALL_MPROTECT: usize = 0xe2;
 pub const LINUX_SYSCALL_MSYNC: usize = 0xe3;
 pub const LINUX_SYSCALL_MADVISE: usize = 0xe9;
+pub const LINUX_SYSCALL_ACCEPT4: usize = 0xf2;
 pub const LINUX_SYSCALL_WAIT4: usize = 0x104;
 pub const LINUX_SYSCALL_PRLIMIT64: usize = 0x105;
+pub const LINUX_SYSCALL_SYNCFS: usize = 0x10b;
+pub const LINUX_SYSCALL_FINIT_MODULE: usize = 0x111;
 pub const LINUX_SYSCALL_GETRANDOM: usize = 0x116;
 pub const LINUX_SYSCALL_RSEQ: usize = 0x125;
 
 pub const LINUX_SYSCALL_SET_TID_ADDRESS: usize = 0x60;
 pub const LINUX_SYSCALL_SET_ROBUST_LIST: usize = 0x63;
+pub const LINUX_SYSCALL_CLOCK_SETTIME: usize = 0x70;
 pub const LINUX_SYSCALL_CLOCK_GETTIME: usize = 0x71;
+pub const LINUX_SYSCALL_CLOCK_GETRES: usize = 0x72;
 pub const LINUX_SYSCALL_CLOCK_NANOSLEEP: usize = 0x73;
+pub const LINUX_SYSCALL_SYSLOG: usize = 0x74;
+pub const LINUX_SYSCALL_PTRACE: usize = 0x75;
 pub const LINUX_SYSCALL_SCHED_GETAFFINITY: usize = 0x7b;
 pub const LINUX_SYSCALL_KILL: usize = 0x81;
 pub const LINUX_SYSCALL_RT_SIGACTION: usize = 0x86;
 pub const LINUX_SYSCALL_RT_SIGPROCMASK: usize = 0x87;
+
+/// The name of syscall `sysno`, as strace prints it, if it is one of these.
+pub fn syscall_name(sysno: usize) -> Option<&'static str> {
+    let name = match sysno {
+        LINUX_SYSCALL_SETXATTR => "setxattr",
+        LINUX_SYSCALL_LSETXATTR => "lsetxattr",
+        LINUX_SYSCALL_FSETXATTR => "fsetxattr",
+        LINUX_SYSCALL_GETXATTR => "getxattr",
+        LINUX_SYSCALL_LGETXATTR => "lgetxattr",
+        LINUX_SYSCALL_FGETXATTR => "fgetxattr",
+        LINUX_SYSCALL_LISTXATTR => "listxattr",
+        LINUX_SYSCALL_LLISTXATTR => "llistxattr",
+        LINUX_SYSCALL_FLISTXATTR => "flistxattr",
+        LINUX_SYSCALL_REMOVEXATTR => "removexattr",
+        LINUX_SYSCALL_LREMOVEXATTR => "lremovexattr",
+        LINUX_SYSCALL_FREMOVEXATTR => "fremovexattr",
+        LINUX_SYSCALL_GETCWD => "getcwd",
+        LINUX_SYSCALL_EVENTFD2 => "eventfd2",
+        LINUX_SYSCALL_DUP => "dup",
+        LINUX_SYSCALL_DUP3 => "dup3",
+        LINUX_SYSCALL_FCNTL => "fcntl",
+        LINUX_SYSCALL_IOCTL => "ioctl",
+        LINUX_SYSCALL_MKNODAT => "mknodat",
+        LINUX_SYSCALL_MKDIRAT => "mkdirat",
+        LINUX_SYSCALL_UNLINKAT => "unlinkat",
+        LINUX_SYSCALL_SYMLINKAT => "symlinkat",
+        LINUX_SYSCALL_LINKAT => "linkat",
+        LINUX_SYSCALL_UMOUNT2 => "umount2",
+        LINUX_SYSCALL_STATFS => "statfs",
+        LINUX_SYSCALL_FTRUNCATE => "ftruncate",
+        LINUX_SYSCALL_FALLOCATE => "fallocate",
+        LINUX_SYSCALL_FACCESSAT => "faccessat",
+        LINUX_SYSCALL_CHDIR => "chdir",
+        LINUX_SYSCALL_FCHMOD => "fchmod",
+        LINUX_SYSCALL_FCHMODAT => "fchmodat",
+        LINUX_SYSCALL_FCHOWNAT => "fchownat",
+        LINUX_SYSCALL_FCHOWN => "fchown",
+        LINUX_SYSCALL_OPENAT => "openat",
+        LINUX_SYSCALL_CLOSE => "close",
+        LINUX_SYSCALL_PIPE2 => "pipe2",
+        LINUX_SYSCALL_GETDENTS64 => "getdents64",
+        LINUX_SYSCALL_LSEEK => "lseek",
+        LINUX_SYSCALL_READ => "read",
+        LINUX_SYSCALL_WRITE => "write",
+        LINUX_SYSCALL_WRITEV => "writev",
+        LINUX_SYSCALL_PREAD64 => "pread64",
+        LINUX_SYSCALL_SENDFILE => "sendfile",
+        LINUX_SYSCALL_READLINKAT => "readlinkat",
+        LINUX_SYSCALL_FSTATAT => "fstatat",
+        LINUX_SYSCALL_SYNC => "sync",
+        LINUX_SYSCALL_FSYNC => "fsync",
+        LINUX_SYSCALL_FDATASYNC => "fdatasync",
+        LINUX_SYSCALL_TIMERFD_CREATE => "timerfd_create",
+        LINUX_SYSCALL_TIMERFD_SETTIME => "timerfd_settime",
+        LINUX_SYSCALL_TIMERFD_GETTIME => "timerfd_gettime",
+        LINUX_SYSCALL_UTIMENSAT => "utimensat",
+        LINUX_SYSCALL_CAPGET => "capget",
+        LINUX_SYSCALL_EXIT => "exit",
+        LINUX_SYSCALL_EXIT_GROUP => "exit_group",
+        LINUX_SYSCALL_FUTEX => "futex",
+        LINUX_SYSCALL_SETITIMER => "setitimer",
+        LINUX_SYSCALL_KEXEC_LOAD => "kexec_load",
+        LINUX_SYSCALL_INIT_MODULE => "init_module",
+        LINUX_SYSCALL_DELETE_MODULE => "delete_module",
+        LINUX_SYSCALL_TGKILL => "tgkill",
+        LINUX_SYSCALL_RT_SIGRETURN => "rt_sigreturn",
+        LINUX_SYSCALL_REBOOT => "reboot",
+        LINUX_SYSCALL_SETREGID => "setregid",
+        LINUX_SYSCALL_SETREUID => "setreuid",
+        LINUX_SYSCALL_SETUID => "setuid",
+        LINUX_SYSCALL_SETRESUID => "setresuid",
+        LINUX_SYSCALL_GETRESUID => "getresuid",
+        LINUX_SYSCALL_SETRESGID => "setresgid",
+        LINUX_SYSCALL_GETRESGID => "getresgid",
+        LINUX_SYSCALL_SETPGID => "setpgid",
+        LINUX_SYSCALL_GETPGID => "getpgid",
+        LINUX_SYSCALL_GETSID => "getsid",
+        LINUX_SYSCALL_SETSID => "setsid",
+        LINUX_SYSCALL_GETGROUPS => "getgroups",
+        LINUX_SYSCALL_SETGROUPS => "setgroups",
+        LINUX_SYSCALL_UNAME => "uname",
+        LINUX_SYSCALL_SETHOSTNAME => "sethostname",
+        LINUX_SYSCALL_SETDOMAINNAME => "setdomainname",
+        LINUX_SYSCALL_GETRLIMIT => "getrlimit",
+        LINUX_SYSCALL_SETRLIMIT => "setrlimit",
+        LINUX_SYSCALL_UMASK => "umask",
+        LINUX_SYSCALL_GETCPU => "getcpu",
+        LINUX_SYSCALL_GETTIMEOFDAY => "gettimeofday",
+        LINUX_SYSCALL_SETTIMEOFDAY => "settimeofday",
+        LINUX_SYSCALL_GETPID => "getpid",
+        LINUX_SYSCALL_GETPPID => "getppid",
+        LINUX_SYSCALL_GETUID => "getuid",
+        LINUX_SYSCALL_GETEUID => "geteuid",
+        LINUX_SYSCALL_GETGID => "getgid",
+        LINUX_SYSCALL_GETEGID => "getegid",
+        LINUX_SYSCALL_GETTID => "gettid",
+        LINUX_SYSCALL_SHMGET => "shmget",
+        LINUX_SYSCALL_SHMCTL => "shmctl",
+        LINUX_SYSCALL_SHMAT => "shmat",
+        LINUX_SYSCALL_SHMDT => "shmdt",
+        LINUX_SYSCALL_SOCKET => "socket",
+        LINUX_SYSCALL_SOCKETPAIR => "socketpair",
+        LINUX_SYSCALL_BIND => "bind",
+        LINUX_SYSCALL_LISTEN => "listen",
+        LINUX_SYSCALL_ACCEPT => "accept",
+        LINUX_SYSCALL_CONNECT => "connect",
+        LINUX_SYSCALL_GETSOCKNAME => "getsockname",
+        LINUX_SYSCALL_GETPEERNAME => "getpeername",
+        LINUX_SYSCALL_SENDTO => "sendto",
+        LINUX_SYSCALL_RECVFROM => "recvfrom",
+        LINUX_SYSCALL_SETSOCKOPT => "setsockopt",
+        LINUX_SYSCALL_GETSOCKOPT => "getsockopt",
+        LINUX_SYSCALL_SHUTDOWN => "shutdown",
+        LINUX_SYSCALL_SENDMSG => "sendmsg",
+        LINUX_SYSCALL_RECVMSG => "recvmsg",
+        LINUX_SYSCALL_BRK => "brk",
+        LINUX_SYSCALL_MUNMAP => "munmap",
+        LINUX_SYSCALL_MREMAP => "mremap",
+        LINUX_SYSCALL_CLONE => "clone",
+        LINUX_SYSCALL_EXECVE => "execve",
+        LINUX_SYSCALL_MMAP => "mmap",
+        LINUX_SYSCALL_FADVISE64 => "fadvise64",
+        LINUX_SYSCALL_MPROTECT => "mprotect",
+        LINUX_SYSCALL_MSYNC => "msync",
+        LINUX_SYSCALL_MADVISE => "madvise",
+        LINUX_SYSCALL_ACCEPT4 => "accept4",
+        LINUX_SYSCALL_WAIT4 => "wait4",
+        LINUX_SYSCALL_PRLIMIT64 => "prlimit64",
+        LINUX_SYSCALL_SYNCFS => "syncfs",
+        LINUX_SYSCALL_FINIT_MODULE => "finit_module",
+        LINUX_SYSCALL_GETRANDOM => "getrandom",
+        LINUX_SYSCALL_RSEQ => "rseq",
+        LINUX_SYSCALL_SET_TID_ADDRESS => "set_tid_address",
+        LINUX_SYSCALL_SET_ROBUST_LIST => "set_robust_list",
+        LINUX_SYSCALL_CLOCK_SETTIME => "clock_settime",
+        LINUX_SYSCALL_CLOCK_GETTIME => "clock_gettime",
+        LINUX_SYSCALL_CLOCK_GETRES => "clock_getres",
+        LINUX_SYSCALL_CLOCK_NANOSLEEP => "clock_nanosleep",
+        LINUX_SYSCALL_SYSLOG => "syslog",
+        LINUX_SYSCALL_SCHED_GETAFFINITY => "sched_getaffinity",
+        LINUX_SYSCALL_PTRACE => "ptrace",
+        LINUX_SYSCALL_KILL => "kill",
+        LINUX_SYSCALL_RT_SIGACTION => "rt_sigaction",
+        LINUX_SYSCALL_RT_SIGPROCMASK => "rt_sigprocmask",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -6,7 +6,7 @@ use super::GdtStruct;
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[allow(missing_docs)]
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct TrapFrame {
     pub rax: u64,
     pub rcx: u64,
@@ -179,6 +179,20 @@ impl TaskContext {
         self.fs_base = tls_area.as_usize();
     }
 
+    /// The frame pointer of the task switched out, for its backtrace:
+    /// `rbp` as `context_switch` has pushed it.
+    pub fn frame_pointer(&self) -> usize {
+        let frame = self.rsp as *const ContextSwitchFrame;
+        unsafe { (*frame).rbp as usize }
+    }
+
+    /// Takes the FP/SIMD state of the current task, which is in the
+    /// registers, for this task that is forked from it.
+    pub fn inherit_fp_state(&mut self) {
+        #[cfg(feature = "fp_simd")]
+        self.ext_state.save();
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -60,6 +60,19 @@ pub fn wait_for_irqs() {
     }
 }
 
+/// Waits for interrupts with interrupts disabled, then enables them.
+///
+/// `sti` takes effect after `hlt`, so an interrupt raised right before
+/// can't be missed between checking for work and sleeping.
+#[inline]
+pub fn wait_for_irqs_and_enable() {
+    if cfg!(target_os = "none") {
+        unsafe { asm!("sti; hlt") }
+    } else {
+        core::hint::spin_loop()
+    }
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -101,6 +114,15 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the TLB entries for the kernel range of `size` bytes at
+/// `vaddr`, after its mappings are changed. There is no shootdown IPI on
+/// x86_64 yet, so only this CPU's are.
+pub fn flush_tlb_kernel_range(vaddr: VirtAddr, size: usize) {
+    for addr in (vaddr.as_usize()..vaddr.as_usize() + size).step_by(PAGE_SIZE_4K) {
+        flush_tlb(Some(addr.into()));
+    }
+}
+
 /// Reads the thread pointer of the current CPU.
 ///
 /// It is used to implement TLS (Thread Local Storage).
@@ -13,23 +13,51 @@ pub const LINUX_SYSCALL_ACCESS: usize = 0x15;
 pub const LINUX_SYSCALL_EXIT: usize = 0x3c;
 pub const LINUX_SYSCALL_UNAME: usize = 0x3f;
 pub const LINUX_SYSCALL_PREAD64: usize = 17;
+pub const LINUX_SYSCALL_SHMGET: usize = 29;
+pub const LINUX_SYSCALL_SHMAT: usize = 30;
+pub const LINUX_SYSCALL_SHMCTL: usize = 31;
+pub const LINUX_SYSCALL_SHMDT: usize = 67;
 pub const LINUX_SYSCALL_DUP: usize = 32;
+pub const LINUX_SYSCALL_DUP2: usize = 33;
 pub const LINUX_SYSCALL_DUP3: usize = 292;
 pub const LINUX_SYSCALL_SOCKET: usize = 41;
+pub const LINUX_SYSCALL_CONNECT: usize = 42;
+pub const LINUX_SYSCALL_ACCEPT: usize = 43;
+pub const LINUX_SYSCALL_SENDTO: usize = 44;
+pub const LINUX_SYSCALL_RECVFROM: usize = 45;
+pub const LINUX_SYSCALL_SENDMSG: usize = 46;
+pub const LINUX_SYSCALL_RECVMSG: usize = 47;
+pub const LINUX_SYSCALL_SHUTDOWN: usize = 48;
+pub const LINUX_SYSCALL_BIND: usize = 49;
+pub const LINUX_SYSCALL_LISTEN: usize = 50;
+pub const LINUX_SYSCALL_GETSOCKNAME: usize = 51;
+pub const LINUX_SYSCALL_GETPEERNAME: usize = 52;
+pub const LINUX_SYSCALL_SOCKETPAIR: usize = 53;
+pub const LINUX_SYSCALL_SETSOCKOPT: usize = 54;
+pub const LINUX_SYSCALL_GETSOCKOPT: usize = 55;
+pub const LINUX_SYSCALL_ACCEPT4: usize = 288;
 
 pub const LINUX_SYSCALL_ARCH_PRCTL: usize = 0x9e;
 pub const LINUX_SYSCALL_SET_TID_ADDRESS: usize = 0xda;
+pub const LINUX_SYSCALL_CLOCK_SETTIME: usize = 0xe3;
 pub const LINUX_SYSCALL_CLOCK_GETTIME: usize = 0xe4;
+pub const LINUX_SYSCALL_CLOCK_GETRES: usize = 0xe5;
 pub const LINUX_SYSCALL_EXIT_GROUP: usize = 0xe7;
 pub const LINUX_SYSCALL_OPENAT: usize = 0x101;
 pub const LINUX_SYSCALL_FSTATAT: usize = 0x106;
 pub const LINUX_SYSCALL_SET_ROBUST_LIST: usize = 0x111;
 pub const LINUX_SYSCALL_PRLIMIT64: usize = 0x12e;
+pub const LINUX_SYSCALL_GETRLIMIT: usize = 97;
+pub const LINUX_SYSCALL_SETRLIMIT: usize = 160;
+pub const LINUX_SYSCALL_SYNC: usize = 162;
+pub const LINUX_SYSCALL_SYNCFS: usize = 306;
 pub const LINUX_SYSCALL_GETRANDOM: usize = 0x13e;
 pub const LINUX_SYSCALL_RSEQ: usize = 0x14e;
 
 pub const LINUX_SYSCALL_IOCTL: usize = 16;
 pub const LINUX_SYSCALL_FCNTL: usize = 72;
+pub const LINUX_SYSCALL_FSYNC: usize = 74;
+pub const LINUX_SYSCALL_FDATASYNC: usize = 75;
 pub const LINUX_SYSCALL_FTRUNCATE: usize = 77;
 pub const LINUX_SYSCALL_GETCWD: usize = 79;
 pub const LINUX_SYSCALL_CHDIR: usize = 80;
@@ -39,6 +67,7 @@ pub const LINUX_SYSCALL_GETPID: usize = 39;
 pub const LINUX_SYSCALL_GETPPID: usize = 110;
 pub const LINUX_SYSCALL_GETGID: usize = 104;
 pub const LINUX_SYSCALL_GETUID: usize = 102;
+pub const LINUX_SYSCALL_SYSLOG: usize = 103;
 pub const LINUX_SYSCALL_GETEUID: usize = 107;
 pub const LINUX_SYSCALL_GETEGID: usize = 108;
 pub const LINUX_SYSCALL_GETTID: usize = 186;
@@ -69,8 +98,13 @@ pub const LINUX_SYSCALL_SCHED_GETAFFINITY: usize = 204;
 pub const LINUX_SYSCALL_SETITIMER: usize = 38;
 pub const LINUX_SYSCALL_WAIT4: usize = 61;
 pub const LINUX_SYSCALL_KILL: usize = 62;
+pub const LINUX_SYSCALL_PTRACE: usize = 101;
 pub const LINUX_SYSCALL_SETRESUID: usize = 117;
 pub const LINUX_SYSCALL_SETPGID: usize = 109;
+pub const LINUX_SYSCALL_GETPGRP: usize = 111;
+pub const LINUX_SYSCALL_SETSID: usize = 112;
+pub const LINUX_SYSCALL_GETPGID: usize = 121;
+pub const LINUX_SYSCALL_GETSID: usize = 124;
 pub const LINUX_SYSCALL_VFORK: usize = 58;
 pub const LINUX_SYSCALL_CLOCK_NANOSLEEP: usize = 230;
 pub const LINUX_SYSCALL_MOUNT: usize = 165;
@@ -81,11 +115,198 @@ pub const LINUX_SYSCALL_UTIMENSAT: usize = 280;
 pub const LINUX_SYSCALL_PIPE2: usize = 293;
 pub const LINUX_SYSCALL_STATFS: usize = 137;
 pub const LINUX_SYSCALL_UMASK: usize = 95;
+pub const LINUX_SYSCALL_GETTIMEOFDAY: usize = 96;
+pub const LINUX_SYSCALL_SETTIMEOFDAY: usize = 164;
+pub const LINUX_SYSCALL_GETCPU: usize = 309;
+pub const LINUX_SYSCALL_SETHOSTNAME: usize = 170;
+pub const LINUX_SYSCALL_SETDOMAINNAME: usize = 171;
+pub const LINUX_SYSCALL_INIT_MODULE: usize = 175;
+pub const LINUX_SYSCALL_DELETE_MODULE: usize = 176;
+pub const LINUX_SYSCALL_FINIT_MODULE: usize = 313;
+pub const LINUX_SYSCALL_KEXEC_LOAD: usize = 246;
+pub const LINUX_SYSCALL_REBOOT: usize = 169;
 pub const LINUX_SYSCALL_SETUID: usize = 105;
 pub const LINUX_SYSCALL_SETGID:usize = 106;
 pub const LINUX_SYSCALL_LINKAT: usize = 265;
 pub const LINUX_SYSCALL_SYMLINKAT: usize = 266;
 pub const LINUX_SYSCALL_SETREUID: usize = 113;
+pub const LINUX_SYSCALL_SETREGID: usize = 114;
+pub const LINUX_SYSCALL_GETGROUPS: usize = 115;
+pub const LINUX_SYSCALL_SETGROUPS: usize = 116;
+pub const LINUX_SYSCALL_GETRESUID: usize = 118;
+pub const LINUX_SYSCALL_SETRESGID: usize = 119;
+pub const LINUX_SYSCALL_GETRESGID: usize = 120;
+pub const LINUX_SYSCALL_SETXATTR: usize = 188;
+pub const LINUX_SYSCALL_LSETXATTR: usize = 189;
+pub const LINUX_SYSCALL_FSETXATTR: usize = 190;
+pub const LINUX_SYSCALL_GETXATTR: usize = 191;
+pub const LINUX_SYSCALL_LGETXATTR: usize = 192;
 pub const LINUX_SYSCALL_FGETXATTR: usize = 193;
+pub const LINUX_SYSCALL_LISTXATTR: usize = 194;
+pub const LINUX_SYSCALL_LLISTXATTR: usize = 195;
+pub const LINUX_SYSCALL_FLISTXATTR: usize = 196;
+pub const LINUX_SYSCALL_REMOVEXATTR: usize = 197;
+pub const LINUX_SYSCALL_LREMOVEXATTR: usize = 198;
+pub const LINUX_SYSCALL_FREMOVEXATTR: usize = 199;
+pub const LINUX_SYSCALL_TIMERFD_CREATE: usize = 283;
+pub const LINUX_SYSCALL_EVENTFD: usize = 284;
 pub const LINUX_SYSCALL_FALLOCATE: usize = 285;
+pub const LINUX_SYSCALL_FADVISE64: usize = 221;
+pub const LINUX_SYSCALL_TIMERFD_SETTIME: usize = 286;
+pub const LINUX_SYSCALL_TIMERFD_GETTIME: usize = 287;
+pub const LINUX_SYSCALL_EVENTFD2: usize = 290;
 pub const LINUX_SYSCALL_MREMAP: usize = 25;
+
+/// The name of syscall `sysno`, as strace prints it, if it is one of these.
+pub fn syscall_name(sysno: usize) -> Option<&'static str> {
+    let name = match sysno {
+        LINUX_SYSCALL_READ => "read",
+        LINUX_SYSCALL_WRITE => "write",
+        LINUX_SYSCALL_CLOSE => "close",
+        LINUX_SYSCALL_LSEEK => "lseek",
+        LINUX_SYSCALL_MMAP => "mmap",
+        LINUX_SYSCALL_MPROTECT => "mprotect",
+        LINUX_SYSCALL_BRK => "brk",
+        LINUX_SYSCALL_ACCESS => "access",
+        LINUX_SYSCALL_EXIT => "exit",
+        LINUX_SYSCALL_UNAME => "uname",
+        LINUX_SYSCALL_PREAD64 => "pread64",
+        LINUX_SYSCALL_SHMGET => "shmget",
+        LINUX_SYSCALL_SHMAT => "shmat",
+        LINUX_SYSCALL_SHMCTL => "shmctl",
+        LINUX_SYSCALL_SHMDT => "shmdt",
+        LINUX_SYSCALL_DUP => "dup",
+        LINUX_SYSCALL_DUP2 => "dup2",
+        LINUX_SYSCALL_DUP3 => "dup3",
+        LINUX_SYSCALL_SOCKET => "socket",
+        LINUX_SYSCALL_CONNECT => "connect",
+        LINUX_SYSCALL_ACCEPT => "accept",
+        LINUX_SYSCALL_SENDTO => "sendto",
+        LINUX_SYSCALL_RECVFROM => "recvfrom",
+        LINUX_SYSCALL_SENDMSG => "sendmsg",
+        LINUX_SYSCALL_RECVMSG => "recvmsg",
+        LINUX_SYSCALL_SHUTDOWN => "shutdown",
+        LINUX_SYSCALL_BIND => "bind",
+        LINUX_SYSCALL_LISTEN => "listen",
+        LINUX_SYSCALL_GETSOCKNAME => "getsockname",
+        LINUX_SYSCALL_GETPEERNAME => "getpeername",
+        LINUX_SYSCALL_SOCKETPAIR => "socketpair",
+        LINUX_SYSCALL_SETSOCKOPT => "setsockopt",
+        LINUX_SYSCALL_GETSOCKOPT => "getsockopt",
+        LINUX_SYSCALL_ACCEPT4 => "accept4",
+        LINUX_SYSCALL_ARCH_PRCTL => "arch_prctl",
+        LINUX_SYSCALL_SET_TID_ADDRESS => "set_tid_address",
+        LINUX_SYSCALL_CLOCK_SETTIME => "clock_settime",
+        LINUX_SYSCALL_CLOCK_GETTIME => "clock_gettime",
+        LINUX_SYSCALL_CLOCK_GETRES => "clock_getres",
+        LINUX_SYSCALL_EXIT_GROUP => "exit_group",
+        LINUX_SYSCALL_OPENAT => "openat",
+        LINUX_SYSCALL_FSTATAT => "fstatat",
+        LINUX_SYSCALL_SET_ROBUST_LIST => "set_robust_list",
+        LINUX_SYSCALL_PRLIMIT64 => "prlimit64",
+        LINUX_SYSCALL_GETRLIMIT => "getrlimit",
+        LINUX_SYSCALL_SETRLIMIT => "setrlimit",
+        LINUX_SYSCALL_SYNC => "sync",
+        LINUX_SYSCALL_SYNCFS => "syncfs",
+        LINUX_SYSCALL_GETRANDOM => "getrandom",
+        LINUX_SYSCALL_RSEQ => "rseq",
+        LINUX_SYSCALL_IOCTL => "ioctl",
+        LINUX_SYSCALL_FCNTL => "fcntl",
+        LINUX_SYSCALL_FSYNC => "fsync",
+        LINUX_SYSCALL_FDATASYNC => "fdatasync",
+        LINUX_SYSCALL_FTRUNCATE => "ftruncate",
+        LINUX_SYSCALL_GETCWD => "getcwd",
+        LINUX_SYSCALL_CHDIR => "chdir",
+        LINUX_SYSCALL_FACCESSAT => "faccessat",
+        LINUX_SYSCALL_TGKILL => "tgkill",
+        LINUX_SYSCALL_GETPID => "getpid",
+        LINUX_SYSCALL_GETPPID => "getppid",
+        LINUX_SYSCALL_GETGID => "getgid",
+        LINUX_SYSCALL_GETUID => "getuid",
+        LINUX_SYSCALL_SYSLOG => "syslog",
+        LINUX_SYSCALL_GETEUID => "geteuid",
+        LINUX_SYSCALL_GETEGID => "getegid",
+        LINUX_SYSCALL_GETTID => "gettid",
+        LINUX_SYSCALL_FCHMOD => "fchmod",
+        LINUX_SYSCALL_FCHMODAT => "fchmodat",
+        LINUX_SYSCALL_FCHOWNAT => "fchownat",
+        LINUX_SYSCALL_FCHOWN => "fchown",
+        LINUX_SYSCALL_CAPGET => "capget",
+        LINUX_SYSCALL_MKDIRAT => "mkdirat",
+        LINUX_SYSCALL_UNLINKAT => "unlinkat",
+        LINUX_SYSCALL_WRITEV => "writev",
+        LINUX_SYSCALL_READLINKAT => "readlinkat",
+        LINUX_SYSCALL_MUNMAP => "munmap",
+        LINUX_SYSCALL_MSYNC => "msync",
+        LINUX_SYSCALL_MADVISE => "madvise",
+        LINUX_SYSCALL_SENDFILE => "sendfile",
+        LINUX_SYSCALL_RT_SIGACTION => "rt_sigaction",
+        LINUX_SYSCALL_RT_SIGPROCMASK => "rt_sigprocmask",
+        LINUX_SYSCALL_RT_SIGRETURN => "rt_sigreturn",
+        LINUX_SYSCALL_CLONE => "clone",
+        LINUX_SYSCALL_EXECVE => "execve",
+        LINUX_SYSCALL_SCHED_GETAFFINITY => "sched_getaffinity",
+        LINUX_SYSCALL_SETITIMER => "setitimer",
+        LINUX_SYSCALL_WAIT4 => "wait4",
+        LINUX_SYSCALL_KILL => "kill",
+        LINUX_SYSCALL_PTRACE => "ptrace",
+        LINUX_SYSCALL_SETRESUID => "setresuid",
+        LINUX_SYSCALL_SETPGID => "setpgid",
+        LINUX_SYSCALL_GETPGRP => "getpgrp",
+        LINUX_SYSCALL_SETSID => "setsid",
+        LINUX_SYSCALL_GETPGID => "getpgid",
+        LINUX_SYSCALL_GETSID => "getsid",
+        LINUX_SYSCALL_VFORK => "vfork",
+        LINUX_SYSCALL_CLOCK_NANOSLEEP => "clock_nanosleep",
+        LINUX_SYSCALL_MOUNT => "mount",
+        LINUX_SYSCALL_UMOUNT2 => "umount2",
+        LINUX_SYSCALL_FUTEX => "futex",
+        LINUX_SYSCALL_MKNODAT => "mknodat",
+        LINUX_SYSCALL_UTIMENSAT => "utimensat",
+        LINUX_SYSCALL_PIPE2 => "pipe2",
+        LINUX_SYSCALL_STATFS => "statfs",
+        LINUX_SYSCALL_UMASK => "umask",
+        LINUX_SYSCALL_GETTIMEOFDAY => "gettimeofday",
+        LINUX_SYSCALL_SETTIMEOFDAY => "settimeofday",
+        LINUX_SYSCALL_GETCPU => "getcpu",
+        LINUX_SYSCALL_SETHOSTNAME => "sethostname",
+        LINUX_SYSCALL_SETDOMAINNAME => "setdomainname",
+        LINUX_SYSCALL_INIT_MODULE => "init_module",
+        LINUX_SYSCALL_DELETE_MODULE => "delete_module",
+        LINUX_SYSCALL_FINIT_MODULE => "finit_module",
+        LINUX_SYSCALL_KEXEC_LOAD => "kexec_load",
+        LINUX_SYSCALL_REBOOT => "reboot",
+        LINUX_SYSCALL_SETUID => "setuid",
+        LINUX_SYSCALL_LINKAT => "linkat",
+        LINUX_SYSCALL_SYMLINKAT => "symlinkat",
+        LINUX_SYSCALL_SETREUID => "setreuid",
+        LINUX_SYSCALL_SETREGID => "setregid",
+        LINUX_SYSCALL_GETGROUPS => "getgroups",
+        LINUX_SYSCALL_SETGROUPS => "setgroups",
+        LINUX_SYSCALL_GETRESUID => "getresuid",
+        LINUX_SYSCALL_SETRESGID => "setresgid",
+        LINUX_SYSCALL_GETRESGID => "getresgid",
+        LINUX_SYSCALL_SETXATTR => "setxattr",
+        LINUX_SYSCALL_LSETXATTR => "lsetxattr",
+        LINUX_SYSCALL_FSETXATTR => "fsetxattr",
+        LINUX_SYSCALL_GETXATTR => "getxattr",
+        LINUX_SYSCALL_LGETXATTR => "lgetxattr",
+        LINUX_SYSCALL_FGETXATTR => "fgetxattr",
+        LINUX_SYSCALL_LISTXATTR => "listxattr",
+        LINUX_SYSCALL_LLISTXATTR => "llistxattr",
+        LINUX_SYSCALL_FLISTXATTR => "flistxattr",
+        LINUX_SYSCALL_REMOVEXATTR => "removexattr",
+        LINUX_SYSCALL_LREMOVEXATTR => "lremovexattr",
+        LINUX_SYSCALL_FREMOVEXATTR => "fremovexattr",
+        LINUX_SYSCALL_TIMERFD_CREATE => "timerfd_create",
+        LINUX_SYSCALL_EVENTFD => "eventfd",
+        LINUX_SYSCALL_FALLOCATE => "fallocate",
+        LINUX_SYSCALL_FADVISE64 => "fadvise64",
+        LINUX_SYSCALL_TIMERFD_SETTIME => "timerfd_settime",
+        LINUX_SYSCALL_TIMERFD_GETTIME => "timerfd_gettime",
+        LINUX_SYSCALL_EVENTFD2 => "eventfd2",
+        LINUX_SYSCALL_MREMAP => "mremap",
+        _ => return None,
+    };
+    Some(name)
+}
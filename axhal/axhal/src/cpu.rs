@@ -37,18 +37,13 @@ pub fn current_task_ptr<T>() -> *const T {
         // on x86, only one instruction is needed to read the per-CPU task pointer from `gs:[off]`.
         CURRENT_TASK_PTR.read_current_raw() as _
     }
-    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64", target_arch = "aarch64"))]
     unsafe {
-        // on RISC-V, reading `CURRENT_TASK_PTR` requires multiple instruction, so we disable local IRQs.
+        // on RISC-V and ARM64, reading `CURRENT_TASK_PTR` requires multiple instruction, so we
+        // disable local IRQs. `SP_EL0` of ARM64 is the stack pointer of user mode.
         let _guard = kernel_guard_base::IrqSave::new();
         CURRENT_TASK_PTR.read_current_raw() as _
     }
-    #[cfg(target_arch = "aarch64")]
-    {
-        // on ARM64, we use `SP_EL0` to store the task pointer.
-        use tock_registers::interfaces::Readable;
-        aarch64_cpu::registers::SP_EL0.get() as _
-    }
 }
 
 /// Sets the pointer to the current task with preemption-safety.
@@ -65,16 +60,11 @@ pub unsafe fn set_current_task_ptr<T>(ptr: *const T) {
     {
         CURRENT_TASK_PTR.write_current_raw(ptr as usize)
     }
-    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64", target_arch = "aarch64"))]
     {
         let _guard = kernel_guard_base::IrqSave::new();
         CURRENT_TASK_PTR.write_current_raw(ptr as usize)
     }
-    #[cfg(target_arch = "aarch64")]
-    {
-        use tock_registers::interfaces::Writeable;
-        aarch64_cpu::registers::SP_EL0.set(ptr as u64)
-    }
 }
 
 #[allow(dead_code)]
@@ -39,6 +39,7 @@ pub mod platform;
 pub mod arch;
 pub mod cpu;
 pub mod mem;
+pub mod rtconfig;
 pub mod time;
 pub mod trap;
 
@@ -70,6 +70,21 @@ pub const fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
     VirtAddr::from(paddr.as_usize() + axconfig::PHYS_VIRT_OFFSET)
 }
 
+const SZ_2M: usize = 0x20_0000;
+
+/// Start of the module area, the kernel space for the code and data of
+/// modules, right above the linear mapping of the physical memory.
+pub const MODULES_VADDR: usize =
+    (axconfig::PHYS_VIRT_OFFSET + axconfig::PHYS_MEMORY_END + SZ_2M - 1) & !(SZ_2M - 1);
+/// Bytes of the module area.
+pub const MODULES_SIZE: usize = 64 * 1024 * 1024;
+
+/// Start of the ioremap area, the kernel space for the device memory
+/// outside of the MMIO regions mapped at boot, right above the module area.
+pub const IOREMAP_VADDR: usize = MODULES_VADDR + MODULES_SIZE;
+/// Bytes of the ioremap area.
+pub const IOREMAP_SIZE: usize = 256 * 1024 * 1024;
+
 /// Returns an iterator over all physical memory regions.
 pub fn memory_regions() -> impl Iterator<Item = MemRegion> {
     kernel_image_regions().chain(crate::platform::mem::platform_regions())
@@ -126,17 +141,54 @@ pub(crate) fn default_mmio_regions() -> impl Iterator<Item = MemRegion> {
     })
 }
 
-/// Returns the default free memory regions (kernel image end to physical memory end).
+/// Bytes at the end of the physical memory kept from the page allocator,
+/// for kexec to stage the next kernel in. Only RISC-V has kexec.
+#[cfg(target_arch = "riscv64")]
+pub const KEXEC_RESERVED_SIZE: usize = 0x200_0000;
+#[cfg(not(target_arch = "riscv64"))]
+pub const KEXEC_RESERVED_SIZE: usize = 0;
+
+/// Returns (start, size) of the memory kept for kexec, if there is any.
+pub fn kexec_region() -> Option<(PhysAddr, usize)> {
+    let end = PhysAddr::from(axconfig::runtime::phys_memory_end()).align_down_4k();
+    (KEXEC_RESERVED_SIZE > 0).then(|| (end - KEXEC_RESERVED_SIZE, KEXEC_RESERVED_SIZE))
+}
+
+/// Bytes below the memory kept for kexec kept from the page allocator as
+/// well, for the crash dump written on a panic.
+pub const KDUMP_RESERVED_SIZE: usize = 0x10_0000;
+
+/// Returns (start, size) of the memory kept for the crash dump.
+pub fn kdump_region() -> (PhysAddr, usize) {
+    let end = PhysAddr::from(axconfig::runtime::phys_memory_end()).align_down_4k() - KEXEC_RESERVED_SIZE;
+    (end - KDUMP_RESERVED_SIZE, KDUMP_RESERVED_SIZE)
+}
+
+/// Returns the default free memory regions (kernel image end to physical
+/// memory end), and the memory kept for the crash dump and for kexec at
+/// the end.
 #[allow(dead_code)]
 pub(crate) fn default_free_regions() -> impl Iterator<Item = MemRegion> {
     let start = virt_to_phys((_ekernel as usize).into()).align_up_4k();
-    let end = PhysAddr::from(axconfig::PHYS_MEMORY_END).align_down_4k();
+    let (kdump, kdump_size) = kdump_region();
     core::iter::once(MemRegion {
         paddr: start,
-        size: end.as_usize() - start.as_usize(),
+        size: kdump.as_usize() - start.as_usize(),
         flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
         name: "free memory",
     })
+    .chain(core::iter::once(MemRegion {
+        paddr: kdump,
+        size: kdump_size,
+        flags: MemRegionFlags::RESERVED | MemRegionFlags::READ | MemRegionFlags::WRITE,
+        name: "kdump",
+    }))
+    .chain(kexec_region().map(|(paddr, size)| MemRegion {
+        paddr,
+        size,
+        flags: MemRegionFlags::RESERVED | MemRegionFlags::READ | MemRegionFlags::WRITE,
+        name: "kexec",
+    }))
 }
 
 /// Fills the `.bss` section with zeros.
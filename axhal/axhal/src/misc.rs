@@ -1,23 +1,3 @@
 //! Misc
 
 pub use super::platform::misc::*;
-
-use spinbase::SpinNoIrq;
-use crate::time;
-
-static PARK_MILLER_LEHMER_SEED: SpinNoIrq<u32> = SpinNoIrq::new(0);
-const RAND_MAX: u64 = 2_147_483_647;
-
-pub fn random() -> u128 {
-	let mut seed = PARK_MILLER_LEHMER_SEED.lock();
-    if *seed == 0 {
-        *seed = time::current_ticks() as u32;
-    }
-
-    let mut ret: u128 = 0;
-    for _ in 0..4 {
-	    *seed = ((u64::from(*seed) * 48271) % RAND_MAX) as u32;
-        ret = (ret << 32) | (*seed as u128);
-    }
-    ret
-}
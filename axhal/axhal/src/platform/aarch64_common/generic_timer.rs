@@ -1,6 +1,6 @@
 #![allow(unused_imports)]
 
-use aarch64_cpu::registers::{CNTFRQ_EL0, CNTPCT_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0};
+use aarch64_cpu::registers::{CNTFRQ_EL0, CNTKCTL_EL1, CNTPCT_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0};
 use ratio::Ratio;
 use tock_registers::interfaces::{Readable, Writeable};
 
@@ -51,6 +51,8 @@ pub(crate) fn init_early() {
 }
 
 pub(crate) fn init_percpu() {
+    // Lets user mode read the counter, for the vDSO.
+    CNTKCTL_EL1.write(CNTKCTL_EL1::EL0PCTEN::SET + CNTKCTL_EL1::EL0VCTEN::SET);
     #[cfg(feature = "irq")]
     {
         CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET);
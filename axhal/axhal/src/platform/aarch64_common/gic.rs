@@ -1,60 +1,137 @@
-use crate::{irq::IrqHandler, mem::phys_to_virt};
-use arm_gic::gic_v2::{GicCpuInterface, GicDistributor};
-use arm_gic::{translate_irq, InterruptType};
-use memory_addr::PhysAddr;
-use spinlock::SpinNoIrq;
+//! The GICv2 of the QEMU virt machine. Its distributor routes the shared
+//! peripheral interrupts (SPIs, from 32) to CPU 0, and its CPU interface
+//! acknowledges them, as well as the per-CPU ones (PPIs, from 16) as the
+//! timer, and the software generated ones (SGIs, below 16), the IPIs.
+//!
+//! The lines are masked and unmasked, and the interrupts claimed and
+//! completed, through [`axirq`], as the sources of a PLIC are.
+
+use crate::mem::phys_to_virt;
 
 /// The maximum number of IRQs.
 pub const MAX_IRQ_COUNT: usize = 1024;
 
-/// The timer IRQ number.
-pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
+/// The timer IRQ number: the non-secure EL1 physical timer, PPI 14.
+pub const TIMER_IRQ_NUM: usize = 16 + 14;
+
+/// The IRQ number of inter-processor interrupts, SGI 0.
+pub const IPI_IRQ_NUM: usize = 0;
 
 /// The UART IRQ number.
-pub const UART_IRQ_NUM: usize = translate_irq(axconfig::UART_IRQ, InterruptType::SPI).unwrap();
+pub const UART_IRQ_NUM: usize = 32 + axconfig::UART_IRQ;
+
+/// Interrupt ID of GICC_IAR when nothing is pending.
+const SPURIOUS_IRQ: usize = 1023;
+
+const GICD_CTLR: usize = 0x000;
+const GICD_TYPER: usize = 0x004;
+const GICD_ISENABLER: usize = 0x100;
+const GICD_ICENABLER: usize = 0x180;
+const GICD_IPRIORITYR: usize = 0x400;
+const GICD_ITARGETSR: usize = 0x800;
+const GICD_ICFGR: usize = 0xc00;
+const GICD_SGIR: usize = 0xf00;
 
-const GICD_BASE: PhysAddr = PhysAddr::from(axconfig::GICD_PADDR);
-const GICC_BASE: PhysAddr = PhysAddr::from(axconfig::GICC_PADDR);
+const GICC_CTLR: usize = 0x000;
+const GICC_PMR: usize = 0x004;
+const GICC_IAR: usize = 0x00c;
+const GICC_EOIR: usize = 0x010;
 
-static GICD: SpinNoIrq<GicDistributor> =
-    SpinNoIrq::new(GicDistributor::new(phys_to_virt(GICD_BASE).as_mut_ptr()));
+fn gicd_reg(offset: usize) -> *mut u32 {
+    phys_to_virt((axconfig::GICD_PADDR + offset).into()).as_mut_ptr() as *mut u32
+}
 
-// per-CPU, no lock
-static GICC: GicCpuInterface = GicCpuInterface::new(phys_to_virt(GICC_BASE).as_mut_ptr());
+fn gicc_reg(offset: usize) -> *mut u32 {
+    phys_to_virt((axconfig::GICC_PADDR + offset).into()).as_mut_ptr() as *mut u32
+}
 
-/// Enables or disables the given IRQ.
+/// Enables or disables the given IRQ. The PPIs and SGIs are of this CPU.
 pub fn set_enable(irq_num: usize, enabled: bool) {
     trace!("GICD set enable: {} {}", irq_num, enabled);
-    GICD.lock().set_enable(irq_num as _, enabled);
+    let reg = if enabled { GICD_ISENABLER } else { GICD_ICENABLER };
+    unsafe { gicd_reg(reg + (irq_num / 32) * 4).write_volatile(1 << (irq_num % 32)) };
 }
 
-/// Registers an IRQ handler for the given IRQ.
-///
-/// It also enables the IRQ if the registration succeeds. It returns `false` if
-/// the registration failed.
-pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
-    trace!("register handler irq {}", irq_num);
-    crate::irq::register_handler_common(irq_num, handler)
+/// Routes the SPI `irq` to `cpu` only.
+pub fn set_target(irq_num: usize, cpu: usize) {
+    let reg = gicd_reg(GICD_ITARGETSR + (irq_num & !3));
+    let shift = (irq_num % 4) * 8;
+    unsafe {
+        let targets = reg.read_volatile() & !(0xff << shift);
+        reg.write_volatile(targets | (1 << (cpu + shift)));
+    }
+}
+
+/// The IRQ number of a value that [`claim`] returned.
+#[inline]
+pub const fn irq_of(iar: usize) -> usize {
+    iar & 0x3ff
 }
 
-/// Dispatches the IRQ.
+/// Acknowledges the pending interrupt of the highest priority, if there is
+/// one. It is not raised again until it is completed.
 ///
-/// This function is called by the common interrupt handler. It looks
-/// up in the IRQ handler table and calls the corresponding handler. If
-/// necessary, it also acknowledges the interrupt controller after handling.
-pub fn dispatch_irq(_unused: usize) {
-    GICC.handle_irq(|irq_num| crate::irq::dispatch_irq_common(irq_num as _));
+/// Returns GICC_IAR, which has the ID of the sender CPU above the IRQ
+/// number, see [`irq_of`], for an SGI, and which completes it.
+pub fn claim() -> Option<usize> {
+    let iar = unsafe { gicc_reg(GICC_IAR).read_volatile() } as usize;
+    match irq_of(iar) {
+        SPURIOUS_IRQ => None,
+        _ => Some(iar),
+    }
+}
+
+/// Tells the GIC the interrupt claimed as `iar` has been handled.
+pub fn complete(iar: usize) {
+    unsafe { gicc_reg(GICC_EOIR).write_volatile(iar as u32) };
+}
+
+/// Sends an inter-processor interrupt to `cpu`.
+pub fn send_ipi(cpu: usize) {
+    // CPUTargetList of bit 16, and the SGI number.
+    let sgir = (1 << (16 + cpu)) | IPI_IRQ_NUM;
+    unsafe { gicd_reg(GICD_SGIR).write_volatile(sgir as u32) };
+}
+
+/// Acknowledges the inter-processor interrupt of this CPU, which completing
+/// it at the CPU interface does already.
+pub fn ack_ipi() {}
+
+/// Lets the CPU interface of this CPU signal the interrupts of any
+/// priority, and unmasks the IPI.
+fn init_percpu() {
+    unsafe {
+        gicc_reg(GICC_PMR).write_volatile(0xff);
+        gicc_reg(GICC_CTLR).write_volatile(1);
+    }
+    set_enable(IPI_IRQ_NUM, true);
 }
 
-/// Initializes GICD, GICC on the primary CPU.
+/// Initializes GICD, GICC on the primary CPU: all the SPIs are masked,
+/// level-sensitive and routed to CPU 0, at the same priority.
 pub(crate) fn init_primary() {
     info!("Initialize GICv2...");
-    GICD.lock().init();
-    GICC.init();
+    let lines = ((unsafe { gicd_reg(GICD_TYPER).read_volatile() } as usize & 0x1f) + 1) * 32;
+    let lines = lines.min(MAX_IRQ_COUNT);
+    unsafe {
+        gicd_reg(GICD_CTLR).write_volatile(0);
+        for i in (32..lines).step_by(32) {
+            gicd_reg(GICD_ICENABLER + i / 8).write_volatile(u32::MAX);
+        }
+        for i in (32..lines).step_by(4) {
+            gicd_reg(GICD_IPRIORITYR + i).write_volatile(0xa0a0_a0a0);
+            gicd_reg(GICD_ITARGETSR + i).write_volatile(0x0101_0101);
+        }
+        for i in (32..lines).step_by(16) {
+            gicd_reg(GICD_ICFGR + i / 4).write_volatile(0);
+        }
+        gicd_reg(GICD_CTLR).write_volatile(1);
+    }
+    init_percpu();
 }
 
 /// Initializes GICC on secondary CPUs.
 #[cfg(feature = "smp")]
 pub(crate) fn init_secondary() {
-    GICC.init();
+    init_percpu();
 }
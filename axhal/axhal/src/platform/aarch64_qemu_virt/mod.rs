@@ -8,10 +8,6 @@ pub mod irq {
     pub use crate::platform::aarch64_common::gic::*;
 }
 
-pub mod console {
-    pub use crate::platform::aarch64_common::pl011::*;
-}
-
 pub mod time {
     pub use crate::platform::aarch64_common::generic_timer::*;
 }
@@ -20,39 +16,15 @@ pub mod misc {
     pub use crate::platform::aarch64_common::psci::system_off as terminate;
 }
 
-extern "C" {
-    fn exception_vector_base();
-    fn rust_main(cpu_id: usize, dtb: usize);
-    #[cfg(feature = "smp")]
-    fn rust_main_secondary(cpu_id: usize);
-}
-
-pub(crate) unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
-    crate::mem::clear_bss();
-    crate::arch::set_exception_vector_base(exception_vector_base as usize);
-    crate::arch::write_page_table_root0(0.into()); // disable low address access
-    crate::cpu::init_primary(cpu_id);
-    super::aarch64_common::pl011::init_early();
-    super::aarch64_common::generic_timer::init_early();
-    rust_main(cpu_id, dtb);
-}
-
-#[cfg(feature = "smp")]
-pub(crate) unsafe extern "C" fn rust_entry_secondary(cpu_id: usize) {
-    crate::arch::set_exception_vector_base(exception_vector_base as usize);
-    crate::arch::write_page_table_root0(0.into()); // disable low address access
-    crate::cpu::init_secondary(cpu_id);
-    rust_main_secondary(cpu_id);
-}
-
 /// Initializes the platform devices for the primary CPU.
 ///
 /// For example, the interrupt controller and the timer.
 pub fn platform_init() {
+    axconfig::init_once!();
+
     #[cfg(feature = "irq")]
     super::aarch64_common::gic::init_primary();
     super::aarch64_common::generic_timer::init_percpu();
-    super::aarch64_common::pl011::init();
 }
 
 /// Initializes the platform devices for secondary CPUs.
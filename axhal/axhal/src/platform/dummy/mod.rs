@@ -63,6 +63,15 @@ pub mod irq {
     /// The timer IRQ number.
     pub const TIMER_IRQ_NUM: usize = 0;
 
+    /// The IRQ number of inter-processor interrupts.
+    pub const IPI_IRQ_NUM: usize = 1;
+
+    /// Sends an inter-processor interrupt to `cpu`.
+    pub fn send_ipi(cpu: usize) {}
+
+    /// Acknowledges the inter-processor interrupt of this CPU.
+    pub fn ack_ipi() {}
+
     /// Enables or disables the given IRQ.
     pub fn set_enable(irq_num: usize, enabled: bool) {}
 
@@ -6,7 +6,6 @@ use riscv::register::sie;
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -23,6 +22,20 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 /// The timer IRQ number (supervisor timer interrupt in `scause`).
 pub const TIMER_IRQ_NUM: usize = S_TIMER;
 
+/// The IRQ number of inter-processor interrupts (supervisor software
+/// interrupt in `scause`).
+pub const IPI_IRQ_NUM: usize = S_SOFT;
+
+/// Sends an inter-processor interrupt to `cpu`.
+pub fn send_ipi(cpu: usize) {
+    sbi_rt::send_ipi(1 << cpu, 0);
+}
+
+/// Acknowledges the inter-processor interrupt of this CPU.
+pub fn ack_ipi() {
+    unsafe { riscv::register::sip::clear_ssoft() };
+}
+
 pub(super) fn init_percpu() {
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
@@ -1,7 +1,23 @@
+use core::sync::atomic::{AtomicBool, Ordering};
 use riscv::register::time;
 
 const NANOS_PER_TICK: u64 = crate::time::NANOS_PER_SEC / axconfig::TIMER_FREQUENCY as u64;
 
+/// Whether the harts have the Sstc extension, so that the timer is set with
+/// `stimecmp` rather than with a call to the SBI.
+static SSTC: AtomicBool = AtomicBool::new(false);
+
+/// Sets the timer with `stimecmp` from now on, for the harts that have the
+/// Sstc extension, as their ISA strings in the device tree tell.
+pub fn enable_sstc() {
+    SSTC.store(true, Ordering::Release);
+}
+
+/// Whether the timer is set with `stimecmp`.
+pub fn has_sstc() -> bool {
+    SSTC.load(Ordering::Acquire)
+}
+
 /// Returns the current clock time in hardware ticks.
 #[inline]
 pub fn current_ticks() -> u64 {
@@ -25,10 +41,24 @@ pub const fn nanos_to_ticks(nanos: u64) -> u64 {
 /// A timer interrupt will be triggered at the given deadline (in nanoseconds).
 #[cfg(feature = "irq")]
 pub fn set_oneshot_timer(deadline_ns: u64) {
-    sbi_rt::set_timer(nanos_to_ticks(deadline_ns));
+    set_timer(nanos_to_ticks(deadline_ns));
+}
+
+/// Sets the timer for `deadline` in ticks, which also clears the pending
+/// timer interrupt if it is later than now.
+#[cfg(feature = "irq")]
+fn set_timer(deadline: u64) {
+    if has_sstc() {
+        // stimecmp, by number as the assembler may not know it.
+        unsafe { core::arch::asm!("csrw 0x14d, {}", in(reg) deadline) };
+    } else {
+        sbi_rt::set_timer(deadline);
+    }
 }
 
 pub(super) fn init_percpu() {
+    // Lets user mode read the timer, for the vDSO.
+    unsafe { riscv::register::scounteren::set_tm() };
     #[cfg(feature = "irq")]
-    sbi_rt::set_timer(0);
+    set_timer(0);
 }
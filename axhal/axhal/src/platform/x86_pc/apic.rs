@@ -14,6 +14,7 @@ pub(super) mod vectors {
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const APIC_IPI_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -22,6 +23,9 @@ pub const MAX_IRQ_COUNT: usize = 256;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
+/// The IRQ number of inter-processor interrupts.
+pub const IPI_IRQ_NUM: usize = APIC_IPI_VECTOR as usize;
+
 const IO_APIC_BASE: PhysAddr = PhysAddr::from(0xFEC0_0000);
 
 static mut LOCAL_APIC: Option<LocalApic> = None;
@@ -48,6 +52,17 @@ pub fn end_of_interrupt() {
     unsafe { local_apic().end_of_interrupt() };
 }
 
+/// Sends an inter-processor interrupt to `cpu`.
+#[cfg(feature = "irq")]
+pub fn send_ipi(cpu: usize) {
+    unsafe { local_apic().send_ipi(APIC_IPI_VECTOR, raw_apic_id(cpu as u8)) };
+}
+
+/// Acknowledges the inter-processor interrupt of this CPU. It's done with
+/// the end of interrupt on x86.
+#[cfg(feature = "irq")]
+pub fn ack_ipi() {}
+
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as LAPIC is per-cpu.
     unsafe { LOCAL_APIC.as_mut().unwrap() }
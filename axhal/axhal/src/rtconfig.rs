@@ -0,0 +1,75 @@
+//! Overrides of the config for the machine booted on, see
+//! [`axconfig::runtime`].
+
+use axdtb::DeviceTree;
+
+use crate::mem::phys_to_virt;
+
+/// Sets the memory and the number of CPUs of the config from the device
+/// tree at `dtb_pa`, and from the `mem=` and `nr_cpus=` (or `maxcpus=`) of
+/// its bootargs, which take precedence. Must be called before the memory
+/// allocator is initialized, which takes the memory as it is then.
+pub fn init(dtb_pa: usize) {
+    if dtb_pa != 0 {
+        if let Ok(dt) = DeviceTree::init(phys_to_virt(dtb_pa.into()).as_usize()) {
+            init_from_dtb(&dt);
+        }
+    }
+    axconfig::runtime::freeze();
+    info!(
+        "config: memory [{:#x}, {:#x}), {} CPUs",
+        axconfig::runtime::phys_memory_base(),
+        axconfig::runtime::phys_memory_end(),
+        axconfig::runtime::nr_cpus(),
+    );
+}
+
+fn init_from_dtb(dt: &DeviceTree) {
+    if let Some((base, size)) = dt.find_node("/memory").and_then(|memory| memory.reg()) {
+        axconfig::runtime::set_phys_memory(base, size);
+    }
+    if let Some(cpus) = dt.find_node("/cpus") {
+        let n = cpus.children()
+            .filter(|(name, cpu)| {
+                name.starts_with("cpu@") && cpu.prop_str("status").map_or(true, |s| s == "okay")
+            })
+            .count();
+        axconfig::runtime::set_nr_cpus(n);
+    }
+    let Some(bootargs) = dt.find_node("/chosen").and_then(|chosen| chosen.prop_str("bootargs")) else {
+        return;
+    };
+    for arg in bootargs.split_ascii_whitespace() {
+        let Some((key, value)) = arg.split_once('=') else {
+            continue;
+        };
+        match key {
+            "mem" => {
+                if let Some(size) = parse_size(value) {
+                    axconfig::runtime::set_phys_memory(axconfig::runtime::phys_memory_base(), size);
+                }
+            }
+            "nr_cpus" | "maxcpus" => {
+                if let Ok(n) = value.parse() {
+                    axconfig::runtime::set_nr_cpus(n);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A size as `mem=` takes it: a number with a K, M or G suffix.
+fn parse_size(value: &str) -> Option<usize> {
+    let (num, shift) = match value.as_bytes().last()? {
+        b'K' | b'k' => (&value[..value.len() - 1], 10),
+        b'M' | b'm' => (&value[..value.len() - 1], 20),
+        b'G' | b'g' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let num = match num.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+        None => num.parse().ok()?,
+    };
+    num.checked_mul(1 << shift)
+}
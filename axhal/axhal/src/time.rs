@@ -13,6 +13,8 @@ pub use crate::platform::irq::TIMER_IRQ_NUM;
 #[cfg(feature = "irq")]
 pub use crate::platform::time::set_oneshot_timer;
 pub use crate::platform::time::{current_ticks, nanos_to_ticks, ticks_to_nanos};
+#[cfg(all(target_arch = "riscv64", platform_family = "riscv64-qemu-virt"))]
+pub use crate::platform::time::{enable_sstc, has_sstc};
 
 /// Number of milliseconds in a second.
 pub const MILLIS_PER_SEC: u64 = 1_000;
@@ -1,4 +1,4 @@
-use crate::{BufRead, Read, Result};
+use crate::{BufRead, Read, Result, Seek, SeekFrom};
 
 #[cfg(feature = "alloc")]
 use alloc::{string::String, vec::Vec};
@@ -55,6 +55,28 @@ impl<R> BufReader<R> {
         self.inner
     }
 
+    /// Seeks relative to the current position. If the new position lies
+    /// within the buffer, the buffer is not flushed, which saves a seek of
+    /// the underlying reader.
+    pub fn seek_relative(&mut self, offset: i64) -> Result<()>
+    where
+        R: Seek,
+    {
+        let pos = self.pos as u64;
+        if offset < 0 {
+            if let Some(new_pos) = pos.checked_sub(offset.unsigned_abs()) {
+                self.pos = new_pos as usize;
+                return Ok(());
+            }
+        } else if let Some(new_pos) = pos.checked_add(offset as u64) {
+            if new_pos <= self.filled as u64 {
+                self.pos = new_pos as usize;
+                return Ok(());
+            }
+        }
+        self.seek(SeekFrom::Current(offset)).map(drop)
+    }
+
     fn discard_buffer(&mut self) {
         self.pos = 0;
         self.filled = 0;
@@ -156,3 +178,40 @@ impl<R: Read> BufRead for BufReader<R> {
         self.pos = core::cmp::min(self.pos + amt, self.filled);
     }
 }
+
+impl<R: Seek> Seek for BufReader<R> {
+    /// Seeks to an offset, in bytes, in the underlying reader, and discards
+    /// the buffer.
+    ///
+    /// The position used for [`SeekFrom::Current`] is the one of the
+    /// `BufReader`, not the one of the underlying reader, which is ahead of
+    /// it by the data still in the buffer.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let result = if let SeekFrom::Current(n) = pos {
+            let remainder = (self.filled - self.pos) as i64;
+            // Seek from where the underlying reader is first, in case
+            // `n - remainder` overflows.
+            if let Some(offset) = n.checked_sub(remainder) {
+                self.inner.seek(SeekFrom::Current(offset))?
+            } else {
+                self.inner.seek(SeekFrom::Current(-remainder))?;
+                self.discard_buffer();
+                self.inner.seek(SeekFrom::Current(n))?
+            }
+        } else {
+            self.inner.seek(pos)?
+        };
+        self.discard_buffer();
+        Ok(result)
+    }
+
+    /// Returns the position of the `BufReader` from the start of the stream,
+    /// without seeking the underlying reader.
+    fn stream_position(&mut self) -> Result<u64> {
+        let remainder = (self.filled - self.pos) as u64;
+        self.inner.stream_position().map(|pos| {
+            pos.checked_sub(remainder)
+                .expect("overflow when subtracting remaining buffer size from inner stream position")
+        })
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{BufRead, Read, Result, Seek, SeekFrom, Write};
+use core::cmp;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// A `Cursor` wraps an in-memory buffer and provides it with a [`Seek`]
+/// implementation.
+///
+/// It makes a buffer, such as the contents of a file already read, usable
+/// wherever a reader or a writer that can seek is expected.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    /// Creates a new cursor wrapping the provided underlying in-memory buffer,
+    /// whose initial position is 0.
+    pub const fn new(inner: T) -> Cursor<T> {
+        Cursor { inner, pos: 0 }
+    }
+
+    /// Consumes this cursor, returning the underlying value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Gets a reference to the underlying value in this cursor.
+    pub const fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying value in this cursor.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the current position of this cursor.
+    pub const fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the position of this cursor.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+}
+
+impl<T: AsRef<[u8]>> Cursor<T> {
+    /// Returns the part of the buffer from the current position on, which
+    /// is empty if the position is past its end.
+    pub fn remaining_slice(&self) -> &[u8] {
+        let inner = self.inner.as_ref();
+        let start = cmp::min(self.pos, inner.len() as u64) as usize;
+        &inner[start..]
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.inner.as_ref().len() as u64, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => axerrno::ax_err!(
+                InvalidInput,
+                "invalid seek to a negative or overflowing position"
+            ),
+        }
+    }
+
+    fn stream_position(&mut self) -> Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = Read::read(&mut self.remaining_slice(), buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let n = buf.len();
+        Read::read_exact(&mut self.remaining_slice(), buf)?;
+        self.pos += n as u64;
+        Ok(())
+    }
+}
+
+impl<T: AsRef<[u8]>> BufRead for Cursor<T> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(self.remaining_slice())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
+/// Writes `buf` at `pos` in the fixed-size `slice`, as much of it as fits.
+fn slice_write(pos: &mut u64, slice: &mut [u8], buf: &[u8]) -> Result<usize> {
+    let start = cmp::min(*pos, slice.len() as u64) as usize;
+    let amt = cmp::min(slice.len() - start, buf.len());
+    slice[start..start + amt].copy_from_slice(&buf[..amt]);
+    *pos += amt as u64;
+    Ok(amt)
+}
+
+/// Writes `buf` at `pos` in `vec`, growing it, and filling the gap with
+/// zeroes if `pos` is past its end.
+#[cfg(feature = "alloc")]
+fn vec_write(pos: &mut u64, vec: &mut Vec<u8>, buf: &[u8]) -> Result<usize> {
+    let start = usize::try_from(*pos)
+        .map_err(|_| axerrno::ax_err_type!(InvalidInput, "cursor position exceeds maximum possible vector length"))?;
+    let end = start + buf.len();
+    if end > vec.len() {
+        vec.resize(end, 0);
+    }
+    vec[start..end].copy_from_slice(buf);
+    *pos = end as u64;
+    Ok(buf.len())
+}
+
+impl Write for Cursor<&mut [u8]> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        slice_write(&mut self.pos, self.inner, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Write for Cursor<&mut Vec<u8>> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        vec_write(&mut self.pos, self.inner, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Write for Cursor<Vec<u8>> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        vec_write(&mut self.pos, &mut self.inner, buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -9,12 +9,14 @@ extern crate alloc;
 use core::fmt;
 
 mod buffered;
+mod cursor;
 mod error;
 mod impls;
 
 pub mod prelude;
 
 pub use self::buffered::BufReader;
+pub use self::cursor::Cursor;
 pub use self::error::{Error, Result};
 
 #[cfg(feature = "alloc")]
@@ -66,6 +68,15 @@ pub trait Read {
             Ok(())
         }
     }
+
+    /// Creates a "by reference" adapter for this instance of `Read`, so that
+    /// an adapter such as [`BufReader`] can borrow it rather than take it.
+    fn by_ref(&mut self) -> &mut Self
+    where
+        Self: Sized,
+    {
+        self
+    }
 }
 
 /// A trait for objects which are byte-oriented sinks.
@@ -233,6 +244,86 @@ pub trait BufRead: Read {
     fn read_line(&mut self, buf: &mut String) -> Result<usize> {
         unsafe { append_to_string(buf, |b| self.read_until(b'\n', b)) }
     }
+
+    /// Returns an iterator over the contents of this reader split on the
+    /// byte `byte`, which is not included in the items.
+    #[cfg(feature = "alloc")]
+    fn split(self, byte: u8) -> Split<Self>
+    where
+        Self: Sized,
+    {
+        Split { buf: self, delim: byte }
+    }
+
+    /// Returns an iterator over the lines of this reader, without their
+    /// newline (`\n` or `\r\n`).
+    #[cfg(feature = "alloc")]
+    fn lines(self) -> Lines<Self>
+    where
+        Self: Sized,
+    {
+        Lines { buf: self }
+    }
+}
+
+/// An iterator over the contents of a [`BufRead`] split on a byte.
+///
+/// It is created by [`BufRead::split`].
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct Split<B> {
+    buf: B,
+    delim: u8,
+}
+
+#[cfg(feature = "alloc")]
+impl<B: BufRead> Iterator for Split<B> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>>> {
+        let mut buf = Vec::new();
+        match self.buf.read_until(self.delim, &mut buf) {
+            Ok(0) => None,
+            Ok(_n) => {
+                if buf[buf.len() - 1] == self.delim {
+                    buf.pop();
+                }
+                Some(Ok(buf))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// An iterator over the lines of a [`BufRead`].
+///
+/// It is created by [`BufRead::lines`].
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct Lines<B> {
+    buf: B,
+}
+
+#[cfg(feature = "alloc")]
+impl<B: BufRead> Iterator for Lines<B> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Result<String>> {
+        let mut buf = String::new();
+        match self.buf.read_line(&mut buf) {
+            Ok(0) => None,
+            Ok(_n) => {
+                if buf.ends_with('\n') {
+                    buf.pop();
+                    if buf.ends_with('\r') {
+                        buf.pop();
+                    }
+                }
+                Some(Ok(buf))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
 }
 
 #[cfg(feature = "alloc")]
@@ -258,3 +349,42 @@ pub struct PollState {
     /// Object can be writen now.
     pub writable: bool,
 }
+
+impl PollState {
+    /// Whether the object is ready for any of what `interest` asks for.
+    pub const fn is_ready(&self, interest: Interest) -> bool {
+        (interest.readable && self.readable) || (interest.writable && self.writable)
+    }
+}
+
+/// The readiness an I/O object is polled for, as the events asked of
+/// poll(2).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Interest {
+    /// Wait for the object to be readable.
+    pub readable: bool,
+    /// Wait for the object to be writable.
+    pub writable: bool,
+}
+
+impl Interest {
+    /// Interest in reading.
+    pub const READABLE: Interest = Interest {
+        readable: true,
+        writable: false,
+    };
+
+    /// Interest in writing.
+    pub const WRITABLE: Interest = Interest {
+        readable: false,
+        writable: true,
+    };
+
+    /// Adds the readiness of `other` to this one.
+    pub const fn add(self, other: Interest) -> Interest {
+        Interest {
+            readable: self.readable || other.readable,
+            writable: self.writable || other.writable,
+        }
+    }
+}
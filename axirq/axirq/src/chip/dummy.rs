@@ -0,0 +1,17 @@
+//! No interrupt controller.
+
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::MsiMsg;
+
+pub const CHIP_NAME: &str = "none";
+
+pub fn set_enable(_irq: usize, _enabled: bool) {}
+
+pub fn set_affinity(_irq: usize, _cpu: usize) -> LinuxResult {
+    Err(LinuxError::EIO)
+}
+
+pub fn alloc_msi() -> Option<MsiMsg> {
+    None
+}
@@ -0,0 +1,24 @@
+//! The GICv2 of the QEMU virt machine, which axhal drives, for the timer
+//! and the IPIs too.
+
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::MsiMsg;
+
+pub const CHIP_NAME: &str = "GICv2";
+
+pub use axhal::platform::irq::{claim, complete, irq_of, set_enable};
+
+/// Routes the SPI `irq` to `cpu`. The PPIs and the SGIs are of each CPU.
+pub fn set_affinity(irq: usize, cpu: usize) -> LinuxResult {
+    if irq < 32 {
+        return Err(LinuxError::EIO);
+    }
+    axhal::platform::irq::set_target(irq, cpu);
+    Ok(())
+}
+
+/// The GICv2 has no MSI frame, the devices raise their INTx lines.
+pub fn alloc_msi() -> Option<MsiMsg> {
+    None
+}
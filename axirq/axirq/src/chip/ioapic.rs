@@ -0,0 +1,45 @@
+//! The I/O APIC of the PC, by the vectors it delivers the IRQs at, and
+//! the MSIs, which the local APIC gets directly.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::MsiMsg;
+
+pub const CHIP_NAME: &str = "IO-APIC";
+
+/// Vectors handed out for MSIs, past those of the I/O APIC and below those
+/// of the local APIC.
+const MSI_VECTOR_START: usize = 0x40;
+const MSI_VECTOR_END: usize = 0xf0;
+
+/// Where an MSI is written to reach the local APIC of CPU 0.
+const MSI_ADDRESS: u64 = 0xfee0_0000;
+
+static NEXT_MSI_VECTOR: AtomicUsize = AtomicUsize::new(MSI_VECTOR_START);
+
+/// Unmasks or masks the IRQ of `vector`.
+pub fn set_enable(vector: usize, enabled: bool) {
+    // An MSI is masked at its device.
+    if vector >= MSI_VECTOR_START {
+        return;
+    }
+    axhal::x86_64::set_enable(vector, enabled)
+}
+
+/// The IRQs and the MSIs are all delivered to CPU 0.
+pub fn set_affinity(_vector: usize, _cpu: usize) -> LinuxResult {
+    Err(LinuxError::EIO)
+}
+
+/// Takes a vector for an MSI, delivered to CPU 0.
+pub fn alloc_msi() -> Option<MsiMsg> {
+    let vector = NEXT_MSI_VECTOR
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| (v < MSI_VECTOR_END).then_some(v + 1))
+        .ok()?;
+    Some(MsiMsg {
+        irq: vector,
+        address: MSI_ADDRESS,
+        data: vector as u32,
+    })
+}
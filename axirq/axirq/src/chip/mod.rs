@@ -0,0 +1,17 @@
+//! The interrupt controller, which masks and unmasks the IRQ lines.
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        mod plic;
+        pub use self::plic::*;
+    } else if #[cfg(target_arch = "aarch64")] {
+        mod gic;
+        pub use self::gic::*;
+    } else if #[cfg(target_arch = "x86_64")] {
+        mod ioapic;
+        pub use self::ioapic::*;
+    } else {
+        mod dummy;
+        pub use self::dummy::*;
+    }
+}
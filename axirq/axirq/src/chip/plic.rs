@@ -0,0 +1,96 @@
+//! The PLIC of the QEMU virt machine, which routes the external interrupts
+//! to the supervisor context of the harts.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use axerrno::LinuxResult;
+use axhal::mem::phys_to_virt;
+use axhal::platform::irq::MAX_IRQ_COUNT;
+
+use crate::MsiMsg;
+
+pub const CHIP_NAME: &str = "PLIC";
+
+const PLIC_BASE: usize = 0x0c00_0000;
+const PLIC_PRIORITY: usize = 0x0;
+const PLIC_ENABLE: usize = 0x2000;
+const PLIC_ENABLE_STRIDE: usize = 0x80;
+const PLIC_CONTEXT: usize = 0x20_0000;
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+const PLIC_THRESHOLD: usize = 0x0;
+const PLIC_CLAIM: usize = 0x4;
+
+fn plic_reg(offset: usize) -> *mut u32 {
+    phys_to_virt((PLIC_BASE + offset).into()).as_mut_ptr() as *mut u32
+}
+
+/// The hart each source is routed to.
+static TARGETS: [AtomicUsize; MAX_IRQ_COUNT] = {
+    const HART0: AtomicUsize = AtomicUsize::new(0);
+    [HART0; MAX_IRQ_COUNT]
+};
+
+/// The PLIC context of supervisor mode on `hart`.
+const fn context_of(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+/// The PLIC context of supervisor mode on this hart.
+fn plic_context() -> usize {
+    context_of(axhal::cpu::_this_cpu_id())
+}
+
+/// Sets or clears the enable bit of the source `irq` for `ctx`, and lets
+/// `ctx` take the sources of any priority.
+fn write_enable(ctx: usize, irq: usize, enabled: bool) {
+    let enable = plic_reg(PLIC_ENABLE + ctx * PLIC_ENABLE_STRIDE + (irq / 32) * 4);
+    unsafe {
+        let bits = enable.read_volatile();
+        if enabled {
+            enable.write_volatile(bits | (1 << (irq % 32)));
+        } else {
+            enable.write_volatile(bits & !(1 << (irq % 32)));
+        }
+        plic_reg(PLIC_CONTEXT + ctx * PLIC_CONTEXT_STRIDE + PLIC_THRESHOLD).write_volatile(0);
+    }
+}
+
+/// Unmasks or masks the source `irq`, routed to its hart, hart 0 unless
+/// [`set_affinity`] moved it.
+pub fn set_enable(irq: usize, enabled: bool) {
+    let ctx = context_of(TARGETS[irq].load(Ordering::Acquire));
+    unsafe { plic_reg(PLIC_PRIORITY + irq * 4).write_volatile(enabled as u32) };
+    write_enable(ctx, irq, enabled);
+}
+
+/// Routes the source `irq` to `cpu`, moving its enable bit if it is
+/// unmasked.
+pub fn set_affinity(irq: usize, cpu: usize) -> LinuxResult {
+    let old = TARGETS[irq].swap(cpu, Ordering::AcqRel);
+    let enabled = unsafe { plic_reg(PLIC_PRIORITY + irq * 4).read_volatile() } != 0;
+    if old != cpu && enabled {
+        write_enable(context_of(cpu), irq, true);
+        write_enable(context_of(old), irq, false);
+    }
+    Ok(())
+}
+
+/// Claims the pending source of the highest priority for this hart, if
+/// there is one. It is not raised again until it is completed.
+pub fn claim() -> Option<usize> {
+    let claim = plic_reg(PLIC_CONTEXT + plic_context() * PLIC_CONTEXT_STRIDE + PLIC_CLAIM);
+    match unsafe { claim.read_volatile() } {
+        0 => None,
+        irq => Some(irq as usize),
+    }
+}
+
+/// Tells the PLIC the source `irq` claimed has been handled.
+pub fn complete(irq: usize) {
+    let claim = plic_reg(PLIC_CONTEXT + plic_context() * PLIC_CONTEXT_STRIDE + PLIC_CLAIM);
+    unsafe { claim.write_volatile(irq as u32) };
+}
+
+/// The PLIC has no MSIs, the devices raise their INTx lines.
+pub fn alloc_msi() -> Option<MsiMsg> {
+    None
+}
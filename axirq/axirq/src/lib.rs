@@ -0,0 +1,267 @@
+//! Generic IRQ handling.
+//!
+//! Each IRQ line has a descriptor, with the actions of the drivers that
+//! requested it by [`request_irq`]: one, or many if all of them request it
+//! as [`IrqFlags::SHARED`], which are all run on an interrupt for each to
+//! tell whether its device raised it. A line is unmasked at the interrupt
+//! controller with its first action, and masked with its last, or while
+//! [`disable_irq`] holds it. How many times each line has fired on each
+//! CPU is counted, for /proc/interrupts.
+//!
+//! A line is routed to the first CPU of its affinity, CPU 0 unless
+//! [`set_irq_affinity`] changed it, so that the interrupts of a device can
+//! be kept off the CPU of a task that is sensitive to latency.
+//!
+//! PCI devices may raise an IRQ by an MSI instead of a line, on the
+//! interrupt controllers that take them, from [`alloc_msi`].
+//!
+//! The trap handler calls [`generic_handle_irq`] with the number of the
+//! line; on RISC-V and AArch64, it claims those pending at the PLIC or the
+//! GIC with [`claim`], and completes them with [`complete`].
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod chip;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use axerrno::{LinuxError, LinuxResult};
+use spinbase::SpinNoIrq;
+
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
+pub use chip::{claim, complete};
+#[cfg(target_arch = "aarch64")]
+pub use chip::irq_of;
+pub use chip::alloc_msi;
+
+/// IRQ lines there are descriptors for.
+pub const NR_IRQS: usize = axhal::platform::irq::MAX_IRQ_COUNT;
+
+const SMP: usize = axconfig::SMP;
+
+/// The mask of all the CPUs.
+const ALL_CPUS: usize = usize::MAX >> (usize::BITS as usize - SMP);
+
+/// Whether a handler has handled the interrupt, of its device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// Not raised by its device.
+    None,
+    Handled,
+}
+
+bitflags::bitflags! {
+    /// Flags of [`request_irq`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IrqFlags: usize {
+        /// The line may be shared with others that request it so.
+        const SHARED = 0x80;
+        /// The line is left masked, for [`enable_irq`] to unmask.
+        const NO_AUTOEN = 0x80000;
+    }
+}
+
+/// The IRQ an MSI raises, and the message a PCI device writes for it.
+#[derive(Clone, Copy, Debug)]
+pub struct MsiMsg {
+    pub irq: usize,
+    pub address: u64,
+    pub data: u32,
+}
+
+/// A handler of an IRQ, called with its number and the `dev_id` it was
+/// requested with, with interrupts off.
+pub type IrqHandler = fn(irq: usize, dev_id: usize) -> IrqReturn;
+
+struct IrqAction {
+    handler: IrqHandler,
+    flags: IrqFlags,
+    name: String,
+    dev_id: usize,
+}
+
+struct IrqDesc {
+    actions: SpinNoIrq<Vec<IrqAction>>,
+    /// Nesting of [`disable_irq`]; the line is unmasked at 0 only.
+    depth: AtomicUsize,
+    /// Interrupts on each CPU.
+    counts: [AtomicUsize; SMP],
+    /// The CPUs it may be routed to.
+    affinity: AtomicUsize,
+}
+
+impl IrqDesc {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: Self = {
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            actions: SpinNoIrq::new(Vec::new()),
+            depth: AtomicUsize::new(0),
+            counts: [ZERO; SMP],
+            affinity: AtomicUsize::new(ALL_CPUS),
+        }
+    };
+
+    fn count(&self) -> usize {
+        self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+}
+
+static IRQ_DESCS: [IrqDesc; NR_IRQS] = [IrqDesc::NEW; NR_IRQS];
+
+fn irq_desc(irq: usize) -> LinuxResult<&'static IrqDesc> {
+    IRQ_DESCS.get(irq).ok_or(LinuxError::EINVAL)
+}
+
+/// Adds `handler` to the actions of the line `irq`, as `name`, and
+/// unmasks it if it is the first one, unless `flags` has
+/// [`IrqFlags::NO_AUTOEN`]. Fails with EBUSY if the line is taken, by
+/// actions that do not share it or by one that won't share it.
+pub fn request_irq(
+    irq: usize, handler: IrqHandler, flags: IrqFlags, name: &str, dev_id: usize
+) -> LinuxResult {
+    let desc = irq_desc(irq)?;
+    let mut actions = desc.actions.lock();
+    if let Some(first) = actions.first() {
+        if !first.flags.contains(IrqFlags::SHARED) || !flags.contains(IrqFlags::SHARED) {
+            warn!("IRQ {}: {} can't share it with {}", irq, name, first.name);
+            return Err(LinuxError::EBUSY);
+        }
+    }
+    info!("IRQ {}: requested by {}", irq, name);
+    actions.push(IrqAction { handler, flags, name: name.into(), dev_id });
+    if actions.len() == 1 {
+        if flags.contains(IrqFlags::NO_AUTOEN) {
+            desc.depth.store(1, Ordering::Release);
+        } else if desc.depth.load(Ordering::Acquire) == 0 {
+            chip::set_enable(irq, true);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the action of `dev_id` from the line `irq`, and masks it if it
+/// was the last one.
+pub fn free_irq(irq: usize, dev_id: usize) {
+    let Ok(desc) = irq_desc(irq) else {
+        return;
+    };
+    let mut actions = desc.actions.lock();
+    let Some(i) = actions.iter().position(|a| a.dev_id == dev_id) else {
+        warn!("IRQ {}: freeing a free action {:#x}", irq, dev_id);
+        return;
+    };
+    actions.remove(i);
+    if actions.is_empty() {
+        chip::set_enable(irq, false);
+        desc.depth.store(0, Ordering::Release);
+    }
+}
+
+/// Masks the line `irq`, until as many [`enable_irq`] as there were
+/// these. Waits for its handlers running on other CPUs, so it must not be
+/// called from one of them.
+pub fn disable_irq(irq: usize) {
+    let Ok(desc) = irq_desc(irq) else {
+        return;
+    };
+    let actions = desc.actions.lock();
+    if desc.depth.fetch_add(1, Ordering::AcqRel) == 0 && !actions.is_empty() {
+        chip::set_enable(irq, false);
+    }
+}
+
+/// Undoes a [`disable_irq`], unmasking the line `irq` with the last one.
+pub fn enable_irq(irq: usize) {
+    let Ok(desc) = irq_desc(irq) else {
+        return;
+    };
+    let actions = desc.actions.lock();
+    match desc.depth.load(Ordering::Acquire) {
+        0 => warn!("IRQ {}: unbalanced enable", irq),
+        depth => {
+            desc.depth.store(depth - 1, Ordering::Release);
+            if depth == 1 && !actions.is_empty() {
+                chip::set_enable(irq, true);
+            }
+        }
+    }
+}
+
+/// Routes the line `irq` to the CPUs of `cpumask`, to the first of them.
+/// EINVAL if none of them is a CPU, EIO if the controller can't route it.
+pub fn set_irq_affinity(irq: usize, cpumask: usize) -> LinuxResult {
+    let desc = irq_desc(irq)?;
+    let cpumask = cpumask & ALL_CPUS;
+    if cpumask == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let _actions = desc.actions.lock();
+    chip::set_affinity(irq, cpumask.trailing_zeros() as usize)?;
+    desc.affinity.store(cpumask, Ordering::Release);
+    info!("IRQ {}: affinity {:#x}", irq, cpumask);
+    Ok(())
+}
+
+/// The CPUs the line `irq` may be routed to.
+pub fn irq_affinity(irq: usize) -> LinuxResult<usize> {
+    Ok(irq_desc(irq)?.affinity.load(Ordering::Acquire))
+}
+
+/// Runs the actions of the line `irq`, which has fired on this CPU.
+/// Returns whether one of them handled it.
+pub fn generic_handle_irq(irq: usize) -> bool {
+    let Ok(desc) = irq_desc(irq) else {
+        return false;
+    };
+    desc.counts[axhal::cpu::_this_cpu_id()].fetch_add(1, Ordering::Relaxed);
+    let mut handled = false;
+    for action in desc.actions.lock().iter() {
+        if (action.handler)(irq, action.dev_id) == IrqReturn::Handled {
+            handled = true;
+        }
+    }
+    handled
+}
+
+/// Interrupts of the line `irq` on each CPU.
+pub fn kstat_irqs(irq: usize) -> [usize; SMP] {
+    let mut counts = [0; SMP];
+    if let Ok(desc) = irq_desc(irq) {
+        for (n, c) in counts.iter_mut().zip(desc.counts.iter()) {
+            *n = c.load(Ordering::Relaxed);
+        }
+    }
+    counts
+}
+
+/// The lines requested or that have fired, with their counts on each
+/// CPU, the controller and the names of their actions, as
+/// /proc/interrupts shows them.
+pub fn show_interrupts() -> String {
+    let mut s = String::from("    ");
+    for cpu in 0..SMP {
+        let _ = write!(s, " {:>10}", alloc::format!("CPU{}", cpu));
+    }
+    s.push('\n');
+    for (irq, desc) in IRQ_DESCS.iter().enumerate() {
+        let actions = desc.actions.lock();
+        if actions.is_empty() && desc.count() == 0 {
+            continue;
+        }
+        let _ = write!(s, "{:>3}:", irq);
+        for c in desc.counts.iter() {
+            let _ = write!(s, " {:>10}", c.load(Ordering::Relaxed));
+        }
+        let _ = write!(s, "  {:<8}", chip::CHIP_NAME);
+        let names: Vec<&str> = actions.iter().map(|a| a.name.as_str()).collect();
+        let _ = writeln!(s, " {}", names.join(", "));
+    }
+    s
+}
@@ -0,0 +1,236 @@
+//! The kernel log buffer.
+//!
+//! Each record logged is also kept here, with a sequence number and the
+//! time it was logged, until [`LOG_BUF_RECORDS`] newer ones overwrite it,
+//! so that the log can be read back after the console has scrolled: by
+//! dmesg through syslog(2), or from /proc/kmsg. The buffer is static, as
+//! logging starts before the heap, and a message longer than
+//! [`LOG_LINE_MAX`] bytes is truncated.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::time::Duration;
+use log::Level;
+use spinbase::SpinNoIrq;
+
+/// Records kept at most.
+pub const LOG_BUF_RECORDS: usize = 512;
+/// Bytes kept of a message at most.
+pub const LOG_LINE_MAX: usize = 256;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    seq: u64,
+    time: Duration,
+    level: Level,
+    len: usize,
+    text: [u8; LOG_LINE_MAX],
+}
+
+impl Slot {
+    const EMPTY: Self = Self {
+        seq: 0,
+        time: Duration::ZERO,
+        level: Level::Info,
+        len: 0,
+        text: [0; LOG_LINE_MAX],
+    };
+}
+
+/// Writes into a slot, and drops what does not fit, at a char boundary.
+struct SlotWriter<'a>(&'a mut Slot);
+
+impl Write for SlotWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let slot = &mut *self.0;
+        let mut n = s.len().min(LOG_LINE_MAX - slot.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        slot.text[slot.len..slot.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        slot.len += n;
+        Ok(())
+    }
+}
+
+struct LogBuf {
+    slots: [Slot; LOG_BUF_RECORDS],
+    /// Sequence number of the next record.
+    next_seq: u64,
+    /// Records before it are cleared for [`read_all`].
+    clear_seq: u64,
+    /// Records before it have been taken by [`read_unread`].
+    read_seq: u64,
+}
+
+impl LogBuf {
+    fn first_seq(&self) -> u64 {
+        self.next_seq.saturating_sub(LOG_BUF_RECORDS as u64)
+    }
+}
+
+impl From<&Slot> for LogRecord {
+    fn from(slot: &Slot) -> Self {
+        Self {
+            seq: slot.seq,
+            time: slot.time,
+            level: slot.level,
+            text: String::from_utf8_lossy(&slot.text[..slot.len]).into(),
+        }
+    }
+}
+
+static LOG_BUF: SpinNoIrq<LogBuf> = SpinNoIrq::new(LogBuf {
+    slots: [Slot::EMPTY; LOG_BUF_RECORDS],
+    next_seq: 0,
+    clear_seq: 0,
+    read_seq: 0,
+});
+
+/// A record of the kernel log.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub seq: u64,
+    /// The time since boot it was logged at.
+    pub time: Duration,
+    pub level: Level,
+    pub text: String,
+}
+
+/// The syslog priority of `level`, as `KERN_ERR` and so on.
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+impl LogRecord {
+    /// The syslog priority of the level, as `KERN_ERR` and so on.
+    pub fn priority(&self) -> u8 {
+        priority(self.level)
+    }
+
+    /// The line of it that syslog(2) and /proc/kmsg give:
+    /// `<prio>[secs.usecs] text\n`.
+    pub fn to_line(&self) -> String {
+        format!(
+            "<{}>[{:>5}.{:06}] {}\n",
+            self.priority(),
+            self.time.as_secs(),
+            self.time.subsec_micros(),
+            self.text,
+        )
+    }
+}
+
+/// Appends a record, overwriting the oldest one if the buffer is full.
+pub(crate) fn store(level: Level, time: Duration, args: fmt::Arguments) {
+    let mut buf = LOG_BUF.lock();
+    let seq = buf.next_seq;
+    let slot = &mut buf.slots[seq as usize % LOG_BUF_RECORDS];
+    slot.seq = seq;
+    slot.time = time;
+    slot.level = level;
+    slot.len = 0;
+    let _ = SlotWriter(slot).write_fmt(args);
+    buf.next_seq += 1;
+}
+
+/// Sequence number of the oldest record still in the buffer.
+pub fn first_seq() -> u64 {
+    LOG_BUF.lock().first_seq()
+}
+
+/// Sequence number the next record will have.
+pub fn next_seq() -> u64 {
+    LOG_BUF.lock().next_seq
+}
+
+/// The records from the sequence number `since` tells on, of those still
+/// in the buffer. Nothing is allocated with the buffer locked, as the
+/// allocator may log.
+fn records(since: impl Fn(&LogBuf) -> u64) -> Vec<LogRecord> {
+    let count = {
+        let buf = LOG_BUF.lock();
+        buf.next_seq - since(&buf).max(buf.first_seq())
+    };
+    let mut slots = Vec::with_capacity(count as usize);
+    {
+        let buf = LOG_BUF.lock();
+        // Newer records may have come meanwhile, which wait for next time.
+        let first = since(&buf).max(buf.first_seq());
+        for seq in (first..buf.next_seq).take(count as usize) {
+            slots.push(buf.slots[seq as usize % LOG_BUF_RECORDS]);
+        }
+    }
+    slots.iter().map(LogRecord::from).collect()
+}
+
+/// The records from `since_seq` on, of those still in the buffer.
+pub fn read_log(since_seq: u64) -> Vec<LogRecord> {
+    records(|_| since_seq)
+}
+
+/// The records since the buffer was last cleared, as dmesg shows them.
+pub fn read_all() -> Vec<LogRecord> {
+    records(|buf| buf.clear_seq)
+}
+
+/// Takes the records no one has taken yet with it, as many whole ones as
+/// there are bytes in `limit` for their lines, but at least one.
+pub fn read_unread(limit: usize) -> Vec<LogRecord> {
+    let mut records = records(|buf| buf.read_seq);
+    let mut size = 0;
+    let count = records.iter()
+        .take_while(|r| {
+            size += r.to_line().len();
+            size <= limit
+        })
+        .count()
+        .max(1);
+    records.truncate(count);
+    if let Some(last) = records.last() {
+        let mut buf = LOG_BUF.lock();
+        buf.read_seq = buf.read_seq.max(last.seq + 1);
+    }
+    records
+}
+
+/// Bytes of the lines [`read_unread`] has not taken yet.
+pub fn unread_size() -> usize {
+    records(|buf| buf.read_seq).iter().map(|r| r.to_line().len()).sum()
+}
+
+/// Calls `f` with the priority, the time and the text of each record still
+/// in the buffer, oldest first, without allocating: for the crash dump,
+/// which can't count on the heap. Returns false, without calling it, if
+/// the buffer is locked, as it may be by the panicking CPU. `f` must not
+/// log.
+pub fn try_for_each_record(mut f: impl FnMut(u8, Duration, &[u8])) -> bool {
+    let Some(buf) = LOG_BUF.try_lock() else {
+        return false;
+    };
+    for seq in buf.first_seq()..buf.next_seq {
+        let slot = &buf.slots[seq as usize % LOG_BUF_RECORDS];
+        f(priority(slot.level), slot.time, &slot.text[..slot.len]);
+    }
+    true
+}
+
+/// Bytes the lines of a full buffer may take.
+pub const fn buffer_size() -> usize {
+    // The prefix of a line is 19 bytes until 100000 seconds of uptime.
+    LOG_BUF_RECORDS * (LOG_LINE_MAX + 32)
+}
+
+/// Clears the buffer for [`read_all`], as `dmesg -c` does. The records
+/// stay for [`read_log`].
+pub fn clear() {
+    let mut buf = LOG_BUF.lock();
+    buf.clear_seq = buf.next_seq;
+}
@@ -26,6 +26,8 @@
 //! - `log-level-warn`, `log-level-info`, `log-level-debug`, `log-level-trace`:
 //!   Similar to `log-level-error`.
 //!
+//! Records logged are kept in the [`kmsg`] buffer as well.
+//!
 //! # Examples
 //!
 //! ```
@@ -49,6 +51,9 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate log;
+extern crate alloc;
+
+pub mod kmsg;
 
 use core::fmt::{self, Write};
 use core::str::FromStr;
@@ -144,6 +149,7 @@ impl Log for Logger {
 
         cfg_if::cfg_if! {
             if #[cfg(feature = "std")] {
+                kmsg::store(level, core::time::Duration::ZERO, *record.args());
                 __print_impl(with_color!(
                     ColorCode::White,
                     "[{time} {path}:{line}] {args}\n",
@@ -156,6 +162,7 @@ impl Log for Logger {
                 let cpu_id = early_console::cpu_id();
                 let tid = early_console::task_id();
                 let now = early_console::time();
+                kmsg::store(level, now, *record.args());
                 if let Some(cpu_id) = cpu_id {
                     if let Some(tid) = tid {
                         // show CPU ID and task ID
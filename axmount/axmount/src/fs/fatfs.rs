@@ -2,22 +2,72 @@ use alloc::sync::Arc;
 use core::cell::UnsafeCell;
 
 use rust_fatfs as fatfs;
-use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{FileSystemInfo, VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
-use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
+use fatfs::{Date, DateTime, Dir, File, LossyOemCpConverter, Read, Seek, SeekFrom, Time, TimeProvider, Write};
 
+use axtype::FS_NAME_LEN;
 use mutex::Mutex;
 use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
+const MSDOS_SUPER_MAGIC: u64 = 0x4d44;
+
+/// Years that a FAT timestamp can hold.
+const FAT_MIN_YEAR: i64 = 1980;
+const FAT_MAX_YEAR: i64 = 2107;
+
+/// Stamps the directory entries with the wall clock of the kernel, in UTC,
+/// so that files written here have sane times on the host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelTimeProvider;
+
+impl TimeProvider for KernelTimeProvider {
+    fn get_current_date(&self) -> Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        let secs = timekeeping::ktime_get_real_coarse().as_secs() as i64;
+        let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+        if year < FAT_MIN_YEAR {
+            return DateTime::new(Date::new(FAT_MIN_YEAR as u16, 1, 1), Time::new(0, 0, 0, 0));
+        }
+        if year > FAT_MAX_YEAR {
+            return DateTime::new(Date::new(FAT_MAX_YEAR as u16, 12, 31), Time::new(23, 59, 59, 999));
+        }
+        let secs = secs.rem_euclid(86400) as u16;
+        DateTime::new(
+            Date::new(year as u16, month, day),
+            Time::new(secs / 3600, secs / 60 % 60, secs % 60, 0),
+        )
+    }
+}
+
+/// (year, month, day) of the `days`th day since 1970-01-01, in the
+/// proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u16, u16) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u16;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month as u16, day)
+}
 
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    inner: fatfs::FileSystem<Disk, KernelTimeProvider, LossyOemCpConverter>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
+type FatFile<'a> = File<'a, Disk, KernelTimeProvider, LossyOemCpConverter>;
+
+pub struct FileWrapper<'a>(Mutex<FatFile<'a>>);
+pub struct DirWrapper<'a>(Dir<'a, Disk, KernelTimeProvider, LossyOemCpConverter>);
 
 unsafe impl Sync for FatFileSystem {}
 unsafe impl Send for FatFileSystem {}
@@ -31,7 +81,8 @@ impl FatFileSystem {
     pub fn new(mut disk: Disk) -> Self {
         let opts = fatfs::FormatVolumeOptions::new();
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let opts = fatfs::FsOptions::new().time_provider(KernelTimeProvider);
+        let inner = fatfs::FileSystem::new(disk, opts)
             .expect("failed to initialize FAT filesystem");
         Self {
             inner,
@@ -45,7 +96,8 @@ impl FatFileSystem {
             let opts = fatfs::FormatVolumeOptions::new();
             fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
         }
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let opts = fatfs::FsOptions::new().time_provider(KernelTimeProvider);
+        let inner = fatfs::FileSystem::new(disk, opts)
             .expect("failed to initialize FAT filesystem");
         Self {
             inner,
@@ -58,11 +110,11 @@ impl FatFileSystem {
         unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
     }
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
+    fn new_file(file: FatFile<'_>) -> Arc<FileWrapper> {
         Arc::new(FileWrapper(Mutex::new(file)))
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
+    fn new_dir(dir: Dir<'_, Disk, KernelTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
         Arc::new(DirWrapper(dir))
     }
 }
@@ -86,15 +138,41 @@ impl VfsNodeOps for FileWrapper<'static> {
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
-        file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.write(buf).map_err(as_vfs_err)
+        extend_to(&mut file, offset)?;
+        let len = file.write(buf).map_err(as_vfs_err)?;
+        // Write the size and mtime back to the directory entry now, rather
+        // than when the last reference goes.
+        file.flush().map_err(as_vfs_err)?;
+        Ok(len)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
-        file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.truncate().map_err(as_vfs_err)
+        extend_to(&mut file, size)?;
+        file.truncate().map_err(as_vfs_err)?;
+        #[allow(deprecated)]
+        file.set_modified(KernelTimeProvider.get_current_date_time());
+        file.flush().map_err(as_vfs_err)
+    }
+}
+
+/// Seeks `file` to `offset`. fatfs doesn't seek past the end of a file, so
+/// a file shorter than that is first extended with zeroes, which allocates
+/// the clusters in between.
+fn extend_to(file: &mut FatFile, offset: u64) -> VfsResult {
+    let size = file.seek(SeekFrom::End(0)).map_err(as_vfs_err)?;
+    if offset <= size {
+        file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
+        return Ok(());
+    }
+    let zeroes = [0u8; BLOCK_SIZE];
+    let mut left = offset - size;
+    while left > 0 {
+        let len = left.min(BLOCK_SIZE as u64) as usize;
+        file.write_all(&zeroes[..len]).map_err(as_vfs_err)?;
+        left -= len as u64;
     }
+    Ok(())
 }
 
 impl VfsNodeOps for DirWrapper<'static> {
@@ -204,6 +282,31 @@ impl VfsNodeOps for DirWrapper<'static> {
 }
 
 impl VfsOps for FatFileSystem {
+    fn umount(&self) -> VfsResult {
+        // Write the free cluster count back and mark the volume clean.
+        self.inner.flush().map_err(as_vfs_err)
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        let stats = self.inner.stats().map_err(as_vfs_err)?;
+        Ok(FileSystemInfo {
+            f_type: MSDOS_SUPER_MAGIC,
+            f_bsize: stats.cluster_size() as u64,
+            f_blocks: stats.total_clusters() as u64,
+            f_bfree: stats.free_clusters() as u64,
+            f_bavail: stats.free_clusters() as u64,
+            f_namelen: FS_NAME_LEN as u64,
+            f_frsize: stats.cluster_size() as u64,
+            ..Default::default()
+        })
+    }
+
+    fn sync(&self) -> VfsResult {
+        // The files flush their entries as they are written, so only the
+        // FS info is left.
+        self.inner.flush().map_err(as_vfs_err)
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
         root_dir.clone()
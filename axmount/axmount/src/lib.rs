@@ -22,8 +22,12 @@ mod fs;
 mod mounts;
 
 use axdriver::{prelude::*, AxDeviceContainer};
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
 use lazy_init::LazyInit;
+use spinbase::SpinNoIrq;
 use axfs_vfs::VfsOps;
 use axfs_vfs::RootDirectory;
 //use procfs::{ProcFileSystem, init_procfs};
@@ -40,13 +44,66 @@ cfg_if::cfg_if! {
     }
 }
 
-/// Initializes the main filesystem using available block devices.
-pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>, _need_fmt: bool) -> FsType {
+/// The root device given with `root=`, as "vda2" or "/dev/vda2".
+static ROOT_DEV: SpinNoIrq<Option<String>> = SpinNoIrq::new(None);
+
+fn root_dev_setup(value: &str) -> LinuxResult {
+    let name = value.strip_prefix("/dev/").unwrap_or(value);
+    if name.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    *ROOT_DEV.lock() = Some(name.into());
+    Ok(())
+}
+cmdline::kernel_param!("root", root_dev_setup);
+
+/// Registers the block devices with their partitions, and initializes the
+/// main filesystem on the one named by `root=` on the command line, or
+/// `AX_ROOT` it was built with (e.g. "vda2"), or else on
+/// the first partition of the first disk, or the whole disk if it has none.
+/// Without block devices, it falls back to the disks registered before,
+/// such as RAM disks. `blk_irq` is the IRQ line of the first disk, if it is
+/// known.
+pub fn init_filesystems(
+    mut blk_devs: AxDeviceContainer<AxBlockDevice>,
+    blk_irq: Option<usize>,
+    _need_fmt: bool,
+) -> FsType {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    let disk = axdriver::Disk::new(dev);
+    let mut root = None;
+    let mut index = 0;
+    while let Some(dev) = blk_devs.take_one() {
+        let name = disk_name(dev.device_name(), index);
+        info!("  block device {}: {:?} as {}", index, dev.device_name(), name);
+        let irq = if index == 0 { blk_irq } else { None };
+        axdriver::partition::add_disk(&name, dev, irq);
+        if index == 0 {
+            // Partitions come before their disk.
+            root = axdriver::partition::partitions()
+                .into_iter()
+                .find(|part| part.name().starts_with(name.as_str()))
+                .map(|part| part.name().into());
+        }
+        index += 1;
+    }
+    let root = ROOT_DEV.lock().clone()
+        .or_else(|| {
+            option_env!("AX_ROOT")
+                .filter(|name| !name.is_empty())
+                .map(|name| name.into())
+        })
+        .or(root)
+        .or_else(|| {
+            axdriver::partition::partitions()
+                .first()
+                .map(|part| part.name().into())
+        })
+        .expect("No block device found!");
+    let part = axdriver::partition::lookup_partition(&root)
+        .unwrap_or_else(|| panic!("No root device {}!", root));
+    info!("  use {} as the root device", part.name());
+    let disk = axdriver::Disk::from_partition(part);
 
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
@@ -64,6 +121,16 @@ pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>, _need_fm
     main_fs
 }
 
+/// Names the `index`-th disk as Linux does: vda, vdb... for virtio, and
+/// ram0, ram1... for RAM disks.
+fn disk_name(device_name: &str, index: usize) -> String {
+    let letter = (b'a' + index as u8) as char;
+    match device_name {
+        "ramdisk" => format!("ram{}", index),
+        name if name.starts_with("virtio") => format!("vd{}", letter),
+        _ => format!("sd{}", letter),
+    }
+}
 
 /// Initializes and configures the root filesystem with various mount points.
 pub fn init_rootfs(main_fs: Arc<dyn VfsOps>) -> Arc<RootDirectory> {
@@ -101,12 +168,28 @@ pub fn init_rootfs(main_fs: Arc<dyn VfsOps>) -> Arc<RootDirectory> {
     Arc::new(root_dir)
 }
 
+/// Seeds CLOCK_REALTIME from the RTC, before any file is stamped.
+fn init_wall_clock() {
+    let Some(nanos) = axdriver::rtc::read_time() else {
+        warn!("rtc: none found, realtime starts at the Epoch");
+        return;
+    };
+    if let Err(e) = timekeeping::do_settimeofday(axhal::time::TimeValue::from_nanos(nanos)) {
+        warn!("rtc: bad time {}ns: {:?}", nanos, e);
+    }
+}
+
 /// Initializes the entire filesystem hierarchy.
-pub fn init(_cpu_id: usize, _dtb_pa: usize) {
+pub fn init(_cpu_id: usize, dtb_pa: usize) {
     axconfig::init_once!();
 
-    let all_devices = axdriver::init_drivers2();
-    let main_fs = init_filesystems(all_devices.block, false);
+    let all_devices = axdriver::init_drivers2(dtb_pa);
+    init_wall_clock();
+    netdev::init(all_devices.net, all_devices.net_irq);
+    axdriver::hvc::init(all_devices.char);
+    fbdev::init(all_devices.display, dtb_pa);
+    axdriver::brd::add_disks(dtb_pa);
+    let main_fs = init_filesystems(all_devices.block, all_devices.block_irq, false);
     INIT_ROOT.init_by(init_rootfs(main_fs));
 }
 
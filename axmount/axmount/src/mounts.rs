@@ -9,13 +9,20 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     let gid = 0;
     let null = fs::devfs::NullDev;
     let zero = fs::devfs::ZeroDev;
+    let full = fs::devfs::FullDev;
     let console = fs::devfs::ConsoleDev;
     let bar = fs::devfs::ZeroDev;
     let devfs = fs::devfs::DeviceFileSystem::new();
     let foo_dir = devfs.mkdir("foo", uid, gid);
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
+    devfs.add("full", Arc::new(full));
     devfs.add("console", Arc::new(console));
+    devfs.add("random", Arc::new(random::RandomDev::new(true)));
+    devfs.add("urandom", Arc::new(random::RandomDev::new(false)));
+    if fbdev::is_present() {
+        devfs.add("fb0", Arc::new(fbdev::FbDev));
+    }
 
     foo_dir.add("bar", Arc::new(bar));
     devfs.mkdir("shm", uid, gid);
@@ -8,6 +8,7 @@ use core::str::from_utf8;
 use core::panic::PanicInfo;
 use axfs_vfs::VfsNodeType;
 use axfile::fops::{File, OpenOptions};
+use axtype::Cred;
 
 /// Entry
 #[no_mangle]
@@ -58,7 +59,7 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
 
     let mut opts = OpenOptions::new();
     opts.read(true);
-    let mut rfile = File::open(fname, &opts, &locked_fs, 0, 0).unwrap();
+    let mut rfile = File::open(fname, &opts, &locked_fs, &Cred::root()).unwrap();
     let mut buf = [0u8; 256];
     match rfile.read(&mut buf) {
         Ok(len) => {
@@ -46,7 +46,7 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, _dtb_pa: usize) {
         let mut disk = alldevs.block.take_one().unwrap();
         let mut disk = AxDeviceContainer::from_one(disk);
 
-        let main_fs = axmount::init_filesystems(disk, false);
+        let main_fs = axmount::init_filesystems(disk, None, false);
         let root_dir = axmount::init_rootfs(main_fs);
         let mut fs = FsStruct::new();
         fs.init(root_dir);
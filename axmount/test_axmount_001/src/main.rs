@@ -41,7 +41,7 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, _dtb_pa: usize) {
         let disk = alldevs.block.take_one().unwrap();
         let disk = AxDeviceContainer::from_one(disk);
 
-        let main_fs = axmount::init_filesystems(disk, false);
+        let main_fs = axmount::init_filesystems(disk, None, false);
         let root_dir = axmount::init_rootfs(main_fs);
         let mut fs = FsStruct::new();
         fs.init(root_dir);
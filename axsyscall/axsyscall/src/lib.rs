@@ -14,12 +14,32 @@ use axhal::arch::fault_in_readable;
 #[macro_use]
 extern crate log;
 
+pub mod strace;
+
 const MAX_SYSCALL_ARGS: usize = 6;
 pub type SyscallArgs = [usize; MAX_SYSCALL_ARGS];
 
 pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
+    if strace::should_trace(&args, sysno) {
+        return strace::trace_syscall(args, sysno, dispatch);
+    }
+    dispatch(args, sysno)
+}
+
+fn dispatch(args: SyscallArgs, sysno: usize) -> usize {
     match sysno {
+        LINUX_SYSCALL_SETXATTR => linux_syscall_setxattr(args, true),
+        LINUX_SYSCALL_LSETXATTR => linux_syscall_setxattr(args, false),
+        LINUX_SYSCALL_FSETXATTR => linux_syscall_fsetxattr(args),
+        LINUX_SYSCALL_GETXATTR => linux_syscall_getxattr(args, true),
+        LINUX_SYSCALL_LGETXATTR => linux_syscall_getxattr(args, false),
         LINUX_SYSCALL_FGETXATTR => linux_syscall_fgetxattr(args),
+        LINUX_SYSCALL_LISTXATTR => linux_syscall_listxattr(args, true),
+        LINUX_SYSCALL_LLISTXATTR => linux_syscall_listxattr(args, false),
+        LINUX_SYSCALL_FLISTXATTR => linux_syscall_flistxattr(args),
+        LINUX_SYSCALL_REMOVEXATTR => linux_syscall_removexattr(args, true),
+        LINUX_SYSCALL_LREMOVEXATTR => linux_syscall_removexattr(args, false),
+        LINUX_SYSCALL_FREMOVEXATTR => linux_syscall_fremovexattr(args),
         LINUX_SYSCALL_IOCTL => linux_syscall_ioctl(args),
         LINUX_SYSCALL_FCNTL => linux_syscall_fcntl(args),
         LINUX_SYSCALL_GETCWD => linux_syscall_getcwd(args),
@@ -33,9 +53,17 @@ pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
         LINUX_SYSCALL_STATFS => linux_syscall_statfs(args),
         LINUX_SYSCALL_DUP => linux_syscall_dup(args),
         LINUX_SYSCALL_DUP3 => linux_syscall_dup3(args),
+        #[cfg(target_arch = "x86_64")]
+        LINUX_SYSCALL_DUP2 => linux_syscall_dup2(args),
         LINUX_SYSCALL_OPENAT => linux_syscall_openat(args),
         LINUX_SYSCALL_CLOSE => linux_syscall_close(args),
         LINUX_SYSCALL_PIPE2 => linux_syscall_pipe2(args),
+        #[cfg(target_arch = "x86_64")]
+        LINUX_SYSCALL_EVENTFD => linux_syscall_eventfd(args),
+        LINUX_SYSCALL_EVENTFD2 => linux_syscall_eventfd2(args),
+        LINUX_SYSCALL_TIMERFD_CREATE => linux_syscall_timerfd_create(args),
+        LINUX_SYSCALL_TIMERFD_SETTIME => linux_syscall_timerfd_settime(args),
+        LINUX_SYSCALL_TIMERFD_GETTIME => linux_syscall_timerfd_gettime(args),
         LINUX_SYSCALL_LSEEK => linux_syscall_lseek(args),
         LINUX_SYSCALL_READ => linux_syscall_read(args),
         LINUX_SYSCALL_PREAD64 => linux_syscall_pread64(args),
@@ -46,8 +74,20 @@ pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
         LINUX_SYSCALL_UTIMENSAT => linux_syscall_utimensat(args),
         LINUX_SYSCALL_FTRUNCATE => linux_syscall_ftruncate(args),
         LINUX_SYSCALL_FALLOCATE => linux_syscall_fallocate(args),
+        LINUX_SYSCALL_FADVISE64 => linux_syscall_fadvise64(args),
+        LINUX_SYSCALL_SYNC => linux_syscall_sync(args),
+        LINUX_SYSCALL_FSYNC => linux_syscall_fsync(args),
+        LINUX_SYSCALL_FDATASYNC => linux_syscall_fsync(args),
+        LINUX_SYSCALL_SYNCFS => linux_syscall_fsync(args),
         LINUX_SYSCALL_FSTATAT => linux_syscall_fstatat(args),
         LINUX_SYSCALL_UNAME => linux_syscall_uname(args),
+        LINUX_SYSCALL_SETHOSTNAME => linux_syscall_sethostname(args),
+        LINUX_SYSCALL_SETDOMAINNAME => linux_syscall_setdomainname(args),
+        LINUX_SYSCALL_INIT_MODULE => linux_syscall_init_module(args),
+        LINUX_SYSCALL_FINIT_MODULE => linux_syscall_finit_module(args),
+        LINUX_SYSCALL_DELETE_MODULE => linux_syscall_delete_module(args),
+        LINUX_SYSCALL_KEXEC_LOAD => linux_syscall_kexec_load(args),
+        LINUX_SYSCALL_REBOOT => linux_syscall_reboot(args),
         LINUX_SYSCALL_UMASK => linux_syscall_umask(args),
         LINUX_SYSCALL_BRK => linux_syscall_brk(args),
         LINUX_SYSCALL_RSEQ => linux_syscall_rseq(args),
@@ -56,6 +96,10 @@ pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
         LINUX_SYSCALL_MUNMAP => linux_syscall_munmap(args),
         LINUX_SYSCALL_MREMAP => linux_syscall_mremap(args),
         LINUX_SYSCALL_MMAP => linux_syscall_mmap(args),
+        LINUX_SYSCALL_SHMGET => linux_syscall_shmget(args),
+        LINUX_SYSCALL_SHMAT => linux_syscall_shmat(args),
+        LINUX_SYSCALL_SHMDT => linux_syscall_shmdt(args),
+        LINUX_SYSCALL_SHMCTL => linux_syscall_shmctl(args),
         LINUX_SYSCALL_MSYNC => linux_syscall_msync(args),
         LINUX_SYSCALL_MADVISE => linux_syscall_madvise(args),
         LINUX_SYSCALL_MPROTECT => linux_syscall_mprotect(args),
@@ -63,9 +107,17 @@ pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
         LINUX_SYSCALL_SET_ROBUST_LIST => linux_syscall_set_robust_list(args),
         LINUX_SYSCALL_WAIT4 => linux_syscall_wait4(args),
         LINUX_SYSCALL_PRLIMIT64 => linux_syscall_prlimit64(args),
+        LINUX_SYSCALL_GETRLIMIT => linux_syscall_getrlimit(args),
+        LINUX_SYSCALL_SETRLIMIT => linux_syscall_setrlimit(args),
         LINUX_SYSCALL_GETRANDOM => linux_syscall_getrandom(args),
+        LINUX_SYSCALL_CLOCK_SETTIME => linux_syscall_clock_settime(args),
         LINUX_SYSCALL_CLOCK_GETTIME => linux_syscall_clock_gettime(args),
+        LINUX_SYSCALL_CLOCK_GETRES => linux_syscall_clock_getres(args),
+        LINUX_SYSCALL_GETTIMEOFDAY => linux_syscall_gettimeofday(args),
+        LINUX_SYSCALL_SETTIMEOFDAY => linux_syscall_settimeofday(args),
+        LINUX_SYSCALL_GETCPU => linux_syscall_getcpu(args),
         LINUX_SYSCALL_CLOCK_NANOSLEEP => linux_syscall_clock_nanosleep(args),
+        LINUX_SYSCALL_SYSLOG => linux_syscall_syslog(args),
         LINUX_SYSCALL_RT_SIGPROCMASK => linux_syscall_rt_sigprocmask(args),
         LINUX_SYSCALL_RT_SIGACTION => linux_syscall_rt_sigaction(args),
         LINUX_SYSCALL_RT_SIGRETURN => linux_syscall_rt_sigreturn(args),
@@ -75,13 +127,25 @@ pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
         LINUX_SYSCALL_SETGID => linux_syscall_setgid(args),
         LINUX_SYSCALL_SETREUID => linux_syscall_setreuid(args),
         LINUX_SYSCALL_SETRESUID => linux_syscall_setresuid(args),
+        LINUX_SYSCALL_GETRESUID => linux_syscall_getresuid(args),
+        LINUX_SYSCALL_SETREGID => linux_syscall_setregid(args),
+        LINUX_SYSCALL_SETRESGID => linux_syscall_setresgid(args),
+        LINUX_SYSCALL_GETRESGID => linux_syscall_getresgid(args),
+        LINUX_SYSCALL_GETGROUPS => linux_syscall_getgroups(args),
+        LINUX_SYSCALL_SETGROUPS => linux_syscall_setgroups(args),
         LINUX_SYSCALL_GETPPID => linux_syscall_getppid(args),
         LINUX_SYSCALL_GETGID => linux_syscall_getgid(args),
         LINUX_SYSCALL_GETEGID => linux_syscall_getegid(args),
         LINUX_SYSCALL_SETPGID => linux_syscall_setpgid(args),
+        LINUX_SYSCALL_GETPGID => linux_syscall_getpgid(args),
+        LINUX_SYSCALL_GETSID => linux_syscall_getsid(args),
+        LINUX_SYSCALL_SETSID => linux_syscall_setsid(args),
+        #[cfg(target_arch = "x86_64")]
+        LINUX_SYSCALL_GETPGRP => linux_syscall_getpgrp(args),
         LINUX_SYSCALL_GETUID => linux_syscall_getuid(args),
         LINUX_SYSCALL_GETEUID => linux_syscall_geteuid(args),
         LINUX_SYSCALL_KILL => linux_syscall_kill(args),
+        LINUX_SYSCALL_PTRACE => linux_syscall_ptrace(args),
         LINUX_SYSCALL_TGKILL => linux_syscall_tgkill(args),
         LINUX_SYSCALL_EXIT => linux_syscall_exit(args),
         LINUX_SYSCALL_EXIT_GROUP => linux_syscall_exit_group(args),
@@ -96,7 +160,22 @@ pub fn do_syscall(args: SyscallArgs, sysno: usize) -> usize {
         LINUX_SYSCALL_MOUNT => linux_syscall_mount(args),
         LINUX_SYSCALL_UMOUNT2 => linux_syscall_umount2(args),
         LINUX_SYSCALL_SOCKET => linux_syscall_socket(args),
-        #[cfg(target_arch = "riscv64")]
+        LINUX_SYSCALL_SOCKETPAIR => linux_syscall_socketpair(args),
+        LINUX_SYSCALL_BIND => linux_syscall_bind(args),
+        LINUX_SYSCALL_LISTEN => linux_syscall_listen(args),
+        LINUX_SYSCALL_ACCEPT => linux_syscall_accept(args),
+        LINUX_SYSCALL_ACCEPT4 => linux_syscall_accept4(args),
+        LINUX_SYSCALL_CONNECT => linux_syscall_connect(args),
+        LINUX_SYSCALL_GETSOCKNAME => linux_syscall_getsockname(args),
+        LINUX_SYSCALL_GETPEERNAME => linux_syscall_getpeername(args),
+        LINUX_SYSCALL_SENDTO => linux_syscall_sendto(args),
+        LINUX_SYSCALL_RECVFROM => linux_syscall_recvfrom(args),
+        LINUX_SYSCALL_SENDMSG => linux_syscall_sendmsg(args),
+        LINUX_SYSCALL_RECVMSG => linux_syscall_recvmsg(args),
+        LINUX_SYSCALL_SETSOCKOPT => linux_syscall_setsockopt(args),
+        LINUX_SYSCALL_GETSOCKOPT => linux_syscall_getsockopt(args),
+        LINUX_SYSCALL_SHUTDOWN => linux_syscall_shutdown(args),
+        #[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
         LINUX_SYSCALL_GETDENTS64 => linux_syscall_getdents64(args),
         #[cfg(target_arch = "x86_64")]
         LINUX_SYSCALL_ACCESS => linux_syscall_access(args),
@@ -115,7 +194,7 @@ fn linux_syscall_faccessat(args: SyscallArgs) -> usize {
         dfd, filename, mode
     );
     let filename = get_user_str(filename);
-    fileops::faccessat(dfd, &filename)
+    fileops::faccessat(dfd, &filename, mode)
 }
 
 fn linux_syscall_sched_getaffinity(args: SyscallArgs) -> usize {
@@ -209,7 +288,9 @@ fn linux_syscall_symlinkat(args: SyscallArgs) -> usize {
 fn linux_syscall_statfs(args: SyscallArgs) -> usize {
     let [path, buf, ..] = args;
     let path = get_user_str(path);
-    fileops::statfs(&path, buf)
+    fileops::statfs(&path, buf).unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
 }
 
 fn linux_syscall_openat(args: SyscallArgs) -> usize {
@@ -231,12 +312,24 @@ fn linux_syscall_openat(args: SyscallArgs) -> usize {
 
 fn linux_syscall_dup(args: SyscallArgs) -> usize {
     let [fd, ..] = args;
-    fileops::dup(fd)
+    fileops::dup(fd).unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+fn linux_syscall_dup2(args: SyscallArgs) -> usize {
+    let [oldfd, newfd, ..] = args;
+    fileops::dup2(oldfd, newfd).unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
 }
 
 fn linux_syscall_dup3(args: SyscallArgs) -> usize {
     let [oldfd, newfd, flags, ..] = args;
-    fileops::dup3(oldfd, newfd, flags)
+    fileops::dup3(oldfd, newfd, flags).unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
 }
 
 fn linux_syscall_close(args: SyscallArgs) -> usize {
@@ -259,6 +352,37 @@ fn linux_syscall_pipe2(args: SyscallArgs) -> usize {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+fn linux_syscall_eventfd(args: SyscallArgs) -> usize {
+    let [initval, ..] = args;
+    eventfd::eventfd2(initval, 0)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_eventfd2(args: SyscallArgs) -> usize {
+    let [initval, flags, ..] = args;
+    eventfd::eventfd2(initval, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_timerfd_create(args: SyscallArgs) -> usize {
+    let [clockid, flags, ..] = args;
+    timerfd::timerfd_create(clockid, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_timerfd_settime(args: SyscallArgs) -> usize {
+    let [fd, flags, new_value, old_value, ..] = args;
+    timerfd::timerfd_settime(fd, flags, new_value, old_value)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_timerfd_gettime(args: SyscallArgs) -> usize {
+    let [fd, curr_value, ..] = args;
+    timerfd::timerfd_gettime(fd, curr_value)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
 fn linux_syscall_lseek(args: SyscallArgs) -> usize {
     let [fd, offset, whence, ..] = args;
     fileops::lseek(fd, offset, whence)
@@ -291,7 +415,7 @@ fn linux_syscall_sendfile(args: SyscallArgs) -> usize {
     fileops::sendfile(out_fd, in_fd, offset, count)
 }
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 fn linux_syscall_getdents64(args: SyscallArgs) -> usize {
     let [fd, dirp, count, ..] = args;
     fileops::getdents64(fd, dirp, count)
@@ -351,6 +475,27 @@ fn linux_syscall_fallocate(args: SyscallArgs) -> usize {
         })
 }
 
+fn linux_syscall_fadvise64(args: SyscallArgs) -> usize {
+    let [fd, offset, len, advice, ..] = args;
+    fileops::fadvise64(fd, offset, len, advice)
+        .unwrap_or_else(|e| {
+            linux_err_from!(e)
+        })
+}
+
+fn linux_syscall_sync(_args: SyscallArgs) -> usize {
+    fileops::sync().unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
+}
+
+fn linux_syscall_fsync(args: SyscallArgs) -> usize {
+    let [fd, ..] = args;
+    fileops::fsync(fd).unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
+}
+
 fn linux_syscall_utimensat(args: SyscallArgs) -> usize {
     let [dfd, filename, times, flags, ..] = args;
     let filename = get_user_str(filename);
@@ -383,6 +528,30 @@ fn linux_syscall_munmap(args: SyscallArgs) -> usize {
     mmap::munmap(va, len)
 }
 
+fn linux_syscall_shmget(args: SyscallArgs) -> usize {
+    let [key, size, shmflg, ..] = args;
+    shm::shmget(key as i32, size, shmflg)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_shmat(args: SyscallArgs) -> usize {
+    let [shmid, addr, shmflg, ..] = args;
+    shm::shmat(shmid, addr, shmflg)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_shmdt(args: SyscallArgs) -> usize {
+    let [addr, ..] = args;
+    shm::shmdt(addr)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_shmctl(args: SyscallArgs) -> usize {
+    let [shmid, cmd, buf, ..] = args;
+    shm::shmctl(shmid, cmd, buf)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
 fn linux_syscall_mremap(args: SyscallArgs) -> usize {
     let [oaddr, osize, nsize, flags, naddr, ..] = args;
     mmap::mremap(oaddr, osize, nsize, flags, naddr)
@@ -406,17 +575,69 @@ fn linux_syscall_ioctl(args: SyscallArgs) -> usize {
         })
 }
 
+fn linux_syscall_setxattr(args: SyscallArgs, follow: bool) -> usize {
+    let [path, name, value, size, flags, ..] = args;
+    let path = get_user_str(path);
+    let name = get_user_str(name);
+    fileops::setxattr(0, Some(&path), follow, &name, value, size, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_fsetxattr(args: SyscallArgs) -> usize {
+    let [fd, name, value, size, flags, ..] = args;
+    let name = get_user_str(name);
+    fileops::setxattr(fd, None, true, &name, value, size, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_getxattr(args: SyscallArgs, follow: bool) -> usize {
+    let [path, name, value, size, ..] = args;
+    let path = get_user_str(path);
+    let name = get_user_str(name);
+    fileops::getxattr(0, Some(&path), follow, &name, value, size)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
 fn linux_syscall_fgetxattr(args: SyscallArgs) -> usize {
     let [fd, name, value, size, ..] = args;
     let name = get_user_str(name);
-    warn!("impl linux_syscall_fgetxattr fd:{} {}->{} ({})",
-        fd, name, value, size);
-    linux_err!(EBADF)
+    fileops::getxattr(fd, None, true, &name, value, size)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_listxattr(args: SyscallArgs, follow: bool) -> usize {
+    let [path, list, size, ..] = args;
+    let path = get_user_str(path);
+    fileops::listxattr(0, Some(&path), follow, list, size)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_flistxattr(args: SyscallArgs) -> usize {
+    let [fd, list, size, ..] = args;
+    fileops::listxattr(fd, None, true, list, size)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_removexattr(args: SyscallArgs, follow: bool) -> usize {
+    let [path, name, ..] = args;
+    let path = get_user_str(path);
+    let name = get_user_str(name);
+    fileops::removexattr(0, Some(&path), follow, &name)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_fremovexattr(args: SyscallArgs) -> usize {
+    let [fd, name, ..] = args;
+    let name = get_user_str(name);
+    fileops::removexattr(fd, None, true, &name)
+        .unwrap_or_else(|e| linux_err_from!(e))
 }
 
 fn linux_syscall_fcntl(args: SyscallArgs) -> usize {
     let [fd, cmd, udata, ..] = args;
-    fileops::fcntl(fd, cmd, udata)
+    fileops::fcntl(fd, cmd, udata).unwrap_or_else(|e| {
+        linux_err_from!(e)
+    })
 }
 
 fn linux_syscall_getcwd(args: SyscallArgs) -> usize {
@@ -452,28 +673,65 @@ fn linux_syscall_prlimit64(args: SyscallArgs) -> usize {
     sys::prlimit64(pid, resource, new_rlim, old_rlim)
 }
 
+fn linux_syscall_getrlimit(args: SyscallArgs) -> usize {
+    let [resource, rlim, ..] = args;
+    sys::getrlimit(resource, rlim)
+}
+
+fn linux_syscall_setrlimit(args: SyscallArgs) -> usize {
+    let [resource, rlim, ..] = args;
+    sys::setrlimit(resource, rlim)
+}
+
 fn linux_syscall_wait4(args: SyscallArgs) -> usize {
     let [pid, wstatus, options, rusage, ..] = args;
     sys::wait4(pid, wstatus, options, rusage)
 }
 
 fn linux_syscall_getrandom(args: SyscallArgs) -> usize {
-    let [buf, len, _flags, ..] = args;
-    assert_eq!(len, 8);
-    let r = axhal::misc::random() as u64;
-    let ubuf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
-    ubuf.copy_from_slice(&r.to_le_bytes());
-    len
+    let [buf, len, flags, ..] = args;
+    random::getrandom(buf, len, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
 }
 
-fn linux_syscall_clock_gettime(_args: SyscallArgs) -> usize {
-    warn!("impl linux_syscall_clock_gettime");
-    0
+fn linux_syscall_clock_gettime(args: SyscallArgs) -> usize {
+    let [clockid, tp, ..] = args;
+    sys::clock_gettime(clockid, tp)
 }
 
-fn linux_syscall_clock_nanosleep(_args: SyscallArgs) -> usize {
-    warn!("impl linux_syscall_clock_nanosleep");
-    0
+fn linux_syscall_clock_settime(args: SyscallArgs) -> usize {
+    let [clockid, tp, ..] = args;
+    sys::clock_settime(clockid, tp)
+}
+
+fn linux_syscall_clock_getres(args: SyscallArgs) -> usize {
+    let [clockid, res, ..] = args;
+    sys::clock_getres(clockid, res)
+}
+
+fn linux_syscall_gettimeofday(args: SyscallArgs) -> usize {
+    let [tv, tz, ..] = args;
+    sys::gettimeofday(tv, tz)
+}
+
+fn linux_syscall_settimeofday(args: SyscallArgs) -> usize {
+    let [tv, tz, ..] = args;
+    sys::settimeofday(tv, tz)
+}
+
+fn linux_syscall_getcpu(args: SyscallArgs) -> usize {
+    let [cpu, node, ..] = args;
+    sys::getcpu(cpu, node)
+}
+
+fn linux_syscall_clock_nanosleep(args: SyscallArgs) -> usize {
+    let [clockid, flags, req, rem, ..] = args;
+    sys::clock_nanosleep(clockid, flags, req, rem)
+}
+
+fn linux_syscall_syslog(args: SyscallArgs) -> usize {
+    let [ty, buf, len, ..] = args;
+    sys::syslog(ty, buf, len)
 }
 
 fn linux_syscall_rt_sigprocmask(args: SyscallArgs) -> usize {
@@ -517,11 +775,41 @@ fn linux_syscall_setreuid(args: SyscallArgs) -> usize {
     sys::setreuid(ruid, euid)
 }
 
+fn linux_syscall_getresuid(args: SyscallArgs) -> usize {
+    let [ruid, euid, suid, ..] = args;
+    sys::getresuid(ruid, euid, suid)
+}
+
 fn linux_syscall_setgid(args: SyscallArgs) -> usize {
     let gid = args[0];
     sys::setgid(gid)
 }
 
+fn linux_syscall_setregid(args: SyscallArgs) -> usize {
+    let [rgid, egid, ..] = args;
+    sys::setregid(rgid, egid)
+}
+
+fn linux_syscall_setresgid(args: SyscallArgs) -> usize {
+    let [rgid, egid, sgid, ..] = args;
+    sys::setresgid(rgid, egid, sgid)
+}
+
+fn linux_syscall_getresgid(args: SyscallArgs) -> usize {
+    let [rgid, egid, sgid, ..] = args;
+    sys::getresgid(rgid, egid, sgid)
+}
+
+fn linux_syscall_getgroups(args: SyscallArgs) -> usize {
+    let [size, list, ..] = args;
+    sys::getgroups(size, list)
+}
+
+fn linux_syscall_setgroups(args: SyscallArgs) -> usize {
+    let [size, list, ..] = args;
+    sys::setgroups(size, list)
+}
+
 fn linux_syscall_getgid(_args: SyscallArgs) -> usize {
     sys::getgid()
 }
@@ -531,13 +819,11 @@ fn linux_syscall_getegid(_args: SyscallArgs) -> usize {
 }
 
 fn linux_syscall_geteuid(_args: SyscallArgs) -> usize {
-    warn!("impl linux_syscall_geteuid");
-    0
+    sys::geteuid()
 }
 
 fn linux_syscall_getuid(_args: SyscallArgs) -> usize {
-    warn!("impl linux_syscall_getuid");
-    0
+    sys::getuid()
 }
 
 fn linux_syscall_setpgid(args: SyscallArgs) -> usize {
@@ -545,6 +831,25 @@ fn linux_syscall_setpgid(args: SyscallArgs) -> usize {
     sys::setpgid(pid, pgid)
 }
 
+fn linux_syscall_getpgid(args: SyscallArgs) -> usize {
+    let [pid, ..] = args;
+    sys::getpgid(pid)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn linux_syscall_getpgrp(_args: SyscallArgs) -> usize {
+    sys::getpgid(0)
+}
+
+fn linux_syscall_getsid(args: SyscallArgs) -> usize {
+    let [pid, ..] = args;
+    sys::getsid(pid)
+}
+
+fn linux_syscall_setsid(_args: SyscallArgs) -> usize {
+    sys::setsid()
+}
+
 fn linux_syscall_tgkill(_args: SyscallArgs) -> usize {
     warn!("impl linux_syscall_tgkill");
     0
@@ -555,6 +860,11 @@ fn linux_syscall_kill(args: SyscallArgs) -> usize {
     signal::kill(pid, sig)
 }
 
+fn linux_syscall_ptrace(args: SyscallArgs) -> usize {
+    let [request, pid, addr, data, ..] = args;
+    ptrace::ptrace(request, pid, addr, data)
+}
+
 #[cfg(target_arch = "x86_64")]
 fn linux_syscall_arch_prctl(args: SyscallArgs) -> usize {
     let [code, addr, ..] = args;
@@ -578,27 +888,70 @@ fn linux_syscall_uname(args: SyscallArgs) -> usize {
     info!("uname: {:#x}", ptr);
 
     let uname = unsafe { (ptr as *mut utsname).as_mut().unwrap() };
+    let uts_ns = task::current().nsproxy.uts_ns.clone();
 
     init_bytes_from_str(&mut uname.sysname[..], "Linux");
-    init_bytes_from_str(&mut uname.nodename[..], "(none)");
+    init_bytes_from_slice(&mut uname.nodename[..], &uts_ns.nodename());
     init_bytes_from_str(&mut uname.release[..], "5.15.135+");
     init_bytes_from_str(
         &mut uname.version[..],
         "#98 SMP Wed Jul 17 09:12:19 UTC 2024",
     );
     init_bytes_from_str(&mut uname.machine[..], "riscv64");
-    init_bytes_from_str(&mut uname.domainname[..], "(none)");
+    init_bytes_from_slice(&mut uname.domainname[..], &uts_ns.domainname());
 
     return 0;
 }
 
+fn linux_syscall_sethostname(args: SyscallArgs) -> usize {
+    let [name, len, ..] = args;
+    sys::sethostname(name, len)
+}
+
+fn linux_syscall_setdomainname(args: SyscallArgs) -> usize {
+    let [name, len, ..] = args;
+    sys::setdomainname(name, len)
+}
+
+fn linux_syscall_init_module(args: SyscallArgs) -> usize {
+    let [umod, len, uargs, ..] = args;
+    module::init_module(umod, len, uargs)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_finit_module(args: SyscallArgs) -> usize {
+    let [fd, uargs, flags, ..] = args;
+    module::finit_module(fd, uargs, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_delete_module(args: SyscallArgs) -> usize {
+    let [name, flags, ..] = args;
+    module::delete_module(name, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_kexec_load(args: SyscallArgs) -> usize {
+    let [entry, nr_segments, segments, flags, ..] = args;
+    kexec::kexec_load(entry, nr_segments, segments, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_reboot(args: SyscallArgs) -> usize {
+    let [magic1, magic2, cmd, arg, ..] = args;
+    sys::reboot(magic1, magic2, cmd, arg)
+}
+
 fn linux_syscall_umask(args: SyscallArgs) -> usize {
     let mode = args[0] as u32;
     sys::do_umask(mode)
 }
 
 fn init_bytes_from_str(dst: &mut [u8], src: &str) {
-    let src = src.as_bytes();
+    init_bytes_from_slice(dst, src.as_bytes())
+}
+
+fn init_bytes_from_slice(dst: &mut [u8], src: &[u8]) {
     let (left, right) = dst.split_at_mut(src.len());
     left.copy_from_slice(src);
     right.fill(0);
@@ -622,7 +975,12 @@ fn linux_syscall_clone(args: SyscallArgs) -> usize {
 fn linux_syscall_execve(args: SyscallArgs) -> usize {
     let [path, argv, envp, ..] = args;
     let path = get_user_str(path);
-    exec::execve(&path, argv, envp)
+    let ret = exec::execve(&path, argv, envp);
+    if ret == 0 {
+        let tgid = taskctx::current_ctx().tgid();
+        ptrace::ptrace_event(ptrace::PTRACE_EVENT_EXEC, task::pid_vnr(tgid));
+    }
+    ret
 }
 
 fn linux_syscall_exit(args: SyscallArgs) -> usize {
@@ -664,9 +1022,114 @@ fn linux_syscall_umount2(_args: SyscallArgs) -> usize {
     0
 }
 
-fn linux_syscall_socket(_args: SyscallArgs) -> usize {
-    error!("linux_syscall_socket: unimplemented!");
-    linux_err!(EINVAL)
+fn linux_syscall_socket(args: SyscallArgs) -> usize {
+    let [domain, ty, protocol, ..] = args;
+    socket::socket(domain, ty, protocol)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_socketpair(args: SyscallArgs) -> usize {
+    let [domain, ty, protocol, sv, ..] = args;
+    socket::socketpair(domain, ty, protocol, sv)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_bind(args: SyscallArgs) -> usize {
+    let [fd, addr, addrlen, ..] = args;
+    socket::bind(fd, addr, addrlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_listen(args: SyscallArgs) -> usize {
+    let [fd, backlog, ..] = args;
+    socket::listen(fd, backlog)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_accept(args: SyscallArgs) -> usize {
+    let [fd, addr, addrlen, ..] = args;
+    socket::accept4(fd, addr, addrlen, 0)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_accept4(args: SyscallArgs) -> usize {
+    let [fd, addr, addrlen, flags, ..] = args;
+    socket::accept4(fd, addr, addrlen, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_connect(args: SyscallArgs) -> usize {
+    let [fd, addr, addrlen, ..] = args;
+    socket::connect(fd, addr, addrlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_getsockname(args: SyscallArgs) -> usize {
+    let [fd, addr, addrlen, ..] = args;
+    socket::getsockname(fd, addr, addrlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_getpeername(args: SyscallArgs) -> usize {
+    let [fd, addr, addrlen, ..] = args;
+    socket::getpeername(fd, addr, addrlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_sendto(args: SyscallArgs) -> usize {
+    let [fd, buf, len, flags, addr, addrlen] = args;
+    if len == 0 {
+        return socket::sendto(fd, &[], flags, addr, addrlen)
+            .unwrap_or_else(|e| linux_err_from!(e));
+    }
+    let err = fault_in_readable(buf, len);
+    if err != 0 {
+        return err;
+    }
+    let ubuf = unsafe { core::slice::from_raw_parts(buf as *const u8, len) };
+    socket::sendto(fd, ubuf, flags, addr, addrlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_recvfrom(args: SyscallArgs) -> usize {
+    let [fd, buf, len, flags, addr, addrlen] = args;
+    let err = axhal::arch::fault_in_writeable(buf, len);
+    if err != 0 {
+        return err;
+    }
+    let ubuf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+    socket::recvfrom(fd, ubuf, flags, addr, addrlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_sendmsg(args: SyscallArgs) -> usize {
+    let [fd, msg, flags, ..] = args;
+    socket::sendmsg(fd, msg, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_recvmsg(args: SyscallArgs) -> usize {
+    let [fd, msg, flags, ..] = args;
+    socket::recvmsg(fd, msg, flags)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_setsockopt(args: SyscallArgs) -> usize {
+    let [fd, level, name, optval, optlen, ..] = args;
+    socket::setsockopt(fd, level, name, optval, optlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_getsockopt(args: SyscallArgs) -> usize {
+    let [fd, level, name, optval, optlen, ..] = args;
+    socket::getsockopt(fd, level, name, optval, optlen)
+        .unwrap_or_else(|e| linux_err_from!(e))
+}
+
+fn linux_syscall_shutdown(args: SyscallArgs) -> usize {
+    let [fd, how, ..] = args;
+    socket::shutdown(fd, how)
+        .unwrap_or_else(|e| linux_err_from!(e))
 }
 
 pub fn getname(filename: usize) -> Result<String, usize> {
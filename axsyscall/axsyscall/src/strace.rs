@@ -0,0 +1,263 @@
+//! Tracing of the syscalls of chosen tasks, in the manner of strace.
+//!
+//! A task is traced while it has [`TIF_SYSCALL_TRACEPOINT`], which its
+//! children inherit at fork. It is set by [`set_task_trace`], or at the
+//! execve of a program named by the `strace=` parameter of the command
+//! line, a list separated by commas, so that one program and all it runs
+//! can be traced from boot.
+//!
+//! Each syscall of a traced task that passes the filter is recorded with
+//! its number, its arguments and its return value: into the ktrace ring
+//! buffer as the `sys_enter` and `sys_exit` events, or, with
+//! `strace_console` or [`set_console`], as a line on the console with the
+//! arguments decoded, as `openat(AT_FDCWD, "/etc/passwd", 0x0, 0) = -1
+//! ENOENT (No such file or directory)`. The filter takes all syscalls
+//! until `strace_filter=`, a list of names or numbers, or [`set_filter`]
+//! narrows it.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::sysno::*;
+use axlog2::ax_println;
+use axtype::get_user_str;
+use spinbase::SpinNoIrq;
+use taskctx::{Tid, TIF_SYSCALL_TRACEPOINT};
+
+use crate::SyscallArgs;
+
+/// Syscall numbers that the filter has room for.
+const NR_SYSCALLS: usize = 512;
+const FILTER_WORDS: usize = NR_SYSCALLS / 64;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ALL: AtomicU64 = AtomicU64::new(u64::MAX);
+/// The syscalls that are traced, a bit each; the others are let through.
+static FILTER: [AtomicU64; FILTER_WORDS] = [ALL; FILTER_WORDS];
+/// Whether to print the syscalls on the console rather than record them.
+static CONSOLE: AtomicBool = AtomicBool::new(false);
+/// The programs whose execve turns tracing on, by their file names.
+static PROGRAMS: SpinNoIrq<Vec<String>> = SpinNoIrq::new(Vec::new());
+
+const AT_FDCWD: i32 = -100;
+
+/// How an argument is printed.
+#[derive(Clone, Copy)]
+enum Arg {
+    Int,
+    Hex,
+    Oct,
+    /// A file descriptor, or AT_FDCWD.
+    Fd,
+    /// A NUL-terminated string of the user.
+    Str,
+}
+
+use Arg::*;
+
+/// The arguments of the syscalls that are decoded; the others are printed
+/// as the six of them in hex.
+fn arg_kinds(sysno: usize) -> Option<&'static [Arg]> {
+    let kinds: &[Arg] = match sysno {
+        LINUX_SYSCALL_READ | LINUX_SYSCALL_WRITE => &[Fd, Hex, Int],
+        LINUX_SYSCALL_PREAD64 => &[Fd, Hex, Int, Int],
+        LINUX_SYSCALL_WRITEV => &[Fd, Hex, Int],
+        LINUX_SYSCALL_OPENAT => &[Fd, Str, Hex, Oct],
+        LINUX_SYSCALL_CLOSE | LINUX_SYSCALL_DUP | LINUX_SYSCALL_FSYNC => &[Fd],
+        LINUX_SYSCALL_DUP3 => &[Fd, Fd, Hex],
+        LINUX_SYSCALL_LSEEK => &[Fd, Int, Int],
+        LINUX_SYSCALL_IOCTL => &[Fd, Hex, Hex],
+        LINUX_SYSCALL_FCNTL => &[Fd, Int, Hex],
+        LINUX_SYSCALL_FSTATAT => &[Fd, Str, Hex, Hex],
+        LINUX_SYSCALL_FACCESSAT => &[Fd, Str, Oct],
+        LINUX_SYSCALL_READLINKAT => &[Fd, Str, Hex, Int],
+        LINUX_SYSCALL_MKDIRAT => &[Fd, Str, Oct],
+        LINUX_SYSCALL_MKNODAT => &[Fd, Str, Oct, Hex],
+        LINUX_SYSCALL_UNLINKAT => &[Fd, Str, Hex],
+        LINUX_SYSCALL_SYMLINKAT => &[Str, Fd, Str],
+        LINUX_SYSCALL_LINKAT => &[Fd, Str, Fd, Str, Hex],
+        LINUX_SYSCALL_FCHMODAT => &[Fd, Str, Oct],
+        LINUX_SYSCALL_FCHOWNAT => &[Fd, Str, Int, Int, Hex],
+        LINUX_SYSCALL_UTIMENSAT => &[Fd, Str, Hex, Hex],
+        LINUX_SYSCALL_CHDIR | LINUX_SYSCALL_STATFS => &[Str, Hex],
+        LINUX_SYSCALL_GETCWD => &[Hex, Int],
+        LINUX_SYSCALL_EXECVE => &[Str, Hex, Hex],
+        LINUX_SYSCALL_EXIT | LINUX_SYSCALL_EXIT_GROUP => &[Int],
+        LINUX_SYSCALL_WAIT4 => &[Int, Hex, Hex, Hex],
+        LINUX_SYSCALL_KILL => &[Int, Int],
+        LINUX_SYSCALL_TGKILL => &[Int, Int, Int],
+        LINUX_SYSCALL_BRK => &[Hex],
+        LINUX_SYSCALL_MMAP => &[Hex, Int, Hex, Hex, Fd, Hex],
+        LINUX_SYSCALL_MUNMAP => &[Hex, Int],
+        LINUX_SYSCALL_MPROTECT => &[Hex, Int, Hex],
+        LINUX_SYSCALL_MOUNT => &[Str, Str, Str, Hex, Hex],
+        LINUX_SYSCALL_UMOUNT2 => &[Str, Hex],
+        #[cfg(target_arch = "x86_64")]
+        LINUX_SYSCALL_ACCESS => &[Str, Oct],
+        #[cfg(target_arch = "x86_64")]
+        LINUX_SYSCALL_DUP2 => &[Fd, Fd],
+        _ => return None,
+    };
+    Some(kinds)
+}
+
+/// Traces the syscalls of task `tid`, and of the children it makes from
+/// now on, or stops.
+pub fn set_task_trace(tid: Tid, on: bool) -> LinuxResult {
+    let task = task::get_task(tid).ok_or(LinuxError::ESRCH)?;
+    if on {
+        task.sched_info.set_tsk_thread_flag(TIF_SYSCALL_TRACEPOINT);
+    } else {
+        task.sched_info.clear_tsk_thread_flag(TIF_SYSCALL_TRACEPOINT);
+    }
+    Ok(())
+}
+
+/// Traces only the syscalls in `sysnos`, or all of them if it is empty.
+pub fn set_filter(sysnos: &[usize]) -> LinuxResult {
+    if sysnos.iter().any(|&sysno| sysno >= NR_SYSCALLS) {
+        return Err(LinuxError::EINVAL);
+    }
+    let fill = if sysnos.is_empty() { u64::MAX } else { 0 };
+    for word in FILTER.iter() {
+        word.store(fill, Ordering::Relaxed);
+    }
+    for &sysno in sysnos {
+        FILTER[sysno / 64].fetch_or(1 << (sysno % 64), Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Prints the traced syscalls on the console if `on`, or records them into
+/// the ring buffer.
+pub fn set_console(on: bool) {
+    CONSOLE.store(on, Ordering::Release);
+}
+
+fn filtered(sysno: usize) -> bool {
+    sysno < NR_SYSCALLS && FILTER[sysno / 64].load(Ordering::Relaxed) & (1 << (sysno % 64)) != 0
+}
+
+/// Turns tracing on for the current task if it is to run a program of
+/// `strace=`.
+fn check_program(path: usize) {
+    if PROGRAMS.lock().is_empty() {
+        return;
+    }
+    // Read out of the lock, as it may fault the page in.
+    let path = get_user_str(path);
+    let name = path.rsplit('/').next().unwrap_or_default();
+    if PROGRAMS.lock().iter().any(|p| p == name) {
+        taskctx::current_ctx().set_tsk_thread_flag(TIF_SYSCALL_TRACEPOINT);
+    }
+}
+
+/// Whether syscall `sysno` of the current task is to be traced.
+pub(crate) fn should_trace(args: &SyscallArgs, sysno: usize) -> bool {
+    if sysno == LINUX_SYSCALL_EXECVE {
+        check_program(args[0]);
+    }
+    taskctx::current_ctx().test_tsk_thread_flag(TIF_SYSCALL_TRACEPOINT) && filtered(sysno)
+}
+
+fn format_arg(out: &mut String, kind: Arg, val: usize) {
+    let _ = match kind {
+        Int => write!(out, "{}", val as isize),
+        Hex => write!(out, "{:#x}", val),
+        Oct => write!(out, "{:#o}", val),
+        Fd if val as i32 == AT_FDCWD => write!(out, "AT_FDCWD"),
+        Fd => write!(out, "{}", val as i32),
+        Str if val == 0 => write!(out, "NULL"),
+        Str => write!(out, "{:?}", get_user_str(val)),
+    };
+}
+
+/// The call as strace prints it, up to the closing parenthesis. The strings
+/// are read before the syscall, as it may change them, or the whole
+/// address space for execve.
+fn format_call(args: &SyscallArgs, sysno: usize) -> String {
+    let mut out = match syscall_name(sysno) {
+        Some(name) => format!("{}(", name),
+        None => format!("syscall_{:#x}(", sysno),
+    };
+    let kinds = arg_kinds(sysno).unwrap_or(&[Hex; 6]);
+    for (i, (&kind, &val)) in kinds.iter().zip(args.iter()).enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        format_arg(&mut out, kind, val);
+    }
+    out.push(')');
+    out
+}
+
+fn format_ret(ret: usize) -> String {
+    let errno = -(ret as isize);
+    if (1..4096).contains(&errno) {
+        match LinuxError::try_from(errno as i32) {
+            Ok(e) => format!("-1 {:?} ({})", e, e.as_str()),
+            Err(_) => format!("-1 errno {}", errno),
+        }
+    } else {
+        format!("{:#x}", ret)
+    }
+}
+
+/// Runs syscall `sysno` by `dispatch`, tracing it.
+pub(crate) fn trace_syscall(
+    args: SyscallArgs, sysno: usize, dispatch: fn(SyscallArgs, usize) -> usize
+) -> usize {
+    let tid = taskctx::current_ctx().tid();
+    if !CONSOLE.load(Ordering::Acquire) {
+        ktrace::trace_event!(sys_enter, nr = sysno, a0 = args[0], a1 = args[1], a2 = args[2]);
+        let ret = dispatch(args, sysno);
+        ktrace::trace_event!(sys_exit, nr = sysno, ret = ret);
+        return ret;
+    }
+    let call = format_call(&args, sysno);
+    if sysno == LINUX_SYSCALL_EXIT || sysno == LINUX_SYSCALL_EXIT_GROUP {
+        // Which doesn't return.
+        ax_println!("[{}] {} = ?", tid, call);
+        return dispatch(args, sysno);
+    }
+    let ret = dispatch(args, sysno);
+    ax_println!("[{}] {} = {}", tid, call, format_ret(ret));
+    ret
+}
+
+fn parse_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn strace_setup(value: &str) -> LinuxResult {
+    let mut programs = PROGRAMS.lock();
+    programs.clear();
+    programs.extend(parse_list(value).map(String::from));
+    Ok(())
+}
+cmdline::kernel_param!("strace", strace_setup);
+
+/// The number of syscall `name`, which may be given as a number.
+fn parse_sysno(name: &str) -> LinuxResult<usize> {
+    if let Ok(sysno) = name.parse::<usize>() {
+        return Ok(sysno);
+    }
+    (0..NR_SYSCALLS)
+        .find(|&sysno| syscall_name(sysno) == Some(name))
+        .ok_or(LinuxError::EINVAL)
+}
+
+fn strace_filter_setup(value: &str) -> LinuxResult {
+    let sysnos = parse_list(value).map(parse_sysno).collect::<LinuxResult<Vec<_>>>()?;
+    set_filter(&sysnos)
+}
+cmdline::kernel_param!("strace_filter", strace_filter_setup);
+
+fn strace_console_setup(value: &str) -> LinuxResult {
+    set_console(value.is_empty() || cmdline::parse_bool(value)?);
+    Ok(())
+}
+cmdline::kernel_param!("strace_console", strace_console_setup);
@@ -0,0 +1,178 @@
+use aarch64_cpu::registers::{ESR_EL1, FAR_EL1};
+use tock_registers::interfaces::Readable;
+use axhal::arch::TrapFrame;
+use axhal::arch::user_mode;
+use axhal::arch::{EXC_INST_PAGE_FAULT, EXC_LOAD_PAGE_FAULT, EXC_STORE_PAGE_FAULT};
+use axsyscall::SyscallArgs;
+use preempt_guard::NoPreempt;
+use mmap::{VM_FAULT_SIGBUS, VM_FAULT_OOM, VM_FAULT_ERROR};
+use signal::force_sig_fault;
+use task::{SIGBUS, BUS_ADRERR};
+
+axhal::include_asm_marcos!();
+
+/// The exception class of ESR_EL1 for SVC.
+const EXC_SYSCALL: usize = 0x15;
+
+/// WnR of the syndrome of a data abort: the fault is of a write.
+const ISS_DABT_WNR: u64 = 1 << 6;
+
+core::arch::global_asm!(include_str!("trap.S"));
+extern "C" {
+    fn exception_vector_base();
+}
+
+#[repr(u8)]
+#[derive(Debug)]
+#[allow(dead_code)]
+enum TrapKind {
+    Synchronous = 0,
+    Irq = 1,
+    Fiq = 2,
+    SError = 3,
+}
+
+#[repr(u8)]
+#[derive(Debug)]
+#[allow(dead_code)]
+enum TrapSource {
+    CurrentSpEl0 = 0,
+    CurrentSpElx = 1,
+    LowerAArch64 = 2,
+    LowerAArch32 = 3,
+}
+
+/// Writes Vector Base Address Register (`VBAR_EL1`).
+#[inline]
+pub fn init_trap() {
+    axhal::arch::set_exception_vector_base(exception_vector_base as usize);
+}
+
+#[no_mangle]
+fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
+    panic!(
+        "Invalid exception {:?} from {:?}:\n{:#x?}",
+        kind, source, tf
+    );
+}
+
+#[no_mangle]
+fn handle_sync_exception(tf: &mut TrapFrame) {
+    let esr = ESR_EL1.extract();
+    match esr.read_as_enum(ESR_EL1::EC) {
+        Some(ESR_EL1::EC::Value::Brk64) => handle_breakpoint(tf),
+        Some(ESR_EL1::EC::Value::SVC64) => handle_linux_syscall(tf),
+        Some(ESR_EL1::EC::Value::InstrAbortLowerEL)
+        | Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => {
+            handle_page_fault(FAR_EL1.get() as usize, EXC_INST_PAGE_FAULT, tf);
+        }
+        Some(ESR_EL1::EC::Value::DataAbortLowerEL)
+        | Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => {
+            let cause = if esr.read(ESR_EL1::ISS) & ISS_DABT_WNR != 0 {
+                EXC_STORE_PAGE_FAULT
+            } else {
+                EXC_LOAD_PAGE_FAULT
+            };
+            handle_page_fault(FAR_EL1.get() as usize, cause, tf);
+        }
+        _ => {
+            panic!(
+                "Unhandled synchronous exception @ {:#x}: ESR={:#x} (EC {:#08b}, ISS {:#x}):\n{:#x?}",
+                tf.elr,
+                esr.get(),
+                esr.read(ESR_EL1::EC),
+                esr.read(ESR_EL1::ISS),
+                tf,
+            );
+        }
+    }
+}
+
+#[no_mangle]
+fn handle_irq_exception(tf: &mut TrapFrame) {
+    handle_irq_extern(tf)
+}
+
+/// Call page fault handler.
+fn handle_page_fault(badaddr: usize, cause: usize, tf: &mut TrapFrame) {
+    debug!("handle_page_fault... cause {:#x}, elr {:#x}", cause, tf.elr);
+    let mut fixup = 0;
+    if let Err(fault) = mmap::faultin_page(badaddr, cause, tf.elr as usize, &mut fixup) {
+        debug!("fault: {:#x}", fault);
+        if fault == usize::MAX {
+            if fixup != 0 {
+                assert!(!user_mode());
+                tf.elr = fixup as u64;
+            }
+        } else if (fault & VM_FAULT_ERROR) != 0 {
+            mm_fault_error(badaddr, fault);
+        }
+    }
+    signal::do_signal(tf, cause);
+}
+
+#[inline]
+fn mm_fault_error(addr: usize, fault: usize) {
+    if (fault & VM_FAULT_OOM) != 0 {
+        unimplemented!("VM_FAULT_OOM");
+    } else if (fault & VM_FAULT_SIGBUS) != 0 {
+        let tid = task::current().tid();
+        error!("VM_FAULT_SIGBUS");
+        /* Kernel mode? Handle exceptions or die */
+        force_sig_fault(tid, SIGBUS, BUS_ADRERR, addr);
+        return;
+    }
+    unimplemented!("mm_fault_error!");
+}
+
+/// Call the external IRQ handler. The GIC tells which IRQ it is.
+fn handle_irq_extern(tf: &mut TrapFrame) {
+    let guard = NoPreempt::new();
+    let from_user = user_mode();
+    crate::set_irq_from_user(from_user);
+    softirq::irq_enter();
+    crate::platform::irq::dispatch_irq(0);
+    softirq::irq_exit();
+    // Preempt on the way out, if the interrupted context allows it.
+    drop(guard); // rescheduling may occur when preemption is re-enabled.
+    // Out of IRQ context now, signals may block or exit the task.
+    if from_user {
+        pm::try_to_freeze();
+        signal::do_signal(tf, 0);
+    }
+}
+
+/// Probes are not supported on AArch64 yet, so it is a `brk` of the code.
+fn handle_breakpoint(tf: &mut TrapFrame) {
+    debug!("Exception(Breakpoint) @ {:#x} ", tf.elr);
+    tf.elr += 4
+}
+
+fn handle_linux_syscall(tf: &mut TrapFrame) {
+    debug!("handle_linux_syscall");
+    syscall(tf, axsyscall::do_syscall);
+    pm::try_to_freeze();
+    signal::do_signal(tf, EXC_SYSCALL);
+}
+
+fn syscall_args(tf: &TrapFrame) -> SyscallArgs {
+    [
+        tf.r[0], tf.r[1], tf.r[2], tf.r[3], tf.r[4], tf.r[5],
+    ].map(|x| x as usize)
+}
+
+fn syscall<F>(tf: &mut TrapFrame, do_syscall: F)
+where
+    F: FnOnce(SyscallArgs, usize) -> usize,
+{
+    warn!("Syscall: {:#x}, {}, {:#x}", tf.r[8], tf.r[8], tf.elr);
+    // ELR_EL1 is already past `svc`. The number stays in x8, for the
+    // syscall to be restarted. A tracer may change it, or skip the syscall
+    // with -1.
+    ptrace::report_syscall_entry();
+    if tf.r[8] != u64::MAX {
+        let args = syscall_args(tf);
+        tf.r[0] = do_syscall(args, tf.r[8] as usize) as u64;
+    }
+    ptrace::report_syscall_exit();
+}
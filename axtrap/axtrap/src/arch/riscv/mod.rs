@@ -32,7 +32,7 @@ pub fn init_trap() {
 pub fn riscv_trap_handler(tf: &mut TrapFrame, _from_user: bool) {
     let scause = scause::read();
     match scause.cause() {
-        Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
+        Trap::Exception(E::Breakpoint) => handle_breakpoint(tf),
         Trap::Exception(E::UserEnvCall) => handle_linux_syscall(tf),
         Trap::Exception(E::InstructionPageFault) => {
             handle_page_fault(stval::read(), scause.code(), tf);
@@ -88,21 +88,34 @@ fn mm_fault_error(addr: usize, fault: usize) {
 }
 
 /// Call the external IRQ handler.
-fn handle_irq_extern(irq_num: usize, _tf: &mut TrapFrame) {
-    let _ = NoPreempt::new();
+fn handle_irq_extern(irq_num: usize, tf: &mut TrapFrame) {
+    let guard = NoPreempt::new();
+    let from_user = user_mode();
+    crate::set_irq_from_user(from_user);
+    softirq::irq_enter();
     crate::platform::irq::dispatch_irq(irq_num);
-    // Todo: why we cannot do_signal here (irq context -> userland).
-    //drop(guard); // rescheduling may occur when preemption is re-enabled.
+    softirq::irq_exit();
+    // Preempt on the way out, if the interrupted context allows it.
+    drop(guard); // rescheduling may occur when preemption is re-enabled.
+    // Out of IRQ context now, signals may block or exit the task.
+    if from_user {
+        pm::try_to_freeze();
+        signal::do_signal(tf, irq_num);
+    }
 }
 
-fn handle_breakpoint(sepc: &mut usize) {
-    debug!("Exception(Breakpoint) @ {:#x} ", sepc);
-    *sepc += 2
+fn handle_breakpoint(tf: &mut TrapFrame) {
+    if !user_mode() && kprobes::handle_breakpoint(tf) {
+        return;
+    }
+    debug!("Exception(Breakpoint) @ {:#x} ", tf.sepc);
+    tf.sepc += 2
 }
 
 fn handle_linux_syscall(tf: &mut TrapFrame) {
     debug!("handle_linux_syscall");
     syscall(tf, axsyscall::do_syscall);
+    pm::try_to_freeze();
     signal::do_signal(tf, EXC_SYSCALL);
 }
 
@@ -117,10 +130,15 @@ where
     F: FnOnce(SyscallArgs, usize) -> usize,
 {
     warn!("Syscall: {:#x}, {}, {:#x}", tf.regs.a7, tf.regs.a7, tf.sepc);
-    let args = syscall_args(tf);
     // Note: "tf.sepc += 4;" must be put before do_syscall. Or:
     // E.g., when we do clone, child task will call clone again
     // and cause strange behavior.
     tf.sepc += 4;
-    tf.regs.a0 = do_syscall(args, tf.regs.a7);
+    // A tracer may change the syscall, or skip it with -1.
+    ptrace::report_syscall_entry();
+    if tf.regs.a7 != usize::MAX {
+        let args = syscall_args(tf);
+        tf.regs.a0 = do_syscall(args, tf.regs.a7);
+    }
+    ptrace::report_syscall_exit();
 }
@@ -67,7 +67,7 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
                 tf.rip, tf.error_code, tf
             );
         }
-        IRQ_VECTOR_START..=IRQ_VECTOR_END => handle_irq_extern(tf.vector as _),
+        IRQ_VECTOR_START..=IRQ_VECTOR_END => handle_irq_extern(tf.vector as _, tf.is_user()),
         _ => {
             panic!(
                 "Unhandled exception {} (error_code = {:#x}) @ {:#x}:\n{:#x?}",
@@ -75,9 +75,9 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
             );
         }
     }
-    #[cfg(feature = "signal")]
     if tf.is_user() {
-        crate::trap::handle_signal();
+        pm::try_to_freeze();
+        signal::do_signal(tf, tf.vector as usize);
     }
 }
 /// Call page fault handler.
@@ -87,9 +87,13 @@ fn handle_page_fault(badaddr: usize, cause: usize) {
 }
 
 /// Call the external IRQ handler.
-fn handle_irq_extern(irq_num: usize) {
+fn handle_irq_extern(irq_num: usize, user: bool) {
     debug!("handle_irq_extern irq: {:#X} ...", irq_num);
-    let _ = NoPreempt::new();
+    let guard = NoPreempt::new();
+    crate::set_irq_from_user(user);
+    softirq::irq_enter();
     crate::platform::irq::dispatch_irq(irq_num);
-    //drop(guard); // rescheduling may occur when preemption is re-enabled.
+    softirq::irq_exit();
+    // Preempt on the way out, if the interrupted context allows it.
+    drop(guard); // rescheduling may occur when preemption is re-enabled.
 }
@@ -45,6 +45,8 @@ pub fn init_syscall() {
 fn x86_syscall_handler(tf: &mut TrapFrame) {
     debug!("handle_linux_syscall");
     syscall(tf, axsyscall::do_syscall);
+    pm::try_to_freeze();
+    signal::do_signal(tf, signal::SYSCALL_VECTOR);
 }
 fn syscall_args(tf: &TrapFrame) -> SyscallArgs {
     [tf.rdi, tf.rsi, tf.rdx, tf.r10, tf.r8, tf.r9].map(|n| n as _)
@@ -55,6 +57,14 @@ where
     F: FnOnce(SyscallArgs, usize) -> usize,
 {
     info!("Syscall: {:#x}, {}", tf.rax, tf.rax);
-    let args = syscall_args(tf);
-    tf.rax = do_syscall(args, tf.rax as usize) as u64;
+    // Keep the syscall number for restarting it, see `signal::restart_syscall`.
+    // It is orig_rax for a tracer, which may change it, or skip the syscall
+    // with -1.
+    tf.error_code = tf.rax;
+    ptrace::report_syscall_entry();
+    if tf.error_code != u64::MAX {
+        let args = syscall_args(tf);
+        tf.rax = do_syscall(args, tf.error_code as usize) as u64;
+    }
+    ptrace::report_syscall_exit();
 }
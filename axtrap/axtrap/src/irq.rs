@@ -1,20 +1,17 @@
-//! Interrupt management.
+//! Interrupt management, on top of the generic IRQ handling of [`axirq`].
 
-use axhal::platform::irq::MAX_IRQ_COUNT;
-use handler_table::HandlerTable;
-
-pub use crate::platform::irq::{dispatch_irq, register_handler, set_enable};
+pub use crate::platform::irq::{dispatch_irq, register_handler};
 
 /// The type if an IRQ handler.
-pub type IrqHandler = handler_table::Handler;
-
-static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
+pub type IrqHandler = axirq::IrqHandler;
 
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
     trace!("IRQ {}", irq_num);
-    if !IRQ_HANDLER_TABLE.handle(irq_num) {
+    pm::pm_system_irq_wakeup(irq_num);
+    random::add_interrupt_randomness(irq_num);
+    if !axirq::generic_handle_irq(irq_num) {
         warn!("Unhandled IRQ {}", irq_num);
     }
 }
@@ -24,9 +21,8 @@ pub(crate) fn dispatch_irq_common(irq_num: usize) {
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
 /// the registration failed.
 #[allow(dead_code)]
-pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bool {
-    if irq_num < MAX_IRQ_COUNT && IRQ_HANDLER_TABLE.register_handler(irq_num, handler) {
-        set_enable(irq_num, true);
+pub(crate) fn register_handler_common(irq_num: usize, name: &str, handler: IrqHandler) -> bool {
+    if axirq::request_irq(irq_num, handler, axirq::IrqFlags::empty(), name, 0).is_ok() {
         return true;
     }
     warn!("register handler for IRQ {} failed", irq_num);
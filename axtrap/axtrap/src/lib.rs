@@ -8,8 +8,11 @@ mod arch;
 pub mod irq;
 mod platform;
 use crate::irq::IrqHandler;
+use axirq::IrqReturn;
+use axhal::platform::irq::IPI_IRQ_NUM;
 use axhal::time::TIMER_IRQ_NUM;
 use preempt_guard::NoPreempt;
+use softirq::{RCU_SOFTIRQ, TIMER_SOFTIRQ};
 
 pub fn init(cpu_id: usize, dtb_pa: usize) {
     axconfig::init_once!();
@@ -20,38 +23,89 @@ pub fn init(cpu_id: usize, dtb_pa: usize) {
     axalloc::init();
     axhal::platform_init();
     task::init(cpu_id, dtb_pa);
+    ktrace::init();
 
     arch::init_trap();
-    // Todo: extract irq as standalone modular axirq.
     axsyscall::init();
 
-    register_irq_handler(TIMER_IRQ_NUM, || {
-        update_timer();
-        let _ = NoPreempt::new();
-        run_queue::on_timer_tick();
+    // Expired sleepers are woken up in the bottom half.
+    softirq::open_softirq(TIMER_SOFTIRQ, run_queue::on_timer_event);
+    softirq::open_softirq(RCU_SOFTIRQ, rcu::rcu_process_callbacks);
+    register_irq_handler(TIMER_IRQ_NUM, "timer", |_, _| {
+        watchdog::watchdog_timer_interrupt();
+        let ticked = update_timer();
+        // Only sets `need_resched`, the switch happens on IRQ return.
+        let _guard = NoPreempt::new();
+        if ticked {
+            timekeeping::update_wall_time();
+            run_queue::on_timer_tick(irq_from_user());
+            if rcu::rcu_check_callbacks() {
+                softirq::raise_softirq(RCU_SOFTIRQ);
+            }
+        }
+        softirq::raise_softirq(TIMER_SOFTIRQ);
+        IrqReturn::Handled
     });
+    register_irq_handler(IPI_IRQ_NUM, "ipi", |_, _| {
+        kexec::handle_stop_ipi();
+        run_queue::on_resched_ipi();
+        IrqReturn::Handled
+    });
+    pm::register_pm_ops(&SCHED_PM_OPS);
+}
+
+fn sched_suspend() -> axerrno::LinuxResult {
+    run_queue::suspend_tick();
+    Ok(())
+}
+
+/// The scheduler idles with its tick stopped while suspended.
+static SCHED_PM_OPS: pm::PmOps = pm::PmOps {
+    name: "sched",
+    suspend: sched_suspend,
+    resume: run_queue::resume_tick,
+};
+
+#[percpu2::def_percpu]
+static IRQ_FROM_USER: bool = false;
+
+/// Records whether the IRQ being handled has interrupted user mode.
+pub(crate) fn set_irq_from_user(user: bool) {
+    // Safety: we have disabled preemption in IRQ handler.
+    unsafe { IRQ_FROM_USER.write_current_raw(user) };
+}
+
+fn irq_from_user() -> bool {
+    unsafe { IRQ_FROM_USER.read_current_raw() }
 }
 
-pub fn update_timer() {
+/// Re-arms the timer for the next periodic tick, or for an earlier sleeper
+/// deadline. Returns whether a periodic tick has elapsed.
+pub fn update_timer() -> bool {
     // Setup timer interrupt handler
     const PERIODIC_INTERVAL_NANOS: u64 =
         axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
     #[percpu2::def_percpu]
-    static NEXT_DEADLINE: u64 = 0;
+    static NEXT_TICK: u64 = 0;
 
     let now_ns = axhal::time::current_time_nanos();
     // Safety: we have disabled preemption in IRQ handler.
-    let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
-    if now_ns >= deadline {
-        deadline = now_ns + PERIODIC_INTERVAL_NANOS;
+    let mut next_tick = unsafe { NEXT_TICK.read_current_raw() };
+    let ticked = now_ns >= next_tick;
+    if ticked {
+        next_tick += PERIODIC_INTERVAL_NANOS;
+        if next_tick <= now_ns {
+            next_tick = now_ns + PERIODIC_INTERVAL_NANOS;
+        }
+        unsafe { NEXT_TICK.write_current_raw(next_tick) };
     }
-    unsafe { NEXT_DEADLINE.write_current_raw(deadline + PERIODIC_INTERVAL_NANOS) };
-    axhal::time::set_oneshot_timer(deadline);
+    run_queue::program_timer(next_tick);
+    ticked
 }
 
-pub fn register_irq_handler(irq: usize, handler: IrqHandler) {
-    irq::register_handler(irq, handler);
+pub fn register_irq_handler(irq: usize, name: &str, handler: IrqHandler) {
+    irq::register_handler(irq, name, handler);
 }
 
 pub fn start(_cpu_id: usize, _dtb: usize) {
@@ -0,0 +1,25 @@
+//! Interrupts of the QEMU virt machine, all through the GICv2, which
+//! [`axirq`] drives: the timer and the IPIs have their IRQ numbers there
+//! as the devices do.
+
+use crate::irq::IrqHandler;
+
+/// Registers an IRQ handler for the given IRQ.
+///
+/// It also enables the IRQ if the registration succeeds. It returns `false` if
+/// the registration failed.
+pub fn register_handler(irq_num: usize, name: &str, handler: IrqHandler) -> bool {
+    crate::irq::register_handler_common(irq_num, name, handler)
+}
+
+/// Dispatches the IRQs pending at the GIC.
+///
+/// This function is called by the common interrupt handler. It looks
+/// up in the IRQ handler table and calls the corresponding handler. If
+/// necessary, it also acknowledges the interrupt controller after handling.
+pub fn dispatch_irq(_unused: usize) {
+    while let Some(iar) = axirq::claim() {
+        crate::irq::dispatch_irq_common(axirq::irq_of(iar));
+        axirq::complete(iar);
+    }
+}
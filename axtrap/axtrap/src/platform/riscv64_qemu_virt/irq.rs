@@ -1,4 +1,6 @@
-//! TODO:
+//! Interrupts of the QEMU virt machine: timer and software interrupts of
+//! the hart, and external ones routed through the PLIC, which [`axirq`]
+//! drives.
 
 use crate::irq::IrqHandler;
 use lazy_init::LazyInit;
@@ -7,7 +9,6 @@ use lazy_init::LazyInit;
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -17,33 +18,36 @@ pub(super) const S_TIMER: usize = INTC_IRQ_BASE + 5;
 pub(super) const S_EXT: usize = INTC_IRQ_BASE + 9;
 
 static TIMER_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
+static IPI_HANDLER: LazyInit<IrqHandler> = LazyInit::new();
 
 /// The maximum number of IRQs.
 #[allow(unused)]
 pub const MAX_IRQ_COUNT: usize = 1024;
 
 macro_rules! with_cause {
-    ($cause: expr, @TIMER => $timer_op: expr, @EXT => $ext_op: expr $(,)?) => {
+    (
+        $cause: expr,
+        @TIMER => $timer_op: expr,
+        @SOFT => $soft_op: expr,
+        @EXT => $ext_op: expr $(,)?
+    ) => {
         match $cause {
             S_TIMER => $timer_op,
+            S_SOFT => $soft_op,
             S_EXT => $ext_op,
             _ => panic!("invalid trap cause: {:#x}", $cause),
         }
     };
 }
 
-/// Enables or disables the given IRQ.
-pub fn set_enable(scause: usize, _enabled: bool) {
-    if scause == S_EXT {
-        // TODO: set enable in PLIC
-    }
-}
-
 /// Registers an IRQ handler for the given IRQ.
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
 /// the registration failed.
-pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
+pub fn register_handler(scause: usize, name: &str, handler: IrqHandler) -> bool {
+    if (scause & INTC_IRQ_BASE) == 0 {
+        return crate::irq::register_handler_common(scause, name, handler);
+    }
     with_cause!(
         scause,
         @TIMER => if !TIMER_HANDLER.is_init() {
@@ -52,7 +56,13 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
         } else {
             false
         },
-        @EXT => crate::irq::register_handler_common(scause & !INTC_IRQ_BASE, handler),
+        @SOFT => if !IPI_HANDLER.is_init() {
+            IPI_HANDLER.init_by(handler);
+            true
+        } else {
+            false
+        },
+        @EXT => false, // handlers are for the sources of the PLIC
     )
 }
 
@@ -66,8 +76,18 @@ pub fn dispatch_irq(scause: usize) {
         scause,
         @TIMER => {
             trace!("IRQ: timer");
-            TIMER_HANDLER();
+            TIMER_HANDLER(scause, 0);
+        },
+        @SOFT => {
+            trace!("IRQ: ipi");
+            axhal::platform::irq::ack_ipi();
+            IPI_HANDLER(scause, 0);
+        },
+        @EXT => {
+            while let Some(irq) = axirq::claim() {
+                crate::irq::dispatch_irq_common(irq);
+                axirq::complete(irq);
+            }
         },
-        @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
     );
 }
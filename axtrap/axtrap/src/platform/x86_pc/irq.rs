@@ -1,18 +1,12 @@
 #![allow(dead_code)]
 
-/// Enables or disables the given IRQ.
-#[cfg(feature = "irq")]
-pub fn set_enable(vector: usize, enabled: bool) {
-    axhal::x86_64::set_enable(vector, enabled)
-}
-
 /// Registers an IRQ handler for the given IRQ.
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
 /// the registration failed.
 #[cfg(feature = "irq")]
-pub fn register_handler(vector: usize, handler: crate::irq::IrqHandler) -> bool {
-    crate::irq::register_handler_common(vector, handler)
+pub fn register_handler(vector: usize, name: &str, handler: crate::irq::IrqHandler) -> bool {
+    crate::irq::register_handler_common(vector, name, handler)
 }
 
 /// Dispatches the IRQ.
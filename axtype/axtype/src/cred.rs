@@ -0,0 +1,63 @@
+//! Task credentials.
+
+use alloc::vec::Vec;
+
+/// Most supplementary groups a task may have.
+pub const NGROUPS_MAX: usize = 65536;
+
+/// Identity of a task for permission checks.
+#[derive(Clone, Default)]
+pub struct Cred {
+    pub uid:    u32,    // real UID of the task
+    pub gid:    u32,    // real GID of the task
+    pub suid:   u32,    // saved UID of the task
+    pub sgid:   u32,    // saved GID of the task
+    pub euid:   u32,    // effective UID of the task
+    pub egid:   u32,    // effective GID of the task
+    pub fsuid:   u32,   // UID for filesystem
+    pub fsgid:   u32,   // GID for filesystem
+    /// Supplementary groups, sorted for lookup.
+    pub groups: Vec<u32>,
+}
+
+impl Cred {
+    /// Credentials of the superuser, which kernel threads and init run as.
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Whether it may change its ids freely, which stands in for
+    /// CAP_SETUID/CAP_SETGID until capabilities are tracked.
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// Whether it bypasses file permission bits, as CAP_DAC_OVERRIDE.
+    pub fn may_override(&self) -> bool {
+        self.fsuid == 0
+    }
+
+    /// Whether `gid` is its filesystem group or one of its supplementary
+    /// groups.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.fsgid == gid || self.groups.binary_search(&gid).is_ok()
+    }
+
+    /// Replaces the supplementary groups.
+    pub fn set_groups(&mut self, mut groups: Vec<u32>) {
+        groups.sort_unstable();
+        groups.dedup();
+        self.groups = groups;
+    }
+
+    /// Whether an unprivileged task may switch a uid to `id`, which has to
+    /// be one it holds already.
+    pub fn holds_uid(&self, id: u32) -> bool {
+        id == self.uid || id == self.euid || id == self.suid
+    }
+
+    /// Whether an unprivileged task may switch a gid to `id`.
+    pub fn holds_gid(&self, id: u32) -> bool {
+        id == self.gid || id == self.egid || id == self.sgid
+    }
+}
@@ -1,7 +1,9 @@
 #![cfg_attr(not(test), no_std)]
 
 mod path;
+mod cred;
 pub use path::Path;
+pub use cred::{Cred, NGROUPS_MAX};
 
 extern crate alloc;
 use alloc::string::String;
@@ -200,23 +202,35 @@ pub fn clr_bit(nr: usize, bitword: &mut usize) {
 // RLimit64
 //
 
+pub const RLIMIT_CPU:  usize = 0;  /* CPU time in sec */
+pub const RLIMIT_FSIZE:usize = 1;  /* maximum filesize */
 pub const RLIMIT_DATA: usize = 2;  /* max data size */
 pub const RLIMIT_STACK:usize = 3;  /* max stack size */
 pub const RLIMIT_CORE: usize = 4;  /* max core size */
 pub const RLIMIT_NOFILE: usize = 7; /* max number of open files */
+pub const RLIMIT_MEMLOCK: usize = 8; /* max locked-in-memory address space */
+pub const RLIMIT_AS:   usize = 9;  /* address space limit */
 pub const RLIM_NLIMITS: usize = 16;
 
-#[derive(Default, Copy, Clone)]
+pub const RLIM_INFINITY: u64 = u64::MAX;
+/// Hard ceiling of RLIMIT_NOFILE.
+pub const NR_OPEN: u64 = 1024 * 1024;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
 pub struct RLimit64 {
     pub rlim_cur: u64,
-    #[allow(dead_code)]
-    rlim_max: u64,
+    pub rlim_max: u64,
 }
 
 impl RLimit64 {
     pub fn new(rlim_cur: u64, rlim_max: u64) -> Self {
         Self { rlim_cur, rlim_max }
     }
+
+    pub const fn infinity() -> Self {
+        Self { rlim_cur: RLIM_INFINITY, rlim_max: RLIM_INFINITY }
+    }
 }
 
 ///
@@ -228,9 +242,14 @@ pub const O_WRONLY:     i32 = 0o000001;
 pub const O_RDWR:       i32 = 0o000002;
 pub const O_CREAT:      i32 = 0o000100;
 pub const O_EXCL:       i32 = 0o000200;
+pub const O_NOCTTY:     i32 = 0o000400;     /* not to become controlling tty */
 pub const O_TRUNC:      i32 = 0o001000;
 pub const O_APPEND:     i32 = 0o002000;
 pub const O_NONBLOCK:   i32 = 0o004000;
+pub const O_DSYNC:      i32 = 0o010000;
+pub const O_ASYNC:      i32 = 0o020000;     /* fcntl, for BSD compatibility */
+pub const O_DIRECT:     i32 = 0o040000;     /* direct disk access hint */
+pub const O_LARGEFILE:  i32 = 0o100000;
 pub const O_DIRECTORY:  i32 = 0o200000;     /* must be a directory */
 pub const O_NOFOLLOW:   i32 = 0o400000;     /* don't follow links */
 pub const O_NOATIME:    i32 = 0o1000000;
@@ -240,6 +259,28 @@ pub const __O_TMPFILE:  i32 = 0o20000000;
 
 pub const FS_NAME_LEN: usize = 255;
 
+///
+/// Advice of posix_fadvise.
+///
+pub const POSIX_FADV_NORMAL:     usize = 0;
+pub const POSIX_FADV_RANDOM:     usize = 1;
+pub const POSIX_FADV_SEQUENTIAL: usize = 2;
+pub const POSIX_FADV_WILLNEED:   usize = 3;
+pub const POSIX_FADV_DONTNEED:   usize = 4;
+pub const POSIX_FADV_NOREUSE:    usize = 5;
+
+///
+/// Mount flags.
+///
+pub const MS_RDONLY:        usize = 1;      /* Mount read-only */
+pub const MS_NOSUID:        usize = 2;      /* Ignore suid and sgid bits */
+pub const MS_NODEV:         usize = 4;      /* Disallow access to device special files */
+pub const MS_NOEXEC:        usize = 8;      /* Disallow program execution */
+pub const MS_SYNCHRONOUS:   usize = 16;     /* Writes are synced at once */
+pub const MS_REMOUNT:       usize = 32;     /* Alter flags of a mounted FS */
+/// Flags that remount may change, which statfs reports as the ST_* ones.
+pub const MS_RMT_MASK: usize = MS_RDONLY | MS_NOSUID | MS_NODEV | MS_NOEXEC | MS_SYNCHRONOUS;
+
 ///
 /// File flags.
 ///
@@ -261,4 +302,18 @@ pub const S_ISVTX:  i32 = 0o01000;
 pub const MAX_LOOP_NUMBER: usize = 2;
 
 /// Major Code
+pub const MAJOR_MEM: u32 = 1;
 pub const MAJOR_LOOP: u32 = 7;
+pub const MAJOR_TTY: u32 = 4;
+pub const MAJOR_TTYAUX: u32 = 5;
+pub const MAJOR_FB: u32 = 29;
+pub const MAJOR_HVC: u32 = 229;
+pub const MAJOR_PTS: u32 = 136;
+
+///
+/// Extended attribute flags and limits.
+///
+pub const XATTR_CREATE:   usize = 0x1;    /* set value, fail if attr already exists */
+pub const XATTR_REPLACE:  usize = 0x2;    /* set value, fail if attr does not exist */
+pub const XATTR_NAME_MAX: usize = 255;
+pub const XATTR_SIZE_MAX: usize = 65536;
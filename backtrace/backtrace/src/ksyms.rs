@@ -0,0 +1,68 @@
+//! The symbol table of the kernel, embedded in its image.
+//!
+//! It is a blob in the `.ksyms` section, which the linker script puts in
+//! `.rodata`, reserved zeroed here and filled by `scripts/make/ksyms.py`
+//! once the kernel is linked, little-endian:
+//!
+//! - a header: the magic `KSYM`, the number of symbols and the offset of
+//!   the string table, as u32;
+//! - the symbols, sorted by address: the address as u64, then the offset
+//!   and the length of the name in the string table, as u32;
+//! - the string table, of the demangled names without their hashes.
+
+/// Bytes reserved for the table, which the script has to fit in.
+const KSYMS_SIZE: usize = 2 << 20;
+const KSYMS_MAGIC: &[u8; 4] = b"KSYM";
+const HEADER_SIZE: usize = 12;
+const ENTRY_SIZE: usize = 16;
+
+// Mutable, so the compiler takes nothing for its content, which is filled
+// after linking. The script finds it by its name.
+#[used]
+#[no_mangle]
+#[link_section = ".ksyms"]
+static mut __KSYMS: [u8; KSYMS_SIZE] = [0; KSYMS_SIZE];
+
+fn ksyms() -> &'static [u8] {
+    unsafe { &*core::ptr::addr_of!(__KSYMS) }
+}
+
+fn le32(buf: &[u8], off: usize) -> usize {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap()) as usize
+}
+
+fn le64(buf: &[u8], off: usize) -> usize {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap()) as usize
+}
+
+/// The function `addr` is in, and the offset of `addr` in it, if the
+/// symbol table has been filled.
+pub fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    let ksyms = ksyms();
+    if &ksyms[..4] != KSYMS_MAGIC {
+        return None;
+    }
+    let count = le32(ksyms, 4);
+    let strtab = le32(ksyms, 8);
+    let entry = |i: usize| HEADER_SIZE + i * ENTRY_SIZE;
+    if entry(count) > strtab || strtab > KSYMS_SIZE {
+        return None;
+    }
+
+    // The last symbol at or below `addr`: `lo` ends as the number of them.
+    let (mut lo, mut hi) = (0, count);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if le64(ksyms, entry(mid)) <= addr {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    let i = lo.checked_sub(1)?;
+    let start = le64(ksyms, entry(i));
+    let name_off = strtab + le32(ksyms, entry(i) + 8);
+    let name_len = le32(ksyms, entry(i) + 12);
+    let name = ksyms.get(name_off..name_off + name_len)?;
+    Some((core::str::from_utf8(name).ok()?, addr - start))
+}
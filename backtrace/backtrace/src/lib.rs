@@ -0,0 +1,123 @@
+//! Backtraces of the kernel stack, with the names of the functions.
+//!
+//! The stack is walked by the chain of frame pointers, which the kernel is
+//! built to keep (`-C force-frame-pointers=yes`). Frames of code built
+//! without them, as the precompiled `core` and `alloc` are, may be
+//! skipped. The return addresses are resolved against the symbol table
+//! that `scripts/make/ksyms.py` writes into the `.ksyms` section of the
+//! kernel image after it is linked; without it, they are printed bare.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+
+mod ksyms;
+
+pub use ksyms::lookup;
+
+/// Frames walked at most, against a corrupt chain.
+const MAX_FRAMES: usize = 64;
+
+extern "C" {
+    fn _skernel();
+    fn _stext();
+    fn _etext();
+}
+
+/// Return addresses of a stack, from the innermost frame out.
+pub struct Frames {
+    fp: usize,
+    depth: usize,
+}
+
+impl Frames {
+    /// Walks the stack from the frame pointer `fp`, e.g. one saved in a
+    /// trap frame.
+    pub fn from_fp(fp: usize) -> Self {
+        Self { fp, depth: 0 }
+    }
+
+    /// Walks the stack of the caller.
+    #[inline(always)]
+    pub fn current() -> Self {
+        Self::from_fp(current_fp())
+    }
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let fp = self.fp;
+        if self.depth >= MAX_FRAMES
+            || fp < _skernel as usize
+            || fp % core::mem::size_of::<usize>() != 0
+        {
+            return None;
+        }
+        let (prev_fp, ra) = unsafe { read_frame(fp) };
+        if ra == 0 {
+            return None;
+        }
+        // The stack grows down, so callers have higher frames.
+        self.fp = if prev_fp > fp { prev_fp } else { 0 };
+        self.depth += 1;
+        Some(ra)
+    }
+}
+
+#[inline(always)]
+fn current_fp() -> usize {
+    let fp: usize;
+    unsafe {
+        #[cfg(target_arch = "riscv64")]
+        core::arch::asm!("mv {}, s0", out(reg) fp);
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("mov {}, rbp", out(reg) fp);
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("mov {}, x29", out(reg) fp);
+    }
+    fp
+}
+
+/// Reads the frame record at `fp`: the frame pointer of the caller and
+/// the return address into it. s0 points above the record, which is
+/// [prev fp, ra] below it.
+#[cfg(target_arch = "riscv64")]
+unsafe fn read_frame(fp: usize) -> (usize, usize) {
+    let fp = fp as *const usize;
+    (*fp.sub(2), *fp.sub(1))
+}
+
+/// Reads the frame record at `fp`: the frame pointer of the caller and
+/// the return address into it. rbp and x29 point at the record, which is
+/// [prev fp, ra].
+#[cfg(not(target_arch = "riscv64"))]
+unsafe fn read_frame(fp: usize) -> (usize, usize) {
+    let fp = fp as *const usize;
+    (*fp, *fp.add(1))
+}
+
+fn in_text(addr: usize) -> bool {
+    (_stext as usize.._etext as usize).contains(&addr)
+}
+
+/// Prints the frames of `frames` at the error level, with the function
+/// each return address is in when the symbol table has it.
+pub fn print_frames(frames: Frames) {
+    error!("Backtrace:");
+    for (i, ra) in frames.take_while(|ra| in_text(*ra)).enumerate() {
+        // The call is the instruction before the return address.
+        match lookup(ra - 1) {
+            Some((name, offset)) => error!("  #{:<2} {:#018x} {}+{:#x}", i, ra, name, offset + 1),
+            None => error!("  #{:<2} {:#018x}", i, ra),
+        }
+    }
+}
+
+/// Prints the backtrace of the caller, as the panic handler does.
+#[inline(always)]
+pub fn print_backtrace() {
+    print_frames(Frames::current());
+}
@@ -30,7 +30,9 @@ use core::str::from_utf8;
 use alloc::vec::Vec;
 use alloc::string::String;
 
-use axerrno::LinuxResult;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::virt_to_phys;
+use page_table::paging::MappingFlags;
 use axhal::arch::STACK_SIZE;
 use elf::abi::{PT_INTERP, PT_LOAD};
 use elf::endian::AnyEndian;
@@ -41,6 +43,7 @@ use elf::ElfBytes;
 use axio::SeekFrom;
 use axtype::{align_down, align_down_4k, align_up_4k, PAGE_SIZE};
 use axtype::is_aligned;
+use axtype::{S_ISUID, S_ISGID};
 use mmap::FileRef;
 use mmap::{MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE};
 use user_stack::UserStack;
@@ -57,9 +60,30 @@ pub fn execve(
 ) -> LinuxResult<(usize, usize)> {
     debug!("bprm_execve: {}", filename);
     let file = do_open_execat(filename, flags)?;
+    bprm_creds_from_file(&file)?;
     exec_binprm(filename, file, argv, envp)
 }
 
+// Takes the owner of a set-user-ID file, and the group of a set-group-ID
+// one, as effective ids. The saved ids follow the effective ones.
+fn bprm_creds_from_file(file: &FileRef) -> LinuxResult {
+    let attr = file.lock().get_attr()?;
+    let mode = attr.mode();
+    let current = task::current();
+    let mut cred = current.cred.lock();
+    if (mode & S_ISUID) != 0 {
+        cred.euid = attr.uid();
+    }
+    if (mode & (S_ISGID | 0o010)) == (S_ISGID | 0o010) {
+        cred.egid = attr.gid();
+    }
+    cred.suid = cred.euid;
+    cred.sgid = cred.egid;
+    cred.fsuid = cred.euid;
+    cred.fsgid = cred.egid;
+    Ok(())
+}
+
 // Opens executable file
 fn do_open_execat(filename: &str, _flags: usize) -> LinuxResult<FileRef> {
     fileops::do_open(filename, _flags as i32)
@@ -323,22 +347,40 @@ fn load_elf_binary(
         panic!("No interpret file!");
     };
 
-    // Todo: setup vdso pages for riscv64. vdso_len = 0x2000.
-    arch_setup_additional_pages();
+    let vdso_base = arch_setup_additional_pages()?;
 
     let interp_load_addr = elf_entry;
     elf_entry += interp_e_entry;
 
     create_elf_tables(e_phnum, interp_load_addr, entry, phdr_addr);
 
-    let sp = get_arg_page(filename, e_phnum, interp_load_addr, entry, phdr_addr, elf_entry, argv, envp)?;
+    let sp = get_arg_page(
+        filename, e_phnum, interp_load_addr, entry, phdr_addr, vdso_base, elf_entry, argv, envp
+    )?;
     Ok((elf_entry, sp))
 }
 
-// Set up architecture-specific pages (like vDSO)
-fn arch_setup_additional_pages() {
-    // Todo: implement it. setup vdso pages.
-    let _ = mmap::_mmap(0, 0x2000, PROT_READ | PROT_EXEC, MAP_ANONYMOUS, None, 0);
+// Set up architecture-specific pages: the vvar page and the vDSO image
+// above it. Returns the address of the image, if there is one.
+fn arch_setup_additional_pages() -> LinuxResult<Option<usize>> {
+    let Some(image) = vdso::image() else {
+        return Ok(None);
+    };
+    let len = vdso::VVAR_SIZE + image.len();
+    let va = mmap::_mmap(0, len, PROT_READ | PROT_EXEC, MAP_ANONYMOUS, None, 0)?;
+    let vdso_base = va + vdso::VVAR_SIZE;
+    let mm = task::current().mm();
+    let mut mm = mm.write();
+    mm.map_special(va, vdso::vvar_paddr(), vdso::VVAR_SIZE, MappingFlags::READ | MappingFlags::USER)
+        .map_err(|_| LinuxError::ENOMEM)?;
+    mm.map_special(
+        vdso_base,
+        virt_to_phys((image.as_ptr() as usize).into()).into(),
+        image.len(),
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+    ).map_err(|_| LinuxError::ENOMEM)?;
+    debug!("vdso at {:#x}", vdso_base);
+    Ok(Some(vdso_base))
 }
 
 // Create ELF auxiliary tables
@@ -375,7 +417,7 @@ fn set_brk(elf_bss: usize, elf_brk: usize) {
         .unwrap();
     }
 
-    task::current().mm().lock().set_brk(elf_brk as usize)
+    task::current().mm().write().set_brk(elf_brk as usize)
 }
 
 // Calculate protection flags from ELF segment flags
@@ -444,20 +486,14 @@ const AT_FLAGS  : usize = 8;    /* flags */
 const AT_ENTRY  : usize = 9;    /* entry point of program */
 #[allow(unused)]
 const AT_NOTELF : usize = 10;   /* program is not ELF */
-#[allow(unused)]
 const AT_UID    : usize = 11;   /* real uid */
-#[allow(unused)]
 const AT_EUID   : usize = 12;   /* effective uid */
-#[allow(unused)]
 const AT_GID    : usize = 13;   /* real gid */
-#[allow(unused)]
 const AT_EGID   : usize = 14;   /* effective gid */
-#[allow(unused)]
 const AT_PLATFORM: usize = 15; /* string identifying CPU for optimizations */
 const AT_HWCAP  : usize = 16;   /* arch dependent hints at CPU capabilities */
 const AT_CLKTCK : usize = 17;   /* frequency at which times() increments */
 /* AT_* values 18 through 22 are reserved */
-#[allow(unused)]
 const AT_SECURE : usize = 23;   /* secure mode boolean */
 #[allow(unused)]
 const AT_BASE_PLATFORM: usize = 24;       /* string identifying real platform, may differ from AT_PLATFORM. */
@@ -465,9 +501,42 @@ const AT_RANDOM : usize = 25;   /* address of 16 random bytes */
 #[allow(unused)]
 const AT_HWCAP2 : usize = 26;   /* extension of AT_HWCAP */
 const AT_EXECFN : usize = 31;   /* filename of program */
+const AT_SYSINFO_EHDR: usize = 33; /* address of the vDSO */
 
 const MAX_ARG_STRLEN: usize = PAGE_SIZE;
 
+/// The string of AT_PLATFORM.
+#[cfg(target_arch = "riscv64")]
+const ELF_PLATFORM: &str = "riscv64";
+#[cfg(target_arch = "x86_64")]
+const ELF_PLATFORM: &str = "x86_64";
+#[cfg(target_arch = "aarch64")]
+const ELF_PLATFORM: &str = "aarch64";
+
+/// AT_HWCAP: the single-letter extensions, a bit each, IMAFDC.
+#[cfg(target_arch = "riscv64")]
+fn elf_hwcap() -> usize {
+    const fn isa(c: u8) -> usize {
+        1 << (c - b'a')
+    }
+    isa(b'i') | isa(b'm') | isa(b'a') | isa(b'f') | isa(b'd') | isa(b'c')
+}
+
+/// AT_HWCAP: the feature flags of CPUID leaf 1, in edx.
+#[cfg(target_arch = "x86_64")]
+fn elf_hwcap() -> usize {
+    unsafe { core::arch::x86_64::__cpuid(1).edx as usize }
+}
+
+/// AT_HWCAP: HWCAP_FP and HWCAP_ASIMD, which all the cores of the QEMU
+/// virt machine have.
+#[cfg(target_arch = "aarch64")]
+fn elf_hwcap() -> usize {
+    const HWCAP_FP: usize = 1 << 0;
+    const HWCAP_ASIMD: usize = 1 << 1;
+    HWCAP_FP | HWCAP_ASIMD
+}
+
 // Create new auxiliary vector entry
 fn new_aux_ent(elf_info: &mut Vec<usize>, id: usize, val: usize) {
     elf_info.push(id);
@@ -477,7 +546,7 @@ fn new_aux_ent(elf_info: &mut Vec<usize>, id: usize, val: usize) {
 fn get_arg_page(
     filename: &str,
     e_phnum: usize, interp_load_addr: usize, entry: usize, phdr_addr: usize,
-    _entry: usize, argv: Vec<String>, envp: Vec<String>
+    vdso_base: Option<usize>, _entry: usize, argv: Vec<String>, envp: Vec<String>
 ) -> LinuxResult<usize> {
     //let auxv = : usize = get_auxv_vector(entry);
 
@@ -507,18 +576,28 @@ fn get_arg_page(
     let mut arg_start = stack.get_sp();
     debug!("argv {:#x}", stack.get_sp());
 
-    let random_str: &[usize; 2] = &[0, 0];
-    stack.push(random_str.as_slice());
+    let u_platform = stack.push_str(ELF_PLATFORM);
+    debug!("platform {:#x}", stack.get_sp());
+
+    let mut random_bytes = [0u8; 16];
+    random::get_random_bytes(&mut random_bytes);
+    stack.push(random_bytes.as_slice());
     let u_rand_bytes = stack.get_sp();
     debug!("random {:#x} AT_VECTOR_SIZE {:#x}", stack.get_sp(), AT_VECTOR_SIZE);
 
-    // Note: Just for riscv64
-    const ELF_HWCAP: usize = 0x112d;
     const ELF_EXEC_PAGESIZE: usize = 0x1000;
     const CLOCKS_PER_SEC: usize = 0x64;
 
+    // The ids are the ones bprm_creds_from_file has set up: a set-id
+    // program runs in secure mode, so ld.so ignores LD_PRELOAD and the like.
+    let (uid, euid, gid, egid) = {
+        let cred = task::current().cred.lock();
+        (cred.uid, cred.euid, cred.gid, cred.egid)
+    };
+    let secure = uid != euid || gid != egid;
+
     let mut saved_auxv: Vec<usize> = Vec::with_capacity(AT_VECTOR_SIZE);
-    new_aux_ent(&mut saved_auxv, AT_HWCAP, ELF_HWCAP);
+    new_aux_ent(&mut saved_auxv, AT_HWCAP, elf_hwcap());
     new_aux_ent(&mut saved_auxv, AT_PAGESZ, ELF_EXEC_PAGESIZE);
     new_aux_ent(&mut saved_auxv, AT_CLKTCK, CLOCKS_PER_SEC);
     new_aux_ent(&mut saved_auxv, AT_PHDR, phdr_addr);
@@ -527,13 +606,17 @@ fn get_arg_page(
     new_aux_ent(&mut saved_auxv, AT_BASE, interp_load_addr);
     new_aux_ent(&mut saved_auxv, AT_FLAGS, 0);
     new_aux_ent(&mut saved_auxv, AT_ENTRY, entry);
-    new_aux_ent(&mut saved_auxv, AT_UID, 0);
-    new_aux_ent(&mut saved_auxv, AT_EUID, 0);
-    new_aux_ent(&mut saved_auxv, AT_GID, 0);
-    new_aux_ent(&mut saved_auxv, AT_EGID, 0);
-    new_aux_ent(&mut saved_auxv, AT_SECURE, 0);
+    new_aux_ent(&mut saved_auxv, AT_UID, uid as usize);
+    new_aux_ent(&mut saved_auxv, AT_EUID, euid as usize);
+    new_aux_ent(&mut saved_auxv, AT_GID, gid as usize);
+    new_aux_ent(&mut saved_auxv, AT_EGID, egid as usize);
+    new_aux_ent(&mut saved_auxv, AT_SECURE, secure as usize);
     new_aux_ent(&mut saved_auxv, AT_RANDOM, u_rand_bytes);
     new_aux_ent(&mut saved_auxv, AT_EXECFN, exec_fname);
+    new_aux_ent(&mut saved_auxv, AT_PLATFORM, u_platform);
+    if let Some(vdso_base) = vdso_base {
+        new_aux_ent(&mut saved_auxv, AT_SYSINFO_EHDR, vdso_base);
+    }
     new_aux_ent(&mut saved_auxv, AT_NULL, 0);
 
     let mut sp = stack.get_sp() - saved_auxv.len() * 8;
@@ -555,6 +638,7 @@ fn get_arg_page(
     pos = put_user(argv.len(), pos);
 
     /* Populate list of argv pointers back to argv strings. */
+    let argv_start = arg_start;
     for _ in 0..argv.len() {
         pos = put_user(arg_start, pos);
         let len = strnlen_user(arg_start, MAX_ARG_STRLEN);
@@ -564,6 +648,12 @@ fn get_arg_page(
         arg_start += len;
     }
     pos = put_user(0, pos);
+    {
+        let mm = task::current().mm();
+        let mut locked_mm = mm.write();
+        locked_mm.arg_start = argv_start;
+        locked_mm.arg_end = arg_start;
+    }
 
     /* Populate list of envp pointers back to envp strings. */
     for _ in 0..envp.len() {
@@ -0,0 +1,80 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+#include <pthread.h>
+#include <sys/mman.h>
+
+#define PAGE_SIZE 4096
+
+/* Faults in pages of an area of its own in each thread at once, with 1,
+ * 2, 4, ... threads, and prints how fast the faults go. Faults on unrelated
+ * ranges don't serialize, so the rate should scale with the CPUs.
+ *
+ *   faultbench [pages per thread] [max threads]
+ */
+
+struct slice {
+    char *start;
+    long pages;
+};
+
+static void *touch(void *arg)
+{
+    struct slice *s = arg;
+    for (long i = 0; i < s->pages; i++) {
+        s->start[i * PAGE_SIZE] = 1;
+    }
+    return NULL;
+}
+
+static long elapsed_us(struct timespec *t0, struct timespec *t1)
+{
+    return (t1->tv_sec - t0->tv_sec) * 1000000 + (t1->tv_nsec - t0->tv_nsec) / 1000;
+}
+
+static int run(int nthreads, long pages)
+{
+    size_t len = (size_t)nthreads * pages * PAGE_SIZE;
+    char *area = mmap(NULL, len, PROT_READ | PROT_WRITE,
+                      MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (area == MAP_FAILED) {
+        printf("mmap %zu bytes error!\n", len);
+        return -1;
+    }
+
+    pthread_t threads[nthreads];
+    struct slice slices[nthreads];
+    struct timespec t0, t1;
+    clock_gettime(CLOCK_MONOTONIC, &t0);
+    for (int i = 0; i < nthreads; i++) {
+        slices[i].start = area + (size_t)i * pages * PAGE_SIZE;
+        slices[i].pages = pages;
+        pthread_create(&threads[i], NULL, touch, &slices[i]);
+    }
+    for (int i = 0; i < nthreads; i++) {
+        pthread_join(threads[i], NULL);
+    }
+    clock_gettime(CLOCK_MONOTONIC, &t1);
+
+    long us = elapsed_us(&t0, &t1);
+    long faults = nthreads * pages;
+    printf("threads %2d: %8ld faults in %8ld us, %6ld faults/ms\n",
+           nthreads, faults, us, us ? faults * 1000 / us : 0);
+    munmap(area, len);
+    return 0;
+}
+
+int main(int argc, char *argv[])
+{
+    long pages = argc > 1 ? atol(argv[1]) : 1024;
+    int max_threads = argc > 2 ? atoi(argv[2]) : 4;
+
+    printf("faultbench: %ld pages per thread\n", pages);
+    for (int n = 1; n <= max_threads; n *= 2) {
+        if (run(n, pages) < 0) {
+            exit(-1);
+        }
+    }
+    printf("faultbench ok!\n");
+    return 0;
+}
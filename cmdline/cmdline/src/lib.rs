@@ -0,0 +1,178 @@
+//! The kernel command line, from the bootargs of /chosen in the device
+//! tree.
+//!
+//! It is a list of parameters separated by spaces, each `name=value` or a
+//! bare `name`, which gets an empty value. A value with spaces is put in
+//! double quotes. In names, `-` and `_` are the same.
+//!
+//! Subsystems declare the parameters they take with [`kernel_param!`],
+//! which puts an entry into the `__param` section, gathered between
+//! `__start___param` and `__stop___param` by the linker. [`parse`] hands
+//! each parameter of the command line to the setup function declared for
+//! its name, early at boot, so they store what they are given for the init
+//! of their subsystem to use, in place of the value it was built with.
+//!
+//! What is left over is for init, as Linux does: the bare words and all
+//! after `--` are its arguments, and the `name=value` ones without a `.`
+//! in the name its environment.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use lazy_init::LazyInit;
+
+/// An entry of the `__param` section.
+#[repr(C)]
+pub struct KernelParam {
+    name: &'static str,
+    setup: fn(&str) -> LinuxResult,
+}
+
+impl KernelParam {
+    pub const fn new(name: &'static str, setup: fn(&str) -> LinuxResult) -> Self {
+        Self { name, setup }
+    }
+}
+
+/// Declares the parameter `name` of the command line, whose value is
+/// handed to `setup`, a `fn(&str) -> LinuxResult`, as it is parsed. An
+/// error is logged, and the value ignored.
+#[macro_export]
+macro_rules! kernel_param {
+    ($name:literal, $setup:path) => {
+        const _: () = {
+            #[used]
+            #[link_section = "__param"]
+            static __PARAM: $crate::KernelParam = $crate::KernelParam::new($name, $setup);
+        };
+    };
+}
+
+extern "C" {
+    fn __start___param();
+    fn __stop___param();
+}
+
+fn params() -> &'static [KernelParam] {
+    let start = __start___param as usize;
+    let len = (__stop___param as usize - start) / core::mem::size_of::<KernelParam>();
+    unsafe { core::slice::from_raw_parts(start as *const KernelParam, len) }
+}
+
+static COMMAND_LINE: LazyInit<String> = LazyInit::new();
+static INIT_ARGV: LazyInit<Vec<String>> = LazyInit::new();
+static INIT_ENVP: LazyInit<Vec<String>> = LazyInit::new();
+
+/// Whether the names `a` and `b` are the same, with `-` for `_`.
+fn parameq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes().zip(b.bytes()).all(|(x, y)| {
+            let dash = |c| if c == b'-' { b'_' } else { c };
+            dash(x) == dash(y)
+        })
+}
+
+/// Splits `cmdline` into its parameters, at the spaces out of quotes, and
+/// drops the quotes.
+fn split_args(cmdline: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_quote = false;
+    let mut in_arg = false;
+    for c in cmdline.chars() {
+        match c {
+            '"' => {
+                in_quote = !in_quote;
+                in_arg = true;
+            }
+            c if c.is_ascii_whitespace() && !in_quote => {
+                if in_arg {
+                    args.push(core::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            c => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+    args
+}
+
+/// Parses the command line `cmdline`, handing each parameter to the setup
+/// functions declared for it, and keeps what is left for init. Called
+/// once at boot, with an empty one if there is none.
+pub fn parse(cmdline: &str) {
+    let cmdline = cmdline.trim_end_matches('\0').trim();
+    info!("Kernel command line: {}", cmdline);
+    COMMAND_LINE.init_by(cmdline.into());
+
+    let mut argv = Vec::new();
+    let mut envp = Vec::new();
+    let mut args = split_args(cmdline).into_iter();
+    for arg in args.by_ref() {
+        if arg == "--" {
+            break;
+        }
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let mut known = false;
+        for param in params().iter().filter(|p| parameq(p.name, name)) {
+            known = true;
+            if let Err(e) = (param.setup)(value.unwrap_or("")) {
+                warn!("Malformed kernel parameter {}: {:?}", arg, e);
+            }
+        }
+        if known {
+            continue;
+        }
+        if name.contains('.') {
+            warn!("Unknown kernel command line parameter {}", arg);
+        } else if let Some(value) = value {
+            envp.push(format!("{}={}", name, value));
+        } else {
+            argv.push(arg);
+        }
+    }
+    argv.extend(args);
+    INIT_ARGV.init_by(argv);
+    INIT_ENVP.init_by(envp);
+}
+
+/// The command line the kernel was booted with, as /proc/cmdline gives it.
+pub fn saved_command_line() -> &'static str {
+    COMMAND_LINE.try_get().map_or("", |s| s.as_str())
+}
+
+/// The arguments for init, after its name, from the command line.
+pub fn init_argv() -> &'static [String] {
+    INIT_ARGV.try_get().map_or(&[], |v| v.as_slice())
+}
+
+/// The environment for init from the command line, as `name=value`.
+pub fn init_envp() -> &'static [String] {
+    INIT_ENVP.try_get().map_or(&[], |v| v.as_slice())
+}
+
+/// Parses a boolean value as Linux does: `1`, `y`, `on` or `true` and
+/// `0`, `n`, `off` or `false`. An empty one, of a bare name, is true.
+pub fn parse_bool(value: &str) -> LinuxResult<bool> {
+    match value {
+        "" | "1" | "y" | "Y" | "on" | "true" => Ok(true),
+        "0" | "n" | "N" | "off" | "false" => Ok(false),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
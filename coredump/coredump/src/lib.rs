@@ -0,0 +1,499 @@
+//! Core dumps: an ELF core file of a process killed by a signal whose
+//! default action is to dump core, as SIGSEGV or SIGABRT, for gdb on the
+//! host to look into.
+//!
+//! The file is named by `core_pattern=` of the command line, `core` by
+//! default, where %p is replaced by the pid, %e by the name of the program,
+//! %s by the signal, and %% by a '%'. A relative name is in the current
+//! directory of the process. Nothing is dumped while RLIMIT_CORE is 0, as
+//! it is by default, and the dump is given up where it would go past it.
+//!
+//! The core has a PT_NOTE segment, with an NT_PRSTATUS for each thread,
+//! the dumping one first, and an NT_PRPSINFO, then a PT_LOAD segment for
+//! each area of the address space. An area has its content in the file
+//! only if the coredump filter of its mm takes its kind, see `MMF_DUMP_*`;
+//! it is set by `/proc/<pid>/coredump_filter`, or `coredump_filter=` for
+//! all. Pages never faulted in are written as zeros.
+//!
+//! The other threads aren't stopped for the dump: their registers are the
+//! ones they last entered the kernel with.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::mem::size_of;
+use core::sync::atomic::Ordering;
+use axerrno::{LinuxError, LinuxResult};
+use axfile::fops::{File, OpenOptions};
+use axtype::{O_CREAT, O_NOFOLLOW, O_TRUNC, O_WRONLY, PAGE_SIZE, RLIMIT_CORE};
+use elf::abi::*;
+use mm::{MmStruct, VmAreaStruct, VM_IO, VM_READ, VM_WRITE, VM_EXEC, VM_SHARED};
+use mm::{MMF_DUMP_ANON_PRIVATE, MMF_DUMP_ANON_SHARED};
+use mm::{MMF_DUMP_MAPPED_PRIVATE, MMF_DUMP_MAPPED_SHARED, MMF_DUMP_ELF_HEADERS};
+use ptrace::UserRegs;
+use spinbase::SpinNoIrq;
+use task::{SigInfo, TaskStruct};
+
+#[cfg(target_arch = "x86_64")]
+const ELF_ARCH: u16 = EM_X86_64;
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const ELF_ARCH: u16 = EM_RISCV;
+#[cfg(target_arch = "aarch64")]
+const ELF_ARCH: u16 = EM_AARCH64;
+
+/// Most segments the header can count, the notes with the areas.
+const MAX_SEGMENTS: usize = PN_XNUM as usize - 1;
+
+/// Longest name of a program, as TASK_COMM_LEN without the nul.
+const COMM_LEN: usize = 15;
+const PSARGS_LEN: usize = 80;
+
+/// The name of the core file, `core` if empty.
+static CORE_PATTERN: SpinNoIrq<String> = SpinNoIrq::new(String::new());
+
+#[repr(C)]
+struct Elf64Ehdr {
+    e_ident: [u8; EI_NIDENT],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfSiginfo {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Timeval {
+    tv_sec: i64,
+    tv_usec: i64,
+}
+
+impl Timeval {
+    fn from_nanos(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / 1_000_000_000) as i64,
+            tv_usec: ((ns % 1_000_000_000) / 1_000) as i64,
+        }
+    }
+}
+
+/// `struct elf_prstatus`: the state of a thread.
+#[repr(C)]
+struct ElfPrstatus {
+    info: ElfSiginfo,
+    cursig: i16,
+    sigpend: u64,
+    sighold: u64,
+    pid: i32,
+    ppid: i32,
+    pgrp: i32,
+    sid: i32,
+    utime: Timeval,
+    stime: Timeval,
+    cutime: Timeval,
+    cstime: Timeval,
+    reg: UserRegs,
+    fpvalid: i32,
+}
+
+/// `struct elf_prpsinfo`: the process, as ps shows it.
+#[repr(C)]
+struct ElfPrpsinfo {
+    state: u8,
+    sname: u8,
+    zomb: u8,
+    nice: i8,
+    flag: u64,
+    uid: u32,
+    gid: u32,
+    pid: i32,
+    ppid: i32,
+    pgrp: i32,
+    sid: i32,
+    fname: [u8; COMM_LEN + 1],
+    psargs: [u8; PSARGS_LEN],
+}
+
+/// An area of the address space, and how much of it is dumped.
+struct Area {
+    start: usize,
+    end: usize,
+    flags: u32,
+    dump_size: usize,
+}
+
+fn as_bytes<T>(val: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) }
+}
+
+/// Dumps the core of the current process, which is killed by `info`.
+/// Returns whether the core was written, for WCOREDUMP of its wait status.
+pub fn do_coredump(info: &SigInfo) -> bool {
+    let task = task::current();
+    match dump(&task, info.signo) {
+        Ok(dumped) => dumped,
+        Err(e) => {
+            warn!("coredump: task {} not dumped: {:?}", task.tid(), e);
+            false
+        }
+    }
+}
+
+fn dump(task: &TaskStruct, signo: i32) -> LinuxResult<bool> {
+    let limit = task.rlimit(RLIMIT_CORE);
+    // Too small for anything of use.
+    if limit < PAGE_SIZE as u64 {
+        return Ok(false);
+    }
+    let Some(mm) = task.try_mm() else {
+        return Ok(false);
+    };
+    let (mut areas, args) = {
+        let mm = mm.read();
+        (collect_areas(&mm), cmdline(&mm))
+    };
+    areas.truncate(MAX_SEGMENTS - 1);
+    let comm = comm(&args);
+
+    let path = core_name(task, signo, &comm);
+    info!("coredump: task {} by signal {} into '{}'", task.tid(), signo, path);
+    let mut core = CoreFile {
+        file: open_core(task, &path)?,
+        written: 0,
+        limit,
+    };
+
+    let notes = notes(task, signo, &comm, &args);
+    let phnum = areas.len() + 1;
+    let notes_offset = size_of::<Elf64Ehdr>() + phnum * size_of::<Elf64Phdr>();
+    let data_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+
+    core.emit(as_bytes(&elf_header(phnum)))?;
+    core.emit(as_bytes(&Elf64Phdr {
+        p_type: PT_NOTE,
+        p_flags: 0,
+        p_offset: notes_offset as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes.len() as u64,
+        p_memsz: 0,
+        p_align: 0,
+    }))?;
+    let mut offset = data_offset;
+    for area in &areas {
+        core.emit(as_bytes(&Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags: area.flags,
+            p_offset: offset as u64,
+            p_vaddr: area.start as u64,
+            p_paddr: 0,
+            p_filesz: area.dump_size as u64,
+            p_memsz: (area.end - area.start) as u64,
+            p_align: PAGE_SIZE as u64,
+        }))?;
+        offset += area.dump_size;
+    }
+    core.emit(&notes)?;
+    core.skip_to(data_offset)?;
+
+    let mut page = vec![0u8; PAGE_SIZE];
+    for area in &areas {
+        for va in (area.start..area.start + area.dump_size).step_by(PAGE_SIZE) {
+            // Copied out of the lock, which the write can't be under.
+            let size = mm.read().access_remote(va, &mut page);
+            page[size..].fill(0);
+            core.emit(&page)?;
+        }
+    }
+    Ok(true)
+}
+
+/// The core file, which may grow up to `limit` bytes.
+struct CoreFile {
+    file: File,
+    written: u64,
+    limit: u64,
+}
+
+impl CoreFile {
+    /// Writes `buf` all, or nothing of it if it would go past the limit.
+    fn emit(&mut self, mut buf: &[u8]) -> LinuxResult {
+        if self.written + buf.len() as u64 > self.limit {
+            return Err(LinuxError::EFBIG);
+        }
+        while !buf.is_empty() {
+            let size = self.file.write(buf)?;
+            if size == 0 {
+                return Err(LinuxError::EIO);
+            }
+            self.written += size as u64;
+            buf = &buf[size..];
+        }
+        Ok(())
+    }
+
+    /// Pads with zeros up to `offset`.
+    fn skip_to(&mut self, offset: usize) -> LinuxResult {
+        let pad = offset as u64 - self.written;
+        self.emit(&vec![0u8; pad as usize])
+    }
+}
+
+/// Opens the core file, made anew. It must be a regular file of the one
+/// who dumps, not a device nor a pipe left in its place.
+fn open_core(task: &TaskStruct, path: &str) -> LinuxResult<File> {
+    let mut opts = OpenOptions::new();
+    opts.set_flags(O_WRONLY | O_CREAT | O_TRUNC | O_NOFOLLOW);
+    opts.set_mode(0o600);
+    opts.write(true);
+    opts.create(true);
+    opts.truncate(true);
+    let cred = task.cred();
+    let file = {
+        let fs = task.fs.lock();
+        File::open(path, &opts, &fs, &cred)?
+    };
+    let attr = file.get_attr()?;
+    if !attr.is_file() || attr.uid() != cred.fsuid {
+        return Err(LinuxError::EPERM);
+    }
+    Ok(file)
+}
+
+/// The name of the core file by `core_pattern=`.
+fn core_name(task: &TaskStruct, signo: i32, comm: &str) -> String {
+    let pattern = CORE_PATTERN.lock().clone();
+    let pattern = if pattern.is_empty() { "core" } else { &pattern };
+    let mut name = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            name.push(c);
+            continue;
+        }
+        // The specifiers not known are dropped.
+        let _ = match chars.next() {
+            Some('%') => write!(name, "%"),
+            Some('p') => write!(name, "{}", task::pid_vnr(task.tgid())),
+            Some('e') => write!(name, "{}", comm),
+            Some('s') => write!(name, "{}", signo),
+            _ => Ok(()),
+        };
+    }
+    name
+}
+
+/// The areas of `mm`, in order.
+fn collect_areas(mm: &MmStruct) -> Vec<Area> {
+    let filter = mm.coredump_filter;
+    mm.vmas.values().map(|vma| {
+        let mut flags = 0;
+        if (vma.vm_flags & VM_READ) != 0 {
+            flags |= PF_R;
+        }
+        if (vma.vm_flags & VM_WRITE) != 0 {
+            flags |= PF_W;
+        }
+        if (vma.vm_flags & VM_EXEC) != 0 {
+            flags |= PF_X;
+        }
+        Area {
+            start: vma.vm_start,
+            end: vma.vm_end,
+            flags,
+            dump_size: vma_dump_size(mm, vma, filter),
+        }
+    }).collect()
+}
+
+/// How much of `vma` is dumped with `filter`, as vma_dump_size of Linux.
+fn vma_dump_size(mm: &MmStruct, vma: &VmAreaStruct, filter: usize) -> usize {
+    let whole = vma.vm_end - vma.vm_start;
+    if (vma.vm_flags & (VM_IO | VM_READ)) != VM_READ {
+        return 0;
+    }
+    let shared = (vma.vm_flags & VM_SHARED) != 0;
+    let file = vma.vm_file.get().is_some();
+    let bit = match (file, shared) {
+        (false, false) => MMF_DUMP_ANON_PRIVATE,
+        (false, true) => MMF_DUMP_ANON_SHARED,
+        (true, false) => MMF_DUMP_MAPPED_PRIVATE,
+        (true, true) => MMF_DUMP_MAPPED_SHARED,
+    };
+    if (filter & bit) != 0 {
+        return whole;
+    }
+    // A private mapping that may have been written to, as the data of a
+    // program, is anonymous in its written pages.
+    if file && !shared && (vma.vm_flags & VM_WRITE) != 0 && (filter & MMF_DUMP_ANON_PRIVATE) != 0 {
+        return whole;
+    }
+    // The ELF header of a program or a library, for gdb to match them.
+    if file && !shared && vma.vm_pgoff == 0 && (filter & MMF_DUMP_ELF_HEADERS) != 0 {
+        let mut magic = [0u8; 4];
+        if mm.access_remote(vma.vm_start, &mut magic) == magic.len() && magic == ELFMAGIC {
+            return PAGE_SIZE.min(whole);
+        }
+    }
+    0
+}
+
+/// The arguments as they are on the initial stack, each ended by a nul.
+fn cmdline(mm: &MmStruct) -> Vec<u8> {
+    let mut args = vec![0u8; mm.arg_end - mm.arg_start];
+    let size = mm.access_remote(mm.arg_start, &mut args);
+    args.truncate(size);
+    args
+}
+
+/// The name of the program, from its first argument.
+fn comm(args: &[u8]) -> String {
+    let arg0 = args.split(|&c| c == 0).next().unwrap_or(&[]);
+    let name = arg0.rsplit(|&c| c == b'/').next().unwrap_or(&[]);
+    String::from_utf8_lossy(&name[..name.len().min(COMM_LEN)]).into_owned()
+}
+
+fn elf_header(phnum: usize) -> Elf64Ehdr {
+    let mut ident = [0u8; EI_NIDENT];
+    ident[..ELFMAGIC.len()].copy_from_slice(&ELFMAGIC);
+    ident[EI_CLASS] = ELFCLASS64;
+    ident[EI_DATA] = ELFDATA2LSB;
+    ident[EI_VERSION] = EV_CURRENT;
+    ident[EI_OSABI] = ELFOSABI_NONE;
+    Elf64Ehdr {
+        e_ident: ident,
+        e_type: ET_CORE,
+        e_machine: ELF_ARCH,
+        e_version: EV_CURRENT as u32,
+        e_entry: 0,
+        e_phoff: size_of::<Elf64Ehdr>() as u64,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: size_of::<Elf64Ehdr>() as u16,
+        e_phentsize: size_of::<Elf64Phdr>() as u16,
+        e_phnum: phnum as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    }
+}
+
+/// Appends a note of `ty` named "CORE", each part padded to 4 bytes.
+fn push_note(notes: &mut Vec<u8>, ty: u64, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0";
+    notes.extend_from_slice(&(NAME.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&(ty as u32).to_le_bytes());
+    for part in [NAME, desc] {
+        notes.extend_from_slice(part);
+        notes.resize(notes.len().next_multiple_of(4), 0);
+    }
+}
+
+/// The notes: NT_PRSTATUS of the current thread, NT_PRPSINFO, then
+/// NT_PRSTATUS of the other threads.
+fn notes(task: &TaskStruct, signo: i32, comm: &str, args: &[u8]) -> Vec<u8> {
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRSTATUS, as_bytes(&prstatus(task, signo)));
+    push_note(&mut notes, NT_PRPSINFO, as_bytes(&prpsinfo(task, comm, args)));
+    for thread in task.thread_group().into_iter()
+        .filter(|&tid| tid != task.tid())
+        .filter_map(task::get_task) {
+        push_note(&mut notes, NT_PRSTATUS, as_bytes(&prstatus(&thread, signo)));
+    }
+    notes
+}
+
+fn ppid(task: &TaskStruct) -> i32 {
+    task.parent().map_or(0, |parent| task::pid_vnr(parent.tgid())) as i32
+}
+
+fn prstatus(task: &TaskStruct, signo: i32) -> ElfPrstatus {
+    // Zeroed, padding and all, as it is written out whole.
+    let mut status: ElfPrstatus = unsafe { core::mem::zeroed() };
+    status.info.si_signo = signo;
+    status.cursig = signo as i16;
+    status.sigpend = task.sigpending.lock().signal;
+    status.sighold = task.blocked.load(Ordering::Relaxed);
+    status.pid = task::pid_vnr(task.tid()) as i32;
+    status.ppid = ppid(task);
+    status.pgrp = task::pid_vnr(task.pgid()) as i32;
+    status.sid = task::pid_vnr(task.sid()) as i32;
+    status.utime = Timeval::from_nanos(task.sched_info.utime_ns());
+    status.stime = Timeval::from_nanos(task.sched_info.stime_ns());
+    status.reg = ptrace::get_regs(task);
+    status
+}
+
+fn prpsinfo(task: &TaskStruct, comm: &str, args: &[u8]) -> ElfPrpsinfo {
+    let mut psinfo: ElfPrpsinfo = unsafe { core::mem::zeroed() };
+    psinfo.sname = b'R';
+    let cred = task.cred();
+    psinfo.uid = cred.uid;
+    psinfo.gid = cred.gid;
+    psinfo.pid = task::pid_vnr(task.tgid()) as i32;
+    psinfo.ppid = ppid(task);
+    psinfo.pgrp = task::pid_vnr(task.pgid()) as i32;
+    psinfo.sid = task::pid_vnr(task.sid()) as i32;
+    let len = comm.len().min(COMM_LEN);
+    psinfo.fname[..len].copy_from_slice(&comm.as_bytes()[..len]);
+    // The arguments separated by spaces, ended by a nul.
+    let size = args.len().min(PSARGS_LEN - 1);
+    for (dst, &src) in psinfo.psargs.iter_mut().zip(&args[..size]) {
+        *dst = if src == 0 { b' ' } else { src };
+    }
+    psinfo
+}
+
+fn core_pattern_setup(value: &str) -> LinuxResult {
+    // No helper to pipe the core into.
+    if value.starts_with('|') {
+        return Err(LinuxError::EINVAL);
+    }
+    *CORE_PATTERN.lock() = String::from(value);
+    Ok(())
+}
+cmdline::kernel_param!("core_pattern", core_pattern_setup);
+
+fn coredump_filter_setup(value: &str) -> LinuxResult {
+    let value = value.trim_start_matches("0x");
+    let filter = usize::from_str_radix(value, 16).map_err(|_| LinuxError::EINVAL)?;
+    mm::set_default_coredump_filter(filter);
+    Ok(())
+}
+cmdline::kernel_param!("coredump_filter", coredump_filter_setup);
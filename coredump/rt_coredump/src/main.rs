@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::PanicInfo;
+use axerrno::AxError;
+use axtype::{O_RDONLY, PAGE_SIZE, RLIMIT_CORE, RLIM_INFINITY};
+use elf::abi::{ET_CORE, PT_LOAD, PT_NOTE};
+use fileops::AT_FDCWD;
+use mmap::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+use task::{SigInfo, SIGSEGV};
+
+/// The name of the core file by default, in the current directory.
+const CORE: &str = "core";
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_coredump]: ...");
+
+    fileops::init(cpu_id, dtb_pa);
+    task::alloc_mm();
+
+    // An area whose content is in the core.
+    let va = mmap::mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0)
+        .unwrap();
+    let mut fixup = 0;
+    let page = mmap::faultin_page(va, 0, 0, &mut fixup).unwrap();
+    let data = unsafe { core::slice::from_raw_parts_mut(page as *mut u8, PAGE_SIZE) };
+    data.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+
+    remove_core();
+    test_no_limit();
+    test_limit_reached();
+    test_core_file(va);
+
+    info!("[rt_coredump]: ok!");
+    axhal::misc::terminate();
+}
+
+fn set_core_limit(limit: u64) {
+    task::current().rlim.lock()[RLIMIT_CORE].rlim_cur = limit;
+}
+
+fn dump() -> bool {
+    let info = SigInfo {
+        signo: SIGSEGV as i32,
+        errno: 0,
+        code: 0,
+        tid: task::current().tid(),
+    };
+    coredump::do_coredump(&info)
+}
+
+/// The core file, if there is one.
+fn read_core() -> Option<Vec<u8>> {
+    let file = match fileops::openat(AT_FDCWD, CORE, O_RDONLY as usize, 0) {
+        Ok(file) => file,
+        Err(e) => {
+            assert_eq!(e, AxError::NotFound);
+            return None;
+        }
+    };
+    let mut core = Vec::new();
+    let mut buf = vec![0u8; PAGE_SIZE];
+    loop {
+        let size = file.read_at(core.len() as u64, &mut buf).unwrap();
+        if size == 0 {
+            return Some(core);
+        }
+        core.extend_from_slice(&buf[..size]);
+    }
+}
+
+fn remove_core() {
+    fileops::unlinkat(AT_FDCWD, CORE, 0);
+    assert!(read_core().is_none());
+}
+
+/// Nothing is dumped while RLIMIT_CORE is 0, as it is by default, nor if
+/// it's too small for anything.
+fn test_no_limit() {
+    assert_eq!(task::current().rlimit(RLIMIT_CORE), 0);
+    assert!(!dump());
+    assert!(read_core().is_none());
+
+    set_core_limit(100);
+    assert!(!dump());
+    assert!(read_core().is_none());
+    info!("[rt_coredump]: no core without RLIMIT_CORE ok!");
+}
+
+/// The dump is given up where it would go past the limit.
+fn test_limit_reached() {
+    set_core_limit(PAGE_SIZE as u64);
+    assert!(!dump());
+    let core = read_core().unwrap();
+    assert!(core.len() <= PAGE_SIZE);
+    remove_core();
+    info!("[rt_coredump]: limit reached ok!");
+}
+
+fn u16_at(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], off: usize) -> usize {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap()) as usize
+}
+
+/// With RLIMIT_CORE, the core is an ELF core file, with the notes first,
+/// and the content of the anonymous area at its segment.
+fn test_core_file(va: usize) {
+    set_core_limit(RLIM_INFINITY);
+    assert!(dump());
+    let core = read_core().unwrap();
+    assert_eq!(&core[..4], b"\x7fELF");
+    assert_eq!(u16_at(&core, 16), ET_CORE);
+
+    let phoff = u64_at(&core, 32);
+    let phentsize = u16_at(&core, 54) as usize;
+    let phnum = u16_at(&core, 56) as usize;
+    let phdrs: Vec<_> = (0..phnum).map(|i| &core[phoff + i * phentsize..]).collect();
+    assert_eq!(u32_at(phdrs[0], 0), PT_NOTE);
+    let load = phdrs.iter()
+        .find(|ph| u32_at(ph, 0) == PT_LOAD && u64_at(ph, 16) == va)
+        .unwrap();
+    let offset = u64_at(load, 8);
+    assert_eq!(u64_at(load, 32), PAGE_SIZE);
+    let dumped = &core[offset..offset + PAGE_SIZE];
+    assert!(dumped.iter().enumerate().all(|(i, b)| *b == i as u8));
+
+    set_core_limit(0);
+    remove_core();
+    info!("[rt_coredump]: core file ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
@@ -0,0 +1,90 @@
+//! Block devices on a region of memory, such as an image the boot loader
+//! loaded or a range the firmware reserved.
+
+use crate::BlockDriverOps;
+use driver_common::{BaseDriverOps, DevError, DevResult, DeviceType};
+
+const BLOCK_SIZE: usize = 512;
+
+/// A RAM disk on a memory region it does not own.
+pub struct Brd {
+    base: *mut u8,
+    size: usize,
+}
+
+unsafe impl Send for Brd {}
+unsafe impl Sync for Brd {}
+
+impl Brd {
+    /// Creates a RAM disk on the `size` bytes at `base`. A partial block at
+    /// the end is left out.
+    ///
+    /// # Safety
+    ///
+    /// The region must be mapped, and nothing else may use it for as long
+    /// as the RAM disk lives.
+    pub const unsafe fn new(base: *mut u8, size: usize) -> Self {
+        Self {
+            base,
+            size: size & !(BLOCK_SIZE - 1),
+        }
+    }
+
+    /// Returns the size of the RAM disk in bytes.
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    fn check(&self, block_id: u64, len: usize) -> DevResult<usize> {
+        let offset = block_id as usize * BLOCK_SIZE;
+        if offset + len > self.size {
+            return Err(DevError::Io);
+        }
+        if len % BLOCK_SIZE != 0 {
+            return Err(DevError::InvalidParam);
+        }
+        Ok(offset)
+    }
+}
+
+impl const BaseDriverOps for Brd {
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn device_name(&self) -> &str {
+        "brd"
+    }
+}
+
+impl BlockDriverOps for Brd {
+    #[inline]
+    fn num_blocks(&self) -> u64 {
+        (self.size / BLOCK_SIZE) as u64
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        let offset = self.check(block_id, buf.len())?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.base.add(offset), buf.as_mut_ptr(), buf.len());
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+        let offset = self.check(block_id, buf.len())?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), self.base.add(offset), buf.len());
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> DevResult {
+        Ok(())
+    }
+}
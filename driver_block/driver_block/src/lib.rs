@@ -5,6 +5,7 @@
 #![feature(const_trait_impl)]
 
 pub mod ramdisk;
+pub mod brd;
 
 #[cfg(feature = "bcm2835-sdhci")]
 pub mod bcm2835sdhci;
@@ -35,4 +36,44 @@ pub trait BlockDriverOps: BaseDriverOps {
 
     /// Flushes the device to write all pending data to the storage.
     fn flush(&mut self) -> DevResult;
+
+    /// The number of requests the device can have in flight at once through
+    /// [`submit_read`](Self::submit_read) and
+    /// [`submit_write`](Self::submit_write). Devices which only do the
+    /// synchronous calls above leave it at 0.
+    fn queue_depth(&self) -> usize {
+        0
+    }
+
+    /// Posts a read of `len` bytes from the given block into `buf`, without
+    /// waiting for it, and returns a token that
+    /// [`poll_completion`](Self::poll_completion) gives back once it is done.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must stay valid and untouched until the request is completed.
+    unsafe fn submit_read(&mut self, _block_id: u64, _buf: *mut u8, _len: usize) -> DevResult<u16> {
+        Err(DevError::Unsupported)
+    }
+
+    /// Posts a write of `len` bytes at `buf` to the given block, without
+    /// waiting for it, as [`submit_read`](Self::submit_read) does.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must stay valid and untouched until the request is completed.
+    unsafe fn submit_write(&mut self, _block_id: u64, _buf: *const u8, _len: usize) -> DevResult<u16> {
+        Err(DevError::Unsupported)
+    }
+
+    /// Takes a request the device has completed, with its result.
+    fn poll_completion(&mut self) -> Option<(u16, DevResult)> {
+        None
+    }
+
+    /// Acknowledges an interrupt of the device, and returns whether it was
+    /// raised by it.
+    fn ack_interrupt(&mut self) -> bool {
+        false
+    }
 }
@@ -8,6 +8,8 @@
 //! - [`driver_display`][3]: Common traits and types for graphics display drivers.
 //! - [`driver_net`][4]: Common traits and types for network (NIC) drivers.
 //!
+//! Character devices only need [`CharDriverOps`] of this crate.
+//!
 //! [1]: https://github.com/rcore-os/arceos
 //! [2]: ../driver_block/index.html
 //! [3]: ../driver_display/index.html
@@ -62,3 +64,16 @@ pub trait BaseDriverOps: Send + Sync {
     /// The type of the device.
     fn device_type(&self) -> DeviceType;
 }
+
+/// Operations of character devices, such as consoles.
+pub trait CharDriverOps: BaseDriverOps {
+    /// Writes all of `buf` to the device.
+    fn write_bytes(&mut self, buf: &[u8]) -> DevResult;
+
+    /// Takes the next byte received, if there is one.
+    fn getchar(&mut self) -> DevResult<Option<u8>>;
+
+    /// Acknowledges an interrupt of the device. Returns whether it had
+    /// raised one.
+    fn ack_interrupt(&mut self) -> bool;
+}
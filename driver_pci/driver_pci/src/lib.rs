@@ -1,7 +1,8 @@
 //! Structures and functions for PCI bus operations.
 //!
-//! Currently, it just re-exports structures from the crate [virtio-drivers][1]
-//! and its module [`virtio_drivers::transport::pci::bus`][2].
+//! Mostly, it re-exports structures from the crate [virtio-drivers][1] and
+//! its module [`virtio_drivers::transport::pci::bus`][2], with the setup of
+//! MSI on top.
 //!
 //! [1]: https://docs.rs/virtio-drivers/latest/virtio_drivers/
 //! [2]: https://docs.rs/virtio-drivers/latest/virtio_drivers/transport/pci/bus/index.html
@@ -13,6 +14,53 @@ pub use virtio_drivers::transport::pci::bus::{
     CapabilityInfo, Command, DeviceFunction, DeviceFunctionInfo, PciRoot, Status,
 };
 
+/// Id of the MSI capability.
+const PCI_CAP_ID_MSI: u8 = 0x05;
+
+/// Bits of the message control of the MSI capability.
+const PCI_MSI_FLAGS_ENABLE: u16 = 0x0001;
+const PCI_MSI_FLAGS_QSIZE: u16 = 0x0070;
+const PCI_MSI_FLAGS_64BIT: u16 = 0x0080;
+
+/// Whether the device at `bdf` has an MSI capability.
+pub fn has_msi(root: &PciRoot, bdf: DeviceFunction) -> bool {
+    root.capabilities(bdf).any(|cap| cap.id == PCI_CAP_ID_MSI)
+}
+
+/// Enables the MSI of the device at `bdf`, with a single message: it
+/// writes `data` at `address` to raise its interrupt, and its INTx line is
+/// disabled. Returns false, leaving it on INTx, if it has no MSI
+/// capability, or can't reach `address`.
+pub fn enable_msi(root: &mut PciRoot, bdf: DeviceFunction, address: u64, data: u16) -> bool {
+    let Some(cap) = root.capabilities(bdf).find(|cap| cap.id == PCI_CAP_ID_MSI) else {
+        return false;
+    };
+    let control = cap.private_header;
+    let is_64bit = (control & PCI_MSI_FLAGS_64BIT) != 0;
+    if !is_64bit && (address >> 32) != 0 {
+        return false;
+    }
+
+    root.config_write_word(bdf, cap.offset + 4, address as u32);
+    let data_offset = if is_64bit {
+        root.config_write_word(bdf, cap.offset + 8, (address >> 32) as u32);
+        cap.offset + 12
+    } else {
+        cap.offset + 8
+    };
+    // The message data is the low half of its dword.
+    let word = root.config_read_word(bdf, data_offset);
+    root.config_write_word(bdf, data_offset, (word & 0xffff_0000) | data as u32);
+
+    let control = (control & !PCI_MSI_FLAGS_QSIZE) | PCI_MSI_FLAGS_ENABLE;
+    let word = root.config_read_word(bdf, cap.offset);
+    root.config_write_word(bdf, cap.offset, (word & 0xffff) | (control as u32) << 16);
+
+    let (_status, cmd) = root.get_status_command(bdf);
+    root.set_command(bdf, cmd | Command::INTERRUPT_DISABLE);
+    true
+}
+
 /// Used to allocate MMIO regions for PCI BARs.
 pub struct PciRangeAllocator {
     _start: u64,
@@ -1,11 +1,29 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use crate::as_dev_err;
 use driver_block::BlockDriverOps;
 use driver_common::{BaseDriverOps, DevResult, DeviceType};
-use virtio_drivers::{device::blk::VirtIOBlk as InnerDev, transport::Transport, Hal};
+use virtio_drivers::device::blk::{BlkReq, BlkResp, VirtIOBlk as InnerDev};
+use virtio_drivers::{transport::Transport, Hal};
+
+/// A request posted to the virtqueue. The header and status the device
+/// reads and writes are boxed, so they stay put until it is completed.
+struct InFlight {
+    req: Box<BlkReq>,
+    resp: Box<BlkResp>,
+    buf: *mut u8,
+    len: usize,
+    write: bool,
+}
 
 /// The VirtIO block device driver.
+///
+/// Besides the synchronous calls, requests can be posted to the virtqueue
+/// without waiting, as many as it has room for, each taking one to three
+/// descriptors, and completed when the device has used them.
 pub struct VirtIoBlkDev<H: Hal, T: Transport> {
     inner: InnerDev<H, T>,
+    inflight: BTreeMap<u16, InFlight>,
 }
 
 unsafe impl<H: Hal, T: Transport> Send for VirtIoBlkDev<H, T> {}
@@ -17,6 +35,7 @@ impl<H: Hal, T: Transport> VirtIoBlkDev<H, T> {
     pub fn try_new(transport: T) -> DevResult<Self> {
         Ok(Self {
             inner: InnerDev::new(transport).map_err(as_dev_err)?,
+            inflight: BTreeMap::new(),
         })
     }
 }
@@ -57,4 +76,50 @@ impl<H: Hal, T: Transport> BlockDriverOps for VirtIoBlkDev<H, T> {
     fn flush(&mut self) -> DevResult {
         Ok(())
     }
+
+    fn queue_depth(&self) -> usize {
+        // A request takes up to three descriptors.
+        self.inner.virt_queue_size() as usize / 3
+    }
+
+    unsafe fn submit_read(&mut self, block_id: u64, buf: *mut u8, len: usize) -> DevResult<u16> {
+        let mut req = Box::new(BlkReq::default());
+        let mut resp = Box::new(BlkResp::default());
+        let slice = core::slice::from_raw_parts_mut(buf, len);
+        let token = self.inner
+            .read_block_nb(block_id as _, &mut req, slice, &mut resp)
+            .map_err(as_dev_err)?;
+        self.inflight.insert(token, InFlight { req, resp, buf, len, write: false });
+        Ok(token)
+    }
+
+    unsafe fn submit_write(&mut self, block_id: u64, buf: *const u8, len: usize) -> DevResult<u16> {
+        let mut req = Box::new(BlkReq::default());
+        let mut resp = Box::new(BlkResp::default());
+        let slice = core::slice::from_raw_parts(buf, len);
+        let token = self.inner
+            .write_block_nb(block_id as _, &mut req, slice, &mut resp)
+            .map_err(as_dev_err)?;
+        self.inflight.insert(token, InFlight { req, resp, buf: buf as *mut u8, len, write: true });
+        Ok(token)
+    }
+
+    fn poll_completion(&mut self) -> Option<(u16, DevResult)> {
+        let token = self.inner.peek_used()?;
+        let mut f = self.inflight.remove(&token)?;
+        let result = unsafe {
+            if f.write {
+                let buf = core::slice::from_raw_parts(f.buf, f.len);
+                self.inner.complete_write_block(token, &f.req, buf, &mut f.resp)
+            } else {
+                let buf = core::slice::from_raw_parts_mut(f.buf, f.len);
+                self.inner.complete_read_block(token, &f.req, buf, &mut f.resp)
+            }
+        };
+        Some((token, result.map_err(as_dev_err)))
+    }
+
+    fn ack_interrupt(&mut self) -> bool {
+        self.inner.ack_interrupt()
+    }
 }
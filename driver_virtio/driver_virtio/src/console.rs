@@ -0,0 +1,54 @@
+use crate::as_dev_err;
+use driver_common::{BaseDriverOps, CharDriverOps, DevResult, DeviceType};
+use virtio_drivers::device::console::VirtIOConsole as InnerDev;
+use virtio_drivers::{transport::Transport, Hal};
+
+/// The VirtIO console device driver.
+///
+/// Only port 0 is driven, as a single stream, even if the device offers
+/// the multiport feature.
+pub struct VirtIoConsoleDev<H: Hal, T: Transport> {
+    inner: InnerDev<H, T>,
+}
+
+unsafe impl<H: Hal, T: Transport> Send for VirtIoConsoleDev<H, T> {}
+unsafe impl<H: Hal, T: Transport> Sync for VirtIoConsoleDev<H, T> {}
+
+impl<H: Hal, T: Transport> VirtIoConsoleDev<H, T> {
+    /// Creates a new driver instance and initializes the device, or returns
+    /// an error if any step fails.
+    pub fn try_new(transport: T) -> DevResult<Self> {
+        Ok(Self {
+            inner: InnerDev::new(transport).map_err(as_dev_err)?,
+        })
+    }
+}
+
+impl<H: Hal, T: Transport> const BaseDriverOps for VirtIoConsoleDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-console"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+}
+
+impl<H: Hal, T: Transport> CharDriverOps for VirtIoConsoleDev<H, T> {
+    fn write_bytes(&mut self, buf: &[u8]) -> DevResult {
+        for &c in buf {
+            self.inner.send(c).map_err(as_dev_err)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn getchar(&mut self) -> DevResult<Option<u8>> {
+        self.inner.recv(true).map_err(as_dev_err)
+    }
+
+    #[inline]
+    fn ack_interrupt(&mut self) -> bool {
+        self.inner.ack_interrupt().unwrap_or(false)
+    }
+}
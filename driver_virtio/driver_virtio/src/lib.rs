@@ -14,8 +14,14 @@
 #![feature(const_trait_impl)]
 #![feature(doc_auto_cfg)]
 
+extern crate alloc;
+
+mod transport;
+
 #[cfg(feature = "block")]
 pub mod blk;
+#[cfg(feature = "console")]
+mod console;
 #[cfg(feature = "gpu")]
 mod gpu;
 #[cfg(feature = "net")]
@@ -23,6 +29,8 @@ mod net;
 
 #[cfg(feature = "block")]
 pub use self::blk::VirtIoBlkDev;
+#[cfg(feature = "console")]
+pub use self::console::VirtIoConsoleDev;
 #[cfg(feature = "gpu")]
 pub use self::gpu::VirtIoGpuDev;
 #[cfg(feature = "net")]
@@ -31,6 +39,7 @@ pub use self::net::VirtIoNetDev;
 pub use virtio_drivers::transport::pci::bus as pci;
 pub use virtio_drivers::transport::{mmio::MmioTransport, pci::PciTransport, Transport};
 pub use virtio_drivers::{BufferDirection, Hal as VirtIoHal, PhysAddr};
+pub use self::transport::VirtIoTransport;
 
 use self::pci::{DeviceFunction, DeviceFunctionInfo, PciRoot};
 use driver_common::{DevError, DeviceType};
@@ -75,6 +84,7 @@ const fn as_dev_type(t: VirtIoDevType) -> Option<DeviceType> {
         Block => Some(DeviceType::Block),
         Network => Some(DeviceType::Net),
         GPU => Some(DeviceType::Display),
+        Console => Some(DeviceType::Char),
         _ => None,
     }
 }
@@ -67,7 +67,10 @@ impl<H: Hal, T: Transport, const QS: usize> VirtIoNetDev<H, T, QS> {
             dev.free_tx_bufs.push(tx_buf);
         }
 
-        // 3. Return the driver instance.
+        // 3. Have used rx buffers raise interrupts.
+        dev.inner.enable_interrupts();
+
+        // 4. Return the driver instance.
         Ok(dev)
     }
 }
@@ -190,4 +193,8 @@ impl<H: Hal, T: Transport, const QS: usize> NetDriverOps for VirtIoNetDev<H, T,
         // 2. Return the buffer.
         Ok(net_buf.into_buf_ptr())
     }
+
+    fn ack_interrupt(&mut self) -> bool {
+        self.inner.ack_interrupt()
+    }
 }
@@ -0,0 +1,85 @@
+use core::ptr::NonNull;
+use virtio_drivers::transport::{mmio::MmioTransport, pci::PciTransport};
+use virtio_drivers::transport::{DeviceStatus, DeviceType, Transport};
+use virtio_drivers::{PhysAddr, Result};
+
+/// The transport of a device on either bus, for the kernels that probe the
+/// VirtIO MMIO slots and the PCI bus both, with one type per device kind.
+pub enum VirtIoTransport {
+    Mmio(MmioTransport),
+    Pci(PciTransport),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $t:ident => $e:expr) => {
+        match $self {
+            VirtIoTransport::Mmio($t) => $e,
+            VirtIoTransport::Pci($t) => $e,
+        }
+    };
+}
+
+impl Transport for VirtIoTransport {
+    fn device_type(&self) -> DeviceType {
+        dispatch!(self, t => t.device_type())
+    }
+
+    fn read_device_features(&mut self) -> u64 {
+        dispatch!(self, t => t.read_device_features())
+    }
+
+    fn write_driver_features(&mut self, driver_features: u64) {
+        dispatch!(self, t => t.write_driver_features(driver_features))
+    }
+
+    fn max_queue_size(&self) -> u32 {
+        dispatch!(self, t => t.max_queue_size())
+    }
+
+    fn notify(&mut self, queue: u16) {
+        dispatch!(self, t => t.notify(queue))
+    }
+
+    fn get_status(&self) -> DeviceStatus {
+        dispatch!(self, t => t.get_status())
+    }
+
+    fn set_status(&mut self, status: DeviceStatus) {
+        dispatch!(self, t => t.set_status(status))
+    }
+
+    fn set_guest_page_size(&mut self, guest_page_size: u32) {
+        dispatch!(self, t => t.set_guest_page_size(guest_page_size))
+    }
+
+    fn requires_legacy_layout(&self) -> bool {
+        dispatch!(self, t => t.requires_legacy_layout())
+    }
+
+    fn queue_set(
+        &mut self,
+        queue: u16,
+        size: u32,
+        descriptors: PhysAddr,
+        driver_area: PhysAddr,
+        device_area: PhysAddr,
+    ) {
+        dispatch!(self, t => t.queue_set(queue, size, descriptors, driver_area, device_area))
+    }
+
+    fn queue_unset(&mut self, queue: u16) {
+        dispatch!(self, t => t.queue_unset(queue))
+    }
+
+    fn queue_used(&mut self, queue: u16) -> bool {
+        dispatch!(self, t => t.queue_used(queue))
+    }
+
+    fn ack_interrupt(&mut self) -> bool {
+        dispatch!(self, t => t.ack_interrupt())
+    }
+
+    fn config_space<T: 'static>(&self) -> Result<NonNull<T>> {
+        dispatch!(self, t => t.config_space())
+    }
+}
@@ -15,7 +15,7 @@ const BLOCK_SIZE: usize = 0x200;        // 512
 
 /// Entry
 #[no_mangle]
-pub extern "Rust" fn runtime_main(cpu_id: usize, _dtb_pa: usize) {
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
     axlog2::init("debug");
     info!("[rt_driver_virtio]: ...");
 
@@ -38,7 +38,7 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, _dtb_pa: usize) {
     info!("Initialize kernel page table...");
     page_table::init();
 
-    let mut alldevs = axdriver::init_drivers2();
+    let mut alldevs = axdriver::init_drivers2(dtb_pa);
     let mut disk = alldevs.block.take_one().unwrap();
 
     assert_eq!(disk.device_type(), DeviceType::Block);
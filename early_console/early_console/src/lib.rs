@@ -2,25 +2,46 @@
 
 mod platform;
 mod time;
+mod uart;
 
-pub use platform::{getchar, putchar};
-
-pub fn init() {
+/// Initializes the console of the platform, and moves it to the UART of
+/// the `stdout-path` of the device tree at `dtb_va` if there is one, see
+/// [`uart::init`].
+pub fn init(dtb_va: usize, map: impl FnOnce(usize, usize) -> Option<usize>) {
     #[cfg(target_arch = "x86_64")]
     platform::console_init();
+    uart::init(dtb_va, map);
+}
+
+/// Writes a byte to the console.
+pub fn putchar(c: u8) {
+    if uart::active() {
+        uart::putchar(c);
+    } else {
+        platform::putchar(c);
+    }
+}
+
+/// Reads a byte from the console, or returns [`None`] if no input is available.
+pub fn getchar() -> Option<u8> {
+    if uart::active() {
+        uart::getchar()
+    } else {
+        platform::getchar()
+    }
 }
 
 /// Write a slice of bytes to the console.
 pub fn write_bytes(bytes: &[u8]) {
     for c in bytes {
-        platform::putchar(*c);
+        putchar(*c);
     }
 }
 
 pub fn read_bytes(bytes: &mut [u8]) -> usize {
     let mut read_len = 0;
     while read_len < bytes.len() {
-        if let Some(c) = platform::getchar() {
+        if let Some(c) = getchar() {
             bytes[read_len] = c;
             read_len += 1;
         } else {
@@ -0,0 +1,38 @@
+pub mod time;
+
+/// The PL011 UART, at its address in the linear mapping.
+const UART_BASE: usize = axconfig::PHYS_VIRT_OFFSET + axconfig::UART_PADDR;
+
+/// Data register.
+const UARTDR: usize = 0x00;
+/// Flag register.
+const UARTFR: usize = 0x18;
+/// Receive FIFO empty.
+const UARTFR_RXFE: u32 = 1 << 4;
+/// Transmit FIFO full.
+const UARTFR_TXFF: u32 = 1 << 5;
+
+fn uart_reg(offset: usize) -> *mut u32 {
+    (UART_BASE + offset) as *mut u32
+}
+
+/// Writes a byte to the console.
+pub fn putchar(c: u8) {
+    unsafe {
+        while uart_reg(UARTFR).read_volatile() & UARTFR_TXFF != 0 {
+            core::hint::spin_loop();
+        }
+        uart_reg(UARTDR).write_volatile(c as u32);
+    }
+}
+
+/// Reads a byte from the console, or returns [`None`] if no input is available.
+pub fn getchar() -> Option<u8> {
+    unsafe {
+        if uart_reg(UARTFR).read_volatile() & UARTFR_RXFE != 0 {
+            None
+        } else {
+            Some(uart_reg(UARTDR).read_volatile() as u8)
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use core::arch::asm;
+
+/// Returns the current clock time in hardware ticks.
+#[inline]
+pub fn current_ticks() -> u64 {
+    let ticks: u64;
+    unsafe { asm!("mrs {}, cntpct_el0", out(reg) ticks) };
+    ticks
+}
+
+/// Converts hardware ticks to nanoseconds.
+#[inline]
+pub fn ticks_to_nanos(ticks: u64) -> u64 {
+    let freq: u64;
+    unsafe { asm!("mrs {}, cntfrq_el0", out(reg) freq) };
+    (ticks as u128 * crate::time::NANOS_PER_SEC as u128 / freq as u128) as u64
+}
@@ -1,11 +1,5 @@
 //! Platform-specific operations.
 
-cfg_if::cfg_if! {
-    if #[cfg(target_arch = "aarch64")]{
-        mod aarch64_common;
-    }
-}
-
 cfg_if::cfg_if! {
     if #[cfg(all(target_arch = "x86_64", platform_family = "x86-pc"))] {
         mod x86_pc;
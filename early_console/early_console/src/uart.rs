@@ -0,0 +1,132 @@
+//! The UART of the `stdout-path` of /chosen in the device tree, which the
+//! console takes over from that of the platform once it is mapped.
+
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// The driver in use: none, so that of the platform, or one of these.
+const NONE: u8 = 0;
+const NS16550: u8 = 1;
+const PL011: u8 = 2;
+
+static KIND: AtomicU8 = AtomicU8::new(NONE);
+/// The virtual address of its registers.
+static BASE: AtomicUsize = AtomicUsize::new(0);
+/// For a 16550, the log2 of the stride of its registers, and their width
+/// in bytes.
+static REG_SHIFT: AtomicUsize = AtomicUsize::new(0);
+static REG_WIDTH: AtomicUsize = AtomicUsize::new(1);
+
+/// 16550: receive buffer and transmit holding registers, line status.
+const UART_RX: usize = 0;
+const UART_TX: usize = 0;
+const UART_LSR: usize = 5;
+const UART_LSR_DR: u32 = 0x01;
+const UART_LSR_THRE: u32 = 0x20;
+
+/// PL011: data and flag registers.
+const UARTDR: usize = 0x00;
+const UARTFR: usize = 0x18;
+const UARTFR_RXFE: u32 = 1 << 4;
+const UARTFR_TXFF: u32 = 1 << 5;
+
+fn ns16550_read(reg: usize) -> u32 {
+    let addr = BASE.load(Ordering::Relaxed) + (reg << REG_SHIFT.load(Ordering::Relaxed));
+    unsafe {
+        match REG_WIDTH.load(Ordering::Relaxed) {
+            4 => (addr as *const u32).read_volatile(),
+            _ => (addr as *const u8).read_volatile() as u32,
+        }
+    }
+}
+
+fn ns16550_write(reg: usize, val: u32) {
+    let addr = BASE.load(Ordering::Relaxed) + (reg << REG_SHIFT.load(Ordering::Relaxed));
+    unsafe {
+        match REG_WIDTH.load(Ordering::Relaxed) {
+            4 => (addr as *mut u32).write_volatile(val),
+            _ => (addr as *mut u8).write_volatile(val as u8),
+        }
+    }
+}
+
+fn pl011_reg(offset: usize) -> *mut u32 {
+    (BASE.load(Ordering::Relaxed) + offset) as *mut u32
+}
+
+/// Whether the console is on the UART of the device tree.
+pub(crate) fn active() -> bool {
+    KIND.load(Ordering::Acquire) != NONE
+}
+
+pub(crate) fn putchar(c: u8) {
+    match KIND.load(Ordering::Acquire) {
+        NS16550 => {
+            while ns16550_read(UART_LSR) & UART_LSR_THRE == 0 {
+                core::hint::spin_loop();
+            }
+            ns16550_write(UART_TX, c as u32);
+        }
+        PL011 => unsafe {
+            while pl011_reg(UARTFR).read_volatile() & UARTFR_TXFF != 0 {
+                core::hint::spin_loop();
+            }
+            pl011_reg(UARTDR).write_volatile(c as u32);
+        },
+        _ => {}
+    }
+}
+
+pub(crate) fn getchar() -> Option<u8> {
+    match KIND.load(Ordering::Acquire) {
+        NS16550 => (ns16550_read(UART_LSR) & UART_LSR_DR != 0).then(|| ns16550_read(UART_RX) as u8),
+        PL011 => unsafe {
+            if pl011_reg(UARTFR).read_volatile() & UARTFR_RXFE != 0 {
+                None
+            } else {
+                Some(pl011_reg(UARTDR).read_volatile() as u8)
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Takes the UART of the `stdout-path` of the device tree at `dtb_va`, if
+/// there is a driver for it here, and if `map` maps its registers, which
+/// it is given the physical address and the size of. The console stays on
+/// that of the platform otherwise: the SBI on RISC-V, the UART of the
+/// platform config elsewhere.
+pub fn init(dtb_va: usize, map: impl FnOnce(usize, usize) -> Option<usize>) {
+    if dtb_va == 0 {
+        return;
+    }
+    let Ok(dt) = axdtb::DeviceTree::init(dtb_va) else {
+        return;
+    };
+    let Some(node) = dt.stdout_node() else {
+        return;
+    };
+    let kind = node.compatible()
+        .find_map(|compatible| match compatible {
+            "ns16550a" | "ns16550" | "snps,dw-apb-uart" => Some(NS16550),
+            "arm,pl011" => Some(PL011),
+            _ => None,
+        })
+        .unwrap_or(NONE);
+    if kind == NONE {
+        return;
+    }
+    let Some((paddr, size)) = node.reg() else {
+        return;
+    };
+    let Some(base) = map(paddr, size) else {
+        return;
+    };
+    if kind == NS16550 {
+        let width = node.prop_u32("reg-io-width").unwrap_or(1) as usize;
+        REG_SHIFT.store(node.prop_u32("reg-shift").unwrap_or(0) as usize, Ordering::Relaxed);
+        REG_WIDTH.store(width, Ordering::Relaxed);
+    }
+    BASE.store(base, Ordering::Relaxed);
+    KIND.store(kind, Ordering::Release);
+    log::info!("console: {} at {:#x}", dt.stdout_path().unwrap_or_default(), paddr);
+}
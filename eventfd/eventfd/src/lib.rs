@@ -0,0 +1,155 @@
+//! Event file descriptors.
+//!
+//! An [`EventFd`] is a 64-bit counter behind a file: write() adds to it
+//! and read() takes it, or only one of it in semaphore mode, sleeping
+//! while it is zero. Writes that would overflow it sleep until it has
+//! been read. It is readable whenever the counter is not zero, which is
+//! what makes it a cheap wake-up for poll loops.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfile::fops::File;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::alloc_ino;
+use axio::PollState;
+use axtype::{O_CLOEXEC, O_NONBLOCK};
+use capability::Cap;
+use mutex::Mutex;
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+pub const EFD_SEMAPHORE: usize = 1;
+pub const EFD_CLOEXEC: usize = O_CLOEXEC as usize;
+pub const EFD_NONBLOCK: usize = O_NONBLOCK as usize;
+
+/// The counter never reaches it, so a write of it is always invalid.
+const EFD_MAX: u64 = u64::MAX - 1;
+
+pub struct EventFd {
+    count: SpinNoIrq<u64>,
+    semaphore: bool,
+    nonblock: AtomicBool,
+    /// Readers waiting for the counter to be set, and writers for it to
+    /// make room.
+    waiters: WaitQueue,
+    ino: usize,
+    uid: u32,
+    gid: u32,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, flags: usize, uid: u32, gid: u32) -> Self {
+        Self {
+            count: SpinNoIrq::new(initval),
+            semaphore: (flags & EFD_SEMAPHORE) != 0,
+            nonblock: AtomicBool::new((flags & EFD_NONBLOCK) != 0),
+            waiters: WaitQueue::new(),
+            ino: alloc_ino(),
+            uid,
+            gid,
+        }
+    }
+
+    /// Switches between blocking and nonblocking mode.
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    /// The value of the counter.
+    pub fn count(&self) -> u64 {
+        *self.count.lock()
+    }
+}
+
+impl VfsNodeOps for EventFd {
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_file(0, 0, self.uid, self.gid, 0o600))
+    }
+
+    fn read_at(&self, _pos: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.len() < 8 {
+            return Err(AxError::InvalidInput);
+        }
+        let value = loop {
+            let mut count = self.count.lock();
+            if *count != 0 {
+                let value = if self.semaphore { 1 } else { *count };
+                *count -= value;
+                break value;
+            }
+            drop(count);
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(AxError::WouldBlock);
+            }
+            self.waiters.wait_until_interruptible(|| self.count() != 0)?;
+        };
+        self.waiters.notify_all(false);
+        buf[..8].copy_from_slice(&value.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn write_at(&self, _pos: u64, buf: &[u8]) -> VfsResult<usize> {
+        if buf.len() < 8 {
+            return Err(AxError::InvalidInput);
+        }
+        let value = u64::from_ne_bytes(buf[..8].try_into().unwrap());
+        if value > EFD_MAX {
+            return Err(AxError::InvalidInput);
+        }
+        loop {
+            let mut count = self.count.lock();
+            if EFD_MAX - *count >= value {
+                *count += value;
+                break;
+            }
+            drop(count);
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(AxError::WouldBlock);
+            }
+            self.waiters.wait_until_interruptible(|| EFD_MAX - self.count() >= value)?;
+        }
+        if value != 0 {
+            self.waiters.notify_all(false);
+        }
+        Ok(8)
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        let count = self.count();
+        Ok(PollState {
+            readable: count != 0,
+            writable: count < EFD_MAX,
+        })
+    }
+
+    fn set_nonblock(&self, nonblock: bool) -> VfsResult {
+        EventFd::set_nonblock(self, nonblock);
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// Creates an event file whose counter starts at `initval`.
+pub fn eventfd2(initval: usize, flags: usize) -> LinuxResult<usize> {
+    info!("eventfd2: initval {} flags {:#x}", initval, flags);
+    if (flags & !(EFD_SEMAPHORE | EFD_CLOEXEC | EFD_NONBLOCK)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let current = task::current();
+    let efd = EventFd::new(initval as u32 as u64, flags, current.fsuid(), current.fsgid());
+    let mut file = File::new(Arc::new(efd), Cap::READ | Cap::WRITE);
+    file.set_status_flags((flags & EFD_NONBLOCK) as i32);
+    fileops::install_file(Arc::new(Mutex::new(file)), flags & EFD_CLOEXEC)
+}
@@ -13,8 +13,13 @@ use axtype::get_user_str_vec;
 pub fn kernel_execve(filename: &str, argv: Vec<String>, envp: Vec<String>) -> LinuxResult<usize> {
     info!("kernel_execve... {}", filename);
 
+    let coredump_filter = task::current().try_mm().map(|mm| mm.read().coredump_filter);
     task::alloc_mm();
+    if let Some(filter) = coredump_filter {
+        task::current().mm().write().coredump_filter = filter;
+    }
 
+    task::unshare_files();
     do_close_on_exec()?;
 
     let (entry, sp) = bprm_loader::execve(filename, 0, argv, envp)?;
@@ -26,16 +31,9 @@ pub fn kernel_execve(filename: &str, argv: Vec<String>, envp: Vec<String>) -> Li
 
 fn do_close_on_exec() -> LinuxResult {
     let current = task::current();
-    let mut locked_ft = current.filetable.lock();
-    let mut set = locked_ft.close_on_exec();
-    let mut fd = 0;
-    while set != 0 {
-        if (set & 1) == 1 {
-            locked_ft.remove(fd);
-        }
-        set >>= 1;
-        fd += 1;
-    }
+    let files = current.filetable.lock().take_close_on_exec();
+    // Closed out of the lock of the table.
+    drop(files);
     Ok(())
 }
 
@@ -3,6 +3,8 @@
 mod inode;
 mod typeperm;
 pub mod directory_entry;
+pub mod xattr;
+pub mod acl;
 
 pub use inode::{Inode, gid_t, uid_t};
 pub use typeperm::{TypePerm, Mode, SFlag};
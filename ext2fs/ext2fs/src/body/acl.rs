@@ -0,0 +1,115 @@
+//! This file describe the POSIX ACL model, as stored in the
+//! system.posix_acl_access and system.posix_acl_default attributes
+
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+
+// The xattr syscalls pass an ACL as a version, then entries of a tag, the
+// permissions and an id. Ext2 stores it with another version, and without
+// the id in the entries of the tags that have none.
+
+/// Version of an ACL passed by the xattr syscalls
+const ACL_XATTR_VERSION: u32 = 2;
+/// Version of an ACL stored by ext2
+const EXT2_ACL_VERSION: u32 = 1;
+
+/// Id of the entries of the tags that have none
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+/// Tags of the entries
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+/// Size of an entry with its id, then without it
+const ACL_ENTRY_SIZE: usize = 8;
+const ACL_SHORT_ENTRY_SIZE: usize = 4;
+
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// The (tag, permissions, id) entries of an ACL as the xattr syscalls
+/// pass it. EINVAL if it is malformed
+fn xattr_entries(value: &[u8]) -> LinuxResult<impl Iterator<Item = (u16, u16, u32)> + '_> {
+    if value.len() < 4
+        || (value.len() - 4) % ACL_ENTRY_SIZE != 0
+        || read_u32(value, 0) != ACL_XATTR_VERSION
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(value[4..]
+        .chunks(ACL_ENTRY_SIZE)
+        .map(|e| (read_u16(e, 0), read_u16(e, 2), read_u32(e, 4))))
+}
+
+/// Convert an ACL from the format of the xattr syscalls to the one of ext2
+pub fn acl_to_disk(value: &[u8]) -> LinuxResult<Vec<u8>> {
+    let mut disk = Vec::from(EXT2_ACL_VERSION.to_le_bytes());
+    for (tag, perm, id) in xattr_entries(value)? {
+        disk.extend_from_slice(&tag.to_le_bytes());
+        disk.extend_from_slice(&perm.to_le_bytes());
+        match tag {
+            ACL_USER_OBJ | ACL_GROUP_OBJ | ACL_MASK | ACL_OTHER => {}
+            ACL_USER | ACL_GROUP => disk.extend_from_slice(&id.to_le_bytes()),
+            _ => return Err(LinuxError::EINVAL),
+        }
+    }
+    Ok(disk)
+}
+
+/// Convert an ACL from the format of ext2 to the one of the xattr syscalls
+pub fn acl_from_disk(disk: &[u8]) -> LinuxResult<Vec<u8>> {
+    if disk.len() < 4 || read_u32(disk, 0) != EXT2_ACL_VERSION {
+        return Err(LinuxError::EIO);
+    }
+    let mut value = Vec::from(ACL_XATTR_VERSION.to_le_bytes());
+    let mut off = 4;
+    while off < disk.len() {
+        if off + ACL_SHORT_ENTRY_SIZE > disk.len() {
+            return Err(LinuxError::EIO);
+        }
+        let (id, size) = match read_u16(disk, off) {
+            ACL_USER_OBJ | ACL_GROUP_OBJ | ACL_MASK | ACL_OTHER => {
+                (ACL_UNDEFINED_ID, ACL_SHORT_ENTRY_SIZE)
+            }
+            ACL_USER | ACL_GROUP if off + ACL_ENTRY_SIZE <= disk.len() => {
+                (read_u32(disk, off + 4), ACL_ENTRY_SIZE)
+            }
+            _ => return Err(LinuxError::EIO),
+        };
+        value.extend_from_slice(&disk[off..off + ACL_SHORT_ENTRY_SIZE]);
+        value.extend_from_slice(&id.to_le_bytes());
+        off += size;
+    }
+    Ok(value)
+}
+
+/// The permission bits of the mode that an access ACL, as the xattr
+/// syscalls pass it, amounts to: the owner ones from its user entry, the
+/// group ones from its mask entry, or else from its group entry, and the
+/// other ones from its other entry
+pub fn acl_mode(value: &[u8]) -> LinuxResult<u16> {
+    let (mut user, mut group, mut mask, mut other) = (None, None, None, None);
+    for (tag, perm, _) in xattr_entries(value)? {
+        let perm = Some(perm & 0o7);
+        match tag {
+            ACL_USER_OBJ => user = perm,
+            ACL_GROUP_OBJ => group = perm,
+            ACL_MASK => mask = perm,
+            ACL_OTHER => other = perm,
+            _ => {}
+        }
+    }
+    match (user, mask.or(group), other) {
+        (Some(user), Some(group), Some(other)) => Ok(user << 6 | group << 3 | other),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
@@ -5,8 +5,6 @@ use super::TypePerm;
 
 use bitflags::bitflags;
 
-use core::mem::size_of;
-
 #[allow(non_camel_case_types)]
 pub type gid_t = u32;
 #[allow(non_camel_case_types)]
@@ -81,7 +79,7 @@ pub struct Inode {
     generation_number: u32,
     /// In Ext2 version 0, this field is reserved. In version >= 1, Extended attribute block (File ACL).
     /*104 	107 	4*/
-    extended_attribute_block: u32,
+    pub extended_attribute_block: Block,
     /// In Ext2 version 0, this field is reserved. In version >= 1, Upper 32 bits of file size (if feature bit set) if it's a file, Directory ACL if it's a directory
     /*108 	111 	4*/
     pub upper_size: u32,
@@ -152,58 +150,32 @@ impl Inode {
         }
     }
 
-    pub fn update_size(&mut self, new_size: u64, block_size: u32) {
+    pub fn update_size(&mut self, new_size: u64) {
         self.low_size = new_size as u32;
         self.upper_size = (new_size >> 32) as u32;
+    }
 
-        let block_size = block_size as u64;
-        let multiplier = block_size / 512;
-        let block_off = if new_size == 0 {
-            0
-        } else {
-            (new_size - 1) / block_size as u64
-        };
-        let blocknumber_per_block = block_size as u64 / size_of::<Block>() as u64;
-
-        /* Very complex calcul to compute the number of disk_sector use by the data of the inode */
-        let block_data = if new_size == 0 {
-            0
-        } else {
-            /* SIMPLE ADDRESSING */
-            let mut offset_start = 0;
-            let mut offset_end = 12;
-            let mut block_data = 0;
-
-            if block_off >= offset_start {
-                block_data = (block_off + 1) * multiplier;
-            }
-            /* SINGLY INDIRECT ADDRESSING */
-            offset_start = offset_end;
-            offset_end += blocknumber_per_block;
-            if block_off >= offset_start {
-                block_data += multiplier
-            }
-            /* DOUBLY INDIRECT ADDRESSING */
-            offset_start = offset_end;
-            offset_end += blocknumber_per_block * blocknumber_per_block;
-            if block_off >= offset_start {
-                block_data += multiplier
-                    + ((block_off - offset_start) / blocknumber_per_block + 1) * multiplier
-            }
+    /// Get the pointer `i` of the block map: the 12 direct ones, then the
+    /// singly, doubly and triply indirect ones
+    pub fn block_pointer(&self, i: usize) -> Block {
+        match i {
+            0..=11 => self.direct_block_pointers[i],
+            12 => self.singly_indirect_block_pointers,
+            13 => self.doubly_indirect_block_pointers,
+            14 => self.triply_indirect_block_pointers,
+            _ => panic!("bad block pointer {}", i),
+        }
+    }
 
-            // Triply Indirect Addressing
-            offset_start = offset_end;
-            //offset_end += blocknumber_per_block * blocknumber_per_block * blocknumber_per_block;
-            if block_off >= offset_start {
-                block_data += multiplier
-                    + (((block_off - offset_start)
-                        / (blocknumber_per_block * blocknumber_per_block))
-                        + 1)
-                        * multiplier
-            }
-            block_data
-        };
-        self.nbr_disk_sectors = block_data as u32;
+    /// Set the pointer `i` of the block map
+    pub fn set_block_pointer(&mut self, i: usize, block: Block) {
+        match i {
+            0..=11 => self.direct_block_pointers[i] = block,
+            12 => self.singly_indirect_block_pointers = block,
+            13 => self.doubly_indirect_block_pointers = block,
+            14 => self.triply_indirect_block_pointers = block,
+            _ => panic!("bad block pointer {}", i),
+        }
     }
 
     /// read the fast symlink on the inode if it exist, return None
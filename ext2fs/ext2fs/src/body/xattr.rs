@@ -0,0 +1,204 @@
+//! This file describe the Extended Attribute Block model
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axtype::XATTR_NAME_MAX;
+use core::mem::size_of;
+
+// The extended attributes of an inode are stored in one block, pointed to
+// by the inode. The block starts with a header, then the entries, sorted by
+// name index, name length and name, which are ended by 4 zero bytes. The
+// values are at the end of the block, from the last entry down. The block
+// may be shared by inodes with the same attributes.
+
+// *** Extended Attribute Block ***
+// 0        32                                                 block size
+// +--------+---------+---------+---+------+---------+---------+
+// | header | entry 1 | entry 2 | 0 | free | value 2 | value 1 |
+// +--------+---------+---------+---+------+---------+---------+
+
+/// Used to help confirm the presence of an Extended Attribute Block
+pub const XATTR_MAGIC: u32 = 0xEA020000;
+
+/// Namespaces of the names, as their index in the entries
+pub const XATTR_INDEX_USER: u8 = 1;
+pub const XATTR_INDEX_POSIX_ACL_ACCESS: u8 = 2;
+pub const XATTR_INDEX_POSIX_ACL_DEFAULT: u8 = 3;
+pub const XATTR_INDEX_TRUSTED: u8 = 4;
+pub const XATTR_INDEX_SECURITY: u8 = 6;
+
+/// The prefix of the names of each namespace. The POSIX ACLs are whole
+/// names, the others are followed by a name of their own.
+const XATTR_PREFIXES: [(u8, &str); 5] = [
+    (XATTR_INDEX_USER, "user."),
+    (XATTR_INDEX_POSIX_ACL_ACCESS, "system.posix_acl_access"),
+    (XATTR_INDEX_POSIX_ACL_DEFAULT, "system.posix_acl_default"),
+    (XATTR_INDEX_TRUSTED, "trusted."),
+    (XATTR_INDEX_SECURITY, "security."),
+];
+
+/// Size of the fixed part of an entry, before its name
+const XATTR_ENTRY_SIZE: usize = 16;
+
+/// Entries and values are aligned on 4 bytes
+const XATTR_PAD: usize = 4;
+
+/// Extended Attribute Block header
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+#[allow(unused)]
+pub struct XattrHeader {
+    /// XATTR_MAGIC
+    /*0 	3 	4*/
+    pub magic: u32,
+    /// Number of inodes sharing the block
+    /*4 	7 	4*/
+    pub refcount: u32,
+    /// Number of blocks used, always 1
+    /*8 	11 	4*/
+    pub blocks: u32,
+    /// Hash of all the entries
+    /*12 	15 	4*/
+    pub hash: u32,
+    /*16 	31 	16*/
+    reserved: [u32; 4],
+}
+
+/// An extended attribute, as its name index, its name without the prefix
+/// of the namespace, and its value as stored on disk
+#[derive(Debug, Clone)]
+pub struct Xattr {
+    pub index: u8,
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+/// Split a full name into its name index and its name without the prefix
+pub fn split_name(name: &str) -> LinuxResult<(u8, &str)> {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(LinuxError::ERANGE);
+    }
+    for &(index, prefix) in XATTR_PREFIXES.iter() {
+        if let Some(suffix) = name.strip_prefix(prefix) {
+            let whole = !prefix.ends_with('.');
+            if whole == suffix.is_empty() {
+                return Ok((index, suffix));
+            }
+        }
+    }
+    Err(LinuxError::EOPNOTSUPP)
+}
+
+/// The full name of the attribute, None if its namespace is unknown
+pub fn full_name(xattr: &Xattr) -> Option<String> {
+    let (_, prefix) = XATTR_PREFIXES.iter().find(|(index, _)| *index == xattr.index)?;
+    let mut name = String::from(*prefix);
+    name.push_str(&xattr.name);
+    Some(name)
+}
+
+fn pad(len: usize) -> usize {
+    (len + XATTR_PAD - 1) & !(XATTR_PAD - 1)
+}
+
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// Hash of an entry, over its name and value
+fn entry_hash(xattr: &Xattr) -> u32 {
+    let mut hash = xattr
+        .name
+        .bytes()
+        .fold(0u32, |hash, c| hash.rotate_left(5) ^ c as u32);
+    let mut value = xattr.value.clone();
+    value.resize(pad(value.len()), 0);
+    for word in value.chunks(4) {
+        hash = hash.rotate_left(16) ^ read_u32(word, 0);
+    }
+    hash
+}
+
+/// Read the extended attributes of the Extended Attribute Block `block`
+pub fn parse_block(block: &[u8]) -> LinuxResult<Vec<Xattr>> {
+    if block.len() < size_of::<XattrHeader>()
+        || read_u32(block, 0) != XATTR_MAGIC
+        || read_u32(block, 8) != 1
+    {
+        return Err(LinuxError::EIO);
+    }
+    let mut xattrs = Vec::new();
+    let mut off = size_of::<XattrHeader>();
+    loop {
+        if off + 4 > block.len() {
+            return Err(LinuxError::EIO);
+        }
+        if read_u32(block, off) == 0 {
+            return Ok(xattrs);
+        }
+        let name_len = block[off] as usize;
+        let entry_end = off + XATTR_ENTRY_SIZE + name_len;
+        if entry_end > block.len() {
+            return Err(LinuxError::EIO);
+        }
+        let value_offs = read_u16(block, off + 2) as usize;
+        let value_block = read_u32(block, off + 4);
+        let value_size = read_u32(block, off + 8) as usize;
+        if value_block != 0 || value_offs + value_size > block.len() {
+            return Err(LinuxError::EIO);
+        }
+        let name = core::str::from_utf8(&block[off + XATTR_ENTRY_SIZE..entry_end])
+            .map_err(|_| LinuxError::EIO)?;
+        xattrs.push(Xattr {
+            index: block[off + 1],
+            name: String::from(name),
+            value: block[value_offs..value_offs + value_size].to_vec(),
+        });
+        off += pad(XATTR_ENTRY_SIZE + name_len);
+    }
+}
+
+/// Build an Extended Attribute Block of `block_size` bytes holding
+/// `xattrs`, which are sorted as the entries are. ENOSPC if they don't fit
+pub fn build_block(xattrs: &mut [Xattr], refcount: u32, block_size: usize) -> LinuxResult<Vec<u8>> {
+    xattrs.sort_by(|a, b| (a.index, a.name.len(), &a.name).cmp(&(b.index, b.name.len(), &b.name)));
+
+    let mut block = vec![0u8; block_size];
+    let mut off = size_of::<XattrHeader>();
+    let mut value_offs = block_size;
+    let mut block_hash = 0u32;
+    for xattr in xattrs.iter() {
+        let name_len = xattr.name.len();
+        let entry_size = pad(XATTR_ENTRY_SIZE + name_len);
+        let value_size = pad(xattr.value.len());
+        // Keep room for the 4 zero bytes after the entries
+        if name_len > u8::MAX as usize || off + entry_size + 4 + value_size > value_offs {
+            return Err(LinuxError::ENOSPC);
+        }
+        value_offs -= value_size;
+        block[value_offs..value_offs + xattr.value.len()].copy_from_slice(&xattr.value);
+
+        let hash = entry_hash(xattr);
+        block[off] = name_len as u8;
+        block[off + 1] = xattr.index;
+        block[off + 2..off + 4].copy_from_slice(&(value_offs as u16).to_le_bytes());
+        block[off + 8..off + 12].copy_from_slice(&(xattr.value.len() as u32).to_le_bytes());
+        block[off + 12..off + 16].copy_from_slice(&hash.to_le_bytes());
+        block[off + XATTR_ENTRY_SIZE..off + XATTR_ENTRY_SIZE + name_len]
+            .copy_from_slice(xattr.name.as_bytes());
+        off += entry_size;
+
+        block_hash = block_hash.rotate_left(16) ^ hash;
+    }
+    block[0..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+    block[4..8].copy_from_slice(&refcount.to_le_bytes());
+    block[8..12].copy_from_slice(&1u32.to_le_bytes());
+    block[12..16].copy_from_slice(&block_hash.to_le_bytes());
+    Ok(block)
+}
@@ -159,6 +159,21 @@ impl SuperBlock {
         Block(self.block_per_block_grp)
     }
 
+    /// Get the number of blocks reserved for superuser
+    pub fn get_nbr_blocks_reserved(&self) -> u32 {
+        self.nbr_blocks_reserved
+    }
+
+    /// Set the last written time (in POSIX time)
+    pub fn set_last_written_time(&mut self, time: u32) {
+        self.last_written_time = time;
+    }
+
+    /// Get the block number of the block containing the superblock, the first one of block group 0
+    pub fn get_first_data_block(&self) -> Block {
+        self.block_containing_superblock
+    }
+
     /// Get the log2 (block size) - 10. (In other words, the number to shift 1,024 to the left by to obtain the block size)
     pub fn get_log2_block_size(&self) -> u32 {
         self.log2_block_size
@@ -169,6 +184,19 @@ impl SuperBlock {
         self.size_inode
     }
 
+    /// True if inodes may have extended attributes
+    pub fn has_extended_attributes(&self) -> bool {
+        let flag = self.optional_features_flag;
+        flag.contains(OptionalFeaturesFlag::INODES_HAVE_EXTENDED_ATTRIBUTES)
+    }
+
+    /// Tell that inodes may have extended attributes
+    pub fn set_extended_attributes(&mut self) {
+        let mut flag = self.optional_features_flag;
+        flag.insert(OptionalFeaturesFlag::INODES_HAVE_EXTENDED_ATTRIBUTES);
+        self.optional_features_flag = flag;
+    }
+
     /// True if directories entry have file type
     #[allow(unused)]
     pub fn directory_entry_contain_type_field(&self) -> bool {
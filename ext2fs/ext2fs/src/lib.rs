@@ -12,16 +12,21 @@ use core::mem::size_of;
 use core::mem::MaybeUninit;
 use alloc::vec;
 use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::string::String;
 use alloc::format;
 use tools::{align_next, err_if_zero, u32_align_next, Block};
 use header::{BlockGroupDescriptor, SuperBlock};
+use body::xattr::{self, Xattr, XattrHeader};
+use body::xattr::{XATTR_INDEX_POSIX_ACL_ACCESS, XATTR_INDEX_POSIX_ACL_DEFAULT};
+use body::acl;
 use axerrno::{LinuxResult, LinuxError};
-use axfs_vfs::{VfsResult, VfsOps, VfsNodeRef, VfsNodeOps, VfsNodeType, VfsError};
+use axfs_vfs::{VfsResult, VfsOps, VfsNodeRef, VfsNodeOps, VfsNodeType, VfsError, FileSystemInfo};
 use lazy_init::LazyInit;
 use axdriver::Disk;
 use axtype::Path;
+use axtype::{XATTR_CREATE, XATTR_REPLACE, XATTR_SIZE_MAX, FS_NAME_LEN, MS_RDONLY};
 use bit_field::{BitArray, BitField};
 use crate::body::Mode;
 use crate::body::{uid_t, gid_t};
@@ -52,6 +57,16 @@ extern crate alloc;
 /// Used to help confirm the presence of Ext2 on a volume
 const EXT2_SIGNATURE_MAGIC: u16 = 0xef53;
 
+/// The pointers of the block map of an inode: 12 direct ones, then the
+/// singly, doubly and triply indirect ones
+const NDIR_BLOCKS: usize = 12;
+const IND_BLOCK: usize = NDIR_BLOCKS;
+const DIND_BLOCK: usize = IND_BLOCK + 1;
+const TIND_BLOCK: usize = DIND_BLOCK + 1;
+
+/// Directories with a name index at most, the others are forgotten
+const DIR_INDEX_MAX: usize = 64;
+
 static EXT2_FS: LazyInit<Arc<Ext2Fs>> = LazyInit::new();
 
 pub struct Ext2Fs {
@@ -102,9 +117,45 @@ impl Ext2Fs {
         self.inner.lock()._write_at(ino, offset, buf)
     }
 
+    pub fn readahead(&self, ino: u32, offset: u64, len: u64) -> LinuxResult {
+        self.inner.lock()._readahead(ino, offset, len)
+    }
+
+    pub fn read_direct(&self, ino: u32, offset: &mut u64, buf: &mut [u8]) -> LinuxResult<u64> {
+        self.inner.lock()._read_direct(ino, offset, buf)
+    }
+
+    pub fn write_direct(&self, ino: u32, offset: &mut u64, buf: &[u8]) -> LinuxResult<u64> {
+        self.inner.lock()._write_direct(ino, offset, buf)
+    }
+
     pub fn truncate(&self, ino: u32, new_size: u64) -> LinuxResult {
         self.inner.lock()._truncate(ino, new_size)
     }
+
+    pub fn getxattr(&self, ino: u32, name: &str, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.inner.lock()._getxattr(ino, name, buf)
+    }
+
+    pub fn setxattr(&self, ino: u32, name: &str, value: &[u8], flags: usize) -> LinuxResult {
+        self.inner.lock()._setxattr(ino, name, value, flags)
+    }
+
+    pub fn listxattr(&self, ino: u32, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.inner.lock()._listxattr(ino, buf)
+    }
+
+    pub fn removexattr(&self, ino: u32, name: &str) -> LinuxResult {
+        self.inner.lock()._removexattr(ino, name)
+    }
+
+    pub fn statfs(&self) -> FileSystemInfo {
+        self.inner.lock()._statfs()
+    }
+
+    pub fn sync(&self) -> LinuxResult {
+        self.inner.lock()._sync()
+    }
 }
 
 /// Global structure of ext2Filesystem, such as disk partition.
@@ -117,6 +168,8 @@ struct Ext2Filesystem {
     block_mask: u32,
     block_shift: u32,
     cache: Cache<u64, Block>,
+    /// The name index of the directories looked up
+    dir_index: RefCell<BTreeMap<InodeNbr, DirIndex>>,
 }
 
 impl fmt::Debug for Ext2Filesystem {
@@ -129,6 +182,7 @@ impl fmt::Debug for Ext2Filesystem {
             .field("block_mask", &self.block_mask)
             .field("block_shift", &self.block_shift)
             .field("cache", &self.cache)
+            .field("dir_index", &self.dir_index)
             // Not include disk in debug output.
             .finish()
     }
@@ -137,6 +191,8 @@ impl fmt::Debug for Ext2Filesystem {
 type OffsetDirEntry = u32;
 type InodeAddr = u64;
 type InodeNbr = u32;
+/// The entries of a directory by name, as (inode, offset of the entry)
+type DirIndex = BTreeMap<String, (InodeNbr, OffsetDirEntry)>;
 
 impl Ext2Filesystem {
     /// Invocation of a new FileSystem instance: take a FD and his reader as parameter
@@ -168,6 +224,7 @@ impl Ext2Filesystem {
             nbr_block_grp,
             disk: RefCell::new(disk),
             cache: Cache::new(block_size as usize / size_of::<Block>()),
+            dir_index: RefCell::new(BTreeMap::new()),
         })
     }
 
@@ -226,7 +283,7 @@ impl Ext2Filesystem {
 
     pub fn _read_at(&mut self, inode_nbr: u32, file_offset: &mut u64, mut buf: &mut [u8]) -> LinuxResult<u64> {
         debug!("offset {:#x} buflen {:#x}", file_offset, buf.len());
-        let (mut inode, _inode_addr) = self.get_inode(inode_nbr)?;
+        let (inode, _inode_addr) = self.get_inode(inode_nbr)?;
 
         // EOF
         if *file_offset >= inode.get_size() {
@@ -242,41 +299,48 @@ impl Ext2Filesystem {
         self.cache.invalidate();
 
         let file_curr_offset_start = *file_offset;
-        let block_mask = (self.block_size - 1) as u64;
+        let block_mask = self.block_mask as u64;
 
         while buf.len() != 0 {
+            // Read the blocks contiguous on disk at once, and zero the
+            // blocks of a hole at once
+            let start_offset = *file_offset;
+            let start_block = self.inode_block_cached(&inode, *file_offset >> self.block_shift)?;
+            let mut block = start_block;
             let mut bytes_to_read = 0;
-
-            let mut start_data_address = None;
-            let mut last_data_address: Option<u64> = None;
             loop {
-                let data_address = self.inode_data(&mut inode, *file_offset).expect("WTF");
-                if let Some(last_address) = last_data_address {
-                    if data_address != last_address + self.block_size as u64 {
-                        break;
-                    }
-                } else {
-                    start_data_address = Some(data_address);
-                }
-                let mut bytes = min(
+                let bytes = min(
                     self.block_size as u64 - (*file_offset & block_mask),
-                    inode.get_size() - *file_offset,
+                    buf.len() as u64 - bytes_to_read,
                 );
-                bytes = min(bytes, buf.len() as u64 - bytes_to_read);
-
                 *file_offset += bytes;
                 bytes_to_read += bytes;
                 if bytes_to_read == buf.len() as u64 {
                     break;
                 }
-                last_data_address = Some(data_address);
+                let next = self.inode_block_cached(&inode, *file_offset >> self.block_shift)?;
+                let contiguous = match (block, next) {
+                    (Some(block), Some(next)) => next.0 == block.0 + 1,
+                    (None, None) => true,
+                    _ => false,
+                };
+                if !contiguous {
+                    break;
+                }
+                block = next;
+            }
+            let (data, rest) = mem::take(&mut buf).split_at_mut(bytes_to_read as usize);
+            match start_block {
+                Some(start_block) => {
+                    let data_read = self.disk.borrow_mut().read_buffer(
+                        self.to_addr(start_block) + (start_offset & block_mask),
+                        data,
+                    )?;
+                    assert!(data_read == bytes_to_read);
+                }
+                None => data.fill(0),
             }
-            let data_read = self.disk.borrow_mut().read_buffer(
-                start_data_address.expect("WOOT"),
-                &mut buf[0..bytes_to_read as usize],
-            )?;
-            assert!(data_read == bytes_to_read);
-            buf = &mut buf[bytes_to_read as usize..];
+            buf = rest;
         }
         Ok(*file_offset - file_curr_offset_start)
     }
@@ -289,9 +353,7 @@ impl Ext2Filesystem {
     ) -> LinuxResult<u64> {
         let (mut inode, inode_addr) = self.get_inode(inode_nbr)?;
         let file_curr_offset_start = *file_offset;
-        if *file_offset > inode.get_size() {
-            return Ok(0);
-        }
+        // Writing after the end leaves a hole up to it
         if buf.len() == 0 {
             return Ok(0);
         }
@@ -306,7 +368,7 @@ impl Ext2Filesystem {
             .write_buffer(data_address, &buf[0..offset as usize])?;
         *file_offset += data_write as u64;
         if inode.get_size() < *file_offset {
-            inode.update_size(*file_offset, self.block_size);
+            inode.update_size(*file_offset);
             self.disk.borrow_mut().write_struct(inode_addr, &inode)?;
         }
         if data_write < offset {
@@ -318,7 +380,7 @@ impl Ext2Filesystem {
             let data_write = self.disk.borrow_mut().write_buffer(data_address, &chunk)?;
             *file_offset += data_write as u64;
             if inode.get_size() < *file_offset {
-                inode.update_size(*file_offset, self.block_size);
+                inode.update_size(*file_offset);
                 self.disk.borrow_mut().write_struct(inode_addr, &inode)?;
             }
             if data_write < chunk.len() as u64 {
@@ -328,6 +390,135 @@ impl Ext2Filesystem {
         Ok(*file_offset - file_curr_offset_start)
     }
 
+    /// Start reading the blocks of `len` bytes of the file from `offset`
+    /// into the buffer cache, without waiting, in runs of blocks contiguous
+    /// on disk. Holes and what is past the end of the file are skipped.
+    pub fn _readahead(&mut self, inode_nbr: u32, offset: u64, len: u64) -> LinuxResult {
+        let (inode, _inode_addr) = self.get_inode(inode_nbr)?;
+        let end = min(offset.saturating_add(len), inode.get_size());
+        if offset >= end {
+            return Ok(());
+        }
+
+        // Invalidate the cache used after
+        self.cache.invalidate();
+
+        let mut runs: Vec<(Block, u64)> = Vec::new();
+        for block_off in (offset >> self.block_shift)..=((end - 1) >> self.block_shift) {
+            let Some(block) = self.inode_block_cached(&inode, block_off)? else {
+                continue;
+            };
+            match runs.last_mut() {
+                Some((start, nbr)) if start.0 as u64 + *nbr == block.0 as u64 => *nbr += 1,
+                _ => runs.push((block, 1)),
+            }
+        }
+        let disk = self.disk.borrow();
+        for (start, nbr) in runs {
+            disk.readahead(self.to_addr(start), nbr * self.block_size as u64);
+        }
+        Ok(())
+    }
+
+    /// Read as `_read_at`, for O_DIRECT: whole blocks from `file_offset`,
+    /// a multiple of the block size, go from the disk into `buf` without
+    /// the buffer cache. The block of the end of the file is read whole,
+    /// but only what is before the end counts.
+    pub fn _read_direct(&mut self, inode_nbr: u32, file_offset: &mut u64, buf: &mut [u8]) -> LinuxResult<u64> {
+        debug!("direct offset {:#x} buflen {:#x}", file_offset, buf.len());
+        let block_mask = self.block_mask as u64;
+        if (*file_offset & block_mask) != 0 || (buf.len() as u64 & block_mask) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let (inode, _inode_addr) = self.get_inode(inode_nbr)?;
+
+        // EOF
+        if *file_offset >= inode.get_size() {
+            return Ok(0);
+        }
+        let count = min(buf.len() as u64, inode.get_size() - *file_offset);
+        let mut buf = &mut buf[..((count + block_mask) & !block_mask) as usize];
+
+        // Invalidate the cache used after
+        self.cache.invalidate();
+
+        let block_size = self.block_size as usize;
+        let mut block_off = *file_offset >> self.block_shift;
+        while buf.len() != 0 {
+            // Read the blocks contiguous on disk at once, and zero the
+            // blocks of a hole at once
+            let start_block = self.inode_block_cached(&inode, block_off)?;
+            let mut nbr_blocks = 1;
+            while nbr_blocks * block_size < buf.len() {
+                let next = self.inode_block_cached(&inode, block_off + nbr_blocks as u64)?;
+                let contiguous = match (start_block, next) {
+                    (Some(start), Some(next)) => next.0 == start.0 + nbr_blocks as u32,
+                    (None, None) => true,
+                    _ => false,
+                };
+                if !contiguous {
+                    break;
+                }
+                nbr_blocks += 1;
+            }
+            let (data, rest) = mem::take(&mut buf).split_at_mut(nbr_blocks * block_size);
+            match start_block {
+                Some(start_block) => self
+                    .disk
+                    .borrow()
+                    .read_direct(self.to_addr(start_block), data)
+                    .map_err(|_| LinuxError::EIO)?,
+                None => data.fill(0),
+            }
+            buf = rest;
+            block_off += nbr_blocks as u64;
+        }
+        *file_offset += count;
+        Ok(count)
+    }
+
+    /// Write as `_write_at`, for O_DIRECT: whole blocks from `file_offset`,
+    /// a multiple of the block size, go from `buf` to the disk at once
+    /// without the buffer cache. The blocks are allocated as for any write,
+    /// and the metadata goes through the buffer cache.
+    pub fn _write_direct(&mut self, inode_nbr: u32, file_offset: &mut u64, buf: &[u8]) -> LinuxResult<u64> {
+        debug!("direct offset {:#x} buflen {:#x}", file_offset, buf.len());
+        let block_mask = self.block_mask as u64;
+        if (*file_offset & block_mask) != 0 || (buf.len() as u64 & block_mask) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let (mut inode, inode_addr) = self.get_inode(inode_nbr)?;
+        let file_curr_offset_start = *file_offset;
+
+        let block_size = self.block_size as usize;
+        let mut buf = buf;
+        while buf.len() != 0 {
+            // Write the blocks contiguous on disk at once. A block found
+            // not to be is written with the next ones.
+            let start_addr = self.inode_data_alloc((&mut inode, inode_addr), *file_offset)?;
+            let mut len = block_size;
+            while len < buf.len() {
+                let next = self.inode_data_alloc((&mut inode, inode_addr), *file_offset + len as u64)?;
+                if next != start_addr + len as u64 {
+                    break;
+                }
+                len += block_size;
+            }
+            let (data, rest) = buf.split_at(len);
+            self.disk
+                .borrow()
+                .write_direct(start_addr, data)
+                .map_err(|_| LinuxError::EIO)?;
+            *file_offset += len as u64;
+            buf = rest;
+        }
+        if inode.get_size() < *file_offset {
+            inode.update_size(*file_offset);
+            self.disk.borrow_mut().write_struct(inode_addr, &inode)?;
+        }
+        Ok(*file_offset - file_curr_offset_start)
+    }
+
     /// return all the (directory, inode) conainted in inode_nbr
     pub fn lookup_directory<'a>(
         &'a self, ino: u32
@@ -347,60 +538,40 @@ impl Ext2Filesystem {
     fn _lookup_directory<'a>(
         &'a self, ino: u32, path: &Path,
     ) -> LinuxResult<impl Iterator<Item = Entry> + 'a> {
-        let mut iter = self.lookup_directory(ino).unwrap();
+        self.lookup_directory(self.walk_dir(ino, path)?)
+    }
+
+    /// the directory at `path` from the directory `ino`
+    fn walk_dir(&self, ino: u32, path: &Path) -> LinuxResult<InodeNbr> {
+        let mut ino = ino;
         for directory in path.components() {
             if directory.len() == 0 {
                 continue;
             }
-            let elem = iter.find(|entry| {
-                let filelen = directory.len();
-                unsafe {
-                    libc_memcmp(
-                        entry.directory.filename.0.as_ptr() as *const u8,
-                        directory.as_bytes().as_ptr() as *const u8,
-                        filelen,
-                    )
-                }
-            });
-            match elem {
-                None => return Err(LinuxError::ENOENT),
-                Some(entry) => {
-                    let inode = entry.directory.get_inode();
-                    iter = self.lookup_directory(inode)?;
-                }
-            }
+            ino = self.dir_lookup(ino, directory)?.ok_or(LinuxError::ENOENT)?.0;
         }
-        Ok(iter)
+        Ok(ino)
     }
 
     fn _find_entry(&self, ino: u32, path: &Path) -> LinuxResult<Option<Entry>> {
         info!("_find_entry: parent: ino: {}, {:?}", ino, path.parent());
-        Ok(match path.parent() {
-            Some(parent) => {
-                let parent = Path::new(parent);
-                let mut iter = self._lookup_directory(ino, &parent)?;
-                iter.find(|entry| unsafe { entry.directory.get_filename() } == path.file_name().unwrap())
-            }
-            None => {
-                info!("is rootdir: ino {} {}", ino, path.as_str());
-                if path.as_str() == "" {
-                    let mut iter = self._lookup_directory(ino, path)?;
-                    iter.find(|entry| unsafe { entry.directory.get_filename() } == ".")
-                } else {
-                    let parent = Path::new("/");
-                    let mut iter = self._lookup_directory(ino, &parent)?;
-                    iter.find(|entry| unsafe { entry.directory.get_filename() } == path.file_name().unwrap())
-                }
-            }
+        let (dir, filename) = match path.parent() {
+            Some(parent) => (self.walk_dir(ino, &Path::new(parent))?, path.file_name().unwrap()),
             // rootdir
-            /*
-            None => {
+            None if path.as_str() == "" => {
                 info!("is rootdir: ino {} {}", ino, path.as_str());
-                let mut iter = self._lookup_directory(ino, path)?;
-                iter.find(|entry| unsafe { entry.directory.get_filename() } == ".")
+                (ino, ".")
             }
-            */
-        })
+            None => (ino, path.file_name().unwrap()),
+        };
+        match self.find_entry_in_inode(dir, filename) {
+            Ok((directory, _)) => {
+                let (inode, _) = self.get_inode(directory.get_inode())?;
+                Ok(Some(Entry { directory, inode }))
+            }
+            Err(LinuxError::ENOENT) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
     pub fn _create_file(
@@ -468,29 +639,16 @@ impl Ext2Filesystem {
     }
 
     pub fn create_file(&mut self, ino: u32, path: &str, uid: u32, gid: u32, mode: i32) -> LinuxResult<()> {
-        let timestamp = axhal::time::current_time();
+        let timestamp = timekeeping::ktime_get_real_coarse();
         let path = Path::new(path);
         let parent = Path::new(path.parent().unwrap_or("/"));
         let filename: &str = path.file_name().unwrap();
         //info!("parent {:?}", parent);
-        let iter = self._lookup_directory(ino, &parent)?;
-        let parent = iter.fold(Ok(None), |res, entry| {
-            if unsafe { entry.directory.get_filename() } == filename {
-                return Err(LinuxError::EEXIST);
-            }
-            res.map(|opt| {
-                opt.or({
-                    if unsafe { entry.directory.get_filename() == "." } {
-                        Some(entry)
-                    } else {
-                        None
-                    }
-                })
-            })
-        })?;
+        let parent_inode_nbr = self.walk_dir(ino, &parent)?;
+        if self.dir_lookup(parent_inode_nbr, filename)?.is_some() {
+            return Err(LinuxError::EEXIST);
+        }
 
-        //info!("parent: {:?}", parent);
-        let parent_inode_nbr = parent.unwrap().directory.header.inode;
         self._create_file(
             filename,
             parent_inode_nbr,
@@ -502,29 +660,17 @@ impl Ext2Filesystem {
     }
 
     pub fn create_dir(&mut self, ino: u32, path: &str, uid: u32, gid: u32) -> LinuxResult<()> {
-        let timestamp = axhal::time::current_time();
+        let timestamp = timekeeping::ktime_get_real_coarse();
         let path = Path::new(path);
         let parent = Path::new(path.parent().unwrap_or("/"));
         let filename: &str = path.file_name().unwrap();
         //info!("parent {:?}", parent);
-        let iter = self._lookup_directory(ino, &parent)?;
-        let parent = iter.fold(Ok(None), |res, entry| {
-            if unsafe { entry.directory.get_filename() } == filename {
-                return Err(LinuxError::EEXIST);
-            }
-            res.map(|opt| {
-                opt.or({
-                    if unsafe { entry.directory.get_filename() == "." } {
-                        Some(entry)
-                    } else {
-                        None
-                    }
-                })
-            })
-        })?;
+        let parent_inode_nbr = self.walk_dir(ino, &parent)?;
+        if self.dir_lookup(parent_inode_nbr, filename)?.is_some() {
+            return Err(LinuxError::EEXIST);
+        }
 
         info!("path {}", path.as_str());
-        let parent_inode_nbr = parent.unwrap().directory.header.inode;
         self._create_dir(
             parent_inode_nbr,
             filename,
@@ -615,12 +761,12 @@ impl Ext2Filesystem {
             return Err(LinuxError::EISDIR);
         }
 
-        let mut size = inode.get_size();
-        if new_size <= size {
+        if new_size <= inode.get_size() {
             self.truncate_inode((&mut inode, inode_addr), new_size)
         } else {
-            let buf = vec![0u8; (new_size - size) as usize];
-            let _ = self._write_at(inode_nbr, &mut size, &buf)?;
+            // The blocks after the end are a hole, read as zeroes
+            inode.update_size(new_size);
+            self.disk.borrow_mut().write_struct(inode_addr, &inode)?;
             Ok(())
         }
     }
@@ -638,6 +784,119 @@ impl Ext2Filesystem {
         Ok(())
     }
 
+    /// The attributes of the filesystem, from the superblock
+    pub fn _statfs(&self) -> FileSystemInfo {
+        let sb = &self.superblock;
+        let free_blocks = sb.nbr_free_blocks;
+        FileSystemInfo {
+            f_type: EXT2_SIGNATURE_MAGIC as u64,
+            f_bsize: self.block_size as u64,
+            f_blocks: sb.nbr_blocks as u64,
+            f_bfree: free_blocks as u64,
+            f_bavail: free_blocks.saturating_sub(sb.get_nbr_blocks_reserved()) as u64,
+            f_files: sb.nbr_inode as u64,
+            f_ffree: sb.nbr_free_inodes as u64,
+            f_namelen: FS_NAME_LEN as u64,
+            f_frsize: self.block_size as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Write the superblock back, stamped with the time. The inodes,
+    /// bitmaps and data are written through as they change, so the buffer
+    /// cache of the disk holds all the rest already.
+    pub fn _sync(&mut self) -> LinuxResult {
+        let now = timekeeping::ktime_get_real_coarse().as_secs() as u32;
+        self.superblock.set_last_written_time(now);
+        self.disk
+            .borrow_mut()
+            .write_struct(self.superblock_addr, &self.superblock)?;
+        Ok(())
+    }
+
+    /// Get the value of the extended attribute `name` of the inode into
+    /// `buf`, or only its size if `buf` is empty
+    pub fn _getxattr(&self, inode_nbr: u32, name: &str, buf: &mut [u8]) -> LinuxResult<usize> {
+        let (index, name) = xattr::split_name(name)?;
+        let (inode, _) = self.get_inode(inode_nbr)?;
+        let xattrs = self.read_xattrs(&inode)?;
+        let xattr = xattrs
+            .iter()
+            .find(|x| x.index == index && x.name == name)
+            .ok_or(LinuxError::ENODATA)?;
+        if is_acl(index) {
+            copy_out(&acl::acl_from_disk(&xattr.value)?, buf)
+        } else {
+            copy_out(&xattr.value, buf)
+        }
+    }
+
+    /// Set the extended attribute `name` of the inode to `value`. An
+    /// access ACL sets the permissions of the inode too
+    pub fn _setxattr(&mut self, inode_nbr: u32, name: &str, value: &[u8], flags: usize) -> LinuxResult<()> {
+        let (index, name) = xattr::split_name(name)?;
+        if value.len() > XATTR_SIZE_MAX {
+            return Err(LinuxError::ERANGE);
+        }
+        let (mut inode, inode_addr) = self.get_inode(inode_nbr)?;
+        let mut xattrs = self.read_xattrs(&inode)?;
+        let pos = xattrs.iter().position(|x| x.index == index && x.name == name);
+        if flags & XATTR_CREATE != 0 && pos.is_some() {
+            return Err(LinuxError::EEXIST);
+        }
+        if flags & XATTR_REPLACE != 0 && pos.is_none() {
+            return Err(LinuxError::ENODATA);
+        }
+
+        let value = if is_acl(index) {
+            if index == XATTR_INDEX_POSIX_ACL_DEFAULT && !inode.is_a_directory() {
+                return Err(LinuxError::EACCES);
+            }
+            let disk_value = acl::acl_to_disk(value)?;
+            if index == XATTR_INDEX_POSIX_ACL_ACCESS {
+                let mode = acl::acl_mode(value)?;
+                inode.type_and_perm = TypePerm((inode.type_and_perm.0 & !0o777) | mode);
+            }
+            disk_value
+        } else {
+            Vec::from(value)
+        };
+        match pos {
+            Some(pos) => xattrs[pos].value = value,
+            None => xattrs.push(Xattr {
+                index,
+                name: String::from(name),
+                value,
+            }),
+        }
+        self.write_xattrs((&mut inode, inode_addr), &mut xattrs)
+    }
+
+    /// List the names of the extended attributes of the inode into `buf`,
+    /// each ended by a 0, or only get their size if `buf` is empty
+    pub fn _listxattr(&self, inode_nbr: u32, buf: &mut [u8]) -> LinuxResult<usize> {
+        let (inode, _) = self.get_inode(inode_nbr)?;
+        let mut names = Vec::new();
+        for name in self.read_xattrs(&inode)?.iter().filter_map(xattr::full_name) {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        copy_out(&names, buf)
+    }
+
+    /// Remove the extended attribute `name` of the inode
+    pub fn _removexattr(&mut self, inode_nbr: u32, name: &str) -> LinuxResult<()> {
+        let (index, name) = xattr::split_name(name)?;
+        let (mut inode, inode_addr) = self.get_inode(inode_nbr)?;
+        let mut xattrs = self.read_xattrs(&inode)?;
+        let pos = xattrs
+            .iter()
+            .position(|x| x.index == index && x.name == name)
+            .ok_or(LinuxError::ENODATA)?;
+        xattrs.remove(pos);
+        self.write_xattrs((&mut inode, inode_addr), &mut xattrs)
+    }
+
     fn lookup(&self, ino: u32, path: &str) -> LinuxResult<VfsNodeRef> {
         info!("ext2: lookup: path: {} parent_ino {} ...", path, ino);
         let path = Path::new(path);
@@ -668,10 +927,41 @@ impl Ext2Filesystem {
         inode_nbr: u32,
         filename: &str,
     ) -> LinuxResult<(DirectoryEntry, OffsetDirEntry)> {
-        Ok(self
-            .iter_entries(inode_nbr)?
-            .find(|(x, _)| unsafe { x.get_filename() } == filename)
-            .ok_or(LinuxError::ENOENT)?)
+        let (_, offset) = self
+            .dir_lookup(inode_nbr, filename)?
+            .ok_or(LinuxError::ENOENT)?;
+        let (mut inode, inode_addr) = self.get_inode(inode_nbr)?;
+        let entry = self
+            .find_entry((&mut inode, inode_addr), offset as u64)
+            .ok_or(LinuxError::ENOENT)?;
+        Ok((entry, offset))
+    }
+
+    /// find the entry `filename` of the directory `dir` as (inode, offset
+    /// of the entry) by its name index, which is built from its entries
+    /// on its first lookup
+    fn dir_lookup(
+        &self,
+        dir: InodeNbr,
+        filename: &str,
+    ) -> LinuxResult<Option<(InodeNbr, OffsetDirEntry)>> {
+        if let Some(index) = self.dir_index.borrow().get(&dir) {
+            return Ok(index.get(filename).copied());
+        }
+        let index: DirIndex = self
+            .iter_entries(dir)?
+            .map(|(entry, offset)| {
+                let filename = unsafe { entry.get_filename() };
+                (String::from(filename), (entry.get_inode(), offset))
+            })
+            .collect();
+        let found = index.get(filename).copied();
+        let mut dir_index = self.dir_index.borrow_mut();
+        if dir_index.len() >= DIR_INDEX_MAX {
+            dir_index.pop_first();
+        }
+        dir_index.insert(dir, index);
+        Ok(found)
     }
 
     /// truncate inode to the size `new_size` deleting all data blocks above
@@ -685,15 +975,23 @@ impl Ext2Filesystem {
         if size == 0 {
             return Ok(());
         }
-        let new_size_block = self.to_block_addr(new_size);
-        // let curr_size = self.to_block_addr(align_next(size, self.block_size as u64));
+        // the block new_size ends in is kept, with its tail zeroed
+        let new_size_block = self.to_block(new_size);
         // size - 1 to get the previous block addr
         let curr_size = self.to_block_addr(size - 1);
         for block_off in (new_size_block.0..=curr_size.0).rev() {
-            self.inode_free_block((inode, inode_addr), Block(block_off))
-                .unwrap();
+            self.inode_free_block((inode, inode_addr), Block(block_off))?;
         }
-        inode.update_size(new_size, self.block_size);
+        let tail = new_size & self.block_mask as u64;
+        if tail != 0 {
+            if let Some(block) = self.inode_block(inode, new_size >> self.block_shift)? {
+                let zeroes = vec![0; (self.block_size as u64 - tail) as usize];
+                self.disk
+                    .borrow_mut()
+                    .write_buffer(self.to_addr(block) + tail, &zeroes)?;
+            }
+        }
+        inode.update_size(new_size);
         self.disk.borrow_mut().write_struct(inode_addr, inode)?;
         Ok(())
     }
@@ -712,6 +1010,13 @@ impl Ext2Filesystem {
         {
             self.truncate_inode((inode, inode_addr), 0).unwrap();
         }
+        if inode.extended_attribute_block != Block(0) {
+            let block = inode.extended_attribute_block;
+            self.release_xattr_block(inode, block)?;
+            inode.extended_attribute_block = Block(0);
+        }
+        // The inode number may be a new directory next
+        self.dir_index.get_mut().remove(&inode_nbr);
         /* Unset Inode bitmap */
         let block_grp = (inode_nbr - 1) / self.superblock.inodes_per_block_grp;
         let index = (inode_nbr as u64 - 1) % self.superblock.inodes_per_block_grp as u64;
@@ -757,6 +1062,8 @@ impl Ext2Filesystem {
         let entry = self
             .find_entry((&mut inode, inode_addr), curr_offset as u64)
             .unwrap();
+        // Its name index is built again on the next lookup
+        self.dir_index.get_mut().remove(&parent_inode_nbr);
 
         let (mut previous, previous_offset) = self
             .iter_entries(parent_inode_nbr)
@@ -882,7 +1189,7 @@ impl Ext2Filesystem {
         if new_size < inode.get_size() {
             self.truncate_inode((inode, inode_addr), new_size)?;
         } else {
-            inode.update_size(new_size, self.block_size);
+            inode.update_size(new_size);
             self.disk.borrow_mut().write_struct(inode_addr, inode)?;
         }
         Ok(())
@@ -896,7 +1203,7 @@ impl Ext2Filesystem {
     ) -> LinuxResult<()> {
         let (mut inode, inode_addr) = self.get_inode(parent_inode_nbr)?;
         // Get the last entry of the Directory
-        match self.iter_entries(parent_inode_nbr)?.last() {
+        let new_offset = match self.iter_entries(parent_inode_nbr)?.last() {
             Some((mut entry, offset)) => {
                 let offset = offset as u64;
 
@@ -920,11 +1227,18 @@ impl Ext2Filesystem {
                 /* Update previous entry size */
                 entry.set_size((new_offset - offset) as u16);
                 entry.write_on_disk(entry_addr, &mut self.disk.borrow_mut())?;
-
-                self.set_as_last_entry((&mut inode, inode_addr), (new_entry, new_offset as u32))
+                new_offset as u32
             }
-            None => self.set_as_last_entry((&mut inode, inode_addr), (new_entry, 0 as u32)),
+            None => 0,
+        };
+        self.set_as_last_entry((&mut inode, inode_addr), (new_entry, new_offset))?;
+
+        // Keep the name index of the directory up to date
+        if let Some(index) = self.dir_index.get_mut().get_mut(&parent_inode_nbr) {
+            let filename = unsafe { new_entry.get_filename() };
+            index.insert(String::from(filename), (new_entry.get_inode(), new_offset));
         }
+        Ok(())
     }
 
     /// find the directory entry a offset file.curr_offset
@@ -973,50 +1287,73 @@ impl Ext2Filesystem {
         Ok((block_grp, block_grp_addr))
     }
 
-    /// try to allocate a new block on block grp number `n`
-    fn alloc_block_on_grp(&mut self, n: u32) -> Option<Block> {
+    /// the first block of block grp number `n`
+    fn grp_first_block(&self, n: u32) -> Block {
+        self.superblock.get_first_data_block() + self.superblock.get_block_per_block_grp() * n
+    }
+
+    /// try to allocate a new block on block grp number `n`, the first free
+    /// one from index `start` on, wrapping around
+    fn alloc_block_on_grp(&mut self, n: u32, start: u32) -> Option<Block> {
         let (mut block_dtr, block_dtr_addr) = self.get_block_grp_descriptor(n).ok()?;
         if block_dtr.nbr_free_blocks == 0 {
             return None;
         }
-        // TODO: dynamic alloc ?
+        // The last block grp may be shorter
+        let first = self.grp_first_block(n);
+        let nbr_blocks = min(
+            self.superblock.get_block_per_block_grp().0,
+            self.superblock.nbr_blocks - first.0,
+        );
         let bitmap_addr = self.to_addr(block_dtr.block_usage_bitmap);
-        let mut bitmap: [u8; 1024] = self.disk.borrow_mut().read_struct(bitmap_addr).ok()?;
-        for i in 0..self.superblock.get_block_per_block_grp().0 {
-            if !bitmap.get_bit(i as usize) {
-                bitmap.set_bit(i as usize, true);
-                self.disk
-                    .borrow_mut()
-                    .write_struct(bitmap_addr + i as u64 / 8, &bitmap[(i / 8) as usize])
-                    .ok()?;
-
-                block_dtr.nbr_free_blocks -= 1;
-                self.disk
-                    .borrow_mut()
-                    .write_struct(block_dtr_addr, &block_dtr)
-                    .ok()?;
-                self.superblock.nbr_free_blocks -= 1;
-                self.disk
-                    .borrow_mut()
-                    .write_struct(self.superblock_addr, &self.superblock)
-                    .ok()?;
-                // TODO: Check the + 1
-                return Some(self.superblock.get_block_per_block_grp() * n + Block(i + 1));
-            }
-        }
-        None
-    }
+        let mut bitmap = vec![0u8; div_rounded_up(nbr_blocks as u64, 8) as usize];
+        self.disk
+            .borrow_mut()
+            .read_buffer(bitmap_addr, &mut bitmap)
+            .ok()?;
+        let start = if start < nbr_blocks { start } else { 0 };
+        let i = (start..nbr_blocks)
+            .chain(0..start)
+            .find(|&i| !bitmap.get_bit(i as usize))?;
+        bitmap.set_bit(i as usize, true);
+        self.disk
+            .borrow_mut()
+            .write_struct(bitmap_addr + i as u64 / 8, &bitmap[(i / 8) as usize])
+            .ok()?;
 
-    /// try to allocate a new block anywhere on the filesystem
-    fn alloc_block(&mut self) -> Option<Block> {
-        for n in 0..self.nbr_block_grp {
-            if let Some(addr) = self.alloc_block_on_grp(n) {
-                // TODO: dynamic alloc ?
+        block_dtr.nbr_free_blocks -= 1;
+        self.disk
+            .borrow_mut()
+            .write_struct(block_dtr_addr, &block_dtr)
+            .ok()?;
+        self.superblock.nbr_free_blocks -= 1;
+        self.disk
+            .borrow_mut()
+            .write_struct(self.superblock_addr, &self.superblock)
+            .ok()?;
+        Some(first + Block(i))
+    }
+
+    /// try to allocate a new block, the first free one from `goal` on in
+    /// its block grp, or else in the next block grps, and zero it
+    fn alloc_block(&mut self, goal: Block) -> Option<Block> {
+        let first_data_block = self.superblock.get_first_data_block().0;
+        let per_grp = self.superblock.get_block_per_block_grp().0;
+        let goal = if goal.0 >= first_data_block && goal.0 < self.superblock.nbr_blocks {
+            goal.0 - first_data_block
+        } else {
+            0
+        };
+        let goal_grp = goal / per_grp;
+        for i in 0..self.nbr_block_grp {
+            let n = (goal_grp + i) % self.nbr_block_grp;
+            let start = if i == 0 { goal % per_grp } else { 0 };
+            if let Some(block) = self.alloc_block_on_grp(n, start) {
                 let _res = self
                     .disk
                     .borrow_mut()
-                    .write_buffer(self.to_addr(addr), &[0; 1024]);
-                return Some(addr);
+                    .write_buffer(self.to_addr(block), &vec![0; self.block_size as usize]);
+                return Some(block);
             }
         }
         None
@@ -1024,8 +1361,9 @@ impl Ext2Filesystem {
 
     /// try to free the block block_nbr
     fn free_block(&mut self, block_nbr: Block) -> LinuxResult<()> {
-        let block_grp = (block_nbr.0 - 1) / self.superblock.get_block_per_block_grp().0;
-        let index = (block_nbr.0 as u64 - 1) % self.superblock.get_block_per_block_grp().0 as u64;
+        let block_nbr = block_nbr - self.superblock.get_first_data_block();
+        let block_grp = block_nbr.0 / self.superblock.get_block_per_block_grp().0;
+        let index = block_nbr.0 as u64 % self.superblock.get_block_per_block_grp().0 as u64;
 
         let (mut block_dtr, block_dtr_addr) = self.get_block_grp_descriptor(block_grp)?;
         let bitmap_addr = self.to_addr(block_dtr.block_usage_bitmap);
@@ -1043,432 +1381,284 @@ impl Ext2Filesystem {
         Ok(())
     }
 
-    /// get the data of inode at offset `offset`, and allocate the data block if necessary
-    fn inode_data_alloc(&mut self, inode: (&mut Inode, u64), offset: u64) -> LinuxResult<u64> {
-        self.inode_data_may_alloc(inode, offset)
+    /// allocate a block for inode, data or indirect, near `goal`, and
+    /// count it in the disk sectors it uses
+    fn alloc_inode_block(&mut self, inode: &mut Inode, goal: Block) -> LinuxResult<Block> {
+        let block = self.alloc_block(goal).ok_or(LinuxError::ENOSPC)?;
+        inode.nbr_disk_sectors += self.block_size / 512;
+        Ok(block)
     }
 
-    /// alloc a pointer (used by the function inode_data_alloc)
-    fn alloc_pointer(&mut self, pointer_addr: u64) -> LinuxResult<Block> {
-        err_if_zero({
-            let pointer = self.disk.borrow_mut().read_struct(pointer_addr)?;
-            if pointer == Block(0) {
-                let new_block = self.alloc_block().ok_or(LinuxError::ENOSPC)?;
-                self.disk
-                    .borrow_mut()
-                    .write_struct(pointer_addr, &new_block)?;
-                new_block
-            } else {
-                pointer
-            }
-        })
+    /// free a block of inode, data or indirect
+    fn free_inode_block(&mut self, inode: &mut Inode, block: Block) -> LinuxResult<()> {
+        self.free_block(block)?;
+        inode.nbr_disk_sectors = inode.nbr_disk_sectors.saturating_sub(self.block_size / 512);
+        Ok(())
     }
 
-    fn pointer(&self, pointer_addr: u64) -> LinuxResult<Block> {
-        err_if_zero({
-            let pointer = self.disk.borrow_mut().read_struct(pointer_addr)?;
-            pointer
-        })
+    /// read the extended attributes of the inode from its block
+    fn read_xattrs(&self, inode: &Inode) -> LinuxResult<Vec<Xattr>> {
+        if inode.extended_attribute_block == Block(0) {
+            return Ok(Vec::new());
+        }
+        let mut block = vec![0; self.block_size as usize];
+        self.disk
+            .borrow_mut()
+            .read_buffer(self.to_addr(inode.extended_attribute_block), &mut block)?;
+        xattr::parse_block(&block)
     }
 
-    /// free a pointer (used by the function inode_data_alloc)
-    fn free_pointer(&mut self, pointer_addr: u64) -> LinuxResult<()> {
-        let pointer = self.disk.borrow_mut().read_struct(pointer_addr)?;
-        if pointer == Block(0) {
-            panic!("free pointer null");
+    /// write the extended attributes of the inode to its block, to a new
+    /// one if other inodes share it, or free it if there are none left
+    fn write_xattrs(
+        &mut self,
+        (inode, inode_addr): (&mut Inode, InodeAddr),
+        xattrs: &mut [Xattr],
+    ) -> LinuxResult<()> {
+        // Build the block first, to change nothing if they don't fit
+        let data = if xattrs.is_empty() {
+            None
         } else {
-            self.disk
+            Some(xattr::build_block(xattrs, 1, self.block_size as usize)?)
+        };
+        let old = inode.extended_attribute_block;
+        let shared = old != Block(0)
+            && self
+                .disk
                 .borrow_mut()
-                .write_struct(pointer_addr, &Block(0))?;
-            self.free_block(pointer)
+                .read_struct::<XattrHeader>(self.to_addr(old))?
+                .refcount
+                > 1;
+        let block = match data {
+            Some(ref data) => {
+                let block = if old != Block(0) && !shared {
+                    old
+                } else {
+                    let goal = self.to_block_addr(inode_addr);
+                    self.alloc_inode_block(inode, goal)?
+                };
+                self.disk.borrow_mut().write_buffer(self.to_addr(block), data)?;
+                if !self.superblock.has_extended_attributes() {
+                    self.superblock.set_extended_attributes();
+                    self.disk
+                        .borrow_mut()
+                        .write_struct(self.superblock_addr, &self.superblock)?;
+                }
+                block
+            }
+            None => Block(0),
+        };
+        if old != Block(0) && old != block {
+            self.release_xattr_block(inode, old)?;
         }
+        inode.extended_attribute_block = block;
+        inode.creation_time = timekeeping::ktime_get_real_coarse().as_secs() as u32;
+        self.disk.borrow_mut().write_struct(inode_addr, inode)?;
+        Ok(())
     }
 
-    /// Get the file location at offset 'offset'
-    fn inode_free_block(
-        &mut self,
-        (inode, inode_addr): (&mut Inode, InodeAddr),
-        block_off: Block,
-    ) -> LinuxResult<()> {
-        let blocknumber_per_block = (self.block_size as usize / size_of::<Block>()) as u32;
-        let block_off = block_off.0 as u64;
+    /// drop the extended attribute block of an inode: free it, or only
+    /// count one inode less sharing it
+    fn release_xattr_block(&mut self, inode: &mut Inode, block: Block) -> LinuxResult<()> {
+        let addr = self.to_addr(block);
+        let mut header: XattrHeader = self.disk.borrow_mut().read_struct(addr)?;
+        if header.refcount > 1 {
+            header.refcount -= 1;
+            self.disk.borrow_mut().write_struct(addr, &header)?;
+            inode.nbr_disk_sectors = inode.nbr_disk_sectors.saturating_sub(self.block_size / 512);
+            Ok(())
+        } else {
+            self.free_inode_block(inode, block)
+        }
+    }
+
+    /// get the data of inode at offset `offset`, and allocate the data block if necessary
+    fn inode_data_alloc(&mut self, inode: (&mut Inode, u64), offset: u64) -> LinuxResult<u64> {
+        self.inode_data_may_alloc(inode, offset)
+    }
+
+    /// the path to the block `block_off` of a file: the index of its
+    /// pointer in the inode, then in each indirect block down to it, and
+    /// how many of them there are
+    fn block_to_path(&self, block_off: u64) -> LinuxResult<([usize; 4], usize)> {
+        let ptrs = (self.block_size as usize / size_of::<Block>()) as u64;
+        let mut off = block_off;
 
         // SIMPLE ADDRESSING
-        let mut offset_start = 0;
-        let mut offset_end = 12;
-        if block_off >= offset_start && block_off < offset_end {
-            let pointer = err_if_zero(inode.direct_block_pointers[block_off as usize])?;
-            self.free_block(pointer)?;
-            inode.direct_block_pointers[block_off as usize] = Block(0);
-            self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-            return Ok(());
+        if off < NDIR_BLOCKS as u64 {
+            return Ok(([off as usize, 0, 0, 0], 1));
         }
-
         // SINGLY INDIRECT ADDRESSING
-        // 12 * blocksize .. 12 * blocksize + (blocksize / 4) * blocksize
-        offset_start = offset_end;
-        offset_end += blocknumber_per_block as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = (block_off - offset_start) as u64;
-            let pointer = err_if_zero(inode.singly_indirect_block_pointers)?;
-
-            self.free_pointer(self.to_addr(pointer) + off * size_of::<Block>() as u64)?;
-
-            if block_off == offset_start {
-                let pointer = err_if_zero(inode.singly_indirect_block_pointers)?;
-                self.free_block(pointer)?;
-                inode.singly_indirect_block_pointers = Block(0);
-                self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-            }
-            return Ok(());
+        off -= NDIR_BLOCKS as u64;
+        if off < ptrs {
+            return Ok(([IND_BLOCK, off as usize, 0, 0], 2));
         }
-
         // DOUBLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end += (blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let doubly_indirect = err_if_zero(inode.doubly_indirect_block_pointers)?;
-
-            let off_doubly = (block_off - offset_start) / blocknumber_per_block as u64;
-            let addr_pointer_to_pointer =
-                self.to_addr(doubly_indirect) + off_doubly * size_of::<Block>() as u64;
-
-            let pointer_to_pointer: Block = err_if_zero(
-                self.disk
-                    .borrow_mut()
-                    .read_struct(addr_pointer_to_pointer)?,
-            )?;
-            let off = (block_off - offset_start) % blocknumber_per_block as u64;
-
-            self.free_pointer(self.to_addr(pointer_to_pointer) + off * size_of::<Block>() as u64)?;
-
-            if off == 0 {
-                self.free_pointer(addr_pointer_to_pointer)?;
-            }
-
-            if block_off == offset_start {
-                let pointer = err_if_zero(inode.doubly_indirect_block_pointers)?;
-                self.free_block(pointer)?;
-                inode.doubly_indirect_block_pointers = Block(0);
-                self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-            }
-            return Ok(());
+        off -= ptrs;
+        if off < ptrs * ptrs {
+            return Ok(([DIND_BLOCK, (off / ptrs) as usize, (off % ptrs) as usize, 0], 3));
         }
-
         // TRIPLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end +=
-            (blocknumber_per_block * blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off_triply =
-                (block_off - offset_start) / (blocknumber_per_block * blocknumber_per_block) as u64;
-
-            let tripply_indirect = err_if_zero(inode.triply_indirect_block_pointers)?;
-
-            let addr_pointer_to_pointer_to_pointer =
-                self.to_addr(tripply_indirect) + off_triply * size_of::<Block>() as u64;
-            let pointer_to_pointer_to_pointer: Block = err_if_zero(
-                self.disk
-                    .borrow_mut()
-                    .read_struct(addr_pointer_to_pointer_to_pointer)?,
-            )?;
-
-            let off_doubly = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                / blocknumber_per_block as u64) as u64;
-
-            let addr_pointer_to_pointer = self.to_addr(pointer_to_pointer_to_pointer)
-                + off_doubly * size_of::<Block>() as u64;
-
-            let pointer_to_pointer: Block = err_if_zero(
-                self.disk
-                    .borrow_mut()
-                    .read_struct(addr_pointer_to_pointer)?,
-            )?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                % blocknumber_per_block as u64) as u64;
+        off -= ptrs * ptrs;
+        if off < ptrs * ptrs * ptrs {
+            return Ok((
+                [
+                    TIND_BLOCK,
+                    (off / (ptrs * ptrs)) as usize,
+                    (off / ptrs % ptrs) as usize,
+                    (off % ptrs) as usize,
+                ],
+                4,
+            ));
+        }
+        Err(LinuxError::EFBIG)
+    }
 
-            self.free_pointer(self.to_addr(pointer_to_pointer) + off * size_of::<Block>() as u64)?;
+    /// the address of the pointer number `off` in the indirect block `block`
+    fn pointer_addr(&self, block: Block, off: usize) -> u64 {
+        self.to_addr(block) + (off * size_of::<Block>()) as u64
+    }
 
-            if off == 0 {
-                self.free_pointer(addr_pointer_to_pointer)?;
+    /// where to allocate a block whose pointer is number `off` in the
+    /// indirect block `block`, or in the inode if None: after the closest
+    /// block mapped before it there, or else after that indirect block,
+    /// or after the inode in the inode table, in its block grp
+    fn find_near(
+        &self,
+        (inode, inode_addr): (&Inode, InodeAddr),
+        block: Option<Block>,
+        off: usize,
+    ) -> LinuxResult<Block> {
+        let near = match block {
+            None => (0..off).rev().map(|i| inode.block_pointer(i)).find(|b| *b != Block(0)),
+            Some(block) => {
+                let mut pointers = vec![Block(0); off];
+                unsafe {
+                    self.disk.borrow_mut().read_buffer(
+                        self.to_addr(block),
+                        core::slice::from_raw_parts_mut(
+                            pointers.as_mut_ptr() as *mut u8,
+                            off * size_of::<Block>(),
+                        ),
+                    )?
+                };
+                pointers.into_iter().rev().find(|b| *b != Block(0)).or(Some(block))
             }
+        };
+        Ok(near.unwrap_or(self.to_block_addr(inode_addr)))
+    }
 
-            if off_doubly == 0 {
-                self.free_pointer(addr_pointer_to_pointer_to_pointer)?;
+    /// free the block `block_off` of the inode if it is mapped, and the
+    /// indirect blocks down to it that it is the first block under, as
+    /// all the blocks after it are already freed
+    fn inode_free_block(
+        &mut self,
+        (inode, inode_addr): (&mut Inode, InodeAddr),
+        block_off: Block,
+    ) -> LinuxResult<()> {
+        let (path, depth) = self.block_to_path(block_off.0 as u64)?;
+
+        // the pointers down to the block, as (address in an indirect
+        // block, or None in the inode, block), until a hole
+        let mut chain: [(Option<u64>, Block); 4] = [(None, Block(0)); 4];
+        let mut block = inode.block_pointer(path[0]);
+        chain[0] = (None, block);
+        let mut level = 1;
+        while level < depth && block != Block(0) {
+            let addr = self.pointer_addr(block, path[level]);
+            block = self.disk.borrow_mut().read_struct(addr)?;
+            chain[level] = (Some(addr), block);
+            level += 1;
+        }
+
+        for (i, &(addr, block)) in chain[..level].iter().enumerate().rev() {
+            if block == Block(0) {
+                continue;
             }
-
-            if block_off == offset_start {
-                let pointer = err_if_zero(inode.triply_indirect_block_pointers)?;
-                self.free_block(pointer)?;
-                inode.triply_indirect_block_pointers = Block(0);
-                self.disk.borrow_mut().write_struct(inode_addr, inode)?;
+            // an indirect block still maps the blocks before this one
+            if path[i + 1..depth].iter().any(|&off| off != 0) {
+                break;
+            }
+            self.free_inode_block(inode, block)?;
+            if let Some(addr) = addr {
+                self.disk.borrow_mut().write_struct(addr, &Block(0))?;
+            } else {
+                inode.set_block_pointer(path[0], Block(0));
             }
-            return Ok(());
         }
-        Err(LinuxError::EFBIG)
+        self.disk.borrow_mut().write_struct(inode_addr, inode)?;
+        Ok(())
     }
 
-    /// Get the file location at offset 'offset'
+    /// Get the file location at offset 'offset', allocating the data
+    /// block and the indirect blocks down to it if necessary, each near
+    /// the block before it in the file
     fn inode_data_may_alloc(
         &mut self,
         (inode, inode_addr): (&mut Inode, InodeAddr),
         offset: u64,
     ) -> LinuxResult<u64> {
-        let block_off = offset / self.block_size as u64;
-        let blocknumber_per_block = self.block_size as usize / size_of::<Block>();
-
-        // SIMPLE ADDRESSING
-        let mut offset_start = 0;
-        let mut offset_end = 12;
-        if block_off >= offset_start && block_off < offset_end {
-            if inode.direct_block_pointers[block_off as usize] == Block(0) {
-                inode.direct_block_pointers[block_off as usize] =
-                    self.alloc_block().ok_or(LinuxError::ENOSPC)?;
-                self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-            }
-            return Ok(self.to_addr(err_if_zero(
-                inode.direct_block_pointers[block_off as usize],
-            )?) + offset % self.block_size as u64);
-        }
+        let (path, depth) = self.block_to_path(offset >> self.block_shift)?;
 
-        // SINGLY INDIRECT ADDRESSING
-        // 12 * blocksize .. 12 * blocksize + (blocksize / 4) * blocksize
-        offset_start = offset_end;
-        offset_end += blocknumber_per_block as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = block_off - offset_start;
-
-            let singly_indirect = err_if_zero({
-                if inode.singly_indirect_block_pointers == Block(0) {
-                    inode.singly_indirect_block_pointers =
-                        self.alloc_block().ok_or(LinuxError::ENOSPC)?;
-                    self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-                }
-                inode.singly_indirect_block_pointers
-            })?;
-
-            let pointer: Block = self
-                .alloc_pointer(self.to_addr(singly_indirect) + off * size_of::<Block>() as u64)?;
-
-            return Ok(self.to_addr(pointer) + offset % self.block_size as u64);
-        }
-
-        // DOUBLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end += (blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = (block_off - offset_start) / blocknumber_per_block as u64;
-            let doubly_indirect = err_if_zero({
-                if inode.doubly_indirect_block_pointers == Block(0) {
-                    inode.doubly_indirect_block_pointers =
-                        self.alloc_block().ok_or(LinuxError::ENOSPC)?;
-                    self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-                }
-                inode.doubly_indirect_block_pointers
-            })?;
-            let pointer_to_pointer: Block = self
-                .alloc_pointer(self.to_addr(doubly_indirect) + off * size_of::<Block>() as u64)?;
-            let off = (block_off - offset_start) % blocknumber_per_block as u64;
-            let pointer: Block = self.alloc_pointer(
-                self.to_addr(pointer_to_pointer) + off * size_of::<Block>() as u64,
-            )?;
-            return Ok(self.to_addr(pointer) + offset % self.block_size as u64);
+        let mut block = inode.block_pointer(path[0]);
+        if block == Block(0) {
+            let goal = self.find_near((&*inode, inode_addr), None, path[0])?;
+            block = self.alloc_inode_block(inode, goal)?;
+            inode.set_block_pointer(path[0], block);
+            self.disk.borrow_mut().write_struct(inode_addr, inode)?;
         }
-
-        // TRIPLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end +=
-            (blocknumber_per_block * blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off =
-                (block_off - offset_start) / (blocknumber_per_block * blocknumber_per_block) as u64;
-
-            let tripply_indirect = err_if_zero({
-                if inode.triply_indirect_block_pointers == Block(0) {
-                    inode.triply_indirect_block_pointers =
-                        self.alloc_block().ok_or(LinuxError::ENOSPC)?;
-                    self.disk.borrow_mut().write_struct(inode_addr, inode)?;
-                }
-                inode.triply_indirect_block_pointers
-            })?;
-            let pointer_to_pointer_to_pointer: Block = self
-                .alloc_pointer(self.to_addr(tripply_indirect) + off * size_of::<Block>() as u64)?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                / blocknumber_per_block as u64) as u64;
-            let pointer_to_pointer: Block = self.alloc_pointer(
-                self.to_addr(pointer_to_pointer_to_pointer) + off * size_of::<Block>() as u64,
-            )?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                % blocknumber_per_block as u64) as u64;
-            let pointer: Block = self.alloc_pointer(
-                self.to_addr(pointer_to_pointer) + off * size_of::<Block>() as u64,
-            )?;
-
-            return Ok(self.to_addr(pointer) + offset % self.block_size as u64);
+        for &off in &path[1..depth] {
+            let addr = self.pointer_addr(block, off);
+            let pointer: Block = self.disk.borrow_mut().read_struct(addr)?;
+            block = if pointer == Block(0) {
+                let goal = self.find_near((&*inode, inode_addr), Some(block), off)?;
+                let new_block = self.alloc_inode_block(inode, goal)?;
+                self.disk.borrow_mut().write_struct(addr, &new_block)?;
+                self.disk.borrow_mut().write_struct(inode_addr, inode)?;
+                new_block
+            } else {
+                pointer
+            };
         }
-        Err(LinuxError::EFBIG)
+        Ok(self.to_addr(block) + (offset & self.block_mask as u64))
     }
 
-    /// Get the file location at offset 'offset'
-    /// Return which block store the file data at offset T
+    /// the block `block_off` of the inode, or None in a hole
     /// Simple Read without CACHE
-    fn inode_data_xxx(&self, inode: &mut Inode, offset: u64) -> LinuxResult<u64> {
-        let block_off = offset / self.block_size as u64;
-        let blocknumber_per_block = self.block_size as usize / size_of::<Block>();
-
-        // SIMPLE ADDRESSING
-        let mut offset_start = 0;
-        let mut offset_end = 12;
-        if block_off >= offset_start && block_off < offset_end {
-            return Ok(self.to_addr(err_if_zero(
-                inode.direct_block_pointers[block_off as usize],
-            )?) + offset % self.block_size as u64);
-        }
-
-        // SINGLY INDIRECT ADDRESSING
-        // 12 * blocksize .. 12 * blocksize + (blocksize / 4) * blocksize
-        offset_start = offset_end;
-        offset_end += blocknumber_per_block as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = block_off - offset_start;
-
-            let singly_indirect = err_if_zero(inode.singly_indirect_block_pointers)?;
-
-            let pointer: Block =
-                self.pointer(self.to_addr(singly_indirect) + off * size_of::<Block>() as u64)?;
-
-            return Ok(self.to_addr(pointer) + offset % self.block_size as u64);
+    fn inode_block(&self, inode: &Inode, block_off: u64) -> LinuxResult<Option<Block>> {
+        let (path, depth) = self.block_to_path(block_off)?;
+        let mut block = inode.block_pointer(path[0]);
+        for &off in &path[1..depth] {
+            if block == Block(0) {
+                return Ok(None);
+            }
+            block = self.disk.borrow_mut().read_struct(self.pointer_addr(block, off))?;
         }
+        Ok(Some(block).filter(|b| *b != Block(0)))
+    }
 
-        // DOUBLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end += (blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = (block_off - offset_start) / blocknumber_per_block as u64;
-            let doubly_indirect = err_if_zero(inode.doubly_indirect_block_pointers)?;
-            let pointer_to_pointer: Block =
-                self.pointer(self.to_addr(doubly_indirect) + off * size_of::<Block>() as u64)?;
-            let off = (block_off - offset_start) % blocknumber_per_block as u64;
-            let pointer: Block =
-                self.pointer(self.to_addr(pointer_to_pointer) + off * size_of::<Block>() as u64)?;
-            return Ok(self.to_addr(pointer) + offset % self.block_size as u64);
-        }
+    /// the block `block_off` of the inode, or None in a hole
+    /// Read with the CACHE of the indirect blocks
+    fn inode_block_cached(&mut self, inode: &Inode, block_off: u64) -> LinuxResult<Option<Block>> {
+        const LEVELS: [Level; NB_LAYERS] = [Level::L1, Level::L2, Level::L3];
 
-        // TRIPLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end +=
-            (blocknumber_per_block * blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off =
-                (block_off - offset_start) / (blocknumber_per_block * blocknumber_per_block) as u64;
-
-            let tripply_indirect = err_if_zero(inode.triply_indirect_block_pointers)?;
-            let pointer_to_pointer_to_pointer: Block =
-                self.pointer(self.to_addr(tripply_indirect) + off * size_of::<Block>() as u64)?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                / blocknumber_per_block as u64) as u64;
-            let pointer_to_pointer: Block = self.pointer(
-                self.to_addr(pointer_to_pointer_to_pointer) + off * size_of::<Block>() as u64,
-            )?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                % blocknumber_per_block as u64) as u64;
-            let pointer: Block =
-                self.pointer(self.to_addr(pointer_to_pointer) + off * size_of::<Block>() as u64)?;
-
-            return Ok(self.to_addr(pointer) + offset % self.block_size as u64);
+        let (path, depth) = self.block_to_path(block_off)?;
+        let mut block = inode.block_pointer(path[0]);
+        for (i, &off) in path[1..depth].iter().enumerate() {
+            if block == Block(0) {
+                return Ok(None);
+            }
+            block = self.get_pointer(self.to_addr(block), off as u64, LEVELS[i])?;
         }
-        Err(LinuxError::EFBIG)
+        Ok(Some(block).filter(|b| *b != Block(0)))
     }
 
     /// Get the file location at offset 'offset'
     /// Return which block store the file data at offset T
-    /// Simple Read
-    fn inode_data(&mut self, inode: &Inode, offset: u64) -> LinuxResult<u64> {
-        let block_off = offset >> self.block_shift as u64;
-        let blocknumber_per_block = self.block_size as usize / size_of::<Block>();
-        let blocknumber_per_block_mask = blocknumber_per_block - 1;
-        let blocknumber_per_block_shift = usize::trailing_zeros(blocknumber_per_block);
-
-        // SIMPLE ADDRESSING
-        let mut offset_start = 0;
-        let mut offset_end = 12;
-        if block_off >= offset_start && block_off < offset_end {
-            return Ok(self.to_addr(err_if_zero(
-                inode.direct_block_pointers[block_off as usize],
-            )?) + (offset & self.block_mask as u64));
-        }
-
-        // SINGLY INDIRECT ADDRESSING
-        // 12 * blocksize .. 12 * blocksize + (blocksize / 4) * blocksize
-        offset_start = offset_end;
-        offset_end += blocknumber_per_block as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = block_off - offset_start;
-            let singly_indirect = err_if_zero(inode.singly_indirect_block_pointers)?;
-
-            let addr = self.to_addr(singly_indirect);
-            let pointer = self.get_pointer(addr, off, Level::L1)?;
-            return Ok(self.to_addr(pointer) + (offset & self.block_mask as u64));
-        }
-
-        // DOUBLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end += (blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off = (block_off - offset_start) >> blocknumber_per_block_shift as u64;
-            let doubly_indirect = err_if_zero(inode.doubly_indirect_block_pointers)?;
-
-            let addr = self.to_addr(doubly_indirect);
-            let pointer_to_pointer = self.get_pointer(addr, off, Level::L1)?;
-
-            let off = (block_off - offset_start) & blocknumber_per_block_mask as u64;
-
-            let addr = self.to_addr(pointer_to_pointer);
-            let pointer = self.get_pointer(addr, off, Level::L2)?;
-
-            return Ok(self.to_addr(pointer) + (offset & self.block_mask as u64));
-        }
-
-        // TRIPLY INDIRECT ADDRESSING
-        offset_start = offset_end;
-        offset_end +=
-            (blocknumber_per_block * blocknumber_per_block * blocknumber_per_block) as u64;
-        if block_off >= offset_start && block_off < offset_end {
-            let off =
-                (block_off - offset_start) / (blocknumber_per_block * blocknumber_per_block) as u64;
-            let tripply_indirect = err_if_zero(inode.triply_indirect_block_pointers)?;
-
-            let addr = self.to_addr(tripply_indirect);
-            let pointer_to_pointer_to_pointer = self.get_pointer(addr, off, Level::L1)?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                >> blocknumber_per_block_shift as u64) as u64;
-
-            let addr = self.to_addr(pointer_to_pointer_to_pointer);
-            let pointer_to_pointer = self.get_pointer(addr, off, Level::L2)?;
-
-            let off = (((block_off - offset_start)
-                % (blocknumber_per_block * blocknumber_per_block) as u64)
-                & blocknumber_per_block_mask as u64) as u64;
-
-            let addr = self.to_addr(pointer_to_pointer);
-            let pointer = self.get_pointer(addr, off, Level::L3)?;
-
-            return Ok(self.to_addr(pointer) + (offset & self.block_mask as u64));
-        }
-        Err(LinuxError::EFBIG)
+    /// Simple Read without CACHE
+    fn inode_data_xxx(&self, inode: &mut Inode, offset: u64) -> LinuxResult<u64> {
+        let block = self.inode_block(inode, offset >> self.block_shift)?;
+        Ok(self.to_addr(err_if_zero(block.unwrap_or_default())?) + (offset & self.block_mask as u64))
     }
 
     /// Get a inode pointer
@@ -1634,6 +1824,22 @@ impl VfsOps for Ext2Fs {
         let entry = self.inner.lock()._find_entry(2, &path).unwrap().unwrap();
         Arc::new(Ext2Inode::new(entry))
     }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        Ok(Ext2Fs::statfs(self))
+    }
+
+    fn sync(&self) -> VfsResult {
+        Ok(Ext2Fs::sync(self)?)
+    }
+
+    fn remount(&self, flags: usize) -> VfsResult {
+        // Leave nothing behind when it turns read-only.
+        if flags & MS_RDONLY != 0 {
+            Ext2Fs::sync(self)?;
+        }
+        Ok(())
+    }
 }
 
 unsafe impl Sync for Ext2Fs {}
@@ -1728,11 +1934,51 @@ impl VfsNodeOps for Ext2Inode {
         Ok(ret as usize)
     }
 
+    fn readahead(&self, offset: u64, len: usize) -> VfsResult {
+        let ino = self.entry.directory.get_inode();
+        Ok(Ext2Fs::get().readahead(ino, offset, len as u64)?)
+    }
+
+    fn read_direct(&self, mut offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        assert!(!self.entry.inode.type_and_perm.is_directory());
+        let ino = self.entry.directory.get_inode();
+        let ret = Ext2Fs::get().read_direct(ino, &mut offset, buf)?;
+        self.curr_offset.store(offset, Ordering::Relaxed);
+        Ok(ret as usize)
+    }
+
+    fn write_direct(&self, mut offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let ino = self.entry.directory.get_inode();
+        let ret = Ext2Fs::get().write_direct(ino, &mut offset, buf)?;
+        self.curr_offset.store(offset, Ordering::Relaxed);
+        Ok(ret as usize)
+    }
+
     fn truncate(&self, size: u64) -> VfsResult {
         info!("truncate");
         let ino = self.entry.directory.get_inode();
         Ok(Ext2Fs::get().truncate(ino, size)?)
     }
+
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        let ino = self.entry.directory.get_inode();
+        Ok(Ext2Fs::get().getxattr(ino, name, buf)?)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: usize) -> VfsResult {
+        let ino = self.entry.directory.get_inode();
+        Ok(Ext2Fs::get().setxattr(ino, name, value, flags)?)
+    }
+
+    fn listxattr(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let ino = self.entry.directory.get_inode();
+        Ok(Ext2Fs::get().listxattr(ino, buf)?)
+    }
+
+    fn removexattr(&self, name: &str) -> VfsResult {
+        let ino = self.entry.directory.get_inode();
+        Ok(Ext2Fs::get().removexattr(ino, name)?)
+    }
 }
 
 /// Magic iterator over the entire fileSytem
@@ -1786,13 +2032,21 @@ pub trait Ext2Disk {
     }
 }
 
-unsafe fn libc_memcmp(s1: *const u8, s2: *const u8, n: usize) -> bool {
-    for i in 0..n {
-        if *s1.add(i) != *s2.add(i) {
-            return false;
-        }
+/// True if the name index is the one of a POSIX ACL
+fn is_acl(index: u8) -> bool {
+    index == XATTR_INDEX_POSIX_ACL_ACCESS || index == XATTR_INDEX_POSIX_ACL_DEFAULT
+}
+
+/// Copy `data` into `buf`, or only get its size if `buf` is empty
+fn copy_out(data: &[u8], buf: &mut [u8]) -> LinuxResult<usize> {
+    if buf.is_empty() {
+        return Ok(data.len());
+    }
+    if buf.len() < data.len() {
+        return Err(LinuxError::ERANGE);
     }
-    true
+    buf[..data.len()].copy_from_slice(data);
+    Ok(data.len())
 }
 
 fn def_mode() -> Mode {
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+extern crate alloc;
+
+use alloc::vec;
+use core::panic::PanicInfo;
+use axfs_vfs::{VfsNodeRef, VfsNodeType};
+
+const CHUNK: usize = 64 * 1024;
+/// In the double indirect blocks, with 1K blocks or 4K.
+const BIG_SIZE: usize = 6 * 1024 * 1024;
+/// In the triple indirect blocks with 1K blocks.
+const SPARSE_OFFSET: u64 = 70 * 1024 * 1024;
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    assert_eq!(cpu_id, 0);
+
+    axlog2::init("info");
+    info!("[rt_ext2fs]: ...");
+
+    axhal::cpu::init_primary(cpu_id);
+    axalloc::init();
+    page_table::init();
+
+    fstree::init(cpu_id, dtb_pa);
+    let fs = fstree::init_fs();
+    let locked_fs = fs.lock();
+    let root = locked_fs.root_dir().unwrap();
+    let free = root.statfs("/").unwrap().f_bfree;
+
+    let fname = "/rt_ext2fs_big";
+    let file = locked_fs.create_file(None, fname, VfsNodeType::File, 0, 0, 0o644).unwrap();
+    test_big_file(&file);
+    let sparse = "/rt_ext2fs_sparse";
+    let file = locked_fs.create_file(None, sparse, VfsNodeType::File, 0, 0, 0o644).unwrap();
+    test_sparse_file(&file);
+
+    // Truncating and removing them gives back all their blocks, the
+    // indirect ones too.
+    let file = locked_fs.lookup(None, fname, 0).unwrap();
+    file.truncate(0).unwrap();
+    let mut buf = [0u8; 16];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 0);
+    assert!(locked_fs.remove_file(None, fname).is_ok());
+    assert!(locked_fs.remove_file(None, sparse).is_ok());
+    root.sync().unwrap();
+    assert_eq!(root.statfs("/").unwrap().f_bfree, free);
+    info!("[rt_ext2fs]: blocks freed ok!");
+
+    info!("[rt_ext2fs]: ok!");
+    axhal::misc::terminate();
+}
+
+fn pattern(pos: usize) -> u8 {
+    // Not a divisor of the block size, so that each block differs.
+    (pos % 251) as u8
+}
+
+/// A file of some megabytes, written and read back in chunks, maps its
+/// blocks through the indirect ones.
+fn test_big_file(file: &VfsNodeRef) {
+    let mut buf = vec![0u8; CHUNK];
+    for off in (0..BIG_SIZE).step_by(CHUNK) {
+        buf.iter_mut().enumerate().for_each(|(i, b)| *b = pattern(off + i));
+        assert_eq!(file.write_at(off as u64, &buf).unwrap(), CHUNK);
+    }
+    for off in (0..BIG_SIZE).step_by(CHUNK) {
+        buf.fill(0);
+        assert_eq!(file.read_at(off as u64, &mut buf).unwrap(), CHUNK);
+        assert!(buf.iter().enumerate().all(|(i, b)| *b == pattern(off + i)));
+    }
+    // Nothing is read at the end.
+    assert_eq!(file.read_at(BIG_SIZE as u64, &mut buf).unwrap(), 0);
+    info!("[rt_ext2fs]: big file ok!");
+}
+
+/// Data written far past the end leaves a hole, read as zeroes.
+fn test_sparse_file(file: &VfsNodeRef) {
+    let data = b"far away";
+    assert_eq!(file.write_at(SPARSE_OFFSET, data).unwrap(), data.len());
+
+    let mut buf = [0xffu8; 64];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), buf.len());
+    assert_eq!(buf, [0; 64]);
+    let hole = SPARSE_OFFSET - buf.len() as u64;
+    assert_eq!(file.read_at(hole, &mut buf).unwrap(), buf.len());
+    assert_eq!(buf, [0; 64]);
+    assert_eq!(file.read_at(SPARSE_OFFSET, &mut buf).unwrap(), data.len());
+    assert_eq!(&buf[..data.len()], data);
+    info!("[rt_ext2fs]: sparse file ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
@@ -0,0 +1,229 @@
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axio::PollState;
+use axtype::MAJOR_FB;
+
+use crate::FbInfo;
+
+/* ioctls */
+const FBIOGET_VSCREENINFO: usize = 0x4600;
+const FBIOPUT_VSCREENINFO: usize = 0x4601;
+const FBIOGET_FSCREENINFO: usize = 0x4602;
+const FBIOPAN_DISPLAY: usize = 0x4606;
+const FBIOBLANK: usize = 0x4611;
+
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+impl FbVarScreeninfo {
+    fn new(info: &FbInfo) -> Self {
+        let bitfield = |offset| FbBitfield { offset, length: 8, msb_right: 0 };
+        Self {
+            xres: info.width,
+            yres: info.height,
+            xres_virtual: info.width,
+            yres_virtual: info.height,
+            bits_per_pixel: info.bits_per_pixel,
+            red: bitfield(16),
+            green: bitfield(8),
+            blue: bitfield(0),
+            // Unknown physical size
+            height: u32::MAX,
+            width: u32::MAX,
+            ..Default::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FbFixScreeninfo {
+    id: [u8; 16],
+    smem_start: usize,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: usize,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+impl FbFixScreeninfo {
+    fn new(info: &FbInfo) -> Self {
+        Self {
+            id: info.id,
+            smem_start: info.base_pa,
+            smem_len: info.size as u32,
+            type_: FB_TYPE_PACKED_PIXELS,
+            visual: FB_VISUAL_TRUECOLOR,
+            line_length: info.stride,
+            ..Default::default()
+        }
+    }
+}
+
+/// `/dev/fb0`, the framebuffer.
+///
+/// Reads and writes go to the pixels at the offset, and mmap maps them.
+pub struct FbDev;
+
+impl FbDev {
+    fn info() -> VfsResult<FbInfo> {
+        crate::info().ok_or(AxError::NoDevOrAddr)
+    }
+}
+
+impl VfsNodeOps for FbDev {
+    fn get_ino(&self) -> usize {
+        0
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+            0,
+            0,
+        );
+        attr.set_rdev(MAJOR_FB, 0);
+        Ok(attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let info = Self::info()?;
+        let offset = offset as usize;
+        if offset >= info.size {
+            return Ok(0);
+        }
+        let len = buf.len().min(info.size - offset);
+        let src = unsafe {
+            core::slice::from_raw_parts((info.base_va + offset) as *const u8, len)
+        };
+        buf[..len].copy_from_slice(src);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let info = Self::info()?;
+        let offset = offset as usize;
+        if offset >= info.size {
+            return if buf.is_empty() { Ok(0) } else { Err(AxError::StorageFull) };
+        }
+        let len = buf.len().min(info.size - offset);
+        let dst = unsafe {
+            core::slice::from_raw_parts_mut((info.base_va + offset) as *mut u8, len)
+        };
+        dst.copy_from_slice(&buf[..len]);
+        crate::flush();
+        Ok(len)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn ioctl(&self, req: usize, data: usize) -> VfsResult<usize> {
+        let info = Self::info()?;
+        match req {
+            FBIOGET_VSCREENINFO | FBIOPUT_VSCREENINFO => {
+                // The mode is fixed, so setting one leaves it as it is
+                // and tells what it is.
+                if data == 0 {
+                    return Err(AxError::BadAddress);
+                }
+                unsafe { *(data as *mut FbVarScreeninfo) = FbVarScreeninfo::new(&info) };
+                Ok(0)
+            },
+            FBIOGET_FSCREENINFO => {
+                if data == 0 {
+                    return Err(AxError::BadAddress);
+                }
+                unsafe { *(data as *mut FbFixScreeninfo) = FbFixScreeninfo::new(&info) };
+                Ok(0)
+            },
+            FBIOPAN_DISPLAY => {
+                if data == 0 {
+                    return Err(AxError::BadAddress);
+                }
+                let var = unsafe { *(data as *const FbVarScreeninfo) };
+                if var.xoffset != 0 || var.yoffset != 0 {
+                    return Err(AxError::InvalidInput);
+                }
+                crate::flush();
+                Ok(0)
+            },
+            FBIOBLANK => Ok(0),
+            _ => Err(AxError::Unsupported),
+        }
+    }
+
+    fn get_page(&self, offset: u64) -> VfsResult<usize> {
+        let info = Self::info()?;
+        let offset = offset as usize;
+        if offset >= info.size {
+            return Err(AxError::InvalidInput);
+        }
+        crate::start_deferred_io();
+        Ok(info.base_va + offset)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
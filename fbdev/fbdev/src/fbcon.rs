@@ -0,0 +1,129 @@
+//! A text console drawn on the framebuffer.
+//!
+//! Characters are cells of the 8x8 font with each line doubled, light grey
+//! on black, and the screen scrolls up when the last row is full. Escape
+//! sequences are skipped rather than interpreted.
+
+use spinbase::SpinNoIrq;
+
+use crate::font::FONT8X8;
+use crate::FbInfo;
+
+const CHAR_WIDTH: usize = 8;
+const CHAR_HEIGHT: usize = 16;
+
+const FG_COLOR: u32 = 0x00aa_aaaa;
+const BG_COLOR: u32 = 0x0000_0000;
+
+const ESC: u8 = 0x1b;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EscState {
+    Normal,
+    Escape,
+    Csi,
+}
+
+struct FbCon {
+    col: usize,
+    row: usize,
+    esc: EscState,
+}
+
+static FBCON: SpinNoIrq<FbCon> = SpinNoIrq::new(FbCon {
+    col: 0,
+    row: 0,
+    esc: EscState::Normal,
+});
+
+impl FbCon {
+    fn put_pixel(info: &FbInfo, x: usize, y: usize, color: u32) {
+        let pos = y * info.stride as usize + x * 4;
+        unsafe { *((info.base_va + pos) as *mut u32) = color };
+    }
+
+    fn draw_char(&self, info: &FbInfo, c: u8) {
+        let glyph = &FONT8X8[(c - b' ') as usize];
+        let x0 = self.col * CHAR_WIDTH;
+        let y0 = self.row * CHAR_HEIGHT;
+        for y in 0..CHAR_HEIGHT {
+            let bits = glyph[y / 2];
+            for x in 0..CHAR_WIDTH {
+                let color = if (bits >> x) & 1 != 0 { FG_COLOR } else { BG_COLOR };
+                Self::put_pixel(info, x0 + x, y0 + y, color);
+            }
+        }
+    }
+
+    fn scroll_up(info: &FbInfo, rows: usize) {
+        let line = CHAR_HEIGHT * info.stride as usize;
+        let base = info.base_va as *mut u8;
+        unsafe {
+            core::ptr::copy(base.add(line), base, (rows - 1) * line);
+            core::ptr::write_bytes(base.add((rows - 1) * line), 0, line);
+        }
+    }
+
+    fn newline(&mut self, info: &FbInfo, rows: usize) {
+        self.col = 0;
+        if self.row + 1 < rows {
+            self.row += 1;
+        } else {
+            Self::scroll_up(info, rows);
+        }
+    }
+
+    fn putc(&mut self, info: &FbInfo, c: u8) {
+        let cols = info.width as usize / CHAR_WIDTH;
+        let rows = info.height as usize / CHAR_HEIGHT;
+        match self.esc {
+            EscState::Escape => {
+                self.esc = if c == b'[' { EscState::Csi } else { EscState::Normal };
+                return;
+            },
+            EscState::Csi => {
+                if (0x40..=0x7e).contains(&c) {
+                    self.esc = EscState::Normal;
+                }
+                return;
+            },
+            EscState::Normal => (),
+        }
+        match c {
+            ESC => self.esc = EscState::Escape,
+            b'\n' => self.newline(info, rows),
+            b'\r' => self.col = 0,
+            b'\t' => {
+                self.col = (self.col + 8) & !7;
+                if self.col >= cols {
+                    self.newline(info, rows);
+                }
+            },
+            0x08 => self.col = self.col.saturating_sub(1),
+            b' '..=b'~' => {
+                if self.col >= cols {
+                    self.newline(info, rows);
+                }
+                self.draw_char(info, c);
+                self.col += 1;
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Draws `buf` at the cursor of the console, if there is a framebuffer.
+pub fn write_bytes(buf: &[u8]) {
+    let Some(info) = crate::info() else {
+        return;
+    };
+    if info.width < CHAR_WIDTH as u32 || info.height < CHAR_HEIGHT as u32 {
+        return;
+    }
+    let mut con = FBCON.lock();
+    for &c in buf {
+        con.putc(&info, c);
+    }
+    drop(con);
+    crate::flush();
+}
@@ -0,0 +1,204 @@
+//! The framebuffer device layer, and /dev/fb0.
+//!
+//! Keeps the framebuffer of the display axdriver has probed, or else the
+//! one of a "simple-framebuffer" node in the device tree, which is how
+//! firmware hands over a framebuffer it has set up, e.g. for QEMU's ramfb.
+//! /dev/fb0 reads and writes it, tells its mode by the FBIOGET_* ioctls,
+//! and maps it into user space as device memory. A display that has to be
+//! flushed, as virtio-gpu does, is flushed after writes, and every
+//! [`FB_DEFERRED_IO_DELAY`] once it has been mapped, as writes through a
+//! mapping go unseen. With the `fbcon` feature, the kernel console is
+//! drawn on it too.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod dev;
+#[cfg(feature = "fbcon")]
+pub mod fbcon;
+#[cfg(feature = "fbcon")]
+mod font;
+
+pub use self::dev::FbDev;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use axdriver::prelude::*;
+use axdriver::AxDeviceContainer;
+use axhal::mem::virt_to_phys;
+use lazy_init::LazyInit;
+use spinbase::SpinNoIrq;
+
+/// How often a mapped framebuffer is flushed to the display.
+pub const FB_DEFERRED_IO_DELAY: Duration = Duration::from_millis(50);
+
+/// The mode of the framebuffer. Pixels are 32 bits, blue, green, red and
+/// an unused byte in memory order, which all of virtio-gpu and the
+/// "a8r8g8b8" and "x8r8g8b8" formats of simple-framebuffer are.
+#[derive(Debug, Clone, Copy)]
+pub struct FbInfo {
+    /// Identification, as FBIOGET_FSCREENINFO tells.
+    pub id: [u8; 16],
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per line.
+    pub stride: u32,
+    pub bits_per_pixel: u32,
+    pub base_pa: usize,
+    pub base_va: usize,
+    pub size: usize,
+}
+
+impl FbInfo {
+    fn new(name: &str, width: u32, height: u32, stride: u32, base_va: usize, size: usize) -> Self {
+        let mut id = [0u8; 16];
+        let len = name.len().min(id.len() - 1);
+        id[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self {
+            id,
+            width,
+            height,
+            stride,
+            bits_per_pixel: 32,
+            base_pa: virt_to_phys(base_va.into()).into(),
+            base_va,
+            size,
+        }
+    }
+}
+
+struct Framebuffer {
+    info: FbInfo,
+    /// The display behind it, if it is not a firmware framebuffer.
+    display: Option<SpinNoIrq<AxDisplayDevice>>,
+}
+
+static FB: LazyInit<Framebuffer> = LazyInit::new();
+/// Whether the periodic flush has started.
+static DEFERRED_IO: AtomicBool = AtomicBool::new(false);
+
+/// Takes the first display of `devs` for the framebuffer, or the
+/// simple-framebuffer of the device tree at `dtb_pa` if there is none.
+pub fn init(mut devs: AxDeviceContainer<AxDisplayDevice>, dtb_pa: usize) {
+    if let Some(dev) = devs.take_one() {
+        let di = dev.info();
+        let info = FbInfo::new(
+            dev.device_name(), di.width, di.height, di.width * 4, di.fb_base_vaddr, di.fb_size
+        );
+        info!("fbdev: {} {}x{} at {:#x}", dev.device_name(), info.width, info.height, info.base_pa);
+        FB.init_by(Framebuffer { info, display: Some(SpinNoIrq::new(dev)) });
+        return;
+    }
+    if let Some(info) = probe_simplefb(dtb_pa) {
+        info!("fbdev: simple-framebuffer {}x{} at {:#x}", info.width, info.height, info.base_pa);
+        FB.init_by(Framebuffer { info, display: None });
+        return;
+    }
+    info!("fbdev: no framebuffer");
+}
+
+#[cfg(target_arch = "riscv64")]
+fn probe_simplefb(dtb_pa: usize) -> Option<FbInfo> {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use axhal::mem::phys_to_virt;
+
+    let mut found = None;
+    let mut cb = |_name: String,
+                  addr_cells: usize,
+                  size_cells: usize,
+                  props: Vec<(String, Vec<u8>)>| {
+        if found.is_none() {
+            found = parse_simplefb(addr_cells, size_cells, &props);
+        }
+    };
+    axdtb::parse(phys_to_virt(dtb_pa.into()).into(), &mut cb);
+    found
+}
+
+#[cfg(not(target_arch = "riscv64"))]
+fn probe_simplefb(_dtb_pa: usize) -> Option<FbInfo> {
+    None
+}
+
+/// Takes the framebuffer of a node compatible with "simple-framebuffer",
+/// if it is enabled and in a format we draw.
+#[cfg(target_arch = "riscv64")]
+fn parse_simplefb(
+    addr_cells: usize,
+    size_cells: usize,
+    props: &[(alloc::string::String, alloc::vec::Vec<u8>)],
+) -> Option<FbInfo> {
+    use axdtb::read_cells;
+    use axhal::mem::phys_to_virt;
+
+    let prop = |key: &str| props.iter().find(|p| p.0 == key).map(|p| p.1.as_slice());
+    let compatible = prop("compatible")?;
+    if !compatible.split(|c| *c == 0).any(|s| s == b"simple-framebuffer") {
+        return None;
+    }
+    if let Some(status) = prop("status") {
+        if !status.starts_with(b"okay") && !status.starts_with(b"ok\0") {
+            return None;
+        }
+    }
+    let format = prop("format")?;
+    if !format.starts_with(b"a8r8g8b8\0") && !format.starts_with(b"x8r8g8b8\0") {
+        warn!("fbdev: simple-framebuffer format {:?} is not supported", format);
+        return None;
+    }
+    let reg = prop("reg")?;
+    let base = read_cells(reg, 0, addr_cells)?;
+    let size = read_cells(reg, addr_cells, size_cells)?;
+    let width = read_cells(prop("width")?, 0, 1)? as u32;
+    let height = read_cells(prop("height")?, 0, 1)? as u32;
+    let stride = read_cells(prop("stride")?, 0, 1)? as u32;
+    // Firmware puts it in RAM, which the kernel maps linearly.
+    let base_va = phys_to_virt(base.into()).into();
+    Some(FbInfo::new("simplefb", width, height, stride, base_va, size))
+}
+
+/// Whether there is a framebuffer.
+pub fn is_present() -> bool {
+    FB.is_init()
+}
+
+/// The mode of the framebuffer, if there is one.
+pub fn info() -> Option<FbInfo> {
+    FB.try_get().map(|fb| fb.info)
+}
+
+fn need_flush() -> bool {
+    FB.try_get()
+        .and_then(|fb| fb.display.as_ref())
+        .is_some_and(|display| display.lock().need_flush())
+}
+
+/// Brings what is in the framebuffer to the screen.
+pub fn flush() {
+    let Some(display) = FB.try_get().and_then(|fb| fb.display.as_ref()) else {
+        return;
+    };
+    let mut display = display.lock();
+    if display.need_flush() {
+        if let Err(e) = display.flush() {
+            warn!("fbdev: flush err {:?}", e);
+        }
+    }
+}
+
+/// Starts flushing the framebuffer periodically, as it gets mapped.
+fn start_deferred_io() {
+    if need_flush() && !DEFERRED_IO.swap(true, Ordering::AcqRel) {
+        debug!("fbdev: start deferred io");
+        workqueue::schedule_delayed_work(deferred_io, FB_DEFERRED_IO_DELAY);
+    }
+}
+
+fn deferred_io() {
+    flush();
+    workqueue::schedule_delayed_work(deferred_io, FB_DEFERRED_IO_DELAY);
+}
@@ -23,23 +23,28 @@
 extern crate log;
 
 extern crate alloc;
-use alloc::string::String;
+use alloc::string::{String, ToString};
+use alloc::collections::BTreeMap;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::format;
 use core::slice;
 use core::cmp::min;
 use axtype::{S_IFMT, S_IFREG, S_IFIFO, S_IFCHR};
-use axtype::RLIMIT_NOFILE;
+use axtype::{RLIMIT_NOFILE, RLIMIT_FSIZE, RLIM_INFINITY};
+use axtype::Cred;
 use capability::Cap;
 use pipefs::PipeNode;
 use signal::force_sig_fault;
-use axfs_vfs::VfsNodeRef;
+use axfs_vfs::{VfsNodeRef, VfsOps};
 use axmount::init_root;
 use axfs_vfs::{FileSystemInfo, VfsNodeType, VfsNodeAttrValid, VfsNodeAttr};
 use axfs_vfs::path::canonicalize;
 use axtype::MAX_LOOP_NUMBER;
 use block_loop::{LoopCtlDev, LoopDev};
+use tty::{TtyNode, CurrentTtyDev, PtmxDev, PtsDir};
 
 use axerrno::AxResult;
 use axerrno::{LinuxError, LinuxResult, linux_err, linux_err_from};
@@ -50,6 +55,8 @@ use mutex::Mutex;
 use axtype::get_user_str;
 use axio::SeekFrom;
 use axtype::{O_CREAT, O_TRUNC, O_APPEND, O_WRONLY, O_RDWR, O_EXCL, O_NOFOLLOW};
+use axtype::{O_NONBLOCK, O_CLOEXEC};
+use axtype::{MS_REMOUNT, MS_RMT_MASK};
 use procfs::init_procfs;
 
 use axtype::__O_TMPFILE;
@@ -70,8 +77,18 @@ const SEEK_SET: usize = 0;
 const SEEK_CUR: usize = 1;
 const SEEK_END: usize = 2;
 
-// dup
+// fcntl commands
 const F_DUPFD: usize = 0;
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_DUPFD_CLOEXEC: usize = 1030;
+/// The only file descriptor flag
+const FD_CLOEXEC: usize = 1;
+
+// ioctl requests common to all files
+const FIONBIO: usize = 0x5421;
 
 /// Opens a file relative to a directory file descriptor
 pub fn openat(dfd: usize, filename: &str, flags: usize, mode: usize) -> AxResult<File> {
@@ -109,17 +126,16 @@ pub fn openat(dfd: usize, filename: &str, flags: usize, mode: usize) -> AxResult
     let current = task::current();
     let fs = current.fs.lock();
 
-    let fsuid = current.fsuid();
-    let fsgid = current.fsgid();
+    let cred = current.cred();
 
     let path = handle_path(dfd, filename);
     debug!("openat path {} flags", path);
 
     if (flags as i32 & __O_TMPFILE) != 0 {
-        return do_tmpfile(&path, &opts, fsuid, fsgid);
+        return do_tmpfile(&path, &opts, cred.fsuid, cred.fsgid);
     }
 
-    File::open(&path, &opts, &fs, fsuid, fsgid)
+    File::open(&path, &opts, &fs, &cred)
 }
 
 fn do_tmpfile(path: &str, opts: &OpenOptions, uid: u32, gid: u32) -> AxResult<File> {
@@ -146,15 +162,18 @@ pub fn register_file(file: AxResult<File>, flags: usize) -> usize {
             return linux_err_from!(e);
         }
     };
+    install_file(Arc::new(Mutex::new(file)), flags).unwrap_or_else(|e| linux_err_from!(e))
+}
+
+/// Gives an open file, e.g. one passed over a UNIX socket, a new file
+/// descriptor in the current process
+pub fn install_file(file: Arc<Mutex<File>>, flags: usize) -> LinuxResult<usize> {
     let current = task::current();
-    let nofile = current.rlim[RLIMIT_NOFILE].rlim_cur;
-    let fd = current.filetable
-        .lock().insert(Arc::new(Mutex::new(file)), flags);
+    let nofile = current.rlimit(RLIMIT_NOFILE) as usize;
+    let cloexec = (flags & O_CLOEXEC as usize) != 0;
+    let fd = current.filetable.lock().alloc_fd(0, nofile, file, cloexec)?;
     info!("register fd {}", fd);
-    if fd >= nofile as usize {
-        return linux_err!(EMFILE);
-    }
-    fd
+    Ok(fd)
 }
 
 /// Removes file descriptor and returns associated file
@@ -225,7 +244,8 @@ pub fn write(fd: usize, ubuf: &[u8]) -> LinuxResult<usize> {
     kbuf.copy_from_slice(ubuf);
 
     let mut locked_file = file.lock();
-    match locked_file.write(&kbuf) {
+    let count = check_fsize(&locked_file, count)?;
+    match locked_file.write(&kbuf[..count]) {
         Ok(pos) => Ok(pos),
         Err(BrokenPipe) => {
             let tid = current.tid();
@@ -236,6 +256,21 @@ pub fn write(fd: usize, ubuf: &[u8]) -> LinuxResult<usize> {
     }
 }
 
+/// Clips a write of `count` bytes to a regular file at RLIMIT_FSIZE. A
+/// write that starts beyond it fails with EFBIG and raises SIGXFSZ.
+fn check_fsize(file: &File, count: usize) -> LinuxResult<usize> {
+    let limit = task::current().rlimit(RLIMIT_FSIZE);
+    if limit == RLIM_INFINITY || !file.get_attr()?.is_file() {
+        return Ok(count);
+    }
+    let pos = file.write_pos()?;
+    if pos >= limit {
+        force_sig_fault(task::current().tid(), task::SIGXFSZ, 0, 0);
+        return Err(LinuxError::EFBIG);
+    }
+    Ok(min(count as u64, limit - pos) as usize)
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct iovec {
@@ -281,14 +316,24 @@ pub struct KernelStat {
 }
 
 /// Checks if file is accessible
-pub fn faccessat(dfd: usize, path: &str) -> usize {
-    match lookup_node(dfd, &path) {
-        Ok(_) => {
-            return 0;
-        },
+/// Checks access to `path` for the rwx bits in `mode`, or just that it
+/// exists for F_OK. As for access(2), the real ids are checked.
+pub fn faccessat(dfd: usize, path: &str, mode: usize) -> usize {
+    let node = match lookup_node(dfd, &path) {
+        Ok(node) => node,
         Err(e) => {
             return linux_err_from!(e);
         }
+    };
+    if mode == 0 {
+        return 0;
+    }
+    let mut cred = task::current().cred();
+    cred.fsuid = cred.uid;
+    cred.fsgid = cred.gid;
+    match node.get_attr().and_then(|attr| File::may_open(mode as u32 & 0o7, &cred, attr)) {
+        Ok(_) => 0,
+        Err(e) => linux_err_from!(e),
     }
 }
 
@@ -424,7 +469,7 @@ pub fn fstatat(dfd: usize, path: usize, statbuf_ptr: usize, flags: usize) -> usi
     unsafe {
         *statbuf = KernelStat {
             st_ino: ino as u64,
-            st_nlink: 1,
+            st_nlink: metadata.nlink(),
             st_mode,
             st_uid: fsuid,
             st_gid: fsgid,
@@ -433,6 +478,12 @@ pub fn fstatat(dfd: usize, path: usize, statbuf_ptr: usize, flags: usize) -> usi
             // Todo: get real block_size from dev
             st_blksize: BLOCK_SIZE,
             st_rdev: metadata.rdev() as u64,
+            st_atime_sec: metadata.atime().as_secs() as isize,
+            st_atime_nsec: metadata.atime().subsec_nanos() as isize,
+            st_mtime_sec: metadata.mtime().as_secs() as isize,
+            st_mtime_nsec: metadata.mtime().subsec_nanos() as isize,
+            st_ctime_sec: metadata.ctime().as_secs() as isize,
+            st_ctime_nsec: metadata.ctime().subsec_nanos() as isize,
             ..Default::default()
         };
     }
@@ -473,6 +524,15 @@ pub fn ioctl(fd: usize, request: usize, udata: usize) -> LinuxResult<usize> {
     let file = current.filetable.lock()
         .get_file(fd).ok_or(LinuxError::EBADF)?;
 
+    if request == FIONBIO {
+        // As F_SETFL with O_NONBLOCK on or off.
+        let nonblock = unsafe { *(udata as *const i32) } != 0;
+        let mut file = file.lock();
+        let flags = file.flags() & !O_NONBLOCK;
+        file.set_status_flags(if nonblock { flags | O_NONBLOCK } else { flags });
+        return Ok(0);
+    }
+
     let ret = file.lock().ioctl(request, udata)?;
     Ok(ret)
 }
@@ -704,6 +764,46 @@ pub fn fallocate(fd: usize, mode: usize, offset: usize, len: usize) -> LinuxResu
     Ok(0)
 }
 
+/// Advises how a file is about to be accessed, for its readahead
+pub fn fadvise64(fd: usize, offset: usize, len: usize, advice: usize) -> LinuxResult<usize> {
+    info!("fadvise64: fd {} offset {:#x}, len {:#x} advice {}",
+        fd, offset, len, advice);
+    if (offset as isize) < 0 || (len as isize) < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let current = task::current();
+    let file = current.filetable.lock().get_file(fd)
+        .ok_or(LinuxError::EBADF)?;
+    let mut locked_file = file.lock();
+    if locked_file.get_attr()?.file_type() == VfsNodeType::Fifo {
+        return Err(LinuxError::ESPIPE);
+    }
+    locked_file.fadvise(offset as u64, len as u64, advice)?;
+    Ok(0)
+}
+
+/// Writes back all the filesystems, then all dirty blocks of the buffer
+/// cache, and flushes the disks.
+pub fn sync() -> LinuxResult<usize> {
+    let ret = init_root().sync();
+    axdriver::bcache::sync().map_err(|e| {
+        error!("sync: {:?}", e);
+        LinuxError::EIO
+    })?;
+    ret?;
+    Ok(0)
+}
+
+/// Synchronizes the file `fd` with the disk. Dirty blocks are not tracked
+/// by file, so all of them are written back.
+pub fn fsync(fd: usize) -> LinuxResult<usize> {
+    let current = task::current();
+    current.filetable.lock().get_file(fd)
+        .ok_or(LinuxError::EBADF)?;
+    sync()
+}
+
 /// Opens a file
 pub fn do_open(filename: &str, flags: i32) -> LinuxResult<FileRef> {
     debug!("do_open path {}", filename);
@@ -716,9 +816,7 @@ pub fn do_open(filename: &str, flags: i32) -> LinuxResult<FileRef> {
 
     let current = task::current();
     let fs = current.fs.lock();
-    let fsuid = current.fsuid();
-    let fsgid = current.fsgid();
-    let file = File::open(filename, &opts, &fs, fsuid, fsgid)?;
+    let file = File::open(filename, &opts, &fs, &current.cred())?;
     Ok(Arc::new(Mutex::new(file)))
 }
 
@@ -736,42 +834,88 @@ pub fn filetype(fname: &str) -> LinuxResult<VfsNodeType> {
 }
 
 /// Manipulates file descriptor
-pub fn fcntl(fd: usize, cmd: usize, udata: usize) -> usize {
-    //assert_eq!(F_DUPFD, cmd);
-    if cmd != F_DUPFD {
-        warn!("implement fcntl cmd [{}]", cmd);
-        return 0;
-    }
-
+pub fn fcntl(fd: usize, cmd: usize, udata: usize) -> LinuxResult<usize> {
+    debug!("fcntl: fd {} cmd {} udata {:#x}", fd, cmd, udata);
     let cur = task::current();
     let mut locked_fdt = cur.filetable.lock();
-    let new_fd = locked_fdt.alloc_fd(udata);
-    debug!("fcntl: fd {}-{} cmd {} udata {}", fd, new_fd, cmd, udata);
-    let file = locked_fdt.get_file(fd).unwrap();
-    locked_fdt.fd_install(new_fd, file.clone());
-    new_fd
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            let nofile = cur.rlimit(RLIMIT_NOFILE) as usize;
+            if udata >= nofile {
+                return Err(LinuxError::EINVAL);
+            }
+            let file = locked_fdt.get_file(fd).ok_or(LinuxError::EBADF)?;
+            locked_fdt.alloc_fd(udata, nofile, file, cmd == F_DUPFD_CLOEXEC)
+        }
+        F_GETFD => {
+            let cloexec = locked_fdt.is_cloexec(fd).ok_or(LinuxError::EBADF)?;
+            Ok(if cloexec { FD_CLOEXEC } else { 0 })
+        }
+        F_SETFD => {
+            locked_fdt.set_cloexec(fd, (udata & FD_CLOEXEC) != 0)?;
+            Ok(0)
+        }
+        F_GETFL => {
+            let file = locked_fdt.get_file(fd).ok_or(LinuxError::EBADF)?;
+            let flags = file.lock().flags();
+            Ok(flags as usize)
+        }
+        F_SETFL => {
+            let file = locked_fdt.get_file(fd).ok_or(LinuxError::EBADF)?;
+            file.lock().set_status_flags(udata as i32);
+            Ok(0)
+        }
+        _ => {
+            warn!("implement fcntl cmd [{}]", cmd);
+            Ok(0)
+        }
+    }
 }
 
-/// Duplicates a file descriptor
-pub fn dup(fd: usize) -> usize {
+/// Duplicates a file descriptor to the lowest free one
+pub fn dup(fd: usize) -> LinuxResult<usize> {
     info!("dup [{:#x}] ...", fd);
     let cur = task::current();
+    let nofile = cur.rlimit(RLIMIT_NOFILE) as usize;
     let mut locked_fdt = cur.filetable.lock();
-    let new_fd = locked_fdt.alloc_fd(fd);
-    let file = locked_fdt.get_file(fd).unwrap();
-    locked_fdt.fd_install(new_fd, file.clone());
-    new_fd
+    let file = locked_fdt.get_file(fd).ok_or(LinuxError::EBADF)?;
+    locked_fdt.alloc_fd(0, nofile, file, false)
 }
 
-/// Duplicates file descriptor to specific number
-pub fn dup3(oldfd: usize, newfd: usize, flags: usize) -> usize {
-    assert_eq!(flags, 0);
+/// Duplicates file descriptor to specific number, closing what was there
+pub fn dup2(oldfd: usize, newfd: usize) -> LinuxResult<usize> {
+    info!("dup2 [{:#x}, {:#x}] ...", oldfd, newfd);
+    if oldfd == newfd {
+        let cur = task::current();
+        cur.filetable.lock().get_file(oldfd).ok_or(LinuxError::EBADF)?;
+        return Ok(newfd);
+    }
+    do_dup2(oldfd, newfd, false)
+}
+
+/// Duplicates file descriptor to specific number, as dup2 does, with
+/// O_CLOEXEC in `flags` for it to be closed on exec
+pub fn dup3(oldfd: usize, newfd: usize, flags: usize) -> LinuxResult<usize> {
     info!("dup3 [{:#x}, {:#x}, {:#x}] ...", oldfd, newfd, flags);
+    if (flags & !(O_CLOEXEC as usize)) != 0 || oldfd == newfd {
+        return Err(LinuxError::EINVAL);
+    }
+    do_dup2(oldfd, newfd, (flags & O_CLOEXEC as usize) != 0)
+}
+
+fn do_dup2(oldfd: usize, newfd: usize, cloexec: bool) -> LinuxResult<usize> {
     let cur = task::current();
-    let mut locked_fdt = cur.filetable.lock();
-    let file = locked_fdt.get_file(oldfd).unwrap();
-    locked_fdt.fd_install(newfd, file.clone());
-    newfd
+    if newfd as u64 >= cur.rlimit(RLIMIT_NOFILE) {
+        return Err(LinuxError::EBADF);
+    }
+    let old = {
+        let mut locked_fdt = cur.filetable.lock();
+        let file = locked_fdt.get_file(oldfd).ok_or(LinuxError::EBADF)?;
+        locked_fdt.replace(newfd, file, cloexec)
+    };
+    // The file that was at newfd is closed, out of the lock.
+    drop(old);
+    Ok(newfd)
 }
 
 /// Gets directory entries
@@ -821,45 +965,183 @@ pub fn sendfile(out_fd: usize, in_fd: usize, offset: usize, count: usize) -> usi
 }
 
 /// Gets filesystem statistics
-pub fn statfs(path: &str, buf: usize) -> usize {
+pub fn statfs(path: &str, buf: usize) -> LinuxResult<usize> {
     info!("statfs: path {}...", path);
     let current = task::current();
     let fs = current.fs.lock();
-    let path = fs.absolute_path(path).unwrap();
+    let path = fs.absolute_path(path)?;
     let root = init_root();
+    let info = root.statfs(&path)?;
     let statbuf = buf as *mut FileSystemInfo;
     unsafe {
-        *statbuf = root.statfs(&path).unwrap();
+        *statbuf = info;
+    }
+    Ok(0)
+}
+
+/// Resolves the target of an xattr call, by `path` or else by `fd`
+fn xattr_node(fd: usize, path: Option<&str>, follow: bool) -> LinuxResult<VfsNodeRef> {
+    let current = task::current();
+    match path {
+        Some(path) => {
+            let flags = if follow { 0 } else { O_NOFOLLOW };
+            let path = handle_path(AT_FDCWD, path);
+            Ok(current.fs.lock().lookup(None, &path, flags)?)
+        },
+        None => {
+            let file = current.filetable.lock().get_file(fd)
+                .ok_or(LinuxError::EBADF)?;
+            let node = file.lock().get_node()?;
+            Ok(node)
+        },
     }
-    0
 }
 
+/// Gets an extended attribute value
+pub fn getxattr(
+    fd: usize, path: Option<&str>, follow: bool,
+    name: &str, value: usize, size: usize
+) -> LinuxResult<usize> {
+    info!("getxattr: fd {} path {:?} name {} size {}", fd, path, name, size);
+    let node = xattr_node(fd, path, follow)?;
+    // A size of 0 asks for the size only, and the value may be NULL.
+    let buf: &mut [u8] = if size == 0 {
+        &mut []
+    } else {
+        unsafe { slice::from_raw_parts_mut(value as *mut u8, size) }
+    };
+    Ok(node.getxattr(name, buf)?)
+}
+
+/// Sets an extended attribute value
+pub fn setxattr(
+    fd: usize, path: Option<&str>, follow: bool,
+    name: &str, value: usize, size: usize, flags: usize
+) -> LinuxResult<usize> {
+    info!("setxattr: fd {} path {:?} name {} size {} flags {:#x}", fd, path, name, size, flags);
+    let node = xattr_node(fd, path, follow)?;
+    let buf: &[u8] = if size == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(value as *const u8, size) }
+    };
+    node.setxattr(name, buf, flags)?;
+    Ok(0)
+}
+
+/// Lists extended attribute names
+pub fn listxattr(
+    fd: usize, path: Option<&str>, follow: bool,
+    list: usize, size: usize
+) -> LinuxResult<usize> {
+    info!("listxattr: fd {} path {:?} size {}", fd, path, size);
+    let node = xattr_node(fd, path, follow)?;
+    // As for getxattr, a size of 0 asks for the size only.
+    let buf: &mut [u8] = if size == 0 {
+        &mut []
+    } else {
+        unsafe { slice::from_raw_parts_mut(list as *mut u8, size) }
+    };
+    Ok(node.listxattr(buf)?)
+}
+
+/// Removes an extended attribute
+pub fn removexattr(
+    fd: usize, path: Option<&str>, follow: bool, name: &str
+) -> LinuxResult<usize> {
+    info!("removexattr: fd {} path {:?} name {}", fd, path, name);
+    let node = xattr_node(fd, path, follow)?;
+    node.removexattr(name)?;
+    Ok(0)
+}
+
+/// Time value passed by utimensat
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct KernelTimespec {
+    tv_sec: isize,
+    tv_nsec: isize,
+}
+
+const UTIME_NOW: isize = (1 << 30) - 1;
+const UTIME_OMIT: isize = (1 << 30) - 2;
+
 /// Updates file timestamps
 pub fn utimensat(dfd: usize, filename: &str, times: usize, flags: usize) -> usize {
-    let path = handle_path(dfd, filename);
-    error!("utimensat: dfd {:#x} path {} times {} flags {}",
-        dfd, path, times, flags);
-    error!("utimensat: unimplemented yet!");
-    0
+    info!("utimensat: dfd {:#x} path {} times {:#x} flags {:#x}",
+        dfd, filename, times, flags);
+
+    let now = timekeeping::ktime_get_real_coarse();
+    let (atime, mtime) = if times == 0 {
+        (KernelTimespec { tv_sec: 0, tv_nsec: UTIME_NOW }, KernelTimespec { tv_sec: 0, tv_nsec: UTIME_NOW })
+    } else {
+        let times = unsafe { slice::from_raw_parts(times as *const KernelTimespec, 2) };
+        (times[0], times[1])
+    };
+
+    let mut attr = VfsNodeAttr::default();
+    let mut valid = VfsNodeAttrValid::empty();
+    let to_duration = |ts: KernelTimespec| match ts.tv_nsec {
+        UTIME_NOW => now,
+        _ => core::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32),
+    };
+    if atime.tv_nsec != UTIME_OMIT {
+        valid.insert(VfsNodeAttrValid::ATTR_ATIME);
+        attr.set_atime(to_duration(atime));
+    }
+    if mtime.tv_nsec != UTIME_OMIT {
+        valid.insert(VfsNodeAttrValid::ATTR_MTIME);
+        attr.set_mtime(to_duration(mtime));
+    }
+    if valid.is_empty() {
+        return 0;
+    }
+
+    let ret = if filename.is_empty() {
+        let current = task::current();
+        let file = current.filetable.lock().get_file(dfd);
+        match file {
+            Some(file) => file.lock().set_attr(&attr, &valid),
+            None => return linux_err!(EBADF),
+        }
+    } else {
+        lookup_node(dfd, filename).and_then(|node| node.set_attr(&attr, &valid))
+    };
+    match ret {
+        Ok(()) => 0,
+        Err(e) => linux_err_from!(e),
+    }
 }
 
-/// Creates a pipe
+/// Creates a pipe, with both ends nonblocking for O_NONBLOCK and closed
+/// on exec for O_CLOEXEC.
 pub fn pipe2(fds: usize, flags: usize) -> LinuxResult {
     debug!("pipe2: fds {:#x} flags {:#x}", fds, flags);
+    if (flags & !((O_NONBLOCK | O_CLOEXEC) as usize)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
     let current = task::current();
     let fsuid = current.fsuid();
     let fsgid = current.fsgid();
-    let node = Arc::new(PipeNode::init_pipe_node(fsuid, fsgid));
-    let rfile = File::new(node.clone(), Cap::READ);
-    let wfile = File::new(node.clone(), Cap::WRITE);
-    let rfd = register_file(Ok(rfile), 0) as i32;
-    let wfd = register_file(Ok(wfile), 0) as i32;
-    assert!(rfd > 0);
-    assert!(wfd > 0);
+    let nonblock = (flags & O_NONBLOCK as usize) != 0;
+    let (rend, wend) = PipeNode::pipe(fsuid, fsgid, nonblock);
+    let mut rfile = File::new(Arc::new(rend), Cap::READ);
+    let mut wfile = File::new(Arc::new(wend), Cap::WRITE);
+    rfile.set_status_flags(flags as i32);
+    wfile.set_status_flags(flags as i32);
+    let rfd = register_file(Ok(rfile), flags);
+    if (rfd as isize) < 0 {
+        return Err(LinuxError::EMFILE);
+    }
+    let wfd = register_file(Ok(wfile), flags);
+    if (wfd as isize) < 0 {
+        let _ = unregister_file(rfd);
+        return Err(LinuxError::EMFILE);
+    }
     let fds = fds as *mut i32;
     let fds = unsafe { slice::from_raw_parts_mut(fds, 2) };
-    fds[0] = rfd;
-    fds[1] = wfd;
+    fds[0] = rfd as i32;
+    fds[1] = wfd as i32;
     debug!("pipe2 ok! fd0 {:#x} fd1 {:#x}", fds[0], fds[1]);
     Ok(())
 }
@@ -869,21 +1151,95 @@ pub fn mount(fsname: &str, dir: &str, fstype: &str, flags: usize, data: usize) -
     info!("mount: name {} dir {} ty {} flags {:#x} data {:#x}",
         fsname, dir, fstype, flags, data);
 
-    // TODO: Now only handle procfs. Handle other filesystems in future.
+    let uid = 0;
+    let gid = 0;
+    let current = task::current();
+    if flags & MS_REMOUNT != 0 {
+        let fs = current.fs.lock();
+        let root = fs.root_dir().expect("bad root");
+        root.remount(dir, flags & MS_RMT_MASK)?;
+        return Ok(0);
+    }
     if fstype == "proc" {
         assert_eq!(dir, "/proc");
         assert_eq!(fsname, "proc");
-        let uid = 0;
-        let gid = 0;
         let mode = 0o777;
-        let current = task::current();
         let fs = current.fs.lock();
         let root = fs.root_dir().expect("bad root");
         root.mount(dir, init_procfs(uid, gid, mode).unwrap(), uid, gid)?;
+        return Ok(0);
+    }
+
+    let mut filesystems = FILESYSTEMS.lock();
+    let Some(fs_type) = filesystems.get_mut(fstype) else {
+        // TODO: Handle the other builtin filesystems in future.
+        return Ok(0);
+    };
+    let vfs = (fs_type.mount)(fsname, flags, data)?;
+    {
+        let fs = current.fs.lock();
+        let root = fs.root_dir().expect("bad root");
+        root.mount(dir, vfs.clone(), uid, gid)?;
     }
+    fs_type.mounts.retain(|m| m.strong_count() > 0);
+    fs_type.mounts.push(Arc::downgrade(&vfs));
     Ok(0)
 }
 
+/// Makes a filesystem of a registered type, from the source, the flags
+/// and the data given to mount().
+pub type MountFn = fn(source: &str, flags: usize, data: usize) -> LinuxResult<Arc<dyn VfsOps>>;
+
+struct FileSystemType {
+    mount: MountFn,
+    /// The filesystems of the type that are mounted, or were.
+    mounts: Vec<Weak<dyn VfsOps>>,
+}
+
+/// The filesystem types registered at runtime, as by modules, by name.
+static FILESYSTEMS: Mutex<BTreeMap<String, FileSystemType>> = Mutex::new(BTreeMap::new());
+
+/// Lets mount() mount filesystems of type `name`, which `mount` makes.
+/// Fails with EBUSY if there is such a type already.
+pub fn register_filesystem(name: &str, mount: MountFn) -> LinuxResult {
+    let mut filesystems = FILESYSTEMS.lock();
+    if name == "proc" || filesystems.contains_key(name) {
+        return Err(LinuxError::EBUSY);
+    }
+    filesystems.insert(name.to_string(), FileSystemType { mount, mounts: Vec::new() });
+    info!("register_filesystem: {}", name);
+    Ok(())
+}
+
+/// Removes the filesystem type `name`. Fails with ENOENT if there is
+/// none, and EBUSY while a filesystem of it is still mounted.
+pub fn unregister_filesystem(name: &str) -> LinuxResult {
+    let mut filesystems = FILESYSTEMS.lock();
+    let fs_type = filesystems.get(name).ok_or(LinuxError::ENOENT)?;
+    if fs_type.mounts.iter().any(|m| m.strong_count() > 0) {
+        return Err(LinuxError::EBUSY);
+    }
+    filesystems.remove(name);
+    info!("unregister_filesystem: {}", name);
+    Ok(())
+}
+
+/// Puts the device `node` into /dev as `name`.
+pub fn register_device(name: &str, node: VfsNodeRef) -> LinuxResult {
+    let current = task::current();
+    let fs = current.fs.lock();
+    fs.create_link(None, &format!("/dev/{}", name), node)?;
+    Ok(())
+}
+
+/// Removes the device `name` from /dev. The files open on it keep it.
+pub fn unregister_device(name: &str) -> LinuxResult {
+    let current = task::current();
+    let fs = current.fs.lock();
+    fs.remove_file(None, &format!("/dev/{}", name))?;
+    Ok(())
+}
+
 // Gets file descriptor file size
 fn file_size(file: FileRef) -> LinuxResult<usize> {
     let metadata = file.lock().get_attr()?;
@@ -899,7 +1255,7 @@ pub fn console_on_rootfs() -> LinuxResult {
 
     let current = task::current();
     let fs = current.fs.lock();
-    let console = File::open("/dev/console", &opts, &fs, 0, 0)
+    let console = File::open("/dev/console", &opts, &fs, &Cred::root())
         .expect("bad /dev/console");
     let console = Arc::new(Mutex::new(console));
 
@@ -912,6 +1268,22 @@ pub fn console_on_rootfs() -> LinuxResult {
     Ok(())
 }
 
+/// Puts the ttys into /dev: the console as a tty in place of the raw one,
+/// /dev/tty for the controlling tty, /dev/hvcN for the hypervisor consoles,
+/// and /dev/ptmx with /dev/pts for ptys.
+pub fn tty_init() -> LinuxResult {
+    let current = task::current();
+    let fs = current.fs.lock();
+    fs.create_link(None, "/dev/console", Arc::new(TtyNode::new(tty::console())))?;
+    fs.create_link(None, "/dev/tty", Arc::new(CurrentTtyDev))?;
+    for (i, hvc) in tty::hvc_ttys().into_iter().enumerate() {
+        fs.create_link(None, &format!("/dev/hvc{}", i), Arc::new(TtyNode::new(hvc)))?;
+    }
+    fs.create_link(None, "/dev/ptmx", Arc::new(PtmxDev))?;
+    fs.create_link(None, "/dev/pts", Arc::new(PtsDir::new()))?;
+    Ok(())
+}
+
 /// Initializes loop devices
 pub fn loop_init() -> LinuxResult {
     let loop_ctl = LoopCtlDev::new();
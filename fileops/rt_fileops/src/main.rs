@@ -3,8 +3,26 @@
 
 #[macro_use]
 extern crate axlog2;
+extern crate alloc;
 
+use alloc::vec::Vec;
+use core::mem;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use axerrno::LinuxError;
+use axtype::{O_CLOEXEC, O_NONBLOCK, PAGE_SIZE, RLIMIT_NOFILE};
+use task::{SigAction, SIGPIPE};
+
+const F_DUPFD: usize = 0;
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_DUPFD_CLOEXEC: usize = 1030;
+const FD_CLOEXEC: usize = 1;
+
+const SIG_IGN: usize = 1;
+const SIG_SETMASK: usize = 2;
 
 #[no_mangle]
 pub extern "Rust" fn runtime_main(cpu_id: usize, _dtb_pa: usize) {
@@ -12,10 +30,186 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, _dtb_pa: usize) {
     info!("[rt_fileops]: ...");
     fileops::init(cpu_id, _dtb_pa);
 
+    test_pipe_nonblock();
+    test_pipe_broken();
+    test_pipe_blocking();
+    test_fdtable();
+
     info!("[rt_fileops]: ok!");
     axhal::misc::terminate();
 }
 
+fn pipe(flags: i32) -> (usize, usize) {
+    let mut fds = [0i32; 2];
+    fileops::pipe2(fds.as_mut_ptr() as usize, flags as usize).unwrap();
+    (fds[0] as usize, fds[1] as usize)
+}
+
+fn close(fd: usize) {
+    // The file is released with its last reference.
+    drop(fileops::unregister_file(fd).unwrap());
+}
+
+/// Reads and writes of a nonblocking pipe fail with EAGAIN instead of
+/// sleeping, and a full pipe takes no more.
+fn test_pipe_nonblock() {
+    assert_eq!(fileops::pipe2(0, 0x1), Err(LinuxError::EINVAL));
+    let (rfd, wfd) = pipe(O_NONBLOCK | O_CLOEXEC);
+    let flags = fileops::fcntl(rfd, F_GETFL, 0).unwrap() as i32;
+    assert_ne!(flags & O_NONBLOCK, 0);
+
+    let mut buf = [0u8; 8];
+    assert_eq!(fileops::read(rfd, &mut buf), Err(LinuxError::EAGAIN));
+    assert_eq!(fileops::write(wfd, b"abc"), Ok(3));
+    assert_eq!(fileops::read(rfd, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"abc");
+
+    // Writes of a page go in whole or not at all.
+    let page = [0x5au8; PAGE_SIZE];
+    let mut total = 0;
+    while let Ok(n) = fileops::write(wfd, &page) {
+        assert_eq!(n, PAGE_SIZE);
+        total += n;
+    }
+    assert_eq!(total, 16 * PAGE_SIZE);
+    assert_eq!(fileops::write(wfd, b"x"), Err(LinuxError::EAGAIN));
+    assert_eq!(fileops::read(rfd, &mut buf), Ok(buf.len()));
+    assert_eq!(buf, [0x5a; 8]);
+
+    // F_SETFL switches the mode of an end.
+    let (rfd2, wfd2) = pipe(0);
+    fileops::fcntl(rfd2, F_SETFL, O_NONBLOCK as usize).unwrap();
+    assert_eq!(fileops::read(rfd2, &mut buf), Err(LinuxError::EAGAIN));
+
+    for fd in [rfd, wfd, rfd2, wfd2] {
+        close(fd);
+    }
+    info!("[rt_fileops]: nonblocking pipe ok!");
+}
+
+/// Writing to a pipe without readers fails with EPIPE and raises SIGPIPE,
+/// and reading one without writers gets the end of data.
+fn test_pipe_broken() {
+    let (rfd, wfd) = pipe(0);
+    assert_eq!(fileops::write(wfd, b"abc"), Ok(3));
+    close(wfd);
+    let mut buf = [0u8; 8];
+    assert_eq!(fileops::read(rfd, &mut buf), Ok(3));
+    assert_eq!(fileops::read(rfd, &mut buf), Ok(0));
+    close(rfd);
+
+    let (rfd, wfd) = pipe(0);
+    close(rfd);
+    let sigpipe = 1u64 << (SIGPIPE - 1);
+    assert_eq!(task::current().sigpending.lock().signal & sigpipe, 0);
+    assert_eq!(fileops::write(wfd, b"abc"), Err(LinuxError::EPIPE));
+    assert_ne!(task::current().sigpending.lock().signal & sigpipe, 0);
+    close(wfd);
+
+    // Ignoring it discards it, as nothing is here to deliver it, and a
+    // change of the mask clears TIF_SIGPENDING, or the next sleeps would
+    // be interrupted.
+    let act = SigAction { handler: SIG_IGN, ..Default::default() };
+    let size = mem::size_of::<u64>();
+    assert_eq!(signal::rt_sigaction(SIGPIPE, &act as *const _ as usize, 0, size), 0);
+    assert_eq!(task::current().sigpending.lock().signal & sigpipe, 0);
+    let (block, unblock) = (sigpipe, 0u64);
+    assert_eq!(signal::rt_sigprocmask(SIG_SETMASK, &block as *const _ as usize, 0, size), 0);
+    assert_eq!(signal::rt_sigprocmask(SIG_SETMASK, &unblock as *const _ as usize, 0, size), 0);
+    info!("[rt_fileops]: broken pipe ok!");
+}
+
+static WRITER_DONE: AtomicBool = AtomicBool::new(false);
+
+/// A read of an empty pipe sleeps till a writer, here another task,
+/// puts data in it.
+fn test_pipe_blocking() {
+    let (rfd, wfd) = pipe(0);
+    // It gets a copy of the file table, with both ends.
+    fork::kernel_thread(move || {
+        assert_eq!(fileops::write(wfd, b"hello"), Ok(5));
+        close(wfd);
+        close(rfd);
+        WRITER_DONE.store(true, Ordering::Release);
+        run_queue::exit_current(0);
+    }, None);
+
+    let mut buf = [0u8; 8];
+    assert_eq!(fileops::read(rfd, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    while !WRITER_DONE.load(Ordering::Acquire) {
+        task::yield_now();
+    }
+    // The last writer is gone with this one.
+    close(wfd);
+    assert_eq!(fileops::read(rfd, &mut buf), Ok(0));
+    close(rfd);
+    info!("[rt_fileops]: blocking pipe ok!");
+}
+
+/// Fds are allocated lowest first and duplicated with their fd flags of
+/// their own, which exec honors, and not at or above RLIMIT_NOFILE.
+fn test_fdtable() {
+    let (r, w) = pipe(0);
+    let d = fileops::dup(r).unwrap();
+    close(r);
+    // The lowest free one.
+    let r = fileops::dup(w).unwrap();
+    assert!(r < w && r < d);
+
+    assert_eq!(fileops::dup2(w, 100), Ok(100));
+    assert_eq!(fileops::fcntl(100, F_GETFD, 0), Ok(0));
+    // It replaces what is there.
+    assert_eq!(fileops::dup3(d, 100, O_CLOEXEC as usize), Ok(100));
+    assert_eq!(fileops::fcntl(100, F_GETFD, 0), Ok(FD_CLOEXEC));
+    assert_eq!(fileops::dup3(w, w, 0), Err(LinuxError::EINVAL));
+    assert_eq!(fileops::dup3(w, 101, O_NONBLOCK as usize), Err(LinuxError::EINVAL));
+    assert_eq!(fileops::dup2(w, w), Ok(w));
+    assert_eq!(fileops::dup2(200, 201), Err(LinuxError::EBADF));
+    assert_eq!(fileops::dup(200), Err(LinuxError::EBADF));
+
+    assert_eq!(fileops::fcntl(w, F_DUPFD, 50), Ok(50));
+    assert_eq!(fileops::fcntl(w, F_DUPFD_CLOEXEC, 50), Ok(51));
+    assert_eq!(fileops::fcntl(51, F_GETFD, 0), Ok(FD_CLOEXEC));
+    assert_eq!(fileops::fcntl(51, F_SETFD, 0), Ok(0));
+    assert_eq!(fileops::fcntl(51, F_GETFD, 0), Ok(0));
+    assert_eq!(fileops::fcntl(51, F_SETFD, FD_CLOEXEC), Ok(0));
+    assert_eq!(fileops::fcntl(52, F_SETFD, FD_CLOEXEC), Err(LinuxError::EBADF));
+
+    // Exec closes those with FD_CLOEXEC only.
+    let files = task::current().filetable.lock().take_close_on_exec();
+    assert_eq!(files.len(), 2);
+    drop(files);
+    assert_eq!(fileops::fcntl(100, F_GETFD, 0), Err(LinuxError::EBADF));
+    assert_eq!(fileops::fcntl(51, F_GETFD, 0), Err(LinuxError::EBADF));
+    assert_eq!(fileops::fcntl(50, F_GETFD, 0), Ok(0));
+
+    // Fd 50 stays open above the limit, but no new one gets there.
+    let nofile = task::current().rlimit(RLIMIT_NOFILE);
+    task::current().rlim.lock()[RLIMIT_NOFILE].rlim_cur = 50;
+    let mut fds = Vec::new();
+    loop {
+        match fileops::dup(w) {
+            Ok(fd) => fds.push(fd),
+            Err(err) => {
+                assert_eq!(err, LinuxError::EMFILE);
+                break;
+            }
+        }
+    }
+    assert!(fds.iter().all(|fd| *fd < 50));
+    assert_eq!(fileops::dup2(w, 50), Err(LinuxError::EBADF));
+    assert_eq!(fileops::fcntl(w, F_DUPFD, 50), Err(LinuxError::EINVAL));
+    let mut pipe_fds = [0i32; 2];
+    assert_eq!(fileops::pipe2(pipe_fds.as_mut_ptr() as usize, 0), Err(LinuxError::EMFILE));
+    task::current().rlim.lock()[RLIMIT_NOFILE].rlim_cur = nofile;
+
+    for fd in fds.into_iter().chain([r, w, d, 50]) {
+        close(fd);
+    }
+    info!("[rt_fileops]: fdtable ok!");
+}
+
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
     arch_boot::panic(info)
@@ -3,178 +3,189 @@
 extern crate alloc;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
 use axfile::fops::File;
 use mutex::Mutex;
 use spinpreempt::SpinLock;
-use axtype::{set_bit, clr_bit, O_CLOEXEC};
+use axtype::O_CLOEXEC;
 
+type FileRef = Arc<Mutex<File>>;
+
+const BITS: usize = usize::BITS as usize;
+
+/// The open files of a process by file descriptor, as the files_struct of
+/// Linux. Threads cloned with CLONE_FILES share one, fork copies it.
+///
+/// The lowest free fd is found through two bitmaps, one of the fds in use
+/// and one of its full words, rather than by walking the table, and from
+/// `next_fd` on, as all the fds below it are in use.
+#[derive(Clone)]
 pub struct FileTable {
-    table: SlotVec<FileTableEntry>,
-    close_on_exec: usize,
+    table: Vec<Option<FileTableEntry>>,
+    /// Bit n is set if fd n is in use.
+    open_fds: Vec<usize>,
+    /// Bit n is set if word n of `open_fds` is full.
+    full_fds_bits: Vec<usize>,
+    /// All the fds below it are in use.
+    next_fd: usize,
 }
 
 impl FileTable {
     pub const fn new() -> Self {
         Self {
-            table: SlotVec::new(),
-            close_on_exec: 0,
+            table: Vec::new(),
+            open_fds: Vec::new(),
+            full_fds_bits: Vec::new(),
+            next_fd: 0,
         }
     }
 
-    pub fn close_on_exec(&self) -> usize {
-        self.close_on_exec
+    pub fn get_file(&self, fd: usize) -> Option<FileRef> {
+        self.get(fd).map(|entry| entry.file.clone())
     }
 
-    pub fn get_file(&self, fd: usize) -> Option<Arc<Mutex<File>>> {
-        if (fd as i32) < 0 {
-            return None;
-        }
-        self.table
-            .get(fd)
-            .map(|entry| entry.file.clone())
-    }
-
-    pub fn insert(&mut self, item: Arc<Mutex<File>>, flags: usize) -> usize {
-        let entry = FileTableEntry::new(item);
-        let fd = self.table.put(entry);
-        if (flags & O_CLOEXEC as usize) != 0 {
-            set_bit(fd, &mut self.close_on_exec);
-        } else {
-            clr_bit(fd, &mut self.close_on_exec);
+    /// Whether `fd` is closed on exec, None if it is not open.
+    pub fn is_cloexec(&self, fd: usize) -> Option<bool> {
+        self.get(fd).map(|entry| entry.cloexec)
+    }
+
+    /// Sets whether `fd` is closed on exec. EBADF if it is not open.
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) -> LinuxResult {
+        match self.table.get_mut(fd) {
+            Some(Some(entry)) => {
+                entry.cloexec = cloexec;
+                Ok(())
+            }
+            _ => Err(LinuxError::EBADF),
         }
-        fd
     }
 
-    pub fn remove(&mut self, fd: usize) -> Option<Arc<Mutex<File>>> {
-        clr_bit(fd, &mut self.close_on_exec);
-        self.table.remove(fd).map(|item| item.file)
+    /// Puts `file` at the lowest free fd, with O_CLOEXEC in `flags` for it
+    /// to be closed on exec. For the files set up at boot, which no limit
+    /// applies to.
+    pub fn insert(&mut self, file: FileRef, flags: usize) -> usize {
+        let cloexec = (flags & O_CLOEXEC as usize) != 0;
+        self.alloc_fd(0, usize::MAX, file, cloexec).unwrap()
     }
 
-    pub fn alloc_fd(&mut self, start: usize) -> usize {
-        self.table.alloc_pos(start).unwrap()
+    /// Puts `file` at the lowest free fd from `start` on. EMFILE if that is
+    /// not below `nofile`, the RLIMIT_NOFILE of the process.
+    pub fn alloc_fd(&mut self, start: usize, nofile: usize, file: FileRef, cloexec: bool) -> LinuxResult<usize> {
+        let fd = self.find_free_fd(start.max(self.next_fd));
+        if fd >= nofile {
+            return Err(LinuxError::EMFILE);
+        }
+        self.install(fd, FileTableEntry::new(file, cloexec));
+        if start <= self.next_fd {
+            self.next_fd = fd + 1;
+        }
+        Ok(fd)
     }
 
-    pub fn fd_install(&mut self, pos: usize, file: Arc<Mutex<File>>) {
-        let entry = FileTableEntry::new(file.clone());
-        self.table.install(pos, entry)
+    /// Puts `file` at `fd`, as dup2 does, and returns the file that was
+    /// there, for the caller to close it after unlocking the table.
+    pub fn replace(&mut self, fd: usize, file: FileRef, cloexec: bool) -> Option<FileRef> {
+        let old = self.table.get_mut(fd).and_then(|slot| slot.take());
+        self.install(fd, FileTableEntry::new(file, cloexec));
+        old.map(|entry| entry.file)
     }
 
-    pub fn slots_len(&self) -> usize {
-        self.table.slots.len()
+    pub fn remove(&mut self, fd: usize) -> Option<FileRef> {
+        let entry = self.table.get_mut(fd)?.take()?;
+        self.clear_open(fd);
+        if fd < self.next_fd {
+            self.next_fd = fd;
+        }
+        Some(entry.file)
     }
 
-    pub fn copy_from(&mut self, src: &Self) {
-        self.close_on_exec = src.close_on_exec;
-        self.table.copy_from(&src.table)
+    /// Removes the fds that are closed on exec, and returns their files,
+    /// for the caller to close them after unlocking the table.
+    pub fn take_close_on_exec(&mut self) -> Vec<FileRef> {
+        let fds: Vec<usize> = self.table.iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.as_ref().map_or(false, |entry| entry.cloexec))
+            .map(|(fd, _)| fd)
+            .collect();
+        fds.into_iter().filter_map(|fd| self.remove(fd)).collect()
     }
 
-    pub fn reserve(&mut self, size: usize, num_occupied: usize) {
-        self.table.num_occupied = num_occupied;
-        self.table.reserve(size)
+    pub fn copy_from(&mut self, src: &Self) {
+        *self = src.clone();
     }
-}
 
-#[derive(Clone)]
-pub struct FileTableEntry {
-    file: Arc<Mutex<File>>,
-}
+    fn get(&self, fd: usize) -> Option<&FileTableEntry> {
+        self.table.get(fd)?.as_ref()
+    }
 
-impl FileTableEntry {
-    pub fn new(file: Arc<Mutex<File>>) -> Self {
-        Self {
-            file,
+    fn install(&mut self, fd: usize, entry: FileTableEntry) {
+        if fd >= self.table.len() {
+            self.table.resize(fd + 1, None);
         }
+        self.table[fd] = Some(entry);
+        self.set_open(fd);
     }
-}
-
-pub struct SlotVec<T: Clone> {
-    // The slots to store items.
-    slots: Vec<Option<T>>,
-    // The number of occupied slots.
-    // The i-th slot is occupied if `self.slots[i].is_some()`.
-    num_occupied: usize,
-}
 
-impl<T: Clone> SlotVec<T> {
-    /// New an empty vector.
-    pub const fn new() -> Self {
-        Self {
-            slots: Vec::new(),
-            num_occupied: 0,
+    /// The lowest fd from `start` on that is not in use.
+    fn find_free_fd(&self, start: usize) -> usize {
+        let mut word = start / BITS;
+        let mut bits = self.open_fds.get(word).copied().unwrap_or(0) | low_bits(start % BITS);
+        while bits == usize::MAX {
+            word = self.find_nonfull_word(word + 1);
+            bits = self.open_fds.get(word).copied().unwrap_or(0);
         }
+        word * BITS + bits.trailing_ones() as usize
     }
 
-    pub fn copy_from(&mut self, src: &Self) {
-        self.slots = src.slots.clone();
-        self.num_occupied = src.num_occupied;
+    /// The first word of `open_fds` from `start` on that is not full, which
+    /// may be past its end.
+    fn find_nonfull_word(&self, start: usize) -> usize {
+        let mut i = start / BITS;
+        let mut bits = self.full_fds_bits.get(i).copied().unwrap_or(0) | low_bits(start % BITS);
+        while bits == usize::MAX {
+            i += 1;
+            bits = self.full_fds_bits.get(i).copied().unwrap_or(0);
+        }
+        i * BITS + bits.trailing_ones() as usize
     }
 
-    pub fn reserve(&mut self, size: usize) {
-        self.slots.resize(size, None)
+    fn set_open(&mut self, fd: usize) {
+        let word = fd / BITS;
+        if word >= self.open_fds.len() {
+            self.open_fds.resize(word + 1, 0);
+            self.full_fds_bits.resize(word / BITS + 1, 0);
+        }
+        self.open_fds[word] |= 1 << (fd % BITS);
+        if self.open_fds[word] == usize::MAX {
+            self.full_fds_bits[word / BITS] |= 1 << (word % BITS);
+        }
     }
 
-    /// Return the number of slots.
-    pub fn slots_len(&self) -> usize {
-        self.slots.len()
+    fn clear_open(&mut self, fd: usize) {
+        let word = fd / BITS;
+        self.open_fds[word] &= !(1 << (fd % BITS));
+        self.full_fds_bits[word / BITS] &= !(1 << (word % BITS));
     }
+}
 
-    /// Get slot at index.
-    pub fn get(&self, idx: usize) -> Option<&T> {
-        if idx >= self.slots.len() {
-            return None;
-        }
-        self.slots[idx].as_ref()
-    }
-    /// Put an item into the vector.
-    /// It may be put into any existing empty slots or the back of the vector.
-    ///
-    /// Return the index of the inserted item.
-    pub fn put(&mut self, entry: T) -> usize {
-        let idx = if self.num_occupied == self.slots.len() {
-            self.slots.push(Some(entry));
-            self.slots.len() - 1
-        } else {
-            let idx = self.slots.iter().position(|x| x.is_none()).unwrap();
-            self.slots[idx] = Some(entry);
-            idx
-        };
-        self.num_occupied += 1;
-        idx
-    }
-
-    pub fn install(&mut self, pos: usize, entry: T) {
-        self.slots[pos] = Some(entry);
-    }
-
-    /// Remove and return the item at position `idx`.
-    ///
-    /// Return `None` if `idx` is out of bounds or the item has been removed.
-    pub fn remove(&mut self, idx: usize) -> Option<T> {
-        if idx >= self.slots.len() {
-            return None;
-        }
-        let mut del_item = None;
-        core::mem::swap(&mut del_item, &mut self.slots[idx]);
-        if del_item.is_some() {
-            debug_assert!(self.num_occupied > 0);
-            self.num_occupied -= 1;
-        }
-        del_item
-    }
+/// The bits below bit `n`.
+const fn low_bits(n: usize) -> usize {
+    (1 << n) - 1
+}
 
-    /// Alloc a slot from 'start' postion
-    ///
-    /// Return `None` if no slot can be used or the postion.
-    pub fn alloc_pos(&mut self, mut start: usize) -> Option<usize> {
-        if start < self.slots.len() {
-            start = self.slots.len();
-        }
-        let mut pos = self.slots.len();
-        while pos <= start {
-            self.slots.push(None);
-            pos += 1;
+#[derive(Clone)]
+struct FileTableEntry {
+    file: FileRef,
+    /// FD_CLOEXEC, the only fd flag.
+    cloexec: bool,
+}
+
+impl FileTableEntry {
+    fn new(file: FileRef, cloexec: bool) -> Self {
+        Self {
+            file,
+            cloexec,
         }
-        Some(start)
     }
 }
 
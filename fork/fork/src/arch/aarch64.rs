@@ -0,0 +1,46 @@
+use core::mem;
+use axhal::arch::{PSR_MODE_EL1H, PSR_I_BIT};
+use axerrno::LinuxResult;
+use crate::KernelCloneArgs;
+use taskctx::SchedInfo;
+use axhal::arch::TrapFrame;
+
+pub fn copy_thread(
+    pt_regs: &mut TrapFrame,
+    args: &KernelCloneArgs,
+) -> LinuxResult {
+    info!("copy_thread ...");
+
+    if args.entry.is_some() {
+        *pt_regs = unsafe { mem::zeroed() };
+        // EL1 with SP_EL1, irqs masked till the task enables them:
+        pt_regs.spsr = (PSR_MODE_EL1H | PSR_I_BIT) as u64;
+    } else {
+        let ctx = taskctx::current_ctx();
+        *pt_regs = ctx.pt_regs().clone();
+        if let Some(sp) = args.stack {
+            pt_regs.usp = sp as u64; // User fork
+        }
+        pt_regs.r[0] = 0; // Return value of fork()
+    }
+
+    info!("copy_thread!");
+    Ok(())
+}
+
+/// Sets the TPIDR_EL0 the new task returns to userland with. It's loaded
+/// when the task is switched in.
+pub fn set_new_tls(sched_info: &SchedInfo, tls: usize) {
+    unsafe { (*sched_info.ctx_mut_ptr()).tpidr_el0 = tls as u64 };
+}
+
+/// Gives the new task the TPIDR_EL0 of the current one, which is not in
+/// the trap frame.
+pub fn inherit_tls(sched_info: &SchedInfo) {
+    set_new_tls(sched_info, axhal::arch::read_thread_pointer());
+}
+
+/// The FP/SIMD registers of the parent are taken as they are.
+pub fn inherit_fp_state(sched_info: &SchedInfo) {
+    unsafe { (*sched_info.ctx_mut_ptr()).inherit_fp_state() };
+}
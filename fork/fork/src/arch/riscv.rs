@@ -3,8 +3,8 @@ use core::mem;
 use axhal::arch::gp_in_global;
 use axhal::arch::{SR_SPP, SR_SPIE};
 use axerrno::LinuxResult;
-use crate::CloneFlags;
 use crate::KernelCloneArgs;
+use taskctx::SchedInfo;
 use axhal::arch::TrapFrame;
 
 
@@ -25,12 +25,24 @@ pub fn copy_thread(
         if let Some(sp) = args.stack {
             pt_regs.regs.sp = sp; // User fork
         }
-        if args.flags.contains(CloneFlags::CLONE_SETTLS) {
-            pt_regs.regs.tp = args.tls;
-        }
         pt_regs.regs.a0 = 0; // Return value of fork()
     }
 
     info!("copy_thread!");
     Ok(())
 }
+
+/// Sets the thread pointer the new task returns to userland with.
+pub fn set_new_tls(sched_info: &SchedInfo, tls: usize) {
+    sched_info.pt_regs().regs.tp = tls;
+}
+
+/// The thread pointer is in the trap frame, which the new task has a copy
+/// of already.
+pub fn inherit_tls(_sched_info: &SchedInfo) {}
+
+/// The FP state of the parent is in the registers, or in its context if it
+/// has not used the FPU since it was switched in.
+pub fn inherit_fp_state(sched_info: &SchedInfo) {
+    unsafe { (*sched_info.ctx_mut_ptr()).inherit_fp_state() };
+}
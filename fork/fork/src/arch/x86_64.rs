@@ -1,7 +1,7 @@
 use core::mem;
 use axerrno::LinuxResult;
-use crate::CloneFlags;
 use crate::KernelCloneArgs;
+use taskctx::SchedInfo;
 use axhal::arch::TrapFrame;
 
 pub fn copy_thread(
@@ -24,17 +24,23 @@ pub fn copy_thread(
         pt_regs.rax = 0; // Return value of fork()
     }
 
-    if args.flags.contains(CloneFlags::CLONE_SETTLS) {
-        set_new_tls(args.tls);
-    }
-
-
     info!("copy_thread!");
     Ok(())
 }
 
-fn set_new_tls(tls: usize) {
-    // Todo: modules/sys/src/lib.rs: arch_prctl ARCH_SET_FS
-    // We need to differentiate current_ctx and common taskctx.
-    unimplemented!("impl set_new_tls! tls {:#X}", tls);
+/// Sets the FS base the new task runs with, as `arch_prctl(ARCH_SET_FS)`
+/// does for the current one. It's loaded when the task is switched in.
+pub fn set_new_tls(sched_info: &SchedInfo, tls: usize) {
+    unsafe { (*sched_info.ctx_mut_ptr()).fs_base = tls };
+}
+
+/// Gives the new task the FS base of the current one, which is not in the
+/// trap frame.
+pub fn inherit_tls(sched_info: &SchedInfo) {
+    set_new_tls(sched_info, axhal::arch::read_thread_pointer());
+}
+
+/// The FP/SIMD registers of the parent are taken as they are.
+pub fn inherit_fp_state(sched_info: &SchedInfo) {
+    unsafe { (*sched_info.ctx_mut_ptr()).inherit_fp_state() };
 }
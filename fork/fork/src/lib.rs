@@ -24,9 +24,11 @@ use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::sync::Arc;
 
-use axerrno::{LinuxError, LinuxResult};
+use axerrno::{LinuxError, LinuxResult, linux_err_from};
 use task::{current, Tid, TaskRef, TaskStruct};
-use spinbase::SpinNoIrq;
+use mutex::RwSem;
+use spinpreempt::SpinLock;
+use fstree::FsStruct;
 use task::SIGCHLD;
 
 bitflags::bitflags! {
@@ -63,6 +65,12 @@ bitflags::bitflags! {
         const CLONE_UNTRACED        = 0x00800000;
         /// set the TID in the child
         const CLONE_CHILD_SETTID    = 0x01000000;
+        /// New utsname namespace
+        const CLONE_NEWUTS          = nsproxy::CLONE_NEWUTS;
+        /// New ipc namespace
+        const CLONE_NEWIPC          = nsproxy::CLONE_NEWIPC;
+        /// New pid namespace
+        const CLONE_NEWPID          = nsproxy::CLONE_NEWPID;
     }
 }
 
@@ -76,9 +84,14 @@ struct KernelCloneArgs {
     child_tid: usize,
     stack: Option<usize>,
     entry: Option<*mut dyn FnOnce()>,
+    bind_cpu: Option<usize>,
 }
 
 impl KernelCloneArgs {
+    const NEWNS_FLAGS: CloneFlags = CloneFlags::CLONE_NEWUTS
+        .union(CloneFlags::CLONE_NEWIPC)
+        .union(CloneFlags::CLONE_NEWPID);
+
     fn new(
         flags: CloneFlags,
         name: &str,
@@ -98,6 +111,7 @@ impl KernelCloneArgs {
             child_tid,
             stack,
             entry,
+            bind_cpu: None,
         }
     }
 
@@ -108,8 +122,14 @@ impl KernelCloneArgs {
     /// The arg *exit_signal* is expected to be checked for sanity
     /// by the caller.
     fn perform(&self) -> LinuxResult<Tid> {
-        // Todo: handle ptrace in future.
-        let trace = !self.flags.contains(CloneFlags::CLONE_UNTRACED);
+        self.check_flags()?;
+        // The event to report to the tracer, who then traces the child too.
+        let trace = if self.flags.contains(CloneFlags::CLONE_UNTRACED) {
+            0
+        } else {
+            let vfork = self.flags.contains(CloneFlags::CLONE_VFORK);
+            ptrace::clone_event(vfork, self.exit_signal)
+        };
 
         let task = self.copy_process(None, trace)?;
         debug!(
@@ -120,19 +140,52 @@ impl KernelCloneArgs {
 
         let tid = task.tid();
         if self.flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
-            let ptid_ptr = self.parent_tid as *mut usize;
-            unsafe { (*ptid_ptr) = tid; }
+            let ptid_ptr = self.parent_tid as *mut u32;
+            unsafe { (*ptid_ptr) = task::pid_vnr(tid) as u32; }
         }
 
         self.wake_up_new_task(task.clone());
 
+        if trace != 0 {
+            ptrace::ptrace_event(trace, task::pid_vnr(tid));
+        }
+
         if self.flags.contains(CloneFlags::CLONE_VFORK) {
             task.wait_for_vfork_done();
+            ptrace::ptrace_event(ptrace::PTRACE_EVENT_VFORK_DONE, task::pid_vnr(tid));
         }
 
         Ok(tid)
     }
 
+    /// Rejects combinations of flags that make no sense, as copy_process
+    /// in linux kernel.
+    fn check_flags(&self) -> LinuxResult {
+        // Threads share signal handlers, and handlers live in the address space.
+        if self.flags.contains(CloneFlags::CLONE_THREAD)
+            && !self.flags.contains(CloneFlags::CLONE_SIGHAND)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if self.flags.contains(CloneFlags::CLONE_SIGHAND)
+            && !self.flags.contains(CloneFlags::CLONE_VM)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        // A thread can't have its own parent.
+        if self.flags.contains(CloneFlags::CLONE_THREAD) && self.exit_signal != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        // Nor a pid namespace other than the one of its group.
+        if self.flags.contains(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_NEWPID) {
+            return Err(LinuxError::EINVAL);
+        }
+        if self.flags.intersects(Self::NEWNS_FLAGS) && !task::current().cred().is_privileged() {
+            return Err(LinuxError::EPERM);
+        }
+        Ok(())
+    }
+
     /// Wake up a newly created task for the first time.
     ///
     /// This function will do some initial scheduler statistics housekeeping
@@ -143,9 +196,8 @@ impl KernelCloneArgs {
         info!("wakeup the new task[{}].", task.tid());
     }
 
-    fn copy_process(&self, tid: Option<Tid>, _trace: bool) -> LinuxResult<TaskRef> {
+    fn copy_process(&self, tid: Option<Tid>, trace: usize) -> LinuxResult<TaskRef> {
         info!("copy_process...");
-        //assert!(!trace);
         let tid = match tid {
             Some(tid) => tid,
             None => task::alloc_tid(),
@@ -153,16 +205,25 @@ impl KernelCloneArgs {
 
         let mut task = current().dup_task_struct();
 
+        self.copy_creds(&mut task)?;
         self.copy_files(&mut task)?;
         self.copy_fs(&mut task)?;
         self.copy_sighand(&mut task)?;
-        //copy_signal();
+        self.copy_signal(&mut task)?;
         self.copy_mm(&mut task)?;
-        self.copy_thread(&mut task, tid)?;
+        self.copy_namespaces(&mut task)?;
+        let vtid = task.pid_ns.alloc_pid(tid)?;
+        if let Err(e) = self.copy_thread(&mut task, tid, vtid) {
+            task.pid_ns.free_pid(tid);
+            return Err(e);
+        }
 
         if self.flags.contains(CloneFlags::CLONE_VFORK) {
             task.init_vfork_done();
         }
+        if trace != 0 {
+            ptrace::ptrace_init_task(&task);
+        }
 
         let arc_task = Arc::new(task);
         task::register_task(arc_task.clone());
@@ -170,6 +231,15 @@ impl KernelCloneArgs {
         Ok(arc_task)
     }
 
+    // Threads share credentials, so that set*id applies to the whole
+    // process; a new process has its own copy from dup_task_struct.
+    fn copy_creds(&self, task: &mut TaskStruct) -> LinuxResult {
+        if self.flags.contains(CloneFlags::CLONE_THREAD) {
+            task.cred = task::current().cred.clone();
+        }
+        Ok(())
+    }
+
     fn copy_files(&self, task: &mut TaskStruct) -> LinuxResult {
         if self.flags.contains(CloneFlags::CLONE_FILES) {
             task.filetable = task::current().filetable.clone();
@@ -182,6 +252,14 @@ impl KernelCloneArgs {
         }
     }
 
+    // Resource limits are per process like the rest of signal_struct.
+    fn copy_signal(&self, task: &mut TaskStruct) -> LinuxResult {
+        if self.flags.contains(CloneFlags::CLONE_THREAD) {
+            task.rlim = task::current().rlim.clone();
+        }
+        Ok(())
+    }
+
     fn copy_sighand(&self, task: &mut TaskStruct) -> LinuxResult {
         if self.flags.contains(CloneFlags::CLONE_SIGHAND) {
             task.sighand = task::current().sighand.clone();
@@ -191,7 +269,16 @@ impl KernelCloneArgs {
         Ok(())
     }
 
-    fn copy_thread(&self, task: &mut TaskStruct, tid: Tid) -> LinuxResult {
+    /// The namespaces the flags ask for; the child has its pid in the one
+    /// for the children of its parent, the new one with CLONE_NEWPID.
+    fn copy_namespaces(&self, task: &mut TaskStruct) -> LinuxResult {
+        let flags = self.flags.intersection(Self::NEWNS_FLAGS).bits();
+        task.nsproxy = task::current().nsproxy.copy_namespaces(flags)?;
+        task.pid_ns = task.nsproxy.pid_ns_for_children.clone();
+        Ok(())
+    }
+
+    fn copy_thread(&self, task: &mut TaskStruct, tid: Tid, vtid: Tid) -> LinuxResult {
         let current_ctx = taskctx::current_ctx();
         let group_leader;
         let tgid;
@@ -235,7 +322,6 @@ impl KernelCloneArgs {
             group_leader.clone().unwrap().siblings.lock().push(tid);
         }
 
-        // Todo: in exit_mm_release && exec_mm_release, handle clear_child_tid.
         /*
          * This _must_ happen before we call free_task(), i.e. before we jump
          * to any of the bad_fork_* labels. This is to avoid freeing
@@ -259,20 +345,46 @@ impl KernelCloneArgs {
                 0
             };
 
-        warn!("Todo: handle exit_signal {}", exit_signal);
+        // Threads are reaped by themselves on exit.
+        task.exit_signal = exit_signal;
+        if let Some(parent) = &real_parent {
+            *task.real_parent.get_mut() = parent.tid();
+        }
 
         let mut sched_info = run_queue::spawn_task(tid, self.entry);
         sched_info.init_tgid(tgid);
+        sched_info.init_vtid(vtid);
         sched_info.real_parent = real_parent;
         sched_info.group_leader = group_leader;
         sched_info.set_child_tid = set_child_tid;
         sched_info.clear_child_tid = clear_child_tid;
+        // Policy and nice value are inherited by the child.
+        let parent = current();
+        let (policy, prio) = parent.sched_info.base_sched_params();
+        sched_info.set_sched_params(policy, prio);
+        sched_info.set_group(parent.sched_info.group());
+        // A traced task has its children traced too, as strace -f does.
+        if parent.sched_info.test_tsk_thread_flag(taskctx::TIF_SYSCALL_TRACEPOINT) {
+            sched_info.set_tsk_thread_flag(taskctx::TIF_SYSCALL_TRACEPOINT);
+        }
+        if let Some(cpu) = self.bind_cpu {
+            sched_info.set_cpu(cpu);
+            sched_info.set_cpus_allowed(1 << cpu);
+        }
         if let Some(mm) = task.try_mm() {
-            let locked_mm = mm.lock();
+            let locked_mm = mm.read();
             sched_info.set_mm(locked_mm.id(), locked_mm.pgd());
         }
 
         arch::copy_thread(sched_info.pt_regs(), self)?;
+        if self.flags.contains(CloneFlags::CLONE_SETTLS) {
+            arch::set_new_tls(&sched_info, self.tls);
+        } else if self.entry.is_none() {
+            arch::inherit_tls(&sched_info);
+        }
+        if self.entry.is_none() {
+            arch::inherit_fp_state(&sched_info);
+        }
         task.sched_info = Arc::new(sched_info);
         Ok(())
     }
@@ -283,24 +395,29 @@ impl KernelCloneArgs {
             task.mm = current().mm.clone();
         } else {
             info!("copy_mm: NO CLONE_VM");
-            let mm = current().mm().lock().deep_dup();
-            task.mm = Some(Arc::new(SpinNoIrq::new(mm)));
+            // No faults fill it in while it is copied.
+            let mm = current().mm().write().deep_dup();
+            task.mm = Some(Arc::new(RwSem::new(mm)));
         }
         Ok(())
     }
 
     fn copy_fs(&self, task: &mut TaskStruct) -> LinuxResult {
+        let fs = task::current().fs.clone();
         if self.flags.contains(CloneFlags::CLONE_FS) {
-            /* task.fs is already what we want */
-            let fs = task::current().fs.clone();
-            let mut locked_fs = fs.lock();
-            if locked_fs.in_exec {
-                return Err(LinuxError::EAGAIN);
+            {
+                let mut locked_fs = fs.lock();
+                if locked_fs.in_exec {
+                    return Err(LinuxError::EAGAIN);
+                }
+                locked_fs.users += 1;
             }
-            locked_fs.users += 1;
+            task.fs = fs;
             return Ok(());
         }
-        task.fs.lock().copy_fs_struct(task::current().fs.clone());
+        let mut new_fs = FsStruct::new();
+        new_fs.copy_fs_struct(fs);
+        task.fs = Arc::new(SpinLock::new(new_fs));
         Ok(())
     }
 }
@@ -329,6 +446,22 @@ where
     args.perform().expect("kernel_clone failed.")
 }
 
+/// Creates a kernel thread running `f`, bound to `cpu` if it's given.
+///
+/// The thread shares the address space and fs of the caller, and `f`
+/// is not expected to return.
+pub fn kernel_thread<F>(f: F, cpu: Option<usize>) -> Tid
+where
+    F: FnOnce() + 'static,
+{
+    info!("create a kernel thread ...");
+    let f = Box::into_raw(Box::new(f));
+    let flags = CloneFlags::CLONE_VM | CloneFlags::CLONE_FS | CloneFlags::CLONE_UNTRACED;
+    let mut args = KernelCloneArgs::new(flags, "", 0, 0, 0, 0, None, Some(f));
+    args.bind_cpu = cpu;
+    args.perform().expect("kernel_clone failed.")
+}
+
 
 /// Clone thread according to SysCall requirements
 ///
@@ -349,7 +482,8 @@ pub fn sys_clone(
     let args = KernelCloneArgs::new(flags, "", exit_signal, tls, ptid, ctid, stack, None);
     warn!("impl clone: flags {:#X} sig {:#X} stack {:#X} ptid {:#X} tls {:#X} ctid {:#X}",
         flags.bits(), exit_signal, stack.unwrap_or(0), ptid, tls, ctid);
-    args.perform().unwrap_or(usize::MAX)
+    // The parent sees the child by its pid in its own namespace.
+    args.perform().map_or_else(|e| linux_err_from!(e), task::pid_vnr)
 }
 
 /// System call interface for vfork operation.
@@ -357,7 +491,7 @@ pub fn sys_clone(
 pub fn sys_vfork() -> usize {
     let flags = CloneFlags::CLONE_VFORK | CloneFlags::CLONE_VM;
     let args = KernelCloneArgs::new(flags, "", SIGCHLD as i32, 0, 0, 0, None, None);
-    args.perform().unwrap_or(usize::MAX)
+    args.perform().map_or_else(|e| linux_err_from!(e), task::pid_vnr)
 }
 
 /// Sets the clear child TID address for the current thread.
@@ -365,7 +499,7 @@ pub fn set_tid_address(tidptr: usize) -> usize {
     info!("set_tid_address: tidptr {:#X}", tidptr);
     let mut ctx = taskctx::current_ctx();
     ctx.as_ctx_mut().clear_child_tid = tidptr;
-    ctx.tid()
+    ctx.vtid()
 }
 
 /// Initializes the process/thread management subsystem.
@@ -5,6 +5,7 @@
 extern crate axlog2;
 extern crate alloc;
 
+use alloc::sync::Arc;
 use core::panic::PanicInfo;
 use fork::user_mode_thread;
 use fork::CloneFlags;
@@ -31,6 +32,15 @@ pub fn start(_cpu_id: usize, _dtb: usize) {
         CloneFlags::CLONE_FS,
     );
     assert_eq!(tid, 1);
+    let child = task::get_task(tid).unwrap();
+    assert!(Arc::ptr_eq(&child.fs, &task::current().fs));
+    assert!(!Arc::ptr_eq(&child.filetable, &task::current().filetable));
+    assert_eq!(task::current().thread_group(), [0]);
+
+    // Threads must share signal handlers, which must share the VM.
+    let flags = CloneFlags::CLONE_THREAD | CloneFlags::CLONE_VM;
+    assert_eq!(fork::sys_clone(flags.bits(), 0, 0, 0, 0), usize::MAX);
+    assert_eq!(fork::sys_clone(CloneFlags::CLONE_SIGHAND.bits(), 0, 0, 0, 0), usize::MAX);
 
     schedule_preempt_disabled();
 
@@ -46,7 +56,7 @@ fn schedule_preempt_disabled() {
 fn kernel_init() {
     info!("[new process]: enter ...");
     let task = task::current();
-    task.set_state(taskctx::TaskState::Blocked);
+    task.set_state(taskctx::TaskState::Uninterruptible);
     let rq = run_queue::task_rq(&task.sched_info);
     info!("[new process]: yield ...");
     rq.lock().resched(false);
@@ -68,12 +68,18 @@ impl FsStruct {
         self.umask = mode;
     }
 
+    /// Returns the file creation mask
+    pub fn umask(&self) -> u32 {
+        self.umask
+    }
+
     /// Copies filesystem context from another process
     pub fn copy_fs_struct(&mut self, fs: Arc<SpinLock<FsStruct>>) {
         let locked_fs = &fs.lock();
         self.root_dir = locked_fs.root_dir.as_ref().map(|root_dir| root_dir.clone());
         self.curr_dir = locked_fs.curr_dir.as_ref().map(|curr_dir| curr_dir.clone());
         self.curr_path = locked_fs.curr_path.clone();
+        self.umask = locked_fs.umask;
     }
 
     /// Copies filesystem context from another process
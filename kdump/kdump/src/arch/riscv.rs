@@ -0,0 +1,60 @@
+//! RISC-V registers: pc, x1..x31, then sstatus, sepc, scause, stval and
+//! satp, of the last trap.
+
+pub const ELF_MACHINE: u32 = 243;
+
+pub const NR_REGS: usize = 37;
+
+/// Saves the registers of the caller into `regs`.
+#[inline(always)]
+pub fn save_regs(regs: &mut [u64; NR_REGS]) {
+    unsafe {
+        core::arch::asm!(
+            "auipc t0, 0",
+            "sd t0, 0*8({0})",
+            "sd x1, 1*8({0})",
+            "sd x2, 2*8({0})",
+            "sd x3, 3*8({0})",
+            "sd x4, 4*8({0})",
+            "sd x5, 5*8({0})",
+            "sd x6, 6*8({0})",
+            "sd x7, 7*8({0})",
+            "sd x8, 8*8({0})",
+            "sd x9, 9*8({0})",
+            "sd x10, 10*8({0})",
+            "sd x11, 11*8({0})",
+            "sd x12, 12*8({0})",
+            "sd x13, 13*8({0})",
+            "sd x14, 14*8({0})",
+            "sd x15, 15*8({0})",
+            "sd x16, 16*8({0})",
+            "sd x17, 17*8({0})",
+            "sd x18, 18*8({0})",
+            "sd x19, 19*8({0})",
+            "sd x20, 20*8({0})",
+            "sd x21, 21*8({0})",
+            "sd x22, 22*8({0})",
+            "sd x23, 23*8({0})",
+            "sd x24, 24*8({0})",
+            "sd x25, 25*8({0})",
+            "sd x26, 26*8({0})",
+            "sd x27, 27*8({0})",
+            "sd x28, 28*8({0})",
+            "sd x29, 29*8({0})",
+            "sd x30, 30*8({0})",
+            "sd x31, 31*8({0})",
+            "csrr t0, sstatus",
+            "sd t0, 32*8({0})",
+            "csrr t0, sepc",
+            "sd t0, 33*8({0})",
+            "csrr t0, scause",
+            "sd t0, 34*8({0})",
+            "csrr t0, stval",
+            "sd t0, 35*8({0})",
+            "csrr t0, satp",
+            "sd t0, 36*8({0})",
+            in(reg) regs.as_mut_ptr(),
+            out("t0") _,
+        );
+    }
+}
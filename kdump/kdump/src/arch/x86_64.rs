@@ -0,0 +1,42 @@
+//! x86_64 registers: rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8..r15,
+//! then rip, rflags, cr2 and cr3.
+
+pub const ELF_MACHINE: u32 = 62;
+
+pub const NR_REGS: usize = 20;
+
+/// Saves the registers of the caller into `regs`.
+#[inline(always)]
+pub fn save_regs(regs: &mut [u64; NR_REGS]) {
+    unsafe {
+        core::arch::asm!(
+            "mov [{0} + 0*8], rax",
+            "mov [{0} + 1*8], rbx",
+            "mov [{0} + 2*8], rcx",
+            "mov [{0} + 3*8], rdx",
+            "mov [{0} + 4*8], rsi",
+            "mov [{0} + 5*8], rdi",
+            "mov [{0} + 6*8], rbp",
+            "mov [{0} + 7*8], rsp",
+            "mov [{0} + 8*8], r8",
+            "mov [{0} + 9*8], r9",
+            "mov [{0} + 10*8], r10",
+            "mov [{0} + 11*8], r11",
+            "mov [{0} + 12*8], r12",
+            "mov [{0} + 13*8], r13",
+            "mov [{0} + 14*8], r14",
+            "mov [{0} + 15*8], r15",
+            "lea {1}, [rip]",
+            "mov [{0} + 16*8], {1}",
+            "pushfq",
+            "pop {1}",
+            "mov [{0} + 17*8], {1}",
+            "mov {1}, cr2",
+            "mov [{0} + 18*8], {1}",
+            "mov {1}, cr3",
+            "mov [{0} + 19*8], {1}",
+            in(reg) regs.as_mut_ptr(),
+            out(reg) _,
+        );
+    }
+}
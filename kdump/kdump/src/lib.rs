@@ -0,0 +1,370 @@
+//! Crash dump (kdump-lite): a compact dump of the kernel, written on a
+//! panic for post-mortem analysis, as of a CI run with no one at the
+//! console.
+//!
+//! The dump is built in the memory kept for it below the memory kept for
+//! kexec, which a warm reboot leaves alone and the host can read out of a
+//! stopped QEMU with `pmemsave`. If `kdump_dev=` on the command line, or
+//! `AX_KDUMP_DEV` the kernel was built with, names a disk or partition, as
+//! `vdb1`, the dump is written to its first blocks too, bypassing the
+//! queue and the buffer cache. `scripts/kdump.py` parses it on the host.
+//!
+//! Nothing is allocated on a panic, and no lock is waited for: whatever is
+//! locked, maybe by the panicking CPU, is left out of the dump.
+//!
+//! The layout, all little-endian, is a header of [`HEADER_SIZE`] bytes:
+//!
+//! | offset | size | field                                               |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 8    | [`KDUMP_MAGIC`]                                     |
+//! | 8      | 4    | version, [`KDUMP_VERSION`]                          |
+//! | 12     | 4    | size of the header                                  |
+//! | 16     | 8    | size of the dump, with the header                   |
+//! | 24     | 4    | number of sections                                  |
+//! | 28     | 4    | flags, as [`KDUMP_FLAG_TRUNCATED`]                  |
+//! | 32     | 4    | ELF machine of the kernel                           |
+//! | 36     | 4    | the panicking CPU                                   |
+//! | 40     | 8    | the current task on it, or 0                        |
+//! | 48     | 8    | nanoseconds since boot                              |
+//! | 56     | 4    | FNV-1a of the dump, with this field 0               |
+//! | 60     | 4    | reserved                                            |
+//!
+//! followed by sections, each a type (u32), a reserved u32 and the size of
+//! its payload (u64), then the payload, padded to 8 bytes. See the
+//! `SECTION_*` constants for what the payloads hold.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod arch;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use axdriver::partition::Partition;
+use axdriver::prelude::DevResult;
+use axhal::mem::phys_to_virt;
+use spinbase::SpinNoIrq;
+
+pub const KDUMP_MAGIC: &[u8; 8] = b"LKDUMP\0\0";
+pub const KDUMP_VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 64;
+
+/// The dump did not fit, and ends where it was cut.
+pub const KDUMP_FLAG_TRUNCATED: u32 = 1 << 0;
+
+/// The panic message, in UTF-8.
+pub const SECTION_MESSAGE: u32 = 1;
+/// The registers of the panicking CPU, as u64s in the order of the
+/// architecture.
+pub const SECTION_REGS: u32 = 2;
+/// Return addresses of the panicking stack, as u64s from the innermost
+/// frame out.
+pub const SECTION_BACKTRACE: u32 = 3;
+/// A record of 6 u64s per task: tid, tgid, state, CPU, switches, and the
+/// frame pointer it was switched out with.
+pub const SECTION_TASKS: u32 = 4;
+/// The kernel log buffer, in the lines /proc/kmsg gives.
+pub const SECTION_DMESG: u32 = 5;
+/// A memory range: its start and length (u64s) and its name (16 bytes, 0
+/// padded), then its bytes.
+pub const SECTION_MEMORY: u32 = 6;
+
+const SECTION_HEADER_SIZE: usize = 16;
+const MEMORY_NAME_LEN: usize = 16;
+
+/// Bytes written to the dump device at once.
+const WRITE_CHUNK: usize = 64 * 1024;
+
+/// A range of kernel memory to dump.
+struct MemRange {
+    name: &'static str,
+    start: usize,
+    len: usize,
+}
+
+static MEMORY_RANGES: SpinNoIrq<Vec<MemRange>> = SpinNoIrq::new(Vec::new());
+
+/// The disk or partition the dump is written to as well.
+static DUMP_DEV: SpinNoIrq<Option<Partition>> = SpinNoIrq::new(None);
+
+/// Set by the first panic, so that one within the dump doesn't recurse.
+static DUMPING: AtomicBool = AtomicBool::new(false);
+
+/// Adds the `len` bytes of kernel memory at `start` to the dump, as
+/// `name`.
+pub fn add_memory_range(name: &'static str, start: usize, len: usize) {
+    MEMORY_RANGES.lock().push(MemRange { name, start, len });
+}
+
+pub fn remove_memory_range(name: &str) {
+    MEMORY_RANGES.lock().retain(|r| r.name != name);
+}
+
+/// Writes the dump into a buffer, cutting it at the end.
+struct DumpWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    nr_sections: u32,
+    truncated: bool,
+    /// Offset of the header of the section being written.
+    section: usize,
+}
+
+impl<'a> DumpWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            pos: HEADER_SIZE,
+            nr_sections: 0,
+            truncated: false,
+            section: 0,
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        let n = bytes.len().min(self.buf.len() - self.pos);
+        self.buf[self.pos..self.pos + n].copy_from_slice(&bytes[..n]);
+        self.pos += n;
+        if n < bytes.len() {
+            self.truncated = true;
+        }
+    }
+
+    fn put_u64(&mut self, v: u64) {
+        self.put(&v.to_le_bytes());
+    }
+
+    /// Starts a section of type `ty`, if there is room for one.
+    fn begin(&mut self, ty: u32) -> bool {
+        if self.truncated || self.buf.len() - self.pos < SECTION_HEADER_SIZE {
+            self.truncated = true;
+            return false;
+        }
+        self.section = self.pos;
+        self.put(&ty.to_le_bytes());
+        self.put(&0u32.to_le_bytes());
+        self.put_u64(0);
+        true
+    }
+
+    /// Ends the section being written with its size, and pads it.
+    fn end(&mut self) {
+        let size = (self.pos - self.section - SECTION_HEADER_SIZE) as u64;
+        self.buf[self.section + 8..self.section + 16].copy_from_slice(&size.to_le_bytes());
+        let pad = self.pos.next_multiple_of(8).min(self.buf.len()) - self.pos;
+        self.buf[self.pos..self.pos + pad].fill(0);
+        self.pos += pad;
+        self.nr_sections += 1;
+    }
+
+    /// Fills in the header, and returns the size of the dump.
+    fn finish(self, tid: u64) -> usize {
+        let total = self.pos;
+        let now = axhal::time::current_time().as_nanos() as u64;
+        let flags = if self.truncated { KDUMP_FLAG_TRUNCATED } else { 0 };
+        let hdr = &mut self.buf[..HEADER_SIZE];
+        hdr[0..8].copy_from_slice(KDUMP_MAGIC);
+        hdr[8..12].copy_from_slice(&KDUMP_VERSION.to_le_bytes());
+        hdr[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        hdr[16..24].copy_from_slice(&(total as u64).to_le_bytes());
+        hdr[24..28].copy_from_slice(&self.nr_sections.to_le_bytes());
+        hdr[28..32].copy_from_slice(&flags.to_le_bytes());
+        hdr[32..36].copy_from_slice(&arch::ELF_MACHINE.to_le_bytes());
+        hdr[36..40].copy_from_slice(&(axhal::cpu::_this_cpu_id() as u32).to_le_bytes());
+        hdr[40..48].copy_from_slice(&tid.to_le_bytes());
+        hdr[48..56].copy_from_slice(&now.to_le_bytes());
+        hdr[56..64].fill(0);
+        let sum = fnv1a(&self.buf[..total]);
+        self.buf[56..60].copy_from_slice(&sum.to_le_bytes());
+        total
+    }
+}
+
+impl Write for DumpWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.put(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5u32, |h, &b| (h ^ b as u32).wrapping_mul(0x01000193))
+}
+
+fn write_tasks(w: &mut DumpWriter) {
+    task::try_for_each_task(|task| {
+        let fp = unsafe { (*task.sched_info.ctx_mut_ptr()).frame_pointer() };
+        w.put_u64(task.tid() as u64);
+        w.put_u64(task.tgid() as u64);
+        w.put_u64(task.sched_info.state() as u64);
+        w.put_u64(task.sched_info.cpu() as u64);
+        w.put_u64(task.sched_info.sched_stats().nr_switches as u64);
+        w.put_u64(fp as u64);
+    });
+}
+
+fn write_dmesg(w: &mut DumpWriter) {
+    axlog2::kmsg::try_for_each_record(|prio, time, text| {
+        let _ = write!(w, "<{}>[{:>5}.{:06}] ", prio, time.as_secs(), time.subsec_micros());
+        w.put(text);
+        w.put(b"\n");
+    });
+}
+
+fn write_memory(w: &mut DumpWriter, name: &str, start: usize, len: usize) {
+    if !w.begin(SECTION_MEMORY) {
+        return;
+    }
+    let mut name_buf = [0u8; MEMORY_NAME_LEN];
+    let n = name.len().min(MEMORY_NAME_LEN);
+    name_buf[..n].copy_from_slice(&name.as_bytes()[..n]);
+    w.put_u64(start as u64);
+    w.put_u64(len as u64);
+    w.put(&name_buf);
+    w.put(unsafe { core::slice::from_raw_parts(start as *const u8, len) });
+    w.end();
+}
+
+/// Writes the dump of the panic `info` into `buf`, and returns its size.
+#[inline(always)]
+fn write_dump(buf: &mut [u8], info: &PanicInfo) -> usize {
+    let mut regs = [0u64; arch::NR_REGS];
+    arch::save_regs(&mut regs);
+    let sp = &regs as *const _ as usize;
+    let ctx = taskctx::try_current_ctx();
+    let tid = ctx.as_ref().map_or(0, |ctx| ctx.tid() as u64);
+
+    let mut w = DumpWriter::new(buf);
+    if w.begin(SECTION_MESSAGE) {
+        let _ = write!(w, "{}", info);
+        w.end();
+    }
+    if w.begin(SECTION_REGS) {
+        regs.iter().for_each(|r| w.put_u64(*r));
+        w.end();
+    }
+    if w.begin(SECTION_BACKTRACE) {
+        backtrace::Frames::current().for_each(|ra| w.put_u64(ra as u64));
+        w.end();
+    }
+    if w.begin(SECTION_TASKS) {
+        write_tasks(&mut w);
+        w.end();
+    }
+    if w.begin(SECTION_DMESG) {
+        write_dmesg(&mut w);
+        w.end();
+    }
+    // The stack of the panicking task, from here up.
+    if let Some(top) = ctx.as_ref().and_then(|ctx| ctx.kstack.as_ref()).map(|s| s.top()) {
+        if sp < top && top - sp <= taskctx::THREAD_SIZE {
+            write_memory(&mut w, "stack", sp, top - sp);
+        }
+    }
+    if let Some(ranges) = MEMORY_RANGES.try_lock() {
+        for r in ranges.iter() {
+            write_memory(&mut w, r.name, r.start, r.len);
+        }
+    }
+    w.finish(tid)
+}
+
+/// Writes the dump `image`, of whole blocks, to the first blocks of `dev`,
+/// as much as fits. Returns the bytes written.
+fn write_to_device(dev: &Partition, image: &[u8]) -> DevResult<usize> {
+    let bs = dev.block_size();
+    let chunk = WRITE_CHUNK / bs * bs;
+    let len = image.len().min(dev.num_blocks() as usize * bs);
+    for (i, buf) in image[..len].chunks(chunk).enumerate() {
+        dev.panic_write((i * chunk / bs) as u64, buf)?;
+    }
+    Ok(len)
+}
+
+/// Writes the dump of the panic `info`, to the memory kept for it and to
+/// the dump device if there is one. Called by the panic handler, once.
+#[inline(always)]
+pub fn crash_dump(info: &PanicInfo) {
+    if DUMPING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let (paddr, size) = axhal::mem::kdump_region();
+    let buf = unsafe { core::slice::from_raw_parts_mut(phys_to_virt(paddr).as_mut_ptr(), size) };
+    let total = write_dump(buf, info);
+    error!("kdump: dump of {} bytes at {:#x}", total, paddr);
+
+    let Some(dev) = DUMP_DEV.try_lock() else {
+        return;
+    };
+    if let Some(dev) = dev.as_ref() {
+        // Whole blocks are written, with zeros after the dump.
+        let end = total.next_multiple_of(dev.block_size()).min(size);
+        buf[total..end].fill(0);
+        match write_to_device(dev, &buf[..end]) {
+            Ok(len) if len < end => error!("kdump: {} of {} bytes written to {}", len, end, dev.name()),
+            Ok(_) => error!("kdump: written to {}", dev.name()),
+            Err(e) => error!("kdump: failed to write to {}: {:?}", dev.name(), e),
+        }
+    }
+}
+
+/// Returns the size of the dump a panic of the last boot left in memory,
+/// if it is there intact.
+fn previous_dump() -> Option<usize> {
+    let (paddr, size) = axhal::mem::kdump_region();
+    let buf = unsafe { core::slice::from_raw_parts_mut(phys_to_virt(paddr).as_mut_ptr(), size) };
+    if &buf[..8] != KDUMP_MAGIC {
+        return None;
+    }
+    let total = u64::from_le_bytes(buf[16..24].try_into().unwrap()) as usize;
+    if !(HEADER_SIZE..=size).contains(&total) {
+        return None;
+    }
+    let sum = u32::from_le_bytes(buf[56..60].try_into().unwrap());
+    buf[56..60].fill(0);
+    let intact = fnv1a(&buf[..total]) == sum;
+    buf[56..60].copy_from_slice(&sum.to_le_bytes());
+    intact.then_some(total)
+}
+
+/// The dump device given with `kdump_dev=`.
+static DUMP_DEV_NAME: SpinNoIrq<Option<String>> = SpinNoIrq::new(None);
+
+fn kdump_dev_setup(value: &str) -> LinuxResult {
+    let name = value.strip_prefix("/dev/").unwrap_or(value);
+    if name.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    *DUMP_DEV_NAME.lock() = Some(name.into());
+    Ok(())
+}
+cmdline::kernel_param!("kdump_dev", kdump_dev_setup);
+
+/// Tells of the dump a panic of the last boot left in memory, and sets up
+/// the dump device named by `kdump_dev=` or `AX_KDUMP_DEV`. Called once
+/// the disks are found.
+pub fn init() {
+    let (paddr, size) = axhal::mem::kdump_region();
+    info!("kdump: {:#x} bytes at {:#x}", size, paddr);
+    if let Some(total) = previous_dump() {
+        warn!("kdump: a dump of {} bytes from the last boot is at {:#x}", total, paddr);
+    }
+    let name = DUMP_DEV_NAME.lock().clone()
+        .or_else(|| option_env!("AX_KDUMP_DEV").filter(|name| !name.is_empty()).map(|name| name.into()));
+    if let Some(name) = name {
+        match axdriver::partition::lookup_partition(&name) {
+            Some(dev) => {
+                info!("kdump: dumping to {} as well", name);
+                *DUMP_DEV.lock() = Some(dev);
+            }
+            None => warn!("kdump: no disk {}", name),
+        }
+    }
+}
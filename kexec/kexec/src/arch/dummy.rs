@@ -0,0 +1,20 @@
+//! No memory is kept for kexec here, so no image is ever staged.
+
+use axerrno::{LinuxError, LinuxResult};
+use crate::Kimage;
+
+pub const KEXEC_ARCH: usize = 0;
+
+pub fn prepare(_image: &Kimage) -> LinuxResult {
+    Err(LinuxError::ENOSYS)
+}
+
+pub fn machine_kexec(_image: &Kimage) -> ! {
+    unreachable!("kexec: no image can be staged");
+}
+
+pub fn stop_this_cpu() -> ! {
+    loop {
+        axhal::arch::halt();
+    }
+}
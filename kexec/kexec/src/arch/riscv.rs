@@ -0,0 +1,111 @@
+//! RISC-V: the new kernel is entered as the SBI firmware enters one, in
+//! S-mode with paging off, with the hart id in a0 and the physical address
+//! of the device tree in a1.
+
+use core::arch::{asm, global_asm};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{phys_to_virt, virt_to_phys, PAGE_SIZE_4K};
+use crate::{Kimage, Segment};
+
+pub const KEXEC_ARCH: usize = 243 << 16; // EM_RISCV
+
+/// Offset of the segment list in the control page, after the stub.
+const LIST_OFFSET: usize = 2048;
+
+// Copies each segment of the list at a0 (dst, src, len; up to a len of 0),
+// then enters a1 with the hart id a2 and the device tree a3. Runs from the
+// control page with paging off, so it must be position independent.
+global_asm!(
+    "
+    .section .text
+    .balign 8
+    .global kexec_relocate_start
+    .global kexec_relocate_end
+kexec_relocate_start:
+1:
+    ld      t0, 0(a0)
+    ld      t1, 8(a0)
+    ld      t2, 16(a0)
+    beqz    t2, 3f
+2:
+    ld      t3, 0(t1)
+    sd      t3, 0(t0)
+    addi    t0, t0, 8
+    addi    t1, t1, 8
+    addi    t2, t2, -8
+    bnez    t2, 2b
+    addi    a0, a0, 24
+    j       1b
+3:
+    fence.i
+    mv      t0, a1
+    mv      a0, a2
+    mv      a1, a3
+    li      a2, 0
+    li      a3, 0
+    jr      t0
+kexec_relocate_end:
+    "
+);
+
+extern "C" {
+    fn kexec_relocate_start();
+    fn kexec_relocate_end();
+}
+
+pub fn prepare(image: &Kimage) -> LinuxResult {
+    // The kernel finds the memory and the devices in its device tree.
+    if image.dtb.is_none() {
+        warn!("kexec: no device tree among the segments");
+        return Err(LinuxError::EINVAL);
+    }
+    let stub_len = kexec_relocate_end as usize - kexec_relocate_start as usize;
+    let list_len = (image.segments.len() + 1) * core::mem::size_of::<Segment>();
+    assert!(stub_len <= LIST_OFFSET && LIST_OFFSET + list_len <= PAGE_SIZE_4K);
+    Ok(())
+}
+
+pub fn machine_kexec(image: &Kimage) -> ! {
+    let control = phys_to_virt(image.control.into()).as_usize();
+    let stub_len = kexec_relocate_end as usize - kexec_relocate_start as usize;
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            kexec_relocate_start as usize as *const u8, control as *mut u8, stub_len
+        );
+        let list = (control + LIST_OFFSET) as *mut Segment;
+        for (i, seg) in image.segments.iter().enumerate() {
+            list.add(i).write(*seg);
+        }
+        list.add(image.segments.len()).write(Segment { dst: 0, src: 0, len: 0 });
+    }
+    axhal::arch::local_flush_icache_all();
+    // No timer interrupt for the new kernel before it sets one.
+    axhal::time::set_oneshot_timer(u64::MAX);
+
+    let hartid = axhal::cpu::_this_cpu_id();
+    let list_pa = virt_to_phys((control + LIST_OFFSET).into()).as_usize();
+    // Once satp is cleared, the next fetch faults, on the kernel's virtual
+    // address, and so traps to stvec: to the stub, at its physical address.
+    unsafe {
+        asm!(
+            "csrw sie, zero",
+            "csrw stvec, {stub}",
+            "csrw satp, zero",
+            "sfence.vma",
+            "jr {stub}",
+            stub = in(reg) image.control,
+            in("a0") list_pa,
+            in("a1") image.entry,
+            in("a2") hartid,
+            in("a3") image.dtb.unwrap(),
+            options(noreturn),
+        )
+    }
+}
+
+pub fn stop_this_cpu() -> ! {
+    let _ = sbi_rt::hart_stop();
+    loop {
+        axhal::arch::halt();
+    }
+}
@@ -0,0 +1,205 @@
+//! kexec: booting a new kernel from the running one, without the firmware.
+//!
+//! kexec_load() stages the segments of the new image, its kernel and its
+//! device tree, in the memory kept for kexec at the end of the physical
+//! memory, which the page allocator never hands out. reboot() with
+//! `LINUX_REBOOT_CMD_KEXEC` then stops the other CPUs, resets the devices
+//! and leaves the kernel for a stub in the first page of that memory,
+//! which copies the segments where they belong, with paging off, and
+//! jumps to the entry of the new image. Segments may go anywhere in the
+//! physical memory but in the memory kept for kexec, over the running
+//! kernel too.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod arch;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{phys_to_virt, PAGE_SIZE_4K};
+use mutex::Mutex;
+
+/* Flags of kexec_load() */
+pub const KEXEC_ON_CRASH: usize = 0x00000001;
+pub const KEXEC_ARCH_MASK: usize = 0xffff0000;
+pub const KEXEC_ARCH_DEFAULT: usize = 0;
+
+/// Segments of an image at most.
+pub const KEXEC_SEGMENT_MAX: usize = 16;
+
+/// A segment as kexec_load() takes it: `bufsz` bytes at `buf` in user
+/// memory, for the `memsz` bytes at the physical address `mem`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct KexecSegment {
+    buf: usize,
+    bufsz: usize,
+    mem: usize,
+    memsz: usize,
+}
+
+/// A segment staged at the physical address `src`, for `dst`. The stub
+/// reads them in this layout, up to one whose `len` is 0.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct Segment {
+    dst: usize,
+    src: usize,
+    len: usize,
+}
+
+/// An image staged to be booted.
+pub(crate) struct Kimage {
+    /// Physical address of the control page, for the stub.
+    control: usize,
+    entry: usize,
+    segments: Vec<Segment>,
+    /// Where its device tree goes, if it has one.
+    dtb: Option<usize>,
+}
+
+static KEXEC_IMAGE: Mutex<Option<Kimage>> = Mutex::new(None);
+
+/// The other CPUs are to stop, at their next IPI.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static NR_STOPPED: AtomicUsize = AtomicUsize::new(0);
+
+fn overlaps(a: usize, a_len: usize, b: usize, b_len: usize) -> bool {
+    a < b + b_len && b < a + a_len
+}
+
+/// Stages `usegs` in the `size` bytes of memory at `base`, after the
+/// control page.
+fn stage(entry: usize, usegs: &[KexecSegment], base: usize, size: usize) -> LinuxResult<Kimage> {
+    let mut next = base + PAGE_SIZE_4K;
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut dtb = None;
+    for seg in usegs.iter().filter(|seg| seg.memsz > 0) {
+        if seg.mem % PAGE_SIZE_4K != 0 || seg.memsz % PAGE_SIZE_4K != 0 {
+            return Err(LinuxError::EADDRNOTAVAIL);
+        }
+        if seg.mem < axconfig::runtime::phys_memory_base()
+            || seg.mem + seg.memsz > axconfig::runtime::phys_memory_end()
+            || overlaps(seg.mem, seg.memsz, base, size)
+        {
+            return Err(LinuxError::EADDRNOTAVAIL);
+        }
+        if seg.bufsz > seg.memsz || segments.iter().any(|s| overlaps(seg.mem, seg.memsz, s.dst, s.len)) {
+            return Err(LinuxError::EINVAL);
+        }
+        if next + seg.memsz > base + size {
+            return Err(LinuxError::ENOMEM);
+        }
+        let buf = unsafe { core::slice::from_raw_parts(seg.buf as *const u8, seg.bufsz) };
+        let dst = unsafe {
+            core::slice::from_raw_parts_mut(phys_to_virt(next.into()).as_mut_ptr(), seg.memsz)
+        };
+        dst[..seg.bufsz].copy_from_slice(buf);
+        dst[seg.bufsz..].fill(0);
+        if buf.starts_with(&[0xd0, 0x0d, 0xfe, 0xed]) {
+            dtb = Some(seg.mem);
+        }
+        segments.push(Segment { dst: seg.mem, src: next, len: seg.memsz });
+        next += seg.memsz;
+    }
+    if !segments.iter().any(|s| (s.dst..s.dst + s.len).contains(&entry)) {
+        return Err(LinuxError::EADDRNOTAVAIL);
+    }
+    Ok(Kimage { control: base, entry, segments, dtb })
+}
+
+/// kexec_load(): stages the image of the `nr_segments` segments at
+/// `usegments`, with its entry at the physical address `entry`, in place
+/// of the one staged before. Without segments, unloads that one.
+pub fn kexec_load(
+    entry: usize, nr_segments: usize, usegments: usize, flags: usize
+) -> LinuxResult<usize> {
+    info!("kexec_load: entry {:#x} nr_segments {} flags {:#x}", entry, nr_segments, flags);
+    if !task::current().cred().is_privileged() {
+        return Err(LinuxError::EPERM);
+    }
+    let arch_flags = flags & KEXEC_ARCH_MASK;
+    if (flags & !KEXEC_ARCH_MASK) != 0
+        || (arch_flags != KEXEC_ARCH_DEFAULT && arch_flags != arch::KEXEC_ARCH)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    if nr_segments > KEXEC_SEGMENT_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let Some((base, size)) = axhal::mem::kexec_region() else {
+        return Err(LinuxError::ENOSYS);
+    };
+
+    let mut image = KEXEC_IMAGE.lock();
+    // Whatever is staged is overwritten from here on.
+    *image = None;
+    if nr_segments == 0 {
+        info!("kexec: image unloaded");
+        return Ok(0);
+    }
+    let usegs = unsafe {
+        core::slice::from_raw_parts(usegments as *const KexecSegment, nr_segments)
+    }.to_vec();
+    let kimage = stage(entry, &usegs, base.as_usize(), size)?;
+    arch::prepare(&kimage)?;
+    info!("kexec: image with {} segments staged", kimage.segments.len());
+    *image = Some(kimage);
+    Ok(0)
+}
+
+/// Whether an image is staged.
+pub fn kexec_loaded() -> bool {
+    KEXEC_IMAGE.lock().is_some()
+}
+
+/// Stops this CPU for good, if the CPUs are being stopped. Called on each
+/// IPI.
+pub fn handle_stop_ipi() {
+    if STOPPING.load(Ordering::Acquire) {
+        axhal::arch::disable_irqs();
+        NR_STOPPED.fetch_add(1, Ordering::AcqRel);
+        arch::stop_this_cpu();
+    }
+}
+
+/// Stops the other CPUs that have been brought up, waiting a second at
+/// most for them.
+fn smp_send_stop() {
+    let this = axhal::cpu::_this_cpu_id();
+    let others: Vec<usize> = (0..axconfig::SMP)
+        .filter(|&cpu| cpu != this && run_queue::cpu_present(cpu))
+        .collect();
+    STOPPING.store(true, Ordering::Release);
+    for &cpu in others.iter() {
+        axhal::platform::irq::send_ipi(cpu);
+    }
+    let deadline = axhal::time::current_time() + Duration::from_secs(1);
+    while NR_STOPPED.load(Ordering::Acquire) < others.len() {
+        if axhal::time::current_time() >= deadline {
+            warn!("kexec: {} of {} CPUs stopped", NR_STOPPED.load(Ordering::Acquire), others.len());
+            break;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Boots the staged image, for reboot() with `LINUX_REBOOT_CMD_KEXEC`.
+/// Only returns if there is none, with EINVAL.
+pub fn kernel_kexec() -> LinuxResult {
+    let image = KEXEC_IMAGE.lock();
+    let Some(image) = image.as_ref() else {
+        return Err(LinuxError::EINVAL);
+    };
+    info!("kexec: booting the new image at {:#x}", image.entry);
+    axhal::arch::disable_irqs();
+    smp_send_stop();
+    axdriver::shutdown_devices();
+    arch::machine_kexec(image)
+}
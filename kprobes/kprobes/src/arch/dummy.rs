@@ -0,0 +1,47 @@
+//! Probes are not supported here: no instruction decodes, so none can be
+//! registered, and the rest is never called.
+
+use axhal::arch::TrapFrame;
+use crate::Insn;
+
+pub fn decode(_addr: usize) -> Option<Insn> {
+    None
+}
+
+pub fn is_breakpoint(_addr: usize) -> bool {
+    false
+}
+
+pub fn breakpoint(_insn: &Insn) -> &'static [u8] {
+    unreachable!()
+}
+
+pub fn slot_addr(_slot: usize) -> usize {
+    unreachable!()
+}
+
+pub fn slot_bytes(_insn: &Insn) -> [u8; 8] {
+    unreachable!()
+}
+
+pub fn pc(_tf: &TrapFrame) -> usize {
+    unreachable!()
+}
+
+pub fn set_pc(_tf: &mut TrapFrame, _pc: usize) {
+    unreachable!()
+}
+
+pub fn mask_irqs(_tf: &mut TrapFrame) -> bool {
+    unreachable!()
+}
+
+pub fn restore_irqs(_tf: &mut TrapFrame, _enabled: bool) {
+    unreachable!()
+}
+
+pub fn flush_icache() {}
+
+pub fn simulate(_insn: &Insn, _addr: usize, _tf: &mut TrapFrame) {
+    unreachable!()
+}
@@ -0,0 +1,11 @@
+//! Architecture-specific decoding, simulation and stepping of instructions.
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        mod riscv;
+        pub use self::riscv::*;
+    } else {
+        mod dummy;
+        pub use self::dummy::*;
+    }
+}
@@ -0,0 +1,233 @@
+//! RISC-V: probes are `ebreak`s, compressed for compressed instructions so
+//! the next one is left alone. There is no single-step, so the
+//! instructions that depend on the pc, the jumps, branches and `auipc`,
+//! are simulated, and the others are executed out of line, from a slot
+//! that ends with another `ebreak` to trap back.
+
+use axhal::arch::{TrapFrame, SR_SPIE};
+use crate::{Insn, MAX_KPROBES};
+
+const EBREAK: u32 = 0x0010_0073;
+const C_EBREAK: u16 = 0x9002;
+
+const OP_BRANCH: u32 = 0x63;
+const OP_JALR: u32 = 0x67;
+const OP_JAL: u32 = 0x6f;
+const OP_AUIPC: u32 = 0x17;
+const OP_SYSTEM: u32 = 0x73;
+const OP_AMO: u32 = 0x2f;
+
+/// Bytes of a slot: room for a 4-byte instruction, and the `ebreak`.
+const SLOT_SIZE: usize = 8;
+
+core::arch::global_asm!(
+    ".pushsection .text",
+    ".balign 4",
+    ".global kprobe_insn_slots",
+    "kprobe_insn_slots:",
+    ".rept {count}",
+    ".4byte {ebreak}",
+    ".4byte {ebreak}",
+    ".endr",
+    ".popsection",
+    count = const MAX_KPROBES,
+    ebreak = const EBREAK,
+);
+
+extern "C" {
+    fn kprobe_insn_slots();
+}
+
+/// Reads the instruction at `addr`, and tells how to step it, unless it
+/// can not be probed: `ebreak`, `ecall`, the returns from traps and the
+/// CSR accesses, and LR/SC, whose reservation a trap between them breaks.
+pub fn decode(addr: usize) -> Option<Insn> {
+    let low = unsafe { (addr as *const u16).read() };
+    if low & 0b11 != 0b11 {
+        return decode_compressed(low);
+    }
+    let high = unsafe { (addr as *const u16).add(1).read() };
+    let bits = (high as u32) << 16 | low as u32;
+    let simulated = match bits & 0x7f {
+        OP_BRANCH | OP_JALR | OP_JAL | OP_AUIPC => true,
+        OP_SYSTEM => return None,
+        OP_AMO if matches!(bits >> 27, 0b00010 | 0b00011) => return None,
+        _ => false,
+    };
+    Some(Insn { bits, len: 4, simulated })
+}
+
+fn decode_compressed(bits: u16) -> Option<Insn> {
+    let funct3 = bits >> 13;
+    let simulated = match (bits & 0b11, funct3) {
+        // c.j, c.beqz, c.bnez
+        (0b01, 0b101 | 0b110 | 0b111) => true,
+        (0b10, 0b100) => {
+            let (rs1, rs2) = ((bits >> 7) & 0x1f, (bits >> 2) & 0x1f);
+            match (bits >> 12 & 1, rs1, rs2) {
+                (1, 0, 0) => return None, // c.ebreak
+                // c.jr, c.jalr
+                (_, rs1, 0) if rs1 != 0 => true,
+                _ => false,
+            }
+        },
+        _ => false,
+    };
+    Some(Insn { bits: bits as u32, len: 2, simulated })
+}
+
+/// Whether there is a breakpoint at `addr`.
+pub fn is_breakpoint(addr: usize) -> bool {
+    let low = unsafe { (addr as *const u16).read() };
+    low == C_EBREAK
+        || (low == EBREAK as u16 && unsafe { (addr as *const u16).add(1).read() } == (EBREAK >> 16) as u16)
+}
+
+/// The breakpoint to put over `insn`, as long as it.
+pub fn breakpoint(insn: &Insn) -> &'static [u8] {
+    static EBREAK_BYTES: [u8; 4] = EBREAK.to_le_bytes();
+    static C_EBREAK_BYTES: [u8; 2] = C_EBREAK.to_le_bytes();
+    match insn.len {
+        2 => &C_EBREAK_BYTES,
+        _ => &EBREAK_BYTES,
+    }
+}
+
+pub fn slot_addr(slot: usize) -> usize {
+    kprobe_insn_slots as usize + slot * SLOT_SIZE
+}
+
+/// The slot to step `insn` from: it, then `ebreak`.
+pub fn slot_bytes(insn: &Insn) -> [u8; SLOT_SIZE] {
+    let mut bytes = [0; SLOT_SIZE];
+    bytes[..insn.len].copy_from_slice(&insn.bits.to_le_bytes()[..insn.len]);
+    bytes[insn.len..insn.len + 4].copy_from_slice(&EBREAK.to_le_bytes());
+    bytes
+}
+
+pub fn pc(tf: &TrapFrame) -> usize {
+    tf.sepc
+}
+
+pub fn set_pc(tf: &mut TrapFrame, pc: usize) {
+    tf.sepc = pc;
+}
+
+/// Keeps interrupts off on the return to the slot, so the step is not
+/// interrupted. Returns whether they were on.
+pub fn mask_irqs(tf: &mut TrapFrame) -> bool {
+    let enabled = tf.sstatus & SR_SPIE != 0;
+    tf.sstatus &= !SR_SPIE;
+    enabled
+}
+
+pub fn restore_irqs(tf: &mut TrapFrame, enabled: bool) {
+    if enabled {
+        tf.sstatus |= SR_SPIE;
+    }
+}
+
+pub fn flush_icache() {
+    axhal::arch::flush_icache_all();
+}
+
+/// The registers x1 to x31, which `GeneralRegisters` lists in order.
+fn regs(tf: &mut TrapFrame) -> &mut [usize; 31] {
+    unsafe { &mut *(&mut tf.regs as *mut _ as *mut [usize; 31]) }
+}
+
+fn reg(tf: &mut TrapFrame, r: u32) -> usize {
+    match r {
+        0 => 0,
+        r => regs(tf)[r as usize - 1],
+    }
+}
+
+fn set_reg(tf: &mut TrapFrame, r: u32, val: usize) {
+    if r != 0 {
+        regs(tf)[r as usize - 1] = val;
+    }
+}
+
+/// Bits `hi..=lo` of `bits`, shifted to `to`.
+fn field(bits: u32, hi: u32, lo: u32, to: u32) -> u32 {
+    (bits >> lo & ((1 << (hi - lo + 1)) - 1)) << to
+}
+
+/// `imm` of `width` bits, sign-extended.
+fn sext(imm: u32, width: u32) -> usize {
+    ((imm << (32 - width)) as i32 >> (32 - width)) as isize as usize
+}
+
+/// Simulates the instruction `insn`, of those [`decode`] says are, as if
+/// it had been executed at `addr`.
+pub fn simulate(insn: &Insn, addr: usize, tf: &mut TrapFrame) {
+    let bits = insn.bits;
+    let next = addr + insn.len;
+    let target = if insn.len == 2 {
+        simulate_compressed(bits, addr, tf)
+    } else {
+        let rd = field(bits, 11, 7, 0);
+        let rs1 = field(bits, 19, 15, 0);
+        let rs2 = field(bits, 24, 20, 0);
+        match bits & 0x7f {
+            OP_JAL => {
+                let imm = field(bits, 31, 31, 20) | field(bits, 30, 21, 1)
+                    | field(bits, 20, 20, 11) | field(bits, 19, 12, 12);
+                set_reg(tf, rd, next);
+                addr.wrapping_add(sext(imm, 21))
+            },
+            OP_JALR => {
+                let target = reg(tf, rs1).wrapping_add(sext(bits >> 20, 12)) & !1;
+                set_reg(tf, rd, next);
+                target
+            },
+            OP_AUIPC => {
+                set_reg(tf, rd, addr.wrapping_add(sext(bits & 0xffff_f000, 32)));
+                next
+            },
+            _ => {
+                let imm = field(bits, 31, 31, 12) | field(bits, 30, 25, 5)
+                    | field(bits, 11, 8, 1) | field(bits, 7, 7, 11);
+                let (a, b) = (reg(tf, rs1), reg(tf, rs2));
+                let taken = match field(bits, 14, 12, 0) {
+                    0b000 => a == b,
+                    0b001 => a != b,
+                    0b100 => (a as isize) < (b as isize),
+                    0b101 => (a as isize) >= (b as isize),
+                    0b110 => a < b,
+                    _ => a >= b,
+                };
+                if taken { addr.wrapping_add(sext(imm, 13)) } else { next }
+            },
+        }
+    };
+    set_pc(tf, target);
+}
+
+fn simulate_compressed(bits: u32, addr: usize, tf: &mut TrapFrame) -> usize {
+    let next = addr + 2;
+    match (bits & 0b11, bits >> 13) {
+        (0b01, 0b101) => {
+            let imm = field(bits, 12, 12, 11) | field(bits, 11, 11, 4) | field(bits, 10, 9, 8)
+                | field(bits, 8, 8, 10) | field(bits, 7, 7, 6) | field(bits, 6, 6, 7)
+                | field(bits, 5, 3, 1) | field(bits, 2, 2, 5);
+            addr.wrapping_add(sext(imm, 12))
+        },
+        (0b01, funct3) => {
+            let imm = field(bits, 12, 12, 8) | field(bits, 11, 10, 3) | field(bits, 6, 5, 6)
+                | field(bits, 4, 3, 1) | field(bits, 2, 2, 5);
+            let rs1 = 8 + field(bits, 9, 7, 0);
+            let zero = reg(tf, rs1) == 0;
+            if zero == (funct3 == 0b110) { addr.wrapping_add(sext(imm, 9)) } else { next }
+        },
+        _ => {
+            let target = reg(tf, field(bits, 11, 7, 0)) & !1;
+            // c.jalr links to ra.
+            if bits >> 12 & 1 != 0 {
+                set_reg(tf, 1, next);
+            }
+            target
+        },
+    }
+}
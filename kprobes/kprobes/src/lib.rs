@@ -0,0 +1,238 @@
+//! Kernel probes: handlers called when the kernel executes an address.
+//!
+//! [`register_kprobe`] replaces the instruction at the address with a
+//! breakpoint. When it traps, the pre-handler is called with the trap
+//! frame; then the instruction is either simulated, or stepped out of line
+//! from a slot of its own, with interrupts off; then the post-handler is
+//! called, and execution goes on after the instruction. A probe hit inside
+//! a handler is stepped without calling its handlers, and counted as
+//! missed.
+//!
+//! The instruction is stepped at the address of its slot, so a fault in
+//! it is not fixed up as at the original address: instructions that
+//! access user memory must not be probed.
+
+#![no_std]
+#![feature(asm_const)]
+
+#[macro_use]
+extern crate log;
+
+mod arch;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use axconfig::SMP;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axhal::mem::VirtAddr;
+use spinbase::SpinNoIrq;
+
+/// Probes registered at most.
+pub const MAX_KPROBES: usize = 64;
+
+/// Called before the probed instruction. Returns true if it has changed
+/// the pc of `tf`, to go on there without executing the instruction.
+pub type KprobePreHandler = fn(addr: usize, tf: &mut TrapFrame) -> bool;
+/// Called after the probed instruction.
+pub type KprobePostHandler = fn(addr: usize, tf: &mut TrapFrame);
+
+/// An instruction, as [`arch::decode`] reads it.
+#[derive(Clone, Copy)]
+pub(crate) struct Insn {
+    bits: u32,
+    /// In bytes.
+    len: usize,
+    /// Simulated rather than stepped out of line.
+    simulated: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Kprobe {
+    addr: usize,
+    insn: Insn,
+    slot: usize,
+    pre: Option<KprobePreHandler>,
+    post: Option<KprobePostHandler>,
+}
+
+impl Kprobe {
+    /// The address the step out of line traps back at.
+    fn slot_end(&self) -> usize {
+        arch::slot_addr(self.slot) + self.insn.len
+    }
+}
+
+/// A probe this CPU is handling.
+#[derive(Clone, Copy)]
+struct Active {
+    probe: Kprobe,
+    /// Hit inside a handler, so its handlers are not called.
+    reentered: bool,
+    /// Stepping out of line, with interrupts masked: whether they were on.
+    stepping: Option<bool>,
+}
+
+/// The probes this CPU is handling: one, and the one it was handling when
+/// that one was hit in a handler.
+struct KprobeCtl {
+    cur: Option<Active>,
+    prev: Option<Active>,
+}
+
+impl KprobeCtl {
+    const fn new() -> Self {
+        Self { cur: None, prev: None }
+    }
+
+    fn has(&self, addr: usize) -> bool {
+        [self.cur, self.prev].iter().flatten().any(|a| a.probe.addr == addr)
+    }
+}
+
+static KPROBES: SpinNoIrq<[Option<Kprobe>; MAX_KPROBES]> = SpinNoIrq::new([None; MAX_KPROBES]);
+/// Serializes patching the text.
+static TEXT_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+#[allow(clippy::declare_interior_mutable_const)]
+const CTL_INIT: SpinNoIrq<KprobeCtl> = SpinNoIrq::new(KprobeCtl::new());
+static KPROBE_CTL: [SpinNoIrq<KprobeCtl>; SMP] = [CTL_INIT; SMP];
+static NMISSED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    fn _stext();
+    fn _etext();
+}
+
+fn patch(addr: usize, bytes: &[u8]) {
+    let _guard = TEXT_LOCK.lock();
+    unsafe {
+        page_table::paging::patch_kernel_text(VirtAddr::from(addr), bytes)
+            .expect("kprobes: kernel text not mapped");
+    }
+    arch::flush_icache();
+}
+
+/// Puts a probe at `addr` of the kernel text. Fails with EINVAL if the
+/// address is not in the text, EEXIST if it is probed already, ENOSPC if
+/// [`MAX_KPROBES`] are, and EOPNOTSUPP if the instruction there can not
+/// be probed.
+pub fn register_kprobe(
+    addr: usize,
+    pre: Option<KprobePreHandler>,
+    post: Option<KprobePostHandler>,
+) -> LinuxResult {
+    if !(_stext as usize.._etext as usize).contains(&addr) || addr % 2 != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let probe = {
+        let mut probes = KPROBES.lock();
+        if probes.iter().flatten().any(|p| p.addr == addr) {
+            return Err(LinuxError::EEXIST);
+        }
+        let insn = arch::decode(addr).ok_or(LinuxError::EOPNOTSUPP)?;
+        let slot = probes.iter().position(Option::is_none).ok_or(LinuxError::ENOSPC)?;
+        let probe = Kprobe { addr, insn, slot, pre, post };
+        probes[slot] = Some(probe);
+        probe
+    };
+    if !probe.insn.simulated {
+        patch(arch::slot_addr(probe.slot), &arch::slot_bytes(&probe.insn));
+    }
+    patch(addr, arch::breakpoint(&probe.insn));
+    info!("kprobes: registered at {:#x}", addr);
+    Ok(())
+}
+
+/// Removes the probe at `addr`, once no CPU is handling it. Fails with
+/// ENOENT if there is none.
+pub fn unregister_kprobe(addr: usize) -> LinuxResult {
+    let probe = KPROBES.lock()
+        .iter()
+        .flatten()
+        .find(|p| p.addr == addr)
+        .copied()
+        .ok_or(LinuxError::ENOENT)?;
+    patch(addr, &probe.insn.bits.to_le_bytes()[..probe.insn.len]);
+    KPROBES.lock()[probe.slot] = None;
+    // Its slot is free only once the CPUs that hit it are done with it.
+    for ctl in KPROBE_CTL.iter() {
+        while ctl.lock().has(addr) {
+            core::hint::spin_loop();
+        }
+    }
+    info!("kprobes: unregistered at {:#x}", addr);
+    Ok(())
+}
+
+/// Probe hits whose handlers were not called, as they were inside one.
+pub fn nmissed() -> usize {
+    NMISSED.load(Ordering::Relaxed)
+}
+
+/// Hands the probe this CPU is done with back for the one before it.
+fn finish(ctl: &SpinNoIrq<KprobeCtl>) {
+    let mut ctl = ctl.lock();
+    ctl.cur = ctl.prev.take();
+}
+
+/// Handles a breakpoint trap, if it is one of a probe. Returns false if it
+/// is not.
+pub fn handle_breakpoint(tf: &mut TrapFrame) -> bool {
+    let ctl = &KPROBE_CTL[axhal::cpu::_this_cpu_id()];
+    let addr = arch::pc(tf);
+
+    // Back from stepping out of line?
+    let done = {
+        let ctl = ctl.lock();
+        ctl.cur.filter(|a| a.stepping.is_some() && a.probe.slot_end() == addr)
+    };
+    if let Some(active) = done {
+        let probe = active.probe;
+        arch::restore_irqs(tf, active.stepping.unwrap());
+        arch::set_pc(tf, probe.addr + probe.insn.len);
+        finish(ctl);
+        if let (false, Some(post)) = (active.reentered, probe.post) {
+            post(probe.addr, tf);
+        }
+        return true;
+    }
+
+    let active = {
+        let probes = KPROBES.lock();
+        let Some(probe) = probes.iter().flatten().find(|p| p.addr == addr).copied() else {
+            // Unregistered since it trapped: execute what is there now.
+            return !arch::is_breakpoint(addr);
+        };
+        let mut ctl = ctl.lock();
+        let reentered = ctl.cur.is_some();
+        if reentered {
+            if ctl.prev.is_some() {
+                panic!("kprobes: recursion too deep at {:#x}", addr);
+            }
+            ctl.prev = ctl.cur.take();
+            NMISSED.fetch_add(1, Ordering::Relaxed);
+        }
+        let active = Active { probe, reentered, stepping: None };
+        ctl.cur = Some(active);
+        active
+    };
+    let probe = active.probe;
+
+    if let (false, Some(pre)) = (active.reentered, probe.pre) {
+        if pre(addr, tf) {
+            finish(ctl);
+            return true;
+        }
+    }
+    if probe.insn.simulated {
+        arch::simulate(&probe.insn, addr, tf);
+        finish(ctl);
+        if let (false, Some(post)) = (active.reentered, probe.post) {
+            post(addr, tf);
+        }
+    } else {
+        let enabled = arch::mask_irqs(tf);
+        ctl.lock().cur.as_mut().unwrap().stepping = Some(enabled);
+        arch::set_pc(tf, arch::slot_addr(probe.slot));
+    }
+    true
+}
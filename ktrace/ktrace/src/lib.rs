@@ -0,0 +1,275 @@
+//! Tracing of kernel events, in the manner of ftrace.
+//!
+//! An event is recorded with [`trace_event!`], or [`trace_function!`] for
+//! the entry of a function, into the ring buffer of the CPU it happens on,
+//! with the time, the task and up to [`MAX_ARGS`] numbers. Recording takes
+//! no lock, so it may happen anywhere, interrupt handlers included: a
+//! writer reserves its entry with an atomic increment and publishes it with
+//! its sequence number, and readers drop the entries overwritten under
+//! them. When a buffer is full, the newest events overwrite the oldest.
+//!
+//! Tracing is off until [`tracing_on`] turns it on, and costs a load and a
+//! branch per event meanwhile. [`init`] hooks the scheduler tracepoints of
+//! the run queue; the block layer records its own events.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use axconfig::SMP;
+use taskctx::{TaskState, Tid};
+
+/// Entries of the buffer of each CPU.
+pub const TRACE_BUF_ENTRIES: usize = 2048;
+/// Numbers recorded with an event at most.
+pub const MAX_ARGS: usize = 4;
+
+/// The sequence number of an entry being written.
+const WRITING: u64 = u64::MAX;
+
+static TRACING_ON: AtomicBool = AtomicBool::new(false);
+
+/// What an event is: its name and those of its numbers, in order.
+pub struct TraceEvent {
+    pub name: &'static str,
+    pub fields: &'static [&'static str],
+}
+
+impl TraceEvent {
+    pub const fn new(name: &'static str, fields: &'static [&'static str]) -> Self {
+        Self { name, fields }
+    }
+}
+
+struct Entry {
+    /// The position of the entry in the buffer plus 1, or [`WRITING`]; 0 if
+    /// never written.
+    seq: AtomicU64,
+    time: AtomicU64,
+    tid: AtomicUsize,
+    /// `&'static TraceEvent`.
+    event: AtomicUsize,
+    args: [AtomicU64; MAX_ARGS],
+}
+
+impl Entry {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Self {
+        seq: AtomicU64::new(0),
+        time: AtomicU64::new(0),
+        tid: AtomicUsize::new(0),
+        event: AtomicUsize::new(0),
+        args: [Self::ZERO; MAX_ARGS],
+    };
+}
+
+struct CpuBuffer {
+    entries: [Entry; TRACE_BUF_ENTRIES],
+    /// Position of the next entry.
+    head: AtomicU64,
+    /// Entries before it are dropped by [`reset`].
+    start: AtomicU64,
+}
+
+impl CpuBuffer {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Self {
+        entries: [Entry::EMPTY; TRACE_BUF_ENTRIES],
+        head: AtomicU64::new(0),
+        start: AtomicU64::new(0),
+    };
+
+    fn write(&self, event: &'static TraceEvent, args: &[u64]) {
+        let pos = self.head.fetch_add(1, Ordering::Relaxed);
+        let entry = &self.entries[pos as usize % TRACE_BUF_ENTRIES];
+        entry.seq.store(WRITING, Ordering::Relaxed);
+        fence(Ordering::Release);
+        entry.time.store(axhal::time::current_time_nanos(), Ordering::Relaxed);
+        entry.tid.store(current_tid(), Ordering::Relaxed);
+        entry.event.store(event as *const _ as usize, Ordering::Relaxed);
+        for (i, arg) in entry.args.iter().enumerate() {
+            arg.store(args.get(i).copied().unwrap_or(0), Ordering::Relaxed);
+        }
+        entry.seq.store(pos + 1, Ordering::Release);
+    }
+
+    /// The entry at `pos`, unless it has been overwritten or is still
+    /// being written.
+    fn read(&self, cpu: usize, pos: u64) -> Option<TraceRecord> {
+        let entry = &self.entries[pos as usize % TRACE_BUF_ENTRIES];
+        if entry.seq.load(Ordering::Acquire) != pos + 1 {
+            return None;
+        }
+        let event = entry.event.load(Ordering::Relaxed);
+        let record = TraceRecord {
+            cpu,
+            seq: pos,
+            time: entry.time.load(Ordering::Relaxed),
+            tid: entry.tid.load(Ordering::Relaxed),
+            event: unsafe { &*(event as *const TraceEvent) },
+            args: core::array::from_fn(|i| entry.args[i].load(Ordering::Relaxed)),
+        };
+        fence(Ordering::Acquire);
+        (entry.seq.load(Ordering::Relaxed) == pos + 1).then_some(record)
+    }
+
+    /// Position of the oldest entry still in the buffer.
+    fn first(&self) -> u64 {
+        let head = self.head.load(Ordering::Acquire);
+        head.saturating_sub(TRACE_BUF_ENTRIES as u64)
+            .max(self.start.load(Ordering::Relaxed))
+    }
+}
+
+static TRACE_BUFS: [CpuBuffer; SMP] = [CpuBuffer::EMPTY; SMP];
+
+fn current_tid() -> Tid {
+    taskctx::try_current_ctx().map_or(0, |ctx| ctx.tid())
+}
+
+/// An event read back from a buffer.
+#[derive(Clone, Copy)]
+pub struct TraceRecord {
+    pub cpu: usize,
+    /// Its position in the buffer of its CPU.
+    pub seq: u64,
+    /// The time since boot it happened at, in nanoseconds.
+    pub time: u64,
+    /// The task it happened in, 0 before there is one.
+    pub tid: Tid,
+    pub event: &'static TraceEvent,
+    pub args: [u64; MAX_ARGS],
+}
+
+/// The line of the trace file: `tid [cpu] secs.usecs: name: field=value...`.
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>7} [{:03}] {:>5}.{:06}: {}:",
+            self.tid,
+            self.cpu,
+            self.time / 1_000_000_000,
+            self.time % 1_000_000_000 / 1000,
+            self.event.name,
+        )?;
+        for (field, arg) in self.event.fields.iter().zip(self.args.iter()) {
+            write!(f, " {}={}", field, arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether events are recorded.
+#[inline(always)]
+pub fn is_tracing_on() -> bool {
+    TRACING_ON.load(Ordering::Relaxed)
+}
+
+/// Starts recording events.
+pub fn tracing_on() {
+    TRACING_ON.store(true, Ordering::Relaxed);
+}
+
+/// Stops recording events. Those recorded stay to be read.
+pub fn tracing_off() {
+    TRACING_ON.store(false, Ordering::Relaxed);
+}
+
+/// Records `event` with `args` on this CPU. Use [`trace_event!`], which
+/// checks whether tracing is on first.
+pub fn record(event: &'static TraceEvent, args: &[u64]) {
+    // Preempted here, the task writes to the buffer of the CPU it left,
+    // which is safe, as any CPU may write to any buffer.
+    let cpu = axhal::cpu::_this_cpu_id();
+    TRACE_BUFS[cpu].write(event, args);
+}
+
+/// The events of `cpu` still in its buffer, oldest first.
+pub fn read_cpu(cpu: usize) -> Vec<TraceRecord> {
+    let buf = &TRACE_BUFS[cpu];
+    let head = buf.head.load(Ordering::Acquire);
+    (buf.first()..head).filter_map(|pos| buf.read(cpu, pos)).collect()
+}
+
+/// The events of all CPUs, in the order they happened.
+pub fn read_all() -> Vec<TraceRecord> {
+    let mut records: Vec<_> = (0..SMP).flat_map(read_cpu).collect();
+    records.sort_by_key(|r| r.time);
+    records
+}
+
+/// Events of `cpu` that were overwritten before being dropped by [`reset`].
+pub fn overrun(cpu: usize) -> u64 {
+    let buf = &TRACE_BUFS[cpu];
+    let head = buf.head.load(Ordering::Acquire);
+    head.saturating_sub(TRACE_BUF_ENTRIES as u64)
+        .saturating_sub(buf.start.load(Ordering::Relaxed))
+}
+
+/// Drops the events recorded so far.
+pub fn reset() {
+    for buf in TRACE_BUFS.iter() {
+        buf.start.store(buf.head.load(Ordering::Acquire), Ordering::Relaxed);
+    }
+}
+
+/// Records an event, if tracing is on, with up to [`MAX_ARGS`] named
+/// numbers:
+///
+/// ```ignore
+/// trace_event!(block_rq_issue, sector = req.start, nr = req.num_blocks);
+/// ```
+#[macro_export]
+macro_rules! trace_event {
+    ($name:ident $(, $field:ident = $value:expr)* $(,)?) => {{
+        static EVENT: $crate::TraceEvent =
+            $crate::TraceEvent::new(stringify!($name), &[$(stringify!($field)),*]);
+        if $crate::is_tracing_on() {
+            $crate::record(&EVENT, &[$(($value) as u64),*]);
+        }
+    }};
+}
+
+/// Records the entry of the function it is put at the start of, named by
+/// its module and line.
+#[macro_export]
+macro_rules! trace_function {
+    () => {{
+        static EVENT: $crate::TraceEvent =
+            $crate::TraceEvent::new(concat!(module_path!(), ":", line!()), &[]);
+        if $crate::is_tracing_on() {
+            $crate::record(&EVENT, &[]);
+        }
+    }};
+}
+
+fn probe_sched_switch(_cpu: usize, prev: Tid, prev_state: TaskState, next: Tid) {
+    trace_event!(sched_switch, prev = prev, prev_state = prev_state as u8, next = next);
+}
+
+fn probe_sched_wakeup(tid: Tid, target_cpu: usize) {
+    trace_event!(sched_wakeup, tid = tid, target_cpu = target_cpu);
+}
+
+fn probe_sched_migrate(tid: Tid, orig_cpu: usize, dest_cpu: usize) {
+    trace_event!(sched_migrate, tid = tid, orig_cpu = orig_cpu, dest_cpu = dest_cpu);
+}
+
+/// Hooks the scheduler tracepoints. Tracing stays off.
+pub fn init() {
+    let ok = run_queue::register_sched_switch(probe_sched_switch)
+        & run_queue::register_sched_wakeup(probe_sched_wakeup)
+        & run_queue::register_sched_migrate(probe_sched_migrate);
+    if !ok {
+        warn!("ktrace: scheduler tracepoints taken");
+    }
+    info!("ktrace: {} entries per CPU", TRACE_BUF_ENTRIES);
+}
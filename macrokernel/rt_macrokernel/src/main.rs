@@ -69,6 +69,8 @@ fn show_logo() {
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
     error!("{}", info);
+    backtrace::print_backtrace();
+    kdump::crash_dump(info);
     axhal::misc::terminate();
     #[allow(unreachable_code)]
     arch_boot::panic(info)
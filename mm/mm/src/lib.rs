@@ -14,6 +14,7 @@
 //!
 //! # Core Components
 //! - [`MmStruct`]: Main memory management structure for a process
+//! - [`MmStructRef`]: An address space shared by threads, under its mmap lock
 //! - [`VmAreaStruct`]: Virtual memory area descriptor
 //! 
 
@@ -24,9 +25,12 @@
 extern crate log;
 extern crate alloc;
 
+mod mapped;
+
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use core::cell::OnceCell;
+use core::cmp::min;
 use axfile::fops::File;
 use page_table::paging::pgd_alloc;
 use page_table::paging::MappingFlags;
@@ -37,12 +41,45 @@ use axtype::PAGE_SIZE;
 use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering;
 use spinbase::SpinNoIrq;
-use mutex::Mutex;
+use mutex::{Mutex, RwSem};
+
+pub use mapped::MappedPages;
 
 pub type FileRef = Arc<Mutex<File>>;
 
+/// An address space, shared by the threads of a process that were cloned
+/// with CLONE_VM. The lock is the mmap_lock of Linux: page faults take it
+/// for reading, so the threads fault pages in at once, and what changes
+/// the areas, as mmap, munmap, mprotect or brk, for writing.
+///
+/// It sleeps, so it can't be taken in an interrupt or under a spin lock.
+/// Nor may user memory be touched while holding it, as a fault would take
+/// it again.
+pub type MmStructRef = Arc<RwSem<MmStruct>>;
+
 static MM_UNIQUE_ID: AtomicUsize = AtomicUsize::new(1);
 
+/// Bits of the coredump filter, the kinds of areas that a core dump has
+/// the content of.
+pub const MMF_DUMP_ANON_PRIVATE: usize = 1 << 0;
+pub const MMF_DUMP_ANON_SHARED: usize = 1 << 1;
+pub const MMF_DUMP_MAPPED_PRIVATE: usize = 1 << 2;
+pub const MMF_DUMP_MAPPED_SHARED: usize = 1 << 3;
+pub const MMF_DUMP_ELF_HEADERS: usize = 1 << 4;
+pub const MMF_DUMP_HUGETLB_PRIVATE: usize = 1 << 5;
+pub const MMF_DUMP_HUGETLB_SHARED: usize = 1 << 6;
+pub const MMF_DUMP_FILTER_MASK: usize = 0x1ff;
+/// Anonymous areas, the ELF headers, and private huge pages.
+pub const MMF_DUMP_FILTER_DEFAULT: usize = 0x33;
+
+/// The coredump filter of a new address space, see `coredump_filter=`.
+static DEFAULT_COREDUMP_FILTER: AtomicUsize = AtomicUsize::new(MMF_DUMP_FILTER_DEFAULT);
+
+/// Sets the coredump filter that new address spaces start with.
+pub fn set_default_coredump_filter(filter: usize) {
+    DEFAULT_COREDUMP_FILTER.store(filter & MMF_DUMP_FILTER_MASK, Ordering::Relaxed);
+}
+
 /*
  * vm_flags in vm_area_struct, see mm_types.h.
  * When changing, update also include/trace/events/mmflags.h
@@ -70,8 +107,12 @@ pub const VM_MAYEXEC : usize = 0x00000040;
 pub const VM_MAYSHARE: usize = 0x00000080;
 /// Stack segment that grows downward
 pub const VM_GROWSDOWN: usize = 0x00000100;
+/// Page-ranges managed without "struct page", just pure PFN
+pub const VM_PFNMAP: usize = 0x00000400;
 /// Pages are locked in memory
 pub const VM_LOCKED: usize = 0x00002000;
+/// Memory mapped I/O or similar
+pub const VM_IO: usize = 0x00004000;
 /// Synchronous page faults
 pub const VM_SYNC: usize = 0x00800000;
 
@@ -116,10 +157,24 @@ pub struct MmStruct {
     brk: usize,
 
     // Todo: temprarily record mapped (va, pa)
-    pub mapped: BTreeMap<usize, usize>,
+    /// Locked by shards of its own, for the faults that fill it under the
+    /// read lock.
+    pub mapped: MappedPages,
+
+    /// Regions of pages the kernel owns and shares, as the vDSO, mapped
+    /// at once rather than faulted in: (pa, len, flags) by va.
+    special_mapped: BTreeMap<usize, (usize, usize, MappingFlags)>,
 
     /// Pages that have PG_mlocked set
     pub locked_vm: usize,
+
+    /// Range of the argument strings on the initial stack
+    pub arg_start: usize,
+    pub arg_end: usize,
+
+    /// MMF_DUMP_* bits of the areas a core dump has the content of. It is
+    /// kept by fork and execve, as in Linux.
+    pub coredump_filter: usize,
 }
 
 impl MmStruct {
@@ -132,8 +187,12 @@ impl MmStruct {
             brk: 0,
 
             // Todo: temprarily record mapped (va, pa)
-            mapped: BTreeMap::new(),
+            mapped: MappedPages::new(),
+            special_mapped: BTreeMap::new(),
             locked_vm: 0,
+            arg_start: 0,
+            arg_end: 0,
+            coredump_filter: DEFAULT_COREDUMP_FILTER.load(Ordering::Relaxed),
         }
     }
 
@@ -149,10 +208,8 @@ impl MmStruct {
             vmas.insert(vma.vm_start, new_vma);
         }
 
-        let mut mapped = BTreeMap::<usize, usize>::new();
-        for (va, dva) in &self.mapped {
-            let va = *va;
-            let old_page = *dva;
+        let mapped = MappedPages::new();
+        self.mapped.for_each(|va, old_page| {
             debug!("mapped: {:#X} -> {:#X}", va, old_page);
             let new_page: usize = axalloc::global_allocator()
                 .alloc_pages(1, PAGE_SIZE) .unwrap();
@@ -171,6 +228,10 @@ impl MmStruct {
                 MappingFlags::EXECUTE | MappingFlags::USER;
             pgd.map_region(va.into(), pa.into(), PAGE_SIZE, flags, true).unwrap();
             mapped.insert(va, new_page);
+        });
+        // The same pages, not copies.
+        for (va, (pa, len, flags)) in &self.special_mapped {
+            pgd.map_region((*va).into(), (*pa).into(), *len, *flags, false).unwrap();
         }
         Self {
             id: MM_UNIQUE_ID.fetch_add(1, Ordering::SeqCst),
@@ -179,7 +240,11 @@ impl MmStruct {
             brk: self.brk,
 
             mapped,
+            special_mapped: self.special_mapped.clone(),
             locked_vm: self.locked_vm,
+            arg_start: self.arg_start,
+            arg_end: self.arg_end,
+            coredump_filter: self.coredump_filter,
         }
     }
 
@@ -209,6 +274,67 @@ impl MmStruct {
         self.brk = brk;
     }
 
+    /// Returns the size of the address space in bytes
+    pub fn total_vm(&self) -> usize {
+        self.vmas.values().map(|vma| vma.vm_end - vma.vm_start).sum()
+    }
+
+    /// Returns how many bytes of `[start, end)` are mapped already
+    pub fn mapped_in(&self, start: usize, end: usize) -> usize {
+        self.vmas.values()
+            .filter(|vma| vma.vm_start < end && start < vma.vm_end)
+            .map(|vma| vma.vm_end.min(end) - vma.vm_start.max(start))
+            .sum()
+    }
+
+    /// Copies user memory at `va` into `buf` through the kernel mappings of
+    /// its pages, so it works for any address space, not just the current
+    /// one. It stops at the first page not faulted in yet and returns how
+    /// many bytes were copied.
+    pub fn access_remote(&self, mut va: usize, buf: &mut [u8]) -> usize {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let offset = va & (PAGE_SIZE - 1);
+            let Some(page) = self.mapped.get(va - offset) else {
+                break;
+            };
+            let size = min(PAGE_SIZE - offset, buf.len() - copied);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    (page + offset) as *const u8,
+                    buf[copied..].as_mut_ptr(),
+                    size
+                );
+            }
+            copied += size;
+            va += size;
+        }
+        copied
+    }
+
+    /// Copies `buf` into user memory at `va`, the other way round of
+    /// [`access_remote`](Self::access_remote), which it stops as does.
+    pub fn write_remote(&self, mut va: usize, buf: &[u8]) -> usize {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let offset = va & (PAGE_SIZE - 1);
+            let Some(page) = self.mapped.get(va - offset) else {
+                break;
+            };
+            let size = min(PAGE_SIZE - offset, buf.len() - copied);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf[copied..].as_ptr(),
+                    (page + offset) as *mut u8,
+                    size
+                );
+            }
+            copied += size;
+            va += size;
+        }
+        copied
+    }
+
     /// Maps a virtual address region to a physical address with specified flags
     pub fn map_region(&self, va: usize, pa: usize, len: usize, _uflags: usize) -> PagingResult {
         let flags =
@@ -218,6 +344,17 @@ impl MmStruct {
             .map_region(va.into(), pa.into(), len, flags, true)
     }
 
+    /// Maps `len` bytes of pages the kernel owns at `pa` to `va`, with
+    /// `flags`, for good: they are never faulted in nor freed, and a fork
+    /// maps the same ones.
+    pub fn map_special(&mut self, va: usize, pa: usize, len: usize, flags: MappingFlags) -> PagingResult {
+        self.pgd
+            .lock()
+            .map_region(va.into(), pa.into(), len, flags, false)?;
+        self.special_mapped.insert(va, (pa, len, flags));
+        Ok(())
+    }
+
     /// Unmaps a region of virtual memory
     pub fn unmap_region(&self, va: usize, len: usize) -> PagingResult {
         self.pgd.lock().unmap_region(va.into(), len)
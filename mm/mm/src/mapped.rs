@@ -0,0 +1,72 @@
+//! Pages faulted in, sharded by range.
+
+use alloc::collections::BTreeMap;
+use spinbase::{SpinNoIrq, SpinNoIrqGuard};
+
+/// Shards of [`MappedPages`].
+const NR_SHARDS: usize = 16;
+/// Each shard holds the pages of 2M ranges, as one leaf page table maps.
+const SHARD_SHIFT: usize = 21;
+
+/// The pages of an address space that were faulted in, as (va, direct va
+/// of the page), sharded by range, each under a lock of its own, as the
+/// split page table locks of Linux: faults on unrelated ranges, which run
+/// at once under the read lock of the address space, don't serialize on
+/// one lock.
+pub struct MappedPages {
+    shards: [SpinNoIrq<BTreeMap<usize, usize>>; NR_SHARDS],
+}
+
+impl MappedPages {
+    pub fn new() -> Self {
+        Self {
+            shards: core::array::from_fn(|_| SpinNoIrq::new(BTreeMap::new())),
+        }
+    }
+
+    /// Locks the shard of `va`, for a fault to check for it and install it
+    /// as one step.
+    pub fn lock(&self, va: usize) -> SpinNoIrqGuard<BTreeMap<usize, usize>> {
+        self.shards[(va >> SHARD_SHIFT) % NR_SHARDS].lock()
+    }
+
+    pub fn get(&self, va: usize) -> Option<usize> {
+        self.lock(va).get(&va).copied()
+    }
+
+    pub fn insert(&self, va: usize, page: usize) {
+        self.lock(va).insert(va, page);
+    }
+
+    pub fn remove(&self, va: usize) -> Option<usize> {
+        self.lock(va).remove(&va)
+    }
+
+    /// Removes any page, to tear the address space down.
+    pub fn pop_first(&self) -> Option<(usize, usize)> {
+        self.shards.iter().find_map(|shard| shard.lock().pop_first())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` on each page, shard by shard.
+    pub fn for_each(&self, mut f: impl FnMut(usize, usize)) {
+        for shard in self.shards.iter() {
+            for (&va, &page) in shard.lock().iter() {
+                f(va, page);
+            }
+        }
+    }
+}
+
+impl Default for MappedPages {
+    fn default() -> Self {
+        Self::new()
+    }
+}
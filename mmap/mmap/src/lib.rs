@@ -29,24 +29,26 @@ use axerrno::{LinuxResult, LinuxError, linux_err};
 use axfile::fops::File;
 use axhal::arch::STACK_TOP;
 use axhal::mem::{phys_to_virt, virt_to_phys};
-#[cfg(target_arch = "riscv64")]
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 use axhal::arch::user_mode;
 use axio::SeekFrom;
 use signal::force_sig_fault;
 use core::ops::Bound;
 use memory_addr::{align_up_4k, align_down_4k, is_aligned_4k, PAGE_SHIFT, PAGE_SIZE_4K};
 pub use mm::FileRef;
-use mm::VmAreaStruct;
+use mm::{MmStruct, MmStructRef, VmAreaStruct};
+use axtype::{RLIMIT_AS, RLIMIT_STACK};
 use axhal::arch::TASK_SIZE;
 use mm::{VM_READ, VM_WRITE, VM_EXEC, VM_SHARED, VM_MAYSHARE};
 use mm::{VM_MAYREAD, VM_MAYWRITE, VM_MAYEXEC};
-use mm::{VM_GROWSDOWN, VM_LOCKED, VM_SYNC};
-#[cfg(target_arch = "riscv64")]
+use mm::{VM_GROWSDOWN, VM_LOCKED, VM_SYNC, VM_IO, VM_PFNMAP};
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 use axhal::arch::{EXC_INST_PAGE_FAULT, EXC_LOAD_PAGE_FAULT, EXC_STORE_PAGE_FAULT};
 // #[cfg(target_arch = "riscv64")]
 // use signal::force_sig_fault;
 use capability::Cap;
 use axhal::arch::flush_tlb;
+use page_table::paging::PagingError;
 
 /// enforced gap between the expanding stack and other mappings.
 const STACK_GUARD_GAP: usize = 256 << PAGE_SHIFT;
@@ -165,6 +167,7 @@ pub fn mmap(
         check_file_mode(flags, prot, f.clone())?;
         f
     };
+    drop(filetable);
     let va = _mmap(va, len, prot, flags, file, offset)?;
 
     if (flags & MAP_POPULATE) != 0 {
@@ -212,6 +215,21 @@ fn check_file_mode(flags: usize, prot: usize, file: Option<FileRef>) -> LinuxRes
 }
 
 pub fn _mmap(
+    va: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    file: Option<FileRef>,
+    offset: usize,
+) -> LinuxResult<usize> {
+    let mm = task::current().mm();
+    let mut locked_mm = mm.write();
+    do_mmap(&mut locked_mm, va, len, prot, flags, file, offset)
+}
+
+/// Maps `[va, va + len)` into `mm`, whose write lock is held.
+fn do_mmap(
+    mm: &mut MmStruct,
     mut va: usize,
     mut len: usize,
     prot: usize,
@@ -229,7 +247,7 @@ pub fn _mmap(
     }
 
     if (flags & MAP_FIXED) == 0 {
-        va = get_unmapped_vma(va, len);
+        va = get_unmapped_vma(mm, va, len);
         debug!("Get unmapped vma {:#X}", va);
     }
 
@@ -237,8 +255,10 @@ pub fn _mmap(
         return Err(LinuxError::ENOMEM);
     }
 
-    let mm = task::current().mm();
-    if let Some(mut overlap) = cut_overlap(va, len) {
+    if !may_expand_vm(mm, va, len) {
+        return Err(LinuxError::ENOMEM);
+    }
+    if let Some(mut overlap) = cut_overlap(mm, va, len) {
         debug!("find overlap {:#X}-{:#X}", overlap.vm_start, overlap.vm_end);
         assert!(
             overlap.vm_start <= va && va + len <= overlap.vm_end,
@@ -258,11 +278,11 @@ pub fn _mmap(
             let mut new = overlap.clone();
             new.vm_start = va + len;
             new.vm_pgoff += bias;
-            mm.lock().vmas.insert(va + len, new);
+            mm.vmas.insert(va + len, new);
         }
         if va > overlap.vm_start {
             overlap.vm_end = va;
-            mm.lock().vmas.insert(overlap.vm_start, overlap);
+            mm.vmas.insert(overlap.vm_start, overlap);
         }
     }
 
@@ -272,6 +292,15 @@ pub fn _mmap(
     if (flags & MAP_SHARED) != 0 {
         vm_flags |= VM_SHARED | VM_MAYSHARE;
     }
+    // A char device that provides its own pages (e.g. a framebuffer) is
+    // device memory, mapped as it is whether the mapping is shared or not.
+    if let Some(f) = file.as_ref() {
+        let f = f.lock();
+        let is_chrdev = f.get_attr().is_ok_and(|attr| attr.file_type().is_char_device());
+        if is_chrdev && f.get_node().and_then(|node| node.get_page(offset as u64)).is_ok() {
+            vm_flags |= VM_IO | VM_PFNMAP;
+        }
+    }
     debug!(
         "mmap region: {:#X} - {:#X}, vm_flags: {:#X}, prot {:#X}",
         va,
@@ -280,15 +309,23 @@ pub fn _mmap(
         prot
     );
     let vma = VmAreaStruct::new(va, va + len, offset >> PAGE_SHIFT, file, vm_flags);
-    mm.lock().vmas.insert(va, vma);
+    mm.vmas.insert(va, vma);
 
     if (flags & MAP_LOCKED) != 0 {
-        mm.lock().locked_vm = len >> PAGE_SHIFT;
+        mm.locked_vm = len >> PAGE_SHIFT;
     }
 
     Ok(va)
 }
 
+/// Whether mapping `[va, va + len)` keeps the address space within
+/// RLIMIT_AS. What is mapped there already gets replaced.
+fn may_expand_vm(mm: &MmStruct, va: usize, len: usize) -> bool {
+    let limit = task::current().rlimit(RLIMIT_AS);
+    let total = mm.total_vm() - mm.mapped_in(va, va + len) + len;
+    (total as u64) <= limit
+}
+
 /*
  * Combine the mmap "prot" argument into "vm_flags" used internally.
  */
@@ -323,23 +360,20 @@ fn calc_vm_flag_bits(flags: usize) -> usize {
     vm_flags
 }
 
-fn find_overlap(va: usize, len: usize) -> Option<usize> {
-    let mm = task::current().mm();
-    let locked_mm = mm.lock();
-    locked_mm.vmas.iter().find(|(_, vma)| {
+fn find_overlap(mm: &MmStruct, va: usize, len: usize) -> Option<usize> {
+    mm.vmas.iter().find(|(_, vma)| {
         in_vma(va, va + len, vma) || in_range(vma.vm_start, vma.vm_end, va, va + len)
     }).map(|(key, _)| {
         *key
     })
 }
 
-fn cut_overlap(va: usize, len: usize) -> Option<VmAreaStruct> {
+fn cut_overlap(mm: &mut MmStruct, va: usize, len: usize) -> Option<VmAreaStruct> {
     debug!("cut_overlap: va {:#X} len {:#X}", va, len);
 
-    if let Some(key) = find_overlap(va, len) {
+    if let Some(key) = find_overlap(mm, va, len) {
         warn!("### Removed!!!");
-        let current = task::current();
-        current.mm().lock().vmas.remove(&key)
+        mm.vmas.remove(&key)
     } else {
         None
     }
@@ -361,16 +395,14 @@ fn mmap_base() -> usize {
 }
 
 /// Find an unmapped virtual memory area of the requested size
-pub fn get_unmapped_vma(va: usize, len: usize) -> usize {
+pub fn get_unmapped_vma(mm: &MmStruct, va: usize, len: usize) -> usize {
     debug!("get_unmapped_vma va {:#x} len {:#x}", va, len);
-    if va != 0 && find_overlap(va, len).is_none() {
+    if va != 0 && find_overlap(mm, va, len).is_none() {
         return va;
     }
 
-    let mm = task::current().mm();
-    let locked_mm = mm.lock();
     let mut gap_end = mmap_base();
-    for (_, vma) in locked_mm.vmas.iter().rev() {
+    for (_, vma) in mm.vmas.iter().rev() {
         debug!(
             "get_unmapped_vma iterator: {:#X} {:#X} {:#X}",
             vma.vm_start, vma.vm_end, gap_end
@@ -395,49 +427,35 @@ pub fn get_unmapped_vma(va: usize, len: usize) -> usize {
     unimplemented!("NO available unmapped vma!");
 }
 
+// address not mapped to object
+const SEGV_MAPERR: usize = 1;
 // invalid permissions for mapped object
 const SEGV_ACCERR: usize = 2;
 
 /// Handle page faults by mapping pages on demand
+///
+/// It runs under the read lock of the address space, so the threads
+/// sharing it fault at once; it only takes the write lock to grow the
+/// stack. A page is installed under the lock of the shard of `mapped` for
+/// its range, and the page table is locked only to be written.
 pub fn faultin_page(
     va: usize, cause: usize, epc: usize, fixup: &mut usize,
 ) -> Result<usize, usize> {
     let va = align_down_4k(va);
     info!("--------- faultin_page... va {:#X} cause {}", va, cause);
     let mm = task::current().mm();
-    let mut locked_mm = mm.lock();
-    if locked_mm.mapped.get(&va).is_some() {
+    let mut locked_mm = mm.read();
+    if locked_mm.mapped.get(va).is_some() {
         warn!("============== find page {:#X} already exists!", va);
         return Ok(0);
     }
 
-    let cursor = locked_mm.vmas.upper_bound(Bound::Included(&va));
-    let mut vma = cursor.value().unwrap();
-    if va < vma.vm_start || va >= vma.vm_end {
-        let (_, next_vma) = cursor.peek_next().unwrap();
-        info!("{:#X} - {:#X}; {:#x} pgoff {:#x}",
-            next_vma.vm_start, next_vma.vm_end, next_vma.vm_flags, next_vma.vm_pgoff);
-
-        if (next_vma.vm_flags & VM_GROWSDOWN) != 0 {
-            // Todo: wrap these into a function 'expand_stack'
-            assert!(next_vma.vm_file.get().is_none());
-            assert_eq!(next_vma.vm_pgoff, 0);
-
-            // Check that both stack segments have the same anon_vma?
-            if (vma.vm_flags & VM_GROWSDOWN) == 0 {
-                if va - vma.vm_end < STACK_GUARD_GAP {
-                    error!("SEGV_ACCERR");
-                    let tid = task::current().tid();
-                    force_sig_fault(tid, task::SIGSEGV, SEGV_ACCERR, va);
-                    return Err(usize::MAX);
-                }
-            }
-
-            let stack = VmAreaStruct::new(va, next_vma.vm_start, 0, None, next_vma.vm_flags);
-            locked_mm.vmas.insert(va, stack);
-            vma = locked_mm.vmas.get(&va).unwrap();
-        }
+    if below_stack(&locked_mm, va) {
+        drop(locked_mm);
+        expand_stack(&mm, va)?;
+        locked_mm = mm.read();
     }
+    let vma = locked_mm.vmas.upper_bound(Bound::Included(&va)).value().unwrap();
     if va < vma.vm_start || va >= vma.vm_end {
         error!("va {:#X} in {:#X} - {:#X}", va, vma.vm_start, vma.vm_end);
         let tid = task::current().tid();
@@ -445,7 +463,7 @@ pub fn faultin_page(
         return Err(usize::MAX);
     }
 
-    #[cfg(target_arch = "riscv64")]
+    #[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
     {
         if access_error(cause, vma) {
             // tsk->thread.bad_cause = cause;
@@ -467,13 +485,33 @@ pub fn faultin_page(
         }
     }
 
+    if (vma.vm_flags & VM_PFNMAP) != 0 {
+        // Device memory is neither copied on fork nor freed on unmap,
+        // so it stays out of `mapped`.
+        let f = vma.vm_file.get().unwrap().clone();
+        let page = f.lock().get_node()
+            .and_then(|node| node.get_page(offset as u64))
+            .map_err(|_| VM_FAULT_SIGBUS)?;
+        let pa = virt_to_phys(page.into()).into();
+        set_pte(&locked_mm, va, pa);
+
+        return Ok(page);
+    }
+
     if (vma.vm_flags & VM_SHARED) != 0 {
         assert!(vma.vm_file.get().is_some());
         let f = vma.vm_file.get().unwrap().clone();
         let f = f.lock();
+        // Memory-backed files (e.g. /dev/shm) provide their own pages,
+        // so all mappings of the same file share them.
+        if let Ok(page) = f.get_node().and_then(|node| node.get_page(offset as u64)) {
+            let pa = virt_to_phys(page.into()).into();
+            set_pte(&locked_mm, va, pa);
+
+            return Ok(page);
+        }
         if let Some(pa) = f.shared_map.get(&offset) {
-            locked_mm.map_region(va, *pa, PAGE_SIZE_4K, 1)
-                .unwrap_or_else(|e| { panic!("{:?}", e) });
+            set_pte(&locked_mm, va, *pa);
 
             return Ok(phys_to_virt((*pa).into()).into());
         }
@@ -491,23 +529,109 @@ pub fn faultin_page(
 
     if vma.vm_file.get().is_some() {
         let f = vma.vm_file.get().unwrap().clone();
-        fill_cache(pa, PAGE_SIZE_4K, &mut f.lock(), offset);
+        let mut f = f.lock();
+        fill_cache(pa, PAGE_SIZE_4K, &mut f, offset);
         if (vma.vm_flags & VM_SHARED) != 0 {
-            f.lock().shared_map.insert(offset, pa);
+            // Another thread may have read it in meanwhile.
+            if let Some(&shared) = f.shared_map.get(&offset) {
+                axalloc::global_allocator().dealloc_pages(direct_va, 1);
+                set_pte(&locked_mm, va, shared);
+                return Ok(phys_to_virt(shared.into()).into());
+            }
+            f.shared_map.insert(offset, pa);
         }
     }
+    if (vma.vm_flags & VM_SHARED) != 0 {
+        set_pte(&locked_mm, va, pa);
+        return Ok(direct_va);
+    }
+
+    // Todo: temporarily record mapped va->pa(direct_va)
+    let mut mapped = locked_mm.mapped.lock(va);
+    if let Some(&page) = mapped.get(&va) {
+        // Another thread has faulted it in meanwhile.
+        axalloc::global_allocator().dealloc_pages(direct_va, 1);
+        return Ok(page);
+    }
     locked_mm.map_region(va, pa, PAGE_SIZE_4K, 1)
         .unwrap_or_else(|e| { panic!("{:?}", e) });
+    mapped.insert(va, direct_va);
+
+    Ok(direct_va)
+}
+
+/// Maps the page at `pa` to `va`, unless another thread that faulted on
+/// it too has already.
+fn set_pte(mm: &MmStruct, va: usize, pa: usize) {
+    match mm.map_region(va, pa, PAGE_SIZE_4K, 1) {
+        Ok(()) | Err(PagingError::AlreadyMapped) => {}
+        Err(e) => panic!("{:?}", e),
+    }
+}
+
+/// Whether `va` is in no area but right below a stack, which has to grow
+/// down to it.
+fn below_stack(mm: &MmStruct, va: usize) -> bool {
+    let cursor = mm.vmas.upper_bound(Bound::Included(&va));
+    if cursor.value().is_some_and(|vma| va < vma.vm_end) {
+        return false;
+    }
+    cursor.peek_next().is_some_and(|(_, next)| (next.vm_flags & VM_GROWSDOWN) != 0)
+}
+
+/// Grows the stack down to `va`. The areas change, so it takes the write
+/// lock of `mm`, and another thread may have grown it meanwhile.
+fn expand_stack(mm: &MmStructRef, va: usize) -> Result<(), usize> {
+    let mut locked_mm = mm.write();
+    if !below_stack(&locked_mm, va) {
+        return Ok(());
+    }
+    let cursor = locked_mm.vmas.upper_bound(Bound::Included(&va));
+    let (_, next_vma) = cursor.peek_next().unwrap();
+    info!("{:#X} - {:#X}; {:#x} pgoff {:#x}",
+        next_vma.vm_start, next_vma.vm_end, next_vma.vm_flags, next_vma.vm_pgoff);
+    assert!(next_vma.vm_file.get().is_none());
+    assert_eq!(next_vma.vm_pgoff, 0);
+
+    if !may_grow_stack(&locked_mm, va, next_vma) {
+        error!("stack {:#X} beyond RLIMIT_STACK", va);
+        let tid = task::current().tid();
+        force_sig_fault(tid, task::SIGSEGV, SEGV_MAPERR, va);
+        return Err(usize::MAX);
+    }
 
-    if (vma.vm_flags & VM_SHARED) == 0 {
-        // Todo: temporarily record mapped va->pa(direct_va)
-        locked_mm.mapped.insert(va, direct_va);
+    // Check that both stack segments have the same anon_vma?
+    if let Some(vma) = cursor.value() {
+        if (vma.vm_flags & VM_GROWSDOWN) == 0 && va - vma.vm_end < STACK_GUARD_GAP {
+            error!("SEGV_ACCERR");
+            let tid = task::current().tid();
+            force_sig_fault(tid, task::SIGSEGV, SEGV_ACCERR, va);
+            return Err(usize::MAX);
+        }
     }
 
-    Ok(phys_to_virt(pa.into()).into())
+    let stack = VmAreaStruct::new(va, next_vma.vm_start, 0, None, next_vma.vm_flags);
+    locked_mm.vmas.insert(va, stack);
+    Ok(())
+}
+
+/// Whether the stack whose lowest area is `stack` may grow down to `va`
+/// within RLIMIT_STACK and RLIMIT_AS.
+fn may_grow_stack(mm: &MmStruct, va: usize, stack: &VmAreaStruct) -> bool {
+    // The stack is made of the adjacent growsdown areas up from the lowest.
+    let mut top = stack.vm_end;
+    for vma in mm.vmas.range(stack.vm_end..).map(|(_, vma)| vma) {
+        if vma.vm_start != top || (vma.vm_flags & VM_GROWSDOWN) == 0 {
+            break;
+        }
+        top = vma.vm_end;
+    }
+    let current = task::current();
+    ((top - va) as u64) <= current.rlimit(RLIMIT_STACK)
+        && ((mm.total_vm() + stack.vm_start - va) as u64) <= current.rlimit(RLIMIT_AS)
 }
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 fn bad_area(va: usize, epc: usize, fixup: &mut usize) -> Result<usize, usize> {
     //
     // Something tried to access memory that isn't in our memory map.
@@ -524,7 +648,7 @@ fn bad_area(va: usize, epc: usize, fixup: &mut usize) -> Result<usize, usize> {
     no_context(epc, fixup)
 }
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 fn no_context(epc: usize, fixup: &mut usize) -> Result<usize, usize> {
     // Are we prepared to handle this kernel fault?
     if let Some(addr) = fixup_exception(epc) {
@@ -564,12 +688,12 @@ fn search_exception_table(epc: usize) -> Option<usize> {
     //None
 }
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 fn fixup_exception(epc: usize) -> Option<usize> {
     search_exception_table(epc)
 }
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64"))]
 fn access_error(cause: usize, vma: &VmAreaStruct) -> bool {
     // Todo: consider that the cause can be ZERO?!
     if cause == 0 {
@@ -617,7 +741,8 @@ pub fn set_brk(va: usize) -> usize {
     // Have a guard for mm to lock this whole function,
     // because mm.brk() and mm.set_brk() should be in a atomic context.
     let mm = task::current().mm();
-    let brk = mm.lock().brk();
+    let mut locked_mm = mm.write();
+    let brk = locked_mm.brk();
 
     assert!(is_aligned_4k(brk));
     debug!("brk!!! {:#x}, {:#x}", va, brk);
@@ -628,11 +753,16 @@ pub fn set_brk(va: usize) -> usize {
         assert!(va > brk);
         let offset = va - brk;
         assert!(is_aligned_4k(offset));
-        _mmap(brk, offset, PROT_READ | PROT_WRITE, MAP_FIXED | MAP_ANONYMOUS, None, 0).unwrap();
+        do_mmap(
+            &mut locked_mm, brk, offset,
+            PROT_READ | PROT_WRITE, MAP_FIXED | MAP_ANONYMOUS, None, 0
+        ).unwrap();
+        locked_mm.set_brk(va);
+        // The fault takes the lock for reading.
+        drop(locked_mm);
         // Todo: set proper cause for faultin_page.
         let mut _fixup = 0;
         let _ = faultin_page(brk, 0 /* cause */, 0, &mut _fixup);
-        mm.lock().set_brk(va);
         va
     }
 }
@@ -642,7 +772,7 @@ pub fn msync(va: usize, len: usize, flags: usize) -> usize {
     debug!("msync: va {:#X} len {:#X} flags {:#X}", va, len, flags);
 
     let mm = task::current().mm();
-    let locked_mm = mm.lock();
+    let locked_mm = mm.read();
 
     let vma = locked_mm
         .vmas
@@ -663,6 +793,8 @@ pub fn msync(va: usize, len: usize, flags: usize) -> usize {
 
     if vma.vm_file.get().is_some() {
         let file = vma.vm_file.get().unwrap().clone();
+        // Reading the pages may fault them in, which takes the lock again.
+        drop(locked_mm);
         sync_file(va, len, &mut file.lock(), offset);
     }
     0
@@ -703,11 +835,13 @@ pub fn munmap(va: usize, mut len: usize) -> usize {
 
     info!("munmap {:#x} - {:#x}", va, va + len);
 
-    while let Some(mut overlap) = cut_overlap(va, len) {
+    let mm = task::current().mm();
+    let mut locked_mm = mm.write();
+    while let Some(mut overlap) = cut_overlap(&mut locked_mm, va, len) {
         debug!("find overlap {:#X}-{:#X}", overlap.vm_start, overlap.vm_end);
         if va <= overlap.vm_start && overlap.vm_end <= va + len {
             let len = overlap.vm_end - overlap.vm_start;
-            let _ = remove_region(overlap.vm_start, len);
+            let _ = remove_region(&locked_mm, overlap.vm_start, len);
             continue;
         }
 
@@ -725,15 +859,13 @@ pub fn munmap(va: usize, mut len: usize) -> usize {
             let mut new = overlap.clone();
             new.vm_start = va + len;
             new.vm_pgoff += bias;
-            let mm = task::current().mm();
-            mm.lock().vmas.insert(va + len, new);
+            locked_mm.vmas.insert(va + len, new);
         }
         if va > overlap.vm_start {
             overlap.vm_end = va;
-            let mm = task::current().mm();
-            mm.lock().vmas.insert(overlap.vm_start, overlap);
+            locked_mm.vmas.insert(overlap.vm_start, overlap);
         }
-        let _ = remove_region(va, len);
+        let _ = remove_region(&locked_mm, va, len);
     }
 
     0
@@ -752,38 +884,35 @@ pub fn mremap(
     error!("mremap oaddr {:#x} osize {:#x} nsize {:#x}, flags {:#x} naddr {:#x}",
         oaddr, osize, nsize, flags, naddr);
 
-    let mut area = cut_overlap(oaddr, osize).unwrap_or_else(|| {
+    let mm = task::current().mm();
+    let mut locked_mm = mm.write();
+    let mut area = cut_overlap(&mut locked_mm, oaddr, osize).unwrap_or_else(|| {
         panic!("no area {:#x} : {:#x}", oaddr, osize);
     });
     assert_eq!(oaddr, area.vm_start);
     assert_eq!(oaddr+osize, area.vm_end);
 
-    if let Some(next_area) = find_next_area(oaddr + nsize) {
+    if let Some(next_area) = find_next_area(&locked_mm, oaddr + nsize) {
         error!("{:#x} next_area: {:#x}", oaddr + nsize, next_area.vm_start);
         assert!(oaddr + nsize <= next_area.vm_start);
     }
 
     area.vm_end = area.vm_start + nsize;
 
-    let mm = task::current().mm();
-    mm.lock().vmas.insert(area.vm_start, area);
+    locked_mm.vmas.insert(area.vm_start, area);
     oaddr
 }
 
-fn find_next_area(cur_end: usize) -> Option<VmAreaStruct> {
-    let mm = task::current().mm();
-    let locked_mm = mm.lock();
-    let cursor = locked_mm.vmas.lower_bound(Bound::Excluded(&cur_end));
+fn find_next_area(mm: &MmStruct, cur_end: usize) -> Option<VmAreaStruct> {
+    let cursor = mm.vmas.lower_bound(Bound::Excluded(&cur_end));
     cursor.peek_prev().map(|(_, area)| area.clone())
 }
 
-fn remove_region(va: usize, len: usize) -> usize {
-    let mm = task::current().mm();
-    let mut locked_mm = mm.lock();
+fn remove_region(mm: &MmStruct, va: usize, len: usize) -> usize {
     // Todo: handle temporary mmaped.
-    locked_mm.mapped.remove(&va);
+    mm.mapped.remove(va);
     debug!("remove_region va {:#x} len {:#x}", va, len);
-    match locked_mm.unmap_region(va, len) {
+    match mm.unmap_region(va, len) {
         Ok(_) => {
             flush_tlb(None);
         },
@@ -801,7 +930,8 @@ pub fn mprotect(va: usize, len: usize, prot: usize) -> usize {
 
     let mut vma;
     let mm = task::current().mm();
-    if let Some(mut overlap) = cut_overlap(va, len) {
+    let mut locked_mm = mm.write();
+    if let Some(mut overlap) = cut_overlap(&mut locked_mm, va, len) {
         debug!("find overlap {:#X}-{:#X}", overlap.vm_start, overlap.vm_end);
         assert!(
             overlap.vm_start <= va && va + len <= overlap.vm_end,
@@ -823,11 +953,11 @@ pub fn mprotect(va: usize, len: usize, prot: usize) -> usize {
             let mut new = overlap.clone();
             new.vm_start = va + len;
             new.vm_pgoff += bias;
-            mm.lock().vmas.insert(va + len, new);
+            locked_mm.vmas.insert(va + len, new);
         }
         if va > overlap.vm_start {
             overlap.vm_end = va;
-            mm.lock().vmas.insert(overlap.vm_start, overlap);
+            locked_mm.vmas.insert(overlap.vm_start, overlap);
         }
     } else {
         panic!("No such vma!");
@@ -842,7 +972,7 @@ pub fn mprotect(va: usize, len: usize, prot: usize) -> usize {
     let newflags = calc_vm_prot_bits(prot) | (vma.vm_flags & !mask_off);
 
     vma.vm_flags = newflags;
-    mm.lock().vmas.insert(va, vma);
+    locked_mm.vmas.insert(va, vma);
     info!("mprotect: newflags {:#X}", newflags);
     0
 }
@@ -0,0 +1,13 @@
+//! Modules are not supported here: no object is for this machine, so none
+//! is relocated.
+
+use axerrno::{LinuxError, LinuxResult};
+use elf::relocation::Rela;
+
+pub const EM_MACHINE: u16 = elf::abi::EM_NONE;
+
+pub fn apply_relocate_add(_relas: &[Rela], _base: usize, _syms: &[usize]) -> LinuxResult {
+    Err(LinuxError::ENOEXEC)
+}
+
+pub fn flush_icache() {}
@@ -0,0 +1,14 @@
+//! Architecture-specific relocations.
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        mod riscv;
+        pub use self::riscv::*;
+    } else if #[cfg(target_arch = "x86_64")] {
+        mod x86_64;
+        pub use self::x86_64::*;
+    } else {
+        mod dummy;
+        pub use self::dummy::*;
+    }
+}
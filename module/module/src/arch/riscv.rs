@@ -0,0 +1,182 @@
+//! RISC-V relocations, of objects built with the medany code model and
+//! without relaxation (-mno-relax): the linker would be the one to relax,
+//! and we do not.
+
+use alloc::collections::BTreeMap;
+use axerrno::{LinuxError, LinuxResult};
+use elf::abi::*;
+use elf::relocation::Rela;
+
+pub const EM_MACHINE: u16 = EM_RISCV;
+
+fn read32(loc: usize) -> u32 {
+    unsafe { (loc as *const u32).read_unaligned() }
+}
+
+fn write32(loc: usize, v: u32) {
+    unsafe { (loc as *mut u32).write_unaligned(v) }
+}
+
+/// Keeps the bits of the instruction at `loc` in `keep`, and sets `bits`.
+fn rmw32(loc: usize, keep: u32, bits: u32) {
+    write32(loc, (read32(loc) & keep) | bits);
+}
+
+fn rmw16(loc: usize, keep: u16, bits: u16) {
+    let p = loc as *mut u16;
+    unsafe { p.write_unaligned((p.read_unaligned() & keep) | bits) }
+}
+
+fn fits_i32(v: usize) -> bool {
+    v == v as i32 as usize
+}
+
+/// The upper 20 bits of `offset`, rounded for the sign of the lower 12.
+fn hi20(offset: usize) -> u32 {
+    (offset.wrapping_add(0x800) as u32) & 0xfffff000
+}
+
+fn lo12(offset: usize) -> u32 {
+    (offset as u32).wrapping_sub(hi20(offset))
+}
+
+/// Applies `relas` to the section at `base`, with the values of the
+/// symbols `syms`.
+pub fn apply_relocate_add(relas: &[Rela], base: usize, syms: &[usize]) -> LinuxResult {
+    let value = |rela: &Rela| syms[rela.r_sym as usize].wrapping_add(rela.r_addend as usize);
+
+    // A PCREL_LO12 points at the auipc of its PCREL_HI20, whose offset
+    // it takes the lower bits of.
+    let pcrel_hi: BTreeMap<usize, usize> = relas
+        .iter()
+        .filter(|rela| rela.r_type == R_RISCV_PCREL_HI20)
+        .map(|rela| {
+            let loc = base + rela.r_offset as usize;
+            (loc, value(rela).wrapping_sub(loc))
+        })
+        .collect();
+
+    for rela in relas {
+        let loc = base + rela.r_offset as usize;
+        let v = value(rela);
+        match rela.r_type {
+            R_RISCV_32 => {
+                if v != v as u32 as usize {
+                    return overflow(rela, v);
+                }
+                write32(loc, v as u32);
+            }
+            R_RISCV_64 => unsafe { (loc as *mut u64).write_unaligned(v as u64) },
+            R_RISCV_32_PCREL => write32(loc, v.wrapping_sub(loc) as u32),
+            R_RISCV_ADD32 => write32(loc, read32(loc).wrapping_add(v as u32)),
+            R_RISCV_SUB32 => write32(loc, read32(loc).wrapping_sub(v as u32)),
+            R_RISCV_ADD64 | R_RISCV_SUB64 => {
+                let p = loc as *mut u64;
+                unsafe {
+                    let old = p.read_unaligned();
+                    p.write_unaligned(if rela.r_type == R_RISCV_ADD64 {
+                        old.wrapping_add(v as u64)
+                    } else {
+                        old.wrapping_sub(v as u64)
+                    });
+                }
+            }
+            R_RISCV_BRANCH => {
+                let off = v.wrapping_sub(loc) as u32;
+                let imm12 = (off & 0x1000) << (31 - 12);
+                let imm11 = (off & 0x800) >> (11 - 7);
+                let imm10_5 = (off & 0x7e0) << (30 - 10);
+                let imm4_1 = (off & 0x1e) << (11 - 4);
+                rmw32(loc, 0x1fff07f, imm12 | imm11 | imm10_5 | imm4_1);
+            }
+            R_RISCV_JAL => {
+                let off = v.wrapping_sub(loc) as u32;
+                let imm20 = (off & 0x100000) << (31 - 20);
+                let imm19_12 = off & 0xff000;
+                let imm11 = (off & 0x800) << (20 - 11);
+                let imm10_1 = (off & 0x7fe) << (30 - 10);
+                rmw32(loc, 0xfff, imm20 | imm19_12 | imm11 | imm10_1);
+            }
+            R_RISCV_RVC_BRANCH => {
+                let off = v.wrapping_sub(loc) as u16;
+                let imm8 = (off & 0x100) << (12 - 8);
+                let imm7_6 = (off & 0xc0) >> (6 - 5);
+                let imm5 = (off & 0x20) >> (5 - 2);
+                let imm4_3 = (off & 0x18) << (12 - 5);
+                let imm2_1 = (off & 0x6) << (12 - 10);
+                rmw16(loc, 0xe383, imm8 | imm7_6 | imm5 | imm4_3 | imm2_1);
+            }
+            R_RISCV_RVC_JUMP => {
+                let off = v.wrapping_sub(loc) as u16;
+                let imm11 = (off & 0x800) << (12 - 11);
+                let imm10 = (off & 0x400) >> (10 - 8);
+                let imm9_8 = (off & 0x300) << (12 - 11);
+                let imm7 = (off & 0x80) >> (7 - 6);
+                let imm6 = (off & 0x40) << (12 - 11);
+                let imm5 = (off & 0x20) >> (5 - 2);
+                let imm4 = (off & 0x10) << (12 - 5);
+                let imm3_1 = (off & 0xe) << (12 - 10);
+                rmw16(loc, 0xe003, imm11 | imm10 | imm9_8 | imm7 | imm6 | imm5 | imm4 | imm3_1);
+            }
+            R_RISCV_CALL | R_RISCV_CALL_PLT => {
+                let off = v.wrapping_sub(loc);
+                if !fits_i32(off) {
+                    return overflow(rela, off);
+                }
+                // auipc, then jalr.
+                rmw32(loc, 0xfff, hi20(off));
+                rmw32(loc + 4, 0xfffff, lo12(off) << 20);
+            }
+            R_RISCV_PCREL_HI20 => {
+                let off = v.wrapping_sub(loc);
+                if !fits_i32(off) {
+                    return overflow(rela, off);
+                }
+                rmw32(loc, 0xfff, hi20(off));
+            }
+            R_RISCV_PCREL_LO12_I | R_RISCV_PCREL_LO12_S => {
+                let Some(&off) = pcrel_hi.get(&v) else {
+                    warn!("module: no PCREL_HI20 at {:#x} for {:#x}", v, loc);
+                    return Err(LinuxError::ENOEXEC);
+                };
+                lo12_insn(rela.r_type == R_RISCV_PCREL_LO12_S, loc, lo12(off));
+            }
+            R_RISCV_HI20 => {
+                if !fits_i32(v) {
+                    return overflow(rela, v);
+                }
+                rmw32(loc, 0xfff, hi20(v));
+            }
+            R_RISCV_LO12_I | R_RISCV_LO12_S => {
+                lo12_insn(rela.r_type == R_RISCV_LO12_S, loc, lo12(v));
+            }
+            R_RISCV_RELAX => {}
+            t => {
+                warn!("module: unsupported relocation {} at {:#x}", t, loc);
+                return Err(LinuxError::ENOEXEC);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Puts `lo12` into the immediate of the I-type, or S-type if `store`,
+/// instruction at `loc`.
+fn lo12_insn(store: bool, loc: usize, lo12: u32) {
+    if store {
+        let imm11_5 = (lo12 & 0xfe0) << (31 - 11);
+        let imm4_0 = (lo12 & 0x1f) << (11 - 4);
+        rmw32(loc, 0x1fff07f, imm11_5 | imm4_0);
+    } else {
+        rmw32(loc, 0xfffff, (lo12 & 0xfff) << 20);
+    }
+}
+
+fn overflow(rela: &Rela, v: usize) -> LinuxResult {
+    warn!("module: relocation {} overflows with {:#x}", rela.r_type, v);
+    Err(LinuxError::ENOEXEC)
+}
+
+pub fn flush_icache() {
+    axhal::arch::flush_icache_all();
+}
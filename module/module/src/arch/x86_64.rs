@@ -0,0 +1,56 @@
+//! x86_64 relocations, of objects built with the kernel code model.
+
+use axerrno::{LinuxError, LinuxResult};
+use elf::abi::*;
+use elf::relocation::Rela;
+
+pub const EM_MACHINE: u16 = EM_X86_64;
+
+/// Applies `relas` to the section at `base`, with the values of the
+/// symbols `syms`.
+pub fn apply_relocate_add(relas: &[Rela], base: usize, syms: &[usize]) -> LinuxResult {
+    for rela in relas {
+        let loc = base + rela.r_offset as usize;
+        let v = syms[rela.r_sym as usize].wrapping_add(rela.r_addend as usize);
+        match rela.r_type {
+            R_X86_64_NONE => {}
+            R_X86_64_64 => unsafe { (loc as *mut u64).write_unaligned(v as u64) },
+            R_X86_64_32 => {
+                if v != v as u32 as usize {
+                    return overflow(rela, v);
+                }
+                unsafe { (loc as *mut u32).write_unaligned(v as u32) }
+            }
+            R_X86_64_32S => {
+                if v != v as i32 as usize {
+                    return overflow(rela, v);
+                }
+                unsafe { (loc as *mut u32).write_unaligned(v as u32) }
+            }
+            R_X86_64_PC32 | R_X86_64_PLT32 => {
+                let v = v.wrapping_sub(loc);
+                if v != v as i32 as usize {
+                    return overflow(rela, v);
+                }
+                unsafe { (loc as *mut u32).write_unaligned(v as u32) }
+            }
+            R_X86_64_PC64 => {
+                let v = v.wrapping_sub(loc);
+                unsafe { (loc as *mut u64).write_unaligned(v as u64) }
+            }
+            t => {
+                warn!("module: unsupported relocation {} at {:#x}", t, loc);
+                return Err(LinuxError::ENOEXEC);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn overflow(rela: &Rela, v: usize) -> LinuxResult {
+    warn!("module: relocation {} overflows with {:#x}", rela.r_type, v);
+    Err(LinuxError::ENOEXEC)
+}
+
+/// The instruction caches snoop the stores.
+pub fn flush_icache() {}
@@ -0,0 +1,97 @@
+//! The module area: kernel space for the code and data of modules, right
+//! above the linear mapping of the physical memory. It is under a root
+//! entry of the kernel space already, so the page tables of the processes
+//! see what is mapped there, and in reach of the pc-relative calls and
+//! jumps between it and the kernel text.
+
+use alloc::collections::BTreeMap;
+use axalloc::global_allocator;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{virt_to_phys, PAGE_SIZE_4K};
+use page_table::paging::{self, MappingFlags};
+use spinbase::SpinNoIrq;
+
+pub use axhal::mem::{MODULES_SIZE, MODULES_VADDR};
+
+/// The ranges of the area in use, from their start to their end.
+static USED: SpinNoIrq<BTreeMap<usize, usize>> = SpinNoIrq::new(BTreeMap::new());
+
+fn reserve(size: usize) -> LinuxResult<usize> {
+    let mut used = USED.lock();
+    let mut start = MODULES_VADDR;
+    for (&s, &e) in used.iter() {
+        if s - start >= size {
+            break;
+        }
+        start = e;
+    }
+    if start + size > MODULES_VADDR + MODULES_SIZE {
+        return Err(LinuxError::ENOMEM);
+    }
+    used.insert(start, start + size);
+    Ok(start)
+}
+
+fn release(start: usize) {
+    USED.lock().remove(&start);
+}
+
+/// Memory of a module in the area, mapped read-write and zeroed to begin
+/// with. It is unmapped and freed on drop.
+pub(crate) struct ModuleMem {
+    vaddr: usize,
+    size: usize,
+    /// The frames, in the linear mapping.
+    frames: usize,
+}
+
+impl ModuleMem {
+    pub fn alloc(size: usize) -> LinuxResult<Self> {
+        let size = (size + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
+        let vaddr = reserve(size)?;
+        let Ok(frames) = global_allocator().alloc_pages(size / PAGE_SIZE_4K, PAGE_SIZE_4K) else {
+            release(vaddr);
+            return Err(LinuxError::ENOMEM);
+        };
+        let paddr = virt_to_phys(frames.into());
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        if let Err(e) = paging::map_kernel_region(vaddr.into(), paddr, size, flags) {
+            error!("module: cannot map {:#x}: {:?}", vaddr, e);
+            global_allocator().dealloc_pages(frames, size / PAGE_SIZE_4K);
+            release(vaddr);
+            return Err(LinuxError::ENOMEM);
+        }
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, size) };
+        Ok(Self { vaddr, size, frames })
+    }
+
+    pub fn vaddr(&self) -> usize {
+        self.vaddr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Gives the pages of `[start, end)`, offsets in it, the `flags`.
+    pub fn protect(&self, start: usize, end: usize, flags: MappingFlags) {
+        if start == end {
+            return;
+        }
+        unsafe {
+            paging::protect_kernel_region((self.vaddr + start).into(), end - start, flags)
+                .expect("module: area not mapped");
+        }
+    }
+}
+
+impl Drop for ModuleMem {
+    fn drop(&mut self) {
+        unsafe {
+            paging::unmap_kernel_region(self.vaddr.into(), self.size)
+                .expect("module: area not mapped");
+        }
+        global_allocator().dealloc_pages(self.frames, self.size / PAGE_SIZE_4K);
+        release(self.vaddr);
+    }
+}
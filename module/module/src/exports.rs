@@ -0,0 +1,68 @@
+//! What the kernel exports to modules.
+//!
+//! The functions with the C ABI serve modules in any language. Those with
+//! the Rust ABI pass the types of the kernel crates, so the modules that
+//! call them must be built by the same compiler against the same crates.
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use axerrno::LinuxResult;
+use axfs_vfs::VfsNodeRef;
+use fileops::MountFn;
+
+/// Logs the `len` bytes of text at `msg`.
+#[no_mangle]
+pub extern "C" fn printk(msg: *const u8, len: usize) {
+    let msg = unsafe { core::slice::from_raw_parts(msg, len) };
+    info!("{}", core::str::from_utf8(msg).unwrap_or("(bad utf-8)"));
+}
+
+/// Allocates `size` bytes aligned to `align`, or returns null.
+#[no_mangle]
+pub extern "C" fn kmalloc(size: usize, align: usize) -> *mut u8 {
+    match Layout::from_size_align(size, align) {
+        Ok(layout) if size > 0 => unsafe { alloc(layout) },
+        _ => core::ptr::null_mut(),
+    }
+}
+
+/// Frees what [`kmalloc`] has allocated with `size` and `align`.
+#[no_mangle]
+pub extern "C" fn kfree(ptr: *mut u8, size: usize, align: usize) {
+    if let Ok(layout) = Layout::from_size_align(size, align) {
+        if !ptr.is_null() {
+            unsafe { dealloc(ptr, layout) };
+        }
+    }
+}
+
+/// See [`fileops::register_filesystem`].
+#[no_mangle]
+pub fn register_filesystem(name: &str, mount: MountFn) -> LinuxResult {
+    fileops::register_filesystem(name, mount)
+}
+
+/// See [`fileops::unregister_filesystem`].
+#[no_mangle]
+pub fn unregister_filesystem(name: &str) -> LinuxResult {
+    fileops::unregister_filesystem(name)
+}
+
+/// See [`fileops::register_device`].
+#[no_mangle]
+pub fn register_device(name: &str, node: VfsNodeRef) -> LinuxResult {
+    fileops::register_device(name, node)
+}
+
+/// See [`fileops::unregister_device`].
+#[no_mangle]
+pub fn unregister_device(name: &str) -> LinuxResult {
+    fileops::unregister_device(name)
+}
+
+export_symbol!(printk);
+export_symbol!(kmalloc);
+export_symbol!(kfree);
+export_symbol!(register_filesystem);
+export_symbol!(unregister_filesystem);
+export_symbol!(register_device);
+export_symbol!(unregister_device);
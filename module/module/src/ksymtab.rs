@@ -0,0 +1,63 @@
+//! The symbols the kernel exports to modules.
+//!
+//! [`export_symbol!`] puts an entry for a symbol into the `__ksymtab`
+//! section, which the linker script gathers between `__start___ksymtab`
+//! and `__stop___ksymtab`. A module refers to it by the name of the
+//! symbol, so it must be one the linker knows by that name, as of a
+//! `#[no_mangle]` function or static.
+
+/// An entry of the `__ksymtab` section.
+#[repr(C)]
+pub struct KernelSymbol {
+    name: &'static str,
+    addr: *const (),
+}
+
+unsafe impl Sync for KernelSymbol {}
+
+impl KernelSymbol {
+    pub const fn new(name: &'static str, addr: *const ()) -> Self {
+        Self { name, addr }
+    }
+}
+
+/// Exports the function `sym`, or the static `sym` with `static sym`, to
+/// modules.
+#[macro_export]
+macro_rules! export_symbol {
+    (static $sym:ident) => {
+        const _: () = {
+            #[used]
+            #[link_section = "__ksymtab"]
+            #[allow(unused_unsafe)]
+            static __KSYMTAB: $crate::KernelSymbol = $crate::KernelSymbol::new(
+                stringify!($sym),
+                unsafe { core::ptr::addr_of!($sym) } as *const (),
+            );
+        };
+    };
+    ($sym:ident) => {
+        const _: () = {
+            #[used]
+            #[link_section = "__ksymtab"]
+            static __KSYMTAB: $crate::KernelSymbol =
+                $crate::KernelSymbol::new(stringify!($sym), $sym as *const ());
+        };
+    };
+}
+
+extern "C" {
+    fn __start___ksymtab();
+    fn __stop___ksymtab();
+}
+
+fn ksymtab() -> &'static [KernelSymbol] {
+    let start = __start___ksymtab as usize;
+    let len = (__stop___ksymtab as usize - start) / core::mem::size_of::<KernelSymbol>();
+    unsafe { core::slice::from_raw_parts(start as *const KernelSymbol, len) }
+}
+
+/// The address of the exported symbol `name`.
+pub fn find_symbol(name: &str) -> Option<usize> {
+    ksymtab().iter().find(|s| s.name == name).map(|s| s.addr as usize)
+}
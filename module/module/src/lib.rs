@@ -0,0 +1,346 @@
+//! Loadable kernel modules.
+//!
+//! A module is a relocatable ELF object (`ET_REL`) of the machine of the
+//! kernel, with a `name=` string in its `.modinfo` section. Its allocated
+//! sections are laid out in the module area, text, then read-only data,
+//! then data, each on pages of its own, with the permissions they need.
+//! Its undefined symbols resolve to what the kernel exports with
+//! [`export_symbol!`], or to the global symbols of the modules loaded
+//! before it, which then can not be unloaded before it.
+//!
+//! Loading calls its `init_module`, if it has one, with the arguments
+//! given to init_module(2), as a pointer and a length, and fails with the
+//! negative errno it returns, if it does. Unloading calls its
+//! `cleanup_module`, and a module with an `init_module` but no
+//! `cleanup_module` can not be unloaded. The hooks are called with the
+//! C ABI. The one to unload must undo what the module has registered, as
+//! its filesystems and devices: nothing of it may be used once it has
+//! returned.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod arch;
+mod area;
+#[macro_use]
+mod ksymtab;
+mod exports;
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axtype::get_user_str;
+use elf::abi::*;
+use elf::endian::AnyEndian;
+use elf::relocation::Rela;
+use elf::section::SectionHeader;
+use elf::ElfBytes;
+use mutex::Mutex;
+use page_table::paging::MappingFlags;
+
+use area::ModuleMem;
+pub use area::{MODULES_SIZE, MODULES_VADDR};
+pub use ksymtab::{find_symbol, KernelSymbol};
+
+/// Bytes of a module image at most.
+const MAX_MODULE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Its `init_module`: takes the arguments, returns 0 or a negative errno.
+type InitFn = unsafe extern "C" fn(args: *const u8, len: usize) -> i32;
+/// Its `cleanup_module`.
+type ExitFn = unsafe extern "C" fn();
+
+struct Module {
+    mem: ModuleMem,
+    init: Option<InitFn>,
+    exit: Option<ExitFn>,
+    /// Its global symbols, for the modules loaded after it.
+    syms: BTreeMap<String, usize>,
+    /// The modules whose symbols it uses.
+    uses: Vec<String>,
+    /// How many modules use its symbols.
+    users: usize,
+}
+
+/// The modules loaded, by name. It is held while one is loaded or
+/// unloaded, hooks included.
+static MODULES: Mutex<BTreeMap<String, Module>> = Mutex::new(BTreeMap::new());
+
+/// Which of the three parts of a module a section goes into.
+#[derive(Clone, Copy, PartialEq)]
+enum Part {
+    Text,
+    RoData,
+    Data,
+}
+
+impl Part {
+    fn of(shdr: &SectionHeader) -> Option<Self> {
+        let flags = shdr.sh_flags as u32;
+        if (flags & SHF_ALLOC) == 0 || shdr.sh_size == 0 {
+            None
+        } else if (flags & SHF_EXECINSTR) != 0 {
+            Some(Part::Text)
+        } else if (flags & SHF_WRITE) == 0 {
+            Some(Part::RoData)
+        } else {
+            Some(Part::Data)
+        }
+    }
+
+    fn flags(self) -> MappingFlags {
+        match self {
+            Part::Text => MappingFlags::READ | MappingFlags::EXECUTE,
+            Part::RoData => MappingFlags::READ,
+            Part::Data => MappingFlags::READ | MappingFlags::WRITE,
+        }
+    }
+}
+
+fn align_up(v: usize, align: usize) -> usize {
+    let align = align.max(1);
+    (v + align - 1) / align * align
+}
+
+fn bad_elf<E>(_: E) -> LinuxError {
+    LinuxError::ENOEXEC
+}
+
+/// The value of `key` in the `.modinfo` section, of `key=value` strings.
+fn modinfo<'a>(file: &ElfBytes<'a, AnyEndian>, key: &str) -> Option<&'a str> {
+    let shdr = file.section_header_by_name(".modinfo").ok()??;
+    let (data, _) = file.section_data(&shdr).ok()?;
+    data.split(|&b| b == 0)
+        .filter_map(|s| core::str::from_utf8(s).ok())
+        .find_map(|s| s.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Resolves the undefined symbol `name` for a module: returns its value,
+/// and the module it is from, if not from the kernel.
+fn resolve(
+    modules: &BTreeMap<String, Module>, name: &str
+) -> Option<(usize, Option<String>)> {
+    if let Some(addr) = find_symbol(name) {
+        return Some((addr, None));
+    }
+    modules.iter()
+        .find_map(|(m, module)| Some((*module.syms.get(name)?, Some(m.clone()))))
+}
+
+/// Loads the module `image`, and calls its `init_module` with `args`.
+pub fn load_module(image: &[u8], args: &str) -> LinuxResult {
+    let file = ElfBytes::<AnyEndian>::minimal_parse(image).map_err(bad_elf)?;
+    if file.ehdr.e_type != ET_REL || file.ehdr.e_machine != arch::EM_MACHINE {
+        return Err(LinuxError::ENOEXEC);
+    }
+    let name = modinfo(&file, "name").ok_or(LinuxError::ENOEXEC)?.to_string();
+    let shdrs: Vec<SectionHeader> =
+        file.section_headers().ok_or(LinuxError::ENOEXEC)?.iter().collect();
+
+    let mut modules = MODULES.lock();
+    if modules.contains_key(&name) {
+        return Err(LinuxError::EEXIST);
+    }
+
+    // Lay the sections out, by part, and each part on pages of its own.
+    let mut offsets = vec![None; shdrs.len()];
+    let mut parts = Vec::new();
+    let mut size = 0;
+    for part in [Part::Text, Part::RoData, Part::Data] {
+        let start = size;
+        for (i, shdr) in shdrs.iter().enumerate() {
+            if Part::of(shdr) == Some(part) {
+                size = align_up(size, shdr.sh_addralign as usize);
+                offsets[i] = Some(size);
+                size += shdr.sh_size as usize;
+            }
+        }
+        size = align_up(size, axhal::mem::PAGE_SIZE_4K);
+        parts.push((part, start, size));
+    }
+    if size == 0 {
+        return Err(LinuxError::ENOEXEC);
+    }
+    let mem = ModuleMem::alloc(size)?;
+    let base = mem.vaddr();
+    for (shdr, offset) in shdrs.iter().zip(&offsets) {
+        if let (Some(offset), true) = (offset, shdr.sh_type != SHT_NOBITS) {
+            let (data, _) = file.section_data(shdr).map_err(bad_elf)?;
+            let dst = unsafe {
+                core::slice::from_raw_parts_mut((base + offset) as *mut u8, data.len())
+            };
+            dst.copy_from_slice(data);
+        }
+    }
+
+    // The values of the symbols.
+    let (symtab, strtab) = file.symbol_table().map_err(bad_elf)?.ok_or(LinuxError::ENOEXEC)?;
+    let mut values = Vec::new();
+    let mut syms = BTreeMap::new();
+    let mut uses = Vec::new();
+    for (i, sym) in symtab.iter().enumerate() {
+        let sym_name = strtab.get(sym.st_name as usize).map_err(bad_elf)?;
+        let value = match sym.st_shndx {
+            _ if i == 0 => 0,
+            SHN_UNDEF => match resolve(&modules, sym_name) {
+                Some((value, from)) => {
+                    if let Some(from) = from.filter(|m| !uses.contains(m)) {
+                        uses.push(from);
+                    }
+                    value
+                }
+                None if sym.st_bind() == STB_WEAK => 0,
+                None => {
+                    warn!("module {}: unknown symbol {}", name, sym_name);
+                    return Err(LinuxError::ENOENT);
+                }
+            },
+            SHN_ABS => sym.st_value as usize,
+            SHN_COMMON => {
+                warn!("module {}: common symbol {}, build with -fno-common", name, sym_name);
+                return Err(LinuxError::ENOEXEC);
+            }
+            shndx => match offsets.get(shndx as usize) {
+                Some(Some(offset)) => base + offset + sym.st_value as usize,
+                _ => 0,
+            },
+        };
+        if sym.st_shndx != SHN_UNDEF && sym.st_bind() != STB_LOCAL && !sym_name.is_empty() {
+            syms.insert(sym_name.to_string(), value);
+        }
+        values.push(value);
+    }
+
+    // The relocations of the sections laid out.
+    for shdr in shdrs.iter() {
+        if shdr.sh_type == SHT_REL {
+            return Err(LinuxError::ENOEXEC);
+        }
+        if shdr.sh_type != SHT_RELA {
+            continue;
+        }
+        let Some(Some(offset)) = offsets.get(shdr.sh_info as usize) else {
+            continue;
+        };
+        let relas: Vec<Rela> = file.section_data_as_relas(shdr).map_err(bad_elf)?.collect();
+        if relas.iter().any(|rela| rela.r_sym as usize >= values.len()) {
+            return Err(LinuxError::ENOEXEC);
+        }
+        arch::apply_relocate_add(&relas, base + offset, &values)?;
+    }
+
+    for (part, start, end) in parts {
+        mem.protect(start, end, part.flags());
+    }
+    arch::flush_icache();
+
+    let hook = |name: &str| syms.get(name).copied();
+    let init = hook("init_module").map(|f| unsafe { core::mem::transmute::<usize, InitFn>(f) });
+    let exit = hook("cleanup_module").map(|f| unsafe { core::mem::transmute::<usize, ExitFn>(f) });
+    info!("module {}: loaded at {:#x}, {:#x} bytes", name, base, mem.size());
+
+    if let Some(init) = init {
+        let ret = unsafe { init(args.as_ptr(), args.len()) };
+        if ret < 0 {
+            warn!("module {}: init_module failed with {}", name, ret);
+            return Err(LinuxError::try_from(-ret).unwrap_or(LinuxError::EINVAL));
+        }
+    }
+    for m in uses.iter() {
+        modules.get_mut(m).unwrap().users += 1;
+    }
+    modules.insert(name, Module { mem, init, exit, syms, uses, users: 0 });
+    Ok(())
+}
+
+/// Calls the `cleanup_module` of the module `name`, and unloads it. Fails
+/// with ENOENT if there is no such module, EAGAIN while other modules
+/// use it, and EBUSY if it can not be unloaded.
+pub fn unload_module(name: &str) -> LinuxResult {
+    let mut modules = MODULES.lock();
+    let module = modules.get(name).ok_or(LinuxError::ENOENT)?;
+    if module.users > 0 {
+        return Err(LinuxError::EAGAIN);
+    }
+    if module.init.is_some() && module.exit.is_none() {
+        return Err(LinuxError::EBUSY);
+    }
+    if let Some(exit) = module.exit {
+        unsafe { exit() };
+    }
+    let module = modules.remove(name).unwrap();
+    for m in module.uses.iter() {
+        modules.get_mut(m).unwrap().users -= 1;
+    }
+    info!("module {}: unloaded", name);
+    Ok(())
+}
+
+/// The names of the modules loaded.
+pub fn modules() -> Vec<String> {
+    MODULES.lock().keys().cloned().collect()
+}
+
+fn check_privileged() -> LinuxResult {
+    if !task::current().cred().is_privileged() {
+        return Err(LinuxError::EPERM);
+    }
+    Ok(())
+}
+
+/// init_module(2): loads the `len` bytes of module image at `umod`.
+pub fn init_module(umod: usize, len: usize, uargs: usize) -> LinuxResult<usize> {
+    info!("init_module: len {:#x}", len);
+    check_privileged()?;
+    if len > MAX_MODULE_SIZE {
+        return Err(LinuxError::EFBIG);
+    }
+    let image = unsafe { core::slice::from_raw_parts(umod as *const u8, len) }.to_vec();
+    load_module(&image, &get_user_str(uargs))?;
+    Ok(0)
+}
+
+/// finit_module(2): loads the module image in the file `fd`. There are
+/// no flags, as modules have neither versions nor a version magic.
+pub fn finit_module(fd: usize, uargs: usize, flags: usize) -> LinuxResult<usize> {
+    info!("finit_module: fd {} flags {:#x}", fd, flags);
+    check_privileged()?;
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let file = task::current().filetable.lock().get_file(fd).ok_or(LinuxError::EBADF)?;
+    let image = {
+        let file = file.lock();
+        let size = file.get_attr()?.size() as usize;
+        if size > MAX_MODULE_SIZE {
+            return Err(LinuxError::EFBIG);
+        }
+        let mut image = vec![0u8; size];
+        let mut pos = 0;
+        while pos < size {
+            match file.read_at(pos as u64, &mut image[pos..])? {
+                0 => break,
+                n => pos += n,
+            }
+        }
+        image.truncate(pos);
+        image
+    };
+    load_module(&image, &get_user_str(uargs))?;
+    Ok(0)
+}
+
+/// delete_module(2): unloads the module named at `uname`. The flags
+/// change nothing, as no module is waited for.
+pub fn delete_module(uname: usize, flags: usize) -> LinuxResult<usize> {
+    let name = get_user_str(uname);
+    info!("delete_module: {} flags {:#x}", name, flags);
+    check_privileged()?;
+    unload_module(&name)?;
+    Ok(0)
+}
@@ -3,6 +3,7 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`RwSem`]: A reader-writer lock.
 //! - mod [`spin`](spinlock): spin-locks.
 //!
 //! # Cargo Features
@@ -15,5 +16,7 @@
 #![feature(doc_cfg)]
 
 mod mutex;
+mod rwsem;
 
 pub use self::mutex::{Mutex, MutexGuard};
+pub use self::rwsem::{RwSem, RwSemReadGuard, RwSemWriteGuard};
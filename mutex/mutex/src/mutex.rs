@@ -5,7 +5,7 @@ use core::fmt;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU64, Ordering};
 
-use taskctx::current_ctx;
+use taskctx::{current_ctx, Tid};
 use wait_queue::WaitQueue;
 
 /// A mutual exclusion primitive useful for protecting shared data, similar to
@@ -88,11 +88,15 @@ impl<T: ?Sized> Mutex<T> {
                         "{} tried to acquire mutex it already owns.",
                         current_ctx().tid()
                     );
+                    // Lend our priority to the owner, so that less urgent
+                    // tasks can't hold it off while we wait.
+                    run_queue::pi_boost(owner_id as Tid);
                     // Wait until the lock looks unlocked before retrying
                     self.wq.wait_until(|| !self.is_locked());
                 }
             }
         }
+        current_ctx().lock_acquired();
         MutexGuard {
             lock: self,
             data: unsafe { &mut *self.data.get() },
@@ -110,6 +114,7 @@ impl<T: ?Sized> Mutex<T> {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            current_ctx().lock_acquired();
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -134,6 +139,11 @@ impl<T: ?Sized> Mutex<T> {
             "{} tried to release mutex it doesn't own",
             current_ctx().tid()
         );
+        // Inherited priority is kept until all held mutexes are released,
+        // it's never lower than what the waiters need.
+        if current_ctx().lock_released() {
+            run_queue::pi_unboost();
+        }
         self.wq.notify_one(true);
     }
 
@@ -0,0 +1,203 @@
+//! A sleeping reader-writer lock.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use wait_queue::WaitQueue;
+
+/// Set in the state while a writer holds the lock.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A reader-writer lock, as the rw_semaphore of Linux: many readers or
+/// one writer at a time, and the others sleep in a wait queue.
+///
+/// Readers that come while a writer waits queue up behind it, so that a
+/// stream of readers can't starve writers. It is not recursive: a reader
+/// that reads again while a writer waits deadlocks.
+pub struct RwSem<T: ?Sized> {
+    wq: WaitQueue,
+    /// [`WRITER`] if a writer holds it, or else how many readers do.
+    state: AtomicUsize,
+    /// Writers waiting for it.
+    writers: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides shared data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct RwSemReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwSem<T>,
+    data: *const T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct RwSemWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwSem<T>,
+    data: *mut T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwSem<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwSem<T> {}
+
+impl<T> RwSem<T> {
+    /// Creates a new [`RwSem`] wrapping the supplied data.
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            state: AtomicUsize::new(0),
+            writers: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwSem`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        let RwSem { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwSem<T> {
+    /// Locks it for reading, sleeping while a writer holds it or waits for
+    /// it.
+    pub fn read(&self) -> RwSemReadGuard<T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            self.wq.wait_until(|| {
+                self.state.load(Ordering::Relaxed) & WRITER == 0
+                    && self.writers.load(Ordering::Relaxed) == 0
+            });
+        }
+    }
+
+    /// Tries to lock it for reading, which fails while a writer holds it or
+    /// waits for it.
+    pub fn try_read(&self) -> Option<RwSemReadGuard<T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 || self.writers.load(Ordering::Relaxed) != 0 {
+                return None;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(RwSemReadGuard {
+                        lock: self,
+                        data: self.data.get(),
+                    })
+                }
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    /// Locks it for writing, sleeping until the readers and the writer
+    /// holding it are done.
+    pub fn write(&self) -> RwSemWriteGuard<T> {
+        if let Some(guard) = self.try_write() {
+            return guard;
+        }
+        // From now on, new readers wait for us.
+        self.writers.fetch_add(1, Ordering::Relaxed);
+        loop {
+            self.wq.wait_until(|| self.state.load(Ordering::Relaxed) == 0);
+            if self
+                .state
+                .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+        }
+        self.writers.fetch_sub(1, Ordering::Relaxed);
+        RwSemWriteGuard {
+            lock: self,
+            data: self.data.get(),
+        }
+    }
+
+    /// Tries to lock it for writing, which fails while anyone holds it.
+    pub fn try_write(&self) -> Option<RwSemWriteGuard<T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwSemWriteGuard {
+                lock: self,
+                data: self.data.get(),
+            })
+    }
+
+    /// Returns a mutable reference to the underlying data, which needs no
+    /// locking as the borrow is exclusive.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn read_unlock(&self) {
+        if self.state.fetch_sub(1, Ordering::Release) == 1 {
+            // The last reader lets the writers in.
+            self.wq.notify_all(true);
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.state.store(0, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+}
+
+impl<T: ?Sized + Default> Default for RwSem<T> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwSemReadGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwSemReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwSemWriteGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwSemWriteGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwSemWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
@@ -8,6 +8,7 @@ extern crate alloc;
 use core::panic::PanicInfo;
 use axtype::{align_up_4k, align_down_4k, phys_to_virt, virt_to_phys};
 use mutex::Mutex;
+use taskctx::{SCHED_FIFO, SCHED_NORMAL};
 
 /// Entry
 #[no_mangle]
@@ -28,11 +29,42 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
         // Todo: do some tests according tests below.
         info!("{}", *mutex.lock());
     }
+    test_pi();
 
     info!("[rt_mutex]: ok!");
     axhal::misc::terminate();
 }
 
+static PI_MUTEX: Mutex<u32> = Mutex::new(0);
+
+/// A normal task holding the mutex inherits the priority of a real-time
+/// waiter, until it releases the mutex.
+fn test_pi() {
+    let low = run_queue::spawn_task_raw(1, || {
+        let mut guard = PI_MUTEX.lock();
+        let high = run_queue::spawn_task_raw(2, || {
+            *PI_MUTEX.lock() += 1;
+            run_queue::exit_current(0);
+        });
+        assert!(run_queue::task_rq(&high).lock().set_policy(&high, SCHED_FIFO, 10));
+        run_queue::activate_task(high);
+        run_queue::yield_now();
+
+        let curr = taskctx::current_ctx();
+        assert!(curr.is_boosted());
+        assert_eq!((curr.policy(), curr.priority()), (SCHED_FIFO, 10));
+        *guard += 1;
+        drop(guard);
+        assert!(!curr.is_boosted());
+        assert_eq!((curr.policy(), curr.priority()), (SCHED_NORMAL, 0));
+        run_queue::exit_current(0);
+    });
+    run_queue::activate_task(low);
+    run_queue::yield_now();
+    assert_eq!(*PI_MUTEX.lock(), 2);
+    info!("[rt_mutex]: priority inheritance ok!");
+}
+
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
     error!("{}", info);
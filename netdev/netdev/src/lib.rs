@@ -0,0 +1,207 @@
+//! The network device layer.
+//!
+//! Keeps the NIC axdriver has probed, for the socket layer or a network
+//! stack to send and receive ethernet frames through. The interrupt of the
+//! NIC only acknowledges it and raises `NET_RX_SOFTIRQ`, whose handler
+//! moves the frames received into a backlog that receivers sleep on.
+//! A NIC without a known IRQ line is polled by its receivers instead.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use axdriver::prelude::*;
+use axdriver::AxDeviceContainer;
+use axerrno::{AxError, AxResult};
+use axirq::{IrqFlags, IrqReturn};
+use lazy_init::LazyInit;
+use softirq::NET_RX_SOFTIRQ;
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+/// Frames kept for receivers before newer ones are dropped.
+const NET_RX_BACKLOG: usize = 256;
+
+/// How long a receiver of a polled NIC sleeps between polls.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+static NIC: LazyInit<SpinNoIrq<AxNetDevice>> = LazyInit::new();
+static NET_IRQ: LazyInit<Option<usize>> = LazyInit::new();
+
+static BACKLOG: SpinNoIrq<VecDeque<Vec<u8>>> = SpinNoIrq::new(VecDeque::new());
+/// Receivers waiting for the backlog to fill.
+static RX_WAITERS: WaitQueue = WaitQueue::new();
+
+static RX_PACKETS: AtomicUsize = AtomicUsize::new(0);
+static RX_DROPPED: AtomicUsize = AtomicUsize::new(0);
+static TX_PACKETS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counters of the NIC, as in /proc/net/dev.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetStats {
+    pub rx_packets: usize,
+    pub rx_dropped: usize,
+    pub tx_packets: usize,
+}
+
+/// Takes the first NIC of `devs` for the kernel to use. `irq` is its IRQ
+/// line, which is requested for it.
+pub fn init(mut devs: AxDeviceContainer<AxNetDevice>, irq: Option<usize>) {
+    let Some(dev) = devs.take_one() else {
+        info!("netdev: no NIC");
+        return;
+    };
+    info!(
+        "netdev: {} mac {:x?} irq {:?}",
+        dev.device_name(),
+        dev.mac_address().0,
+        irq
+    );
+    NIC.init_by(SpinNoIrq::new(dev));
+    softirq::open_softirq(NET_RX_SOFTIRQ, net_rx_action);
+    // Polled, if the line can't be had.
+    let irq = irq.filter(|&irq| {
+        axirq::request_irq(irq, handle_irq, IrqFlags::SHARED, "netdev", 0).is_ok()
+    });
+    NET_IRQ.init_by(irq);
+}
+
+/// Whether there is a NIC.
+pub fn is_up() -> bool {
+    NIC.is_init()
+}
+
+/// The IRQ line of the NIC, if it raises interrupts.
+pub fn irq_num() -> Option<usize> {
+    NET_IRQ.try_get().copied().flatten()
+}
+
+/// The MAC address of the NIC.
+pub fn mac_address() -> Option<[u8; 6]> {
+    NIC.try_get().map(|nic| nic.lock().mac_address().0)
+}
+
+pub fn stats() -> NetStats {
+    NetStats {
+        rx_packets: RX_PACKETS.load(Ordering::Relaxed),
+        rx_dropped: RX_DROPPED.load(Ordering::Relaxed),
+        tx_packets: TX_PACKETS.load(Ordering::Relaxed),
+    }
+}
+
+/// The top half of the NIC interrupt.
+fn handle_irq(_irq: usize, _dev_id: usize) -> IrqReturn {
+    if let Some(nic) = NIC.try_get() {
+        if nic.lock().ack_interrupt() {
+            softirq::raise_softirq(NET_RX_SOFTIRQ);
+            return IrqReturn::Handled;
+        }
+    }
+    IrqReturn::None
+}
+
+/// The bottom half: drains the receive queue of the NIC.
+fn net_rx_action() {
+    if poll_rx() {
+        RX_WAITERS.notify_all(false);
+    }
+}
+
+/// Moves received frames from the NIC into the backlog, and returns
+/// whether there were any.
+fn poll_rx() -> bool {
+    let Some(nic) = NIC.try_get() else {
+        return false;
+    };
+    let mut nic = nic.lock();
+    let mut received = false;
+    loop {
+        let rx_buf = match nic.receive() {
+            Ok(rx_buf) => rx_buf,
+            Err(DevError::Again) => break,
+            Err(e) => {
+                warn!("netdev: receive failed: {:?}", e);
+                break;
+            },
+        };
+        {
+            let mut backlog = BACKLOG.lock();
+            if backlog.len() < NET_RX_BACKLOG {
+                backlog.push_back(rx_buf.packet().to_vec());
+                RX_PACKETS.fetch_add(1, Ordering::Relaxed);
+            } else {
+                RX_DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        received = true;
+        if let Err(e) = nic.recycle_rx_buffer(rx_buf) {
+            warn!("netdev: recycle rx buffer failed: {:?}", e);
+            break;
+        }
+    }
+    received
+}
+
+/// Sends the ethernet frame `frame`, without waiting for the device to
+/// finish with it.
+pub fn transmit(frame: &[u8]) -> AxResult<usize> {
+    let nic = NIC.try_get().ok_or(AxError::NotFound)?;
+    let mut nic = nic.lock();
+    nic.recycle_tx_buffers().map_err(|_| AxError::Io)?;
+    if !nic.can_transmit() {
+        return Err(AxError::WouldBlock);
+    }
+    let mut tx_buf = nic.alloc_tx_buffer(frame.len()).map_err(|e| match e {
+        DevError::NoMemory => AxError::WouldBlock,
+        _ => AxError::InvalidInput,
+    })?;
+    tx_buf.packet_mut().copy_from_slice(frame);
+    nic.transmit(tx_buf).map_err(|_| AxError::Io)?;
+    TX_PACKETS.fetch_add(1, Ordering::Relaxed);
+    Ok(frame.len())
+}
+
+/// Receives one ethernet frame into `buf`, truncating it to fit, and
+/// returns its size. Waits for one unless `nonblock`.
+pub fn receive(buf: &mut [u8], nonblock: bool) -> AxResult<usize> {
+    if !is_up() {
+        return Err(AxError::NotFound);
+    }
+    loop {
+        if let Some(frame) = BACKLOG.lock().pop_front() {
+            let size = min(buf.len(), frame.len());
+            buf[..size].copy_from_slice(&frame[..size]);
+            return Ok(size);
+        }
+        let polled = irq_num().is_none();
+        if polled && poll_rx() {
+            continue;
+        }
+        if nonblock {
+            return Err(AxError::WouldBlock);
+        }
+        if polled {
+            if taskctx::signal_pending(&taskctx::current_ctx()) {
+                return Err(AxError::Interrupted);
+            }
+            run_queue::sleep(POLL_INTERVAL);
+            continue;
+        }
+        RX_WAITERS.wait_until_interruptible(|| !BACKLOG.lock().is_empty())?;
+    }
+}
+
+/// Whether a frame can be received without waiting.
+pub fn can_receive() -> bool {
+    if BACKLOG.lock().is_empty() && irq_num().is_none() {
+        poll_rx();
+    }
+    !BACKLOG.lock().is_empty()
+}
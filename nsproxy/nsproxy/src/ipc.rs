@@ -0,0 +1,26 @@
+//! IPC namespaces.
+//!
+//! The IPC objects are kept by their own crates, in a table for each
+//! namespace, which they find by its id: keys and ids of one namespace
+//! mean nothing in another.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_IPC_NS_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct IpcNamespace {
+    id: usize,
+}
+
+impl IpcNamespace {
+    pub(crate) fn new() -> Self {
+        Self {
+            id: NEXT_IPC_NS_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Unique among the namespaces there have been.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
@@ -0,0 +1,89 @@
+//! Namespaces: what a task sees of pids, of the host name and of the
+//! System V IPC objects, which clone() gives a child of its own with
+//! CLONE_NEWPID, CLONE_NEWUTS and CLONE_NEWIPC.
+//!
+//! A task points at an [`NsProxy`] with its namespaces, shared by the
+//! tasks that have the same ones. The pid namespace there is the one of
+//! the children it creates: its own pid namespace is fixed when it is
+//! created, and kept in the task.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod pid;
+mod uts;
+mod ipc;
+
+use alloc::sync::Arc;
+use axerrno::LinuxResult;
+use lazy_init::LazyInit;
+
+pub use pid::{PidNamespace, PID_MAX};
+pub use uts::{UtsNamespace, UTS_LEN};
+pub use ipc::IpcNamespace;
+
+/* Clone flags that create namespaces */
+pub const CLONE_NEWUTS: usize = 0x04000000;
+pub const CLONE_NEWIPC: usize = 0x08000000;
+pub const CLONE_NEWPID: usize = 0x20000000;
+
+pub const CLONE_NEWNS_MASK: usize = CLONE_NEWUTS | CLONE_NEWIPC | CLONE_NEWPID;
+
+/// The namespaces of a task.
+pub struct NsProxy {
+    pub uts_ns: Arc<UtsNamespace>,
+    pub ipc_ns: Arc<IpcNamespace>,
+    /// Where the children of the task get their pids.
+    pub pid_ns_for_children: Arc<PidNamespace>,
+}
+
+impl NsProxy {
+    /// The namespaces of a child cloned with `flags`: new ones for the
+    /// CLONE_NEW* flags among them, and these for the others.
+    pub fn copy_namespaces(self: &Arc<Self>, flags: usize) -> LinuxResult<Arc<Self>> {
+        if (flags & CLONE_NEWNS_MASK) == 0 {
+            return Ok(self.clone());
+        }
+        let uts_ns = if (flags & CLONE_NEWUTS) != 0 {
+            Arc::new(self.uts_ns.copy())
+        } else {
+            self.uts_ns.clone()
+        };
+        let ipc_ns = if (flags & CLONE_NEWIPC) != 0 {
+            Arc::new(IpcNamespace::new())
+        } else {
+            self.ipc_ns.clone()
+        };
+        let pid_ns_for_children = if (flags & CLONE_NEWPID) != 0 {
+            Arc::new(PidNamespace::new_child(&self.pid_ns_for_children)?)
+        } else {
+            self.pid_ns_for_children.clone()
+        };
+        info!("copy_namespaces: flags {:#x}", flags & CLONE_NEWNS_MASK);
+        Ok(Arc::new(Self { uts_ns, ipc_ns, pid_ns_for_children }))
+    }
+}
+
+/// The namespaces of init, which are all the root ones.
+pub fn init_nsproxy() -> Arc<NsProxy> {
+    INIT_NSPROXY.clone()
+}
+
+/// The root pid namespace.
+pub fn init_pid_ns() -> Arc<PidNamespace> {
+    INIT_NSPROXY.pid_ns_for_children.clone()
+}
+
+pub fn init() {
+    info!("Initialize namespaces ...");
+    INIT_NSPROXY.init_by(Arc::new(NsProxy {
+        uts_ns: Arc::new(UtsNamespace::new()),
+        ipc_ns: Arc::new(IpcNamespace::new()),
+        pid_ns_for_children: Arc::new(PidNamespace::new_root()),
+    }));
+}
+
+static INIT_NSPROXY: LazyInit<Arc<NsProxy>> = LazyInit::new();
@@ -0,0 +1,161 @@
+//! Pid namespaces.
+//!
+//! The kernel knows a task by its tid, which is unique in the system. A
+//! pid namespace numbers the tasks created in it, and in the namespaces
+//! below it, from 1 on, and the pids a task passes to and gets from
+//! syscalls are the ones of its own namespace. Tasks in the namespaces
+//! above it are not there. The root namespace numbers the tasks by their
+//! tids, so it needs no table.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use spinbase::SpinNoIrq;
+
+/// Pids of a namespace are below it.
+pub const PID_MAX: usize = 32768;
+/// Namespaces nest at most this deep below the root.
+const MAX_PID_NS_LEVEL: usize = 32;
+/// Pids start over from it once they have reached [`PID_MAX`], to leave
+/// the low ones of daemons alone.
+const RESERVED_PIDS: usize = 300;
+
+struct PidMap {
+    /// The last pid handed out.
+    last: usize,
+    tids: BTreeMap<usize, usize>,
+    pids: BTreeMap<usize, usize>,
+    /// Its init has exited: no task may join it anymore.
+    dead: bool,
+}
+
+impl PidMap {
+    const fn new() -> Self {
+        Self {
+            last: 0,
+            tids: BTreeMap::new(),
+            pids: BTreeMap::new(),
+            dead: false,
+        }
+    }
+
+    fn alloc(&mut self, tid: usize) -> LinuxResult<usize> {
+        if self.dead {
+            return Err(LinuxError::ENOMEM);
+        }
+        let min = if self.last == 0 { 1 } else { RESERVED_PIDS };
+        let pid = (self.last + 1..PID_MAX)
+            .chain(min..=self.last)
+            .find(|pid| !self.tids.contains_key(pid))
+            .ok_or(LinuxError::EAGAIN)?;
+        self.last = pid;
+        self.tids.insert(pid, tid);
+        self.pids.insert(tid, pid);
+        Ok(pid)
+    }
+
+    fn free(&mut self, tid: usize) {
+        if let Some(pid) = self.pids.remove(&tid) {
+            self.tids.remove(&pid);
+            if pid == 1 {
+                self.dead = true;
+            }
+        }
+    }
+}
+
+pub struct PidNamespace {
+    /// Of the root namespace, 0.
+    level: usize,
+    parent: Option<Arc<PidNamespace>>,
+    map: SpinNoIrq<PidMap>,
+}
+
+impl PidNamespace {
+    pub(crate) fn new_root() -> Self {
+        Self {
+            level: 0,
+            parent: None,
+            map: SpinNoIrq::new(PidMap::new()),
+        }
+    }
+
+    pub(crate) fn new_child(parent: &Arc<Self>) -> LinuxResult<Self> {
+        if parent.level >= MAX_PID_NS_LEVEL {
+            return Err(LinuxError::ENOSPC);
+        }
+        Ok(Self {
+            level: parent.level + 1,
+            parent: Some(parent.clone()),
+            map: SpinNoIrq::new(PidMap::new()),
+        })
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn parent(&self) -> Option<&Arc<PidNamespace>> {
+        self.parent.as_ref()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    /// Gives the new task `tid` a pid here and in each namespace above,
+    /// and returns the one here.
+    pub fn alloc_pid(&self, tid: usize) -> LinuxResult<usize> {
+        let mut ns = Some(self);
+        while let Some(cur) = ns.filter(|ns| !ns.is_root()) {
+            if let Err(e) = cur.map.lock().alloc(tid) {
+                self.free_pid(tid);
+                return Err(e);
+            }
+            ns = cur.parent.as_deref();
+        }
+        Ok(self.pid_nr(tid).unwrap())
+    }
+
+    /// Frees the pids of `tid`, when the task has been released.
+    pub fn free_pid(&self, tid: usize) {
+        let mut ns = Some(self);
+        while let Some(cur) = ns.filter(|ns| !ns.is_root()) {
+            cur.map.lock().free(tid);
+            ns = cur.parent.as_deref();
+        }
+    }
+
+    /// The pid of the task `tid` here, or `None` if it is not here.
+    pub fn pid_nr(&self, tid: usize) -> Option<usize> {
+        if self.is_root() {
+            return Some(tid);
+        }
+        self.map.lock().pids.get(&tid).copied()
+    }
+
+    /// The tid of the task with the pid `nr` here, if there is one.
+    pub fn find_tid(&self, nr: usize) -> Option<usize> {
+        if self.is_root() {
+            return Some(nr);
+        }
+        self.map.lock().tids.get(&nr).copied()
+    }
+
+    /// Makes [`alloc_pid`](Self::alloc_pid) fail from now on, as its init
+    /// is exiting.
+    pub fn disable_pid_allocation(&self) {
+        self.map.lock().dead = true;
+    }
+
+    /// The tid of its init, which reaps the orphans in it.
+    pub fn child_reaper(&self) -> Option<usize> {
+        self.find_tid(1)
+    }
+
+    /// The tids of all tasks here, its own and those of the namespaces
+    /// below it. Not for the root one, where they are all.
+    pub fn tids(&self) -> alloc::vec::Vec<usize> {
+        self.map.lock().pids.keys().copied().collect()
+    }
+}
@@ -0,0 +1,63 @@
+//! UTS namespaces: the host and domain names that uname() tells, and
+//! sethostname() and setdomainname() set.
+
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use spinbase::SpinNoIrq;
+
+/// Bytes of a name at most, without the NUL.
+pub const UTS_LEN: usize = 64;
+
+#[derive(Clone)]
+struct UtsName {
+    nodename: Vec<u8>,
+    domainname: Vec<u8>,
+}
+
+pub struct UtsNamespace {
+    name: SpinNoIrq<UtsName>,
+}
+
+impl UtsNamespace {
+    pub(crate) fn new() -> Self {
+        Self {
+            name: SpinNoIrq::new(UtsName {
+                nodename: b"(none)".to_vec(),
+                domainname: b"(none)".to_vec(),
+            }),
+        }
+    }
+
+    /// A new namespace, with its names to begin with.
+    pub(crate) fn copy(&self) -> Self {
+        Self {
+            name: SpinNoIrq::new(self.name.lock().clone()),
+        }
+    }
+
+    pub fn nodename(&self) -> Vec<u8> {
+        self.name.lock().nodename.clone()
+    }
+
+    pub fn domainname(&self) -> Vec<u8> {
+        self.name.lock().domainname.clone()
+    }
+
+    /// Fails with EINVAL if `name` is longer than [`UTS_LEN`].
+    pub fn set_nodename(&self, name: &[u8]) -> LinuxResult {
+        if name.len() > UTS_LEN {
+            return Err(LinuxError::EINVAL);
+        }
+        self.name.lock().nodename = name.to_vec();
+        Ok(())
+    }
+
+    /// Fails with EINVAL if `name` is longer than [`UTS_LEN`].
+    pub fn set_domainname(&self, name: &[u8]) -> LinuxResult {
+        if name.len() > UTS_LEN {
+            return Err(LinuxError::EINVAL);
+        }
+        self.name.lock().domainname = name.to_vec();
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! Mappings of device memory in the kernel space.
+//!
+//! The MMIO regions of the platform config are in the linear mapping from
+//! boot, uncached, and [`ioremap`] gives their linear addresses. The other
+//! device memory, of the device tree or of the BARs of PCI functions, is
+//! mapped in the ioremap area, [`IOREMAP_VADDR`], which is under a root
+//! entry the kernel space has already, so the page tables of the processes
+//! see it too.
+
+use alloc::collections::BTreeMap;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::mem::{IOREMAP_SIZE, IOREMAP_VADDR};
+use spinbase::SpinNoIrq;
+
+use crate::paging::{self, MappingFlags, PagingError, PagingResult};
+
+/// The ranges of the area in use, from their start to their end.
+static USED: SpinNoIrq<BTreeMap<usize, usize>> = SpinNoIrq::new(BTreeMap::new());
+
+fn reserve(size: usize) -> PagingResult<usize> {
+    let mut used = USED.lock();
+    let mut start = IOREMAP_VADDR;
+    for (&s, &e) in used.iter() {
+        if s - start >= size {
+            break;
+        }
+        start = e;
+    }
+    if start + size > IOREMAP_VADDR + IOREMAP_SIZE {
+        return Err(PagingError::NoMemory);
+    }
+    used.insert(start, start + size);
+    Ok(start)
+}
+
+fn release(start: usize) {
+    USED.lock().remove(&start);
+}
+
+/// Whether `[paddr, paddr + size)` is in an MMIO region mapped at boot.
+fn is_boot_mapped(paddr: usize, size: usize) -> bool {
+    memory_regions().any(|r| {
+        r.flags.contains(MemRegionFlags::DEVICE)
+            && r.paddr.as_usize() <= paddr
+            && paddr + size <= r.paddr.as_usize() + r.size
+    })
+}
+
+/// Device memory mapped by [`ioremap`]. It stays mapped until [`iounmap`],
+/// even if it is dropped, as for the devices that are never removed.
+pub struct IoMem {
+    paddr: PhysAddr,
+    vaddr: VirtAddr,
+    size: usize,
+    /// Whether it was mapped in the ioremap area, rather than being in the
+    /// linear mapping.
+    mapped: bool,
+}
+
+impl IoMem {
+    pub fn paddr(&self) -> PhysAddr {
+        self.paddr
+    }
+
+    pub fn vaddr(&self) -> VirtAddr {
+        self.vaddr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The start of the memory, for the drivers that take a pointer. It is
+    /// not to be used after [`iounmap`].
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.vaddr.as_mut_ptr()
+    }
+
+    /// The register of type `T` at `offset`. Panics if it is not all in
+    /// the memory or is misaligned.
+    pub fn reg<T: Copy>(&self, offset: usize) -> VolatileRef<'_, T> {
+        assert!(offset + size_of::<T>() <= self.size, "ioremap: {:#x} out of range", offset);
+        let ptr = (self.vaddr.as_usize() + offset) as *mut T;
+        assert!(ptr as usize % align_of::<T>() == 0, "ioremap: {:#x} misaligned", offset);
+        VolatileRef {
+            ptr,
+            _mem: PhantomData,
+        }
+    }
+}
+
+/// A register of device memory, which is read and written with volatile
+/// accesses of its whole width.
+#[derive(Clone, Copy)]
+pub struct VolatileRef<'a, T> {
+    ptr: *mut T,
+    _mem: PhantomData<&'a IoMem>,
+}
+
+impl<T: Copy> VolatileRef<'_, T> {
+    #[inline]
+    pub fn read(self) -> T {
+        unsafe { self.ptr.read_volatile() }
+    }
+
+    #[inline]
+    pub fn write(self, val: T) {
+        unsafe { self.ptr.write_volatile(val) }
+    }
+}
+
+/// Maps the `size` bytes of device memory at `paddr`, uncached.
+pub fn ioremap(paddr: usize, size: usize) -> PagingResult<IoMem> {
+    if size == 0 {
+        return Err(PagingError::NotAligned);
+    }
+    if is_boot_mapped(paddr, size) {
+        return Ok(IoMem {
+            paddr: paddr.into(),
+            vaddr: phys_to_virt(paddr.into()),
+            size,
+            mapped: false,
+        });
+    }
+    // Whole pages, with the memory at its offset in the first.
+    let start = paddr & !(PAGE_SIZE_4K - 1);
+    let len = (paddr + size - start + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
+    let vaddr = reserve(len)?;
+    let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE;
+    if let Err(e) = paging::map_kernel_region(vaddr.into(), start.into(), len, flags) {
+        release(vaddr);
+        return Err(e);
+    }
+    debug!("ioremap: [PA:{:#x}, PA:{:#x}) at {:#x}", paddr, paddr + size, vaddr);
+    Ok(IoMem {
+        paddr: paddr.into(),
+        vaddr: (vaddr + paddr - start).into(),
+        size,
+        mapped: true,
+    })
+}
+
+/// Unmaps `mem`, which nothing may access anymore.
+pub fn iounmap(mem: IoMem) {
+    if !mem.mapped {
+        return;
+    }
+    let vaddr = mem.vaddr.as_usize() & !(PAGE_SIZE_4K - 1);
+    let len = (mem.vaddr.as_usize() + mem.size - vaddr + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
+    unsafe {
+        paging::unmap_kernel_region(vaddr.into(), len).expect("ioremap: area not mapped");
+    }
+    release(vaddr);
+}
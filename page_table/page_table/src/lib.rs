@@ -23,9 +23,11 @@
 
 #[macro_use]
 extern crate log;
+extern crate alloc;
 
 mod arch;
 mod bits64;
+pub mod ioremap;
 pub mod paging;
 
 use memory_addr::{PhysAddr, VirtAddr};
@@ -5,6 +5,7 @@ use core::cell::OnceCell;
 use crate::PagingIf;
 
 use axhal::arch::write_page_table_root;
+use spinbase::SpinNoIrq;
 use axhal::mem::{phys_to_virt, virt_to_phys, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
 #[doc(no_inline)]
@@ -65,6 +66,9 @@ pub fn reuse_page_table_root() {
     }
 }
 
+/// The kernel space is the upper half of the root, but on AArch64, where
+/// it has its own root in TTBR1_EL1, which all the processes share.
+#[cfg(not(target_arch = "aarch64"))]
 fn sync_kernel_mappings(src_paddr: PhysAddr, dst_paddr: PhysAddr) {
     let dst_ptr = phys_to_virt(dst_paddr).as_mut_ptr();
     let src_ptr = phys_to_virt(src_paddr).as_ptr();
@@ -84,6 +88,81 @@ fn sync_kernel_mappings(src_paddr: PhysAddr, dst_paddr: PhysAddr) {
 pub fn pgd_alloc() -> PageTable {
     let pgtable = unsafe { KERNEL_PAGE_TABLE.get().unwrap().clone() };
     /* Copy kernel mappings */
+    #[cfg(not(target_arch = "aarch64"))]
     sync_kernel_mappings(kernel_pg_root_paddr(), pgtable.root_paddr());
     pgtable
 }
+
+/// Writes `bytes` over the kernel text at `vaddr`, which is mapped
+/// read-only: each page is made writable for the write, and read-only
+/// again. The instruction caches are the caller's to flush.
+///
+/// # Safety
+///
+/// No CPU may be executing the bytes replaced, unless they are replaced
+/// by a single aligned store, and callers must serialize patching.
+pub unsafe fn patch_kernel_text(vaddr: VirtAddr, bytes: &[u8]) -> PagingResult {
+    let pgtable = KERNEL_PAGE_TABLE.get_mut().ok_or(PagingError::NotMapped)?;
+    let mut done = 0;
+    while done < bytes.len() {
+        let addr = vaddr + done;
+        let (_, flags, size) = pgtable.query(addr)?;
+        let n = (size as usize - addr.align_offset(size)).min(bytes.len() - done);
+        pgtable.update(addr, None, Some(flags | MappingFlags::WRITE))?;
+        axhal::arch::flush_tlb(Some(addr));
+        core::ptr::copy_nonoverlapping(bytes[done..].as_ptr(), addr.as_mut_ptr(), n);
+        pgtable.update(addr, None, Some(flags))?;
+        axhal::arch::flush_tlb(Some(addr));
+        done += n;
+    }
+    Ok(())
+}
+
+/// Serializes changing the kernel mappings other than the text.
+static KERNEL_MAP_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+/// Maps the `size` bytes of the kernel space at `vaddr`, which are not
+/// mapped, to those at `paddr`, in 4K pages. They must be under a root
+/// entry the kernel space has already, since the page tables of the
+/// processes have copies of the root entries of the kernel space.
+pub fn map_kernel_region(
+    vaddr: VirtAddr, paddr: PhysAddr, size: usize, flags: MappingFlags
+) -> PagingResult {
+    let _guard = KERNEL_MAP_LOCK.lock();
+    let pgtable = unsafe { KERNEL_PAGE_TABLE.get_mut() }.ok_or(PagingError::NotMapped)?;
+    pgtable.map_region(vaddr, paddr, size, flags, false)?;
+    axhal::arch::flush_tlb_kernel_range(vaddr, size);
+    Ok(())
+}
+
+/// Unmaps the `size` bytes of the kernel space at `vaddr`, mapped by
+/// [`map_kernel_region`]. The frames are the caller's to free.
+///
+/// # Safety
+///
+/// No CPU may be using the range anymore.
+pub unsafe fn unmap_kernel_region(vaddr: VirtAddr, size: usize) -> PagingResult {
+    let _guard = KERNEL_MAP_LOCK.lock();
+    let pgtable = KERNEL_PAGE_TABLE.get_mut().ok_or(PagingError::NotMapped)?;
+    pgtable.unmap_region(vaddr, size)?;
+    axhal::arch::flush_tlb_kernel_range(vaddr, size);
+    Ok(())
+}
+
+/// Changes the flags of the `size` bytes of the kernel space at `vaddr`,
+/// mapped by [`map_kernel_region`].
+///
+/// # Safety
+///
+/// No CPU may be using the range in a way the new flags forbid.
+pub unsafe fn protect_kernel_region(
+    vaddr: VirtAddr, size: usize, flags: MappingFlags
+) -> PagingResult {
+    let _guard = KERNEL_MAP_LOCK.lock();
+    let pgtable = KERNEL_PAGE_TABLE.get_mut().ok_or(PagingError::NotMapped)?;
+    for offset in (0..size).step_by(PAGE_SIZE_4K) {
+        pgtable.update(vaddr + offset, None, Some(flags))?;
+    }
+    axhal::arch::flush_tlb_kernel_range(vaddr, size);
+    Ok(())
+}
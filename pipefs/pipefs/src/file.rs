@@ -1,14 +1,20 @@
 use core::cmp::min;
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult, VfsError};
 use spin::RwLock;
 use axtype::PAGE_SIZE;
 use axtype::{O_WRONLY, O_RDWR, O_NONBLOCK};
 use axfs_vfs::alloc_ino;
+use axio::PollState;
+use wait_queue::WaitQueue;
 
 const PIPE_CAPACITY: usize = 16 * PAGE_SIZE;
 
+/// Writes of at most this many bytes are not interleaved with others.
+pub const PIPE_BUF: usize = PAGE_SIZE;
+
 /// The pipe node in the RAM filesystem.
 pub struct PipeNode {
     buf: RwLock<VecDeque<u8>>,
@@ -18,6 +24,8 @@ pub struct PipeNode {
     write_nonblock: AtomicBool,
     read_ready: AtomicBool,
     write_ready: AtomicBool,
+    /// Tasks sleeping on any change of the pipe.
+    waiters: WaitQueue,
     ino: usize,
     uid: u32,
     gid: u32,
@@ -33,24 +41,27 @@ impl PipeNode {
             write_nonblock: AtomicBool::new(false),
             read_ready: AtomicBool::new(false),
             write_ready: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
             ino: alloc_ino(),
             uid,
             gid,
         }
     }
 
-    pub fn init_pipe_node(uid: u32, gid: u32) -> Self {
-        let node = PipeNode::new(uid, gid);
-        let _ = node.readers.fetch_add(1, Ordering::Relaxed);
-        let _ = node.writers.fetch_add(1, Ordering::Relaxed);
+    /// Creates an anonymous pipe and returns its read and write ends.
+    pub fn pipe(uid: u32, gid: u32, nonblock: bool) -> (PipeEnd, PipeEnd) {
+        let node = Arc::new(PipeNode::new(uid, gid));
+        node.readers.store(1, Ordering::Relaxed);
+        node.writers.store(1, Ordering::Relaxed);
         node.read_ready.store(true, Ordering::Relaxed);
         node.write_ready.store(true, Ordering::Relaxed);
-        node
+        (PipeEnd::new(node.clone(), false, nonblock), PipeEnd::new(node, true, nonblock))
     }
 
     fn open_for_read(&self, block: bool) -> VfsResult {
         info!("open_for_read ...");
         let _ = self.readers.fetch_add(1, Ordering::Relaxed);
+        self.waiters.notify_all(false);
         if self.read_nonblock.load(Ordering::Relaxed) {
             return Ok(());
         }
@@ -59,10 +70,12 @@ impl PipeNode {
             return Ok(());
         }
 
-        while self.writers.load(Ordering::Relaxed) == 0 {
-            run_queue::yield_now();
+        if let Err(err) = self.waiters.wait_until_interruptible(|| self.writers.load(Ordering::Relaxed) != 0) {
+            let _ = self.readers.fetch_sub(1, Ordering::Relaxed);
+            return Err(err.into());
         }
         self.read_ready.store(true, Ordering::Relaxed);
+        self.waiters.notify_all(false);
         info!("open_for_read ok!");
         Ok(())
     }
@@ -70,6 +83,7 @@ impl PipeNode {
     fn open_for_write(&self, block: bool) -> VfsResult {
         info!("open_for_write ...");
         let _ = self.writers.fetch_add(1, Ordering::Relaxed);
+        self.waiters.notify_all(false);
         if self.write_nonblock.load(Ordering::Relaxed) {
             if self.readers.load(Ordering::Relaxed) == 0 {
                 return Err(VfsError::NoDevOrAddr);
@@ -84,13 +98,96 @@ impl PipeNode {
             return Ok(());
         }
 
-        while self.readers.load(Ordering::Relaxed) == 0 {
-            run_queue::yield_now();
+        if let Err(err) = self.waiters.wait_until_interruptible(|| self.readers.load(Ordering::Relaxed) != 0) {
+            let _ = self.writers.fetch_sub(1, Ordering::Relaxed);
+            return Err(err.into());
         }
         self.write_ready.store(true, Ordering::Relaxed);
+        self.waiters.notify_all(false);
         info!("open_for_write ok!");
         Ok(())
     }
+
+    /// Readable while a read would not sleep, with data or without
+    /// writers, and writable while a write of [`PIPE_BUF`] bytes would not,
+    /// with room or without readers.
+    fn poll_state(&self) -> PollState {
+        let len = self.buf.read().len();
+        PollState {
+            readable: len != 0 || self.writers.load(Ordering::Relaxed) == 0,
+            writable: PIPE_CAPACITY - len >= PIPE_BUF || self.readers.load(Ordering::Relaxed) == 0,
+        }
+    }
+
+    /// Takes what is in the buffer, up to `buf.len()` bytes. It sleeps
+    /// while the buffer is empty and there are writers, and returns 0 at
+    /// the end of data, when there are none.
+    fn read_buf(&self, buf: &mut [u8], nonblock: bool) -> VfsResult<usize> {
+        loop {
+            let mut src = self.buf.write();
+            if !src.is_empty() {
+                let size = min(buf.len(), src.len());
+                for (dst, byte) in buf.iter_mut().zip(src.drain(..size)) {
+                    *dst = byte;
+                }
+                drop(src);
+                self.waiters.notify_all(false);
+                return Ok(size);
+            }
+            drop(src);
+
+            if self.writers.load(Ordering::Relaxed) == 0 {
+                return Ok(0);
+            }
+            if nonblock {
+                return Err(VfsError::WouldBlock);
+            }
+            self.waiters.wait_until_interruptible(|| {
+                !self.buf.read().is_empty() || self.writers.load(Ordering::Relaxed) == 0
+            })?;
+        }
+    }
+
+    /// Puts all of `buf` into the buffer, sleeping while it is full unless
+    /// `nonblock`. A write of at most [`PIPE_BUF`] bytes goes in at once,
+    /// larger ones may be split. Writing without readers is a broken pipe.
+    fn write_buf(&self, buf: &[u8], nonblock: bool) -> VfsResult<usize> {
+        let atomic = buf.len() <= PIPE_BUF;
+        let mut written = 0;
+        while written < buf.len() {
+            if self.readers.load(Ordering::Relaxed) == 0 {
+                info!("write_buf: no readers after {} bytes", written);
+                return if written > 0 { Ok(written) } else { Err(VfsError::BrokenPipe) };
+            }
+
+            let wanted = buf.len() - written;
+            let need = if atomic { wanted } else { 1 };
+            let mut dst = self.buf.write();
+            let room = PIPE_CAPACITY - dst.len();
+            if room >= need {
+                let size = min(room, wanted);
+                dst.extend(&buf[written..written + size]);
+                written += size;
+                drop(dst);
+                self.waiters.notify_all(false);
+                continue;
+            }
+            drop(dst);
+
+            if nonblock {
+                return if written > 0 { Ok(written) } else { Err(VfsError::WouldBlock) };
+            }
+            let ready = self.waiters.wait_until_interruptible(|| {
+                PIPE_CAPACITY - self.buf.read().len() >= need
+                    || self.readers.load(Ordering::Relaxed) == 0
+            });
+            // What is written already is reported, not the signal.
+            if let Err(err) = ready {
+                return if written > 0 { Ok(written) } else { Err(err.into()) };
+            }
+        }
+        Ok(written)
+    }
 }
 
 impl VfsNodeOps for PipeNode {
@@ -111,6 +208,7 @@ impl VfsNodeOps for PipeNode {
         let r = self.readers.fetch_sub(1, Ordering::Relaxed);
         let w = self.writers.fetch_sub(1, Ordering::Relaxed);
         info!("---> pipe release! r {}, w {}", r, w);
+        self.waiters.notify_all(false);
         Ok(())
     }
 
@@ -124,56 +222,95 @@ impl VfsNodeOps for PipeNode {
 
     fn read_at(&self, pos: u64, buf: &mut [u8]) -> VfsResult<usize> {
         info!("read_at: pos {} buf {} ..", pos, buf.len());
-        if !self.read_nonblock.load(Ordering::Relaxed) {
-            while !self.write_ready.load(Ordering::Relaxed) {
-                run_queue::yield_now();
-            }
+        let nonblock = self.read_nonblock.load(Ordering::Relaxed);
+        if !nonblock {
+            self.waiters.wait_until_interruptible(|| self.write_ready.load(Ordering::Relaxed))?;
         }
-        while self.buf.read().is_empty() {
-            if self.read_nonblock.load(Ordering::Relaxed) {
-                return Err(VfsError::WouldBlock);
-            }
-            let writers = self.writers.load(Ordering::Relaxed);
-            if writers == 0 {
-                return Ok(0);
-            }
-            info!("read_at WouldBlock! writers {}", writers);
-            run_queue::yield_now();
-        }
-        let size = min(buf.len(), self.buf.read().len());
-        let src = &mut self.buf.write();
-        for i in 0..size {
-            buf[i] = src.pop_front().unwrap();
-        }
-        return Ok(size);
+        self.read_buf(buf, nonblock)
     }
 
     fn write_at(&self, pos: u64, buf: &[u8]) -> VfsResult<usize> {
         info!("write_at: pos {} buf {} ..", pos, buf.len());
-        if !self.write_nonblock.load(Ordering::Relaxed) {
-            while !self.read_ready.load(Ordering::Relaxed) {
-                run_queue::yield_now();
-            }
-            let readers = self.readers.load(Ordering::Relaxed);
-            info!("writer_at: BlockMode readers {}", readers);
-            if readers == 0 {
-                return Err(VfsError::BrokenPipe);
-            }
+        assert_eq!(pos, 0);
+        let nonblock = self.write_nonblock.load(Ordering::Relaxed);
+        if !nonblock {
+            self.waiters.wait_until_interruptible(|| self.read_ready.load(Ordering::Relaxed))?;
+        }
+        self.write_buf(buf, nonblock)
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(self.poll_state())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// One end of an anonymous pipe. Unlike a FIFO, which has no ends of its
+/// own, each end counts as one reader or writer and has its own mode.
+pub struct PipeEnd {
+    pipe: Arc<PipeNode>,
+    write: bool,
+    nonblock: AtomicBool,
+}
+
+impl PipeEnd {
+    fn new(pipe: Arc<PipeNode>, write: bool, nonblock: bool) -> Self {
+        Self {
+            pipe,
+            write,
+            nonblock: AtomicBool::new(nonblock),
         }
+    }
 
-        while self.buf.read().len() >= PIPE_CAPACITY {
-            if self.write_nonblock.load(Ordering::Relaxed) {
-                return Err(VfsError::WouldBlock);
-            }
-            let readers = self.readers.load(Ordering::Relaxed);
-            info!("writer_at: WouldBlock! readers {}", readers);
-            run_queue::yield_now();
+    /// Switches the end between blocking and nonblocking mode.
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+}
+
+impl VfsNodeOps for PipeEnd {
+    fn release(&self) -> VfsResult {
+        let count = if self.write { &self.pipe.writers } else { &self.pipe.readers };
+        let _ = count.fetch_sub(1, Ordering::Relaxed);
+        self.pipe.waiters.notify_all(false);
+        Ok(())
+    }
+
+    fn get_ino(&self) -> usize {
+        self.pipe.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.pipe.get_attr()
+    }
+
+    fn read_at(&self, _pos: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.write {
+            return Err(VfsError::PermissionDenied);
         }
-        assert_eq!(pos, 0);
-        for i in 0..buf.len() {
-            self.buf.write().push_back(buf[i]);
+        self.pipe.read_buf(buf, self.nonblock.load(Ordering::Relaxed))
+    }
+
+    fn write_at(&self, _pos: u64, buf: &[u8]) -> VfsResult<usize> {
+        if !self.write {
+            return Err(VfsError::PermissionDenied);
         }
-        Ok(buf.len())
+        self.pipe.write_buf(buf, self.nonblock.load(Ordering::Relaxed))
+    }
+
+    /// Each end is ready only for what it is opened for.
+    fn poll(&self) -> VfsResult<PollState> {
+        let state = self.pipe.poll_state();
+        Ok(PollState {
+            readable: !self.write && state.readable,
+            writable: self.write && state.writable,
+        })
+    }
+
+    fn set_nonblock(&self, nonblock: bool) -> VfsResult {
+        PipeEnd::set_nonblock(self, nonblock);
+        Ok(())
     }
 
     impl_vfs_non_dir_default! {}
@@ -10,7 +10,7 @@ extern crate alloc;
 
 mod file;
 
-pub use self::file::PipeNode;
+pub use self::file::{PipeNode, PipeEnd, PIPE_BUF};
 
 /*
 pub use self::dir::DirNode;
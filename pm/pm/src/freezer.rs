@@ -0,0 +1,75 @@
+//! Freezing of user tasks: while frozen, no user code runs, and none of
+//! it sees the devices suspended.
+//!
+//! A task freezes on its way back to user mode, and stays in the kernel
+//! until thawed. A task blocked in the kernel needn't be woken up for it,
+//! it freezes on its way out once woken up.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use axerrno::{LinuxError, LinuxResult};
+use taskctx::TaskState;
+use wait_queue::WaitQueue;
+
+/// How long the tasks have to freeze, before the suspend is given up.
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(20);
+
+static PM_FREEZING: AtomicBool = AtomicBool::new(false);
+static THAW_WQ: WaitQueue = WaitQueue::new();
+
+/// Whether the user tasks are to freeze.
+pub fn freezing() -> bool {
+    PM_FREEZING.load(Ordering::Acquire)
+}
+
+/// Freezes the current task until the tasks are thawed, if they are to
+/// freeze. Called on the way back to user mode. Returns whether it has
+/// been frozen.
+pub fn try_to_freeze() -> bool {
+    if !freezing() {
+        return false;
+    }
+    debug!("freezer: task {} frozen", task::current().tid());
+    THAW_WQ.wait_until(|| !freezing());
+    true
+}
+
+/// The user tasks, but the current one, still to freeze: the ones that
+/// run or may run.
+fn nr_unfrozen() -> usize {
+    let current = task::current().tid();
+    task::all_tasks()
+        .iter()
+        .filter(|t| t.tid() != current && t.try_mm().is_some())
+        .filter(|t| matches!(t.sched_info.state(), TaskState::Running | TaskState::Runnable))
+        .count()
+}
+
+/// Freezes the user tasks but the current one, or thaws them again and
+/// fails with EBUSY if some don't freeze in time.
+pub fn freeze_processes() -> LinuxResult {
+    info!("Freezing user space processes ...");
+    PM_FREEZING.store(true, Ordering::Release);
+    let deadline = axhal::time::current_time() + FREEZE_TIMEOUT;
+    loop {
+        let todo = nr_unfrozen();
+        if todo == 0 {
+            break;
+        }
+        if axhal::time::current_time() >= deadline {
+            warn!("Freezing of tasks failed after {:?}: {} tasks refusing to freeze", FREEZE_TIMEOUT, todo);
+            thaw_processes();
+            return Err(LinuxError::EBUSY);
+        }
+        run_queue::sleep(Duration::from_millis(10));
+    }
+    info!("Freezing user space processes completed.");
+    Ok(())
+}
+
+/// Lets the frozen tasks return to user mode.
+pub fn thaw_processes() {
+    info!("Restarting tasks ...");
+    PM_FREEZING.store(false, Ordering::Release);
+    THAW_WQ.notify_all(false);
+}
@@ -0,0 +1,189 @@
+//! Power management: suspend-to-idle, and the suspend and resume
+//! callbacks of drivers and the scheduler.
+//!
+//! A suspend freezes the user tasks, suspends the devices in the reverse
+//! order of their registration, and idles the CPU with the interrupts on
+//! until a wakeup source fires. The devices are then resumed in the order
+//! of registration, and the tasks thawed.
+//!
+//! It is entered by writing "freeze" to /sys/power/state.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod freezer;
+mod wakeup;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use mutex::Mutex;
+use preempt_guard::NoPreempt;
+
+pub use freezer::{freeze_processes, freezing, thaw_processes, try_to_freeze};
+pub use wakeup::{disable_irq_wake, enable_irq_wake, pm_system_irq_wakeup, pm_wakeup_event};
+
+/// The only sleep state there is: the CPUs idle, and the memory and the
+/// devices keep their power.
+pub const PM_SUSPEND_TO_IDLE: usize = 1;
+
+/// Suspend and resume callbacks, of a driver or of the scheduler.
+///
+/// `suspend` may refuse with an error, which aborts the suspend: the ones
+/// suspended already are resumed. `resume` can't fail.
+pub struct PmOps {
+    pub name: &'static str,
+    pub suspend: fn() -> LinuxResult,
+    pub resume: fn(),
+}
+
+static PM_OPS: Mutex<Vec<&'static PmOps>> = Mutex::new(Vec::new());
+
+/// Held across a suspend: there is one at a time.
+static SUSPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// Registers `ops`, to be suspended before the ones registered earlier,
+/// and resumed after.
+pub fn register_pm_ops(ops: &'static PmOps) {
+    info!("PM: register {}", ops.name);
+    PM_OPS.lock().push(ops);
+}
+
+pub fn unregister_pm_ops(ops: &'static PmOps) {
+    PM_OPS.lock().retain(|o| !core::ptr::eq(*o, ops));
+}
+
+/// Resumes `ops`, in order.
+fn dpm_resume(ops: &[&'static PmOps]) {
+    for o in ops {
+        debug!("PM: resume {}", o.name);
+        (o.resume)();
+    }
+}
+
+/// Suspends `ops` in the reverse order. On an error, the ones suspended
+/// are resumed again.
+fn dpm_suspend(ops: &[&'static PmOps]) -> LinuxResult {
+    for (i, o) in ops.iter().enumerate().rev() {
+        debug!("PM: suspend {}", o.name);
+        if let Err(e) = (o.suspend)() {
+            warn!("PM: {} refused to suspend: {:?}", o.name, e);
+            dpm_resume(&ops[i + 1..]);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Idles this CPU until a wakeup event since `count`. Other interrupts
+/// are handled as they come, without switching tasks.
+fn s2idle_loop(count: usize) {
+    info!("PM: suspend-to-idle");
+    let _guard = NoPreempt::new();
+    axhal::arch::disable_irqs();
+    while !wakeup::pm_wakeup_pending(count) {
+        // Idling with preemption off is no lockup.
+        watchdog::touch_softlockup_watchdog();
+        axhal::arch::wait_for_irqs_and_enable();
+        axhal::arch::disable_irqs();
+    }
+    axhal::arch::enable_irqs();
+    info!("PM: resume from suspend-to-idle");
+}
+
+fn suspend_devices_and_enter(count: usize) -> LinuxResult {
+    let ops = PM_OPS.lock().clone();
+    dpm_suspend(&ops)?;
+    // A wakeup while the devices were suspended aborts at once.
+    if wakeup::pm_wakeup_pending(count) {
+        info!("PM: wakeup pending, aborting suspend");
+        dpm_resume(&ops);
+        return Err(LinuxError::EBUSY);
+    }
+    s2idle_loop(count);
+    dpm_resume(&ops);
+    Ok(())
+}
+
+/// Puts the system into the sleep `state`, and returns once it has woken
+/// up. Fails with EBUSY if a suspend is under way, or a wakeup event or a
+/// task that won't freeze aborts it.
+pub fn pm_suspend(state: usize) -> LinuxResult {
+    if state != PM_SUSPEND_TO_IDLE {
+        return Err(LinuxError::EINVAL);
+    }
+    let Some(_guard) = SUSPEND_LOCK.try_lock() else {
+        return Err(LinuxError::EBUSY);
+    };
+    let count = wakeup::begin_suspend();
+    let ret = freeze_processes().and_then(|_| {
+        let ret = suspend_devices_and_enter(count);
+        thaw_processes();
+        ret
+    });
+    wakeup::end_suspend();
+    ret
+}
+
+/// /sys/power/state: reads the states there are, and enters the one
+/// written.
+struct PowerStateNode;
+
+impl VfsNodeOps for PowerStateNode {
+    fn get_ino(&self) -> usize {
+        0
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::File,
+            0,
+            0,
+            0,
+            0,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let states = b"freeze\n";
+        let start = states.len().min(offset as usize);
+        let len = buf.len().min(states.len() - start);
+        buf[..len].copy_from_slice(&states[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if !task::current().cred().is_privileged() {
+            return Err(AxError::PermissionDenied);
+        }
+        let state = match buf.strip_suffix(b"\n").unwrap_or(buf) {
+            b"freeze" => PM_SUSPEND_TO_IDLE,
+            _ => return Err(AxError::InvalidInput),
+        };
+        match pm_suspend(state) {
+            Ok(()) => Ok(buf.len()),
+            Err(LinuxError::EBUSY) => Err(AxError::ResourceBusy),
+            Err(_) => Err(AxError::InvalidInput),
+        }
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// Creates /sys/power/state, in the sysfs mounted at /sys.
+pub fn init() -> LinuxResult {
+    let current = task::current();
+    let fs = current.fs.lock();
+    fs.create_dir(None, "/sys/power", 0, 0, 0o755)?;
+    fs.create_link(None, "/sys/power/state", Arc::new(PowerStateNode))?;
+    Ok(())
+}
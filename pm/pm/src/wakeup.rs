@@ -0,0 +1,55 @@
+//! Wakeup sources: the events that end a suspend, or abort one that is
+//! under way.
+
+use alloc::collections::BTreeSet;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spinbase::SpinNoIrq;
+
+/// The IRQs that wake the system up.
+static WAKEUP_IRQS: SpinNoIrq<BTreeSet<usize>> = SpinNoIrq::new(BTreeSet::new());
+
+/// Events reported so far. A suspend ends once it has changed.
+static WAKEUP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a suspend is under way, for the wakeup IRQs to count.
+static SUSPENDING: AtomicBool = AtomicBool::new(false);
+
+/// Makes `irq` wake the system up.
+pub fn enable_irq_wake(irq: usize) {
+    WAKEUP_IRQS.lock().insert(irq);
+}
+
+pub fn disable_irq_wake(irq: usize) {
+    WAKEUP_IRQS.lock().remove(&irq);
+}
+
+/// Reports a wakeup event, for drivers whose devices have one to signal
+/// but no IRQ of their own to.
+pub fn pm_wakeup_event() {
+    WAKEUP_COUNT.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Counts `irq` as a wakeup event, if it is a wakeup IRQ and a suspend is
+/// under way. Called on each IRQ.
+pub fn pm_system_irq_wakeup(irq: usize) {
+    if SUSPENDING.load(Ordering::Acquire) && WAKEUP_IRQS.lock().contains(&irq) {
+        debug!("PM: wakeup by IRQ {}", irq);
+        pm_wakeup_event();
+    }
+}
+
+/// Begins a suspend, returning the count the events are to be checked
+/// against.
+pub(crate) fn begin_suspend() -> usize {
+    SUSPENDING.store(true, Ordering::Release);
+    WAKEUP_COUNT.load(Ordering::Acquire)
+}
+
+pub(crate) fn end_suspend() {
+    SUSPENDING.store(false, Ordering::Release);
+}
+
+/// Whether a wakeup event has been reported since `count`.
+pub(crate) fn pm_wakeup_pending(count: usize) -> bool {
+    WAKEUP_COUNT.load(Ordering::Acquire) != count
+}
@@ -0,0 +1,26 @@
+//! No counters here.
+
+use axerrno::{LinuxError, LinuxResult};
+use crate::{Counter, PerfEventAttr};
+
+pub fn probe() -> bool {
+    false
+}
+
+pub fn event_idx(_attr: &PerfEventAttr) -> LinuxResult<usize> {
+    Err(LinuxError::ENODEV)
+}
+
+pub fn alloc_counter(_event_idx: usize, _exclude_user: bool, _exclude_kernel: bool) -> LinuxResult<Counter> {
+    Err(LinuxError::ENODEV)
+}
+
+pub fn release_counter(_counter: &Counter) {}
+
+pub fn start_counter(_counter: &Counter) {}
+
+pub fn stop_counter(_counter: &Counter) {}
+
+pub fn read_counter(_counter: &Counter) -> u64 {
+    0
+}
@@ -0,0 +1,11 @@
+//! Architecture-specific counters.
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        mod riscv;
+        pub use self::riscv::*;
+    } else {
+        mod dummy;
+        pub use self::dummy::*;
+    }
+}
@@ -0,0 +1,160 @@
+//! RISC-V counters, programmed by the SBI PMU extension: the firmware
+//! maps an event to a counter, starts and stops it, and we read it from
+//! its CSR, or through the firmware if it is one of its own.
+
+use core::arch::asm;
+use axerrno::{LinuxError, LinuxResult};
+use crate::{Counter, PerfEventAttr};
+use crate::{PERF_TYPE_HARDWARE, PERF_TYPE_HW_CACHE, PERF_COUNT_HW_MAX};
+use crate::{PERF_COUNT_HW_CACHE_MAX, PERF_COUNT_HW_CACHE_OP_MAX, PERF_COUNT_HW_CACHE_RESULT_MAX};
+
+const SBI_EXT_BASE: usize = 0x10;
+const SBI_EXT_BASE_PROBE_EXT: usize = 3;
+
+const SBI_EXT_PMU: usize = 0x504D55;
+const SBI_EXT_PMU_NUM_COUNTERS: usize = 0;
+const SBI_EXT_PMU_COUNTER_GET_INFO: usize = 1;
+const SBI_EXT_PMU_COUNTER_CFG_MATCH: usize = 2;
+const SBI_EXT_PMU_COUNTER_START: usize = 3;
+const SBI_EXT_PMU_COUNTER_STOP: usize = 4;
+const SBI_EXT_PMU_COUNTER_FW_READ: usize = 5;
+
+/* Flags of counter_config_matching() */
+const SBI_PMU_CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+const SBI_PMU_CFG_FLAG_SET_SINH: usize = 1 << 5;
+const SBI_PMU_CFG_FLAG_SET_UINH: usize = 1 << 6;
+const SBI_PMU_CFG_FLAG_SET_MINH: usize = 1 << 7;
+/* Flags of counter_start() and counter_stop() */
+const SBI_PMU_START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+const SBI_PMU_STOP_FLAG_RESET: usize = 1 << 0;
+
+const SBI_ERR_NOT_SUPPORTED: isize = -2;
+
+/// The first of the counter CSRs, `cycle`.
+const CSR_CYCLE: usize = 0xc00;
+
+fn sbi_call(eid: usize, fid: usize, args: [usize; 5]) -> Result<usize, isize> {
+    let (error, value): (isize, usize);
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    if error == 0 { Ok(value) } else { Err(error) }
+}
+
+fn pmu_call(fid: usize, args: [usize; 5]) -> Result<usize, isize> {
+    sbi_call(SBI_EXT_PMU, fid, args)
+}
+
+/// The SBI event of `attr`. Both the hardware and the cache events are
+/// numbered as perf does, but the hardware ones from 1.
+pub fn event_idx(attr: &PerfEventAttr) -> LinuxResult<usize> {
+    let config = attr.config as usize;
+    match attr.type_ {
+        PERF_TYPE_HARDWARE if config < PERF_COUNT_HW_MAX => Ok(config + 1),
+        PERF_TYPE_HW_CACHE => {
+            let (id, op, result) = (config & 0xff, (config >> 8) & 0xff, (config >> 16) & 0xff);
+            if id >= PERF_COUNT_HW_CACHE_MAX
+                || op >= PERF_COUNT_HW_CACHE_OP_MAX
+                || result >= PERF_COUNT_HW_CACHE_RESULT_MAX
+            {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok((1 << 16) | (id << 3) | (op << 1) | result)
+        }
+        _ => Err(LinuxError::ENOENT),
+    }
+}
+
+/// Whether the firmware has the PMU extension.
+pub fn probe() -> bool {
+    matches!(sbi_call(SBI_EXT_BASE, SBI_EXT_BASE_PROBE_EXT, [SBI_EXT_PMU, 0, 0, 0, 0]), Ok(v) if v != 0)
+}
+
+fn num_counters() -> usize {
+    pmu_call(SBI_EXT_PMU_NUM_COUNTERS, [0; 5]).unwrap_or(0)
+}
+
+/// Has the firmware map `event_idx` to a free counter, stopped and
+/// cleared.
+pub fn alloc_counter(event_idx: usize, exclude_user: bool, exclude_kernel: bool) -> LinuxResult<Counter> {
+    let num = num_counters().min(usize::BITS as usize);
+    if num == 0 {
+        return Err(LinuxError::ENODEV);
+    }
+    let mask = if num == usize::BITS as usize { usize::MAX } else { (1 << num) - 1 };
+    // The machine mode, the firmware, is never counted.
+    let mut flags = SBI_PMU_CFG_FLAG_CLEAR_VALUE | SBI_PMU_CFG_FLAG_SET_MINH;
+    if exclude_user {
+        flags |= SBI_PMU_CFG_FLAG_SET_UINH;
+    }
+    if exclude_kernel {
+        flags |= SBI_PMU_CFG_FLAG_SET_SINH;
+    }
+    let idx = pmu_call(SBI_EXT_PMU_COUNTER_CFG_MATCH, [0, mask, flags, event_idx, 0])
+        .map_err(|e| if e == SBI_ERR_NOT_SUPPORTED { LinuxError::ENOENT } else { LinuxError::EINVAL })?;
+    let info = pmu_call(SBI_EXT_PMU_COUNTER_GET_INFO, [idx, 0, 0, 0, 0]).map_err(|_| LinuxError::EINVAL)?;
+    // [11:0] CSR, [17:12] width - 1, [XLEN-1] firmware.
+    Ok(Counter {
+        idx,
+        csr: info & 0xfff,
+        width: ((info >> 12) & 0x3f) as u32 + 1,
+        firmware: (info >> (usize::BITS - 1)) != 0,
+    })
+}
+
+/// Stops the counter and frees it for other events.
+pub fn release_counter(counter: &Counter) {
+    let _ = pmu_call(SBI_EXT_PMU_COUNTER_STOP, [counter.idx, 1, SBI_PMU_STOP_FLAG_RESET, 0, 0]);
+}
+
+/// Starts the counter from 0.
+pub fn start_counter(counter: &Counter) {
+    let _ = pmu_call(SBI_EXT_PMU_COUNTER_START, [counter.idx, 1, SBI_PMU_START_FLAG_SET_INIT_VALUE, 0, 0]);
+}
+
+pub fn stop_counter(counter: &Counter) {
+    let _ = pmu_call(SBI_EXT_PMU_COUNTER_STOP, [counter.idx, 1, 0, 0, 0]);
+}
+
+macro_rules! read_csr {
+    ($csr:expr, $($n:literal)*) => {
+        match $csr {
+            $($n => {
+                let v: usize;
+                unsafe { asm!(concat!("csrr {}, ", stringify!($n)), out(reg) v) };
+                v
+            })*
+            _ => 0,
+        }
+    };
+}
+
+/// Reads one of `cycle`, `time`, `instret` and `hpmcounter3` to 31.
+fn read_counter_csr(csr: usize) -> usize {
+    read_csr!(csr,
+        0xc00 0xc01 0xc02 0xc03 0xc04 0xc05 0xc06 0xc07
+        0xc08 0xc09 0xc0a 0xc0b 0xc0c 0xc0d 0xc0e 0xc0f
+        0xc10 0xc11 0xc12 0xc13 0xc14 0xc15 0xc16 0xc17
+        0xc18 0xc19 0xc1a 0xc1b 0xc1c 0xc1d 0xc1e 0xc1f)
+}
+
+/// The value of the counter, of `width` bits.
+pub fn read_counter(counter: &Counter) -> u64 {
+    let value = if counter.firmware {
+        pmu_call(SBI_EXT_PMU_COUNTER_FW_READ, [counter.idx, 0, 0, 0, 0]).unwrap_or(0)
+    } else {
+        debug_assert!((CSR_CYCLE..CSR_CYCLE + 32).contains(&counter.csr));
+        read_counter_csr(counter.csr)
+    };
+    let mask = if counter.width >= 64 { u64::MAX } else { (1 << counter.width) - 1 };
+    value as u64 & mask
+}
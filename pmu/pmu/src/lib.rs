@@ -0,0 +1,229 @@
+//! Hardware performance counters, for the code in the kernel to measure
+//! itself: a lite perf events layer.
+//!
+//! An event counts a hardware or a cache event, as perf numbers them, on
+//! a counter of its own. It counts either whatever runs on the CPU it has
+//! been created on, or one task only while that task runs there: its
+//! counter is stopped when the task is switched out, and started again
+//! when it is switched in. The counters are programmed on that CPU only,
+//! so a task that migrates isn't counted elsewhere.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod arch;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use spinbase::SpinNoIrq;
+use taskctx::Tid;
+
+/* Types of events */
+pub const PERF_TYPE_HARDWARE: u32 = 0;
+pub const PERF_TYPE_HW_CACHE: u32 = 3;
+
+/* Hardware events */
+pub const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+pub const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+pub const PERF_COUNT_HW_CACHE_REFERENCES: u64 = 2;
+pub const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+pub const PERF_COUNT_HW_BRANCH_INSTRUCTIONS: u64 = 4;
+pub const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+pub const PERF_COUNT_HW_BUS_CYCLES: u64 = 6;
+pub const PERF_COUNT_HW_STALLED_CYCLES_FRONTEND: u64 = 7;
+pub const PERF_COUNT_HW_STALLED_CYCLES_BACKEND: u64 = 8;
+pub const PERF_COUNT_HW_REF_CPU_CYCLES: u64 = 9;
+pub(crate) const PERF_COUNT_HW_MAX: usize = 10;
+
+/* Cache events: config is cache | op << 8 | result << 16 */
+pub const PERF_COUNT_HW_CACHE_L1D: u64 = 0;
+pub const PERF_COUNT_HW_CACHE_L1I: u64 = 1;
+pub const PERF_COUNT_HW_CACHE_LL: u64 = 2;
+pub const PERF_COUNT_HW_CACHE_DTLB: u64 = 3;
+pub const PERF_COUNT_HW_CACHE_ITLB: u64 = 4;
+pub const PERF_COUNT_HW_CACHE_BPU: u64 = 5;
+pub const PERF_COUNT_HW_CACHE_NODE: u64 = 6;
+pub(crate) const PERF_COUNT_HW_CACHE_MAX: usize = 7;
+
+pub const PERF_COUNT_HW_CACHE_OP_READ: u64 = 0;
+pub const PERF_COUNT_HW_CACHE_OP_WRITE: u64 = 1;
+pub const PERF_COUNT_HW_CACHE_OP_PREFETCH: u64 = 2;
+pub(crate) const PERF_COUNT_HW_CACHE_OP_MAX: usize = 3;
+
+pub const PERF_COUNT_HW_CACHE_RESULT_ACCESS: u64 = 0;
+pub const PERF_COUNT_HW_CACHE_RESULT_MISS: u64 = 1;
+pub(crate) const PERF_COUNT_HW_CACHE_RESULT_MAX: usize = 2;
+
+/// What an event counts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PerfEventAttr {
+    pub type_: u32,
+    pub config: u64,
+    /// Not in user mode.
+    pub exclude_user: bool,
+    /// Not in the kernel.
+    pub exclude_kernel: bool,
+}
+
+impl PerfEventAttr {
+    pub const fn hardware(config: u64) -> Self {
+        Self { type_: PERF_TYPE_HARDWARE, config, exclude_user: false, exclude_kernel: false }
+    }
+
+    pub const fn cache(cache: u64, op: u64, result: u64) -> Self {
+        Self {
+            type_: PERF_TYPE_HW_CACHE,
+            config: cache | (op << 8) | (result << 16),
+            exclude_user: false,
+            exclude_kernel: false,
+        }
+    }
+}
+
+/// A counter mapped to an event.
+pub(crate) struct Counter {
+    pub idx: usize,
+    /// Its CSR, unless it is a firmware one.
+    pub csr: usize,
+    pub width: u32,
+    pub firmware: bool,
+}
+
+struct EventState {
+    enabled: bool,
+    /// The counter runs: enabled, and its task, if any, is switched in.
+    active: bool,
+    /// Counted by the time the counter has last been stopped.
+    count: u64,
+}
+
+pub struct PerfEvent {
+    attr: PerfEventAttr,
+    counter: Counter,
+    /// The task counted, or none for the whole CPU.
+    task: Option<Tid>,
+    cpu: usize,
+    state: SpinNoIrq<EventState>,
+}
+
+/// The events counting for each task.
+static TASK_EVENTS: SpinNoIrq<BTreeMap<Tid, Vec<Arc<PerfEvent>>>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+impl PerfEvent {
+    pub fn attr(&self) -> &PerfEventAttr {
+        &self.attr
+    }
+
+    /// Stops the counter, adding what it has counted.
+    fn sched_out(&self, state: &mut EventState) {
+        if state.active {
+            arch::stop_counter(&self.counter);
+            state.count += arch::read_counter(&self.counter);
+            state.active = false;
+        }
+    }
+
+    fn sched_in(&self, state: &mut EventState) {
+        if state.enabled && !state.active {
+            arch::start_counter(&self.counter);
+            state.active = true;
+        }
+    }
+
+    /// Starts counting, or counting again after `disable`.
+    pub fn enable(&self) {
+        // IRQs off: the current task stays in.
+        let mut state = self.state.lock();
+        state.enabled = true;
+        let this_cpu = taskctx::current_ctx().cpu() == self.cpu;
+        if this_cpu && self.task.map_or(true, |tid| tid == taskctx::current_ctx().tid()) {
+            self.sched_in(&mut state);
+        }
+    }
+
+    pub fn disable(&self) {
+        let mut state = self.state.lock();
+        self.sched_out(&mut state);
+        state.enabled = false;
+    }
+
+    /// The count so far. While its task runs on another CPU, that is the
+    /// count by the time it has been switched in.
+    pub fn read(&self) -> u64 {
+        let state = self.state.lock();
+        let running = state.active && taskctx::current_ctx().cpu() == self.cpu;
+        state.count + if running { arch::read_counter(&self.counter) } else { 0 }
+    }
+
+    /// Sets the count back to 0.
+    pub fn reset(&self) {
+        let mut state = self.state.lock();
+        if state.active {
+            arch::start_counter(&self.counter);
+        }
+        state.count = 0;
+    }
+}
+
+impl Drop for PerfEvent {
+    fn drop(&mut self) {
+        arch::release_counter(&self.counter);
+    }
+}
+
+/// Switches the events of `prev` out, and the ones of `next` in.
+fn perf_event_task_sched(cpu: usize, prev: Tid, next: Tid) {
+    let events = TASK_EVENTS.lock();
+    for event in events.get(&prev).into_iter().flatten().filter(|e| e.cpu == cpu) {
+        event.sched_out(&mut event.state.lock());
+    }
+    for event in events.get(&next).into_iter().flatten().filter(|e| e.cpu == cpu) {
+        event.sched_in(&mut event.state.lock());
+    }
+}
+
+/// Creates an event counting `attr` on this CPU, for the task `task` only
+/// if any. It is disabled until `enable` is called. Fails with ENODEV
+/// without counters, and with ENOENT if none can count the event.
+pub fn perf_event_create(attr: PerfEventAttr, task: Option<Tid>) -> LinuxResult<Arc<PerfEvent>> {
+    if !arch::probe() {
+        return Err(LinuxError::ENODEV);
+    }
+    let event_idx = arch::event_idx(&attr)?;
+    let counter = arch::alloc_counter(event_idx, attr.exclude_user, attr.exclude_kernel)?;
+    info!("pmu: event {:#x} on counter {} for {:?}", event_idx, counter.idx, task);
+    let event = Arc::new(PerfEvent {
+        attr,
+        counter,
+        task,
+        cpu: taskctx::current_ctx().cpu(),
+        state: SpinNoIrq::new(EventState { enabled: false, active: false, count: 0 }),
+    });
+    if let Some(tid) = task {
+        // Registered once, by the first task event.
+        run_queue::register_perf_switch(perf_event_task_sched);
+        TASK_EVENTS.lock().entry(tid).or_default().push(event.clone());
+    }
+    Ok(event)
+}
+
+/// Releases `event`, and its counter with it once the last reference is
+/// dropped.
+pub fn perf_event_release(event: Arc<PerfEvent>) {
+    event.disable();
+    if let Some(tid) = event.task {
+        let mut events = TASK_EVENTS.lock();
+        if let Some(list) = events.get_mut(&tid) {
+            list.retain(|e| !Arc::ptr_eq(e, &event));
+            if list.is_empty() {
+                events.remove(&tid);
+            }
+        }
+    }
+}
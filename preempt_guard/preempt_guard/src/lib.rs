@@ -83,10 +83,44 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(any(target_os = "none", doc))]
+pub use imp::{need_resched, preempt_count, preempt_disable, preempt_enable};
+
 #[cfg(any(target_os = "none", doc))]
 mod imp {
     use super::*;
 
+    /// Disables kernel preemption on this CPU.
+    ///
+    /// The count is kept in the running context, so it follows the CPU
+    /// rather than migrating with a task: a task can't be switched out
+    /// while it's non-zero.
+    pub fn preempt_disable() {
+        if let Some(ctx) = taskctx::CurrentCtx::try_get() {
+            ctx.disable_preempt();
+        }
+    }
+
+    /// Re-enables kernel preemption on this CPU, rescheduling at once if the
+    /// count drops to zero and a reschedule is pending.
+    pub fn preempt_enable() {
+        if let Some(ctx) = taskctx::CurrentCtx::try_get() {
+            if ctx.enable_preempt() {
+                current_check_preempt_pending();
+            }
+        }
+    }
+
+    /// Returns the preemption disable count of this CPU.
+    pub fn preempt_count() -> usize {
+        taskctx::CurrentCtx::try_get().map_or(0, |ctx| ctx.preempt_count())
+    }
+
+    /// Whether the scheduler has asked this CPU to reschedule.
+    pub fn need_resched() -> bool {
+        taskctx::CurrentCtx::try_get().map_or(false, |ctx| ctx.get_preempt_pending())
+    }
+
     fn current_check_preempt_pending() {
         let curr = taskctx::current_ctx();
         if curr.get_preempt_pending() && curr.can_preempt(0) {
@@ -100,42 +134,26 @@ mod imp {
     impl BaseGuard for NoPreempt {
         type State = ();
         fn acquire() -> Self::State {
-            // disable preempt
-            if let Some(ctx) = taskctx::CurrentCtx::try_get() {
-                ctx.disable_preempt();
-            }
+            preempt_disable();
         }
         fn release(_state: Self::State) {
-            // enable preempt
-            if let Some(ctx) = taskctx::CurrentCtx::try_get() {
-                if ctx.enable_preempt() {
-                    // If current task is pending to be preempted, do rescheduling.
-                    current_check_preempt_pending();
-                }
-            }
+            // If current task is pending to be preempted, do rescheduling.
+            preempt_enable();
         }
     }
 
     impl BaseGuard for NoPreemptIrqSave {
         type State = usize;
         fn acquire() -> Self::State {
-            // disable preempt
-            if let Some(ctx) = taskctx::CurrentCtx::try_get() {
-                ctx.disable_preempt();
-            }
+            preempt_disable();
             // disable IRQs and save IRQ states
             super::arch::local_irq_save_and_disable()
         }
         fn release(state: Self::State) {
             // restore IRQ states
             super::arch::local_irq_restore(state);
-            // enable preempt
-            if let Some(ctx) = taskctx::CurrentCtx::try_get() {
-                if ctx.enable_preempt() {
-                    // If current task is pending to be preempted, do rescheduling.
-                    current_check_preempt_pending();
-                }
-            }
+            // If current task is pending to be preempted, do rescheduling.
+            preempt_enable();
         }
     }
 
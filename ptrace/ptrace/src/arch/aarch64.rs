@@ -0,0 +1,32 @@
+use task::TaskStruct;
+
+/// The condition flags, NZCV, which are all of PSTATE the tracer may set.
+const PSR_NZCV: u64 = 0xf000_0000;
+
+/// `struct user_pt_regs`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UserRegs {
+    pub regs: [u64; 31],
+    pub sp: u64,
+    pub pc: u64,
+    pub pstate: u64,
+}
+
+pub fn get_regs(task: &TaskStruct) -> UserRegs {
+    let tf = task.sched_info.pt_regs();
+    UserRegs {
+        regs: tf.r,
+        sp: tf.usp,
+        pc: tf.elr,
+        pstate: tf.spsr,
+    }
+}
+
+pub fn set_regs(task: &TaskStruct, regs: &UserRegs) {
+    let tf = task.sched_info.pt_regs();
+    tf.r = regs.regs;
+    tf.usp = regs.sp;
+    tf.elr = regs.pc;
+    tf.spsr = (tf.spsr & !PSR_NZCV) | (regs.pstate & PSR_NZCV);
+}
@@ -0,0 +1,24 @@
+use axhal::arch::GeneralRegisters;
+use task::TaskStruct;
+
+/// `struct user_regs_struct`: the pc, then x1 to x31.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UserRegs {
+    pub pc: usize,
+    pub regs: GeneralRegisters,
+}
+
+pub fn get_regs(task: &TaskStruct) -> UserRegs {
+    let tf = task.sched_info.pt_regs();
+    UserRegs {
+        pc: tf.sepc,
+        regs: tf.regs,
+    }
+}
+
+pub fn set_regs(task: &TaskStruct, regs: &UserRegs) {
+    let tf = task.sched_info.pt_regs();
+    tf.sepc = regs.pc;
+    tf.regs = regs.regs;
+}
@@ -0,0 +1,93 @@
+use task::TaskStruct;
+
+/// The flags of RFLAGS the tracer may set: CF, PF, AF, ZF, SF, TF, DF, OF,
+/// as FLAG_MASK of Linux.
+const FLAG_MASK: u64 = 0x0dd5;
+
+/// `struct user_regs_struct`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct UserRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+pub fn get_regs(task: &TaskStruct) -> UserRegs {
+    let tf = task.sched_info.pt_regs();
+    UserRegs {
+        r15: tf.r15,
+        r14: tf.r14,
+        r13: tf.r13,
+        r12: tf.r12,
+        rbp: tf.rbp,
+        rbx: tf.rbx,
+        r11: tf.r11,
+        r10: tf.r10,
+        r9: tf.r9,
+        r8: tf.r8,
+        rax: tf.rax,
+        rcx: tf.rcx,
+        rdx: tf.rdx,
+        rsi: tf.rsi,
+        rdi: tf.rdi,
+        // The syscall number is kept there, see `axtrap`.
+        orig_rax: tf.error_code,
+        rip: tf.rip,
+        cs: tf.cs,
+        eflags: tf.rflags,
+        rsp: tf.rsp,
+        ss: tf.ss,
+        // Saved at the switch away from the stopped tracee.
+        fs_base: unsafe { (*task.ctx_mut_ptr()).fs_base as u64 },
+        ..Default::default()
+    }
+}
+
+pub fn set_regs(task: &TaskStruct, regs: &UserRegs) {
+    let tf = task.sched_info.pt_regs();
+    tf.r15 = regs.r15;
+    tf.r14 = regs.r14;
+    tf.r13 = regs.r13;
+    tf.r12 = regs.r12;
+    tf.rbp = regs.rbp;
+    tf.rbx = regs.rbx;
+    tf.r11 = regs.r11;
+    tf.r10 = regs.r10;
+    tf.r9 = regs.r9;
+    tf.r8 = regs.r8;
+    tf.rax = regs.rax;
+    tf.rcx = regs.rcx;
+    tf.rdx = regs.rdx;
+    tf.rsi = regs.rsi;
+    tf.rdi = regs.rdi;
+    tf.error_code = regs.orig_rax;
+    tf.rip = regs.rip;
+    tf.rflags = (tf.rflags & !FLAG_MASK) | (regs.eflags & FLAG_MASK);
+    tf.rsp = regs.rsp;
+    unsafe { (*task.ctx_mut_ptr()).fs_base = regs.fs_base as usize };
+}
@@ -0,0 +1,604 @@
+//! Process tracing, as ptrace(2) of Linux.
+//!
+//! A tracer attaches to a tracee by PTRACE_ATTACH, or the tracee asks its
+//! parent to trace it by PTRACE_TRACEME. The tracee then stops for its
+//! tracer at each signal it is to get, at the entry and the exit of its
+//! syscalls after PTRACE_SYSCALL, and at the events the tracer asks for by
+//! its options, as its forks and its execve. The tracer waits for the
+//! stops by wait4, as for its children, and meanwhile reads and writes the
+//! memory of the tracee, through the pages of its mm, and its registers,
+//! as they are saved in the trap frame at the top of its kernel stack.
+//! PTRACE_CONT and PTRACE_SYSCALL resume it, with a signal or none.
+//!
+//! The tracer is a process: any of its threads may make the requests, and
+//! wait. Single-stepping isn't supported.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod arch;
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::Ordering;
+use axerrno::{linux_err_from, LinuxError, LinuxResult};
+use taskctx::{TaskState, Tid, TIF_SYSCALL_TRACE};
+use task::{SigInfo, TaskStruct, NSIG, SIGCHLD, SIGKILL, SIGSTOP, SIGTRAP};
+
+/// The user registers of a task, as a core dump has them too.
+pub use arch::{get_regs, UserRegs};
+
+pub const PTRACE_TRACEME: usize = 0;
+pub const PTRACE_PEEKTEXT: usize = 1;
+pub const PTRACE_PEEKDATA: usize = 2;
+pub const PTRACE_PEEKUSR: usize = 3;
+pub const PTRACE_POKETEXT: usize = 4;
+pub const PTRACE_POKEDATA: usize = 5;
+pub const PTRACE_POKEUSR: usize = 6;
+pub const PTRACE_CONT: usize = 7;
+pub const PTRACE_KILL: usize = 8;
+pub const PTRACE_SINGLESTEP: usize = 9;
+pub const PTRACE_GETREGS: usize = 12;
+pub const PTRACE_SETREGS: usize = 13;
+pub const PTRACE_ATTACH: usize = 16;
+pub const PTRACE_DETACH: usize = 17;
+pub const PTRACE_SYSCALL: usize = 24;
+pub const PTRACE_SETOPTIONS: usize = 0x4200;
+pub const PTRACE_GETEVENTMSG: usize = 0x4201;
+pub const PTRACE_GETSIGINFO: usize = 0x4202;
+pub const PTRACE_GETREGSET: usize = 0x4204;
+pub const PTRACE_SETREGSET: usize = 0x4205;
+
+/// Options of PTRACE_SETOPTIONS. Each PTRACE_O_TRACE* but the first asks
+/// for the event of the same bit, `1 << event`.
+pub const PTRACE_O_TRACESYSGOOD: usize = 0x00000001;
+pub const PTRACE_O_TRACEFORK: usize = 0x00000002;
+pub const PTRACE_O_TRACEVFORK: usize = 0x00000004;
+pub const PTRACE_O_TRACECLONE: usize = 0x00000008;
+pub const PTRACE_O_TRACEEXEC: usize = 0x00000010;
+pub const PTRACE_O_TRACEVFORKDONE: usize = 0x00000020;
+pub const PTRACE_O_TRACEEXIT: usize = 0x00000040;
+pub const PTRACE_O_EXITKILL: usize = 0x00100000;
+const PTRACE_O_MASK: usize = 0x0000007f | PTRACE_O_EXITKILL;
+
+/// Events, which the tracer gets in bits 16 and up of the wait status.
+pub const PTRACE_EVENT_FORK: usize = 1;
+pub const PTRACE_EVENT_VFORK: usize = 2;
+pub const PTRACE_EVENT_CLONE: usize = 3;
+pub const PTRACE_EVENT_EXEC: usize = 4;
+pub const PTRACE_EVENT_VFORK_DONE: usize = 5;
+pub const PTRACE_EVENT_EXIT: usize = 6;
+
+/// The register set of PTRACE_GETREGSET: the general ones.
+const NT_PRSTATUS: usize = 1;
+
+// si_code of SIGCHLD for a tracee that has exited, or stopped
+const CLD_EXITED: i32 = 1;
+const CLD_TRAPPED: i32 = 4;
+// si_code of a signal sent by kill
+const SI_USER: i32 = 0;
+
+#[repr(C)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+/// `siginfo_t` of the user, with the fields of a signal sent by kill.
+#[repr(C)]
+struct UserSigInfo {
+    signo: i32,
+    errno: i32,
+    code: i32,
+    _pad: i32,
+    pid: i32,
+    uid: u32,
+    _rest: [u64; 13],
+}
+
+/// Wait status of a task stopped by `signo`.
+fn stopped_status(signo: usize) -> u32 {
+    ((signo as u32) << 8) | 0x7f
+}
+
+/// Wait status of a task stopped at `event`.
+fn event_status(event: usize) -> u32 {
+    stopped_status(SIGTRAP | (event << 8))
+}
+
+#[inline]
+fn sigmask(signo: usize) -> u64 {
+    1 << (signo - 1)
+}
+
+fn kill_info(signo: usize) -> SigInfo {
+    SigInfo {
+        signo: signo as i32,
+        errno: 0,
+        code: SI_USER,
+        tid: task::current().tid(),
+    }
+}
+
+/// Whether `task` is traced.
+#[inline]
+pub fn is_traced(task: &TaskStruct) -> bool {
+    task.ptrace.tracer.load(Ordering::Acquire) != 0
+}
+
+/// Whether the tracer of `task` asked for `event`.
+fn event_enabled(task: &TaskStruct, event: usize) -> bool {
+    is_traced(task) && (task.ptrace.options.load(Ordering::Relaxed) & (1 << event)) != 0
+}
+
+/// The group leader of the tracer `tgid`, which keeps the tracees.
+fn tracer_leader(tgid: Tid) -> Option<task::TaskRef> {
+    task::get_task(tgid)
+}
+
+/// Has `task` traced by the process `tracer`. Fails if it is traced
+/// already.
+fn link(task: &TaskStruct, tracer: Tid) -> LinuxResult {
+    let leader = tracer_leader(tracer).ok_or(LinuxError::ESRCH)?;
+    task.ptrace.tracer
+        .compare_exchange(0, tracer, Ordering::AcqRel, Ordering::Relaxed)
+        .map_err(|_| LinuxError::EPERM)?;
+    task.ptrace.options.store(0, Ordering::Relaxed);
+    leader.ptrace.tracees.lock().push(task.tid());
+    Ok(())
+}
+
+/// Lets `task` go from its tracer, if it has one.
+fn unlink(task: &TaskStruct) {
+    let tracer = task.ptrace.tracer.swap(0, Ordering::AcqRel);
+    if tracer == 0 {
+        return;
+    }
+    task.ptrace.options.store(0, Ordering::Relaxed);
+    task.ptrace.status.store(0, Ordering::Relaxed);
+    task.sched_info.clear_tsk_thread_flag(TIF_SYSCALL_TRACE);
+    if let Some(leader) = tracer_leader(tracer) {
+        leader.ptrace.tracees.lock().retain(|&tid| tid != task.tid());
+    }
+}
+
+/// Sends SIGCHLD with `code` to the tracer `tracer`, and wakes up its
+/// threads that may wait for `task`.
+fn notify_tracer(task: &TaskStruct, tracer: Tid, code: i32) {
+    let Some(leader) = tracer_leader(tracer) else {
+        return;
+    };
+    leader.send_sig_info(SigInfo {
+        signo: SIGCHLD as i32,
+        errno: 0,
+        code,
+        tid: task.tid(),
+    });
+    for tid in leader.thread_group() {
+        run_queue::wake_up_task(tid);
+    }
+}
+
+/// Stops the current task for its tracer, which waits for `status`, until
+/// the tracer resumes it or lets it go, or it is killed. Returns the signal
+/// the tracer resumes it with, or 0.
+fn ptrace_stop(task: &TaskStruct, status: u32) -> usize {
+    let tracer = task.ptrace.tracer.load(Ordering::Acquire);
+    if tracer == 0 {
+        return 0;
+    }
+    let state = &task.ptrace;
+    state.resume_signo.store(0, Ordering::Relaxed);
+    state.stopped.store(true, Ordering::Release);
+    state.status.store(status, Ordering::Release);
+    debug!("ptrace: task {} stopped {:#x}", task.tid(), status);
+    notify_tracer(task, tracer, CLD_TRAPPED);
+    while state.stopped.load(Ordering::Acquire) {
+        if (task.sigpending.lock().signal & sigmask(SIGKILL)) != 0 {
+            state.stopped.store(false, Ordering::Release);
+            break;
+        }
+        run_queue::block_current(TaskState::Interruptible);
+    }
+    state.status.store(0, Ordering::Release);
+    state.resume_signo.swap(0, Ordering::AcqRel)
+}
+
+/// Sends the signal the tracer resumed the current task with, rather than
+/// losing it, after a stop that isn't for a signal.
+fn send_resume_signal(task: &TaskStruct, signo: usize) {
+    if signo != 0 {
+        task.send_sig_info(kill_info(signo));
+    }
+}
+
+/// A signal-delivery-stop: the tracer of the current task sees `info`
+/// before the task gets it, and may change or cancel it. Returns the
+/// signal to deliver, if any.
+pub fn ptrace_signal(info: SigInfo) -> Option<SigInfo> {
+    let task = task::current();
+    *task.ptrace.siginfo.lock() = Some(info);
+    let signo = ptrace_stop(&task, stopped_status(info.signo as usize));
+    task.ptrace.siginfo.lock().take();
+    match signo {
+        0 => None,
+        signo if signo == info.signo as usize => Some(info),
+        signo => Some(kill_info(signo)),
+    }
+}
+
+/// A group-stop of a traced task, by a stop signal of default action: it
+/// stops for the tracer, which resumes it, rather than for SIGCONT.
+pub fn ptrace_group_stop(signo: usize) {
+    let task = task::current();
+    ptrace_stop(&task, stopped_status(signo));
+}
+
+/// Stops the current task at the entry or the exit of a syscall, if its
+/// tracer asked for it by PTRACE_SYSCALL.
+fn report_syscall() {
+    if !taskctx::current_ctx().test_tsk_thread_flag(TIF_SYSCALL_TRACE) {
+        return;
+    }
+    let task = task::current();
+    let mut signo = SIGTRAP;
+    if (task.ptrace.options.load(Ordering::Relaxed) & PTRACE_O_TRACESYSGOOD) != 0 {
+        signo |= 0x80;
+    }
+    let resume = ptrace_stop(&task, stopped_status(signo));
+    send_resume_signal(&task, resume);
+}
+
+/// Syscall-enter-stop. The tracer may change the number and the arguments
+/// of the syscall in the registers meanwhile, so they are read after it.
+#[inline]
+pub fn report_syscall_entry() {
+    report_syscall();
+}
+
+/// Syscall-exit-stop, once the return value is in the registers.
+#[inline]
+pub fn report_syscall_exit() {
+    report_syscall();
+}
+
+/// Reports `event` to the tracer of the current task, if it asked for it
+/// by its options, with `message` for PTRACE_GETEVENTMSG. A tracee that
+/// doesn't report its execve gets SIGTRAP after it, as a tracer of old.
+pub fn ptrace_event(event: usize, message: usize) {
+    let task = task::current();
+    if event_enabled(&task, event) {
+        task.ptrace.message.store(message, Ordering::Relaxed);
+        let resume = ptrace_stop(&task, event_status(event));
+        send_resume_signal(&task, resume);
+    } else if event == PTRACE_EVENT_EXEC && is_traced(&task) {
+        task.send_sig_info(kill_info(SIGTRAP));
+    }
+}
+
+/// The event a clone of the current task reports to its tracer, as the
+/// clone is a vfork, a thread or a fork, or 0 if the tracer doesn't ask
+/// for it. The child is traced too only with the event.
+pub fn clone_event(vfork: bool, exit_signal: i32) -> usize {
+    let event = if vfork {
+        PTRACE_EVENT_VFORK
+    } else if exit_signal != SIGCHLD as i32 {
+        PTRACE_EVENT_CLONE
+    } else {
+        PTRACE_EVENT_FORK
+    };
+    if event_enabled(&task::current(), event) {
+        event
+    } else {
+        0
+    }
+}
+
+/// Has the new `child` traced by the tracer of the current task, with its
+/// options, and stopped by SIGSTOP once it runs. It isn't running yet.
+pub fn ptrace_init_task(child: &TaskStruct) {
+    let current = task::current();
+    let tracer = current.ptrace.tracer.load(Ordering::Acquire);
+    if tracer == 0 || link(child, tracer).is_err() {
+        return;
+    }
+    child.ptrace.options.store(current.ptrace.options.load(Ordering::Relaxed), Ordering::Relaxed);
+    // Queued by hand: the child can't be woken up before it is activated.
+    let mut pending = child.sigpending.lock();
+    pending.list.push(kill_info(SIGSTOP));
+    pending.signal |= sigmask(SIGSTOP);
+    child.sched_info.set_tsk_thread_flag(taskctx::TIF_SIGPENDING);
+}
+
+/// At the exit of the current task with the wait status `exit_code`: a
+/// tracee reports it as an event if asked to, and a tracer whose last
+/// thread exits lets its tracees go, killing them with PTRACE_O_EXITKILL.
+pub fn exit_ptrace(exit_code: u32) {
+    let task = task::current();
+    if event_enabled(&task, PTRACE_EVENT_EXIT) {
+        task.ptrace.message.store(exit_code as usize, Ordering::Relaxed);
+        ptrace_stop(&task, event_status(PTRACE_EVENT_EXIT));
+    }
+
+    let last_thread = task.thread_group()
+        .into_iter()
+        .filter(|&tid| tid != task.tid())
+        .filter_map(task::get_task)
+        .all(|t| t.exit_state.load(Ordering::Acquire) != 0);
+    if !last_thread {
+        return;
+    }
+    let Some(leader) = tracer_leader(task.tgid()) else {
+        return;
+    };
+    let tracees = core::mem::take(&mut *leader.ptrace.tracees.lock());
+    for tracee in tracees.into_iter().filter_map(task::get_task) {
+        let exit_kill = (tracee.ptrace.options.load(Ordering::Relaxed) & PTRACE_O_EXITKILL) != 0;
+        tracee.ptrace.tracer.store(0, Ordering::Release);
+        tracee.ptrace.options.store(0, Ordering::Relaxed);
+        tracee.sched_info.clear_tsk_thread_flag(TIF_SYSCALL_TRACE);
+        if exit_kill {
+            tracee.send_sig_info(kill_info(SIGKILL));
+        }
+        tracee.ptrace.stopped.store(false, Ordering::Release);
+        run_queue::wake_up_task(tracee.tid());
+    }
+}
+
+/// Lets the tracer of the current task know that it has exited, unless
+/// the tracer is its parent, which knows already.
+pub fn exit_notify() {
+    let task = task::current();
+    let tracer = task.ptrace.tracer.load(Ordering::Acquire);
+    if tracer != 0 && task.parent().map_or(true, |p| p.tgid() != tracer) {
+        notify_tracer(&task, tracer, CLD_EXITED);
+    }
+}
+
+/// Lets `task` go from its tracer as it is reaped.
+pub fn release_task(task: &TaskStruct) {
+    unlink(task);
+}
+
+/// Tids of the tasks that the process `tgid` traces, which it waits for as
+/// for its children.
+pub fn tracees(tgid: Tid) -> Vec<Tid> {
+    let Some(leader) = tracer_leader(tgid) else {
+        return Vec::new();
+    };
+    let mut tracees = leader.ptrace.tracees.lock();
+    // Threads reap themselves, without the tracer.
+    tracees.retain(|&tid| task::get_task(tid).is_some());
+    tracees.clone()
+}
+
+/// Whether the current task traces `task`.
+pub fn is_tracer_of(task: &TaskStruct) -> bool {
+    task.ptrace.tracer.load(Ordering::Acquire) == task::current().tgid()
+}
+
+/// The wait status of the stop of `task` not reported to its tracer yet,
+/// if any. It is reported only once, unless `nowait`.
+pub fn wait_stopped(task: &TaskStruct, nowait: bool) -> Option<u32> {
+    let status = task.ptrace.status.load(Ordering::Acquire);
+    if status == 0 {
+        return None;
+    }
+    if !nowait && task.ptrace.status
+        .compare_exchange(status, 0, Ordering::AcqRel, Ordering::Relaxed)
+        .is_err() {
+        return None;
+    }
+    Some(status)
+}
+
+/// The ptrace syscall: does `request` on the tracee of pid `pid`.
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> usize {
+    debug!("ptrace: request {:#x} pid {} addr {:#x} data {:#x}", request, pid, addr, data);
+    match do_ptrace(request, pid, addr, data) {
+        Ok(ret) => ret,
+        Err(e) => linux_err_from!(e),
+    }
+}
+
+fn do_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> LinuxResult<usize> {
+    if request == PTRACE_TRACEME {
+        return ptrace_traceme();
+    }
+    let tid = task::find_vpid(pid).ok_or(LinuxError::ESRCH)?;
+    let child = task::get_task(tid).ok_or(LinuxError::ESRCH)?;
+    if request == PTRACE_ATTACH {
+        return ptrace_attach(&child);
+    }
+    check_attach(&child, request == PTRACE_KILL)?;
+
+    match request {
+        PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+            let mut word = [0u8; size_of::<usize>()];
+            let mm = child.try_mm().ok_or(LinuxError::EIO)?;
+            if mm.read().access_remote(addr, &mut word) != word.len() {
+                return Err(LinuxError::EIO);
+            }
+            put_user(data, usize::from_ne_bytes(word));
+            Ok(0)
+        }
+        PTRACE_POKETEXT | PTRACE_POKEDATA => {
+            let mm = child.try_mm().ok_or(LinuxError::EIO)?;
+            let word = data.to_ne_bytes();
+            if mm.read().write_remote(addr, &word) != word.len() {
+                return Err(LinuxError::EIO);
+            }
+            // A breakpoint, most likely.
+            #[cfg(not(target_arch = "x86_64"))]
+            if request == PTRACE_POKETEXT {
+                axhal::arch::flush_icache_all();
+            }
+            Ok(0)
+        }
+        PTRACE_PEEKUSR => {
+            let mut regs = arch::get_regs(&child);
+            put_user(data, reg_words(&mut regs)[reg_index(addr)?]);
+            Ok(0)
+        }
+        PTRACE_POKEUSR => {
+            let mut regs = arch::get_regs(&child);
+            reg_words(&mut regs)[reg_index(addr)?] = data;
+            arch::set_regs(&child, &regs);
+            Ok(0)
+        }
+        PTRACE_GETREGS => {
+            put_user(data, arch::get_regs(&child));
+            Ok(0)
+        }
+        PTRACE_SETREGS => {
+            let regs = unsafe { *(data as *const UserRegs) };
+            arch::set_regs(&child, &regs);
+            Ok(0)
+        }
+        PTRACE_GETREGSET | PTRACE_SETREGSET => {
+            if addr != NT_PRSTATUS {
+                return Err(LinuxError::EINVAL);
+            }
+            let iov = unsafe { &mut *(data as *mut IoVec) };
+            let len = iov.len.min(size_of::<UserRegs>());
+            let mut regs = arch::get_regs(&child);
+            let bytes = &mut regs as *mut UserRegs as *mut u8;
+            unsafe {
+                if request == PTRACE_GETREGSET {
+                    core::ptr::copy_nonoverlapping(bytes, iov.base as *mut u8, len);
+                } else {
+                    core::ptr::copy_nonoverlapping(iov.base as *const u8, bytes, len);
+                    arch::set_regs(&child, &regs);
+                }
+            }
+            iov.len = len;
+            Ok(0)
+        }
+        PTRACE_SETOPTIONS => {
+            if (data & !PTRACE_O_MASK) != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            child.ptrace.options.store(data, Ordering::Relaxed);
+            Ok(0)
+        }
+        PTRACE_GETEVENTMSG => {
+            put_user(data, child.ptrace.message.load(Ordering::Relaxed));
+            Ok(0)
+        }
+        PTRACE_GETSIGINFO => {
+            let info = child.ptrace.siginfo.lock().ok_or(LinuxError::EINVAL)?;
+            put_user(data, UserSigInfo {
+                signo: info.signo,
+                errno: info.errno,
+                code: info.code,
+                _pad: 0,
+                pid: task::pid_vnr(info.tid) as i32,
+                uid: 0,
+                _rest: [0; 13],
+            });
+            Ok(0)
+        }
+        PTRACE_CONT | PTRACE_SYSCALL => {
+            if request == PTRACE_SYSCALL {
+                child.sched_info.set_tsk_thread_flag(TIF_SYSCALL_TRACE);
+            } else {
+                child.sched_info.clear_tsk_thread_flag(TIF_SYSCALL_TRACE);
+            }
+            resume(&child, data)
+        }
+        PTRACE_DETACH => {
+            if data > NSIG {
+                return Err(LinuxError::EIO);
+            }
+            unlink(&child);
+            resume(&child, data)
+        }
+        PTRACE_KILL => {
+            child.send_sig_info(kill_info(SIGKILL));
+            Ok(0)
+        }
+        _ => Err(LinuxError::EIO),
+    }
+}
+
+/// Has the parent of the current task trace it.
+fn ptrace_traceme() -> LinuxResult<usize> {
+    let current = task::current();
+    let parent = current.parent().ok_or(LinuxError::EPERM)?;
+    link(&current, parent.tgid())?;
+    info!("ptrace: task {} traced by its parent {}", current.tid(), parent.tgid());
+    Ok(0)
+}
+
+/// Whether the current task may trace `child`: it has the same ids, or is
+/// privileged.
+fn may_attach(child: &TaskStruct) -> bool {
+    let cred = task::current().cred();
+    let tcred = child.cred();
+    cred.is_privileged() || (
+        cred.uid == tcred.uid && cred.uid == tcred.euid && cred.uid == tcred.suid &&
+        cred.gid == tcred.gid && cred.gid == tcred.egid && cred.gid == tcred.sgid
+    )
+}
+
+fn ptrace_attach(child: &TaskStruct) -> LinuxResult<usize> {
+    let current = task::current();
+    if child.tgid() == current.tgid() || child.try_mm().is_none() {
+        return Err(LinuxError::EPERM);
+    }
+    if !may_attach(child) {
+        return Err(LinuxError::EPERM);
+    }
+    link(child, current.tgid())?;
+    info!("ptrace: task {} attached to {}", child.tid(), current.tgid());
+    child.send_sig_info(kill_info(SIGSTOP));
+    Ok(0)
+}
+
+/// Checks that `child` is a tracee of the current task, and is stopped,
+/// unless `ignore_state`.
+fn check_attach(child: &TaskStruct, ignore_state: bool) -> LinuxResult {
+    if !is_tracer_of(child) {
+        return Err(LinuxError::ESRCH);
+    }
+    if !ignore_state && !child.ptrace.stopped.load(Ordering::Acquire) {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(())
+}
+
+/// Resumes the stopped `child` with the signal `signo`, or none if 0.
+fn resume(child: &TaskStruct, signo: usize) -> LinuxResult<usize> {
+    if signo > NSIG {
+        return Err(LinuxError::EIO);
+    }
+    child.ptrace.resume_signo.store(signo, Ordering::Release);
+    child.ptrace.stopped.store(false, Ordering::Release);
+    run_queue::wake_up_task(child.tid());
+    Ok(0)
+}
+
+/// The index of the register at the byte offset `offset` of the user
+/// registers, as PTRACE_PEEKUSR has it.
+fn reg_index(offset: usize) -> LinuxResult<usize> {
+    if offset % size_of::<usize>() != 0 || offset >= size_of::<UserRegs>() {
+        return Err(LinuxError::EIO);
+    }
+    Ok(offset / size_of::<usize>())
+}
+
+fn reg_words(regs: &mut UserRegs) -> &mut [usize] {
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            regs as *mut UserRegs as *mut usize,
+            size_of::<UserRegs>() / size_of::<usize>(),
+        )
+    }
+}
+
+fn put_user<T>(addr: usize, val: T) {
+    unsafe { (addr as *mut T).write(val) };
+}
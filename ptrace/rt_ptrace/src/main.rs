@@ -0,0 +1,164 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+
+use core::mem::size_of;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use axerrno::{linux_err, LinuxError};
+use mmap::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+use ptrace::*;
+use task::{TaskRef, SIGSTOP, SIGTRAP};
+
+const PAGE_SIZE: usize = 4096;
+
+/// How many syscalls the tracee has made.
+static SYSCALLS: AtomicUsize = AtomicUsize::new(0);
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_ptrace]: ...");
+
+    fork::init(cpu_id, dtb_pa);
+    task::alloc_mm();
+
+    // The tracee is a kernel thread, whose parent is the tracer, and which
+    // shares its mm: the memory it peeks and pokes is a page of it.
+    let va = mmap::mmap(0, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0)
+        .unwrap();
+    let mut fixup = 0;
+    let page = mmap::faultin_page(va, 0, 0, &mut fixup).unwrap();
+
+    let tid = fork::kernel_thread(tracee, None);
+    let child = task::get_task(tid).unwrap();
+    let pid = task::pid_vnr(tid);
+
+    assert_eq!(wait_stop(&child), stopped_status(SIGSTOP));
+    test_errors(pid);
+    test_peek_poke(pid, va, page);
+    test_regs(pid);
+    test_syscall_stops(pid, &child);
+
+    info!("[rt_ptrace]: ok!");
+    axhal::misc::terminate();
+}
+
+/// Asks to be traced, stops as a raised SIGSTOP would have it, and then
+/// makes some syscalls, whose stops are only reported as the entry and
+/// the exit of the syscall layer report them.
+fn tracee() {
+    assert_eq!(ptrace(PTRACE_TRACEME, 0, 0, 0), 0);
+    // Traced already.
+    assert_eq!(ptrace(PTRACE_TRACEME, 0, 0, 0), linux_err!(EPERM));
+    ptrace_group_stop(SIGSTOP);
+
+    while SYSCALLS.load(Ordering::Relaxed) < 2 {
+        report_syscall_entry();
+        report_syscall_exit();
+        SYSCALLS.fetch_add(1, Ordering::Relaxed);
+    }
+    DONE.store(true, Ordering::Release);
+    run_queue::exit_current(0);
+}
+
+fn stopped_status(signo: usize) -> u32 {
+    ((signo as u32) << 8) | 0x7f
+}
+
+/// Waits for the next stop of `child`, as wait4 of its tracer does.
+fn wait_stop(child: &TaskRef) -> u32 {
+    loop {
+        if let Some(status) = wait_stopped(child, false) {
+            return status;
+        }
+        task::yield_now();
+    }
+}
+
+/// Requests that aren't allowed, or not to a tracee that runs.
+fn test_errors(pid: usize) {
+    let self_pid = task::pid_vnr(task::current().tid());
+    assert_eq!(ptrace(PTRACE_ATTACH, self_pid, 0, 0), linux_err!(EPERM));
+    assert_eq!(ptrace(PTRACE_CONT, self_pid, 0, 0), linux_err!(ESRCH));
+    assert_eq!(ptrace(PTRACE_CONT, 0x7fff_ffff, 0, 0), linux_err!(ESRCH));
+    assert_eq!(ptrace(PTRACE_SETOPTIONS, pid, 0, 1 << 31), linux_err!(EINVAL));
+    assert_eq!(ptrace(PTRACE_SINGLESTEP, pid, 0, 0), linux_err!(EIO));
+    info!("[rt_ptrace]: errors ok!");
+}
+
+/// Words of the tracee are read and written through its mm, but not
+/// where nothing is mapped.
+fn test_peek_poke(pid: usize, va: usize, page: usize) {
+    let words = page as *mut usize;
+    unsafe { words.add(1).write(0x1234_5678) };
+
+    let mut word = 0usize;
+    let off = size_of::<usize>();
+    assert_eq!(ptrace(PTRACE_PEEKDATA, pid, va + off, &mut word as *mut _ as usize), 0);
+    assert_eq!(word, 0x1234_5678);
+    assert_eq!(ptrace(PTRACE_POKEDATA, pid, va + off, 0xdead_beef), 0);
+    assert_eq!(unsafe { words.add(1).read() }, 0xdead_beef);
+    // A word across the end of the page reads into what isn't there.
+    assert_eq!(ptrace(PTRACE_PEEKTEXT, pid, va + PAGE_SIZE - 4, &mut word as *mut _ as usize), linux_err!(EIO));
+    assert_eq!(ptrace(PTRACE_POKETEXT, pid, va + PAGE_SIZE, 0), linux_err!(EIO));
+    info!("[rt_ptrace]: peek and poke ok!");
+}
+
+/// The registers of the tracee are those of its trap frame, a word at a
+/// time by their offset too.
+fn test_regs(pid: usize) {
+    let mut regs = UserRegs::default();
+    assert_eq!(ptrace(PTRACE_GETREGS, pid, 0, &mut regs as *mut _ as usize), 0);
+    let first = unsafe { *(&regs as *const _ as *const usize) };
+
+    let mut word = 0usize;
+    assert_eq!(ptrace(PTRACE_PEEKUSR, pid, 0, &mut word as *mut _ as usize), 0);
+    assert_eq!(word, first);
+    assert_eq!(ptrace(PTRACE_POKEUSR, pid, 0, 0x1000), 0);
+    assert_eq!(ptrace(PTRACE_PEEKUSR, pid, 0, &mut word as *mut _ as usize), 0);
+    assert_eq!(word, 0x1000);
+    assert_eq!(ptrace(PTRACE_PEEKUSR, pid, 1, &mut word as *mut _ as usize), linux_err!(EIO));
+    assert_eq!(ptrace(PTRACE_PEEKUSR, pid, size_of::<UserRegs>(), &mut word as *mut _ as usize), linux_err!(EIO));
+
+    assert_eq!(ptrace(PTRACE_SETREGS, pid, 0, &regs as *const _ as usize), 0);
+    assert_eq!(ptrace(PTRACE_PEEKUSR, pid, 0, &mut word as *mut _ as usize), 0);
+    assert_eq!(word, first);
+    info!("[rt_ptrace]: registers ok!");
+}
+
+/// After PTRACE_SYSCALL, the tracee stops at the entry and at the exit of
+/// its next syscall, with SIGTRAP | 0x80 for PTRACE_O_TRACESYSGOOD. It
+/// can't be asked anything while it runs, and after PTRACE_CONT it no
+/// longer stops.
+fn test_syscall_stops(pid: usize, child: &TaskRef) {
+    assert_eq!(ptrace(PTRACE_SETOPTIONS, pid, 0, PTRACE_O_TRACESYSGOOD), 0);
+    assert_eq!(ptrace(PTRACE_SYSCALL, pid, 0, 0), 0);
+    let mut word = 0usize;
+    assert_eq!(ptrace(PTRACE_PEEKUSR, pid, 0, &mut word as *mut _ as usize), linux_err!(ESRCH));
+
+    assert_eq!(wait_stop(child), stopped_status(SIGTRAP | 0x80));
+    assert_eq!(SYSCALLS.load(Ordering::Relaxed), 0);
+    assert_eq!(ptrace(PTRACE_SYSCALL, pid, 0, 0), 0);
+    assert_eq!(wait_stop(child), stopped_status(SIGTRAP | 0x80));
+    // Reported only once.
+    assert_eq!(wait_stopped(child, false), None);
+
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    while !DONE.load(Ordering::Acquire) {
+        assert_eq!(wait_stopped(child, true), None);
+        task::yield_now();
+    }
+    assert_eq!(SYSCALLS.load(Ordering::Relaxed), 2);
+    info!("[rt_ptrace]: syscall stops ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
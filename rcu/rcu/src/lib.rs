@@ -0,0 +1,218 @@
+//! Read-copy-update, for data that is read far more often than updated.
+//!
+//! Readers run between [`rcu_read_lock`] and [`rcu_read_unlock`] without
+//! taking any lock. An updater publishes a new version, and frees the old
+//! one only after a grace period, i.e. once every reader that could have
+//! seen it has finished: with [`synchronize_rcu`] or [`call_rcu`].
+//!
+//! Read-side sections disable preemption, so a CPU has no reader left when
+//! its scheduler tick interrupts a context outside of any section, or when
+//! it idles with the tick stopped. The tick reports these quiescent states
+//! with [`rcu_check_callbacks`], and callbacks whose grace period has
+//! ended run in [`rcu_process_callbacks`].
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+const SMP: usize = axconfig::SMP;
+
+type RcuCallback = Box<dyn FnOnce() + Send>;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const CALLBACKS_INIT: SpinNoIrq<VecDeque<(u64, RcuCallback)>> = SpinNoIrq::new(VecDeque::new());
+
+struct GpState {
+    /// Number of grace periods that have ended.
+    completed: u64,
+    /// Whether grace period `completed + 1` is in progress.
+    active: bool,
+    /// The latest grace period someone waits for.
+    requested: u64,
+    /// CPUs that haven't passed a quiescent state in the current one.
+    qs_needed: usize,
+}
+
+static GP: SpinNoIrq<GpState> = SpinNoIrq::new(GpState {
+    completed: 0,
+    active: false,
+    requested: 0,
+    qs_needed: 0,
+});
+/// Copy of `GpState::completed`, readable without the lock.
+static COMPLETED: AtomicU64 = AtomicU64::new(0);
+/// Tasks in `synchronize_rcu`.
+static GP_WAIT: WaitQueue = WaitQueue::new();
+
+/// CPUs that have been seen running, which must pass quiescent states.
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+/// Nesting depth of read-side sections on each CPU.
+static READERS: [AtomicUsize; SMP] = [ZERO; SMP];
+/// Callbacks of each CPU with the grace period they wait for, in order.
+static CALLBACKS: [SpinNoIrq<VecDeque<(u64, RcuCallback)>>; SMP] = [CALLBACKS_INIT; SMP];
+
+fn this_cpu() -> usize {
+    taskctx::current_ctx().cpu()
+}
+
+/// Enters a read-side section. Sections nest, and mustn't block.
+pub fn rcu_read_lock() {
+    preempt_guard::preempt_disable();
+    READERS[this_cpu()].fetch_add(1, Ordering::AcqRel);
+}
+
+/// Leaves a read-side section.
+pub fn rcu_read_unlock() {
+    let prev = READERS[this_cpu()].fetch_sub(1, Ordering::AcqRel);
+    assert!(prev > 0, "unbalanced rcu_read_unlock");
+    preempt_guard::preempt_enable();
+}
+
+fn start_gp(gp: &mut GpState) {
+    gp.active = true;
+    gp.qs_needed = ONLINE.load(Ordering::Acquire) | (1 << this_cpu());
+}
+
+/// Returns the grace period that a reader which is running now will have
+/// left by its end, starting one if needed.
+fn request_gp() -> u64 {
+    let mut gp = GP.lock();
+    // A grace period in progress may have started before the caller's
+    // readers, wait for the next one.
+    let target = if gp.active {
+        gp.completed + 2
+    } else {
+        start_gp(&mut gp);
+        gp.completed + 1
+    };
+    gp.requested = gp.requested.max(target);
+    target
+}
+
+fn report_qs(gp: &mut GpState, cpu: usize) {
+    gp.qs_needed &= !(1 << cpu);
+    if !gp.active || gp.qs_needed != 0 {
+        return;
+    }
+    gp.completed += 1;
+    gp.active = false;
+    COMPLETED.store(gp.completed, Ordering::Release);
+    if gp.requested > gp.completed {
+        start_gp(gp);
+    }
+    GP_WAIT.notify_all(false);
+}
+
+/// Waits until all read-side sections running now have finished.
+pub fn synchronize_rcu() {
+    assert_eq!(READERS[this_cpu()].load(Ordering::Acquire), 0);
+    let target = request_gp();
+    GP_WAIT.wait_until(|| COMPLETED.load(Ordering::Acquire) >= target);
+}
+
+/// Runs `f` in softirq context after all read-side sections running now
+/// have finished.
+pub fn call_rcu<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let target = request_gp();
+    CALLBACKS[this_cpu()].lock().push_back((target, Box::new(f)));
+}
+
+/// Reports a quiescent state of this CPU if the tick has interrupted it
+/// outside of any read-side section. Called on each scheduler tick.
+///
+/// Returns whether this CPU has callbacks to run in `rcu_process_callbacks`.
+pub fn rcu_check_callbacks() -> bool {
+    let cpu = this_cpu();
+    ONLINE.fetch_or(1 << cpu, Ordering::AcqRel);
+    {
+        let mut gp = GP.lock();
+        if READERS[cpu].load(Ordering::Acquire) == 0 {
+            report_qs(&mut gp, cpu);
+        }
+        // Idle CPUs without tick have no readers, but can't report it.
+        for other in (0..SMP).filter(|other| gp.qs_needed & (1 << other) != 0) {
+            if run_queue::tick_stopped(other) {
+                report_qs(&mut gp, other);
+            }
+        }
+    }
+    let completed = COMPLETED.load(Ordering::Acquire);
+    CALLBACKS[cpu]
+        .lock()
+        .front()
+        .map_or(false, |(target, _)| *target <= completed)
+}
+
+/// Runs callbacks of this CPU whose grace period has ended.
+pub fn rcu_process_callbacks() {
+    let cpu = this_cpu();
+    let completed = COMPLETED.load(Ordering::Acquire);
+    loop {
+        let cb = {
+            let mut callbacks = CALLBACKS[cpu].lock();
+            match callbacks.front() {
+                Some((target, _)) if *target <= completed => callbacks.pop_front(),
+                _ => None,
+            }
+        };
+        match cb {
+            Some((_, f)) => f(),
+            None => break,
+        }
+    }
+}
+
+/// A pointer to RCU-protected data: readers see either the old or the new
+/// version while it's updated, and the old one is dropped after a grace
+/// period.
+pub struct RcuPtr<T: Send + Sync + 'static> {
+    ptr: AtomicPtr<T>,
+}
+
+impl<T: Send + Sync + 'static> RcuPtr<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(data))),
+        }
+    }
+
+    /// Calls `f` on the current version within a read-side section.
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        rcu_read_lock();
+        // Safety: the version isn't dropped before we leave the section.
+        let ret = f(unsafe { &*self.ptr.load(Ordering::Acquire) });
+        rcu_read_unlock();
+        ret
+    }
+
+    /// Publishes `data`, dropping the old version after a grace period.
+    /// Concurrent updaters must be serialized by the caller.
+    pub fn update(&self, data: T) {
+        let old = self.ptr.swap(Box::into_raw(Box::new(data)), Ordering::AcqRel);
+        let old = old as usize;
+        call_rcu(move || {
+            // Safety: no reader can see `old` after the grace period.
+            drop(unsafe { Box::from_raw(old as *mut T) });
+        });
+    }
+}
+
+impl<T: Send + Sync + 'static> Drop for RcuPtr<T> {
+    fn drop(&mut self) {
+        let ptr = self.ptr.swap(ptr::null_mut(), Ordering::AcqRel);
+        // Safety: readers borrow `self`, none is left.
+        drop(unsafe { Box::from_raw(ptr) });
+    }
+}
@@ -5,8 +5,11 @@
 extern crate axlog2;
 extern crate alloc;
 
+use alloc::vec::Vec;
 use core::panic::PanicInfo;
-use taskctx::TaskState::Dead;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use taskctx::TaskState::Interruptible;
+use taskctx::{SCHED_BATCH, SCHED_FIFO};
 
 /// Entry
 #[no_mangle]
@@ -22,21 +25,75 @@ pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
     page_table::init();
 
     run_queue::init(cpu_id, dtb_pa);
+    run_queue::set_wakeup_boost(false);
 
     let ctx = run_queue::spawn_task_raw(1, || {
         info!("In new task:");
-        let ctx = taskctx::current_ctx();
-        ctx.set_state(Dead);
-        run_queue::yield_now();
+        run_queue::exit_current(0);
     });
     let rq = run_queue::task_rq(&ctx);
+    assert!(!rq.lock().set_priority(&ctx, 20));
+    assert!(!rq.lock().set_policy(&ctx, SCHED_FIFO, 0));
+    assert!(rq.lock().set_policy(&ctx, SCHED_BATCH, -5));
+    assert_eq!((ctx.policy(), ctx.priority()), (SCHED_BATCH, -5));
     rq.lock().activate_task(ctx.clone());
     rq.lock().resched(false);
 
+    let ctx = run_queue::spawn_task_raw(2, || {
+        run_queue::block_current(Interruptible);
+        info!("Woken up:");
+        run_queue::exit_current(7);
+    });
+    run_queue::activate_task(ctx.clone());
+    run_queue::yield_now();
+    assert!(ctx.is_interruptible());
+    assert!(!run_queue::wake_up_task(3));
+    assert!(run_queue::wake_up_task(2));
+    assert!(ctx.is_runnable());
+    run_queue::yield_now();
+    assert!(ctx.is_zombie());
+    assert_eq!(ctx.exit_code(), 7);
+    let stats = ctx.sched_stats();
+    assert_eq!((stats.nr_switches, stats.nr_voluntary_switches), (2, 2));
+    assert!(run_queue::stats().cpus[0].nr_switches >= 4);
+    test_yield();
+
+    // The current CPU can't go offline, and is online already.
+    assert!(!run_queue::offline_cpu(0));
+    assert!(!run_queue::online_cpu(0));
+    assert!(run_queue::cpu_online(0));
+
     info!("[rt_run_queue]: ok!");
     axhal::misc::terminate();
 }
 
+const NR_YIELDS: usize = 3;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+static TRACE: [AtomicUsize; 2 * NR_YIELDS] = [ZERO; 2 * NR_YIELDS];
+static TRACE_POS: AtomicUsize = AtomicUsize::new(0);
+
+/// Two tasks that keep yielding run by turns.
+fn test_yield() {
+    for tid in [3, 4] {
+        let ctx = run_queue::spawn_task_raw(tid, move || {
+            for _ in 0..NR_YIELDS {
+                let pos = TRACE_POS.fetch_add(1, Ordering::Relaxed);
+                TRACE[pos].store(tid, Ordering::Relaxed);
+                run_queue::yield_now();
+            }
+            run_queue::exit_current(0);
+        });
+        run_queue::activate_task(ctx);
+    }
+    while TRACE_POS.load(Ordering::Relaxed) < 2 * NR_YIELDS {
+        run_queue::yield_now();
+    }
+    let trace: Vec<usize> = TRACE.iter().map(|t| t.load(Ordering::Relaxed)).collect();
+    assert_eq!(trace, [3, 4, 3, 4, 3, 4]);
+}
+
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
     error!("{}", info);
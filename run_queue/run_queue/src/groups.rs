@@ -0,0 +1,146 @@
+//! Task groups sharing CPU bandwidth, a lightweight take on cgroups'
+//! `cpu.shares` and `cpu.cfs_quota_us`/`cpu.cfs_period_us`.
+//!
+//! Only normal tasks are affected. The shares of a group scale the weight of
+//! each of its tasks, there is no hierarchical fair share between groups.
+//! The quota is consumed by all tasks of the group on all CPUs; once it's
+//! used up, they don't run until the next period.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spinbase::SpinNoIrq;
+use taskctx::CtxRef;
+
+/// Id of a task group.
+pub type GroupId = usize;
+
+/// The group of all tasks which haven't been attached to another one.
+pub const ROOT_GROUP: GroupId = 0;
+
+/// Shares of a new group, the same as a nice-0 task.
+pub const DEFAULT_SHARES: usize = 1024;
+
+const DEF_PERIOD_US: usize = 100_000;
+const NSEC_PER_USEC: u64 = 1_000;
+const TICK_NANOS: u64 = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+struct TaskGroup {
+    shares: usize,
+    period_ns: u64,
+    /// Runtime allowed per period, `None` for unlimited.
+    quota_ns: Option<u64>,
+    period_start: u64,
+    /// Runtime consumed in the current period.
+    runtime_ns: u64,
+}
+
+impl TaskGroup {
+    const fn new() -> Self {
+        Self {
+            shares: DEFAULT_SHARES,
+            period_ns: DEF_PERIOD_US as u64 * NSEC_PER_USEC,
+            quota_ns: None,
+            period_start: 0,
+            runtime_ns: 0,
+        }
+    }
+
+    fn refresh(&mut self, now: u64) {
+        if now >= self.period_start + self.period_ns {
+            self.period_start = now;
+            self.runtime_ns = 0;
+        }
+    }
+
+    fn throttled(&self) -> bool {
+        self.quota_ns.map_or(false, |quota| self.runtime_ns >= quota)
+    }
+}
+
+static GROUPS: SpinNoIrq<BTreeMap<GroupId, TaskGroup>> = SpinNoIrq::new(BTreeMap::new());
+static NEXT_GROUP: AtomicUsize = AtomicUsize::new(ROOT_GROUP + 1);
+
+/// Creates a task group with default shares and no quota.
+pub fn create_group() -> GroupId {
+    let id = NEXT_GROUP.fetch_add(1, Ordering::Relaxed);
+    GROUPS.lock().insert(id, TaskGroup::new());
+    id
+}
+
+/// Removes a task group. Its tasks fall back to the root group.
+pub fn remove_group(id: GroupId) -> bool {
+    GROUPS.lock().remove(&id).is_some()
+}
+
+/// Sets the shares of a task group, relative to `DEFAULT_SHARES`.
+pub fn set_group_shares(id: GroupId, shares: usize) -> bool {
+    if shares == 0 {
+        return false;
+    }
+    match GROUPS.lock().get_mut(&id) {
+        Some(group) => {
+            group.shares = shares;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Allows tasks of a group to run `quota_us` out of every `period_us` in
+/// total. A negative `quota_us` means unlimited.
+pub fn set_group_bandwidth(id: GroupId, period_us: usize, quota_us: isize) -> bool {
+    if period_us == 0 || quota_us == 0 {
+        return false;
+    }
+    match GROUPS.lock().get_mut(&id) {
+        Some(group) => {
+            group.period_ns = period_us as u64 * NSEC_PER_USEC;
+            group.quota_ns = (quota_us > 0).then(|| quota_us as u64 * NSEC_PER_USEC);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Moves `task` into group `id`; the new shares apply from its next tick.
+pub fn attach_task(task: &CtxRef, id: GroupId) -> bool {
+    if id != ROOT_GROUP && !GROUPS.lock().contains_key(&id) {
+        return false;
+    }
+    task.set_group(id);
+    true
+}
+
+/// Shares of the group of `task`.
+pub(crate) fn task_shares(task: &CtxRef) -> usize {
+    GROUPS
+        .lock()
+        .get(&task.group())
+        .map_or(DEFAULT_SHARES, |group| group.shares)
+}
+
+/// Charges a tick to the group of `task`. Returns `true` if the group has
+/// used up its quota.
+pub(crate) fn charge_tick(task: &CtxRef) -> bool {
+    let now = axhal::time::current_time_nanos();
+    let mut groups = GROUPS.lock();
+    let Some(group) = groups.get_mut(&task.group()) else {
+        return false;
+    };
+    group.refresh(now);
+    group.runtime_ns += TICK_NANOS;
+    group.throttled()
+}
+
+/// Whether the group of `task` has used up its quota in this period.
+pub(crate) fn throttled(task: &CtxRef) -> bool {
+    let now = axhal::time::current_time_nanos();
+    let mut groups = GROUPS.lock();
+    match groups.get_mut(&task.group()) {
+        Some(group) => {
+            group.refresh(now);
+            group.throttled()
+        }
+        None => false,
+    }
+}
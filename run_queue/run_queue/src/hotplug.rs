@@ -0,0 +1,84 @@
+//! CPU hotplug: taking a CPU out of scheduling and bringing it back.
+//!
+//! An offline CPU only picks its idle task, which parks the CPU waiting for
+//! interrupts with the tick stopped. Queued tasks that may run on other
+//! CPUs are migrated away, the ones bound to it wait until it's back.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::run_queue::{all_tasks, RUN_QUEUES};
+
+const SMP: usize = axconfig::SMP;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const TRUE: AtomicBool = AtomicBool::new(true);
+#[allow(clippy::declare_interior_mutable_const)]
+const FALSE: AtomicBool = AtomicBool::new(false);
+
+static ONLINE: [AtomicBool; SMP] = [TRUE; SMP];
+/// Whether the idle task of each CPU has parked it.
+static PARKED: [AtomicBool; SMP] = [FALSE; SMP];
+
+/// Whether `cpu` takes part in scheduling.
+pub fn cpu_online(cpu: usize) -> bool {
+    ONLINE[cpu].load(Ordering::Acquire)
+}
+
+/// Whether `cpu` has been brought up, online or not.
+pub fn cpu_present(cpu: usize) -> bool {
+    cpu < SMP && RUN_QUEUES[cpu].is_init()
+}
+
+/// Takes `cpu` offline, and migrates its tasks to other CPUs. It waits
+/// until the task running there is switched out.
+///
+/// Returns `false` if `cpu` is the current CPU, or isn't online.
+pub fn offline_cpu(cpu: usize) -> bool {
+    let this = taskctx::current_ctx().cpu();
+    if cpu >= SMP || cpu == this || !RUN_QUEUES[cpu].is_init() {
+        return false;
+    }
+    if !ONLINE[cpu].swap(false, Ordering::AcqRel) {
+        return false;
+    }
+    info!("cpu {} going offline", cpu);
+    for task in all_tasks().iter().filter(|t| t.cpu() == cpu && t.is_running()) {
+        task.set_preempt_pending(true);
+    }
+    crate::ipi::kick_cpu(cpu);
+    while !PARKED[cpu].load(Ordering::Acquire) {
+        crate::yield_now();
+    }
+    let tasks = RUN_QUEUES[cpu].lock().take_migratable();
+    for task in tasks {
+        crate::activate_task(task);
+    }
+    true
+}
+
+/// Brings `cpu` back online, kicking it out of its parking loop.
+///
+/// Returns `false` if `cpu` isn't offline.
+pub fn online_cpu(cpu: usize) -> bool {
+    if cpu >= SMP || !RUN_QUEUES[cpu].is_init() {
+        return false;
+    }
+    let ret = !ONLINE[cpu].swap(true, Ordering::AcqRel);
+    if ret {
+        info!("cpu {} back online", cpu);
+        crate::ipi::kick_cpu(cpu);
+    }
+    ret
+}
+
+/// Parks the current CPU, whose idle task is running with IRQs disabled,
+/// until it's back online.
+pub(crate) fn park(cpu: usize) {
+    PARKED[cpu].store(true, Ordering::Release);
+    crate::timers::stop_tick(cpu);
+    while !cpu_online(cpu) {
+        axhal::arch::wait_for_irqs_and_enable();
+        axhal::arch::disable_irqs();
+    }
+    crate::timers::restart_tick(cpu);
+    PARKED[cpu].store(false, Ordering::Release);
+}
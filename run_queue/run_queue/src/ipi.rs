@@ -0,0 +1,89 @@
+//! Reschedule IPIs, and wakeups batched in interrupt context.
+//!
+//! A task woken up for another CPU is put on the wake list of that CPU,
+//! which is kicked with an IPI to enqueue it at once rather than at its
+//! next tick. In interrupt context, wakeups of tasks of other run queues
+//! are deferred to the end of the outermost IRQ: each run queue is then
+//! locked once for all of its tasks, and each CPU is kicked once.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spinbase::SpinNoIrq;
+use taskctx::CtxRef;
+use crate::run_queue::RUN_QUEUES;
+
+const SMP: usize = axconfig::SMP;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const BATCH_INIT: SpinNoIrq<Vec<CtxRef>> = SpinNoIrq::new(Vec::new());
+
+/// Nesting depth of wake batches on each CPU.
+static BATCH_DEPTH: [AtomicUsize; SMP] = [ZERO; SMP];
+/// Deferred wakeups of each CPU.
+static BATCH: [SpinNoIrq<Vec<CtxRef>>; SMP] = [BATCH_INIT; SMP];
+/// CPUs to kick at the end of the batch of each CPU, one bit per CPU.
+static DEFERRED_KICKS: [AtomicUsize; SMP] = [ZERO; SMP];
+
+fn this_cpu() -> usize {
+    taskctx::current_ctx().cpu()
+}
+
+fn in_batch(cpu: usize) -> bool {
+    BATCH_DEPTH[cpu].load(Ordering::Acquire) > 0
+}
+
+/// Sends a reschedule IPI to `cpu`, or at the end of the current batch.
+pub(crate) fn kick_cpu(cpu: usize) {
+    let this = this_cpu();
+    if in_batch(this) {
+        DEFERRED_KICKS[this].fetch_or(1 << cpu, Ordering::AcqRel);
+    } else {
+        axhal::platform::irq::send_ipi(cpu);
+    }
+}
+
+/// Defers the wakeup of `task` to the end of the current batch, if it's
+/// in the run queue of another CPU. Returns whether it's deferred.
+pub(crate) fn defer_wakeup(task: &CtxRef) -> bool {
+    let this = this_cpu();
+    if !in_batch(this) || task.cpu() == this {
+        return false;
+    }
+    BATCH[this].lock().push(task.clone());
+    true
+}
+
+/// Starts batching wakeups on this CPU. Called on IRQ entry, batches nest.
+pub fn begin_wake_batch() {
+    BATCH_DEPTH[this_cpu()].fetch_add(1, Ordering::AcqRel);
+}
+
+/// Ends a batch of this CPU, with IRQs disabled. The outermost one does
+/// the deferred wakeups and sends the IPIs.
+pub fn end_wake_batch() {
+    let this = this_cpu();
+    if BATCH_DEPTH[this].load(Ordering::Acquire) > 1 {
+        BATCH_DEPTH[this].fetch_sub(1, Ordering::AcqRel);
+        return;
+    }
+    let mut tasks = core::mem::take(&mut *BATCH[this].lock());
+    while let Some(first) = tasks.first() {
+        let cpu = first.cpu();
+        let mut rq = RUN_QUEUES[cpu].lock();
+        // Tasks that have migrated meanwhile are left for another round.
+        tasks.retain(|task| {
+            if task.cpu() != cpu {
+                return true;
+            }
+            crate::try_wake_up(&mut rq, task.clone());
+            false
+        });
+    }
+    BATCH_DEPTH[this].store(0, Ordering::Release);
+    let kicks = DEFERRED_KICKS[this].swap(0, Ordering::AcqRel);
+    for cpu in (0..SMP).filter(|cpu| kicks & (1 << cpu) != 0) {
+        axhal::platform::irq::send_ipi(cpu);
+    }
+}
@@ -16,17 +16,58 @@
 
 use alloc::boxed::Box;
 use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::TimeValue;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 use taskctx::CtxRef;
-use crate::run_queue::RUN_QUEUE;
+use crate::run_queue::{RUN_QUEUES, find_task, queue_remote, select_task_rq};
 use spinbase::SpinNoIrq;
-use taskctx::{Tid, SchedInfo};
+use taskctx::{Tid, SchedInfo, TaskState};
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
+mod groups;
+mod hotplug;
+mod ipi;
+mod perf;
 mod run_queue;
+mod stats;
+mod timers;
+mod trace;
 pub use run_queue::AxRunQueue;
+pub use stats::{stats, CpuStats, Stats};
+pub use groups::{attach_task, create_group, remove_group, set_group_bandwidth, set_group_shares};
+pub use hotplug::{cpu_online, cpu_present, offline_cpu, online_cpu};
+pub use ipi::{begin_wake_batch, end_wake_batch};
+pub use perf::{register_perf_switch, unregister_perf_switch, PerfSwitchHook};
+pub use groups::{GroupId, DEFAULT_SHARES, ROOT_GROUP};
+pub use trace::{register_sched_migrate, register_sched_switch, register_sched_wakeup};
+pub use trace::{unregister_sched_probes, SchedMigrateProbe, SchedSwitchProbe, SchedWakeupProbe};
+pub use timers::{set_alarm_wakeup, cancel_alarm, program_timer, tick_stopped};
+
+/// Whether woken tasks get an interactivity boost, see [`set_wakeup_boost`].
+static WAKEUP_BOOST: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables the interactivity boost of woken fair tasks: extra
+/// vruntime credit for the time they slept, and preemption of the current
+/// task if they're far enough behind it. Tests disable it for schedules
+/// that don't depend on timing.
+pub fn set_wakeup_boost(enabled: bool) {
+    WAKEUP_BOOST.store(enabled, Ordering::Release);
+}
+
+fn wakeup_boost_setup(value: &str) -> axerrno::LinuxResult {
+    set_wakeup_boost(cmdline::parse_bool(value)?);
+    Ok(())
+}
+cmdline::kernel_param!("sched_wakeup_boost", wakeup_boost_setup);
+
+pub(crate) fn wakeup_boost() -> bool {
+    WAKEUP_BOOST.load(Ordering::Acquire)
+}
 
 /// Initializes the run queue and scheduling system
 pub fn init(cpu_id: usize, dtb_pa: usize) {
@@ -35,24 +76,188 @@ pub fn init(cpu_id: usize, dtb_pa: usize) {
     taskctx::init(cpu_id, dtb_pa);
 
     let idle = taskctx::init_thread();
-    RUN_QUEUE.init_by(AxRunQueue::new(idle));
+    idle.set_cpu(cpu_id);
+    RUN_QUEUES[cpu_id].init_by(AxRunQueue::new(cpu_id, idle));
+}
+
+/// Initializes the run queue of a secondary CPU, whose running context
+/// becomes the idle task of that CPU.
+pub fn init_secondary(cpu_id: usize) {
+    let idle = taskctx::init_secondary(cpu_id);
+    RUN_QUEUES[cpu_id].init_by(AxRunQueue::new(cpu_id, idle));
 }
 
 /// Returns the run queue associated with a task
-pub fn task_rq(_task: &CtxRef) -> &SpinNoIrq<AxRunQueue> {
-    &RUN_QUEUE
+pub fn task_rq(task: &CtxRef) -> &SpinNoIrq<AxRunQueue> {
+    &RUN_QUEUES[task.cpu()]
+}
+
+/// Activates a new task on the least loaded CPU it is allowed to run on
+pub fn activate_task(task: CtxRef) {
+    let cpu = select_task_rq(&task);
+    if cpu != taskctx::current_ctx().cpu() {
+        // Its CPU enqueues it, and preempts its own current task for it.
+        queue_remote(task, cpu);
+        return;
+    }
+    RUN_QUEUES[cpu].lock().activate_task(task);
 }
 
-/// Voluntarily yields the current task's execution time
+/// Voluntarily yields the current task's execution time to the other
+/// ready tasks it competes with.
 pub fn yield_now() {
     let ctx = taskctx::current_ctx();
     let rq = task_rq(&ctx);
-    rq.lock().resched(false);
+    rq.lock().yield_current();
 }
 
-/// Forces unlock of the run queue lock
+/// Blocks the current task in `state` until `wake_up_task` is called on it.
+///
+/// A wakeup that arrives before the task blocks isn't lost, this returns
+/// at once instead. Callers should recheck their condition in a loop.
+pub fn block_current(state: TaskState) {
+    let ctx = taskctx::current_ctx();
+    let mut rq = task_rq(&ctx).lock();
+    if ctx.take_wakeup_pending() {
+        return;
+    }
+    rq.block_current(state, |_| {});
+}
+
+/// Wakes up the task `tid`, or lets its next `block_current` return at once
+/// if it isn't blocked. Returns `false` if there's no such task.
+pub fn wake_up_task(tid: Tid) -> bool {
+    let Some(task) = find_task(tid) else {
+        return false;
+    };
+    if !ipi::defer_wakeup(&task) {
+        let mut rq = task_rq(&task).lock();
+        try_wake_up(&mut rq, task);
+    }
+    true
+}
+
+/// Wakes up `task` whose run queue is locked, see [`wake_up_task`].
+pub(crate) fn try_wake_up(rq: &mut AxRunQueue, task: CtxRef) {
+    if task.is_blocked() {
+        rq.unblock_task(task, true);
+    } else {
+        task.set_wakeup_pending(true);
+    }
+}
+
+/// Handles a reschedule IPI: enqueues the tasks other CPUs have woken up
+/// for this one. The switch, if any, happens on IRQ return.
+pub fn on_resched_ipi() {
+    let ctx = taskctx::current_ctx();
+    let mut rq = RUN_QUEUES[ctx.cpu()].lock();
+    rq.drain_wake_list();
+    rq.check_preempt_queued();
+    // Leave idle at once.
+    if ctx.tid() == 0 && rq.has_queued() {
+        ctx.set_preempt_pending(true);
+    }
+}
+
+/// Sleeps the current task until `deadline` has passed.
+pub fn sleep_until(deadline: TimeValue) {
+    let ctx = taskctx::current_ctx();
+    while axhal::time::current_time() < deadline {
+        task_rq(&ctx).lock().sleep_until(deadline);
+    }
+    // Woken up early by `wake_up_task`.
+    cancel_alarm(&ctx);
+}
+
+/// Sleeps the current task for at least `dur`.
+pub fn sleep(dur: Duration) {
+    sleep_until(axhal::time::current_time() + dur);
+}
+
+/// Sleeps the current task until `deadline` has passed, or fails with
+/// `EINTR` as soon as a signal is pending.
+pub fn sleep_until_interruptible(deadline: TimeValue) -> LinuxResult {
+    let ctx = taskctx::current_ctx();
+    let mut ret = Ok(());
+    while axhal::time::current_time() < deadline {
+        let mut rq = task_rq(&ctx).lock();
+        // Checked under the lock signal senders take to wake us up, so
+        // a signal can't slip in before we sleep.
+        if taskctx::signal_pending(&ctx) {
+            ret = Err(LinuxError::EINTR);
+            break;
+        }
+        rq.sleep_until(deadline);
+    }
+    cancel_alarm(&ctx);
+    ret
+}
+
+/// Exits the current task with `exit_code`, leaving a zombie for its
+/// parent to reap.
+pub fn exit_current(exit_code: u32) -> ! {
+    let ctx = taskctx::current_ctx();
+    task_rq(&ctx).lock().exit_current(exit_code)
+}
+
+/// Runs as the idle task of this CPU: switches to any ready task, and
+/// otherwise waits for interrupts with the periodic tick stopped.
+pub fn idle_loop() -> ! {
+    let ctx = taskctx::current_ctx();
+    assert_eq!(ctx.tid(), 0);
+    let cpu = ctx.cpu();
+    loop {
+        axhal::arch::disable_irqs();
+        if !hotplug::cpu_online(cpu) {
+            hotplug::park(cpu);
+        }
+        let (queued, throttled) = {
+            let rq = RUN_QUEUES[cpu].lock();
+            (rq.has_queued(), rq.has_throttled())
+        };
+        if !queued {
+            if !throttled {
+                timers::stop_tick(cpu);
+            }
+            axhal::arch::wait_for_irqs_and_enable();
+            timers::restart_tick(cpu);
+        } else {
+            axhal::arch::enable_irqs();
+        }
+        yield_now();
+    }
+}
+
+/// Stops the periodic tick of this CPU for a system suspend, as if it
+/// idled: the timer fires for the next wakeup deadline only.
+pub fn suspend_tick() {
+    timers::stop_tick(taskctx::current_ctx().cpu());
+}
+
+/// Restarts the periodic tick of this CPU on resume.
+pub fn resume_tick() {
+    timers::restart_tick(taskctx::current_ctx().cpu());
+}
+
+/// Lends the priority of the current task to `owner`, the holder of a lock
+/// it's about to block on. Chains of blocked owners aren't followed.
+pub fn pi_boost(owner: Tid) {
+    let curr = taskctx::current_ctx();
+    if let Some(task) = find_task(owner) {
+        task_rq(&task).lock().boost_task(&task, curr.policy(), curr.priority());
+    }
+}
+
+/// Drops the priority the current task has inherited, if any.
+pub fn pi_unboost() {
+    let curr = taskctx::current_ctx();
+    task_rq(&curr).lock().unboost_task(&curr);
+}
+
+/// Forces unlock of the run queue lock of the current CPU
 pub fn force_unlock() {
-    unsafe { RUN_QUEUE.force_unlock() }
+    let ctx = taskctx::current_ctx();
+    unsafe { RUN_QUEUES[ctx.cpu()].force_unlock() }
 }
 
 /// Creates and enqueues a new task with a closure
@@ -74,12 +279,21 @@ pub fn spawn_task(tid: Tid, entry: Option<*mut dyn FnOnce()>) -> SchedInfo {
     sched_info
 }
 
-/// Handles periodic timer ticks for the task manager.
+/// Wakes up sleeping tasks whose deadline has passed. Called from the
+/// timer softirq, after a tick or a timer programmed for a deadline.
+pub fn on_timer_event() {
+    timers::check_events();
+}
+
+/// Handles periodic timer ticks for the task manager. `user` tells whether
+/// the tick has interrupted user mode.
 ///
-/// For example, advance scheduler states, checks timed events, etc.
-pub fn on_timer_tick() {
+/// For example, advance scheduler states, etc. Timed events are checked
+/// by `on_timer_event` instead.
+pub fn on_timer_tick(user: bool) {
     debug!("timer tick ...");
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    let ctx = taskctx::current_ctx();
+    task_rq(&ctx).lock().scheduler_timer_tick(user);
 }
 
 // Todo: We should move task_entry to taskctx.
@@ -93,8 +307,8 @@ pub extern "C" fn task_entry() -> ! {
 
     let ctx = taskctx::current_ctx();
     if ctx.set_child_tid != 0 {
-        let ctid_ptr = ctx.set_child_tid as *mut usize;
-        unsafe { (*ctid_ptr) = ctx.tid(); }
+        let ctid_ptr = ctx.set_child_tid as *mut u32;
+        unsafe { (*ctid_ptr) = ctx.vtid() as u32; }
     }
 
     if let Some(entry) = ctx.entry {
@@ -0,0 +1,35 @@
+//! The hook of the perf events on context switches, for the counters that
+//! count for a task only while it runs.
+//!
+//! Unlike the tracepoints, it is always there, and is called before the
+//! switch of the stacks.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use taskctx::Tid;
+
+/// Called on each context switch on `cpu`, from `prev` to `next`, with the
+/// run queue locked and IRQs off.
+pub type PerfSwitchHook = fn(cpu: usize, prev: Tid, next: Tid);
+
+/// The hook stored as an address, 0 if none.
+static PERF_SWITCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers the hook. Fails if one is registered already.
+pub fn register_perf_switch(hook: PerfSwitchHook) -> bool {
+    PERF_SWITCH
+        .compare_exchange(0, hook as usize, Ordering::AcqRel, Ordering::Relaxed)
+        .is_ok()
+}
+
+pub fn unregister_perf_switch() {
+    PERF_SWITCH.store(0, Ordering::Release);
+}
+
+#[inline(always)]
+pub(crate) fn perf_event_task_sched(cpu: usize, prev: Tid, next: Tid) {
+    let hook = PERF_SWITCH.load(Ordering::Acquire);
+    if hook != 0 {
+        let hook: PerfSwitchHook = unsafe { core::mem::transmute(hook) };
+        hook(cpu, prev, next);
+    }
+}
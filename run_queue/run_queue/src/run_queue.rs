@@ -1,6 +1,7 @@
 //! Run queue implementation for task scheduling
 //!
-//! This module implements a CFS (Completely Fair Scheduler) based run queue that:
+//! This module implements per-CPU run queues, based on a real-time and
+//! a CFS (Completely Fair Scheduler) class, that:
 //! - Manages task scheduling and switching
 //! - Handles task state transitions
 //! - Implements preemptive scheduling
@@ -11,7 +12,9 @@
 //! - Selecting the next task to run
 //! - Managing context switches between tasks
 //! - Handling task blocking and unblocking
+//! - Balancing load between CPUs
 
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use lazy_init::LazyInit;
 use scheduler::BaseScheduler;
@@ -23,17 +26,149 @@ use alloc::collections::VecDeque;
 
 use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
 */
-use core::sync::atomic::Ordering;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use axerrno::{LinuxError, LinuxResult};
 use spinbase::SpinNoIrq;
-use taskctx::{CtxRef, CurrentCtx};
+use taskctx::{CtxRef, CurrentCtx, Tid};
+use taskctx::{more_urgent, rt_policy, valid_priority, SCHED_RR};
+use crate::groups;
+use crate::stats::CpuStats;
+
+type FairItem = scheduler::CFSTask<CtxRef>;
+type FairScheduler = scheduler::CFScheduler<CtxRef>;
+type RtItem = scheduler::RTTask<CtxRef, RR_TIME_SLICE>;
+type RtScheduler = scheduler::RTScheduler<CtxRef, RR_TIME_SLICE>;
+
+/// Time slice of SCHED_RR tasks in ticks, 100ms.
+const RR_TIME_SLICE: usize = axconfig::TICKS_PER_SEC / 10;
+
+/// Default bandwidth of real-time tasks, like `sched_rt_period_us` and
+/// `sched_rt_runtime_us` of Linux: 0.95s out of every second, leaving the
+/// rest to normal tasks so that a runaway real-time task can't lock up
+/// the system. The command line may set them, before the run queues are
+/// created; a negative runtime is no limit.
+static DEF_RT_PERIOD_US: AtomicUsize = AtomicUsize::new(1_000_000);
+static DEF_RT_RUNTIME_US: AtomicIsize = AtomicIsize::new(950_000);
+
+fn rt_period_setup(value: &str) -> LinuxResult {
+    match value.parse() {
+        Ok(us) if us > 0 => {
+            DEF_RT_PERIOD_US.store(us, Ordering::Relaxed);
+            Ok(())
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+cmdline::kernel_param!("sched_rt_period_us", rt_period_setup);
+
+fn rt_runtime_setup(value: &str) -> LinuxResult {
+    let us = value.parse().map_err(|_| LinuxError::EINVAL)?;
+    DEF_RT_RUNTIME_US.store(us, Ordering::Relaxed);
+    Ok(())
+}
+cmdline::kernel_param!("sched_rt_runtime_us", rt_runtime_setup);
+
+const USEC_PER_SEC: usize = 1_000_000;
+
+#[inline]
+const fn us_to_ticks(us: usize) -> usize {
+    us * axconfig::TICKS_PER_SEC / USEC_PER_SEC
+}
+
+/// Ticks between two periodic load balancing runs.
+const BALANCE_INTERVAL: usize = 4;
+
+const SMP: usize = axconfig::SMP;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const RQ_INIT: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const WAKE_LIST_INIT: SpinNoIrq<Vec<CtxRef>> = SpinNoIrq::new(Vec::new());
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_VRUNTIME: AtomicIsize = AtomicIsize::new(0);
 
-type SchedItem = scheduler::CFSTask<CtxRef>;
-type Scheduler = scheduler::CFScheduler<CtxRef>;
+/// Per-CPU run queues, indexed by CPU id.
+pub(crate) static RUN_QUEUES: [LazyInit<SpinNoIrq<AxRunQueue>>; SMP] = [RQ_INIT; SMP];
 
-/// Global run queue instance
+/// Tasks woken up by other CPUs, to be enqueued by the owner of the run
+/// queue on the reschedule IPI they send, or at its next tick or
+/// reschedule. It avoids taking two run queue locks at once.
+static WAKE_LISTS: [SpinNoIrq<Vec<CtxRef>>; SMP] = [WAKE_LIST_INIT; SMP];
+
+/// Ready tasks of each run queue, readable without taking its lock.
+static NR_RUNNING: [AtomicUsize; SMP] = [ZERO; SMP];
+
+/// `min_vruntime` of the fair class of each run queue, to translate
+/// vruntime of migrating tasks.
+static MIN_VRUNTIME: [AtomicIsize; SMP] = [ZERO_VRUNTIME; SMP];
+
+/// Tasks that have been scheduled and haven't exited. It's only updated
+/// when a task gets its [`Entity`] and when it dies, the entity itself
+/// is kept in the task.
+static TASKS: SpinNoIrq<BTreeMap<Tid, CtxRef>> = SpinNoIrq::new(BTreeMap::new());
+
+/// All tasks that have been scheduled and haven't exited.
+pub(crate) fn all_tasks() -> Vec<CtxRef> {
+    TASKS.lock().values().cloned().collect()
+}
+
+/// Looks up a task that has been scheduled by its tid.
+pub(crate) fn find_task(tid: Tid) -> Option<CtxRef> {
+    TASKS.lock().get(&tid).cloned()
+}
+
+/// Selects the run queue for a task to be woken up on: the least loaded
+/// CPU it is allowed to run on, preferring the one it ran on last.
 ///
-/// TODO: This should be per-CPU in the future
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+/// Other CPUs idling with their tick stopped are kicked out of idle by an
+/// IPI, see [`crate::ipi`].
+pub(crate) fn select_task_rq(task: &CtxRef) -> usize {
+    let eligible = |cpu: usize| {
+        task.cpu_allowed(cpu) && RUN_QUEUES[cpu].is_init() && crate::hotplug::cpu_online(cpu)
+    };
+    let prev = task.cpu();
+    let mut best = prev;
+    let mut best_load = usize::MAX;
+    if eligible(prev) {
+        best_load = NR_RUNNING[prev].load(Ordering::Acquire);
+    }
+    for cpu in 0..SMP {
+        if cpu == prev || !eligible(cpu) {
+            continue;
+        }
+        let load = NR_RUNNING[cpu].load(Ordering::Acquire);
+        if load < best_load {
+            best = cpu;
+            best_load = load;
+        }
+    }
+    best
+}
+
+/// Lets `cpu` enqueue `task`, see [`WAKE_LISTS`], and kicks it if it's
+/// another CPU. It checks then whether the task preempts its current one.
+pub(crate) fn queue_remote(task: CtxRef, cpu: usize) {
+    NR_RUNNING[cpu].fetch_add(1, Ordering::Release);
+    WAKE_LISTS[cpu].lock().push(task);
+    if cpu != taskctx::current_ctx().cpu() {
+        crate::ipi::kick_cpu(cpu);
+    }
+}
+
+/// Moves the task's vruntime from the base of its run queue to `cpu`.
+fn migrate_vruntime(task: &CtxRef, fair: &FairItem, cpu: usize) {
+    let from = task.cpu();
+    if from != cpu {
+        let delta = MIN_VRUNTIME[cpu].load(Ordering::Acquire)
+            - MIN_VRUNTIME[from].load(Ordering::Acquire);
+        fair.set_vruntime(fair.get_vruntime() + delta);
+        task.set_cpu(cpu);
+        crate::trace::trace_sched_migrate(task.tid(), from, cpu);
+    }
+}
 
 /*
 // TODO: per-CPU
@@ -45,21 +180,339 @@ static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 */
 
+/// Scheduling entities of a task, one for each scheduling class.
+///
+/// Only the entity of the class selected by the task's policy is queued,
+/// the other one keeps its state (e.g. vruntime) for a later switch back.
+#[derive(Clone)]
+struct Entity {
+    fair: Arc<FairItem>,
+    rt: Arc<RtItem>,
+}
+
 /// Run queue structure that manages task scheduling
 ///
-/// Contains the CFS scheduler and an idle task that runs when no other
-/// tasks are available
+/// Contains the real-time scheduler, the CFS scheduler and an idle task
+/// that runs when no other tasks are available. Real-time tasks always
+/// run ahead of normal ones, as long as they are not throttled.
+///
+/// Each task has one scheduling entity for its whole life, which carries
+/// its vruntime and nice value across runs, sleeps and migrations. It's
+/// kept in the task itself, so run queues don't share a lock to find it,
+/// and dropped when the task is switched out as a zombie.
+///
+/// There is one run queue per CPU. Woken tasks are routed to the least
+/// loaded CPU, and idle or overloaded queues pull tasks from the busiest
+/// one.
 pub struct AxRunQueue {
-    scheduler: Scheduler,
-    idle: Arc<SchedItem>,
+    cpu: usize,
+    rt: RtScheduler,
+    fair: FairScheduler,
+    idle: CtxRef,
+    ticks: usize,
+    nr_switches: u64,
+    /// Normal tasks whose group has used up its quota, see `groups`.
+    throttled: Vec<CtxRef>,
+    /// The last task that exited on this CPU. Its kernel stack is in use
+    /// until the switch away from it completes, so it's kept alive here
+    /// till the next switch, even if the parent has reaped it.
+    zombie: Option<CtxRef>,
+
+    /// Length of a real-time bandwidth period, in ticks.
+    rt_period: usize,
+    /// Ticks real-time tasks may run per period, `None` for unlimited.
+    rt_runtime: Option<usize>,
+    /// Ticks consumed by real-time tasks in this period.
+    rt_time: usize,
+    /// Ticks elapsed in this period.
+    rt_clock: usize,
+    rt_throttled: bool,
 }
 
 impl AxRunQueue {
-    /// Creates a new run queue with the given idle task
-    pub fn new(idle: Arc<SchedInfo>) -> SpinNoIrq<Self> {
-        let idle = Arc::new(SchedItem::new(idle));
-        let scheduler = Scheduler::new();
-        SpinNoIrq::new(Self { scheduler, idle })
+    /// Creates a new run queue of `cpu` with the given idle task
+    pub fn new(cpu: usize, idle: Arc<SchedInfo>) -> SpinNoIrq<Self> {
+        SpinNoIrq::new(Self {
+            cpu,
+            rt: RtScheduler::new(),
+            fair: FairScheduler::new(),
+            idle,
+            ticks: 0,
+            nr_switches: 0,
+            throttled: Vec::new(),
+            zombie: None,
+            rt_period: us_to_ticks(DEF_RT_PERIOD_US.load(Ordering::Relaxed)),
+            rt_runtime: usize::try_from(DEF_RT_RUNTIME_US.load(Ordering::Relaxed))
+                .ok()
+                .map(us_to_ticks),
+            rt_time: 0,
+            rt_clock: 0,
+            rt_throttled: false,
+        })
+    }
+
+    /// Returns the scheduling entity of `task`, creating it on first use.
+    fn entity(&mut self, task: &CtxRef) -> Entity {
+        if let Some(se) = task.sched_entity().and_then(|se| se.downcast::<Entity>().ok()) {
+            return (*se).clone();
+        }
+        let se = Entity {
+            fair: Arc::new(FairItem::new(task.clone())),
+            rt: Arc::new(RtItem::new(task.clone())),
+        };
+        task.set_sched_entity(Some(Arc::new(se.clone())));
+        TASKS.lock().insert(task.tid(), task.clone());
+        self.apply_sched_params(task, &se);
+        se
+    }
+
+    /// Publishes the load of this run queue to other CPUs.
+    fn update_load(&self) {
+        let nr = self.rt.nr_running() + self.fair.nr_running();
+        NR_RUNNING[self.cpu].store(nr, Ordering::Release);
+        MIN_VRUNTIME[self.cpu].store(self.fair.min_vruntime(), Ordering::Release);
+    }
+
+    /// Propagates policy and priority of the task to its entities.
+    fn apply_sched_params(&mut self, task: &CtxRef, se: &Entity) {
+        if rt_policy(task.policy()) {
+            self.rt.set_priority(&se.rt, task.priority());
+            se.rt.set_round_robin(task.policy() == SCHED_RR);
+        } else {
+            self.fair.set_priority(&se.fair, task.priority());
+        }
+    }
+
+    fn enqueue_task(&mut self, task: &CtxRef) {
+        let now = axhal::time::current_time_nanos();
+        task.stat_enqueue(now);
+        let slept_ns = task.take_sleep_ns(now);
+        let se = self.entity(task);
+        se.fair.set_shares(groups::task_shares(task) as isize);
+        migrate_vruntime(task, &se.fair, self.cpu);
+        if rt_policy(task.policy()) {
+            self.rt.add_task(se.rt);
+        } else if slept_ns > 0 && crate::wakeup_boost() {
+            let slept_ticks = (slept_ns / crate::timers::TICK_NANOS) as usize;
+            self.fair.add_woken_task(se.fair.clone(), slept_ticks);
+            self.check_wakeup_preempt(&se.fair);
+        } else {
+            self.fair.add_task(se.fair);
+        }
+        self.update_load();
+    }
+
+    /// Preempts the current fair task if the woken `fair` is far enough
+    /// behind it.
+    fn check_wakeup_preempt(&mut self, fair: &Arc<FairItem>) {
+        let curr = taskctx::current_ctx();
+        if curr.tid() == 0 || curr.cpu() != self.cpu || rt_policy(curr.policy()) {
+            return;
+        }
+        let curr_se = self.entity(curr.as_ctx_ref());
+        if self.fair.wakeup_preempt(&curr_se.fair, fair) {
+            curr.set_preempt_pending(true);
+        }
+    }
+
+    fn dequeue_task(&mut self, task: &CtxRef) -> bool {
+        let se = self.entity(task);
+        let queued = if rt_policy(task.policy()) {
+            self.rt.remove_task(&se.rt).is_some()
+        } else {
+            self.fair.remove_task(&se.fair).is_some()
+        };
+        self.update_load();
+        queued
+    }
+
+    fn put_prev_task(&mut self, task: &CtxRef, preempt: bool) {
+        let se = self.entity(task);
+        if rt_policy(task.policy()) {
+            self.rt.put_prev_task(se.rt, preempt);
+        } else {
+            self.fair.put_prev_task(se.fair, preempt);
+        }
+        self.update_load();
+    }
+
+    fn yield_prev_task(&mut self, task: &CtxRef) {
+        let se = self.entity(task);
+        if rt_policy(task.policy()) {
+            self.rt.yield_task(se.rt);
+        } else {
+            self.fair.yield_task(se.fair);
+        }
+        self.update_load();
+    }
+
+    fn pick_next_task(&mut self) -> CtxRef {
+        self.drain_wake_list();
+        if self.rt.nr_running() + self.fair.nr_running() == 0 {
+            // Idle balance: try to pull some work before going idle.
+            self.load_balance();
+        }
+        let next = self.do_pick_next_task();
+        self.update_load();
+        next
+    }
+
+    fn do_pick_next_task(&mut self) -> CtxRef {
+        // Going offline: switch to idle, which parks the CPU.
+        if !crate::hotplug::cpu_online(self.cpu) {
+            return self.idle.clone();
+        }
+        // Throttled real-time tasks still run if nothing else can.
+        if !self.rt_throttled || self.fair.nr_running() == 0 {
+            if let Some(next) = self.rt.pick_next_task() {
+                return next.inner().clone();
+            }
+        }
+        while let Some(next) = self.fair.pick_next_task() {
+            let task = next.inner();
+            if !groups::throttled(task) {
+                return task.clone();
+            }
+            self.throttled.push(task.clone());
+        }
+        self.idle.clone()
+    }
+
+    /// Puts back tasks whose group has got quota again.
+    fn unthrottle(&mut self) {
+        if self.throttled.is_empty() {
+            return;
+        }
+        let (ready, throttled): (Vec<CtxRef>, Vec<CtxRef>) = core::mem::take(&mut self.throttled)
+            .into_iter()
+            .partition(|task| !groups::throttled(task));
+        self.throttled = throttled;
+        for task in ready {
+            self.enqueue_task(&task);
+            self.check_preempt_curr(&task);
+        }
+    }
+
+    pub(crate) fn cpu_stats(&self) -> CpuStats {
+        CpuStats {
+            cpu: self.cpu,
+            nr_running: self.rt.nr_running() + self.fair.nr_running(),
+            nr_switches: self.nr_switches,
+            ticks: self.ticks,
+            idle_time_ns: self.idle.sched_stats().run_time_ns,
+        }
+    }
+
+    /// Whether any task is waiting to run on this CPU.
+    pub fn has_queued(&self) -> bool {
+        self.rt.nr_running() + self.fair.nr_running() > 0 || !WAKE_LISTS[self.cpu].lock().is_empty()
+    }
+
+    /// Whether tasks wait for their group to get quota again, which needs
+    /// the tick.
+    pub fn has_throttled(&self) -> bool {
+        !self.throttled.is_empty()
+    }
+
+    /// Takes the tasks of this offline run queue that may run on other CPUs,
+    /// for them to be activated there.
+    pub(crate) fn take_migratable(&mut self) -> Vec<CtxRef> {
+        let cpu = self.cpu;
+        let elsewhere = |task: &CtxRef| task.cpus_allowed() & !(1 << cpu) != 0;
+        let (mut tasks, bound): (Vec<CtxRef>, Vec<CtxRef>) =
+            core::mem::take(&mut *WAKE_LISTS[cpu].lock()).into_iter().partition(elsewhere);
+        WAKE_LISTS[cpu].lock().extend(bound);
+        let (throttled, bound): (Vec<CtxRef>, Vec<CtxRef>) =
+            core::mem::take(&mut self.throttled).into_iter().partition(elsewhere);
+        self.throttled = bound;
+        tasks.extend(throttled);
+        for task in all_tasks() {
+            if task.cpu() == cpu && task.is_runnable() && elsewhere(&task) && self.dequeue_task(&task) {
+                tasks.push(task);
+            }
+        }
+        self.update_load();
+        tasks
+    }
+
+    /// Enqueues tasks which other CPUs have woken up for this run queue.
+    pub(crate) fn drain_wake_list(&mut self) {
+        let woken = core::mem::take(&mut *WAKE_LISTS[self.cpu].lock());
+        for task in woken {
+            self.enqueue_task(&task);
+            self.check_preempt_curr(&task);
+        }
+    }
+
+    /// Pulls normal tasks from the busiest run queue, until both are about
+    /// even. Real-time tasks are not migrated by the balancer.
+    ///
+    /// The other queue is only try-locked, so two CPUs balancing against
+    /// each other can't deadlock; the balance is just retried later.
+    fn load_balance(&mut self) {
+        if !crate::hotplug::cpu_online(self.cpu) {
+            return;
+        }
+        let this_load = NR_RUNNING[self.cpu].load(Ordering::Acquire);
+        let busiest = (0..SMP)
+            .filter(|&cpu| cpu != self.cpu)
+            .max_by_key(|&cpu| NR_RUNNING[cpu].load(Ordering::Acquire));
+        let Some(busiest) = busiest else {
+            return;
+        };
+        let busiest_load = NR_RUNNING[busiest].load(Ordering::Acquire);
+        if busiest_load <= this_load + 1 || !RUN_QUEUES[busiest].is_init() {
+            return;
+        }
+        let Some(mut src) = RUN_QUEUES[busiest].try_lock() else {
+            return;
+        };
+        let cpu = self.cpu;
+        let mut nr_move = (busiest_load - this_load) / 2;
+        while nr_move > 0 {
+            let Some(fair) = src.fair.steal_task(|t| t.cpu_allowed(cpu)) else {
+                break;
+            };
+            let task = fair.inner().clone();
+            debug!("load_balance: task {} cpu {} -> {}", task.tid(), busiest, cpu);
+            migrate_vruntime(&task, &fair, cpu);
+            self.fair.add_task(fair);
+            nr_move -= 1;
+        }
+        src.update_load();
+        self.update_load();
+    }
+
+    /// Preempts the current task if `task` is more urgent. The run queue
+    /// of another CPU is left to that CPU, which is kicked to check its
+    /// queue, see `check_preempt_queued`.
+    fn check_preempt_curr(&self, task: &CtxRef) {
+        if !rt_policy(task.policy()) || self.rt_throttled {
+            return;
+        }
+        let curr = taskctx::current_ctx();
+        if curr.cpu() != self.cpu {
+            crate::ipi::kick_cpu(self.cpu);
+            return;
+        }
+        if curr.tid() == 0 || !rt_policy(curr.policy()) || task.priority() > curr.priority() {
+            curr.set_preempt_pending(true);
+        }
+    }
+
+    /// Preempts the current task if a queued real-time task is more urgent,
+    /// after another CPU has changed this run queue.
+    pub(crate) fn check_preempt_queued(&self) {
+        let Some(prio) = self.rt.highest_prio() else {
+            return;
+        };
+        let curr = taskctx::current_ctx();
+        if self.rt_throttled || curr.cpu() != self.cpu {
+            return;
+        }
+        if curr.tid() == 0 || !rt_policy(curr.policy()) || prio > curr.priority() {
+            curr.set_preempt_pending(true);
+        }
     }
 
     /// Activates a task by adding it to the scheduler
@@ -71,25 +524,144 @@ impl AxRunQueue {
     pub fn add_task(&mut self, task: CtxRef) {
         info!("task spawn: {}", task.tid());
         assert!(task.tid() != 0);
-        assert!(task.is_ready());
-        let item = Arc::new(SchedItem::new(task.clone()));
-        self.scheduler.add_task(item);
+        assert!(task.is_runnable());
+        self.enqueue_task(&task);
+        self.check_preempt_curr(&task);
     }
 
-    /// Handles scheduler timer tick
-    pub fn scheduler_timer_tick(&mut self) {
+    /// Handles scheduler timer tick. `user` tells whether the tick has
+    /// interrupted the current task in user mode.
+    pub fn scheduler_timer_tick(&mut self, user: bool) {
         let curr = taskctx::current_ctx();
-        if self.scheduler.task_tick(&Arc::new(SchedItem::new(curr.as_ctx_ref().clone()))) {
+        curr.acct_tick(axhal::time::current_time_nanos(), user);
+        let curr_rt = curr.tid() != 0 && rt_policy(curr.policy());
+        self.update_rt_bandwidth(curr_rt);
+        self.drain_wake_list();
+        self.unthrottle();
+        self.ticks += 1;
+        if self.ticks % BALANCE_INTERVAL == 0 {
+            self.load_balance();
+        }
+        if curr.tid() == 0 {
+            // Leave idle as soon as there is something to run.
+            if self.fair.nr_running() > 0 || self.rt.highest_prio().is_some() {
+                curr.set_preempt_pending(true);
+            }
+            return;
+        }
+        let se = self.entity(curr.as_ctx_ref());
+        // Not short-circuited, the tick is always charged to the task.
+        let resched = if curr_rt {
+            (self.rt_throttled && self.fair.nr_running() > 0) | self.rt.task_tick(&se.rt)
+        } else {
+            se.fair.set_shares(groups::task_shares(&curr) as isize);
+            groups::charge_tick(&curr)
+                | (!self.rt_throttled && self.rt.highest_prio().is_some())
+                | self.fair.task_tick(&se.fair)
+        };
+        if resched {
             curr.set_preempt_pending(true);
         }
     }
 
-    /*
-    pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.scheduler
-            .set_priority(crate::current().as_task_ref(), prio)
+    fn update_rt_bandwidth(&mut self, curr_rt: bool) {
+        self.rt_clock += 1;
+        if curr_rt {
+            self.rt_time += 1;
+        }
+        if self.rt_clock >= self.rt_period {
+            if self.rt_throttled {
+                debug!("rt: unthrottled");
+            }
+            self.rt_clock = 0;
+            self.rt_time = 0;
+            self.rt_throttled = false;
+        } else if let Some(runtime) = self.rt_runtime {
+            if !self.rt_throttled && self.rt_time >= runtime {
+                warn!("rt: throttling real-time tasks");
+                self.rt_throttled = true;
+            }
+        }
+    }
+
+    /// Sets the bandwidth of real-time tasks: they may run `runtime_us`
+    /// out of every `period_us`, or without limit if `runtime_us` is -1.
+    ///
+    /// Returns `false` if the values are invalid.
+    pub fn set_rt_bandwidth(&mut self, period_us: usize, runtime_us: isize) -> bool {
+        if period_us == 0 || runtime_us < -1 || runtime_us > period_us as isize {
+            return false;
+        }
+        self.rt_period = us_to_ticks(period_us).max(1);
+        self.rt_runtime = if runtime_us < 0 {
+            None
+        } else {
+            Some(us_to_ticks(runtime_us as usize))
+        };
+        true
+    }
+
+    /// Sets the priority of `task` under its current policy.
+    ///
+    /// Returns `false` if `prio` is out of range.
+    pub fn set_priority(&mut self, task: &CtxRef, prio: isize) -> bool {
+        self.set_policy(task, task.policy(), prio)
+    }
+
+    /// Sets the policy of `task`, along with a priority valid for it.
+    ///
+    /// A queued task is taken out and put back, so that it is ordered
+    /// by its new parameters, possibly in another scheduling class.
+    /// Returns `false` if the policy is unknown or `prio` is out of range.
+    pub fn set_policy(&mut self, task: &CtxRef, policy: usize, prio: isize) -> bool {
+        if task.tid() == 0 || !valid_priority(policy, prio) {
+            return false;
+        }
+        if task.is_boosted() {
+            // Takes effect once the inherited parameters are dropped, unless
+            // it's more urgent than them.
+            task.set_base_sched_params(policy, prio);
+            if !more_urgent(policy, prio, task.policy(), task.priority()) {
+                return true;
+            }
+        }
+        self.change_sched_params(task, policy, prio);
+        true
+    }
+
+    /// Lends `policy` and `prio` of a waiter to `task`, which holds a lock
+    /// the waiter is blocked on, if they are more urgent than its own.
+    pub fn boost_task(&mut self, task: &CtxRef, policy: usize, prio: isize) -> bool {
+        if task.tid() == 0 || task.is_zombie() || !more_urgent(policy, prio, task.policy(), task.priority()) {
+            return false;
+        }
+        debug!("pi: boost {} to ({}, {})", task.tid(), policy, prio);
+        task.start_boost();
+        self.change_sched_params(task, policy, prio);
+        true
+    }
+
+    /// Drops the parameters `task` has inherited, see `boost_task`.
+    pub fn unboost_task(&mut self, task: &CtxRef) {
+        if let Some((policy, prio)) = task.end_boost() {
+            debug!("pi: unboost {} to ({}, {})", task.tid(), policy, prio);
+            self.change_sched_params(task, policy, prio);
+        }
+    }
+
+    fn change_sched_params(&mut self, task: &CtxRef, policy: usize, prio: isize) {
+        let queued = self.dequeue_task(task);
+        task.set_sched_params(policy, prio);
+        let se = self.entity(task);
+        self.apply_sched_params(task, &se);
+        if queued {
+            self.put_prev_task(task, false);
+            self.check_preempt_curr(task);
+        }
+        if task.is_running() {
+            task.set_preempt_pending(true);
+        }
     }
-    */
 
     /// Attempts to preempt the current task
     pub fn preempt_resched(&mut self) {
@@ -118,41 +690,52 @@ impl AxRunQueue {
         }
     }
 
-    /*
-    pub fn exit_current(&mut self, exit_code: i32) -> ! {
-        let curr = crate::current();
-        debug!("task exit: {}, exit_code={}", curr.id_name(), exit_code);
+    /// Turns the current task into a zombie with `exit_code`, wakes up its
+    /// parent and switches away for good.
+    pub fn exit_current(&mut self, exit_code: u32) -> ! {
+        let curr = taskctx::current_ctx();
+        debug!("task exit: {}, exit_code={}", curr.tid(), exit_code);
         assert!(curr.is_running());
-        assert!(!curr.is_idle());
-        if curr.is_init() {
-            EXITED_TASKS.lock().clear();
-            axhal::misc::terminate();
-        } else {
-            curr.set_state(TaskState::Exited);
-            curr.notify_exit(exit_code, self);
-            EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
-            self.resched(false);
+        assert!(curr.tid() != 0);
+
+        crate::timers::cancel_alarm(&curr);
+        curr.set_exit_code(exit_code);
+        curr.set_state(TaskState::Zombie);
+        if let Some(parent) = curr.real_parent.as_ref() {
+            if parent.is_interruptible() {
+                // It may be waiting for children in wait4.
+                self.unblock_task(parent.clone(), false);
+            } else if !parent.is_blocked() {
+                // It's about to wait, don't let it block.
+                parent.set_wakeup_pending(true);
+            }
+            // An uninterruptible sleep isn't disturbed, the parent finds
+            // the zombie when it waits next.
         }
+        self.resched(false);
         unreachable!("task exited!");
     }
-    */
 
-    /// Blocks the current task
-    pub fn block_current<F>(&mut self, wait_queue_push: F)
+    /// Blocks the current task in `state`, which must be `Interruptible`
+    /// or `Uninterruptible`.
+    pub fn block_current<F>(&mut self, state: TaskState, wait_queue_push: F)
     where
         F: FnOnce(CtxRef),
     {
         let curr = taskctx::current_ctx();
         assert!(curr.tid() != 0);
-        info!("task block: {}", curr.tid());
+        info!("task block: {} {:?}", curr.tid(), state);
         assert!(curr.is_running());
+        assert!(matches!(
+            state,
+            TaskState::Interruptible | TaskState::Uninterruptible
+        ));
         //assert!(!curr.is_idle());
 
         // we must not block current task with preemption disabled.
         //assert!(curr.can_preempt(1));
 
-        curr.set_state(TaskState::Blocked);
+        curr.set_state(state);
         wait_queue_push(curr.clone());
         self.resched(false);
     }
@@ -162,30 +745,35 @@ impl AxRunQueue {
         info!("task unblock: {}", task.tid());
         assert!(task.tid() != 0);
         if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(Arc::new(SchedItem::new(task))); // TODO: priority
+            task.set_state(TaskState::Runnable);
+            let cpu = select_task_rq(&task);
+            crate::trace::trace_sched_wakeup(task.tid(), cpu);
+            if cpu != self.cpu || cpu != taskctx::current_ctx().cpu() {
+                queue_remote(task, cpu);
+                return;
+            }
+            self.enqueue_task(&task);
+            self.check_preempt_curr(&task);
             if resched {
                 taskctx::current_ctx().set_preempt_pending(true);
             }
         }
     }
 
-    /*
-    #[cfg(feature = "irq")]
+    /// Blocks the current task until `deadline`, unless it's woken up early.
     pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
-        let curr = crate::current();
-        debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
+        let curr = taskctx::current_ctx();
+        debug!("task sleep: {}, deadline={:?}", curr.tid(), deadline);
         assert!(curr.is_running());
-        assert!(!curr.is_idle());
+        assert!(curr.tid() != 0);
 
         let now = axhal::time::current_time();
         if now < deadline {
             crate::timers::set_alarm_wakeup(deadline, curr.clone());
-            curr.set_state(TaskState::Blocked);
+            curr.set_state(TaskState::Interruptible);
             self.resched(false);
         }
     }
-    */
 }
 
 impl AxRunQueue {
@@ -193,19 +781,38 @@ impl AxRunQueue {
     /// Common reschedule subroutine. If `preempt`, keep current task's time
     /// slice, otherwise reset it.
     pub fn resched(&mut self, preempt: bool) {
+        self.do_resched(preempt, false);
+    }
+
+    /// Yields the CPU: the current task goes behind the ready tasks of its
+    /// priority level, or of the fair class, instead of being picked again.
+    pub fn yield_current(&mut self) {
+        self.do_resched(false, true);
+    }
+
+    fn do_resched(&mut self, preempt: bool, yielding: bool) {
         let prev = taskctx::current_ctx();
         if prev.is_running() {
-            prev.set_state(TaskState::Ready);
+            prev.set_state(TaskState::Runnable);
             // Todo: imitate linux kernel to deal with idle task(tid == 0)
             if prev.tid() != 0 {
-                self.scheduler
-                    .put_prev_task(Arc::new(SchedItem::new(prev.clone())), preempt);
+                prev.stat_enqueue(axhal::time::current_time_nanos());
+                if yielding {
+                    self.yield_prev_task(prev.as_ctx_ref());
+                } else {
+                    self.put_prev_task(prev.as_ctx_ref(), preempt);
+                }
             }
+        } else if prev.is_blocked() {
+            prev.stat_block(axhal::time::current_time_nanos());
+        } else if prev.is_zombie() {
+            prev.set_sched_entity(None);
+            TASKS.lock().remove(&prev.tid());
+            // The previous zombie has been switched away from already.
+            self.zombie = Some(prev.as_ctx_ref().clone());
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| {
-            self.idle.clone()
-        });
-        self.switch_to(prev, next.inner().clone());
+        let next = self.pick_next_task();
+        self.switch_to(prev, next);
     }
 
     /// Switches execution from current task to next task
@@ -213,9 +820,18 @@ impl AxRunQueue {
         debug!("============ context switch: {} -> {}", prev_task.tid(), next_task.tid());
         next_task.set_preempt_pending(false);
         next_task.set_state(TaskState::Running);
+        let now = axhal::time::current_time_nanos();
+        prev_task.stat_run_stop(now);
+        prev_task.acct_switch_out(now);
+        next_task.stat_run_start(now);
+        next_task.acct_switch_in(now);
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        prev_task.stat_switch_out(!prev_task.is_runnable());
+        crate::trace::trace_sched_switch(self.cpu, prev_task.tid(), prev_task.state(), next_task.tid());
+        crate::perf::perf_event_task_sched(self.cpu, prev_task.tid(), next_task.tid());
+        self.nr_switches += 1;
 
         // Switch mm from prev to next
         // kernel ->   user   switch + mmdrop_lazy_tlb() active
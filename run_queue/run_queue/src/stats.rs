@@ -0,0 +1,43 @@
+//! Scheduler statistics, to measure scheduling instead of guessing.
+
+use alloc::vec::Vec;
+use taskctx::{SchedStats, Tid};
+use crate::run_queue::{all_tasks, RUN_QUEUES};
+
+/// Scheduling statistics of a CPU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuStats {
+    pub cpu: usize,
+    /// Ready tasks in its run queue, the running one excluded.
+    pub nr_running: usize,
+    /// Context switches done on it.
+    pub nr_switches: u64,
+    /// Scheduler ticks it has received.
+    pub ticks: usize,
+    /// Time its idle task has run, in nanoseconds.
+    pub idle_time_ns: u64,
+}
+
+/// A snapshot of the scheduling statistics of all CPUs and tasks.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub cpus: Vec<CpuStats>,
+    pub tasks: Vec<(Tid, SchedStats)>,
+}
+
+/// Takes a snapshot of the scheduling statistics.
+///
+/// Run queues are locked one by one, so the snapshot isn't atomic across
+/// CPUs.
+pub fn stats() -> Stats {
+    let cpus = RUN_QUEUES
+        .iter()
+        .filter(|rq| rq.is_init())
+        .map(|rq| rq.lock().cpu_stats())
+        .collect();
+    let tasks = all_tasks()
+        .iter()
+        .map(|task| (task.tid(), task.sched_stats()))
+        .collect();
+    Stats { cpus, tasks }
+}
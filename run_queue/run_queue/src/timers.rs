@@ -0,0 +1,133 @@
+//! Timed wakeup events of blocked tasks.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use axhal::time::TimeValue;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spinbase::SpinNoIrq;
+use taskctx::{CtxRef, Tid};
+
+/// Pending wakeups ordered by deadline. A task has at most one pending
+/// wakeup, so `(deadline, tid)` is unique.
+static TIMER_LIST: SpinNoIrq<BTreeMap<(TimeValue, Tid), CtxRef>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+/// The deadline (in nanoseconds) the timer of each CPU is programmed for.
+static PROGRAMMED: [AtomicU64; axconfig::SMP] = {
+    const NONE: AtomicU64 = AtomicU64::new(u64::MAX);
+    [NONE; axconfig::SMP]
+};
+
+/// Whether the periodic tick of each CPU is stopped while it idles.
+static TICK_STOPPED: [AtomicBool; axconfig::SMP] = {
+    const RUNNING: AtomicBool = AtomicBool::new(false);
+    [RUNNING; axconfig::SMP]
+};
+
+pub(crate) const TICK_NANOS: u64 = axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// The longest an idle CPU sleeps without a tick, so that it still pulls
+/// work from busy CPUs from time to time.
+const MAX_IDLE_NANOS: u64 = axhal::time::NANOS_PER_SEC;
+
+#[inline]
+fn as_nanos(time: TimeValue) -> u64 {
+    time.as_nanos() as u64
+}
+
+fn set_oneshot_timer(cpu: usize, deadline_ns: u64) {
+    PROGRAMMED[cpu].store(deadline_ns, Ordering::Release);
+    axhal::time::set_oneshot_timer(deadline_ns);
+}
+
+/// Programs the timer of this CPU for `next_tick_ns`, or earlier if a
+/// wakeup is due before. Called by the timer IRQ handler.
+///
+/// While the tick is stopped, `next_tick_ns` is ignored.
+pub fn program_timer(next_tick_ns: u64) {
+    let cpu = taskctx::current_ctx().cpu();
+    let now = axhal::time::current_time();
+    let next_tick_ns = if TICK_STOPPED[cpu].load(Ordering::Acquire) {
+        as_nanos(now) + MAX_IDLE_NANOS
+    } else {
+        next_tick_ns
+    };
+    let timers = TIMER_LIST.lock();
+    // Expired ones that aren't blocked yet are left for the next tick.
+    let deadline = timers
+        .keys()
+        .map(|(deadline, _)| *deadline)
+        .find(|deadline| *deadline > now)
+        .map_or(next_tick_ns, |deadline| as_nanos(deadline).min(next_tick_ns));
+    set_oneshot_timer(cpu, deadline);
+}
+
+/// Stops the periodic tick of this CPU, which has nothing to run. The timer
+/// fires for the next wakeup deadline only.
+pub(crate) fn stop_tick(cpu: usize) {
+    TICK_STOPPED[cpu].store(true, Ordering::Release);
+    program_timer(u64::MAX);
+}
+
+/// Restarts the periodic tick of this CPU when it leaves idle.
+pub(crate) fn restart_tick(cpu: usize) {
+    if TICK_STOPPED[cpu].swap(false, Ordering::AcqRel) {
+        program_timer(as_nanos(axhal::time::current_time()) + TICK_NANOS);
+    }
+}
+
+/// Whether `cpu` idles with its tick stopped.
+pub fn tick_stopped(cpu: usize) -> bool {
+    TICK_STOPPED[cpu].load(Ordering::Acquire)
+}
+
+/// Wakes up `task` at `deadline`, unless it's cancelled before.
+///
+/// It may be set up right before the task blocks.
+pub fn set_alarm_wakeup(deadline: TimeValue, task: CtxRef) {
+    let mut timers = TIMER_LIST.lock();
+    task.set_in_timer_list(true);
+    timers.insert((deadline, task.tid()), task);
+    // Fire precisely rather than at the next tick.
+    let cpu = taskctx::current_ctx().cpu();
+    if as_nanos(deadline) < PROGRAMMED[cpu].load(Ordering::Acquire) {
+        set_oneshot_timer(cpu, as_nanos(deadline));
+    }
+}
+
+/// Cancels the pending wakeup of `task`, if any.
+pub fn cancel_alarm(task: &CtxRef) {
+    let mut timers = TIMER_LIST.lock();
+    if task.in_timer_list() {
+        timers.retain(|(_, tid), _| *tid != task.tid());
+        task.set_in_timer_list(false);
+    }
+}
+
+/// Wakes up tasks whose deadline has passed. Called on each timer tick.
+///
+/// An expired task that hasn't blocked yet is left in the list: it either
+/// blocks and is woken at a later tick, or cancels the alarm by itself.
+pub(crate) fn check_events() {
+    let now = axhal::time::current_time();
+    let expired: Vec<CtxRef> = {
+        let mut timers = TIMER_LIST.lock();
+        let keys: Vec<_> = timers
+            .range(..=(now, Tid::MAX))
+            .filter(|(_, task)| task.is_blocked())
+            .map(|(key, _)| *key)
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| timers.remove(&key))
+            .inspect(|task| task.set_in_timer_list(false))
+            .collect()
+    };
+    if expired.is_empty() {
+        return;
+    }
+    let curr = taskctx::current_ctx();
+    let mut rq = crate::task_rq(&curr).lock();
+    for task in expired {
+        rq.unblock_task(task, true);
+    }
+}
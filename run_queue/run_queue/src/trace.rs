@@ -0,0 +1,119 @@
+//! Static scheduler tracepoints, for a tracer to record scheduling events
+//! without patching the run queue.
+//!
+//! Each tracepoint calls at most one probe, a plain function registered at
+//! runtime. Without the `trace` feature, tracepoints compile to nothing
+//! and registering a probe always fails.
+
+use taskctx::{TaskState, Tid};
+
+/// Called on each context switch on `cpu`, with the state `prev` is left in.
+pub type SchedSwitchProbe = fn(cpu: usize, prev: Tid, prev_state: TaskState, next: Tid);
+/// Called when a blocked task is woken up to run on `target_cpu`.
+pub type SchedWakeupProbe = fn(tid: Tid, target_cpu: usize);
+/// Called when a task moves from the run queue of `orig_cpu` to `dest_cpu`.
+pub type SchedMigrateProbe = fn(tid: Tid, orig_cpu: usize, dest_cpu: usize);
+
+#[cfg(feature = "trace")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A probe function stored as an address, 0 if none.
+#[cfg(feature = "trace")]
+struct Tracepoint(AtomicUsize);
+
+#[cfg(feature = "trace")]
+impl Tracepoint {
+    const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    fn register(&self, probe: usize) -> bool {
+        self.0
+            .compare_exchange(0, probe, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn unregister(&self) {
+        self.0.store(0, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn probe(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Compiled out: no probe is ever called, so the checks fold away.
+#[cfg(not(feature = "trace"))]
+struct Tracepoint;
+
+#[cfg(not(feature = "trace"))]
+impl Tracepoint {
+    const fn new() -> Self {
+        Self
+    }
+
+    fn register(&self, _probe: usize) -> bool {
+        false
+    }
+
+    fn unregister(&self) {}
+
+    #[inline(always)]
+    fn probe(&self) -> usize {
+        0
+    }
+}
+
+static SCHED_SWITCH: Tracepoint = Tracepoint::new();
+static SCHED_WAKEUP: Tracepoint = Tracepoint::new();
+static SCHED_MIGRATE: Tracepoint = Tracepoint::new();
+
+/// Registers the probe of `sched_switch`. Fails if one is registered already.
+pub fn register_sched_switch(probe: SchedSwitchProbe) -> bool {
+    SCHED_SWITCH.register(probe as usize)
+}
+
+/// Registers the probe of `sched_wakeup`. Fails if one is registered already.
+pub fn register_sched_wakeup(probe: SchedWakeupProbe) -> bool {
+    SCHED_WAKEUP.register(probe as usize)
+}
+
+/// Registers the probe of `sched_migrate`. Fails if one is registered already.
+pub fn register_sched_migrate(probe: SchedMigrateProbe) -> bool {
+    SCHED_MIGRATE.register(probe as usize)
+}
+
+/// Removes the probes of all scheduler tracepoints.
+pub fn unregister_sched_probes() {
+    SCHED_SWITCH.unregister();
+    SCHED_WAKEUP.unregister();
+    SCHED_MIGRATE.unregister();
+}
+
+#[inline(always)]
+pub(crate) fn trace_sched_switch(cpu: usize, prev: Tid, prev_state: TaskState, next: Tid) {
+    let probe = SCHED_SWITCH.probe();
+    if probe != 0 {
+        let probe: SchedSwitchProbe = unsafe { core::mem::transmute(probe) };
+        probe(cpu, prev, prev_state, next);
+    }
+}
+
+#[inline(always)]
+pub(crate) fn trace_sched_wakeup(tid: Tid, target_cpu: usize) {
+    let probe = SCHED_WAKEUP.probe();
+    if probe != 0 {
+        let probe: SchedWakeupProbe = unsafe { core::mem::transmute(probe) };
+        probe(tid, target_cpu);
+    }
+}
+
+#[inline(always)]
+pub(crate) fn trace_sched_migrate(tid: Tid, orig_cpu: usize, dest_cpu: usize) {
+    let probe = SCHED_MIGRATE.probe();
+    if probe != 0 {
+        let probe: SchedMigrateProbe = unsafe { core::mem::transmute(probe) };
+        probe(tid, orig_cpu, dest_cpu);
+    }
+}
@@ -562,6 +562,17 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         self.unmount_internal()
     }
 
+    /// Updates the FS Information Sector if needed and clears the volume dirty
+    /// flag, as `unmount` does, but keeps the filesystem usable. The next
+    /// write marks the volume dirty again.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn flush(&self) -> Result<(), Error<IO::Error>> {
+        self.unmount_internal()
+    }
+
     fn unmount_internal(&self) -> Result<(), Error<IO::Error>> {
         self.flush_fs_info()?;
         self.set_dirty_flag(false)?;
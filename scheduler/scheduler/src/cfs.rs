@@ -1,15 +1,19 @@
 use alloc::{collections::BTreeMap, sync::Arc};
+use core::cmp::max;
 use core::ops::Deref;
 use core::sync::atomic::{AtomicIsize, Ordering};
 
 use crate::BaseScheduler;
 
 /// task for CFS
+///
+/// `vruntime` is accumulated at each tick, scaled by the weight of the task,
+/// so that changing the nice value only affects its future runtime.
 pub struct CFSTask<T> {
     inner: T,
-    init_vruntime: AtomicIsize,
-    delta: AtomicIsize,
+    vruntime: AtomicIsize,
     nice: AtomicIsize,
+    shares: AtomicIsize,
     id: AtomicIsize,
 }
 
@@ -28,51 +32,71 @@ const NICE2WEIGHT_NEG: [isize; NICE_RANGE_NEG + 1] = [
     29154, 36291, 46273, 56483, 71755, 88761,
 ];
 
+/// Weight of a task with nice 0.
+const NICE_0_WEIGHT: isize = 1024;
+
+/// Virtual runtime charged to a nice-0 task for one tick.
+const TICK_VRUNTIME: isize = 1 << 20;
+
+/// Woken tasks are placed at most this far before `min_vruntime`, so a task
+/// which slept for long can't monopolize the CPU when it comes back.
+const SLEEPER_CREDIT: isize = 3 * TICK_VRUNTIME;
+
+/// Ticks of sleep rewarded with extra credit, see [`CFScheduler::add_woken_task`].
+const MAX_BOOST_TICKS: usize = 3;
+
+/// How far a woken task must be behind the current one to preempt it.
+const WAKEUP_GRANULARITY: isize = TICK_VRUNTIME;
+
 impl<T> CFSTask<T> {
     /// new with default values
     pub const fn new(inner: T) -> Self {
         Self {
             inner,
-            init_vruntime: AtomicIsize::new(0_isize),
-            delta: AtomicIsize::new(0_isize),
+            vruntime: AtomicIsize::new(0_isize),
             nice: AtomicIsize::new(0_isize),
+            shares: AtomicIsize::new(NICE_0_WEIGHT),
             id: AtomicIsize::new(0_isize),
         }
     }
 
     fn get_weight(&self) -> isize {
         let nice = self.nice.load(Ordering::Acquire);
-        if nice >= 0 {
+        let weight = if nice >= 0 {
             NICE2WEIGHT_POS[nice as usize]
         } else {
             NICE2WEIGHT_NEG[(-nice) as usize]
-        }
+        };
+        max(1, weight * self.shares.load(Ordering::Acquire) / NICE_0_WEIGHT)
+    }
+
+    /// Scales the weight of the task by `shares / 1024`, e.g. by the shares
+    /// of its task group. Safe to call when the task is queued.
+    pub fn set_shares(&self, shares: isize) {
+        self.shares.store(max(1, shares), Ordering::Release);
     }
 
     fn get_id(&self) -> isize {
         self.id.load(Ordering::Acquire)
     }
 
-    fn get_vruntime(&self) -> isize {
-        if self.nice.load(Ordering::Acquire) == 0 {
-            self.init_vruntime.load(Ordering::Acquire) + self.delta.load(Ordering::Acquire)
-        } else {
-            self.init_vruntime.load(Ordering::Acquire)
-                + self.delta.load(Ordering::Acquire) * 1024 / self.get_weight()
-        }
+    /// Returns the virtual runtime of the task.
+    pub fn get_vruntime(&self) -> isize {
+        self.vruntime.load(Ordering::Acquire)
+    }
+
+    /// Sets the virtual runtime of the task, which must not be queued.
+    pub fn set_vruntime(&self, v: isize) {
+        self.vruntime.store(v, Ordering::Release);
     }
 
-    fn set_vruntime(&self, v: isize) {
-        self.init_vruntime.store(v, Ordering::Release);
+    /// Returns the nice value of the task.
+    pub fn get_nice(&self) -> isize {
+        self.nice.load(Ordering::Acquire)
     }
 
-    // Simple Implementation: no change in vruntime.
-    // Only modifying priority of current process is supported currently.
+    // Vruntime is kept, so it is safe to call when the task is queued.
     fn set_priority(&self, nice: isize) {
-        let current_init_vruntime = self.get_vruntime();
-        self.init_vruntime
-            .store(current_init_vruntime, Ordering::Release);
-        self.delta.store(0, Ordering::Release);
         self.nice.store(nice, Ordering::Release);
     }
 
@@ -81,7 +105,8 @@ impl<T> CFSTask<T> {
     }
 
     fn task_tick(&self) {
-        self.delta.fetch_add(1, Ordering::Release);
+        let delta = TICK_VRUNTIME * NICE_0_WEIGHT / self.get_weight();
+        self.vruntime.fetch_add(delta, Ordering::Release);
     }
 
     /// Returns a reference to the inner task struct.
@@ -99,11 +124,15 @@ impl<T> Deref for CFSTask<T> {
 
 /// A simple [Completely Fair Scheduler][1] (CFS).
 ///
+/// Ready tasks are ordered by `(vruntime, id)`, and the leftmost one is
+/// picked. `min_vruntime` only grows, it is the base for placing new and
+/// woken tasks.
+///
 /// [1]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 pub struct CFScheduler<T> {
     ready_queue: BTreeMap<(isize, isize), Arc<CFSTask<T>>>, // (vruntime, taskid)
-    min_vruntime: Option<AtomicIsize>,
-    id_pool: AtomicIsize,
+    min_vruntime: isize,
+    id_pool: isize,
 }
 
 impl<T> CFScheduler<T> {
@@ -111,14 +140,61 @@ impl<T> CFScheduler<T> {
     pub const fn new() -> Self {
         Self {
             ready_queue: BTreeMap::new(),
-            min_vruntime: None,
-            id_pool: AtomicIsize::new(0_isize),
+            min_vruntime: 0,
+            id_pool: 0,
         }
     }
     /// get the name of scheduler
     pub fn scheduler_name() -> &'static str {
         "Completely Fair"
     }
+
+    /// Returns the number of ready tasks.
+    pub fn nr_running(&self) -> usize {
+        self.ready_queue.len()
+    }
+
+    /// Returns the base vruntime of the queue.
+    pub fn min_vruntime(&self) -> isize {
+        self.min_vruntime
+    }
+
+    /// Removes the ready task with the largest vruntime that satisfies
+    /// `filter`, i.e. the one which would run last, for migration.
+    pub fn steal_task<F>(&mut self, filter: F) -> Option<Arc<CFSTask<T>>>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let key = self
+            .ready_queue
+            .iter()
+            .rev()
+            .find(|(_, t)| filter(t.inner()))
+            .map(|(key, _)| *key)?;
+        self.ready_queue.remove(&key)
+    }
+
+    /// Adds a task woken up after sleeping for `slept_ticks`. It may be
+    /// placed up to a tick further before `min_vruntime` per tick slept,
+    /// bounded by `MAX_BOOST_TICKS`, so that I/O-bound tasks run soon.
+    pub fn add_woken_task(&mut self, task: Arc<CFSTask<T>>, slept_ticks: usize) {
+        let boost = slept_ticks.min(MAX_BOOST_TICKS) as isize * TICK_VRUNTIME;
+        let vruntime = max(task.get_vruntime(), self.min_vruntime - SLEEPER_CREDIT - boost);
+        task.set_vruntime(vruntime);
+        self.enqueue(task);
+    }
+
+    /// Whether `woken` is far enough behind `curr` to preempt it.
+    pub fn wakeup_preempt(&self, curr: &Arc<CFSTask<T>>, woken: &Arc<CFSTask<T>>) -> bool {
+        woken.get_vruntime() + WAKEUP_GRANULARITY < curr.get_vruntime()
+    }
+
+    fn enqueue(&mut self, task: Arc<CFSTask<T>>) {
+        let taskid = self.id_pool;
+        self.id_pool += 1;
+        task.set_id(taskid);
+        self.ready_queue.insert((task.get_vruntime(), taskid), task);
+    }
 }
 
 impl<T> BaseScheduler for CFScheduler<T> {
@@ -127,56 +203,43 @@ impl<T> BaseScheduler for CFScheduler<T> {
     fn init(&mut self) {}
 
     fn add_task(&mut self, task: Self::SchedItem) {
-        if self.min_vruntime.is_none() {
-            self.min_vruntime = Some(AtomicIsize::new(0_isize));
-        }
-        let vruntime = self.min_vruntime.as_mut().unwrap().load(Ordering::Acquire);
-        let taskid = self.id_pool.fetch_add(1, Ordering::Release);
-        task.set_vruntime(vruntime);
-        task.set_id(taskid);
-        self.ready_queue.insert((vruntime, taskid), task);
-        if let Some(((min_vruntime, _), _)) = self.ready_queue.first_key_value() {
-            self.min_vruntime = Some(AtomicIsize::new(*min_vruntime));
-        } else {
-            self.min_vruntime = None;
-        }
+        self.add_woken_task(task, 0);
     }
 
     fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
-        if let Some((_, tmp)) = self
-            .ready_queue
-            .remove_entry(&(task.clone().get_vruntime(), task.clone().get_id()))
-        {
-            if let Some(((min_vruntime, _), _)) = self.ready_queue.first_key_value() {
-                self.min_vruntime = Some(AtomicIsize::new(*min_vruntime));
-            } else {
-                self.min_vruntime = None;
-            }
-            Some(tmp)
-        } else {
-            None
+        let key = (task.get_vruntime(), task.get_id());
+        // The key of a task which isn't queued may collide with another one.
+        match self.ready_queue.get(&key) {
+            Some(t) if Arc::ptr_eq(t, task) => self.ready_queue.remove(&key),
+            _ => None,
         }
     }
 
     fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
-        if let Some((_, v)) = self.ready_queue.pop_first() {
-            Some(v)
-        } else {
-            None
-        }
+        let (_, next) = self.ready_queue.pop_first()?;
+        self.min_vruntime = max(self.min_vruntime, next.get_vruntime());
+        Some(next)
     }
 
     fn put_prev_task(&mut self, prev: Self::SchedItem, _preempt: bool) {
-        let taskid = self.id_pool.fetch_add(1, Ordering::Release);
-        prev.set_id(taskid);
-        self.ready_queue
-            .insert((prev.clone().get_vruntime(), taskid), prev);
+        self.enqueue(prev);
+    }
+
+    fn yield_task(&mut self, prev: Self::SchedItem) {
+        // Its vruntime may still be the smallest: move it behind the
+        // rightmost task instead.
+        if let Some(((vruntime, _), _)) = self.ready_queue.last_key_value() {
+            prev.set_vruntime(max(prev.get_vruntime(), *vruntime));
+        }
+        self.enqueue(prev);
     }
 
     fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
         current.task_tick();
-        self.min_vruntime.is_none()
-            || current.get_vruntime() > self.min_vruntime.as_mut().unwrap().load(Ordering::Acquire)
+        match self.ready_queue.first_key_value() {
+            Some(((vruntime, _), _)) => current.get_vruntime() > *vruntime,
+            None => false,
+        }
     }
 
     fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
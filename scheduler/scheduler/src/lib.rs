@@ -5,11 +5,13 @@
 //! - [`FifoScheduler`]: FIFO (First-In-First-Out) scheduler (cooperative).
 //! - [`RRScheduler`]: Round-robin scheduler (preemptive).
 //! - [`CFScheduler`]: Completely Fair Scheduler (preemptive).
+//! - [`RTScheduler`]: Real-time FIFO and round-robin scheduler (preemptive).
 
 #![cfg_attr(not(test), no_std)]
 #![feature(const_mut_refs)]
 
 mod cfs;
+mod rt;
 
 #[cfg(test)]
 mod tests;
@@ -17,6 +19,7 @@ mod tests;
 extern crate alloc;
 
 pub use cfs::{CFSTask, CFScheduler};
+pub use rt::{RTTask, RTScheduler, MAX_RT_PRIO};
 
 /// The base scheduler trait that all schedulers should implement.
 ///
@@ -54,6 +57,12 @@ pub trait BaseScheduler {
     /// ready queue.
     fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool);
 
+    /// Puts the previous task back as it yields the CPU, behind the ready
+    /// tasks it competes with, so that it's not picked again at once.
+    fn yield_task(&mut self, prev: Self::SchedItem) {
+        self.put_prev_task(prev, false);
+    }
+
     /// Advances the scheduler state at each timer tick. Returns `true` if
     /// re-scheduling is required.
     ///
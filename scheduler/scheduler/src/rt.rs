@@ -0,0 +1,198 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+
+use crate::BaseScheduler;
+
+/// Number of real-time priority levels, valid priorities are [1, 99].
+pub const MAX_RT_PRIO: isize = 100;
+
+/// A task wrapper for the [`RTScheduler`].
+///
+/// `prio` is the real-time priority, the higher the more urgent. Only
+/// round-robin tasks consume their time slice, FIFO tasks run until they
+/// yield, block or are preempted by a more urgent task.
+pub struct RTTask<T, const RR_TIME_SLICE: usize> {
+    inner: T,
+    prio: AtomicIsize,
+    round_robin: AtomicBool,
+    time_slice: AtomicIsize,
+}
+
+impl<T, const S: usize> RTTask<T, S> {
+    /// Creates a new [`RTTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            prio: AtomicIsize::new(1),
+            round_robin: AtomicBool::new(false),
+            time_slice: AtomicIsize::new(S as isize),
+        }
+    }
+
+    /// Returns the real-time priority of the task.
+    pub fn get_prio(&self) -> isize {
+        self.prio.load(Ordering::Acquire)
+    }
+
+    /// Selects round-robin (`true`) or FIFO (`false`) for the task.
+    pub fn set_round_robin(&self, round_robin: bool) {
+        self.round_robin.store(round_robin, Ordering::Release);
+    }
+
+    fn is_round_robin(&self) -> bool {
+        self.round_robin.load(Ordering::Acquire)
+    }
+
+    fn time_slice(&self) -> isize {
+        self.time_slice.load(Ordering::Acquire)
+    }
+
+    fn reset_time_slice(&self) {
+        self.time_slice.store(S as isize, Ordering::Release);
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, const S: usize> Deref for RTTask<T, S> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A real-time scheduler for SCHED_FIFO and SCHED_RR tasks.
+///
+/// There is one ready queue per priority and a bitmap of non-empty queues,
+/// so the most urgent task is always found in constant time. Tasks of the
+/// same priority are served in FIFO order, round-robin tasks go to the tail
+/// of their queue after they have used up a time slice.
+pub struct RTScheduler<T, const RR_TIME_SLICE: usize> {
+    queues: [VecDeque<Arc<RTTask<T, RR_TIME_SLICE>>>; MAX_RT_PRIO as usize],
+    bitmap: u128,
+    nr_running: usize,
+}
+
+impl<T, const S: usize> RTScheduler<T, S> {
+    /// Creates a new empty [`RTScheduler`].
+    pub fn new() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            bitmap: 0,
+            nr_running: 0,
+        }
+    }
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Real-time"
+    }
+
+    /// Returns the number of ready tasks.
+    pub fn nr_running(&self) -> usize {
+        self.nr_running
+    }
+
+    /// Returns the priority of the most urgent ready task.
+    pub fn highest_prio(&self) -> Option<isize> {
+        if self.bitmap == 0 {
+            None
+        } else {
+            Some((127 - self.bitmap.leading_zeros()) as isize)
+        }
+    }
+
+    fn enqueue(&mut self, task: Arc<RTTask<T, S>>, front: bool) {
+        let prio = task.get_prio() as usize;
+        if front {
+            self.queues[prio].push_front(task);
+        } else {
+            self.queues[prio].push_back(task);
+        }
+        self.bitmap |= 1 << prio;
+        self.nr_running += 1;
+    }
+
+    fn dequeued(&mut self, prio: usize) {
+        self.nr_running -= 1;
+        if self.queues[prio].is_empty() {
+            self.bitmap &= !(1 << prio);
+        }
+    }
+}
+
+impl<T, const S: usize> Default for RTScheduler<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const S: usize> BaseScheduler for RTScheduler<T, S> {
+    type SchedItem = Arc<RTTask<T, S>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        self.enqueue(task, false);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        let prio = task.get_prio() as usize;
+        let ret = self.queues[prio]
+            .iter()
+            .position(|t| Arc::ptr_eq(t, task))
+            .and_then(|idx| self.queues[prio].remove(idx));
+        if ret.is_some() {
+            self.dequeued(prio);
+        }
+        ret
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        let prio = self.highest_prio()? as usize;
+        let next = self.queues[prio].pop_front();
+        self.dequeued(prio);
+        next
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        // A preempted task keeps its place at the head of its queue.
+        if preempt && (!prev.is_round_robin() || prev.time_slice() > 0) {
+            self.enqueue(prev, true);
+        } else {
+            prev.reset_time_slice();
+            self.enqueue(prev, false);
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        if self.highest_prio().is_some_and(|prio| prio > current.get_prio()) {
+            return true;
+        }
+        if !current.is_round_robin() {
+            return false;
+        }
+        let old_slice = current.time_slice.fetch_sub(1, Ordering::Release);
+        if old_slice <= 1 {
+            // Expired: let the peers run, or start a new slice if alone.
+            if self.highest_prio() == Some(current.get_prio()) {
+                return true;
+            }
+            current.reset_time_slice();
+        }
+        false
+    }
+
+    fn set_priority(&mut self, task: &Self::SchedItem, prio: isize) -> bool {
+        if (1..MAX_RT_PRIO).contains(&prio) {
+            task.prio.store(prio, Ordering::Release);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -79,6 +79,198 @@ macro_rules! def_test_sched {
     };
 }
 
-def_test_sched!(fifo, FifoScheduler::<usize>, FifoTask::<usize>);
-def_test_sched!(rr, RRScheduler::<usize, 5>, RRTask::<usize, 5>);
 def_test_sched!(cfs, CFScheduler::<usize>, CFSTask::<usize>);
+
+mod cfs_fair {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    #[test]
+    fn test_nice_share() {
+        const TICKS: usize = 10_000;
+
+        let mut scheduler = CFScheduler::<usize>::new();
+        let tasks = [Arc::new(CFSTask::new(0)), Arc::new(CFSTask::new(1))];
+        for t in tasks.iter() {
+            scheduler.add_task(t.clone());
+        }
+        // nice -5 has about 3x the weight of nice 0.
+        assert!(scheduler.set_priority(&tasks[0], -5));
+        assert!(!scheduler.set_priority(&tasks[1], 20));
+
+        let mut runs = [0usize; 2];
+        let mut curr = scheduler.pick_next_task().unwrap();
+        for _ in 0..TICKS {
+            runs[*curr.inner()] += 1;
+            if scheduler.task_tick(&curr) {
+                scheduler.put_prev_task(curr, true);
+                curr = scheduler.pick_next_task().unwrap();
+            }
+        }
+        let ratio = runs[0] as f64 / runs[1] as f64;
+        assert!(ratio > 2.8 && ratio < 3.4, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_shares() {
+        const TICKS: usize = 10_000;
+
+        let mut scheduler = CFScheduler::<usize>::new();
+        let tasks = [Arc::new(CFSTask::new(0)), Arc::new(CFSTask::new(1))];
+        for t in tasks.iter() {
+            scheduler.add_task(t.clone());
+        }
+        tasks[1].set_shares(256);
+
+        let mut runs = [0usize; 2];
+        let mut curr = scheduler.pick_next_task().unwrap();
+        for _ in 0..TICKS {
+            runs[*curr.inner()] += 1;
+            if scheduler.task_tick(&curr) {
+                scheduler.put_prev_task(curr, true);
+                curr = scheduler.pick_next_task().unwrap();
+            }
+        }
+        let ratio = runs[0] as f64 / runs[1] as f64;
+        assert!(ratio > 3.6 && ratio < 4.4, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_sleeper_placement() {
+        let mut scheduler = CFScheduler::<usize>::new();
+        let busy = Arc::new(CFSTask::new(0));
+        let sleeper = Arc::new(CFSTask::new(1));
+        scheduler.add_task(busy.clone());
+        scheduler.add_task(sleeper.clone());
+        let _ = scheduler.remove_task(&sleeper).unwrap();
+
+        let busy = scheduler.pick_next_task().unwrap();
+        for _ in 0..100 {
+            scheduler.task_tick(&busy);
+            scheduler.put_prev_task(busy.clone(), false);
+            scheduler.pick_next_task().unwrap();
+        }
+        // The woken task runs first, but doesn't get credit for its whole sleep.
+        scheduler.put_prev_task(busy.clone(), false);
+        scheduler.add_task(sleeper.clone());
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+        assert!(sleeper.get_vruntime() > busy.get_vruntime() / 2);
+    }
+
+    #[test]
+    fn test_yield_alternate() {
+        let mut scheduler = CFScheduler::<usize>::new();
+        let a = Arc::new(CFSTask::new(0));
+        let b = Arc::new(CFSTask::new(1));
+        scheduler.add_task(a.clone());
+        scheduler.add_task(b);
+        // `a` is owed CPU time: put back as is, it would be picked again.
+        scheduler.remove_task(&a).unwrap();
+        a.set_vruntime(-100);
+        scheduler.add_task(a);
+
+        for i in 0..10 {
+            let curr = scheduler.pick_next_task().unwrap();
+            assert_eq!(*curr.inner(), i % 2);
+            scheduler.yield_task(curr);
+        }
+    }
+
+    #[test]
+    fn test_wakeup_boost() {
+        let mut scheduler = CFScheduler::<usize>::new();
+        let hog = Arc::new(CFSTask::new(0));
+        scheduler.add_task(hog.clone());
+        let mut curr = scheduler.pick_next_task().unwrap();
+        scheduler.task_tick(&curr);
+        let tick = hog.get_vruntime();
+        for _ in 0..100 {
+            scheduler.put_prev_task(curr, false);
+            curr = scheduler.pick_next_task().unwrap();
+            scheduler.task_tick(&curr);
+        }
+
+        // Longer sleepers are placed further back, up to a bound.
+        let sleepers: Vec<_> = (1..4).map(|i| Arc::new(CFSTask::new(i))).collect();
+        for (t, slept_ticks) in sleepers.iter().zip([0, 100, 1000]) {
+            scheduler.add_woken_task(t.clone(), slept_ticks);
+        }
+        let vruntime: Vec<_> = sleepers.iter().map(|t| t.get_vruntime()).collect();
+        assert_eq!(vruntime[0] - vruntime[1], 3 * tick);
+        assert_eq!(vruntime[1], vruntime[2]);
+        assert!(scheduler.wakeup_preempt(&curr, &sleepers[1]));
+        assert!(!scheduler.wakeup_preempt(&sleepers[0], &sleepers[0]));
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 2);
+    }
+}
+
+mod rt {
+    use crate::*;
+    use alloc::sync::Arc;
+
+    type Task = RTTask<usize, 2>;
+
+    fn new_task(scheduler: &mut RTScheduler<usize, 2>, id: usize, prio: isize, rr: bool) -> Arc<Task> {
+        let t = Arc::new(Task::new(id));
+        assert!(scheduler.set_priority(&t, prio));
+        t.set_round_robin(rr);
+        t
+    }
+
+    #[test]
+    fn test_strict_priority() {
+        let mut scheduler = RTScheduler::<usize, 2>::new();
+        assert!(!scheduler.set_priority(&Arc::new(Task::new(0)), 0));
+        assert!(!scheduler.set_priority(&Arc::new(Task::new(0)), MAX_RT_PRIO));
+
+        for (id, prio) in [(0, 10), (1, 50), (2, 10), (3, 99)] {
+            let t = new_task(&mut scheduler, id, prio, false);
+            scheduler.add_task(t);
+        }
+        assert_eq!(scheduler.highest_prio(), Some(99));
+        let order: Vec<usize> = core::iter::from_fn(|| scheduler.pick_next_task())
+            .map(|t| *t.inner())
+            .collect();
+        assert_eq!(order, [3, 1, 0, 2]);
+        assert_eq!(scheduler.highest_prio(), None);
+    }
+
+    #[test]
+    fn test_round_robin_slice() {
+        let mut scheduler = RTScheduler::<usize, 2>::new();
+        let a = new_task(&mut scheduler, 0, 20, true);
+        let b = new_task(&mut scheduler, 1, 20, true);
+        scheduler.add_task(a.clone());
+        scheduler.add_task(b.clone());
+
+        let curr = scheduler.pick_next_task().unwrap();
+        assert_eq!(*curr.inner(), 0);
+        assert!(!scheduler.task_tick(&curr));
+        assert!(scheduler.task_tick(&curr));
+        scheduler.put_prev_task(curr, true);
+        assert_eq!(*scheduler.pick_next_task().unwrap().inner(), 1);
+
+        // A FIFO task is only preempted by a more urgent one.
+        let fifo = new_task(&mut scheduler, 2, 30, false);
+        for _ in 0..10 {
+            assert!(!scheduler.task_tick(&fifo));
+        }
+        let urgent = new_task(&mut scheduler, 3, 40, false);
+        scheduler.add_task(urgent);
+        assert!(scheduler.task_tick(&fifo));
+    }
+
+    #[test]
+    fn test_yield_alternate() {
+        let mut scheduler = RTScheduler::<usize, 2>::new();
+        for id in 0..2 {
+            let t = new_task(&mut scheduler, id, 20, false);
+            scheduler.add_task(t);
+        }
+        for i in 0..10 {
+            let curr = scheduler.pick_next_task().unwrap();
+            assert_eq!(*curr.inner(), i % 2);
+            scheduler.yield_task(curr);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+#!/usr/bin/env python3
+"""Prints a crash dump the kernel wrote on a panic (see kdump/src/lib.rs).
+
+The dump is read from a file: the first blocks of the dump device, or the
+memory kept for it, saved out of QEMU with `pmemsave`. Return addresses
+are resolved against the kernel ELF if one is given, with addr2line.
+
+    scripts/kdump.py dump.bin [kernel.elf] [--save-memory DIR]
+"""
+
+import argparse
+import os
+import struct
+import subprocess
+import sys
+
+KDUMP_MAGIC = b"LKDUMP\0\0"
+HEADER = struct.Struct("<8sIIQIIIIQQII")
+SECTION = struct.Struct("<IIQ")
+FLAG_TRUNCATED = 1
+
+SECTION_MESSAGE = 1
+SECTION_REGS = 2
+SECTION_BACKTRACE = 3
+SECTION_TASKS = 4
+SECTION_DMESG = 5
+SECTION_MEMORY = 6
+
+MACHINES = {243: "riscv64", 62: "x86_64"}
+REGS = {
+    243: ["pc", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1"]
+    + ["a%d" % i for i in range(8)]
+    + ["s%d" % i for i in range(2, 12)]
+    + ["t3", "t4", "t5", "t6", "sstatus", "sepc", "scause", "stval", "satp"],
+    62: ["rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp"]
+    + ["r%d" % i for i in range(8, 16)]
+    + ["rip", "rflags", "cr2", "cr3"],
+}
+STATES = {1: "running", 2: "runnable", 3: "sleeping", 4: "disk-sleep", 5: "zombie"}
+
+
+def fnv1a(data):
+    h = 0x811c9dc5
+    for b in data:
+        h = ((h ^ b) * 0x01000193) & 0xffffffff
+    return h
+
+
+def resolve(elf, addrs):
+    """Names the functions of `addrs`, by addr2line on `elf`."""
+    if not elf or not addrs:
+        return [""] * len(addrs)
+    out = subprocess.run(
+        ["addr2line", "-f", "-C", "-e", elf] + ["%#x" % (a - 1) for a in addrs],
+        capture_output=True, text=True, check=False,
+    ).stdout.splitlines()
+    return [out[2 * i] if 2 * i < len(out) else "" for i in range(len(addrs))]
+
+
+def parse(data):
+    """Returns the header fields and the (type, payload) of the sections."""
+    if len(data) < HEADER.size:
+        sys.exit("kdump: too short for a header")
+    (magic, version, hdr_size, total, nr_sections, flags, machine, cpu, tid,
+     time_ns, checksum, _) = HEADER.unpack_from(data)
+    if magic != KDUMP_MAGIC:
+        sys.exit("kdump: bad magic %r" % magic)
+    if version != 1:
+        sys.exit("kdump: unknown version %d" % version)
+    data = data[:total]
+    image = data[:56] + b"\0\0\0\0" + data[60:]
+    if fnv1a(image) != checksum:
+        print("warning: bad checksum, the dump may be damaged")
+    header = dict(total=total, flags=flags, machine=machine, cpu=cpu, tid=tid,
+                  time_ns=time_ns)
+    sections = []
+    off = hdr_size
+    for _ in range(nr_sections):
+        if off + SECTION.size > len(data):
+            break
+        ty, _, size = SECTION.unpack_from(data, off)
+        off += SECTION.size
+        sections.append((ty, data[off:off + size]))
+        off += (size + 7) & ~7
+    return header, sections
+
+
+def u64s(payload):
+    return struct.unpack("<%dQ" % (len(payload) // 8), payload[:len(payload) // 8 * 8])
+
+
+def show(header, sections, elf, save_dir):
+    machine = header["machine"]
+    print("kernel dump: %s, cpu %d, task %d, at %d.%06d s%s" % (
+        MACHINES.get(machine, "machine %d" % machine), header["cpu"], header["tid"],
+        header["time_ns"] // 10**9, header["time_ns"] // 1000 % 10**6,
+        " (truncated)" if header["flags"] & FLAG_TRUNCATED else ""))
+    for ty, payload in sections:
+        if ty == SECTION_MESSAGE:
+            print("\n%s" % payload.decode("utf-8", "replace"))
+        elif ty == SECTION_REGS:
+            print("\nRegisters:")
+            names = REGS.get(machine, [])
+            for i, v in enumerate(u64s(payload)):
+                name = names[i] if i < len(names) else "r%d" % i
+                print("  %-8s %#018x" % (name, v))
+        elif ty == SECTION_BACKTRACE:
+            print("\nBacktrace:")
+            addrs = u64s(payload)
+            for i, (ra, name) in enumerate(zip(addrs, resolve(elf, addrs))):
+                print("  #%-2d %#018x %s" % (i, ra, name))
+        elif ty == SECTION_TASKS:
+            print("\nTasks:")
+            print("  %6s %6s %-10s %3s %10s %18s" % ("TID", "TGID", "STATE", "CPU", "SWITCHES", "FP"))
+            v = u64s(payload)
+            for i in range(0, len(v) - 5, 6):
+                tid, tgid, state, cpu, switches, fp = v[i:i + 6]
+                print("  %6d %6d %-10s %3d %10d %#018x" % (
+                    tid, tgid, STATES.get(state, str(state)), cpu, switches, fp))
+        elif ty == SECTION_DMESG:
+            print("\nKernel log:")
+            sys.stdout.write(payload.decode("utf-8", "replace"))
+        elif ty == SECTION_MEMORY:
+            start, length = struct.unpack_from("<QQ", payload)
+            name = payload[16:32].rstrip(b"\0").decode("utf-8", "replace")
+            data = payload[32:]
+            print("\nMemory %s: %#x, %d of %d bytes" % (name, start, len(data), length))
+            if save_dir:
+                path = os.path.join(save_dir, "%s-%x.bin" % (name, start))
+                with open(path, "wb") as f:
+                    f.write(data)
+                print("  saved to %s" % path)
+        else:
+            print("\nSection %d: %d bytes" % (ty, len(payload)))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("dump")
+    parser.add_argument("elf", nargs="?")
+    parser.add_argument("--save-memory", metavar="DIR")
+    args = parser.parse_args()
+    with open(args.dump, "rb") as f:
+        data = f.read()
+    header, sections = parse(data)
+    if args.save_memory:
+        os.makedirs(args.save_memory, exist_ok=True)
+    show(header, sections, args.elf, args.save_memory)
+
+
+if __name__ == "__main__":
+    main()
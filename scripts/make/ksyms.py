@@ -0,0 +1,127 @@
+#!/usr/bin/env python3
+"""Writes the symbol table of a linked kernel into the kernel itself.
+
+The functions of the ELF symbol table are sorted by address, their names
+demangled, and the table is written over the `__KSYMS` array reserved by
+the backtrace crate, in the layout it reads (see backtrace/src/ksyms.rs).
+A kernel without that array is left as it is.
+"""
+
+import re
+import struct
+import sys
+
+KSYMS_MAGIC = b"KSYM"
+KSYMS_SYMBOL = "__KSYMS"
+
+SHT_SYMTAB = 2
+STT_FUNC = 2
+
+ESCAPES = {
+    "$SP$": "@", "$BP$": "*", "$RF$": "&", "$LT$": "<", "$GT$": ">",
+    "$LP$": "(", "$RP$": ")", "$C$": ",",
+}
+
+
+def demangle(name):
+    """Demangles a legacy Rust symbol, without its hash. Others are kept."""
+    if not name.startswith("_ZN") or not name.endswith("E"):
+        return name
+    parts = []
+    rest = name[3:-1]
+    while rest:
+        m = re.match(r"(\d+)", rest)
+        if not m:
+            return name
+        n = int(m.group(1))
+        start = len(m.group(1))
+        parts.append(rest[start:start + n])
+        rest = rest[start + n:]
+    if parts and re.fullmatch(r"h[0-9a-f]{16}", parts[-1]):
+        parts.pop()
+    out = []
+    for part in parts:
+        if part.startswith("_$"):
+            part = part[1:]
+        part = part.replace("..", "::")
+        for esc, c in ESCAPES.items():
+            part = part.replace(esc, c)
+        part = re.sub(r"\$u([0-9a-f]+)\$", lambda m: chr(int(m.group(1), 16)), part)
+        out.append(part)
+    return "::".join(out)
+
+
+def read_sections(elf):
+    (shoff,) = struct.unpack_from("<Q", elf, 0x28)
+    shentsize, shnum = struct.unpack_from("<HH", elf, 0x3a)
+    sections = []
+    for i in range(shnum):
+        name, ty, flags, addr, offset, size, link, info, align, entsize = \
+            struct.unpack_from("<IIQQQQIIQQ", elf, shoff + i * shentsize)
+        sections.append(dict(type=ty, addr=addr, offset=offset, size=size,
+                             link=link, entsize=entsize))
+    return sections
+
+
+def read_symbols(elf, sections):
+    """Yields (name, value, size, type, section index) of the symtab."""
+    for sec in sections:
+        if sec["type"] != SHT_SYMTAB:
+            continue
+        strtab = sections[sec["link"]]
+        for off in range(sec["offset"], sec["offset"] + sec["size"], sec["entsize"]):
+            name, info, _other, shndx, value, size = struct.unpack_from("<IBBHQQ", elf, off)
+            start = strtab["offset"] + name
+            end = elf.index(b"\0", start)
+            yield elf[start:end].decode(errors="replace"), value, size, info & 0xf, shndx
+
+
+def build_table(funcs):
+    strtab = bytearray()
+    entries = bytearray()
+    offsets = {}
+    for addr, name in funcs:
+        raw = name.encode()
+        if raw not in offsets:
+            offsets[raw] = len(strtab)
+            strtab += raw
+        entries += struct.pack("<QII", addr, offsets[raw], len(raw))
+    header_size = 12
+    header = KSYMS_MAGIC + struct.pack("<II", len(funcs), header_size + len(entries))
+    return bytes(header + entries + strtab)
+
+
+def main(path):
+    with open(path, "rb") as f:
+        elf = bytearray(f.read())
+    if elf[:4] != b"\x7fELF" or elf[4] != 2 or elf[5] != 1:
+        sys.exit(f"{path}: not a little-endian ELF64 file")
+    sections = read_sections(elf)
+
+    target = None
+    funcs = {}
+    for name, value, size, ty, shndx in read_symbols(elf, sections):
+        if name == KSYMS_SYMBOL:
+            target = (value, size, shndx)
+        elif ty == STT_FUNC and shndx != 0 and value != 0:
+            funcs.setdefault(value, demangle(name))
+    if target is None:
+        print(f"{path}: no {KSYMS_SYMBOL}, symbols not embedded")
+        return
+
+    value, size, shndx = target
+    table = build_table(sorted(funcs.items()))
+    if len(table) > size:
+        sys.exit(f"{path}: symbol table of {len(table)} bytes exceeds {size}")
+    sec = sections[shndx]
+    offset = sec["offset"] + value - sec["addr"]
+    elf[offset:offset + size] = table.ljust(size, b"\0")
+    with open(path, "wb") as f:
+        f.write(elf)
+    print(f"{path}: {len(funcs)} symbols embedded, {len(table)} bytes")
+
+
+if __name__ == "__main__":
+    if len(sys.argv) != 2:
+        sys.exit(f"usage: {sys.argv[0]} <kernel.elf>")
+    main(sys.argv[1])
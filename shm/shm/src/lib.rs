@@ -0,0 +1,497 @@
+//! System V shared memory.
+//!
+//! A segment is kept in a table by its id, and by its key unless it is
+//! private or removed. shmat() maps it shared like a memory-backed file,
+//! with the segment as the node of the file, so forks and page faults go
+//! the usual way for shared mappings. IPC_RMID only marks a segment that
+//! is still attached: it goes away when its last attach is unmapped, by
+//! shmdt(), munmap(), exec or exit.
+//!
+//! Each IPC namespace has a table of its own. When the namespace goes, so
+//! does its table, and the segments in it but for those still attached.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod segment;
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axfile::fops::File;
+use axtype::{align_up_4k, Cred, PAGE_SHIFT, PAGE_SIZE};
+use capability::Cap;
+use mm::VmAreaStruct;
+use mmap::{MAP_FIXED, MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE};
+use mutex::Mutex;
+use nsproxy::IpcNamespace;
+use spinbase::SpinNoIrq;
+
+use self::segment::{IpcPerm, ShmSegment};
+
+pub(crate) type FileRef = Arc<Mutex<File>>;
+
+pub const IPC_PRIVATE: i32 = 0;
+
+/* shmget() flags */
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+
+/* shmctl() commands */
+pub const IPC_RMID: usize = 0;
+pub const IPC_SET: usize = 1;
+pub const IPC_STAT: usize = 2;
+pub const IPC_INFO: usize = 3;
+pub const SHM_LOCK: usize = 11;
+pub const SHM_UNLOCK: usize = 12;
+pub const SHM_STAT: usize = 13;
+pub const SHM_INFO: usize = 14;
+pub const SHM_STAT_ANY: usize = 15;
+/// Asks for the 64-bit layouts, which are the only ones here.
+const IPC_64: usize = 0x100;
+
+/* shmat() flags */
+pub const SHM_RDONLY: usize = 0o10000;
+pub const SHM_RND: usize = 0o20000;
+pub const SHM_REMAP: usize = 0o40000;
+pub const SHM_EXEC: usize = 0o100000;
+
+/* Mode bits of a segment besides its permissions */
+const SHM_DEST: u32 = 0o1000;
+const SHM_LOCKED: u32 = 0o2000;
+
+/// Attach addresses are multiples of it.
+const SHMLBA: usize = PAGE_SIZE;
+
+/* Limits, with the defaults of Linux */
+/// Smallest segment size.
+pub const SHMMIN: usize = 1;
+/// Largest segment size.
+pub const SHMMAX: usize = usize::MAX - (1 << 24);
+/// Most segments.
+pub const SHMMNI: usize = 4096;
+/// Most pages in all segments.
+pub const SHMALL: usize = usize::MAX - (1 << 24);
+
+/// Ids are the index in the table plus the sequence number times it, so
+/// a stale id of a removed segment does not reach a new one in its slot.
+const IPCMNI: usize = 32768;
+
+pub(crate) struct ShmIds {
+    segs: BTreeMap<usize, Arc<ShmSegment>>,
+    keys: BTreeMap<i32, usize>,
+    seq: usize,
+    /// Pages of all segments.
+    shm_tot: usize,
+}
+
+impl ShmIds {
+    const fn new() -> Self {
+        Self {
+            segs: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            seq: 0,
+            shm_tot: 0,
+        }
+    }
+
+    fn get(&self, id: usize) -> LinuxResult<Arc<ShmSegment>> {
+        self.segs.get(&(id % IPCMNI))
+            .filter(|seg| seg.id() as usize == id)
+            .cloned()
+            .ok_or(LinuxError::EINVAL)
+    }
+
+    /// The lowest free slot.
+    fn free_index(&self) -> Option<usize> {
+        (0..SHMMNI).find(|idx| !self.segs.contains_key(idx))
+    }
+
+    fn remove(&mut self, seg: &ShmSegment) {
+        let idx = seg.id() as usize % IPCMNI;
+        if self.segs.remove(&idx).is_some() {
+            self.shm_tot -= seg.num_pages();
+        }
+    }
+}
+
+pub(crate) type ShmIdsRef = Arc<SpinNoIrq<ShmIds>>;
+
+/// The tables of the IPC namespaces, by the ids of the namespaces.
+static SHM_NS: SpinNoIrq<BTreeMap<usize, (Weak<IpcNamespace>, ShmIdsRef)>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+/// The table of the IPC namespace of the current task. Those of the
+/// namespaces that are gone are dropped on the way.
+fn shm_ids() -> ShmIdsRef {
+    let ipc_ns = task::current().nsproxy.ipc_ns.clone();
+    let mut tables = SHM_NS.lock();
+    tables.retain(|_, (ns, _)| ns.strong_count() > 0);
+    tables.entry(ipc_ns.id())
+        .or_insert_with(|| (Arc::downgrade(&ipc_ns), Arc::new(SpinNoIrq::new(ShmIds::new()))))
+        .1
+        .clone()
+}
+
+pub(crate) fn now() -> u64 {
+    timekeeping::ktime_get_real_coarse().as_secs()
+}
+
+/// Whether `cred` may access `perm` as `flag` asks for, in the lower nine
+/// bits laid out as a file mode.
+fn ipcperms(cred: &Cred, perm: &IpcPerm, flag: usize) -> bool {
+    let requested = ((flag >> 6) | (flag >> 3) | flag) as u32 & 0o7;
+    let mut granted = perm.mode;
+    if cred.euid == perm.cuid || cred.euid == perm.uid {
+        granted >>= 6;
+    } else if cred.in_group(perm.cgid) || cred.in_group(perm.gid) {
+        granted >>= 3;
+    }
+    (requested & !granted & 0o7) == 0 || cred.is_privileged()
+}
+
+/// Whether `cred` owns the segment, for IPC_SET and IPC_RMID.
+fn ipc_owner(cred: &Cred, perm: &IpcPerm) -> bool {
+    cred.euid == perm.cuid || cred.euid == perm.uid || cred.is_privileged()
+}
+
+/// Gets the id of the segment of `key`, creating it as `shmflg` asks.
+pub fn shmget(key: i32, size: usize, shmflg: usize) -> LinuxResult<usize> {
+    info!("shmget: key {:#x} size {:#x} flags {:#o}", key, size, shmflg);
+    let current = task::current();
+    let cred = current.cred();
+    let table = shm_ids();
+    let mut ids = table.lock();
+    if key != IPC_PRIVATE {
+        if let Some(&idx) = ids.keys.get(&key) {
+            if (shmflg & (IPC_CREAT | IPC_EXCL)) == (IPC_CREAT | IPC_EXCL) {
+                return Err(LinuxError::EEXIST);
+            }
+            let seg = ids.segs[&idx].clone();
+            if !ipcperms(&cred, &seg.perm.lock(), shmflg) {
+                return Err(LinuxError::EACCES);
+            }
+            if size > seg.size() {
+                return Err(LinuxError::EINVAL);
+            }
+            return Ok(seg.id() as usize);
+        }
+        if (shmflg & IPC_CREAT) == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+    }
+
+    if size < SHMMIN || size > SHMMAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let num_pages = align_up_4k(size) >> PAGE_SHIFT;
+    if ids.shm_tot + num_pages < ids.shm_tot || ids.shm_tot + num_pages > SHMALL {
+        return Err(LinuxError::ENOSPC);
+    }
+    let idx = ids.free_index().ok_or(LinuxError::ENOSPC)?;
+    let id = ids.seq * IPCMNI + idx;
+    ids.seq = (ids.seq + 1) % (i32::MAX as usize / IPCMNI);
+
+    let perm = IpcPerm {
+        key,
+        uid: cred.euid,
+        gid: cred.egid,
+        cuid: cred.euid,
+        cgid: cred.egid,
+        mode: (shmflg & 0o777) as u32,
+    };
+    let seg = ShmSegment::new(id as i32, size, perm, current.tgid(), Arc::downgrade(&table));
+    ids.segs.insert(idx, Arc::new(seg));
+    if key != IPC_PRIVATE {
+        ids.keys.insert(key, idx);
+    }
+    ids.shm_tot += num_pages;
+    Ok(id)
+}
+
+/// Attaches the segment `shmid` at `addr`, or where there is room if it
+/// is 0, and returns the address.
+pub fn shmat(shmid: usize, mut addr: usize, shmflg: usize) -> LinuxResult<usize> {
+    info!("shmat: id {} addr {:#x} flags {:#o}", shmid, addr, shmflg);
+    let mut flags = MAP_SHARED;
+    if addr != 0 {
+        if (addr & (SHMLBA - 1)) != 0 {
+            if (shmflg & SHM_RND) == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            addr &= !(SHMLBA - 1);
+        }
+        flags |= MAP_FIXED;
+    } else if (shmflg & SHM_REMAP) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let (mut prot, mut acc_mode, mut cap) = if (shmflg & SHM_RDONLY) != 0 {
+        (PROT_READ, 0o444, Cap::READ)
+    } else {
+        (PROT_READ | PROT_WRITE, 0o666, Cap::READ | Cap::WRITE)
+    };
+    if (shmflg & SHM_EXEC) != 0 {
+        prot |= PROT_EXEC;
+        acc_mode |= 0o111;
+        cap |= Cap::EXECUTE;
+    }
+
+    let current = task::current();
+    // Not under the table lock: unmapping what is there may release
+    // another attach, which takes it.
+    let seg = shm_ids().lock().get(shmid)?;
+    if !ipcperms(&current.cred(), &seg.perm.lock(), acc_mode) {
+        return Err(LinuxError::EACCES);
+    }
+
+    let size = align_up_4k(seg.size());
+    if (flags & MAP_FIXED) != 0 && (shmflg & SHM_REMAP) == 0 {
+        // Only SHM_REMAP replaces what is mapped there.
+        if current.mm().read().mapped_in(addr, addr + size) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+
+    let file: FileRef = Arc::new(Mutex::new(File::new(seg.clone(), cap)));
+    seg.attach(&file, current.tgid());
+    mmap::_mmap(addr, size, prot, flags, Some(file), 0)
+}
+
+/// Detaches the segment attached at `addr`.
+pub fn shmdt(addr: usize) -> LinuxResult<usize> {
+    info!("shmdt: addr {:#x}", addr);
+    if (addr & (SHMLBA - 1)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let mm = task::current().mm();
+    let mut areas = Vec::new();
+    {
+        let locked_mm = mm.read();
+        let Some((id, size)) = locked_mm.vmas.get(&addr).and_then(|vma| attached(vma, addr)) else {
+            return Err(LinuxError::EINVAL);
+        };
+        // The attach may have been split since, or partly unmapped.
+        for (_, vma) in locked_mm.vmas.range(addr..addr + size) {
+            if attached(vma, addr).is_some_and(|(other, _)| other == id) {
+                areas.push((vma.vm_start, vma.vm_end - vma.vm_start));
+            }
+        }
+    }
+    for (start, len) in areas {
+        mmap::munmap(start, len);
+    }
+    Ok(0)
+}
+
+/// The id and the mapped size of the segment `vma` maps, if it is part
+/// of an attach at `addr`.
+fn attached(vma: &VmAreaStruct, addr: usize) -> Option<(i32, usize)> {
+    if vma.vm_start.wrapping_sub(vma.vm_pgoff << PAGE_SHIFT) != addr {
+        return None;
+    }
+    let node = vma.vm_file.get()?.lock().get_node().ok()?;
+    ShmSegment::from_node(&node).map(|seg| (seg.id(), align_up_4k(seg.size())))
+}
+
+/// Frees `seg` when it is removed and no longer attached, unless its
+/// namespace is gone, and the segment with it.
+pub(crate) fn shm_destroy(seg: &ShmSegment) {
+    let Some(table) = seg.ids() else {
+        return;
+    };
+    let mut ids = table.lock();
+    if let Ok(seg) = ids.get(seg.id() as usize) {
+        debug!("shm: destroy segment {}", seg.id());
+        ids.remove(&seg);
+    }
+}
+
+#[repr(C)]
+struct Ipc64Perm {
+    key: i32,
+    uid: u32,
+    gid: u32,
+    cuid: u32,
+    cgid: u32,
+    mode: u32,
+    seq: u16,
+    __pad2: u16,
+    __unused1: usize,
+    __unused2: usize,
+}
+
+#[repr(C)]
+struct ShmidDs {
+    shm_perm: Ipc64Perm,
+    shm_segsz: usize,
+    shm_atime: i64,
+    shm_dtime: i64,
+    shm_ctime: i64,
+    shm_cpid: i32,
+    shm_lpid: i32,
+    shm_nattch: usize,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+#[repr(C)]
+struct ShmInfo64 {
+    shmmax: usize,
+    shmmin: usize,
+    shmmni: usize,
+    shmseg: usize,
+    shmall: usize,
+    __unused: [usize; 4],
+}
+
+#[repr(C)]
+struct ShmInfo {
+    used_ids: i32,
+    shm_tot: usize,
+    shm_rss: usize,
+    shm_swp: usize,
+    swap_attempts: usize,
+    swap_successes: usize,
+}
+
+fn user_buf<'a, T>(buf: usize) -> LinuxResult<&'a mut T> {
+    if buf == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    Ok(unsafe { &mut *(buf as *mut T) })
+}
+
+fn fill_shmid_ds(seg: &ShmSegment, ds: &mut ShmidDs) {
+    let perm = *seg.perm.lock();
+    let times = *seg.times.lock();
+    ds.shm_perm = Ipc64Perm {
+        key: perm.key,
+        uid: perm.uid,
+        gid: perm.gid,
+        cuid: perm.cuid,
+        cgid: perm.cgid,
+        mode: perm.mode,
+        seq: (seg.id() as usize / IPCMNI) as u16,
+        __pad2: 0,
+        __unused1: 0,
+        __unused2: 0,
+    };
+    ds.shm_segsz = seg.size();
+    ds.shm_atime = times.atime as i64;
+    ds.shm_dtime = times.dtime as i64;
+    ds.shm_ctime = times.ctime as i64;
+    ds.shm_cpid = task::pid_vnr(times.cpid) as i32;
+    ds.shm_lpid = task::pid_vnr(times.lpid) as i32;
+    ds.shm_nattch = seg.nattch();
+    ds.__unused4 = 0;
+    ds.__unused5 = 0;
+}
+
+/// Controls the segment `shmid`, or the whole table for IPC_INFO and
+/// SHM_INFO.
+pub fn shmctl(shmid: usize, cmd: usize, buf: usize) -> LinuxResult<usize> {
+    let cmd = cmd & !IPC_64;
+    info!("shmctl: id {} cmd {}", shmid, cmd);
+    let cred = task::current().cred();
+    match cmd {
+        IPC_INFO => {
+            let info = user_buf::<ShmInfo64>(buf)?;
+            *info = ShmInfo64 {
+                shmmax: SHMMAX,
+                shmmin: SHMMIN,
+                shmmni: SHMMNI,
+                shmseg: SHMMNI,
+                shmall: SHMALL,
+                __unused: [0; 4],
+            };
+            let ids = shm_ids();
+            let ids = ids.lock();
+            Ok(ids.segs.keys().next_back().copied().unwrap_or(0))
+        },
+        SHM_INFO => {
+            let info = user_buf::<ShmInfo>(buf)?;
+            let ids = shm_ids();
+            let ids = ids.lock();
+            *info = ShmInfo {
+                used_ids: ids.segs.len() as i32,
+                shm_tot: ids.shm_tot,
+                shm_rss: ids.segs.values().map(|seg| seg.rss()).sum(),
+                shm_swp: 0,
+                swap_attempts: 0,
+                swap_successes: 0,
+            };
+            Ok(ids.segs.keys().next_back().copied().unwrap_or(0))
+        },
+        SHM_STAT | SHM_STAT_ANY => {
+            // The id is an index into the table here.
+            let seg = shm_ids().lock().segs.get(&shmid).cloned().ok_or(LinuxError::EINVAL)?;
+            if cmd == SHM_STAT && !ipcperms(&cred, &seg.perm.lock(), 0o444) {
+                return Err(LinuxError::EACCES);
+            }
+            fill_shmid_ds(&seg, user_buf(buf)?);
+            Ok(seg.id() as usize)
+        },
+        IPC_STAT => {
+            let seg = shm_ids().lock().get(shmid)?;
+            if !ipcperms(&cred, &seg.perm.lock(), 0o444) {
+                return Err(LinuxError::EACCES);
+            }
+            fill_shmid_ds(&seg, user_buf(buf)?);
+            Ok(0)
+        },
+        IPC_SET => {
+            let ds = user_buf::<ShmidDs>(buf)?;
+            let seg = shm_ids().lock().get(shmid)?;
+            let mut perm = seg.perm.lock();
+            if !ipc_owner(&cred, &perm) {
+                return Err(LinuxError::EPERM);
+            }
+            perm.uid = ds.shm_perm.uid;
+            perm.gid = ds.shm_perm.gid;
+            perm.mode = (perm.mode & !0o777) | (ds.shm_perm.mode & 0o777);
+            seg.times.lock().ctime = now();
+            Ok(0)
+        },
+        IPC_RMID => {
+            let table = shm_ids();
+            let mut ids = table.lock();
+            let seg = ids.get(shmid)?;
+            let mut perm = seg.perm.lock();
+            if !ipc_owner(&cred, &perm) {
+                return Err(LinuxError::EPERM);
+            }
+            // The key is free for a new segment at once, while the
+            // attaches keep this one until they go.
+            if perm.key != IPC_PRIVATE {
+                ids.keys.remove(&perm.key);
+                perm.key = IPC_PRIVATE;
+            }
+            perm.mode |= SHM_DEST;
+            drop(perm);
+            if seg.nattch() == 0 {
+                ids.remove(&seg);
+            }
+            Ok(0)
+        },
+        SHM_LOCK | SHM_UNLOCK => {
+            let seg = shm_ids().lock().get(shmid)?;
+            let mut perm = seg.perm.lock();
+            if !ipc_owner(&cred, &perm) {
+                return Err(LinuxError::EPERM);
+            }
+            // Pages are never swapped, so locking only shows in the mode.
+            if cmd == SHM_LOCK {
+                perm.mode |= SHM_LOCKED;
+            } else {
+                perm.mode &= !SHM_LOCKED;
+            }
+            Ok(0)
+        },
+        _ => Err(LinuxError::EINVAL),
+    }
+}
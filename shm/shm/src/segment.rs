@@ -0,0 +1,181 @@
+//! A shared memory segment, which is also the node of the files its
+//! attaches map, so that its pages come through `get_page` as for any
+//! memory-backed file mapped shared.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::AxError;
+use axfile::fops::File;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsResult};
+use axfs_vfs::alloc_ino;
+use axtype::{align_up_4k, PAGE_SHIFT, PAGE_SIZE};
+use mutex::Mutex;
+use spinbase::SpinNoIrq;
+
+use crate::{now, FileRef, ShmIds, ShmIdsRef, SHM_DEST};
+
+/// Owner, creator and mode of a segment, as in `struct ipc64_perm`.
+#[derive(Clone, Copy)]
+pub(crate) struct IpcPerm {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    pub mode: u32,
+}
+
+/// What IPC_STAT reports besides the permissions.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ShmTimes {
+    pub atime: u64,
+    pub dtime: u64,
+    pub ctime: u64,
+    pub cpid: usize,
+    pub lpid: usize,
+}
+
+pub(crate) struct ShmSegment {
+    id: i32,
+    size: usize,
+    ino: usize,
+    pub perm: SpinNoIrq<IpcPerm>,
+    pub times: SpinNoIrq<ShmTimes>,
+    /// Kernel addresses of the pages touched so far, by page index.
+    pages: SpinNoIrq<BTreeMap<usize, usize>>,
+    /// The files of its attaches. Forks and splits of an attached area
+    /// share the file, so each holder counts as an attach, as vm_ops
+    /// open() counts them on Linux.
+    attaches: SpinNoIrq<Vec<Weak<Mutex<File>>>>,
+    /// The table of its namespace.
+    ids: Weak<SpinNoIrq<ShmIds>>,
+}
+
+impl ShmSegment {
+    pub fn new(
+        id: i32, size: usize, perm: IpcPerm, cpid: usize, ids: Weak<SpinNoIrq<ShmIds>>
+    ) -> Self {
+        Self {
+            id,
+            size,
+            ino: alloc_ino(),
+            perm: SpinNoIrq::new(perm),
+            times: SpinNoIrq::new(ShmTimes {
+                ctime: now(),
+                cpid,
+                ..Default::default()
+            }),
+            pages: SpinNoIrq::new(BTreeMap::new()),
+            attaches: SpinNoIrq::new(Vec::new()),
+            ids,
+        }
+    }
+
+    /// Returns the segment `node` is, if it is one.
+    pub fn from_node(node: &VfsNodeRef) -> Option<&Self> {
+        node.as_any().downcast_ref::<Self>()
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Size asked for at creation.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_pages(&self) -> usize {
+        align_up_4k(self.size) >> PAGE_SHIFT
+    }
+
+    /// Pages in memory.
+    pub fn rss(&self) -> usize {
+        self.pages.lock().len()
+    }
+
+    /// The table of its namespace, unless the namespace is gone.
+    pub fn ids(&self) -> Option<ShmIdsRef> {
+        self.ids.upgrade()
+    }
+
+    pub fn is_dest(&self) -> bool {
+        (self.perm.lock().mode & SHM_DEST) != 0
+    }
+
+    /// Records `file` as mapping an attach.
+    pub fn attach(&self, file: &FileRef, pid: usize) {
+        let mut attaches = self.attaches.lock();
+        attaches.retain(|f| f.strong_count() > 0);
+        attaches.push(Arc::downgrade(file));
+        let mut times = self.times.lock();
+        times.atime = now();
+        times.lpid = pid;
+    }
+
+    pub fn detach(&self, pid: usize) {
+        let mut times = self.times.lock();
+        times.dtime = now();
+        times.lpid = pid;
+    }
+
+    /// Number of attaches still mapped.
+    pub fn nattch(&self) -> usize {
+        self.attaches.lock().iter().map(|f| f.strong_count()).sum()
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        for (_, page) in self.pages.get_mut().iter() {
+            axalloc::global_allocator().dealloc_pages(*page, 1);
+        }
+    }
+}
+
+impl VfsNodeOps for ShmSegment {
+    /// The last holder of an attach has unmapped it, so a segment marked
+    /// for destruction may go now.
+    fn release(&self) -> VfsResult {
+        self.detach(task::current().tgid());
+        if self.is_dest() && self.nattch() == 0 {
+            crate::shm_destroy(self);
+        }
+        Ok(())
+    }
+
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = self.perm.lock();
+        Ok(VfsNodeAttr::new_file(
+            align_up_4k(self.size) as u64,
+            self.num_pages() as u64,
+            perm.uid,
+            perm.gid,
+            (perm.mode & 0o777) as i32,
+        ))
+    }
+
+    fn get_page(&self, offset: u64) -> VfsResult<usize> {
+        let index = offset as usize >> PAGE_SHIFT;
+        if index >= self.num_pages() {
+            return Err(AxError::InvalidInput);
+        }
+        let mut pages = self.pages.lock();
+        if let Some(page) = pages.get(&index) {
+            return Ok(*page);
+        }
+        let page = axalloc::global_allocator()
+            .alloc_pages(1, PAGE_SIZE)
+            .map_err(|_| AxError::NoMemory)?;
+        unsafe { core::ptr::write_bytes(page as *mut u8, 0, PAGE_SIZE) };
+        pages.insert(index, page);
+        Ok(page)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,227 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+
+use core::mem;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use axerrno::{linux_err, LinuxError};
+use task::{SigAction, NSIG, SIGCHLD, SIGKILL, SIGUSR1, SIGUSR2};
+
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+const SIG_BLOCK: usize = 0;
+const SIG_UNBLOCK: usize = 1;
+const SIG_SETMASK: usize = 2;
+
+const SIGSET_SIZE: usize = mem::size_of::<u64>();
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_signal]: ...");
+
+    fork::init(cpu_id, dtb_pa);
+
+    // Signals are sent to a kernel thread, which has a trap frame of its
+    // own for the handler frames to be built on.
+    fork::kernel_thread(|| {
+        test_sigaction();
+        test_sigprocmask();
+        test_interrupted_sleep();
+        #[cfg(target_arch = "riscv64")]
+        test_delivery();
+        DONE.store(true, Ordering::Release);
+        run_queue::exit_current(0);
+    }, None);
+    while !DONE.load(Ordering::Acquire) {
+        task::yield_now();
+    }
+
+    info!("[rt_signal]: ok!");
+    axhal::misc::terminate();
+}
+
+fn sigmask(signo: usize) -> u64 {
+    1 << (signo - 1)
+}
+
+fn sigaction(sig: usize, handler: usize, mask: u64) -> usize {
+    let act = SigAction {
+        handler,
+        // Handlers return by the restorer of libc on x86_64.
+        flags: if cfg!(target_arch = "x86_64") { task::SA_RESTORER } else { 0 },
+        mask,
+        ..Default::default()
+    };
+    signal::rt_sigaction(sig, &act as *const _ as usize, 0, SIGSET_SIZE)
+}
+
+fn sigprocmask(how: usize, set: u64) -> u64 {
+    let mut old = 0u64;
+    let ret = signal::rt_sigprocmask(how, &set as *const _ as usize, &mut old as *mut _ as usize, SIGSET_SIZE);
+    assert_eq!(ret, 0);
+    old
+}
+
+fn send_self(sig: usize) {
+    let tid = task::current().tid();
+    assert_eq!(signal::kill(task::pid_vnr(tid), sig), 0);
+}
+
+fn pending() -> u64 {
+    task::current().sigpending.lock().signal
+}
+
+fn sigpending_flag() -> bool {
+    taskctx::signal_pending(&taskctx::current_ctx())
+}
+
+/// Actions are set and read back, but not for SIGKILL, and ignoring a
+/// signal discards it if it's pending.
+fn test_sigaction() {
+    assert_eq!(sigaction(SIGKILL, SIG_IGN, 0), linux_err!(EINVAL));
+    assert_eq!(sigaction(NSIG + 1, SIG_IGN, 0), linux_err!(EINVAL));
+    let act = SigAction::default();
+    assert_eq!(signal::rt_sigaction(SIGUSR1, &act as *const _ as usize, 0, 4), linux_err!(EINVAL));
+
+    assert_eq!(sigaction(SIGUSR2, SIG_IGN, sigmask(SIGUSR1) | sigmask(SIGKILL)), 0);
+    let mut oact = SigAction::default();
+    assert_eq!(signal::rt_sigaction(SIGUSR2, 0, &mut oact as *mut _ as usize, SIGSET_SIZE), 0);
+    assert_eq!(oact.handler, SIG_IGN);
+    // SIGKILL can't be blocked in a handler either.
+    assert_eq!(oact.mask, sigmask(SIGUSR1));
+
+    // An ignored signal isn't even queued, unless it's blocked.
+    send_self(SIGUSR2);
+    assert_eq!(pending() & sigmask(SIGUSR2), 0);
+    sigprocmask(SIG_BLOCK, sigmask(SIGUSR2));
+    send_self(SIGUSR2);
+    assert_ne!(pending() & sigmask(SIGUSR2), 0);
+    assert_eq!(sigaction(SIGUSR2, SIG_IGN, 0), 0);
+    assert_eq!(pending() & sigmask(SIGUSR2), 0);
+    sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR2));
+    assert_eq!(sigaction(SIGUSR2, SIG_DFL, 0), 0);
+
+    // SIGCHLD is ignored by default.
+    send_self(SIGCHLD);
+    assert_eq!(pending() & sigmask(SIGCHLD), 0);
+
+    assert_eq!(signal::kill(0x7fff_ffff, SIGUSR1), linux_err!(ESRCH));
+    assert_eq!(signal::kill(1, NSIG + 1), linux_err!(EINVAL));
+    info!("[rt_signal]: sigaction ok!");
+}
+
+/// A blocked signal stays pending, without TIF_SIGPENDING, till it's
+/// unblocked. SIGKILL can't be blocked.
+fn test_sigprocmask() {
+    let old = sigprocmask(SIG_SETMASK, sigmask(SIGUSR1) | sigmask(SIGKILL));
+    assert_eq!(old, 0);
+    assert_eq!(sigprocmask(SIG_BLOCK, sigmask(SIGUSR2)), sigmask(SIGUSR1));
+    assert_eq!(sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR2)), sigmask(SIGUSR1) | sigmask(SIGUSR2));
+
+    send_self(SIGUSR1);
+    assert_ne!(pending() & sigmask(SIGUSR1), 0);
+    // The sender sets the flag, and a change of the mask clears it.
+    assert!(sigpending_flag());
+    sigprocmask(SIG_SETMASK, sigmask(SIGUSR1) | sigmask(SIGUSR2));
+    assert!(!sigpending_flag());
+    sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR2));
+    assert!(!sigpending_flag());
+
+    // Unblocking makes it deliverable.
+    assert_eq!(sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR1)), sigmask(SIGUSR1));
+    assert!(sigpending_flag());
+    // Ignoring it discards it, and then nothing is pending.
+    assert_eq!(sigaction(SIGUSR1, SIG_IGN, 0), 0);
+    assert_eq!(pending(), 0);
+    assert_eq!(sigaction(SIGUSR1, SIG_DFL, 0), 0);
+    sigprocmask(SIG_BLOCK, sigmask(SIGUSR1));
+    sigprocmask(SIG_SETMASK, 0);
+    assert!(!sigpending_flag());
+    info!("[rt_signal]: sigprocmask ok!");
+}
+
+/// A sleep ends with EINTR at once if a signal is pending, whose handler
+/// would run on the way back to user mode.
+fn test_interrupted_sleep() {
+    let deadline = axhal::time::current_time() + Duration::from_secs(3600);
+    assert_eq!(run_queue::sleep_until_interruptible(axhal::time::current_time()), Ok(()));
+
+    sigprocmask(SIG_BLOCK, sigmask(SIGUSR1));
+    assert_eq!(sigaction(SIGUSR1, handler as usize, 0), 0);
+    send_self(SIGUSR1);
+    sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR1));
+    assert_eq!(run_queue::sleep_until_interruptible(deadline), Err(LinuxError::EINTR));
+
+    // Discard it for the next test.
+    assert_eq!(sigaction(SIGUSR1, SIG_IGN, 0), 0);
+    assert_eq!(sigaction(SIGUSR1, SIG_DFL, 0), 0);
+    sigprocmask(SIG_SETMASK, 0);
+    info!("[rt_signal]: interrupted sleep ok!");
+}
+
+/// Never runs: the delivery only points the trap frame at it.
+extern "C" fn handler() {}
+
+#[cfg(target_arch = "riscv64")]
+#[repr(C, align(16))]
+struct SignalStack([u8; 4096]);
+
+#[cfg(target_arch = "riscv64")]
+static mut SIGNAL_STACK: SignalStack = SignalStack([0; 4096]);
+
+/// A signal is delivered on the way back to user mode: the trap frame
+/// goes to the handler on a frame pushed on the user stack, with its mask
+/// blocked, and sigreturn puts back the context and the mask.
+#[cfg(target_arch = "riscv64")]
+fn test_delivery() {
+    // Not a syscall, whose return value would be fixed up.
+    const CAUSE: usize = 0;
+    const EPC: usize = 0x1000;
+    const RET: usize = 0x1234;
+
+    let tf = taskctx::current_ctx().pt_regs();
+    let sp = unsafe { core::ptr::addr_of_mut!(SIGNAL_STACK) as usize } + mem::size_of::<SignalStack>();
+    tf.sepc = EPC;
+    tf.regs.sp = sp;
+    tf.regs.a0 = RET;
+
+    assert_eq!(sigaction(SIGUSR1, handler as usize, sigmask(SIGUSR2)), 0);
+    sigprocmask(SIG_BLOCK, sigmask(SIGUSR1));
+    send_self(SIGUSR1);
+    // Blocked: nothing is delivered.
+    signal::do_signal(tf, CAUSE);
+    assert_eq!(tf.sepc, EPC);
+    assert!(!sigpending_flag());
+
+    sigprocmask(SIG_UNBLOCK, sigmask(SIGUSR1));
+    signal::do_signal(tf, CAUSE);
+    assert_eq!(tf.sepc, handler as usize);
+    assert_eq!(tf.regs.a0, SIGUSR1);
+    assert!(tf.regs.sp < sp && sp - tf.regs.sp >= signal::SIGFRAME_SIZE);
+    assert_eq!(tf.regs.sp % 16, 0);
+    assert_eq!(pending(), 0);
+    let blocked = task::current().blocked.load(Ordering::Relaxed);
+    assert_eq!(blocked, sigmask(SIGUSR1) | sigmask(SIGUSR2));
+
+    assert_eq!(signal::rt_sigreturn(), RET);
+    assert_eq!(tf.sepc, EPC);
+    assert_eq!(tf.regs.sp, sp);
+    assert_eq!(task::current().blocked.load(Ordering::Relaxed), 0);
+    assert_eq!(sigaction(SIGUSR1, SIG_DFL, 0), 0);
+    info!("[rt_signal]: delivery and sigreturn ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
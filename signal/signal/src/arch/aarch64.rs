@@ -0,0 +1,80 @@
+use axhal::arch::{TrapFrame, local_flush_icache_all};
+use crate::{RTSigFrame, KSignal};
+use crate::{setup_rt_frame, push_rt_frame, restore_sigcontext};
+use crate::{set_current_blocked, signal_delivered};
+use task::SA_RESTORER;
+
+/// The exception class of ESR_EL1 for SVC.
+const EXC_SYSCALL: usize = 0x15;
+
+const ERESTARTSYS: isize = 512;
+const EINTR: isize = 4;
+
+pub fn rt_sigreturn() -> usize {
+    info!("sigreturn ...");
+
+    let ctx = taskctx::current_ctx();
+    let tf = ctx.pt_regs();
+
+    let frame_addr = tf.usp as usize;
+    let frame = unsafe { &mut(*(frame_addr as *mut RTSigFrame)) };
+
+    let set = frame.uc._sigmask as u64;
+    debug!("sigreturn_ :  set {:#x}", set);
+    set_current_blocked(set);
+
+    restore_sigcontext(tf, frame);
+
+    // Todo: restore_altstack
+    tf.r[0] as usize
+}
+
+/// Fixes up the return value of a syscall interrupted by a signal.
+///
+/// Todo: restart it if the handler has SA_RESTART. It needs the original
+/// x0, which is overwritten by the return value.
+pub fn restart_syscall(tf: &mut TrapFrame, cause: usize) {
+    if cause == EXC_SYSCALL && tf.r[0] == (-ERESTARTSYS) as u64 {
+        tf.r[0] = (-EINTR) as u64;
+    }
+}
+
+pub fn handle_signal(ksig: &KSignal, tf: &mut TrapFrame, cause: usize) {
+    extern "C" {
+        fn __user_rt_sigreturn();
+    }
+    restart_syscall(tf, cause);
+
+    let mut frame = setup_rt_frame(ksig, tf);
+
+    // Without a restorer of libc, the handler returns to the code of
+    // `__user_rt_sigreturn`, which is put in the frame, as on riscv64.
+    let has_restorer = (ksig.action.flags & SA_RESTORER) != 0;
+    if !has_restorer {
+        let user_rt_sigreturn = __user_rt_sigreturn as usize as *const usize;
+        frame.sigreturn_code = unsafe { *user_rt_sigreturn };
+    }
+
+    let frame_addr = push_rt_frame(&frame, tf.usp as usize);
+    let frame = unsafe { &(*(frame_addr as *const RTSigFrame)) };
+
+    let lr = if has_restorer {
+        ksig.action.restorer
+    } else {
+        /* Make sure the two instructions are pushed to icache. */
+        local_flush_icache_all();
+        &(frame.sigreturn_code) as *const usize as usize
+    };
+    tf.r[30] = lr as u64;
+
+    assert!(ksig.action.handler != 0);
+    tf.elr = ksig.action.handler as u64;
+    tf.usp = frame_addr as u64;
+    tf.r[0] = ksig.signo as u64;                        // x0: signal number
+    tf.r[1] = &frame.info as *const _ as u64;           // x1: siginfo pointer
+    tf.r[2] = &frame.uc as *const _ as u64;             // x2: ucontext pointer
+
+    signal_delivered(ksig);
+    info!("handle_signal signo {} frame {:#X} tf.elr {:#x}",
+          ksig.signo, frame_addr, tf.elr);
+}
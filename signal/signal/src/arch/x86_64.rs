@@ -1,10 +1,75 @@
 use axhal::arch::TrapFrame;
-use crate::KSignal;
+use crate::{RTSigFrame, KSignal};
+use crate::{setup_rt_frame, push_rt_frame, restore_sigcontext};
+use crate::{set_current_blocked, signal_delivered};
+use task::SA_RESTART;
+use user_stack::UserStack;
+
+/// The `cause` of a syscall, the vector of `int 0x80`. Other causes are
+/// exception or IRQ vectors.
+pub const SYSCALL_VECTOR: usize = 0x80;
+
+const ERESTARTSYS: isize = 512;
+const EINTR: isize = 4;
+
+/// Below the user stack pointer, which leaf functions may use.
+const RED_ZONE_SIZE: usize = 128;
 
 pub fn rt_sigreturn() -> usize {
-    unimplemented!("impl rt_sigreturn");
+    info!("sigreturn ...");
+
+    let ctx = taskctx::current_ctx();
+    let tf = ctx.pt_regs();
+
+    // The handler has returned to the restorer, popping its address.
+    let frame_addr = tf.rsp as usize;
+    let frame = unsafe { &(*(frame_addr as *const RTSigFrame)) };
+
+    let set = frame.uc._sigmask as u64;
+    debug!("sigreturn_ :  set {:#x}", set);
+    set_current_blocked(set);
+
+    // Todo: sysret clobbers rcx and r11, restore them by iret.
+    restore_sigcontext(tf, frame);
+
+    // Todo: restore_altstack
+    tf.rax as usize
+}
+
+/// Restarts a syscall interrupted by a signal. The syscall number is kept
+/// in `error_code`, as `orig_ax` of Linux.
+pub fn restart_syscall(tf: &mut TrapFrame, cause: usize) {
+    if cause == SYSCALL_VECTOR && tf.rax == (-ERESTARTSYS) as u64 {
+        tf.rax = tf.error_code;
+        tf.rip -= 2; // length of `syscall`
+    }
 }
 
-pub fn handle_signal(_ksig: &KSignal, _tf: &mut TrapFrame, _cause: usize) {
-    unimplemented!("impl handle_signal");
+pub fn handle_signal(ksig: &KSignal, tf: &mut TrapFrame, cause: usize) {
+    if (ksig.action.flags & SA_RESTART) != 0 {
+        restart_syscall(tf, cause);
+    } else if cause == SYSCALL_VECTOR && tf.rax == (-ERESTARTSYS) as u64 {
+        tf.rax = (-EINTR) as u64;
+    }
+
+    let frame = setup_rt_frame(ksig, tf);
+    let frame_addr = push_rt_frame(&frame, tf.rsp as usize - RED_ZONE_SIZE);
+
+    // The handler returns to the restorer of libc, which calls sigreturn.
+    // As on a call, rsp + 8 is 16-byte aligned on entry.
+    let mut stack = UserStack::new(frame_addr, frame_addr);
+    stack.push(&[ksig.action.restorer]);
+    let frame = unsafe { &(*(frame_addr as *const RTSigFrame)) };
+
+    assert!(ksig.action.handler != 0);
+    tf.rip = ksig.action.handler as u64;
+    tf.rsp = stack.get_sp() as u64;
+    tf.rdi = ksig.signo as u64;                         // signal number
+    tf.rsi = &frame.info as *const _ as u64;            // siginfo pointer
+    tf.rdx = &frame.uc as *const _ as u64;              // ucontext pointer
+    tf.rax = 0;     // no vector registers used for a variadic handler
+
+    signal_delivered(ksig);
+    info!("handle_signal signo {} frame {:#X} tf.rip {:#x}",
+          ksig.signo, frame_addr, tf.rip);
 }
@@ -6,23 +6,35 @@ extern crate alloc;
 
 mod arch;
 pub use arch::rt_sigreturn;
+#[cfg(target_arch = "x86_64")]
+pub use arch::SYSCALL_VECTOR;
 
 use core::mem;
-use alloc::sync::Arc;
-use taskctx::Tid;
-use task::{SigInfo, SigAction, SA_RESTORER, SA_RESTART};
-use axerrno::{linux_err, LinuxError, LinuxResult};
-use task::{SIGKILL, SIGSTOP, TaskStruct};
+use alloc::vec::Vec;
+use taskctx::{Tid, TaskState};
+use task::{SigInfo, SigAction, SA_NODEFER, NSIG};
+#[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
+use task::SA_RESTORER;
+use axerrno::{linux_err, linux_err_from, LinuxError, LinuxResult};
+use task::{SIGKILL, SIGSTOP, SIGCONT, TaskStruct};
+use task::{SIGCHLD, SIGURG, SIGWINCH, SIGTSTP, SIGTTIN, SIGTTOU};
+use task::{SIGQUIT, SIGILL, SIGTRAP, SIGABRT, SIGBUS, SIGFPE, SIGSEGV};
+use task::{SIGXCPU, SIGXFSZ, SIGSYS};
 use axhal::arch::TrapFrame;
 use core::sync::atomic::Ordering;
 use taskctx::TIF_SIGPENDING;
 use taskctx::{_TIF_SIGPENDING, _TIF_NOTIFY_SIGNAL};
-use axtype::ffz;
+use axtype::{ffz, align_down};
+use axtype::{RLIMIT_CPU, RLIM_INFINITY};
+use user_stack::UserStack;
 
 const SIG_DFL: usize = 0;   // default signal handling
-//const SIG_IGN: usize = 1;   // ignore signal
+const SIG_IGN: usize = 1;   // ignore signal
 //const SIG_ERR: usize = -1;  // error return from signal
 
+/// Set in the exit code of a task killed by a signal that has dumped core.
+const WCOREFLAG: usize = 0x80;
+
 const SIG_BLOCK:    usize = 0; // for blocking signals
 const SIG_UNBLOCK:  usize = 1; // for unblocking signals
 const SIG_SETMASK:  usize = 2; // for setting the signal mask
@@ -33,7 +45,8 @@ const SIG_SETMASK:  usize = 2; // for setting the signal mask
 // sent by kill, sigsend, raise
 const SI_USER: usize = 0;
 
-#[derive(Clone)]
+#[repr(C)]
+#[derive(Clone, Copy)]
 struct UContext {
     _flags: usize,
     _stack: usize,
@@ -42,7 +55,7 @@ struct UContext {
 }
 
 #[repr(C)]
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 struct RTSigFrame {
     info: SigInfo,
     uc: UContext,
@@ -53,11 +66,34 @@ pub const SIGFRAME_SIZE: usize = core::mem::size_of::<RTSigFrame>();
 
 struct KSignal {
     action: SigAction,
-    _info: SigInfo,
+    info: SigInfo,
     signo: usize,
 }
 
-//#define SI_KERNEL   0x80        /* sent by the kernel from somewhere */
+/// What a signal does when its handler is SIG_DFL.
+enum DefaultAction {
+    Terminate,
+    CoreDump,
+    Ignore,
+    Stop,
+}
+
+fn default_action(signo: usize) -> DefaultAction {
+    match signo {
+        SIGCHLD | SIGURG | SIGWINCH | SIGCONT => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE |
+        SIGSEGV | SIGXCPU | SIGXFSZ | SIGSYS => DefaultAction::CoreDump,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+fn is_stop_signal(signo: usize) -> bool {
+    matches!(default_action(signo), DefaultAction::Stop)
+}
+
+// sent by the kernel from somewhere
+const SI_KERNEL: usize = 0x80;
 //#define SI_QUEUE    -1      /* sent by sigqueue */
 //#define SI_TIMER    -2      /* sent by timer expiration */
 //#define SI_MESGQ    -3      /* sent by real time mesq state change */
@@ -70,12 +106,59 @@ struct KSignal {
 //#define SI_FROMUSER(siptr)  ((siptr)->si_code <= 0)
 //#define SI_FROMKERNEL(siptr)    ((siptr)->si_code > 0)
 
-pub fn kill(tid: Tid, sig: usize) -> usize {
-    debug!("kill tid {} sig {}", tid, sig);
-    assert!(tid > 0);
-    let info = prepare_kill_siginfo(sig, tid);
-    kill_proc_info(sig, info, tid).unwrap();
-    0
+/// Sends `sig` to the process `pid` if it's positive, to the process group
+/// `-pid` if it's below -1, to the own process group if it's 0, or to every
+/// process but init and the caller if it's -1. Pids are the ones of the
+/// pid namespace of the caller, which sees no process outside of it.
+pub fn kill(pid: usize, sig: usize) -> usize {
+    let pid = pid as isize;
+    debug!("kill pid {} sig {}", pid, sig);
+    if sig > NSIG {
+        return linux_err!(EINVAL);
+    }
+    let ret = if pid > 0 {
+        match task::find_vpid(pid as usize) {
+            Some(tid) => kill_proc_info(sig, prepare_kill_siginfo(sig, tid), tid),
+            None => Err(LinuxError::ESRCH),
+        }
+    } else if pid == -1 {
+        let current = task::current().tgid();
+        let pids: Vec<Tid> = task::all_tasks()
+            .iter()
+            .filter(|t| t.is_group_leader() && t.tid() != current)
+            .filter(|t| !matches!(task::pid_vnr(t.tid()), 0 | 1))
+            .map(|t| t.tid())
+            .collect();
+        kill_pids(&pids, sig)
+    } else if pid == 0 {
+        kill_pgrp(task::current().pgid(), sig)
+    } else {
+        match task::find_vpid((-pid) as usize) {
+            Some(pgid) => kill_pgrp(pgid, sig),
+            None => Err(LinuxError::ESRCH),
+        }
+    };
+    match ret {
+        Ok(()) => 0,
+        Err(e) => linux_err_from!(e),
+    }
+}
+
+/// Sends `sig` to each process of the group `pgid`, e.g. SIGINT from the
+/// tty to its foreground group.
+pub fn kill_pgrp(pgid: usize, sig: usize) -> LinuxResult {
+    let pids: Vec<Tid> = sys::pgrp_members(pgid).map(|t| t.tid()).collect();
+    kill_pids(&pids, sig)
+}
+
+fn kill_pids(pids: &[Tid], sig: usize) -> LinuxResult {
+    if pids.is_empty() {
+        return Err(LinuxError::ESRCH);
+    }
+    for &pid in pids {
+        kill_proc_info(sig, prepare_kill_siginfo(sig, pid), pid)?;
+    }
+    Ok(())
 }
 
 pub fn prepare_kill_siginfo(sig: usize, tid: Tid) -> SigInfo {
@@ -104,16 +187,27 @@ fn do_send_sig_info(sig: usize, info: SigInfo, tid: Tid) -> LinuxResult {
         warn!("No task [{:#x}].", tid);
         return Ok(());
     };
-    let mut pending = task.sigpending.lock();
-    pending.list.push(info);
-    sigaddset(&mut pending.signal, sig);
-    signal_wake_up(task.clone());
+    prepare_signal(sig, &task);
+    task.send_sig_info(info);
     debug!("do_send_sig_info tid {:#x} sig {} ok!", tid, sig);
     Ok(())
 }
 
-fn signal_wake_up(task: Arc<TaskStruct>) {
-    task.sched_info.set_tsk_thread_flag(TIF_SIGPENDING)
+/// A stop signal cancels a pending SIGCONT, and vice versa.
+fn prepare_signal(sig: usize, task: &TaskStruct) {
+    let mut pending = task.sigpending.lock();
+    if is_stop_signal(sig) {
+        flush_sigqueue_mask(&mut pending, sigmask(SIGCONT));
+    } else if sig == SIGCONT {
+        flush_sigqueue_mask(&mut pending,
+            sigmask(SIGSTOP) | sigmask(SIGTSTP) | sigmask(SIGTTIN) | sigmask(SIGTTOU));
+    }
+}
+
+/// Removes the pending signals in `mask`.
+fn flush_sigqueue_mask(pending: &mut task::SigPending, mask: u64) {
+    pending.list.retain(|info| (sigmask(info.signo as usize) & mask) == 0);
+    sigdelsetmask(&mut pending.signal, mask);
 }
 
 #[inline]
@@ -126,6 +220,11 @@ fn sigaddset(set: &mut u64, signo: usize) {
     *set |= 1 << (signo - 1);
 }
 
+#[inline]
+fn sigdelset(set: &mut u64, signo: usize) {
+    *set &= !(1 << (signo - 1));
+}
+
 #[inline]
 fn sigdelsetmask(set: &mut u64, mask: u64) {
     *set &= !mask;
@@ -138,12 +237,17 @@ fn sigorsets(rset: &mut u64, set1: u64, set2: u64) {
 
 #[inline]
 fn sigandnsets(rset: &mut u64, set1: u64, set2: u64) {
-    *rset = set1 & set2;
+    *rset = set1 & !set2;
 }
 
 pub fn rt_sigaction(sig: usize, act: usize, oact: usize, sigsetsize: usize) -> usize {
-    assert_eq!(sigsetsize, 8);
     debug!("rt_sigaction: sig {} act {:#X} oact {:#X}", sig, act, oact);
+    if sigsetsize != mem::size_of::<u64>() {
+        return linux_err!(EINVAL);
+    }
+    if sig == 0 || sig > NSIG || (act != 0 && (sig == SIGKILL || sig == SIGSTOP)) {
+        return linux_err!(EINVAL);
+    }
 
     let task = task::current();
 
@@ -157,19 +261,36 @@ pub fn rt_sigaction(sig: usize, act: usize, oact: usize, sigsetsize: usize) -> u
     if act != 0 {
         let act = unsafe { &(*(act as *const SigAction)) };
         info!("act: {:#X} {:#X} {:#X}", act.handler, act.flags, act.mask);
+        // Handlers return by sa_restorer of libc on x86_64, while riscv64
+        // has no restorer, and aarch64 may have one.
+        #[cfg(target_arch = "x86_64")]
+        assert!((act.flags & SA_RESTORER) != 0 || act.handler <= SIG_IGN);
+        #[cfg(target_arch = "riscv64")]
         assert!((act.flags & SA_RESTORER) == 0);
 
         let mut kact = act.clone();
         sigdelsetmask(&mut kact.mask, sigmask(SIGKILL) | sigmask(SIGSTOP));
         debug!("get_signal signo {} handler {:#X}", sig, kact.handler);
         task.sighand.lock().action[sig - 1] = kact;
+
+        // POSIX: setting an action to ignore discards the pending signal.
+        if sig_handler_ignored(kact.handler, sig) {
+            flush_sigqueue_mask(&mut task.sigpending.lock(), sigmask(sig));
+        }
     }
     0
 }
 
+fn sig_handler_ignored(handler: usize, sig: usize) -> bool {
+    handler == SIG_IGN ||
+        (handler == SIG_DFL && matches!(default_action(sig), DefaultAction::Ignore))
+}
+
 pub fn do_signal(tf: &mut TrapFrame, cause: usize) {
     debug!("do_signal ...");
 
+    check_cpu_limit();
+
     {
         let thread_info_flags = taskctx::current_ctx().flags.load(Ordering::Relaxed);
         if (thread_info_flags & (_TIF_SIGPENDING | _TIF_NOTIFY_SIGNAL)) == 0 {
@@ -183,58 +304,170 @@ pub fn do_signal(tf: &mut TrapFrame, cause: usize) {
         return;
     }
 
-    // Todo: handle 'regs->cause == EXC_SYSCALL';
+    // No handler to run: an interrupted syscall returns -EINTR or restarts.
+    arch::restart_syscall(tf, cause);
+}
+
+/// Raises SIGXCPU once the process has used up the soft RLIMIT_CPU, and
+/// again each second after, up to SIGKILL at the hard limit.
+fn check_cpu_limit() {
+    const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+    let task = task::current();
+    let limit = task.rlim.lock()[RLIMIT_CPU];
+    if limit.rlim_cur == RLIM_INFINITY {
+        return;
+    }
+    let secs = task.thread_group().into_iter()
+        .filter_map(task::get_task)
+        .map(|t| t.sched_info.utime_ns() + t.sched_info.stime_ns())
+        .sum::<u64>() / NSEC_PER_SEC;
+    if secs >= limit.rlim_max {
+        force_sig_fault(task.tid(), SIGKILL, SI_KERNEL, 0);
+    } else if secs >= limit.rlim_cur {
+        force_sig_fault(task.tid(), SIGXCPU, SI_KERNEL, 0);
+        task.rlim.lock()[RLIMIT_CPU].rlim_cur = (secs + 1).min(limit.rlim_max);
+    }
 }
 
 fn get_signal() -> Option<KSignal> {
     let task = task::current();
+    loop {
+        let Some(mut info) = dequeue_signal(&task) else {
+            recalc_sigpending();
+            return None;
+        };
+        if ptrace::is_traced(&task) && info.signo as usize != SIGKILL {
+            // The tracer sees it first, and may cancel or change it.
+            let Some(new_info) = ptrace::ptrace_signal(info) else {
+                continue;
+            };
+            if (task.blocked.load(Ordering::Relaxed) & sigmask(new_info.signo as usize)) != 0 {
+                task.send_sig_info(new_info);
+                continue;
+            }
+            info = new_info;
+        }
+        let signo = info.signo as usize;
+        let action = task.sighand.lock().action[signo - 1];
+        if action.handler == SIG_IGN {
+            continue;
+        }
+        if action.handler != SIG_DFL {
+            debug!("get_signal signo {} handler {:#X}", signo, action.handler);
+            return Some(KSignal {action, info, signo});
+        }
+
+        match default_action(signo) {
+            DefaultAction::Ignore => continue,
+            // A tracee stops for its tracer, which resumes it.
+            DefaultAction::Stop if ptrace::is_traced(&task) => ptrace::ptrace_group_stop(signo),
+            DefaultAction::Stop => do_signal_stop(&task, signo),
+            DefaultAction::Terminate => do_group_exit(&task, signo),
+            DefaultAction::CoreDump => {
+                let core = if coredump::do_coredump(&info) { WCOREFLAG } else { 0 };
+                do_group_exit(&task, signo | core)
+            }
+        }
+    }
+}
+
+/// Takes the first pending signal that isn't blocked.
+fn dequeue_signal(task: &TaskStruct) -> Option<SigInfo> {
     let blocked = task.blocked.load(Ordering::Relaxed);
     let mut sigpending = task.sigpending.lock();
     let signo = next_signal(sigpending.signal, blocked)?;
-    let (idx, _) = sigpending.list.iter().enumerate().find(|(_, &ref item)| {
-        item.signo == signo as i32
-    })?;
-    debug!("next_signal: index {}, signo {}", idx, signo);
-
-    let _info = sigpending.list.remove(idx);
-    assert_eq!(signo, _info.signo as usize);
-
-    let action = task.sighand.lock().action[signo - 1];
-    if action.handler != SIG_DFL {
-        debug!("get_signal signo {} handler {:#X}", signo, action.handler);
-        return Some(KSignal {action, _info, signo});
+    let idx = sigpending.list.iter().position(|item| item.signo == signo as i32);
+    debug!("next_signal: index {:?}, signo {}", idx, signo);
+
+    let info = idx.map(|idx| sigpending.list.remove(idx));
+    if !sigpending.list.iter().any(|item| item.signo == signo as i32) {
+        sigdelset(&mut sigpending.signal, signo);
     }
+    info
+}
 
-    let leader = if let Some(leader) = &task.sched_info.group_leader {
-        force_sig_fault(leader.tid(), signo, 0, 0);
-        leader
-    } else {
-        &task.sched_info
+/// Stops the current task by `signo` until it gets SIGCONT or SIGKILL.
+/// The parent can wait for both the stop and the continue.
+fn do_signal_stop(task: &TaskStruct, signo: usize) {
+    info!("task {} stopped", task.tid());
+    sys::do_notify_parent_cldstop(signo);
+    let resume = sigmask(SIGCONT) | sigmask(SIGKILL);
+    let pending = loop {
+        let pending = task.sigpending.lock().signal & resume;
+        if pending != 0 {
+            break pending;
+        }
+        run_queue::block_current(TaskState::Interruptible);
     };
+    if (pending & sigmask(SIGCONT)) != 0 {
+        info!("task {} continued", task.tid());
+        sys::do_notify_parent_cldstop(0);
+    }
+}
 
-    for tid in leader.siblings.lock().iter() {
-        if *tid == task.tid() {
-            continue;
+/// Kills the whole thread group of the current task, which exits with
+/// `exit_code`.
+fn do_group_exit(task: &TaskStruct, exit_code: usize) -> ! {
+    for tid in task.thread_group() {
+        if tid != task.tid() {
+            force_sig_fault(tid, SIGKILL, 0, 0);
         }
-        force_sig_fault(*tid, signo, 0, 0);
     }
+    sys::do_group_exit(exit_code as u32)
+}
 
-    sys::do_group_exit(signo as u32)
+/// Keeps TIF_SIGPENDING only if a signal that isn't blocked is pending.
+fn recalc_sigpending() {
+    let task = task::current();
+    let blocked = task.blocked.load(Ordering::Relaxed);
+    let sigpending = task.sigpending.lock();
+    if (sigpending.signal & !blocked) == 0 {
+        debug!("recalc_sigpending clear_tsk_thread_flag");
+        task.sched_info.clear_tsk_thread_flag(TIF_SIGPENDING);
+    } else {
+        task.sched_info.set_tsk_thread_flag(TIF_SIGPENDING);
+    }
 }
 
 fn next_signal(mut sigset: u64, blocked: u64) -> Option<usize> {
     sigdelsetmask(&mut sigset, blocked);
+    // SIGKILL goes first, so that a stopped task can be killed.
+    if (sigset & sigmask(SIGKILL)) != 0 {
+        return Some(SIGKILL);
+    }
     Some(ffz(sigset)? + 1)
 }
 
 fn restore_sigcontext(tf: &mut TrapFrame, frame: &RTSigFrame) {
-    *tf = frame.uc.mcontext.clone();
+    *tf = frame.uc.mcontext;
     // Todo: Restore the floating-point state. */
 }
 
-fn setup_sigcontext(frame: &mut RTSigFrame, tf: &TrapFrame) {
-    frame.uc.mcontext = tf.clone();
-    // Todo: Save the floating-point state.
+/// Builds the frame a handler of `ksig` runs on, saving the interrupted
+/// context and the mask that sigreturn restores.
+fn setup_rt_frame(ksig: &KSignal, tf: &TrapFrame) -> RTSigFrame {
+    RTSigFrame {
+        info: ksig.info,
+        uc: UContext {
+            _flags: 0,
+            _stack: 0,
+            _sigmask: task::current().blocked.load(Ordering::Relaxed) as usize,
+            mcontext: *tf,
+            // Todo: Save the floating-point state.
+        },
+        sigreturn_code: 0,
+    }
+}
+
+/// Pushes `frame` below the user stack pointer `sp`, and returns its
+/// address. The frame is aligned to 16 bytes.
+fn push_rt_frame(frame: &RTSigFrame, sp: usize) -> usize {
+    let frame_addr = align_down(sp - SIGFRAME_SIZE, 16);
+    let mut stack = UserStack::new(frame_addr + SIGFRAME_SIZE, frame_addr + SIGFRAME_SIZE);
+    stack.push(core::slice::from_ref(frame));
+    assert_eq!(stack.get_sp(), frame_addr);
+    frame_addr
 }
 
 pub fn force_sig_fault(tid: usize, signo: usize, code: usize, _addr: usize) {
@@ -275,6 +508,47 @@ pub fn rt_sigprocmask(how: usize, nset: usize, oset: usize, sigsetsize: usize) -
     0
 }
 
+///
+/// signal_delivered -
+/// @ksig:       kernel signal struct
+///
+/// This function should be called when a signal has successfully been
+/// delivered. It updates the blocked signals accordingly (@ksig->ka.sa.sa_mask
+/// is always blocked, and the signal itself is blocked unless %SA_NODEFER
+/// is set in @ksig->ka.sa.sa_flags.
+///
+fn signal_delivered(ksig: &KSignal) {
+    let mut blocked = 0;
+
+    /* A signal was successfully delivered, and the
+       saved sigmask was stored on the signal frame,
+       and will be restored by sigreturn.  So we can
+       simply clear the restore sigmask flag.  */
+    // Todo: handle clear_restore_sigmask
+    //clear_restore_sigmask();
+
+    sigorsets(&mut blocked,
+        task::current().blocked.load(Ordering::Relaxed),
+        ksig.action.mask);
+
+    if (ksig.action.flags & SA_NODEFER) == 0 {
+        sigaddset(&mut blocked, ksig.signo);
+    }
+    set_current_blocked(blocked);
+}
+
+/**
+ * set_current_blocked - change current->blocked mask
+ * @newset: new mask
+ *
+ * It is wrong to change ->blocked directly, this helper should be used
+ * to ensure the process can't miss a shared signal we are going to block.
+ */
+fn set_current_blocked(mut newset: u64) {
+    sigdelsetmask(&mut newset, sigmask(SIGKILL) | sigmask(SIGSTOP));
+    __set_current_blocked(newset);
+}
+
 //
 // This is also useful for kernel threads that want to temporarily
 // (or permanently) block certain signals.
@@ -310,5 +584,6 @@ fn __set_current_blocked(newset: u64) {
 
     //spin_lock_irq(&tsk->sighand->siglock);
     task::current().blocked.store(newset, Ordering::Relaxed);
+    recalc_sigpending();
     //spin_unlock_irq(&tsk->sighand->siglock);
 }
@@ -0,0 +1,370 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+
+use core::mem::size_of;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU16, Ordering};
+use axerrno::LinuxError;
+use socket::{AF_INET, AF_UNIX, SOCK_CLOEXEC, SOCK_DGRAM, SOCK_NONBLOCK, SOCK_STREAM};
+use socket::{MSG_DONTWAIT, MSG_NOSIGNAL, MSG_PEEK, SHUT_WR};
+use task::SIGPIPE;
+
+const F_GETFD: usize = 1;
+const FD_CLOEXEC: usize = 1;
+
+const SOL_SOCKET: i32 = 1;
+const SCM_RIGHTS: i32 = 1;
+
+const INADDR_LOOPBACK: u32 = 0x7f00_0001;
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_socket]: ...");
+
+    fork::init(cpu_id, dtb_pa);
+
+    test_socketpair();
+    test_unix_listener();
+    test_scm_rights();
+    test_tcp_loopback();
+    test_udp_loopback();
+
+    info!("[rt_socket]: ok!");
+    axhal::misc::terminate();
+}
+
+fn close(fd: usize) {
+    // The socket is released with its last reference.
+    drop(fileops::unregister_file(fd).unwrap());
+}
+
+fn send(fd: usize, buf: &[u8]) -> Result<usize, LinuxError> {
+    socket::sendto(fd, buf, 0, 0, 0)
+}
+
+fn recv(fd: usize, buf: &mut [u8], flags: usize) -> Result<usize, LinuxError> {
+    socket::recvfrom(fd, buf, flags, 0, 0)
+}
+
+/// A `sockaddr_un`, with a name in the abstract namespace.
+#[repr(C)]
+struct SockAddrUn {
+    family: u16,
+    path: [u8; 108],
+}
+
+impl SockAddrUn {
+    fn new(name: &[u8]) -> (Self, usize) {
+        let mut addr = Self { family: AF_UNIX as u16, path: [0; 108] };
+        addr.path[..name.len()].copy_from_slice(name);
+        (addr, size_of::<u16>() + name.len())
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SockAddrIn {
+    family: u16,
+    /// In network byte order, as is `addr`.
+    port: u16,
+    addr: u32,
+    zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    fn new(addr: u32, port: u16) -> Self {
+        Self {
+            family: AF_INET as u16,
+            port: port.to_be(),
+            addr: addr.to_be(),
+            zero: [0; 8],
+        }
+    }
+
+    fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+fn connect_in(fd: usize, addr: &SockAddrIn) -> Result<usize, LinuxError> {
+    socket::connect(fd, addr as *const _ as usize, size_of::<SockAddrIn>())
+}
+
+fn sockname_in(fd: usize) -> SockAddrIn {
+    let mut addr = SockAddrIn::default();
+    let mut len = size_of::<SockAddrIn>() as u32;
+    socket::getsockname(fd, &mut addr as *mut _ as usize, &mut len as *mut _ as usize).unwrap();
+    assert_eq!(len as usize, size_of::<SockAddrIn>());
+    addr
+}
+
+fn sigpipe_pending() -> bool {
+    task::current().sigpending.lock().signal & (1 << (SIGPIPE - 1)) != 0
+}
+
+/// A pair of UNIX stream sockets is connected both ways, through the file
+/// layer too. After a shutdown, the peer reads the end of the stream and
+/// sending fails with EPIPE, here without SIGPIPE.
+fn test_socketpair() {
+    assert_eq!(socket::socket(AF_UNIX, 3, 0), Err(LinuxError::ESOCKTNOSUPPORT));
+    assert_eq!(socket::socket(5, SOCK_STREAM, 0), Err(LinuxError::EAFNOSUPPORT));
+    let mut sv = [0i32; 2];
+    let sv_ptr = sv.as_mut_ptr() as usize;
+    assert_eq!(socket::socketpair(AF_INET, SOCK_STREAM, 0, sv_ptr), Err(LinuxError::EOPNOTSUPP));
+
+    assert_eq!(socket::socketpair(AF_UNIX, SOCK_STREAM, 0, sv_ptr), Ok(0));
+    let (a, b) = (sv[0] as usize, sv[1] as usize);
+    let mut buf = [0u8; 16];
+    assert_eq!(send(a, b"ping"), Ok(4));
+    assert_eq!(recv(b, &mut buf, MSG_PEEK), Ok(4));
+    assert_eq!(recv(b, &mut buf, 0), Ok(4));
+    assert_eq!(&buf[..4], b"ping");
+    assert_eq!(recv(b, &mut buf, MSG_DONTWAIT), Err(LinuxError::EAGAIN));
+
+    assert_eq!(fileops::write(b, b"pong"), Ok(4));
+    assert_eq!(fileops::read(a, &mut buf), Ok(4));
+    assert_eq!(&buf[..4], b"pong");
+
+    assert_eq!(socket::shutdown(a, SHUT_WR), Ok(0));
+    assert_eq!(recv(b, &mut buf, 0), Ok(0));
+    assert_eq!(socket::sendto(a, b"x", MSG_NOSIGNAL, 0, 0), Err(LinuxError::EPIPE));
+    assert!(!sigpipe_pending());
+    close(a);
+    close(b);
+    info!("[rt_socket]: socketpair ok!");
+}
+
+/// A listener with an abstract name queues connections up to its backlog
+/// till they're accepted, and the name is free again once it's closed.
+fn test_unix_listener() {
+    let (name, len) = SockAddrUn::new(b"\0rt_socket");
+    let name_ptr = &name as *const _ as usize;
+    let srv = socket::socket(AF_UNIX, SOCK_STREAM, 0).unwrap();
+    // Not bound.
+    assert_eq!(socket::listen(srv, 1), Err(LinuxError::EINVAL));
+    assert_eq!(socket::bind(srv, name_ptr, len), Ok(0));
+    let other = socket::socket(AF_UNIX, SOCK_STREAM, 0).unwrap();
+    assert_eq!(socket::bind(other, name_ptr, len), Err(LinuxError::EADDRINUSE));
+    assert_eq!(socket::listen(srv, 1), Ok(0));
+
+    let c1 = socket::socket(AF_UNIX, SOCK_STREAM, 0).unwrap();
+    assert_eq!(socket::connect(c1, name_ptr, len), Ok(0));
+    let c2 = socket::socket(AF_UNIX, SOCK_STREAM | SOCK_NONBLOCK, 0).unwrap();
+    assert_eq!(socket::connect(c2, name_ptr, len), Err(LinuxError::EAGAIN));
+
+    // The client is unnamed.
+    let (mut peer, _) = SockAddrUn::new(b"");
+    let mut peer_len = size_of::<SockAddrUn>() as u32;
+    let peer_ptr = &mut peer as *mut _ as usize;
+    let s1 = socket::accept4(srv, peer_ptr, &mut peer_len as *mut _ as usize, SOCK_CLOEXEC).unwrap();
+    assert_eq!(peer_len as usize, size_of::<u16>());
+    assert_eq!(fileops::fcntl(s1, F_GETFD, 0), Ok(FD_CLOEXEC));
+    assert_eq!(socket::connect(c2, name_ptr, len), Ok(0));
+    let s2 = socket::accept4(srv, 0, 0, 0).unwrap();
+
+    let mut buf = [0u8; 16];
+    assert_eq!(send(c1, b"one"), Ok(3));
+    assert_eq!(send(c2, b"two"), Ok(3));
+    assert_eq!(recv(s2, &mut buf, 0), Ok(3));
+    assert_eq!(&buf[..3], b"two");
+    assert_eq!(recv(s1, &mut buf, 0), Ok(3));
+    assert_eq!(&buf[..3], b"one");
+    assert_eq!(send(s1, b"back"), Ok(4));
+    assert_eq!(recv(c1, &mut buf, 0), Ok(4));
+    assert_eq!(&buf[..4], b"back");
+
+    let (mut got, _) = SockAddrUn::new(b"");
+    let mut got_len = size_of::<SockAddrUn>() as u32;
+    let got_ptr = &mut got as *mut _ as usize;
+    assert_eq!(socket::getpeername(c1, got_ptr, &mut got_len as *mut _ as usize), Ok(0));
+    assert_eq!(got_len as usize, len);
+    assert_eq!(&got.path[..len - 2], b"\0rt_socket");
+
+    let (unknown, unknown_len) = SockAddrUn::new(b"\0rt_socket_none");
+    let c3 = socket::socket(AF_UNIX, SOCK_STREAM, 0).unwrap();
+    let unknown_ptr = &unknown as *const _ as usize;
+    assert_eq!(socket::connect(c3, unknown_ptr, unknown_len), Err(LinuxError::ECONNREFUSED));
+
+    close(srv);
+    assert_eq!(socket::bind(other, name_ptr, len), Ok(0));
+    for fd in [other, c1, c2, c3, s1, s2] {
+        close(fd);
+    }
+    info!("[rt_socket]: unix listener ok!");
+}
+
+#[repr(C)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+#[repr(C)]
+struct MsgHdr {
+    name: usize,
+    namelen: u32,
+    iov: usize,
+    iovlen: usize,
+    control: usize,
+    controllen: usize,
+    flags: i32,
+}
+
+/// A control message with a single fd.
+#[repr(C)]
+#[derive(Default)]
+struct CmsgFd {
+    len: usize,
+    level: i32,
+    ty: i32,
+    fd: i32,
+}
+
+const CMSG_FD_LEN: usize = 2 * size_of::<usize>() + size_of::<i32>();
+
+/// A file is passed over a UNIX datagram pair with SCM_RIGHTS, and the
+/// receiver gets a new fd of it, which is open after the sender closes its
+/// own.
+fn test_scm_rights() {
+    let mut sv = [0i32; 2];
+    assert_eq!(socket::socketpair(AF_UNIX, SOCK_DGRAM, 0, sv.as_mut_ptr() as usize), Ok(0));
+    let (a, b) = (sv[0] as usize, sv[1] as usize);
+    let mut fds = [0i32; 2];
+    fileops::pipe2(fds.as_mut_ptr() as usize, 0).unwrap();
+    let (rfd, wfd) = (fds[0] as usize, fds[1] as usize);
+
+    let data = *b"fd";
+    let iov = IoVec { base: data.as_ptr() as usize, len: data.len() };
+    let cmsg = CmsgFd { len: CMSG_FD_LEN, level: SOL_SOCKET, ty: SCM_RIGHTS, fd: rfd as i32 };
+    let msg = MsgHdr {
+        name: 0,
+        namelen: 0,
+        iov: &iov as *const _ as usize,
+        iovlen: 1,
+        control: &cmsg as *const _ as usize,
+        controllen: size_of::<CmsgFd>(),
+        flags: 0,
+    };
+    assert_eq!(socket::sendmsg(a, &msg as *const _ as usize, 0), Ok(2));
+    close(rfd);
+
+    let mut buf = [0u8; 8];
+    let iov = IoVec { base: buf.as_mut_ptr() as usize, len: buf.len() };
+    let mut cmsg = CmsgFd::default();
+    let mut msg = MsgHdr {
+        name: 0,
+        namelen: 0,
+        iov: &iov as *const _ as usize,
+        iovlen: 1,
+        control: &mut cmsg as *mut _ as usize,
+        controllen: size_of::<CmsgFd>(),
+        flags: 0,
+    };
+    assert_eq!(socket::recvmsg(b, &mut msg as *mut _ as usize, 0), Ok(2));
+    assert_eq!(&buf[..2], b"fd");
+    assert_eq!(msg.flags, 0);
+    assert_eq!((cmsg.len, cmsg.level, cmsg.ty), (CMSG_FD_LEN, SOL_SOCKET, SCM_RIGHTS));
+
+    let passed = cmsg.fd as usize;
+    assert_eq!(fileops::write(wfd, b"through"), Ok(7));
+    assert_eq!(fileops::read(passed, &mut buf), Ok(7));
+    assert_eq!(&buf[..7], b"through");
+    for fd in [passed, wfd] {
+        close(fd);
+    }
+
+    // The other end of the pair, with no name, is gone.
+    close(b);
+    assert_eq!(send(a, b"x"), Err(LinuxError::ECONNREFUSED));
+    close(a);
+    info!("[rt_socket]: SCM_RIGHTS ok!");
+}
+
+static TCP_PORT: AtomicU16 = AtomicU16::new(0);
+
+/// TCP over the loopback: a client, in another task, connects to a
+/// listener bound to an ephemeral port, which accept() waits for.
+fn test_tcp_loopback() {
+    let srv = socket::socket(AF_INET, SOCK_STREAM, 0).unwrap();
+    let any = SockAddrIn::new(0, 0);
+    assert_eq!(socket::bind(srv, &any as *const _ as usize, size_of::<SockAddrIn>()), Ok(0));
+    let port = sockname_in(srv).port();
+    assert_ne!(port, 0);
+    assert_eq!(socket::listen(srv, 8), Ok(0));
+    TCP_PORT.store(port, Ordering::Release);
+
+    fork::kernel_thread(|| {
+        let fd = socket::socket(AF_INET, SOCK_STREAM, 0).unwrap();
+        let port = TCP_PORT.load(Ordering::Acquire);
+        assert_eq!(connect_in(fd, &SockAddrIn::new(INADDR_LOOPBACK, port)), Ok(0));
+        assert_eq!(send(fd, b"hello"), Ok(5));
+        let mut buf = [0u8; 8];
+        assert_eq!(recv(fd, &mut buf, 0), Ok(3));
+        assert_eq!(&buf[..3], b"bye");
+        close(fd);
+        run_queue::exit_current(0);
+    }, None);
+
+    let mut peer = SockAddrIn::default();
+    let mut peer_len = size_of::<SockAddrIn>() as u32;
+    let conn = socket::accept4(
+        srv, &mut peer as *mut _ as usize, &mut peer_len as *mut _ as usize, 0
+    ).unwrap();
+    assert_eq!(u32::from_be(peer.addr), INADDR_LOOPBACK);
+    assert_ne!(peer.port(), 0);
+
+    let mut buf = [0u8; 8];
+    assert_eq!(recv(conn, &mut buf, 0), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(send(conn, b"bye"), Ok(3));
+    // Till the client has closed.
+    assert_eq!(recv(conn, &mut buf, 0), Ok(0));
+    assert_eq!(socket::sendto(conn, b"x", MSG_NOSIGNAL, 0, 0), Err(LinuxError::EPIPE));
+    close(conn);
+
+    let fd = socket::socket(AF_INET, SOCK_STREAM, 0).unwrap();
+    assert_eq!(connect_in(fd, &SockAddrIn::new(INADDR_LOOPBACK, 1)), Err(LinuxError::ECONNREFUSED));
+    close(fd);
+    close(srv);
+    info!("[rt_socket]: tcp loopback ok!");
+}
+
+/// UDP over the loopback: datagrams come with their source, what doesn't
+/// fit into the buffer is lost, and sending to a port nobody has succeeds.
+fn test_udp_loopback() {
+    let a = socket::socket(AF_INET, SOCK_DGRAM, 0).unwrap();
+    let b = socket::socket(AF_INET, SOCK_DGRAM, 0).unwrap();
+    let loopback = SockAddrIn::new(INADDR_LOOPBACK, 0);
+    assert_eq!(socket::bind(b, &loopback as *const _ as usize, size_of::<SockAddrIn>()), Ok(0));
+    let to = sockname_in(b);
+
+    let addrlen = size_of::<SockAddrIn>();
+    assert_eq!(socket::sendto(a, b"datagram", 0, &to as *const _ as usize, addrlen), Ok(8));
+    let mut from = SockAddrIn::default();
+    let mut from_len = addrlen as u32;
+    let mut buf = [0u8; 4];
+    let from_ptr = &mut from as *mut _ as usize;
+    assert_eq!(socket::recvfrom(b, &mut buf, 0, from_ptr, &mut from_len as *mut _ as usize), Ok(4));
+    assert_eq!(&buf, b"data");
+    assert_eq!(u32::from_be(from.addr), INADDR_LOOPBACK);
+    // Bound when it sent.
+    assert_eq!(from.port(), sockname_in(a).port());
+    assert_eq!(recv(b, &mut buf, MSG_DONTWAIT), Err(LinuxError::EAGAIN));
+
+    let nobody = SockAddrIn::new(INADDR_LOOPBACK, 1);
+    assert_eq!(socket::sendto(a, b"lost", 0, &nobody as *const _ as usize, addrlen), Ok(4));
+    close(a);
+    close(b);
+    info!("[rt_socket]: udp loopback ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
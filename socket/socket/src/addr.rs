@@ -0,0 +1,136 @@
+//! Socket addresses, as `sockaddr_un` and `sockaddr_in` from user space.
+
+use core::cmp::min;
+use core::mem::size_of;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::{AF_UNIX, AF_INET};
+
+const UNIX_PATH_MAX: usize = 108;
+
+pub const INADDR_ANY: u32 = 0;
+pub const INADDR_LOOPBACK: u32 = 0x7f00_0001;
+
+#[repr(C)]
+struct SockAddrIn {
+    sin_family: u16,
+    /// In network byte order, as is `sin_addr`.
+    sin_port: u16,
+    sin_addr: u32,
+    sin_zero: [u8; 8],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SockAddr {
+    /// A path, or a name in the abstract namespace if it starts with '\0'.
+    /// An unnamed socket has an empty one.
+    Unix(Vec<u8>),
+    /// An IPv4 address and port, both in host byte order.
+    Inet(u32, u16),
+}
+
+impl SockAddr {
+    /// Reads the address of `len` bytes at `addr` in user space.
+    pub fn from_user(addr: usize, len: usize) -> LinuxResult<Self> {
+        if addr == 0 || len < size_of::<u16>() {
+            return Err(LinuxError::EINVAL);
+        }
+        let family = unsafe { *(addr as *const u16) } as usize;
+        match family {
+            AF_UNIX => {
+                if len > size_of::<u16>() + UNIX_PATH_MAX {
+                    return Err(LinuxError::EINVAL);
+                }
+                let path = unsafe {
+                    core::slice::from_raw_parts((addr + 2) as *const u8, len - 2)
+                };
+                if path.first() == Some(&0) {
+                    return Ok(SockAddr::Unix(path.to_vec()));
+                }
+                let end = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+                Ok(SockAddr::Unix(path[..end].to_vec()))
+            },
+            AF_INET => {
+                if len < size_of::<SockAddrIn>() {
+                    return Err(LinuxError::EINVAL);
+                }
+                let sin = unsafe { &*(addr as *const SockAddrIn) };
+                Ok(SockAddr::Inet(u32::from_be(sin.sin_addr), u16::from_be(sin.sin_port)))
+            },
+            _ => Err(LinuxError::EAFNOSUPPORT),
+        }
+    }
+
+    /// Writes the address to `addr` in user space, which has room for as
+    /// many bytes as `*addrlen` says, and sets `*addrlen` to its full size.
+    pub fn to_user(&self, addr: usize, addrlen: usize) -> LinuxResult {
+        if addr == 0 {
+            return Ok(());
+        }
+        if addrlen == 0 {
+            return Err(LinuxError::EFAULT);
+        }
+        let addrlen = unsafe { &mut *(addrlen as *mut u32) };
+        let mut bytes = Vec::new();
+        match self {
+            SockAddr::Unix(path) => {
+                bytes.extend_from_slice(&(AF_UNIX as u16).to_ne_bytes());
+                bytes.extend_from_slice(path);
+                if !path.is_empty() && path[0] != 0 {
+                    bytes.push(0);
+                }
+            },
+            SockAddr::Inet(ip, port) => {
+                let sin = SockAddrIn {
+                    sin_family: AF_INET as u16,
+                    sin_port: port.to_be(),
+                    sin_addr: ip.to_be(),
+                    sin_zero: [0; 8],
+                };
+                let raw = unsafe {
+                    core::slice::from_raw_parts(
+                        &sin as *const SockAddrIn as *const u8,
+                        size_of::<SockAddrIn>()
+                    )
+                };
+                bytes.extend_from_slice(raw);
+            },
+        }
+        let size = min(*addrlen as usize, bytes.len());
+        let dst = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, size) };
+        dst.copy_from_slice(&bytes[..size]);
+        *addrlen = bytes.len() as u32;
+        Ok(())
+    }
+
+    /// The address of a socket of `domain` not bound yet.
+    pub fn unspecified(domain: usize) -> Self {
+        if domain == AF_UNIX {
+            SockAddr::Unix(Vec::new())
+        } else {
+            SockAddr::Inet(INADDR_ANY, 0)
+        }
+    }
+
+    /// The address as seen from another socket, which is the loopback one
+    /// for INADDR_ANY, as that is all this host has.
+    pub fn resolved(&self) -> Self {
+        match self {
+            SockAddr::Inet(INADDR_ANY, port) => SockAddr::Inet(INADDR_LOOPBACK, *port),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn family(&self) -> usize {
+        match self {
+            SockAddr::Unix(_) => AF_UNIX,
+            SockAddr::Inet(..) => AF_INET,
+        }
+    }
+}
+
+/// Whether `ip` is on this host, which only has the loopback interface.
+pub fn is_local(ip: u32) -> bool {
+    ip == INADDR_ANY || (ip >> 24) == 127
+}
@@ -0,0 +1,206 @@
+//! Datagram sockets: SOCK_DGRAM of AF_UNIX, and UDP over the loopback.
+//!
+//! A datagram goes straight into the queue of the socket it is sent to.
+//! UNIX senders wait for room there, while UDP ones have it dropped, as a
+//! congested network would.
+
+use core::cmp::min;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult, LinuxError};
+use axio::PollState;
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+use crate::addr::SockAddr;
+use crate::socket::Socket;
+use crate::{table, FileRef, AF_INET, SOCK_DGRAM};
+
+/// Bytes of datagrams a socket queues before senders wait or drop.
+pub const DGRAM_QUEUE_SIZE: usize = 64 * 1024;
+
+/// A datagram, with its source and the files passed along.
+struct Msg {
+    from: SockAddr,
+    data: Vec<u8>,
+    rights: Vec<FileRef>,
+}
+
+struct Queue {
+    msgs: VecDeque<Msg>,
+    bytes: usize,
+}
+
+pub(crate) struct Dgram {
+    queue: SpinNoIrq<Queue>,
+    /// Default destination, and the only source accepted once connected.
+    peer: SpinNoIrq<Option<SockAddr>>,
+    /// The other end of a socketpair(), which has no name to be found by.
+    pair: SpinNoIrq<Option<Weak<Socket>>>,
+    shut_rd: AtomicBool,
+    shut_wr: AtomicBool,
+    /// The receiver waiting for datagrams and senders waiting for room.
+    wait: WaitQueue,
+}
+
+impl Dgram {
+    pub fn new() -> Self {
+        Self {
+            queue: SpinNoIrq::new(Queue { msgs: VecDeque::new(), bytes: 0 }),
+            peer: SpinNoIrq::new(None),
+            pair: SpinNoIrq::new(None),
+            shut_rd: AtomicBool::new(false),
+            shut_wr: AtomicBool::new(false),
+            wait: WaitQueue::new(),
+        }
+    }
+
+    pub fn peer(&self) -> Option<SockAddr> {
+        self.peer.lock().clone()
+    }
+
+    pub fn connect(&self, addr: SockAddr) {
+        *self.peer.lock() = Some(addr);
+        *self.pair.lock() = None;
+    }
+
+    /// Connects it to `other`, the other end of a socketpair().
+    pub fn connect_pair(&self, other: &Arc<Socket>) {
+        *self.peer.lock() = Some(other.local_addr());
+        *self.pair.lock() = Some(Arc::downgrade(other));
+    }
+
+    fn has_room(&self, size: usize) -> bool {
+        self.shut_rd.load(Ordering::Relaxed)
+            || self.queue.lock().bytes + size <= DGRAM_QUEUE_SIZE
+    }
+
+    fn readable(&self) -> bool {
+        self.shut_rd.load(Ordering::Relaxed) || !self.queue.lock().msgs.is_empty()
+    }
+
+    /// Sends `buf` from `sock`, which this is the dgram of, as a datagram
+    /// to `dest` or else to the peer it is connected to.
+    pub fn send(
+        &self, sock: &Socket, buf: &[u8], dest: Option<SockAddr>,
+        rights: Vec<FileRef>, nonblock: bool
+    ) -> AxResult<usize> {
+        let pair = match dest {
+            Some(_) => None,
+            None => self.pair.lock().clone(),
+        };
+        let dest = match dest {
+            Some(dest) => dest,
+            None => self.peer().ok_or(AxError::NotConnected)?,
+        };
+        if self.shut_wr.load(Ordering::Relaxed) {
+            return Err(AxError::BrokenPipe);
+        }
+        if buf.len() > DGRAM_QUEUE_SIZE {
+            return Err(AxError::InvalidInput);
+        }
+        let udp = sock.domain() == AF_INET;
+        let from = sock.autobind().map_err(|_| AxError::AddrInUse)?.resolved();
+        let lookup = match pair {
+            Some(pair) => pair.upgrade().ok_or(LinuxError::ECONNREFUSED),
+            None => table::lookup(&dest, SOCK_DGRAM),
+        };
+        let target = match lookup {
+            Ok(target) => target,
+            // Nobody listens on the port, which UDP does not tell.
+            Err(_) if udp => return Ok(buf.len()),
+            Err(LinuxError::ENOENT) => return Err(AxError::NotFound),
+            Err(_) => return Err(AxError::ConnectionRefused),
+        };
+        let to = target.dgram().ok_or(AxError::ConnectionRefused)?;
+        if to.peer().is_some_and(|peer| peer != from) {
+            return Err(AxError::NoPermission);
+        }
+        let mut rights = Some(rights);
+        loop {
+            if to.shut_rd.load(Ordering::Relaxed) {
+                return if udp { Ok(buf.len()) } else { Err(AxError::ConnectionRefused) };
+            }
+            {
+                let mut queue = to.queue.lock();
+                if queue.bytes + buf.len() <= DGRAM_QUEUE_SIZE {
+                    queue.bytes += buf.len();
+                    queue.msgs.push_back(Msg {
+                        from: from.clone(),
+                        data: buf.to_vec(),
+                        rights: rights.take().unwrap_or_default(),
+                    });
+                    drop(queue);
+                    to.wait.notify_all(false);
+                    return Ok(buf.len());
+                }
+            }
+            if udp {
+                return Ok(buf.len());
+            }
+            if nonblock {
+                return Err(AxError::WouldBlock);
+            }
+            to.wait.wait_until_interruptible(|| to.has_room(buf.len()))?;
+        }
+    }
+
+    /// Receives a datagram with its source and the files passed along.
+    /// What does not fit into `buf` is lost.
+    pub fn recv(&self, buf: &mut [u8], peek: bool, nonblock: bool) -> AxResult<(usize, Option<SockAddr>, Vec<FileRef>)> {
+        loop {
+            {
+                let mut queue = self.queue.lock();
+                if let Some(msg) = queue.msgs.front() {
+                    let size = min(buf.len(), msg.data.len());
+                    buf[..size].copy_from_slice(&msg.data[..size]);
+                    let from = msg.from.clone();
+                    if peek {
+                        return Ok((size, Some(from), msg.rights.clone()));
+                    }
+                    let mut rights = Vec::new();
+                    if let Some(msg) = queue.msgs.pop_front() {
+                        queue.bytes -= msg.data.len();
+                        rights = msg.rights;
+                    }
+                    drop(queue);
+                    self.wait.notify_all(false);
+                    return Ok((size, Some(from), rights));
+                }
+            }
+            if self.shut_rd.load(Ordering::Relaxed) {
+                return Ok((0, None, Vec::new()));
+            }
+            if nonblock {
+                return Err(AxError::WouldBlock);
+            }
+            self.wait.wait_until_interruptible(|| self.readable())?;
+        }
+    }
+
+    pub fn shutdown(&self, read: bool, write: bool) {
+        if read {
+            self.shut_rd.store(true, Ordering::Relaxed);
+        }
+        if write {
+            self.shut_wr.store(true, Ordering::Relaxed);
+        }
+        self.wait.notify_all(false);
+    }
+
+    pub fn poll(&self) -> PollState {
+        PollState {
+            readable: self.readable(),
+            writable: !self.shut_wr.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn release(&self) {
+        self.shutdown(true, true);
+        let mut queue = self.queue.lock();
+        queue.msgs.clear();
+        queue.bytes = 0;
+    }
+}
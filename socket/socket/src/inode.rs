@@ -0,0 +1,69 @@
+//! Socket files, which binding a UNIX socket to a path creates.
+//!
+//! The file is only a name for the socket: it stays when the socket is
+//! closed, after which connecting to it is refused, and it cannot be
+//! opened. Like a FIFO, it is linked into ramfs or devfs, which hold
+//! nodes of any kind.
+
+use alloc::sync::{Arc, Weak};
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use axfs_vfs::alloc_ino;
+
+use crate::Socket;
+
+pub(crate) struct SockInode {
+    sock: Weak<Socket>,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    ino: usize,
+}
+
+impl SockInode {
+    pub fn new(sock: &Arc<Socket>, mode: u16, uid: u32, gid: u32) -> Self {
+        Self {
+            sock: Arc::downgrade(sock),
+            mode,
+            uid,
+            gid,
+            ino: alloc_ino(),
+        }
+    }
+
+    /// Returns the socket file `node` is, if it is one.
+    pub fn from_node(node: &VfsNodeRef) -> Option<&Self> {
+        if !node.get_attr().is_ok_and(|attr| attr.file_type().is_socket()) {
+            return None;
+        }
+        node.as_any().downcast_ref::<Self>()
+    }
+
+    /// The socket bound to the file, unless it has been closed.
+    pub fn socket(&self) -> Option<Arc<Socket>> {
+        self.sock.upgrade()
+    }
+}
+
+impl VfsNodeOps for SockInode {
+    fn open(&self, _mode: i32) -> VfsResult {
+        Err(AxError::NoDevOrAddr)
+    }
+
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::set_mode(self.mode),
+            VfsNodeType::Socket,
+            0,
+            0,
+            self.uid,
+            self.gid,
+        ))
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
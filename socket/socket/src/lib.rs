@@ -0,0 +1,292 @@
+//! Sockets.
+//!
+//! A [`Socket`] is the node of the file a socket descriptor refers to, so
+//! read(), write(), poll and close() go through the file layer as for any
+//! other file. There are UNIX stream and datagram sockets, and TCP and UDP
+//! ones, which only reach this host itself over the loopback until there
+//! is a network device. UNIX sockets pass open files to each other with
+//! sendmsg() and recvmsg().
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod addr;
+mod table;
+mod socket;
+mod stream;
+mod dgram;
+mod inode;
+mod msg;
+
+pub use self::addr::SockAddr;
+pub use self::socket::Socket;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axfile::fops::File;
+use axtype::{O_NONBLOCK, O_CLOEXEC};
+use capability::Cap;
+use mutex::Mutex;
+use signal::force_sig_fault;
+
+use self::msg::MsgHdr;
+
+/// An open file, as passed over UNIX sockets.
+pub(crate) type FileRef = Arc<Mutex<File>>;
+
+pub const AF_UNIX: usize = 1;
+pub const AF_INET: usize = 2;
+
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+const SOCK_TYPE_MASK: usize = 0xf;
+pub const SOCK_NONBLOCK: usize = O_NONBLOCK as usize;
+pub const SOCK_CLOEXEC: usize = O_CLOEXEC as usize;
+
+const IPPROTO_IP: usize = 0;
+const IPPROTO_TCP: usize = 6;
+const IPPROTO_UDP: usize = 17;
+
+pub const SHUT_RD: usize = 0;
+pub const SHUT_WR: usize = 1;
+pub const SHUT_RDWR: usize = 2;
+
+pub const MSG_PEEK: usize = 0x2;
+pub const MSG_CTRUNC: usize = 0x8;
+pub const MSG_DONTWAIT: usize = 0x40;
+pub const MSG_NOSIGNAL: usize = 0x4000;
+pub const MSG_CMSG_CLOEXEC: usize = 0x4000_0000;
+
+const SOL_SOCKET: usize = 1;
+const SO_REUSEADDR: usize = 2;
+const SO_TYPE: usize = 3;
+const SO_ERROR: usize = 4;
+const SO_SNDBUF: usize = 7;
+const SO_RCVBUF: usize = 8;
+const SO_KEEPALIVE: usize = 9;
+const SO_ACCEPTCONN: usize = 30;
+const SO_DOMAIN: usize = 39;
+const SCM_RIGHTS: i32 = 1;
+const TCP_NODELAY: usize = 1;
+
+/// Longest backlog of a listening socket.
+const SOMAXCONN: usize = 4096;
+
+/// Checks the arguments of socket() and socketpair(), and returns the
+/// type with the flags split off.
+fn socket_type(domain: usize, ty: usize, protocol: usize) -> LinuxResult<(usize, usize)> {
+    let flags = ty & !SOCK_TYPE_MASK;
+    if (flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let ty = ty & SOCK_TYPE_MASK;
+    if ty != SOCK_STREAM && ty != SOCK_DGRAM {
+        return Err(LinuxError::ESOCKTNOSUPPORT);
+    }
+    match (domain, ty, protocol) {
+        (AF_UNIX, _, 0)
+        | (AF_UNIX, _, AF_UNIX)
+        | (AF_INET, _, IPPROTO_IP)
+        | (AF_INET, SOCK_STREAM, IPPROTO_TCP)
+        | (AF_INET, SOCK_DGRAM, IPPROTO_UDP) => Ok((ty, flags)),
+        (AF_UNIX | AF_INET, ..) => Err(LinuxError::EPROTONOSUPPORT),
+        _ => Err(LinuxError::EAFNOSUPPORT),
+    }
+}
+
+/// Puts `sock` into the file table.
+fn sock_install(sock: Arc<Socket>, flags: usize) -> LinuxResult<usize> {
+    let mut file = File::new(sock, Cap::READ | Cap::WRITE);
+    file.set_status_flags((flags & SOCK_NONBLOCK) as i32);
+    let fd = fileops::register_file(Ok(file), flags);
+    if (fd as isize) < 0 {
+        return Err(LinuxError::EMFILE);
+    }
+    Ok(fd)
+}
+
+fn sockfd_lookup(fd: usize) -> LinuxResult<Arc<Socket>> {
+    let current = task::current();
+    let file = current.filetable.lock().get_file(fd)
+        .ok_or(LinuxError::EBADF)?;
+    let node = file.lock().get_node()?;
+    Socket::from_node(&node).ok_or(LinuxError::ENOTSOCK)
+}
+
+/// Creates an endpoint for communication
+pub fn socket(domain: usize, ty: usize, protocol: usize) -> LinuxResult<usize> {
+    info!("socket: domain {} type {:#x} protocol {}", domain, ty, protocol);
+    let (ty, flags) = socket_type(domain, ty, protocol)?;
+    let sock = Socket::new(domain, ty, (flags & SOCK_NONBLOCK) != 0);
+    sock_install(sock, flags)
+}
+
+/// Creates a pair of connected sockets
+pub fn socketpair(domain: usize, ty: usize, protocol: usize, sv: usize) -> LinuxResult<usize> {
+    info!("socketpair: domain {} type {:#x} protocol {}", domain, ty, protocol);
+    let (ty, flags) = socket_type(domain, ty, protocol)?;
+    if domain != AF_UNIX {
+        return Err(LinuxError::EOPNOTSUPP);
+    }
+    let (a, b) = Socket::pair(domain, ty, (flags & SOCK_NONBLOCK) != 0);
+    let fd0 = sock_install(a, flags)?;
+    let fd1 = match sock_install(b, flags) {
+        Ok(fd) => fd,
+        Err(e) => {
+            let _ = fileops::unregister_file(fd0);
+            return Err(e);
+        },
+    };
+    let sv = unsafe { core::slice::from_raw_parts_mut(sv as *mut i32, 2) };
+    sv[0] = fd0 as i32;
+    sv[1] = fd1 as i32;
+    Ok(0)
+}
+
+/// Binds a name to a socket
+pub fn bind(fd: usize, addr: usize, addrlen: usize) -> LinuxResult<usize> {
+    let addr = SockAddr::from_user(addr, addrlen)?;
+    debug!("bind: fd {} addr {:?}", fd, addr);
+    sockfd_lookup(fd)?.bind(&addr)?;
+    Ok(0)
+}
+
+/// Listens for connections on a socket
+pub fn listen(fd: usize, backlog: usize) -> LinuxResult<usize> {
+    debug!("listen: fd {} backlog {}", fd, backlog);
+    sockfd_lookup(fd)?.listen(backlog as i32 as usize)?;
+    Ok(0)
+}
+
+/// Accepts a connection on a socket
+pub fn accept4(fd: usize, addr: usize, addrlen: usize, flags: usize) -> LinuxResult<usize> {
+    debug!("accept4: fd {} flags {:#x}", fd, flags);
+    if (flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let sock = sockfd_lookup(fd)?.accept()?;
+    sock.set_nonblock((flags & SOCK_NONBLOCK) != 0);
+    sock.peer_addr()?.to_user(addr, addrlen)?;
+    sock_install(sock, flags)
+}
+
+/// Initiates a connection on a socket
+pub fn connect(fd: usize, addr: usize, addrlen: usize) -> LinuxResult<usize> {
+    let addr = SockAddr::from_user(addr, addrlen)?;
+    debug!("connect: fd {} addr {:?}", fd, addr);
+    sockfd_lookup(fd)?.connect(&addr)?;
+    Ok(0)
+}
+
+/// Gets the name a socket is bound to
+pub fn getsockname(fd: usize, addr: usize, addrlen: usize) -> LinuxResult<usize> {
+    sockfd_lookup(fd)?.local_addr().to_user(addr, addrlen)?;
+    Ok(0)
+}
+
+/// Gets the name of the peer a socket is connected to
+pub fn getpeername(fd: usize, addr: usize, addrlen: usize) -> LinuxResult<usize> {
+    sockfd_lookup(fd)?.peer_addr()?.to_user(addr, addrlen)?;
+    Ok(0)
+}
+
+/// Sends a message on a socket, to `addr` if given
+pub fn sendto(fd: usize, buf: &[u8], flags: usize, addr: usize, addrlen: usize) -> LinuxResult<usize> {
+    debug!("sendto: fd {} len {} flags {:#x}", fd, buf.len(), flags);
+    let sock = sockfd_lookup(fd)?;
+    let dest = if addr != 0 {
+        Some(SockAddr::from_user(addr, addrlen)?)
+    } else {
+        None
+    };
+    sent(sock.send(buf, dest, flags), flags)
+}
+
+/// Raises SIGPIPE for sending to a socket nobody receives on any longer,
+/// unless MSG_NOSIGNAL is in `flags`.
+fn sent(ret: AxResult<usize>, flags: usize) -> LinuxResult<usize> {
+    match ret {
+        Ok(size) => Ok(size),
+        Err(AxError::BrokenPipe) => {
+            if (flags & MSG_NOSIGNAL) == 0 {
+                force_sig_fault(task::current().tid(), task::SIGPIPE, 0, 0);
+            }
+            Err(LinuxError::EPIPE)
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Sends a message on a socket, with files to pass in its ancillary data
+pub fn sendmsg(fd: usize, msg: usize, flags: usize) -> LinuxResult<usize> {
+    let hdr = MsgHdr::from_user(msg)?;
+    debug!("sendmsg: fd {} len {} flags {:#x}", fd, hdr.data_len(), flags);
+    let sock = sockfd_lookup(fd)?;
+    let dest = hdr.name()?;
+    let rights = hdr.rights()?;
+    if !rights.is_empty() && sock.domain() != AF_UNIX {
+        return Err(LinuxError::EINVAL);
+    }
+    let buf = hdr.gather();
+    sent(sock.send_msg(&buf, dest, rights, flags), flags)
+}
+
+/// Receives a message from a socket, with the address of its source
+pub fn recvfrom(fd: usize, buf: &mut [u8], flags: usize, addr: usize, addrlen: usize) -> LinuxResult<usize> {
+    debug!("recvfrom: fd {} len {} flags {:#x}", fd, buf.len(), flags);
+    let (size, from) = sockfd_lookup(fd)?.recv(buf, flags)?;
+    if let Some(from) = from {
+        from.to_user(addr, addrlen)?;
+    }
+    Ok(size)
+}
+
+/// Receives a message from a socket, with the files passed along
+pub fn recvmsg(fd: usize, msg: usize, flags: usize) -> LinuxResult<usize> {
+    let hdr = MsgHdr::from_user(msg)?;
+    debug!("recvmsg: fd {} len {} flags {:#x}", fd, hdr.data_len(), flags);
+    let sock = sockfd_lookup(fd)?;
+    let mut buf = vec![0u8; hdr.data_len()];
+    let (size, from, rights) = sock.recv_msg(&mut buf, flags)?;
+    hdr.scatter(&buf[..size]);
+    hdr.set_name(from)?;
+    let fd_flags = if (flags & MSG_CMSG_CLOEXEC) != 0 { O_CLOEXEC as usize } else { 0 };
+    hdr.put_rights(rights, fd_flags)?;
+    Ok(size)
+}
+
+/// Sets an option of a socket
+pub fn setsockopt(fd: usize, level: usize, name: usize, optval: usize, optlen: usize) -> LinuxResult<usize> {
+    if optval == 0 || optlen < core::mem::size_of::<i32>() {
+        return Err(LinuxError::EINVAL);
+    }
+    let val = unsafe { *(optval as *const i32) };
+    sockfd_lookup(fd)?.setsockopt(level, name, val)?;
+    Ok(0)
+}
+
+/// Gets an option of a socket
+pub fn getsockopt(fd: usize, level: usize, name: usize, optval: usize, optlen: usize) -> LinuxResult<usize> {
+    if optval == 0 || optlen == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    let optlen = unsafe { &mut *(optlen as *mut u32) };
+    if (*optlen as usize) < core::mem::size_of::<i32>() {
+        return Err(LinuxError::EINVAL);
+    }
+    let val = sockfd_lookup(fd)?.getsockopt(level, name)?;
+    unsafe { *(optval as *mut i32) = val; }
+    *optlen = core::mem::size_of::<i32>() as u32;
+    Ok(0)
+}
+
+/// Shuts down part of a full-duplex connection
+pub fn shutdown(fd: usize, how: usize) -> LinuxResult<usize> {
+    debug!("shutdown: fd {} how {}", fd, how);
+    sockfd_lookup(fd)?.shutdown(how)?;
+    Ok(0)
+}
@@ -0,0 +1,202 @@
+//! Message headers of sendmsg() and recvmsg(), and the files passed over
+//! UNIX sockets as SCM_RIGHTS ancillary data.
+
+use core::cmp::min;
+use core::mem::size_of;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::addr::SockAddr;
+use crate::{FileRef, SOL_SOCKET, SCM_RIGHTS, MSG_CTRUNC};
+
+/// Most files passed in one message.
+const SCM_MAX_FD: usize = 253;
+
+#[repr(C)]
+struct IoVec {
+    iov_base: usize,
+    iov_len: usize,
+}
+
+#[repr(C)]
+pub struct MsgHdr {
+    msg_name: usize,
+    msg_namelen: u32,
+    msg_iov: usize,
+    msg_iovlen: usize,
+    msg_control: usize,
+    msg_controllen: usize,
+    msg_flags: i32,
+}
+
+#[repr(C)]
+struct CmsgHdr {
+    cmsg_len: usize,
+    cmsg_level: i32,
+    cmsg_type: i32,
+}
+
+const fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+impl MsgHdr {
+    /// The header at `msg` in user space.
+    pub fn from_user<'a>(msg: usize) -> LinuxResult<&'a mut Self> {
+        if msg == 0 {
+            return Err(LinuxError::EFAULT);
+        }
+        Ok(unsafe { &mut *(msg as *mut Self) })
+    }
+
+    fn iovs(&self) -> &[IoVec] {
+        if self.msg_iov == 0 {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.msg_iov as *const IoVec, self.msg_iovlen) }
+    }
+
+    /// The destination of sendmsg(), if any.
+    pub fn name(&self) -> LinuxResult<Option<SockAddr>> {
+        if self.msg_name == 0 {
+            return Ok(None);
+        }
+        SockAddr::from_user(self.msg_name, self.msg_namelen as usize).map(Some)
+    }
+
+    /// Total size of the buffers.
+    pub fn data_len(&self) -> usize {
+        self.iovs().iter().map(|iov| iov.iov_len).sum()
+    }
+
+    /// Gathers the data of sendmsg() from the buffers.
+    pub fn gather(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.data_len());
+        for iov in self.iovs().iter().filter(|iov| iov.iov_len > 0) {
+            let src = unsafe {
+                core::slice::from_raw_parts(iov.iov_base as *const u8, iov.iov_len)
+            };
+            data.extend_from_slice(src);
+        }
+        data
+    }
+
+    /// Scatters what recvmsg() has received over the buffers.
+    pub fn scatter(&self, mut data: &[u8]) {
+        for iov in self.iovs() {
+            if data.is_empty() {
+                break;
+            }
+            let size = min(iov.iov_len, data.len());
+            let dst = unsafe {
+                core::slice::from_raw_parts_mut(iov.iov_base as *mut u8, size)
+            };
+            dst.copy_from_slice(&data[..size]);
+            data = &data[size..];
+        }
+    }
+
+    /// Writes the source of a received message, or clears the name for
+    /// none.
+    pub fn set_name(&mut self, from: Option<SockAddr>) -> LinuxResult {
+        match from {
+            Some(from) if self.msg_name != 0 => {
+                let namelen = &mut self.msg_namelen as *mut u32 as usize;
+                from.to_user(self.msg_name, namelen)
+            },
+            _ => {
+                self.msg_namelen = 0;
+                Ok(())
+            },
+        }
+    }
+
+    /// Takes the files of the SCM_RIGHTS ancillary data of sendmsg().
+    pub fn rights(&self) -> LinuxResult<Vec<FileRef>> {
+        let mut files = Vec::new();
+        let mut offset = 0;
+        while offset + size_of::<CmsgHdr>() <= self.msg_controllen {
+            let cmsg = unsafe { &*((self.msg_control + offset) as *const CmsgHdr) };
+            if cmsg.cmsg_len < size_of::<CmsgHdr>()
+                || offset + cmsg.cmsg_len > self.msg_controllen
+            {
+                return Err(LinuxError::EINVAL);
+            }
+            if cmsg.cmsg_level as usize != SOL_SOCKET || cmsg.cmsg_type != SCM_RIGHTS {
+                warn!("sendmsg: unsupported cmsg level {} type {}", cmsg.cmsg_level, cmsg.cmsg_type);
+                return Err(LinuxError::EINVAL);
+            }
+            let num = (cmsg.cmsg_len - size_of::<CmsgHdr>()) / size_of::<i32>();
+            if files.len() + num > SCM_MAX_FD {
+                return Err(LinuxError::EINVAL);
+            }
+            let fds = unsafe {
+                core::slice::from_raw_parts(
+                    (self.msg_control + offset + size_of::<CmsgHdr>()) as *const i32,
+                    num
+                )
+            };
+            let current = task::current();
+            let filetable = current.filetable.lock();
+            for fd in fds {
+                let file = filetable.get_file(*fd as usize).ok_or(LinuxError::EBADF)?;
+                files.push(file);
+            }
+            offset += cmsg_align(cmsg.cmsg_len);
+        }
+        Ok(files)
+    }
+
+    /// Installs the files a message has passed as new descriptors, and
+    /// writes them as SCM_RIGHTS ancillary data. The files there is no
+    /// room for are closed, and MSG_CTRUNC tells so.
+    pub fn put_rights(&mut self, files: Vec<FileRef>, fd_flags: usize) -> LinuxResult {
+        self.msg_flags = 0;
+        if files.is_empty() {
+            self.msg_controllen = 0;
+            return Ok(());
+        }
+        let room = if self.msg_control == 0 {
+            0
+        } else {
+            self.msg_controllen.saturating_sub(size_of::<CmsgHdr>()) / size_of::<i32>()
+        };
+        if files.len() > room {
+            self.msg_flags |= MSG_CTRUNC as i32;
+        }
+        if room == 0 {
+            self.msg_controllen = 0;
+            return Ok(());
+        }
+        let fds = unsafe {
+            core::slice::from_raw_parts_mut(
+                (self.msg_control + size_of::<CmsgHdr>()) as *mut i32,
+                min(room, files.len())
+            )
+        };
+        let mut num = 0;
+        for (slot, file) in fds.iter_mut().zip(files) {
+            match fileops::install_file(file, fd_flags) {
+                Ok(fd) => {
+                    *slot = fd as i32;
+                    num += 1;
+                },
+                Err(_) => {
+                    self.msg_flags |= MSG_CTRUNC as i32;
+                    break;
+                },
+            }
+        }
+        if num == 0 {
+            self.msg_controllen = 0;
+            return Ok(());
+        }
+        let cmsg_len = size_of::<CmsgHdr>() + num * size_of::<i32>();
+        let cmsg = unsafe { &mut *(self.msg_control as *mut CmsgHdr) };
+        cmsg.cmsg_len = cmsg_len;
+        cmsg.cmsg_level = SOL_SOCKET as i32;
+        cmsg.cmsg_type = SCM_RIGHTS;
+        self.msg_controllen = min(cmsg_align(cmsg_len), self.msg_controllen);
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult};
+use axfs_vfs::alloc_ino;
+use axio::PollState;
+use spinbase::SpinNoIrq;
+
+use crate::addr::{SockAddr, INADDR_ANY};
+use crate::dgram::{Dgram, DGRAM_QUEUE_SIZE};
+use crate::stream::{self, Stream, STREAM_BUF_SIZE};
+use crate::table;
+use crate::FileRef;
+use crate::{AF_UNIX, AF_INET, SOCK_STREAM, SHUT_RD, SHUT_WR, SHUT_RDWR, SOMAXCONN};
+use crate::{MSG_PEEK, MSG_DONTWAIT, SOL_SOCKET, IPPROTO_TCP, TCP_NODELAY};
+use crate::{SO_REUSEADDR, SO_TYPE, SO_ERROR, SO_SNDBUF, SO_RCVBUF, SO_KEEPALIVE};
+use crate::{SO_ACCEPTCONN, SO_DOMAIN};
+
+enum Proto {
+    Stream(Stream),
+    Dgram(Dgram),
+}
+
+/// A socket, which is the node of the file its descriptor refers to.
+pub struct Socket {
+    this: Weak<Socket>,
+    domain: usize,
+    ty: usize,
+    proto: Proto,
+    nonblock: AtomicBool,
+    /// The address it is bound to, if any.
+    local: SpinNoIrq<Option<SockAddr>>,
+    /// Options set by setsockopt(), which are only kept for getsockopt().
+    opts: SpinNoIrq<BTreeMap<(usize, usize), i32>>,
+    uid: u32,
+    gid: u32,
+    ino: usize,
+}
+
+impl Socket {
+    pub fn new(domain: usize, ty: usize, nonblock: bool) -> Arc<Self> {
+        let current = task::current();
+        let proto = if ty == SOCK_STREAM {
+            Proto::Stream(Stream::new())
+        } else {
+            Proto::Dgram(Dgram::new())
+        };
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            domain,
+            ty,
+            proto,
+            nonblock: AtomicBool::new(nonblock),
+            local: SpinNoIrq::new(None),
+            opts: SpinNoIrq::new(BTreeMap::new()),
+            uid: current.fsuid(),
+            gid: current.fsgid(),
+            ino: alloc_ino(),
+        })
+    }
+
+    /// Makes a pair of sockets connected to each other.
+    pub fn pair(domain: usize, ty: usize, nonblock: bool) -> (Arc<Self>, Arc<Self>) {
+        let a = Self::new(domain, ty, nonblock);
+        let b = Self::new(domain, ty, nonblock);
+        match (&a.proto, &b.proto) {
+            (Proto::Dgram(da), Proto::Dgram(db)) => {
+                da.connect_pair(&b);
+                db.connect_pair(&a);
+            },
+            _ => stream::connect_pair(&a, &b),
+        }
+        (a, b)
+    }
+
+    /// Returns the socket `node` is, if it is one.
+    pub fn from_node(node: &VfsNodeRef) -> Option<Arc<Self>> {
+        if !node.get_attr().is_ok_and(|attr| attr.file_type().is_socket()) {
+            return None;
+        }
+        node.as_any().downcast_ref::<Self>().and_then(|sock| sock.this.upgrade())
+    }
+
+    pub fn domain(&self) -> usize {
+        self.domain
+    }
+
+    pub fn ty(&self) -> usize {
+        self.ty
+    }
+
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    fn nonblock(&self, flags: usize) -> bool {
+        self.nonblock.load(Ordering::Relaxed) || (flags & MSG_DONTWAIT) != 0
+    }
+
+    pub(crate) fn stream(&self) -> Option<&Stream> {
+        match &self.proto {
+            Proto::Stream(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn dgram(&self) -> Option<&Dgram> {
+        match &self.proto {
+            Proto::Dgram(dgram) => Some(dgram),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_local(&self, addr: SockAddr) {
+        *self.local.lock() = Some(addr);
+    }
+
+    /// The address it is bound to, which is unspecified before bind().
+    pub fn local_addr(&self) -> SockAddr {
+        self.local.lock().clone().unwrap_or_else(|| SockAddr::unspecified(self.domain))
+    }
+
+    pub fn peer_addr(&self) -> LinuxResult<SockAddr> {
+        let peer = match &self.proto {
+            Proto::Stream(stream) => stream.peer(),
+            Proto::Dgram(dgram) => dgram.peer(),
+        };
+        peer.ok_or(LinuxError::ENOTCONN)
+    }
+
+    pub fn bind(&self, addr: &SockAddr) -> LinuxResult {
+        if addr.family() != self.domain {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut local = self.local.lock();
+        if local.is_some() {
+            return Err(LinuxError::EINVAL);
+        }
+        let sock = self.this.upgrade().ok_or(LinuxError::EBADF)?;
+        *local = Some(table::bind(&sock, addr)?);
+        Ok(())
+    }
+
+    /// Binds an INET socket to an ephemeral port if it is not bound yet,
+    /// and returns its address. A UNIX one stays unnamed.
+    pub(crate) fn autobind(&self) -> LinuxResult<SockAddr> {
+        if self.domain == AF_INET && self.local.lock().is_none() {
+            match self.bind(&SockAddr::Inet(INADDR_ANY, 0)) {
+                Ok(()) | Err(LinuxError::EINVAL) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.local_addr())
+    }
+
+    pub fn listen(&self, backlog: usize) -> LinuxResult {
+        let stream = self.stream().ok_or(LinuxError::EOPNOTSUPP)?;
+        if self.domain == AF_UNIX && self.local.lock().is_none() {
+            return Err(LinuxError::EINVAL);
+        }
+        self.autobind()?;
+        stream.listen(backlog.clamp(1, SOMAXCONN))
+    }
+
+    pub fn accept(&self) -> LinuxResult<Arc<Self>> {
+        let stream = self.stream().ok_or(LinuxError::EOPNOTSUPP)?;
+        stream.accept(self.nonblock(0))
+    }
+
+    pub fn connect(&self, addr: &SockAddr) -> LinuxResult {
+        if addr.family() != self.domain {
+            return Err(LinuxError::EAFNOSUPPORT);
+        }
+        let addr = addr.resolved();
+        if self.domain == AF_INET {
+            let local = self.autobind()?;
+            self.set_local(local.resolved());
+        }
+        match &self.proto {
+            Proto::Stream(stream) => stream.connect(self, &addr, self.nonblock(0)),
+            Proto::Dgram(dgram) => {
+                dgram.connect(addr);
+                Ok(())
+            },
+        }
+    }
+
+    /// Sends `buf` to `dest`, which only datagram sockets take.
+    pub fn send(&self, buf: &[u8], dest: Option<SockAddr>, flags: usize) -> AxResult<usize> {
+        self.send_msg(buf, dest, Vec::new(), flags)
+    }
+
+    /// Sends `buf` to `dest` as send() does, passing the files `rights`
+    /// along to the receiver.
+    pub(crate) fn send_msg(
+        &self, buf: &[u8], dest: Option<SockAddr>,
+        rights: Vec<FileRef>, flags: usize
+    ) -> AxResult<usize> {
+        let nonblock = self.nonblock(flags);
+        match &self.proto {
+            // The destination of a connected stream is ignored, as on Linux.
+            Proto::Stream(stream) => stream.send(buf, rights, nonblock),
+            Proto::Dgram(dgram) => dgram.send(self, buf, dest, rights, nonblock),
+        }
+    }
+
+    /// Receives into `buf`, with the source address for datagrams. Files
+    /// passed along are closed.
+    pub fn recv(&self, buf: &mut [u8], flags: usize) -> AxResult<(usize, Option<SockAddr>)> {
+        self.recv_msg(buf, flags).map(|(size, from, _)| (size, from))
+    }
+
+    /// Receives into `buf` as recv() does, with the files passed along.
+    pub(crate) fn recv_msg(
+        &self, buf: &mut [u8], flags: usize
+    ) -> AxResult<(usize, Option<SockAddr>, Vec<FileRef>)> {
+        let nonblock = self.nonblock(flags);
+        let peek = (flags & MSG_PEEK) != 0;
+        match &self.proto {
+            Proto::Stream(stream) => {
+                stream.recv(buf, peek, nonblock).map(|(size, rights)| (size, None, rights))
+            },
+            Proto::Dgram(dgram) => dgram.recv(buf, peek, nonblock),
+        }
+    }
+
+    pub fn shutdown(&self, how: usize) -> LinuxResult {
+        if how > SHUT_RDWR {
+            return Err(LinuxError::EINVAL);
+        }
+        match &self.proto {
+            Proto::Stream(stream) => stream.shutdown(how),
+            Proto::Dgram(dgram) => {
+                dgram.shutdown(how != SHUT_WR, how != SHUT_RD);
+                Ok(())
+            },
+        }
+    }
+
+    pub fn setsockopt(&self, level: usize, name: usize, val: i32) -> LinuxResult {
+        match (level, name) {
+            (SOL_SOCKET, SO_REUSEADDR | SO_KEEPALIVE | SO_SNDBUF | SO_RCVBUF)
+            | (IPPROTO_TCP, TCP_NODELAY) => {
+                self.opts.lock().insert((level, name), val);
+                Ok(())
+            },
+            _ => {
+                warn!("setsockopt: unsupported level {} name {}", level, name);
+                Err(LinuxError::ENOPROTOOPT)
+            },
+        }
+    }
+
+    pub fn getsockopt(&self, level: usize, name: usize) -> LinuxResult<i32> {
+        let val = match (level, name) {
+            (SOL_SOCKET, SO_TYPE) => self.ty as i32,
+            (SOL_SOCKET, SO_DOMAIN) => self.domain as i32,
+            (SOL_SOCKET, SO_ERROR) => 0,
+            (SOL_SOCKET, SO_ACCEPTCONN) => {
+                self.stream().is_some_and(|stream| stream.is_listening()) as i32
+            },
+            (SOL_SOCKET, SO_SNDBUF | SO_RCVBUF) => {
+                let size = if self.ty == SOCK_STREAM { STREAM_BUF_SIZE } else { DGRAM_QUEUE_SIZE };
+                size as i32
+            },
+            (SOL_SOCKET, SO_REUSEADDR | SO_KEEPALIVE) | (IPPROTO_TCP, TCP_NODELAY) => {
+                self.opts.lock().get(&(level, name)).copied().unwrap_or(0)
+            },
+            _ => {
+                warn!("getsockopt: unsupported level {} name {}", level, name);
+                return Err(LinuxError::ENOPROTOOPT);
+            },
+        };
+        Ok(val)
+    }
+}
+
+impl VfsNodeOps for Socket {
+    fn release(&self) -> VfsResult {
+        if let Some(addr) = self.local.lock().take() {
+            table::unbind(self, &addr);
+        }
+        match &self.proto {
+            Proto::Stream(stream) => stream.release(),
+            Proto::Dgram(dgram) => dgram.release(),
+        }
+        Ok(())
+    }
+
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::set_mode(0o777),
+            VfsNodeType::Socket,
+            0,
+            0,
+            self.uid,
+            self.gid,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.recv(buf, 0).map(|(size, _)| size)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.send(buf, None, 0)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(AxError::InvalidInput)
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        Ok(match &self.proto {
+            Proto::Stream(stream) => stream.poll(),
+            Proto::Dgram(dgram) => dgram.poll(),
+        })
+    }
+
+    fn set_nonblock(&self, nonblock: bool) -> VfsResult {
+        Socket::set_nonblock(self, nonblock);
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,365 @@
+//! Stream sockets: SOCK_STREAM of AF_UNIX, and TCP over the loopback.
+//!
+//! A connection is a pair of channels, one for each direction. Connecting
+//! to a listener queues a socket, already connected, for it to accept.
+//! Files passed along are attached to the first byte sent with them, and
+//! a receive stops short of such a byte so that they come with it.
+
+use core::cmp::min;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axio::PollState;
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+use crate::addr::SockAddr;
+use crate::socket::Socket;
+use crate::{table, FileRef, SOCK_STREAM, SHUT_RD, SHUT_WR, SHUT_RDWR};
+
+/// Bytes a channel holds before senders have to wait.
+pub const STREAM_BUF_SIZE: usize = 64 * 1024;
+
+struct Buf {
+    data: VecDeque<u8>,
+    /// Bytes received so far, which is the position of the first in `data`.
+    read: u64,
+    /// Files passed, by the position of the byte they are attached to.
+    rights: VecDeque<(u64, Vec<FileRef>)>,
+}
+
+/// One direction of a connection.
+struct Channel {
+    buf: SpinNoIrq<Buf>,
+    /// No more data comes, as the sender shut down or closed.
+    eof: AtomicBool,
+    /// Nobody receives any longer, so sending fails with EPIPE.
+    broken: AtomicBool,
+    /// The receiver waiting for data and the sender waiting for room.
+    wait: WaitQueue,
+}
+
+impl Channel {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            buf: SpinNoIrq::new(Buf {
+                data: VecDeque::new(),
+                read: 0,
+                rights: VecDeque::new(),
+            }),
+            eof: AtomicBool::new(false),
+            broken: AtomicBool::new(false),
+            wait: WaitQueue::new(),
+        })
+    }
+
+    fn readable(&self) -> bool {
+        !self.buf.lock().data.is_empty()
+            || self.eof.load(Ordering::Relaxed)
+            || self.broken.load(Ordering::Relaxed)
+    }
+
+    fn writable(&self) -> bool {
+        self.buf.lock().data.len() < STREAM_BUF_SIZE
+            || self.eof.load(Ordering::Relaxed)
+            || self.broken.load(Ordering::Relaxed)
+    }
+
+    fn close_write(&self) {
+        self.eof.store(true, Ordering::Relaxed);
+        self.wait.notify_all(false);
+    }
+
+    fn close_read(&self) {
+        self.broken.store(true, Ordering::Relaxed);
+        self.wait.notify_all(false);
+    }
+}
+
+struct Connection {
+    rx: Arc<Channel>,
+    tx: Arc<Channel>,
+    peer: SockAddr,
+}
+
+enum State {
+    Unconnected,
+    Listening {
+        backlog: usize,
+        queue: VecDeque<Arc<Socket>>,
+    },
+    Connected(Connection),
+}
+
+pub(crate) struct Stream {
+    state: SpinNoIrq<State>,
+    /// accept() waiting for connections, and connect() waiting for room
+    /// in the backlog.
+    wait: WaitQueue,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self {
+            state: SpinNoIrq::new(State::Unconnected),
+            wait: WaitQueue::new(),
+        }
+    }
+
+    fn channels(&self) -> AxResult<(Arc<Channel>, Arc<Channel>)> {
+        match &*self.state.lock() {
+            State::Connected(conn) => Ok((conn.rx.clone(), conn.tx.clone())),
+            _ => Err(AxError::NotConnected),
+        }
+    }
+
+    pub fn peer(&self) -> Option<SockAddr> {
+        match &*self.state.lock() {
+            State::Connected(conn) => Some(conn.peer.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_listening(&self) -> bool {
+        matches!(&*self.state.lock(), State::Listening { .. })
+    }
+
+    pub fn listen(&self, backlog: usize) -> LinuxResult {
+        let mut state = self.state.lock();
+        match &mut *state {
+            State::Listening { backlog: old, .. } => *old = backlog,
+            State::Connected(_) => return Err(LinuxError::EINVAL),
+            State::Unconnected => {
+                *state = State::Listening { backlog, queue: VecDeque::new() };
+            },
+        }
+        drop(state);
+        // Connectors may fit into a larger backlog.
+        self.wait.notify_all(false);
+        Ok(())
+    }
+
+    fn has_room(&self) -> bool {
+        match &*self.state.lock() {
+            State::Listening { backlog, queue } => queue.len() < *backlog,
+            _ => true,
+        }
+    }
+
+    fn has_pending(&self) -> bool {
+        match &*self.state.lock() {
+            State::Listening { queue, .. } => !queue.is_empty(),
+            _ => true,
+        }
+    }
+
+    pub fn accept(&self, nonblock: bool) -> LinuxResult<Arc<Socket>> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                let State::Listening { queue, .. } = &mut *state else {
+                    return Err(LinuxError::EINVAL);
+                };
+                if let Some(sock) = queue.pop_front() {
+                    drop(state);
+                    self.wait.notify_all(false);
+                    return Ok(sock);
+                }
+            }
+            if nonblock {
+                return Err(LinuxError::EAGAIN);
+            }
+            self.wait.wait_until_interruptible(|| self.has_pending())?;
+        }
+    }
+
+    /// Connects `sock`, which this is the stream of, to the listener at
+    /// `addr`. As nothing goes over a wire, the connection is made at once
+    /// unless the backlog of the listener is full.
+    pub fn connect(&self, sock: &Socket, addr: &SockAddr, nonblock: bool) -> LinuxResult {
+        match &*self.state.lock() {
+            State::Connected(_) => return Err(LinuxError::EISCONN),
+            State::Listening { .. } => return Err(LinuxError::EINVAL),
+            State::Unconnected => {},
+        }
+        let target = table::lookup(addr, SOCK_STREAM)?;
+        let listener = target.stream().ok_or(LinuxError::ECONNREFUSED)?;
+        loop {
+            {
+                let mut state = listener.state.lock();
+                let State::Listening { backlog, queue } = &mut *state else {
+                    return Err(LinuxError::ECONNREFUSED);
+                };
+                if queue.len() < *backlog {
+                    let (up, down) = (Channel::new(), Channel::new());
+                    let server = Socket::new(sock.domain(), SOCK_STREAM, false);
+                    server.set_local(addr.clone());
+                    if let Some(stream) = server.stream() {
+                        *stream.state.lock() = State::Connected(Connection {
+                            rx: up.clone(),
+                            tx: down.clone(),
+                            peer: sock.local_addr(),
+                        });
+                    }
+                    queue.push_back(server);
+                    drop(state);
+                    *self.state.lock() = State::Connected(Connection {
+                        rx: down,
+                        tx: up,
+                        peer: addr.clone(),
+                    });
+                    listener.wait.notify_all(false);
+                    return Ok(());
+                }
+            }
+            if nonblock {
+                return Err(LinuxError::EAGAIN);
+            }
+            listener.wait.wait_until_interruptible(|| listener.has_room())?;
+        }
+    }
+
+    /// Sends all of `buf`, waiting for room as needed, or what fits for
+    /// `nonblock`. `rights` go with its first byte.
+    pub fn send(&self, buf: &[u8], mut rights: Vec<FileRef>, nonblock: bool) -> AxResult<usize> {
+        let (_, tx) = self.channels()?;
+        let partial = |sent: usize, err: AxError| if sent > 0 { Ok(sent) } else { Err(err) };
+        let mut sent = 0;
+        while sent < buf.len() {
+            if tx.eof.load(Ordering::Relaxed) || tx.broken.load(Ordering::Relaxed) {
+                return partial(sent, AxError::BrokenPipe);
+            }
+            let size = {
+                let mut data = tx.buf.lock();
+                let size = min(STREAM_BUF_SIZE.saturating_sub(data.data.len()), buf.len() - sent);
+                if size > 0 && !rights.is_empty() {
+                    let pos = data.read + data.data.len() as u64;
+                    data.rights.push_back((pos, core::mem::take(&mut rights)));
+                }
+                data.data.extend(&buf[sent..sent + size]);
+                size
+            };
+            if size > 0 {
+                sent += size;
+                tx.wait.notify_all(false);
+                continue;
+            }
+            if nonblock {
+                return partial(sent, AxError::WouldBlock);
+            }
+            if let Err(err) = tx.wait.wait_until_interruptible(|| tx.writable()) {
+                return partial(sent, err.into());
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Receives what has come, with the files passed along, or 0 at the
+    /// end of the stream.
+    pub fn recv(&self, buf: &mut [u8], peek: bool, nonblock: bool) -> AxResult<(usize, Vec<FileRef>)> {
+        let (rx, _) = self.channels()?;
+        loop {
+            {
+                let mut data = rx.buf.lock();
+                if !data.data.is_empty() {
+                    let mut size = min(buf.len(), data.data.len());
+                    let mut rights = Vec::new();
+                    let read = data.read;
+                    if data.rights.front().is_some_and(|(pos, _)| *pos == read) {
+                        rights = if peek {
+                            data.rights[0].1.clone()
+                        } else {
+                            data.rights.pop_front().map(|(_, files)| files).unwrap_or_default()
+                        };
+                    }
+                    let next = data.rights.iter().map(|(pos, _)| *pos).find(|pos| *pos > read);
+                    if let Some(next) = next {
+                        size = min(size, (next - read) as usize);
+                    }
+                    for (dst, c) in buf.iter_mut().zip(data.data.iter()).take(size) {
+                        *dst = *c;
+                    }
+                    if !peek {
+                        data.data.drain(..size);
+                        data.read += size as u64;
+                        drop(data);
+                        rx.wait.notify_all(false);
+                    }
+                    return Ok((size, rights));
+                }
+            }
+            if rx.eof.load(Ordering::Relaxed) || rx.broken.load(Ordering::Relaxed) {
+                return Ok((0, Vec::new()));
+            }
+            if nonblock {
+                return Err(AxError::WouldBlock);
+            }
+            rx.wait.wait_until_interruptible(|| rx.readable())?;
+        }
+    }
+
+    pub fn shutdown(&self, how: usize) -> LinuxResult {
+        let (rx, tx) = self.channels().map_err(|_| LinuxError::ENOTCONN)?;
+        if how == SHUT_RD || how == SHUT_RDWR {
+            rx.close_read();
+        }
+        if how == SHUT_WR || how == SHUT_RDWR {
+            tx.close_write();
+        }
+        Ok(())
+    }
+
+    pub fn poll(&self) -> PollState {
+        match &*self.state.lock() {
+            State::Connected(conn) => PollState {
+                readable: conn.rx.readable(),
+                writable: conn.tx.writable(),
+            },
+            State::Listening { queue, .. } => PollState {
+                readable: !queue.is_empty(),
+                writable: false,
+            },
+            State::Unconnected => PollState::default(),
+        }
+    }
+
+    /// Closes the stream: the peer sees the end of it and gets EPIPE for
+    /// sending, and connections a listener has not accepted are closed.
+    pub fn release(&self) {
+        let state = core::mem::replace(&mut *self.state.lock(), State::Unconnected);
+        match state {
+            State::Connected(conn) => {
+                conn.rx.close_read();
+                conn.tx.close_write();
+            },
+            State::Listening { queue, .. } => {
+                for sock in queue {
+                    if let Some(stream) = sock.stream() {
+                        stream.release();
+                    }
+                }
+                self.wait.notify_all(false);
+            },
+            State::Unconnected => {},
+        }
+    }
+}
+
+/// Makes a pair of streams connected to each other, as for socketpair().
+pub(crate) fn connect_pair(a: &Socket, b: &Socket) {
+    let (up, down) = (Channel::new(), Channel::new());
+    if let (Some(sa), Some(sb)) = (a.stream(), b.stream()) {
+        *sa.state.lock() = State::Connected(Connection {
+            rx: down.clone(),
+            tx: up.clone(),
+            peer: b.local_addr(),
+        });
+        *sb.state.lock() = State::Connected(Connection {
+            rx: up,
+            tx: down,
+            peer: a.local_addr(),
+        });
+    }
+}
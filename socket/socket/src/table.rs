@@ -0,0 +1,150 @@
+//! Bound sockets, by their addresses.
+//!
+//! A UNIX socket bound to a path is found through the socket file at the
+//! path, and one with an abstract name by that name. INET ones are found
+//! by port, separately for TCP and UDP. Since there is only the loopback
+//! interface, all local IPv4 addresses are the same host.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use spinbase::SpinNoIrq;
+
+use crate::addr::{SockAddr, is_local};
+use crate::inode::SockInode;
+use crate::{Socket, SOCK_STREAM};
+
+/// Ports handed out to sockets bound to port 0 or not bound at all.
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 32768..=60999;
+
+/// Sockets with abstract names.
+static UNIX_NAMES: SpinNoIrq<BTreeMap<Vec<u8>, Weak<Socket>>> =
+    SpinNoIrq::new(BTreeMap::new());
+static TCP_PORTS: SpinNoIrq<BTreeMap<u16, Weak<Socket>>> = SpinNoIrq::new(BTreeMap::new());
+static UDP_PORTS: SpinNoIrq<BTreeMap<u16, Weak<Socket>>> = SpinNoIrq::new(BTreeMap::new());
+
+fn is_abstract(name: &[u8]) -> bool {
+    name.first() == Some(&0)
+}
+
+fn unix_path(name: &[u8]) -> LinuxResult<&str> {
+    core::str::from_utf8(name).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Creates the socket file for `sock` at `path`, which must not exist.
+fn bind_path(sock: &Arc<Socket>, path: &str) -> LinuxResult {
+    let current = task::current();
+    let fs = current.fs.lock();
+    let mode = (0o777 & !fs.umask()) as u16;
+    let inode = SockInode::new(sock, mode, current.fsuid(), current.fsgid());
+    fs.create_link(None, path, Arc::new(inode)).map_err(|e| match e {
+        AxError::AlreadyExists => LinuxError::EADDRINUSE,
+        e => e.into(),
+    })
+}
+
+/// Finds the socket whose file is at `path`.
+fn lookup_path(path: &str) -> LinuxResult<Arc<Socket>> {
+    let node = task::current().fs.lock().lookup(None, path, 0)?;
+    SockInode::from_node(&node)
+        .and_then(|inode| inode.socket())
+        .ok_or(LinuxError::ECONNREFUSED)
+}
+
+fn ports(ty: usize) -> &'static SpinNoIrq<BTreeMap<u16, Weak<Socket>>> {
+    if ty == SOCK_STREAM {
+        &TCP_PORTS
+    } else {
+        &UDP_PORTS
+    }
+}
+
+/// Binds `sock` to `addr` and returns the address it got, which has a
+/// port assigned if `addr` has port 0.
+pub fn bind(sock: &Arc<Socket>, addr: &SockAddr) -> LinuxResult<SockAddr> {
+    match addr {
+        SockAddr::Unix(name) => {
+            if name.is_empty() {
+                return Err(LinuxError::EINVAL);
+            }
+            if !is_abstract(name) {
+                bind_path(sock, unix_path(name)?)?;
+                return Ok(addr.clone());
+            }
+            let mut names = UNIX_NAMES.lock();
+            if names.get(name).is_some_and(|s| s.strong_count() > 0) {
+                return Err(LinuxError::EADDRINUSE);
+            }
+            names.insert(name.clone(), Arc::downgrade(sock));
+            Ok(addr.clone())
+        },
+        SockAddr::Inet(ip, port) => {
+            if !is_local(*ip) {
+                return Err(LinuxError::EADDRNOTAVAIL);
+            }
+            let mut ports = ports(sock.ty()).lock();
+            let port = if *port == 0 {
+                EPHEMERAL_PORTS
+                    .clone()
+                    .find(|p| !ports.get(p).is_some_and(|s| s.strong_count() > 0))
+                    .ok_or(LinuxError::EADDRINUSE)?
+            } else {
+                if ports.get(port).is_some_and(|s| s.strong_count() > 0) {
+                    return Err(LinuxError::EADDRINUSE);
+                }
+                *port
+            };
+            ports.insert(port, Arc::downgrade(sock));
+            Ok(SockAddr::Inet(*ip, port))
+        },
+    }
+}
+
+/// Removes the binding of `sock` to `addr`, if it still has it. The
+/// socket file of a path stays until it is unlinked.
+pub fn unbind(sock: &Socket, addr: &SockAddr) {
+    let is_sock = |s: &Weak<Socket>| core::ptr::eq(s.as_ptr(), sock);
+    match addr {
+        SockAddr::Unix(name) => {
+            let mut names = UNIX_NAMES.lock();
+            if is_abstract(name) && names.get(name).is_some_and(is_sock) {
+                names.remove(name);
+            }
+        },
+        SockAddr::Inet(_, port) => {
+            let mut ports = ports(sock.ty()).lock();
+            if ports.get(port).is_some_and(is_sock) {
+                ports.remove(port);
+            }
+        },
+    }
+}
+
+/// Finds the socket of type `ty` at `addr`.
+pub fn lookup(addr: &SockAddr, ty: usize) -> LinuxResult<Arc<Socket>> {
+    let sock = match addr {
+        SockAddr::Unix(name) => {
+            let sock = if is_abstract(name) {
+                UNIX_NAMES.lock().get(name)
+                    .and_then(|s| s.upgrade())
+                    .ok_or(LinuxError::ECONNREFUSED)?
+            } else {
+                lookup_path(unix_path(name)?)?
+            };
+            if sock.ty() != ty {
+                return Err(LinuxError::EPROTOTYPE);
+            }
+            sock
+        },
+        SockAddr::Inet(ip, port) => {
+            if !is_local(*ip) {
+                return Err(LinuxError::ENETUNREACH);
+            }
+            ports(ty).lock().get(port)
+                .and_then(|s| s.upgrade())
+                .ok_or(LinuxError::ECONNREFUSED)?
+        },
+    };
+    Ok(sock)
+}
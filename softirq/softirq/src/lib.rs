@@ -0,0 +1,253 @@
+//! Softirqs and tasklets: the deferrable bottom half of interrupt handling.
+//!
+//! An interrupt handler does the minimal work with IRQs off, then raises a
+//! softirq for the rest. Pending softirqs run with IRQs on:
+//! - on the way out of the outermost IRQ, see [`irq_exit`]
+//! - in the per-CPU `ksoftirqd` kthread, when raised from task context or
+//!   when they keep being raised faster than IRQ return can serve them
+//!
+//! Tasklets are built on `HI_SOFTIRQ` and `TASKLET_SOFTIRQ`. A tasklet
+//! never runs on two CPUs at once.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use handler_table::{Handler, HandlerTable};
+use preempt_guard::NoPreempt;
+use spinbase::SpinNoIrq;
+use taskctx::TaskState;
+
+const SMP: usize = axconfig::SMP;
+
+pub const HI_SOFTIRQ: usize = 0;
+pub const TIMER_SOFTIRQ: usize = 1;
+pub const NET_TX_SOFTIRQ: usize = 2;
+pub const NET_RX_SOFTIRQ: usize = 3;
+pub const BLOCK_SOFTIRQ: usize = 4;
+pub const TASKLET_SOFTIRQ: usize = 5;
+pub const RCU_SOFTIRQ: usize = 6;
+pub const NR_SOFTIRQS: usize = 7;
+
+/// Rounds of pending softirqs served on IRQ return before the rest is
+/// left to `ksoftirqd`.
+const MAX_SOFTIRQ_RESTART: usize = 10;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const FALSE: AtomicBool = AtomicBool::new(false);
+#[allow(clippy::declare_interior_mutable_const)]
+const TASKLET_LIST_INIT: SpinNoIrq<VecDeque<&'static Tasklet>> = SpinNoIrq::new(VecDeque::new());
+
+static HANDLERS: HandlerTable<NR_SOFTIRQS> = HandlerTable::new();
+
+/// Pending softirqs of each CPU, one bit per softirq.
+static PENDING: [AtomicUsize; SMP] = [ZERO; SMP];
+/// Nesting depth of hard IRQs on each CPU.
+static HARDIRQ_COUNT: [AtomicUsize; SMP] = [ZERO; SMP];
+/// Whether softirqs are being served on each CPU.
+static IN_SOFTIRQ: [AtomicBool; SMP] = [FALSE; SMP];
+/// Tid of `ksoftirqd` of each CPU, 0 before `init`.
+static KSOFTIRQD: [AtomicUsize; SMP] = [ZERO; SMP];
+
+static TASKLET_VEC: [SpinNoIrq<VecDeque<&'static Tasklet>>; SMP] = [TASKLET_LIST_INIT; SMP];
+static TASKLET_HI_VEC: [SpinNoIrq<VecDeque<&'static Tasklet>>; SMP] = [TASKLET_LIST_INIT; SMP];
+
+fn this_cpu() -> usize {
+    taskctx::current_ctx().cpu()
+}
+
+/// Registers `handler` for softirq `nr`. Tasklet softirqs are built in.
+pub fn open_softirq(nr: usize, handler: Handler) -> bool {
+    assert!(nr < NR_SOFTIRQS && nr != HI_SOFTIRQ && nr != TASKLET_SOFTIRQ);
+    HANDLERS.register_handler(nr, handler)
+}
+
+/// Marks softirq `nr` pending on the current CPU.
+///
+/// Out of interrupt context, `ksoftirqd` is woken up to serve it.
+pub fn raise_softirq(nr: usize) {
+    assert!(nr < NR_SOFTIRQS);
+    let cpu = this_cpu();
+    PENDING[cpu].fetch_or(1 << nr, Ordering::AcqRel);
+    if !in_interrupt() {
+        wakeup_softirqd(cpu);
+    }
+}
+
+/// Whether the current CPU is in a hard IRQ or serving softirqs.
+pub fn in_interrupt() -> bool {
+    let cpu = this_cpu();
+    HARDIRQ_COUNT[cpu].load(Ordering::Acquire) > 0 || IN_SOFTIRQ[cpu].load(Ordering::Acquire)
+}
+
+/// Called on entry to a hard IRQ handler, with preemption disabled.
+/// Remote wakeups are batched until the IRQ exits.
+pub fn irq_enter() {
+    HARDIRQ_COUNT[this_cpu()].fetch_add(1, Ordering::AcqRel);
+    run_queue::begin_wake_batch();
+}
+
+/// Called on exit from a hard IRQ handler, with preemption still disabled.
+/// Leaving the outermost IRQ serves pending softirqs.
+pub fn irq_exit() {
+    let cpu = this_cpu();
+    if HARDIRQ_COUNT[cpu].fetch_sub(1, Ordering::AcqRel) == 1
+        && PENDING[cpu].load(Ordering::Acquire) != 0
+    {
+        do_softirq(cpu);
+    }
+    run_queue::end_wake_batch();
+}
+
+fn wakeup_softirqd(cpu: usize) {
+    let tid = KSOFTIRQD[cpu].load(Ordering::Acquire);
+    if tid != 0 {
+        run_queue::wake_up_task(tid);
+    }
+}
+
+/// Serves pending softirqs of `cpu` with IRQs enabled, unless they're
+/// already being served by an interrupted context of this CPU.
+fn do_softirq(cpu: usize) {
+    if IN_SOFTIRQ[cpu].swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let irqs_enabled = axhal::arch::irqs_enabled();
+    for _ in 0..MAX_SOFTIRQ_RESTART {
+        let pending = PENDING[cpu].swap(0, Ordering::AcqRel);
+        if pending == 0 {
+            break;
+        }
+        axhal::arch::enable_irqs();
+        for nr in (0..NR_SOFTIRQS).filter(|nr| pending & (1 << nr) != 0) {
+            match nr {
+                HI_SOFTIRQ => tasklet_action(&TASKLET_HI_VEC[cpu], HI_SOFTIRQ),
+                TASKLET_SOFTIRQ => tasklet_action(&TASKLET_VEC[cpu], TASKLET_SOFTIRQ),
+                _ => {
+                    if !HANDLERS.handle(nr) {
+                        warn!("Unhandled softirq {}", nr);
+                    }
+                }
+            }
+        }
+        axhal::arch::disable_irqs();
+    }
+    IN_SOFTIRQ[cpu].store(false, Ordering::Release);
+    if irqs_enabled {
+        axhal::arch::enable_irqs();
+    }
+    if PENDING[cpu].load(Ordering::Acquire) != 0 {
+        wakeup_softirqd(cpu);
+    }
+}
+
+fn ksoftirqd_loop(cpu: usize) -> ! {
+    loop {
+        if PENDING[cpu].load(Ordering::Acquire) == 0 {
+            run_queue::block_current(TaskState::Interruptible);
+            continue;
+        }
+        {
+            let _guard = NoPreempt::new();
+            do_softirq(cpu);
+        }
+        run_queue::yield_now();
+    }
+}
+
+const TASKLET_STATE_SCHED: usize = 1 << 0;
+const TASKLET_STATE_RUN: usize = 1 << 1;
+
+/// A function run once in softirq context each time it's scheduled.
+pub struct Tasklet {
+    state: AtomicUsize,
+    /// Nesting depth of `disable`.
+    count: AtomicUsize,
+    func: fn(usize),
+    data: usize,
+}
+
+impl Tasklet {
+    pub const fn new(func: fn(usize), data: usize) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            func,
+            data,
+        }
+    }
+
+    /// Schedules the tasklet on the current CPU, unless it's already
+    /// scheduled and hasn't started running yet.
+    pub fn schedule(&'static self) {
+        self.schedule_on(&TASKLET_VEC, TASKLET_SOFTIRQ);
+    }
+
+    /// Like [`Tasklet::schedule`], but runs before other softirqs.
+    pub fn hi_schedule(&'static self) {
+        self.schedule_on(&TASKLET_HI_VEC, HI_SOFTIRQ);
+    }
+
+    fn schedule_on(&'static self, vec: &[SpinNoIrq<VecDeque<&'static Tasklet>>; SMP], nr: usize) {
+        if self.state.fetch_or(TASKLET_STATE_SCHED, Ordering::AcqRel) & TASKLET_STATE_SCHED != 0 {
+            return;
+        }
+        vec[this_cpu()].lock().push_back(self);
+        raise_softirq(nr);
+    }
+
+    /// Keeps the tasklet from running until `enable`. It may still be
+    /// running on another CPU when this returns.
+    pub fn disable(&self) {
+        self.count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Undoes a `disable`.
+    pub fn enable(&self) {
+        self.count.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// Whether the tasklet is scheduled and hasn't started running yet.
+    pub fn is_scheduled(&self) -> bool {
+        self.state.load(Ordering::Acquire) & TASKLET_STATE_SCHED != 0
+    }
+}
+
+fn tasklet_action(vec: &SpinNoIrq<VecDeque<&'static Tasklet>>, nr: usize) {
+    let requeue = |t: &'static Tasklet| {
+        vec.lock().push_back(t);
+        PENDING[this_cpu()].fetch_or(1 << nr, Ordering::AcqRel);
+    };
+    let list = core::mem::take(&mut *vec.lock());
+    for t in list {
+        // Running on another CPU: try it again later.
+        if t.state.fetch_or(TASKLET_STATE_RUN, Ordering::AcqRel) & TASKLET_STATE_RUN != 0 {
+            requeue(t);
+            continue;
+        }
+        if t.count.load(Ordering::Acquire) != 0 {
+            t.state.fetch_and(!TASKLET_STATE_RUN, Ordering::AcqRel);
+            requeue(t);
+            continue;
+        }
+        t.state.fetch_and(!TASKLET_STATE_SCHED, Ordering::AcqRel);
+        (t.func)(t.data);
+        t.state.fetch_and(!TASKLET_STATE_RUN, Ordering::AcqRel);
+    }
+}
+
+/// Creates `ksoftirqd` of each CPU. They're created by the calling task,
+/// so it must be a kernel task that can fork.
+pub fn init() {
+    info!("Initialize softirqs ...");
+    for cpu in 0..SMP {
+        let tid = fork::kernel_thread(move || ksoftirqd_loop(cpu), Some(cpu));
+        KSOFTIRQD[cpu].store(tid, Ordering::Release);
+    }
+}
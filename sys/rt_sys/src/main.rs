@@ -0,0 +1,139 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::time::Duration;
+use axerrno::linux_err;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_CMP_REQUEUE: usize = 4;
+const FUTEX_WAIT_BITSET: usize = 9;
+const FUTEX_PRIVATE_FLAG: usize = 128;
+const FUTEX_CLOCK_REALTIME: usize = 256;
+const FUTEX_BITSET_MATCH_ANY: usize = 0xffffffff;
+
+#[repr(C)]
+struct KernelTimespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+impl From<Duration> for KernelTimespec {
+    fn from(t: Duration) -> Self {
+        Self {
+            tv_sec: t.as_secs() as i64,
+            tv_nsec: t.subsec_nanos() as i64,
+        }
+    }
+}
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_sys]: ...");
+
+    fork::init(cpu_id, dtb_pa);
+
+    test_futex_timeout();
+    test_futex_requeue();
+
+    info!("[rt_sys]: ok!");
+    axhal::misc::terminate();
+}
+
+fn futex(word: &AtomicU32, op: usize, val: usize, timeout: usize, word2: usize, val3: usize) -> usize {
+    let uaddr = word.as_ptr() as usize;
+    sys::do_futex(uaddr, op | FUTEX_PRIVATE_FLAG, val, timeout, word2, val3)
+}
+
+fn wait_bitset(word: &AtomicU32, deadline: Duration, clock: usize) -> usize {
+    let ts = KernelTimespec::from(deadline);
+    let op = FUTEX_WAIT_BITSET | clock;
+    futex(word, op, 0, &ts as *const _ as usize, 0, FUTEX_BITSET_MATCH_ANY)
+}
+
+static TIMEOUT_WORD: AtomicU32 = AtomicU32::new(0);
+static NR_WOKEN_BY_HELPER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Absolute timeouts are on CLOCK_MONOTONIC, or on CLOCK_REALTIME with
+/// FUTEX_CLOCK_REALTIME. The realtime clock is set a day ahead of the
+/// monotonic one: a deadline taken on the wrong clock makes the wait
+/// block, and a helper thread, which only runs then, wakes it up.
+fn test_futex_timeout() {
+    let word = &TIMEOUT_WORD;
+    assert_eq!(futex(word, FUTEX_WAIT, 1, 0, 0, 0), linux_err!(EAGAIN));
+    let ts = KernelTimespec::from(Duration::ZERO);
+    assert_eq!(futex(word, FUTEX_WAIT, 0, &ts as *const _ as usize, 0, 0), linux_err!(ETIMEDOUT));
+
+    let day = Duration::from_secs(24 * 3600);
+    timekeeping::do_settimeofday(timekeeping::ktime_get() + day).unwrap();
+    fork::kernel_thread(|| {
+        let n = futex(&TIMEOUT_WORD, FUTEX_WAKE, usize::MAX, 0, 0, 0);
+        NR_WOKEN_BY_HELPER.store(n, Ordering::Release);
+        run_queue::exit_current(0);
+    }, None);
+
+    let now = timekeeping::ktime_get();
+    assert_eq!(wait_bitset(word, now, 0), linux_err!(ETIMEDOUT));
+    let now = timekeeping::ktime_get_real();
+    assert_eq!(wait_bitset(word, now, FUTEX_CLOCK_REALTIME), linux_err!(ETIMEDOUT));
+
+    while NR_WOKEN_BY_HELPER.load(Ordering::Acquire) == usize::MAX {
+        task::yield_now();
+    }
+    assert_eq!(NR_WOKEN_BY_HELPER.load(Ordering::Acquire), 0);
+    info!("[rt_sys]: futex timeouts ok!");
+}
+
+static WORD1: AtomicU32 = AtomicU32::new(0);
+static WORD2: AtomicU32 = AtomicU32::new(0);
+static NR_WAITING: AtomicUsize = AtomicUsize::new(0);
+static NR_WOKEN: AtomicUsize = AtomicUsize::new(0);
+
+const NR_WAITERS: usize = 3;
+
+fn wait_for(counter: &AtomicUsize, n: usize) {
+    while counter.load(Ordering::Acquire) < n {
+        task::yield_now();
+    }
+}
+
+/// FUTEX_CMP_REQUEUE wakes some waiters of a futex and moves others to
+/// a second one, where they're woken up by a wakeup of that one.
+fn test_futex_requeue() {
+    for _ in 0..NR_WAITERS {
+        fork::kernel_thread(|| {
+            // No other task runs till it blocks, so it's queued once
+            // the main task sees it counted.
+            NR_WAITING.fetch_add(1, Ordering::AcqRel);
+            assert_eq!(futex(&WORD1, FUTEX_WAIT, 0, 0, 0, 0), 0);
+            NR_WOKEN.fetch_add(1, Ordering::AcqRel);
+            run_queue::exit_current(0);
+        }, None);
+    }
+    wait_for(&NR_WAITING, NR_WAITERS);
+
+    let word2 = WORD2.as_ptr() as usize;
+    // The value has changed since the caller looked at it.
+    assert_eq!(futex(&WORD1, FUTEX_CMP_REQUEUE, 1, 1, word2, 1), linux_err!(EAGAIN));
+    // Wake one, requeue one, leave one.
+    assert_eq!(futex(&WORD1, FUTEX_CMP_REQUEUE, 1, 1, word2, 0), 2);
+    wait_for(&NR_WOKEN, 1);
+    assert_eq!(futex(&WORD2, FUTEX_WAKE, usize::MAX, 0, 0, 0), 1);
+    assert_eq!(futex(&WORD1, FUTEX_WAKE, usize::MAX, 0, 0, 0), 1);
+    wait_for(&NR_WOKEN, NR_WAITERS);
+    assert_eq!(futex(&WORD1, FUTEX_WAKE, usize::MAX, 0, 0, 0), 0);
+    info!("[rt_sys]: futex requeue ok!");
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
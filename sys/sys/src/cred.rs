@@ -0,0 +1,241 @@
+//! cred
+//!
+//! The set*id family with the usual rules: a privileged task may set any
+//! id, others may only switch among the real, effective and saved ids they
+//! already hold. An id of -1 leaves it unchanged. The filesystem ids follow
+//! the effective ones. Threads share their credentials, so a change applies
+//! to the whole process.
+
+use alloc::vec::Vec;
+use axerrno::linux_err;
+use axtype::{Cred, NGROUPS_MAX};
+
+const ID_UNCHANGED: u32 = u32::MAX;
+
+fn update_cred<F>(f: F) -> usize
+where
+    F: FnOnce(&mut Cred) -> bool,
+{
+    let task = task::current();
+    let mut cred = task.cred.lock();
+    let mut new = cred.clone();
+    if !f(&mut new) {
+        return linux_err!(EPERM);
+    }
+    new.fsuid = new.euid;
+    new.fsgid = new.egid;
+    *cred = new;
+    0
+}
+
+pub fn getuid() -> usize {
+    task::current().cred.lock().uid as usize
+}
+
+pub fn geteuid() -> usize {
+    task::current().cred.lock().euid as usize
+}
+
+pub fn getgid() -> usize {
+    task::current().cred.lock().gid as usize
+}
+
+pub fn getegid() -> usize {
+    task::current().cred.lock().egid as usize
+}
+
+pub fn setuid(uid: usize) -> usize {
+    info!("setuid: {}", uid);
+    let uid = uid as u32;
+    if uid == ID_UNCHANGED {
+        return linux_err!(EINVAL);
+    }
+    update_cred(|cred| {
+        if cred.is_privileged() {
+            cred.uid = uid;
+            cred.suid = uid;
+        } else if uid != cred.uid && uid != cred.suid {
+            return false;
+        }
+        cred.euid = uid;
+        true
+    })
+}
+
+pub fn setgid(gid: usize) -> usize {
+    info!("setgid: {}", gid);
+    let gid = gid as u32;
+    if gid == ID_UNCHANGED {
+        return linux_err!(EINVAL);
+    }
+    update_cred(|cred| {
+        if cred.is_privileged() {
+            cred.gid = gid;
+            cred.sgid = gid;
+        } else if gid != cred.gid && gid != cred.sgid {
+            return false;
+        }
+        cred.egid = gid;
+        true
+    })
+}
+
+/// The saved uid becomes the new effective one when the real uid is set,
+/// or the effective one is set to something else than the old real uid.
+pub fn setreuid(ruid: usize, euid: usize) -> usize {
+    info!("setreuid: {:#x}, {:#x}", ruid, euid);
+    let ruid = ruid as u32;
+    let euid = euid as u32;
+    update_cred(|cred| {
+        let old = cred.clone();
+        if ruid != ID_UNCHANGED {
+            if !old.is_privileged() && ruid != old.uid && ruid != old.euid {
+                return false;
+            }
+            cred.uid = ruid;
+        }
+        if euid != ID_UNCHANGED {
+            if !old.is_privileged() && !old.holds_uid(euid) {
+                return false;
+            }
+            cred.euid = euid;
+        }
+        if ruid != ID_UNCHANGED || (euid != ID_UNCHANGED && euid != old.uid) {
+            cred.suid = cred.euid;
+        }
+        true
+    })
+}
+
+pub fn setregid(rgid: usize, egid: usize) -> usize {
+    info!("setregid: {:#x}, {:#x}", rgid, egid);
+    let rgid = rgid as u32;
+    let egid = egid as u32;
+    update_cred(|cred| {
+        let old = cred.clone();
+        if rgid != ID_UNCHANGED {
+            if !old.is_privileged() && rgid != old.gid && rgid != old.egid {
+                return false;
+            }
+            cred.gid = rgid;
+        }
+        if egid != ID_UNCHANGED {
+            if !old.is_privileged() && !old.holds_gid(egid) {
+                return false;
+            }
+            cred.egid = egid;
+        }
+        if rgid != ID_UNCHANGED || (egid != ID_UNCHANGED && egid != old.gid) {
+            cred.sgid = cred.egid;
+        }
+        true
+    })
+}
+
+pub fn setresuid(ruid: usize, euid: usize, suid: usize) -> usize {
+    info!("setresuid: {:#x}, {:#x}, {:#x}", ruid, euid, suid);
+    let ids = [ruid as u32, euid as u32, suid as u32];
+    update_cred(|cred| {
+        if !cred.is_privileged()
+            && ids.iter().any(|&id| id != ID_UNCHANGED && !cred.holds_uid(id))
+        {
+            return false;
+        }
+        let [ruid, euid, suid] = ids;
+        if ruid != ID_UNCHANGED {
+            cred.uid = ruid;
+        }
+        if euid != ID_UNCHANGED {
+            cred.euid = euid;
+        }
+        if suid != ID_UNCHANGED {
+            cred.suid = suid;
+        }
+        true
+    })
+}
+
+pub fn setresgid(rgid: usize, egid: usize, sgid: usize) -> usize {
+    info!("setresgid: {:#x}, {:#x}, {:#x}", rgid, egid, sgid);
+    let ids = [rgid as u32, egid as u32, sgid as u32];
+    update_cred(|cred| {
+        if !cred.is_privileged()
+            && ids.iter().any(|&id| id != ID_UNCHANGED && !cred.holds_gid(id))
+        {
+            return false;
+        }
+        let [rgid, egid, sgid] = ids;
+        if rgid != ID_UNCHANGED {
+            cred.gid = rgid;
+        }
+        if egid != ID_UNCHANGED {
+            cred.egid = egid;
+        }
+        if sgid != ID_UNCHANGED {
+            cred.sgid = sgid;
+        }
+        true
+    })
+}
+
+fn put_ids(ids: [u32; 3], ptrs: [usize; 3]) -> usize {
+    for (id, ptr) in ids.into_iter().zip(ptrs) {
+        if ptr == 0 {
+            return linux_err!(EFAULT);
+        }
+        unsafe { *(ptr as *mut u32) = id; }
+    }
+    0
+}
+
+pub fn getresuid(ruid: usize, euid: usize, suid: usize) -> usize {
+    let ids = {
+        let task = task::current();
+        let cred = task.cred.lock();
+        [cred.uid, cred.euid, cred.suid]
+    };
+    put_ids(ids, [ruid, euid, suid])
+}
+
+pub fn getresgid(rgid: usize, egid: usize, sgid: usize) -> usize {
+    let ids = {
+        let task = task::current();
+        let cred = task.cred.lock();
+        [cred.gid, cred.egid, cred.sgid]
+    };
+    put_ids(ids, [rgid, egid, sgid])
+}
+
+/// Copies the supplementary groups to `list` if `size` is nonzero, and
+/// returns how many there are.
+pub fn getgroups(size: usize, list: usize) -> usize {
+    let groups = task::current().cred.lock().groups.clone();
+    if size == 0 {
+        return groups.len();
+    }
+    if size < groups.len() {
+        return linux_err!(EINVAL);
+    }
+    let ulist = unsafe { core::slice::from_raw_parts_mut(list as *mut u32, groups.len()) };
+    ulist.copy_from_slice(&groups);
+    groups.len()
+}
+
+pub fn setgroups(size: usize, list: usize) -> usize {
+    info!("setgroups: size {}", size);
+    if size > NGROUPS_MAX {
+        return linux_err!(EINVAL);
+    }
+    let groups: Vec<u32> = if size == 0 {
+        Vec::new()
+    } else {
+        unsafe { core::slice::from_raw_parts(list as *const u32, size) }.to_vec()
+    };
+    update_cred(|cred| {
+        if !cred.is_privileged() {
+            return false;
+        }
+        cred.set_groups(groups);
+        true
+    })
+}
@@ -1,13 +1,25 @@
 //! futex
+//!
+//! Waiters are kept in a table keyed by the futex they wait on. A private
+//! futex is identified by the address space and its user address, a shared
+//! one by its physical address, which is the same in each process that maps
+//! it. Waiters sleep interruptibly until a waker takes them off the table.
 
 use alloc::sync::Arc;
-use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 use axerrno::{linux_err, LinuxError};
+use axhal::time::TimeValue;
 use mutex::Mutex;
-use wait_queue::WaitQueue;
+use taskctx::{TaskState, Tid};
+use super::{KernelTimespec, NSEC_PER_SEC};
 
 pub const FUTEX_WAIT: usize = 0;
 pub const FUTEX_WAKE: usize = 1;
+pub const FUTEX_REQUEUE: usize = 3;
+pub const FUTEX_CMP_REQUEUE: usize = 4;
 pub const FUTEX_WAIT_BITSET: usize = 9;
 pub const FUTEX_WAKE_BITSET: usize = 10;
 pub const FUTEX_WAIT_REQUEUE_PI: usize = 11;
@@ -27,14 +39,26 @@ const FLAGS_CLOCKRT:    usize = 0x02;
 //
 const FUTEX_BITSET_MATCH_ANY: usize = 0xffffffff;
 
-static FUTEX_MAP: Mutex<BTreeMap<usize, Arc<WaitQueue>>> = Mutex::new(BTreeMap::new());
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum FutexKey {
+    Private { mm_id: usize, uaddr: usize },
+    Shared { paddr: usize },
+}
+
+struct FutexWaiter {
+    tid: Tid,
+    bitset: u32,
+    /// Cleared by the waker that takes it off the table.
+    queued: Arc<AtomicBool>,
+}
+
+static FUTEX_QUEUES: Mutex<BTreeMap<FutexKey, VecDeque<FutexWaiter>>> =
+    Mutex::new(BTreeMap::new());
 
 pub fn do_futex(
     uaddr: usize, op: usize, val: usize, timeout_or_val2: usize,
     uaddr2: usize, mut val3: usize
 ) -> usize {
-    assert_eq!(uaddr2, 0);
-
     let cmd = op & FUTEX_CMD_MASK;
     let mut flags = 0;
 
@@ -54,79 +78,248 @@ pub fn do_futex(
     match cmd {
         FUTEX_WAIT => {
             val3 = FUTEX_BITSET_MATCH_ANY;
-            return futex_wait(uaddr, flags, val, timeout_or_val2, val3);
+            let deadline = match futex_deadline(timeout_or_val2, false, flags) {
+                Ok(deadline) => deadline,
+                Err(err) => return err,
+            };
+            futex_wait(uaddr, flags, val, deadline, val3)
         },
         FUTEX_WAIT_BITSET => {
-            return futex_wait(uaddr, flags, val, timeout_or_val2, val3);
+            let deadline = match futex_deadline(timeout_or_val2, true, flags) {
+                Ok(deadline) => deadline,
+                Err(err) => return err,
+            };
+            futex_wait(uaddr, flags, val, deadline, val3)
         },
         FUTEX_WAKE => {
             val3 = FUTEX_BITSET_MATCH_ANY;
-            return futex_wake(uaddr, flags, val, val3);
+            futex_wake(uaddr, flags, val, val3)
         },
         FUTEX_WAKE_BITSET => {
-            return futex_wake(uaddr, flags, val, val3);
+            futex_wake(uaddr, flags, val, val3)
+        },
+        FUTEX_REQUEUE => {
+            futex_requeue(uaddr, flags, uaddr2, val, timeout_or_val2, None)
+        },
+        FUTEX_CMP_REQUEUE => {
+            futex_requeue(uaddr, flags, uaddr2, val, timeout_or_val2, Some(val3 as u32))
         },
         _ => {
-            error!("cmd: {:#x}", cmd);
+            error!("uaddr: {:#x} op: {:#x} val {:#x} timeout_or_val2 {:#x} uaddr2 {:#x} val3 {:#x}",
+                uaddr, op, val, timeout_or_val2, uaddr2, val3);
+            linux_err!(ENOSYS)
         },
     }
+}
 
-    panic!("uaddr: {:#x} op: {:#x} val {:#x} timeout_or_val2 {:#x} uaddr2 {:#x} val3 {:#x}",
-        uaddr, op, val, timeout_or_val2, uaddr2, val3);
+/// Gets the deadline of a wait from the user timespec at `utime`, which is
+/// relative for FUTEX_WAIT, and absolute for FUTEX_WAIT_BITSET: on
+/// CLOCK_REALTIME with `FLAGS_CLOCKRT`, on CLOCK_MONOTONIC otherwise.
+/// Deadlines are kept as CLOCK_MONOTONIC times.
+fn futex_deadline(
+    utime: usize, absolute: bool, flags: usize
+) -> Result<Option<TimeValue>, usize> {
+    if utime == 0 {
+        return Ok(None);
+    }
+    let ts = unsafe { &*(utime as *const KernelTimespec) };
+    if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= NSEC_PER_SEC {
+        return Err(linux_err!(EINVAL));
+    }
+    let dur = Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    debug!("futex timeout: {:?} absolute {}", dur, absolute);
+    if absolute && (flags & FLAGS_CLOCKRT) != 0 {
+        Ok(Some(timekeeping::real_to_monotonic(dur)))
+    } else if absolute {
+        Ok(Some(dur))
+    } else {
+        Ok(Some(axhal::time::current_time() + dur))
+    }
+}
+
+#[inline]
+fn read_futex(uaddr: usize) -> u32 {
+    // Todo: use atomic operations. We might need asm code.
+    unsafe { (uaddr as *const u32).read_volatile() }
+}
+
+fn get_futex_key(uaddr: usize, flags: usize) -> Result<FutexKey, usize> {
+    if uaddr % core::mem::size_of::<u32>() != 0 {
+        return Err(linux_err!(EINVAL));
+    }
+    if (flags & FLAGS_SHARED) == 0 {
+        let mm_id = taskctx::current_ctx().mm_id.load(Ordering::Relaxed);
+        return Ok(FutexKey::Private { mm_id, uaddr });
+    }
+
+    let Some(mm) = task::current().try_mm() else {
+        return Err(linux_err!(EFAULT));
+    };
+    // Fault the page in, it may not be mapped yet.
+    read_futex(uaddr);
+    let pgd = mm.read().pgd();
+    let paddr = match pgd.lock().query(uaddr.into()) {
+        Ok((paddr, _, _)) => paddr.as_usize(),
+        Err(_) => return Err(linux_err!(EFAULT)),
+    };
+    Ok(FutexKey::Shared { paddr })
+}
+
+/// Takes the waiter of `queued` off the table, unless a waker did it
+/// already. Returns whether it was still queued.
+fn unqueue(queued: &Arc<AtomicBool>) -> bool {
+    let mut queues = FUTEX_QUEUES.lock();
+    if !queued.load(Ordering::Acquire) {
+        return false;
+    }
+    // It may have been requeued, so look for it everywhere.
+    queues.retain(|_, waiters| {
+        waiters.retain(|w| !Arc::ptr_eq(&w.queued, queued));
+        !waiters.is_empty()
+    });
+    queued.store(false, Ordering::Release);
+    true
 }
 
 fn futex_wait(
-    uaddr: usize, _flags: usize, val: usize, abs_time: usize, bitset: usize
+    uaddr: usize, flags: usize, val: usize, deadline: Option<TimeValue>, bitset: usize
 ) -> usize {
     debug!("futex_wait ...");
-    if abs_time != 0 {
-        let abs_time = abs_time as *const i64;
-        let timeout = unsafe { *abs_time };
-        error!("timeout: {}", timeout);
-    }
-    assert_eq!(bitset, FUTEX_BITSET_MATCH_ANY);
     if bitset == 0 {
         return linux_err!(EINVAL);
     }
+    let key = match get_futex_key(uaddr, flags) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
 
-    // Todo: use atomic operations. We might need asm code.
-    let ptr = uaddr as *const u32;
-    if unsafe { *ptr } != val as u32 {
-        return linux_err!(EAGAIN);
-    }
-
-    let wq = {
-        let mut futex_map = FUTEX_MAP.lock();
-        if let Some(q) = futex_map.get(&uaddr) {
-            q.clone()
-        } else {
-            futex_map.insert(uaddr, Arc::new(WaitQueue::new()));
-            futex_map.get(&uaddr).unwrap().clone()
+    let ctx = taskctx::current_ctx();
+    let queued = Arc::new(AtomicBool::new(true));
+    {
+        let mut queues = FUTEX_QUEUES.lock();
+        // Checked under the lock, as a waker changes the value before
+        // taking it, so that its wakeup can't be missed.
+        if read_futex(uaddr) != val as u32 {
+            return linux_err!(EAGAIN);
+        }
+        queues.entry(key).or_default().push_back(FutexWaiter {
+            tid: ctx.tid(),
+            bitset: bitset as u32,
+            queued: queued.clone(),
+        });
+    }
+
+    if let Some(deadline) = deadline {
+        run_queue::set_alarm_wakeup(deadline, ctx.as_ctx_ref().clone());
+    }
+    let ret = loop {
+        if !queued.load(Ordering::Acquire) {
+            break 0;
+        }
+        if deadline.is_some_and(|deadline| axhal::time::current_time() >= deadline) {
+            break linux_err!(ETIMEDOUT);
+        }
+        if taskctx::signal_pending(&ctx) {
+            break linux_err!(EINTR);
         }
+        run_queue::block_current(TaskState::Interruptible);
     };
+    if deadline.is_some() {
+        run_queue::cancel_alarm(ctx.as_ctx_ref());
+    }
 
-    wq.wait();
+    // Woken up just as it gave up: report the wakeup, not to lose it.
+    if ret != 0 && !unqueue(&queued) {
+        return 0;
+    }
     debug!("futex_wait ok!");
-    return 0;
+    ret
+}
+
+/// Takes up to `nr_wake` waiters matching `bitset` off the queue of `key`.
+fn dequeue_waiters(
+    queues: &mut BTreeMap<FutexKey, VecDeque<FutexWaiter>>,
+    key: &FutexKey, nr_wake: usize, bitset: u32
+) -> Vec<Tid> {
+    let mut woken = Vec::new();
+    if let Some(waiters) = queues.get_mut(key) {
+        waiters.retain(|w| {
+            if woken.len() < nr_wake && (w.bitset & bitset) != 0 {
+                w.queued.store(false, Ordering::Release);
+                woken.push(w.tid);
+                false
+            } else {
+                true
+            }
+        });
+        if waiters.is_empty() {
+            queues.remove(key);
+        }
+    }
+    woken
 }
 
 fn futex_wake(
-    uaddr: usize, _flags: usize, nr_wake: usize, bitset: usize
+    uaddr: usize, flags: usize, nr_wake: usize, bitset: usize
 ) -> usize {
-    assert_eq!(nr_wake, 1);
-    assert_eq!(bitset, FUTEX_BITSET_MATCH_ANY);
     if bitset == 0 {
         return linux_err!(EINVAL);
     }
+    let key = match get_futex_key(uaddr, flags) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
 
-    let mut futex_map = FUTEX_MAP.lock();
-    if let Some(wq) = futex_map.get(&uaddr) {
-        wq.notify_one(true);
-        if wq.count() == 0 {
-            futex_map.remove(&uaddr);
+    let woken = {
+        let mut queues = FUTEX_QUEUES.lock();
+        dequeue_waiters(&mut queues, &key, nr_wake, bitset as u32)
+    };
+    for tid in woken.iter() {
+        run_queue::wake_up_task(*tid);
+    }
+    woken.len()
+}
+
+/// Wakes up `nr_wake` waiters of `uaddr`, and moves up to `nr_requeue` of
+/// the others to `uaddr2`. With `cmpval`, fails with EAGAIN unless
+/// `uaddr` still holds it.
+fn futex_requeue(
+    uaddr: usize, flags: usize, uaddr2: usize,
+    nr_wake: usize, nr_requeue: usize, cmpval: Option<u32>
+) -> usize {
+    let key1 = match get_futex_key(uaddr, flags) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
+    let key2 = match get_futex_key(uaddr2, flags) {
+        Ok(key) => key,
+        Err(err) => return err,
+    };
+
+    let (woken, nr_requeued) = {
+        let mut queues = FUTEX_QUEUES.lock();
+        if cmpval.is_some_and(|cmpval| read_futex(uaddr) != cmpval) {
+            return linux_err!(EAGAIN);
         }
-    } else {
-        error!("futex_wake no wq uaddr {:#x}", uaddr);
+        let woken = dequeue_waiters(&mut queues, &key1, nr_wake, u32::MAX);
+        let mut moved = VecDeque::new();
+        if key1 != key2 {
+            if let Some(waiters) = queues.get_mut(&key1) {
+                let n = nr_requeue.min(waiters.len());
+                moved.extend(waiters.drain(..n));
+                if waiters.is_empty() {
+                    queues.remove(&key1);
+                }
+            }
+        }
+        let nr_requeued = moved.len();
+        if nr_requeued > 0 {
+            queues.entry(key2).or_default().append(&mut moved);
+        }
+        (woken, nr_requeued)
+    };
+    for tid in woken.iter() {
+        run_queue::wake_up_task(*tid);
     }
-    return nr_wake;
+    woken.len() + nr_requeued
 }
@@ -1,22 +1,49 @@
 #![cfg_attr(not(test), no_std)]
 
+use alloc::vec::Vec;
 use core::sync::atomic::Ordering;
-use taskctx::Tid;
+use taskctx::{CtxRef, Tid};
 use axtype::PAGE_SIZE;
 use axerrno::{LinuxResult, LinuxError, linux_err_from};
 use taskctx::TaskState;
 use axtype::{RLimit64, RLIM_NLIMITS};
-use axtype::{RLIMIT_DATA, RLIMIT_STACK, RLIMIT_CORE, RLIMIT_NOFILE};
+use axtype::{RLIMIT_NOFILE, NR_OPEN};
+use timekeeping::{CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_REALTIME};
 pub use futex::{do_futex, FUTEX_WAKE};
+pub use cred::{getuid, geteuid, getgid, getegid, getresuid, getresgid, getgroups};
+pub use cred::{setuid, setgid, setreuid, setregid, setresuid, setresgid, setgroups};
+pub use time::{clock_gettime, clock_getres, clock_settime, gettimeofday, settimeofday};
+pub use syslog::syslog;
+pub use uts::{sethostname, setdomainname};
+pub use reboot::reboot;
 
 mod futex;
+mod cred;
+mod time;
+mod syslog;
+mod uts;
+mod reboot;
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
 const WNOHANG: usize = 0x00000001;
+const WUNTRACED: usize = 0x00000002;
 const WEXITED: usize = 0x00000004;
+const WCONTINUED: usize = 0x00000008;
+const WNOWAIT: usize = 0x01000000;
+const __WNOTHREAD: usize = 0x20000000;
+const __WALL: usize = 0x40000000;
+const __WCLONE: usize = 0x80000000;
+
+// si_code of SIGCHLD
+pub const CLD_EXITED: i32 = 1;     // child has exited
+pub const CLD_STOPPED: i32 = 5;    // child has stopped
+pub const CLD_CONTINUED: i32 = 6;  // stopped child has continued
+
+// si_code of a signal sent by the kernel itself
+const SI_KERNEL: i32 = 0x80;
 
 // Used in tsk->exit_state:
 const EXIT_DEAD: usize = 0x0010;
@@ -38,53 +65,201 @@ enum PidType {
 }
 
 pub fn gettid() -> usize {
-    taskctx::current_ctx().tid()
+    taskctx::current_ctx().vtid()
 }
 
 pub fn getpid() -> usize {
-    taskctx::current_ctx().tgid()
+    task::pid_vnr(taskctx::current_ctx().tgid())
+}
+
+/// Stores the CPU the caller runs on, and its NUMA node, which is 0.
+pub fn getcpu(cpu: usize, node: usize) -> usize {
+    if cpu != 0 {
+        unsafe { *(cpu as *mut u32) = taskctx::current_ctx().cpu() as u32 };
+    }
+    if node != 0 {
+        unsafe { *(node as *mut u32) = 0 };
+    }
+    0
 }
 
+/// The parent of the init of a pid namespace is outside of it, so it's 0.
 pub fn getppid() -> usize {
-    let ppid = taskctx::current_ctx().real_parent.as_ref().unwrap().tid();
+    let ppid = task::current().parent().map_or(0, |parent| task::pid_vnr(parent.tgid()));
     info!("getppid: {}", ppid);
     ppid
 }
 
-pub fn getgid() -> usize {
-    let task = task::current();
-    let cred = task.cred.lock();
-    cred.gid as usize
+/// Moves the process `pid` (0 for the current one) into the process group
+/// `pgid` of its session (0 for its own), which it may create. It's allowed
+/// on the current process and its children.
+pub fn setpgid(pid: usize, pgid: usize) -> usize {
+    use axerrno::linux_err;
+
+    info!("setpgid pid {} pgid {}", pid, pgid);
+    if (pgid as isize) < 0 {
+        return linux_err!(EINVAL);
+    }
+    let current = task::current();
+    let pid = if pid == 0 {
+        current.tgid()
+    } else {
+        match task::find_vpid(pid) {
+            Some(tid) => tid,
+            None => return linux_err!(ESRCH),
+        }
+    };
+    let pgid = if pgid == 0 {
+        pid
+    } else {
+        match task::find_vpid(pgid) {
+            Some(tid) => tid,
+            None => return linux_err!(EPERM),
+        }
+    };
+
+    let target = if pid == current.tgid() {
+        task::get_task(pid)
+    } else if thread_group_children().contains(&pid) {
+        task::get_task(pid).filter(|t| t.sid() == current.sid())
+    } else {
+        None
+    };
+    let Some(target) = target else {
+        return linux_err!(ESRCH);
+    };
+    if target.is_session_leader() {
+        return linux_err!(EPERM);
+    }
+    if pgid != pid && !pgrp_in_session(pgid, current.sid()) {
+        return linux_err!(EPERM);
+    }
+    for tid in target.thread_group() {
+        if let Some(t) = task::get_task(tid) {
+            t.pgid.store(pgid, Ordering::Relaxed);
+        }
+    }
+    0
 }
 
-pub fn getegid() -> usize {
-    let task = task::current();
-    let cred = task.cred.lock();
-    cred.egid as usize
+/// The process the current task sees as `pid`, or itself for 0.
+fn find_process(pid: usize) -> Option<task::TaskRef> {
+    let pid = if pid == 0 { task::current().tgid() } else { task::find_vpid(pid)? };
+    task::get_task(pid).filter(|t| t.is_group_leader())
 }
 
-pub fn setpgid(pid: usize, pgid: usize) -> usize {
-    warn!("impl setpgid pid {} pgid {}", pid, pgid);
-    0
+pub fn getpgid(pid: usize) -> usize {
+    use axerrno::linux_err;
+
+    match find_process(pid) {
+        Some(task) => task::pid_vnr(task.pgid()),
+        None => linux_err!(ESRCH),
+    }
+}
+
+pub fn getsid(pid: usize) -> usize {
+    use axerrno::linux_err;
+
+    match find_process(pid) {
+        Some(task) => task::pid_vnr(task.sid()),
+        None => linux_err!(ESRCH),
+    }
+}
+
+/// Makes the current process the leader of a new session and of a new
+/// process group in it, without controlling terminal. Fails if it leads a
+/// process group already.
+pub fn setsid() -> usize {
+    use axerrno::linux_err;
+
+    let current = task::current();
+    let sid = current.tgid();
+    if pgrp_members(sid).next().is_some() {
+        return linux_err!(EPERM);
+    }
+    for tid in current.thread_group() {
+        if let Some(t) = task::get_task(tid) {
+            t.sid.store(sid, Ordering::Relaxed);
+            t.pgid.store(sid, Ordering::Relaxed);
+            t.tty.store(0, Ordering::Relaxed);
+        }
+    }
+    info!("setsid: {}", sid);
+    task::pid_vnr(sid)
+}
+
+/// Processes in the process group `pgid`, by their group leaders.
+pub fn pgrp_members(pgid: usize) -> impl Iterator<Item = task::TaskRef> {
+    task::all_tasks().into_iter().filter(move |t| {
+        t.is_group_leader() && t.pgid() == pgid && t.exit_state.load(Ordering::Acquire) == 0
+    })
+}
+
+/// Whether the process group `pgid` exists in the session `sid`, e.g. for
+/// the tty layer to check a new foreground group.
+pub fn pgrp_in_session(pgid: usize, sid: usize) -> bool {
+    pgrp_members(pgid).any(|t| t.sid() == sid)
 }
 
 // Refer to "include/asm-generic/resource.h"
-pub fn prlimit64(tid: Tid, resource: usize, new_rlim: usize, old_rlim: usize) -> usize {
+/// Gets and/or sets a resource limit of the process `pid`, 0 for the
+/// current one. Raising a hard limit needs privilege, as does changing the
+/// limits of a process with other credentials.
+pub fn prlimit64(pid: usize, resource: usize, new_rlim: usize, old_rlim: usize) -> usize {
+    use axerrno::linux_err;
+
     info!(
-        "linux_syscall_prlimit64: tid {}, resource: {}, {:?} {:?}",
-        tid, resource, new_rlim, old_rlim
+        "linux_syscall_prlimit64: pid {}, resource: {}, {:#x} {:#x}",
+        pid, resource, new_rlim, old_rlim
     );
-    assert!(tid == 0);
-    assert!(resource < RLIM_NLIMITS);
-    assert!(matches!(resource, RLIMIT_DATA|RLIMIT_STACK|RLIMIT_CORE|RLIMIT_NOFILE));
+    if resource >= RLIM_NLIMITS {
+        return linux_err!(EINVAL);
+    }
+    let Some(target) = find_process(pid) else {
+        return linux_err!(ESRCH);
+    };
     let current = task::current();
+    let new = if new_rlim != 0 {
+        Some(unsafe { *(new_rlim as *const RLimit64) })
+    } else {
+        None
+    };
+    if new.is_some() && target.tgid() != current.tgid() {
+        let cred = current.cred();
+        let tcred = target.cred();
+        if !cred.is_privileged() && (cred.uid != tcred.uid || cred.uid != tcred.suid) {
+            return linux_err!(EPERM);
+        }
+    }
+
+    let mut rlim = target.rlim.lock();
+    let old = rlim[resource];
+    if let Some(new) = new {
+        if new.rlim_cur > new.rlim_max {
+            return linux_err!(EINVAL);
+        }
+        if resource == RLIMIT_NOFILE && new.rlim_max > NR_OPEN {
+            return linux_err!(EPERM);
+        }
+        if new.rlim_max > old.rlim_max && !current.cred().is_privileged() {
+            return linux_err!(EPERM);
+        }
+        rlim[resource] = new;
+    }
     if old_rlim != 0 {
-        let old_rlim = old_rlim as *mut RLimit64;
-        unsafe { *old_rlim = current.rlim[resource]; }
+        unsafe { *(old_rlim as *mut RLimit64) = old; }
     }
     0
 }
 
+pub fn getrlimit(resource: usize, rlim: usize) -> usize {
+    prlimit64(0, resource, 0, rlim)
+}
+
+pub fn setrlimit(resource: usize, rlim: usize) -> usize {
+    prlimit64(0, resource, rlim, 0)
+}
+
 #[cfg(target_arch = "x86_64")]
 pub fn arch_prctl(code: usize, addr: usize) -> usize {
     use axerrno::linux_err;
@@ -107,179 +282,218 @@ pub fn arch_prctl(code: usize, addr: usize) -> usize {
     }
 }
 
-pub fn setuid(uid: usize) -> usize {
-    let task = task::current();
-    let mut cred = task.cred.lock();
-    cred.uid = uid as u32;
-    cred.euid = uid as u32;
-    cred.fsuid = uid as u32;
-    // Todo: handle [suid] according to capability
-    0
+#[repr(C)]
+struct KernelTimeval {
+    tv_sec: i64,
+    tv_usec: i64,
 }
 
-pub fn setreuid(ruid: usize, euid: usize) -> usize {
-    info!("setresuid: {:#x}, {:#x}", ruid, euid);
-    let ruid = ruid as u32;
-    let euid = euid as u32;
-
-    let task = task::current();
-    let mut cred = task.cred.lock();
-    cred.uid = ruid;
-    cred.euid = euid;
-    cred.fsuid = euid;
-    0
+impl KernelTimeval {
+    fn from_nanos(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / 1_000_000_000) as i64,
+            tv_usec: ((ns % 1_000_000_000) / 1_000) as i64,
+        }
+    }
 }
 
-pub fn setresuid(ruid: usize, euid: usize, suid: usize) -> usize {
-    info!("setresuid: {:#x}, {:#x}, {:#x}", ruid, euid, suid);
-    let ruid = ruid as u32;
-    let euid = euid as u32;
-    let suid = suid as u32;
-
-    let task = task::current();
-    let mut cred = task.cred.lock();
-    cred.uid = ruid;
-    cred.euid = euid;
-    cred.suid = suid;
-    cred.fsuid = euid;
-    0
+#[repr(C)]
+struct Rusage {
+    ru_utime: KernelTimeval,
+    ru_stime: KernelTimeval,
+    // Todo: maxrss, faults, context switches, ...
+    _unused: [i64; 14],
 }
 
-pub fn setgid(gid: usize) -> usize {
-    info!("setgid: {}", gid);
-    let task = task::current();
-    let mut cred = task.cred.lock();
-    cred.gid = gid as u32;
-    cred.egid = gid as u32;
-    cred.fsgid = gid as u32;
-    // Todo: handle [sgid] according to capability
-    0
+/// A child reported by [`wait_for_child`].
+pub struct WaitResult {
+    pub tid: Tid,
+    /// Wait status, as `wstatus` of wait4.
+    pub status: u32,
+    /// CPU time of the child in user and kernel mode, in nanoseconds.
+    pub utime_ns: u64,
+    pub stime_ns: u64,
 }
 
 pub fn wait4(pid: usize, wstatus: usize, options: usize, rusage: usize) -> usize {
+    use axerrno::linux_err;
+
     let pid = pid as isize;
     info!("wait4: pid {:#X} wstatus {:#X} options {:#X} rusage {:#X}",
            pid, wstatus, options, rusage);
 
-    if rusage != 0 {
-        // Todo: deal with rusage in future.
-        warn!("+++ Handle rusage in wait4 +++");
-    }
-    if options != 0 {
-        // Todo: deal with options in future.
-        warn!("+++ Handle options in wait4 +++");
-    }
-    if (options & WNOHANG) != 0 {
-        warn!("WNOHANG");
+    if (options & !(WNOHANG|WUNTRACED|WCONTINUED|__WNOTHREAD|__WALL|__WCLONE)) != 0 {
+        return linux_err!(EINVAL);
     }
 
-    let pid_type =
-        if pid == -1 {
-            PidType::MAX
-        } else if pid < 0 {
-            //pid = find_get_pid(-pid);
-            PidType::PGID
-        } else if pid == 0 {
-            //pid = get_task_pid(current, PIDTYPE_PGID);
-            PidType::PGID
-        } else /* pid > 0 */ {
-            PidType::PID
-        };
-
-    let mut status = 0u32;
-    let tid = match do_wait(pid_type, pid as usize, options|WEXITED, &mut status) {
-        Ok(tid) => tid,
-        Err(e) => linux_err_from!(e),
-        //Err(e) => panic!("do_wait err: {:?}", e),
+    let result = match wait_for_child(pid, options|WEXITED) {
+        Ok(Some(result)) => result,
+        Ok(None) => return 0,
+        Err(e) => return linux_err_from!(e),
     };
 
     if wstatus != 0 {
         let wstatus = wstatus as *mut u32;
         unsafe {
-            (*wstatus) = status;
+            (*wstatus) = result.status;
         }
     }
-    tid
+    if rusage != 0 {
+        let rusage = rusage as *mut Rusage;
+        unsafe {
+            *rusage = Rusage {
+                ru_utime: KernelTimeval::from_nanos(result.utime_ns),
+                ru_stime: KernelTimeval::from_nanos(result.stime_ns),
+                _unused: [0; 14],
+            };
+        }
+    }
+    task::pid_vnr(result.tid)
 }
 
-fn do_wait(
-    pid_type: PidType, tid: Tid, options: usize, status: &mut u32
-) -> LinuxResult<Tid> {
-    info!("do_wait: pidtype {:?} pid {:#X} options {:#X}; curr {}",
-          pid_type, tid, options, taskctx::current_ctx().tid());
+/// Waits for a child chosen by `pid` as in wait4 to exit, or with
+/// WUNTRACED and WCONTINUED, to stop or continue. An exited child is reaped
+/// unless WNOWAIT is set, which frees all of its resources.
+///
+/// `pid` is one of the pid namespace of the caller, the tid in the result
+/// is not.
+///
+/// Returns `None` with WNOHANG if no child has changed its state yet.
+pub fn wait_for_child(pid: isize, options: usize) -> LinuxResult<Option<WaitResult>> {
+    let (pid_type, id) =
+        if pid == -1 {
+            (PidType::MAX, 0)
+        } else if pid < 0 {
+            (PidType::PGID, task::find_vpid((-pid) as usize).ok_or(LinuxError::ECHILD)?)
+        } else if pid == 0 {
+            (PidType::PGID, task::current().pgid())
+        } else /* pid > 0 */ {
+            (PidType::PID, task::find_vpid(pid as usize).ok_or(LinuxError::ECHILD)?)
+        };
+    do_wait(pid_type, id, options)
+}
 
-    // Todo: sleep with intr
-    //set_current_state(TASK_INTERRUPTIBLE);
+/// Waits for a child: the one of tid `id` for `PidType::PID`, one in the
+/// process group `id` for `PidType::PGID`, or any child.
+fn do_wait(
+    pid_type: PidType, id: usize, options: usize
+) -> LinuxResult<Option<WaitResult>> {
+    let ctx = taskctx::current_ctx();
+    info!("do_wait: pidtype {:?} id {:#X} options {:#X}; curr {}",
+          pid_type, id, options, ctx.tid());
 
     loop {
-        if pid_type == PidType::PID {
-            if let Some(tid) = wait_pid(tid, status) {
-                return Ok(tid);
-            }
-        } else {
-            if children_count() > 0 {
-                if let Some(tid) = wait_children(status) {
-                    return Ok(tid);
-                }
-            }
-
-            if siblings_count() > 0 {
-                let ctx = taskctx::current_ctx();
-                for sibling in ctx.siblings.lock().iter() {
-                    panic!("sibling[{}]", sibling);
-                    // Todo: query tid_map to get sibling reference.
-                    /*
-                    for child in sibling.children.lock().iter() {
-                        info!("Current[{}]: has child[{}]",
-                              sibling.tid(), child);
-                    }
-                    */
-                }
+        // Tracees are waited for as children too.
+        let mut children = thread_group_children();
+        for tid in ptrace::tracees(task::current().tgid()) {
+            if !children.contains(&tid) {
+                children.push(tid);
             }
-
-            if children_count() == 0 && siblings_count() == 0 {
-                return Err(LinuxError::ECHILD);
+        }
+        let candidates: Vec<Tid> = match pid_type {
+            PidType::PID => children.into_iter().filter(|&cid| cid == id).collect(),
+            PidType::PGID => children.into_iter().filter(|&cid| {
+                task::get_task(cid).is_some_and(|child| child.pgid() == id)
+            }).collect(),
+            _ => children,
+        };
+        if candidates.is_empty() {
+            return Err(LinuxError::ECHILD);
+        }
+        for child in candidates {
+            info!("Task[{}]: has child[{}]", ctx.tid(), child);
+            if let Some(result) = wait_task(child, options) {
+                return Ok(Some(result));
             }
         }
+        if (options & WNOHANG) != 0 {
+            return Ok(None);
+        }
+        if taskctx::signal_pending(&ctx) {
+            return Err(LinuxError::EINTR);
+        }
 
-        // Todo: wait on Exit_WaitQueue of child and resched.
-        let task = task::current();
-        let rq = run_queue::task_rq(&task.sched_info);
-        rq.lock().resched(false);
+        // Children wake us up as they exit, stop or continue, see
+        // `exit_notify` and `do_notify_parent_cldstop`.
+        run_queue::block_current(TaskState::Interruptible);
     }
 }
 
-fn wait_pid(tid: Tid, status: &mut u32) -> Option<Tid> {
-    let tid = wait_task_zombie(tid, status)?;
-
-    let ctx = taskctx::current_ctx();
-    ctx.children.lock().retain(|&cid| cid != tid);
-    return Some(tid);
+/// Scheduling contexts of the thread group of the current task. Any of
+/// its threads may wait for the children of the others.
+fn thread_group_ctxs() -> Vec<CtxRef> {
+    task::current()
+        .thread_group()
+        .into_iter()
+        .filter_map(task::get_task)
+        .map(|t| t.sched_info.clone())
+        .collect()
 }
 
-fn children_count() -> usize {
-    taskctx::current_ctx().children.lock().len()
+fn thread_group_children() -> Vec<Tid> {
+    thread_group_ctxs()
+        .iter()
+        .flat_map(|ctx| ctx.children.lock().clone())
+        .collect()
 }
 
-fn siblings_count() -> usize {
-    taskctx::current_ctx().siblings.lock().len()
+fn forget_child(tid: Tid) {
+    for ctx in thread_group_ctxs() {
+        ctx.children.lock().retain(|&cid| cid != tid);
+    }
 }
 
-fn wait_children(status: &mut u32) -> Option<Tid> {
-    let ctx = taskctx::current_ctx();
-    for (index, child) in ctx.children.lock().iter().enumerate() {
-        info!("Current[{}]: has child[{}]", ctx.tid(), child);
-        if let Some(tid) = wait_task_zombie(*child, status) {
-            ctx.children.lock().remove(index);
-            return Some(tid);
+/// Reports the change of state of `tid` that `options` asks for, if any.
+fn wait_task(tid: Tid, options: usize) -> Option<WaitResult> {
+    let target = task::get_task(tid)?;
+    let report = |status| WaitResult {
+        tid,
+        status,
+        utime_ns: target.sched_info.utime_ns(),
+        stime_ns: target.sched_info.stime_ns(),
+    };
+
+    if ptrace::is_tracer_of(&target) {
+        // Its stops for the tracer are reported whatever the options.
+        if let Some(status) = ptrace::wait_stopped(&target, (options & WNOWAIT) != 0) {
+            return Some(report(status));
+        }
+        // A tracee of another parent can't be reaped by the tracer: its
+        // exit is reported, and it is let go.
+        let is_child = target.parent().is_some_and(|p| p.tgid() == task::current().tgid());
+        if !is_child && target.exit_state.load(Ordering::Acquire) != 0 {
+            if (options & WNOWAIT) == 0 {
+                ptrace::release_task(&target);
+            }
+            return Some(report(target.sched_info.exit_code()));
         }
     }
-    None
+
+    if (options & WEXITED) != 0 && target.exit_state.load(Ordering::Acquire) == EXIT_ZOMBIE {
+        if (options & WNOWAIT) != 0 {
+            return Some(report(target.sched_info.exit_code()));
+        }
+        return wait_task_zombie(tid).map(report);
+    }
+
+    let job_status = target.job_status.load(Ordering::Acquire);
+    let wanted = (is_stopped_status(job_status) && (options & WUNTRACED) != 0) ||
+        (job_status == CONTINUED_STATUS && (options & WCONTINUED) != 0);
+    if !wanted {
+        return None;
+    }
+    // Reported only once, unless WNOWAIT.
+    if (options & WNOWAIT) == 0 &&
+        target.job_status.compare_exchange(job_status, 0,
+            Ordering::AcqRel, Ordering::Relaxed).is_err() {
+        return None;
+    }
+    Some(report(job_status))
 }
 
-fn wait_task_zombie(tid: Tid, status: &mut u32) -> Option<Tid> {
+fn wait_task_zombie(tid: Tid) -> Option<u32> {
     info!("wait_task_zombie tid {}", tid);
-    let target = task::get_task(tid).unwrap();
+    let target = task::get_task(tid)?;
     let exit_state = target.exit_state.compare_exchange(
         EXIT_ZOMBIE, EXIT_DEAD,
         Ordering::Relaxed, Ordering::Relaxed
@@ -288,23 +502,116 @@ fn wait_task_zombie(tid: Tid, status: &mut u32) -> Option<Tid> {
         return None;
     }
 
+    forget_child(tid);
+    ptrace::release_task(&target);
+    // The last reference but the one of its CPU, which drops it after
+    // switching away. Then the kernel stack is freed.
     task::unregister_task(tid);
-    *status = target.exit_code.load(Ordering::Relaxed);
-    Some(tid)
+    Some(target.sched_info.exit_code())
+}
+
+/// Wait status of a child continued by SIGCONT.
+const CONTINUED_STATUS: u32 = 0xffff;
+
+/// Wait status of a child stopped by `signo`.
+fn stopped_status(signo: usize) -> u32 {
+    ((signo as u32) << 8) | 0x7f
+}
+
+fn is_stopped_status(status: u32) -> bool {
+    (status & 0xff) == 0x7f
+}
+
+/// Records that the current task has stopped by `signo`, or continued if
+/// `signo` is 0, for its parent to wait for, and lets the parent know.
+pub fn do_notify_parent_cldstop(signo: usize) {
+    let task = task::current();
+    let (status, code) = if signo != 0 {
+        (stopped_status(signo), CLD_STOPPED)
+    } else {
+        (CONTINUED_STATUS, CLD_CONTINUED)
+    };
+    task.job_status.store(status, Ordering::Release);
+    if let Some(parent) = task.parent() {
+        notify_parent(&task, &parent, code);
+    }
+}
+
+/// Sends SIGCHLD with `code` to `parent`, and wakes up its threads that
+/// may wait for `task`.
+fn notify_parent(task: &task::TaskStruct, parent: &task::TaskStruct, code: i32) {
+    // Todo: SA_NOCLDSTOP of the parent.
+    let signo = if code == CLD_EXITED { task.exit_signal } else { task::SIGCHLD as i32 };
+    if signo > 0 {
+        parent.send_sig_info(task::SigInfo {
+            signo,
+            errno: 0,
+            code,
+            tid: task.tid(),
+        });
+    }
+    for tid in parent.thread_group() {
+        run_queue::wake_up_task(tid);
+    }
+}
+
+const TIMER_ABSTIME: usize = 1;
+const NSEC_PER_SEC: i64 = 1_000_000_000;
+
+#[repr(C)]
+struct KernelTimespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+/// Sleeps on the clock `clockid` for the relative (or absolute, with
+/// `TIMER_ABSTIME`) time in `req`.
+pub fn clock_nanosleep(clockid: usize, flags: usize, req: usize, rem: usize) -> usize {
+    use axerrno::linux_err;
+    use core::time::Duration;
+
+    if clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC && clockid != CLOCK_BOOTTIME {
+        return linux_err!(EINVAL);
+    }
+    let req = unsafe { &*(req as *const KernelTimespec) };
+    if req.tv_sec < 0 || req.tv_nsec < 0 || req.tv_nsec >= NSEC_PER_SEC {
+        return linux_err!(EINVAL);
+    }
+    let dur = Duration::new(req.tv_sec as u64, req.tv_nsec as u32);
+    debug!("clock_nanosleep: clock {} flags {:#x} {:?}", clockid, flags, dur);
+    let deadline = if (flags & TIMER_ABSTIME) != 0 && clockid == CLOCK_REALTIME {
+        timekeeping::real_to_monotonic(dur)
+    } else if (flags & TIMER_ABSTIME) != 0 {
+        dur
+    } else {
+        axhal::time::current_time() + dur
+    };
+    if let Err(err) = run_queue::sleep_until_interruptible(deadline) {
+        // A relative sleep tells how much of it is left.
+        if (flags & TIMER_ABSTIME) == 0 && rem != 0 {
+            let left = deadline.saturating_sub(axhal::time::current_time());
+            let rem = unsafe { &mut *(rem as *mut KernelTimespec) };
+            rem.tv_sec = left.as_secs() as i64;
+            rem.tv_nsec = left.subsec_nanos() as i64;
+        }
+        return linux_err_from!(err);
+    }
+    0
 }
 
 /// Exits the current task.
 pub fn exit(exit_code: u32) -> ! {
     info!("task {} exit [{}] ...", taskctx::current_ctx().tid(), exit_code);
-    do_exit(exit_code)
+    do_exit((exit_code & 0xff) << 8)
 }
 
 /// Exits the current task group.
 pub fn exit_group(exit_code: u32) -> ! {
     info!("exit_group ... [{}]", exit_code);
-    do_group_exit(exit_code)
+    do_group_exit((exit_code & 0xff) << 8)
 }
 
+/// Exits the current task group with the wait status `exit_code`.
 pub fn do_group_exit(exit_code: u32) -> ! {
     debug!("do_exit_group ... [{}]", exit_code);
     do_exit(exit_code)
@@ -320,9 +627,82 @@ pub fn do_umask(mode: u32) -> usize {
 }
 
 fn do_exit(exit_code: u32) -> ! {
+    // While the tracer may still read its memory.
+    ptrace::exit_ptrace(exit_code);
     exit_mm();
+    zap_pid_ns_processes();
+    forget_original_parent();
     exit_notify(exit_code);
-    do_task_dead()
+    do_task_dead(exit_code)
+}
+
+/// Kills the other tasks of the pid namespace whose init is exiting, as
+/// they can't have it as reaper anymore. No task may join the namespace
+/// from then on.
+fn zap_pid_ns_processes() {
+    let task = task::current();
+    if !task.is_child_reaper() || !task.is_group_leader() {
+        return;
+    }
+    let tgid = task.tgid();
+    task.pid_ns.disable_pid_allocation();
+    for t in task.pid_ns.tids().into_iter().filter_map(task::get_task) {
+        if t.tgid() != tgid && t.exit_state.load(Ordering::Acquire) == 0 {
+            debug!("zap_pid_ns_processes: kill {}", t.tid());
+            t.send_sig_info(task::SigInfo {
+                signo: task::SIGKILL as i32,
+                errno: 0,
+                code: SI_KERNEL,
+                tid: 0,
+            });
+        }
+    }
+}
+
+/// Hands the children of the exiting task over to another thread of its
+/// group, or to the init of its pid namespace if it's the last one, which
+/// then reaps them.
+fn forget_original_parent() {
+    let task = task::current();
+    let children = core::mem::take(&mut *task.sched_info.children.lock());
+    if children.is_empty() {
+        return;
+    }
+    let reaper = task.thread_group()
+        .into_iter()
+        .filter(|&tid| tid != task.tid())
+        .filter_map(task::get_task)
+        .find(|t| t.exit_state.load(Ordering::Acquire) == 0)
+        .or_else(|| reaper_of(&task));
+    let Some(reaper) = reaper else {
+        return;
+    };
+    debug!("reparent children of {} to {}: {:?}", task.tid(), reaper.tid(), children);
+
+    let mut has_zombie = false;
+    for child in children.iter().filter_map(|&tid| task::get_task(tid)) {
+        child.real_parent.store(reaper.tid(), Ordering::Release);
+        has_zombie |= child.exit_state.load(Ordering::Acquire) == EXIT_ZOMBIE;
+    }
+    reaper.sched_info.children.lock().extend(children);
+    if has_zombie {
+        for tid in reaper.thread_group() {
+            run_queue::wake_up_task(tid);
+        }
+    }
+}
+
+/// The init of the pid namespace of `task`, or the global one if that is
+/// `task` itself, whose children then outlive the namespace.
+fn reaper_of(task: &task::TaskStruct) -> Option<task::TaskRef> {
+    let mut ns = Some(&task.pid_ns);
+    while let Some(cur) = ns {
+        if let Some(reaper) = cur.child_reaper().filter(|&tid| tid != task.tgid()) {
+            return task::get_task(reaper);
+        }
+        ns = cur.parent();
+    }
+    None
 }
 
 fn exit_mm_release() {
@@ -330,13 +710,10 @@ fn exit_mm_release() {
     mm_release();
 }
 
+/// Clears the tid set by `CLONE_CHILD_CLEARTID` or `set_tid_address`, and
+/// wakes up a thread joining us on it.
 fn mm_release() {
     let mut ctx = task::current_ctx();
-    // Todo: temporary solution.
-    // If I'm the main-thread, I don't need to notify parent by futex.
-    if ctx.group_leader.is_none() {
-        return;
-    }
     if ctx.clear_child_tid != 0 {
         put_user_u32(0, ctx.clear_child_tid);
         do_futex(ctx.clear_child_tid, FUTEX_WAKE, 1, 0, 0, 0);
@@ -356,8 +733,8 @@ fn exit_mm() {
 
     exit_mm_release();
 
-    if task.sched_info.group_leader.is_some() {
-        // Todo: dont release mm for threads.
+    if !task.is_group_leader() || !task.sched_info.siblings.lock().is_empty() {
+        // Todo: dont release mm while other threads use it.
         // It's just a temp solution. Implement page refcount.
         return;
     }
@@ -369,39 +746,48 @@ fn exit_mm() {
     }
 
     let mm = task.mm();
-    let mut locked_mm = mm.lock();
+    let locked_mm = mm.write();
     loop {
-        if let Some((va, dva)) = &locked_mm.mapped.pop_first() {
-            let _ = locked_mm.unmap_region((*va).into(), PAGE_SIZE);
-            axalloc::global_allocator().dealloc_pages(*dva, 1);
-        } else {
+        let Some((va, dva)) = locked_mm.mapped.pop_first() else {
             break;
-        }
+        };
+        let _ = locked_mm.unmap_region(va, PAGE_SIZE);
+        axalloc::global_allocator().dealloc_pages(dva, 1);
     }
 }
 
 fn exit_notify(exit_code: u32) {
     let task = task::current();
     debug!("exit_notify: tid {} code {}", task.tid(), exit_code);
-    task.exit_code.store(exit_code, Ordering::Relaxed);
-    task.exit_state.store(EXIT_ZOMBIE, Ordering::Relaxed);
-    // Todo: wakeup parent
     task.complete_vfork_done();
+    if task.exit_signal == -1 {
+        // A thread reaps itself: nobody waits for it.
+        task.exit_state.store(EXIT_DEAD, Ordering::Relaxed);
+        if let Some(leader) = &task.sched_info.group_leader {
+            leader.siblings.lock().retain(|&tid| tid != task.tid());
+        }
+        task::unregister_task(task.tid());
+        return;
+    }
+    // Set before the parent can see us as a zombie and reap us.
+    task.sched_info.set_exit_code(exit_code);
+    task.exit_state.store(EXIT_ZOMBIE, Ordering::Release);
+    // Any thread of the parent may wait for us.
+    if let Some(parent) = task.parent() {
+        notify_parent(&task, &parent, CLD_EXITED);
+    }
+    ptrace::exit_notify();
 }
 
-fn do_task_dead() -> ! {
-    let task = task::current();
-    info!("do_task_dead ... tid {}", task.tid());
-
-    // Causes final put_task_struct in finish_task_switch():
-    task.set_state(TaskState::Dead);
+fn do_task_dead(exit_code: u32) -> ! {
+    // Don't keep a reference of the task across the final switch.
+    let tid = task::current().tid();
+    info!("do_task_dead ... tid {}", tid);
 
-    if task.tid() == 1 {
+    if tid == 1 {
         info!("InitTask[1] exits normally ...");
         axhal::misc::terminate()
     } else {
-        let rq = run_queue::task_rq(&task.sched_info);
-        rq.lock().resched(false);
-        unreachable!()
+        run_queue::exit_current(exit_code)
     }
 }
@@ -0,0 +1,43 @@
+//! reboot: halting and powering off the machine, or booting the kernel
+//! staged by kexec_load().
+
+use axerrno::{linux_err, linux_err_from};
+
+const LINUX_REBOOT_MAGIC1: usize = 0xfee1dead;
+const LINUX_REBOOT_MAGIC2: usize = 672274793;
+const LINUX_REBOOT_MAGIC2A: usize = 85072278;
+const LINUX_REBOOT_MAGIC2B: usize = 369367448;
+const LINUX_REBOOT_MAGIC2C: usize = 537993216;
+
+const LINUX_REBOOT_CMD_CAD_OFF: u32 = 0x00000000;
+const LINUX_REBOOT_CMD_CAD_ON: u32 = 0x89ABCDEF;
+const LINUX_REBOOT_CMD_HALT: u32 = 0xCDEF0123;
+const LINUX_REBOOT_CMD_POWER_OFF: u32 = 0x4321FEDC;
+const LINUX_REBOOT_CMD_KEXEC: u32 = 0x45584543;
+
+pub fn reboot(magic1: usize, magic2: usize, cmd: usize, _arg: usize) -> usize {
+    info!("reboot: cmd {:#x}", cmd);
+    if !task::current().cred().is_privileged() {
+        return linux_err!(EPERM);
+    }
+    if magic1 as u32 as usize != LINUX_REBOOT_MAGIC1 {
+        return linux_err!(EINVAL);
+    }
+    match magic2 as u32 as usize {
+        LINUX_REBOOT_MAGIC2 | LINUX_REBOOT_MAGIC2A |
+        LINUX_REBOOT_MAGIC2B | LINUX_REBOOT_MAGIC2C => (),
+        _ => return linux_err!(EINVAL),
+    }
+    match cmd as u32 {
+        // There is no Ctrl-Alt-Del to trap.
+        LINUX_REBOOT_CMD_CAD_ON | LINUX_REBOOT_CMD_CAD_OFF => 0,
+        LINUX_REBOOT_CMD_HALT | LINUX_REBOOT_CMD_POWER_OFF => {
+            axhal::misc::terminate();
+        },
+        LINUX_REBOOT_CMD_KEXEC => match kexec::kernel_kexec() {
+            Ok(()) => 0,
+            Err(e) => linux_err_from!(e),
+        },
+        _ => linux_err!(EINVAL),
+    }
+}
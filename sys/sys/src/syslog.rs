@@ -0,0 +1,74 @@
+//! syslog
+//!
+//! syslog(2) on the kernel log buffer of axlog2, for dmesg. The console
+//! log level cannot be changed through it: logging goes to the console
+//! at the level the kernel was started with.
+
+use alloc::string::String;
+use axerrno::linux_err;
+use axlog2::kmsg::{self, LogRecord};
+
+const SYSLOG_ACTION_CLOSE: usize = 0;
+const SYSLOG_ACTION_OPEN: usize = 1;
+const SYSLOG_ACTION_READ: usize = 2;
+const SYSLOG_ACTION_READ_ALL: usize = 3;
+const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+const SYSLOG_ACTION_CLEAR: usize = 5;
+const SYSLOG_ACTION_CONSOLE_OFF: usize = 6;
+const SYSLOG_ACTION_CONSOLE_ON: usize = 7;
+const SYSLOG_ACTION_CONSOLE_LEVEL: usize = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+/// Copies the lines of `records` to the user buffer, the last ones that
+/// fit if `tail`, or else the first ones.
+fn copy_lines(records: &[LogRecord], buf: usize, len: usize, tail: bool) -> usize {
+    let lines: String = records.iter().map(|r| r.to_line()).collect();
+    let src = lines.as_bytes();
+    let src = if tail {
+        &src[src.len().saturating_sub(len)..]
+    } else {
+        &src[..src.len().min(len)]
+    };
+    let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, src.len()) };
+    dst.copy_from_slice(src);
+    src.len()
+}
+
+pub fn syslog(ty: usize, buf: usize, len: usize) -> usize {
+    let len = len as isize;
+    match ty {
+        SYSLOG_ACTION_CLOSE | SYSLOG_ACTION_OPEN => 0,
+        SYSLOG_ACTION_READ | SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            if buf == 0 || len < 0 {
+                return linux_err!(EINVAL);
+            }
+            let len = len as usize;
+            match ty {
+                SYSLOG_ACTION_READ if len > 0 => copy_lines(&kmsg::read_unread(len), buf, len, false),
+                SYSLOG_ACTION_READ => 0,
+                _ => {
+                    let copied = copy_lines(&kmsg::read_all(), buf, len, true);
+                    if ty == SYSLOG_ACTION_READ_CLEAR {
+                        kmsg::clear();
+                    }
+                    copied
+                },
+            }
+        },
+        SYSLOG_ACTION_CLEAR => {
+            kmsg::clear();
+            0
+        },
+        SYSLOG_ACTION_CONSOLE_OFF | SYSLOG_ACTION_CONSOLE_ON => 0,
+        SYSLOG_ACTION_CONSOLE_LEVEL => {
+            if !(1..=8).contains(&len) {
+                return linux_err!(EINVAL);
+            }
+            0
+        },
+        SYSLOG_ACTION_SIZE_UNREAD => kmsg::unread_size(),
+        SYSLOG_ACTION_SIZE_BUFFER => kmsg::buffer_size(),
+        _ => linux_err!(EINVAL),
+    }
+}
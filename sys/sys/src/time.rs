@@ -0,0 +1,117 @@
+//! time
+//!
+//! The clock syscalls, on the clocks of timekeeping, and the CPU-time
+//! clocks of the calling thread and of its thread group.
+
+use axerrno::{linux_err, linux_err_from, LinuxError, LinuxResult};
+use axhal::time::TimeValue;
+use timekeeping::{Timespec, Timeval};
+use timekeeping::{CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID};
+
+/// `struct timezone`, which is obsolete and always zero.
+#[repr(C)]
+struct Timezone {
+    tz_minuteswest: i32,
+    tz_dsttime: i32,
+}
+
+fn cputime_ns(clockid: usize) -> u64 {
+    let task = task::current();
+    if clockid == CLOCK_THREAD_CPUTIME_ID {
+        return task.sched_info.utime_ns() + task.sched_info.stime_ns();
+    }
+    task.thread_group()
+        .into_iter()
+        .filter_map(task::get_task)
+        .map(|t| t.sched_info.utime_ns() + t.sched_info.stime_ns())
+        .sum()
+}
+
+fn clock_get(clockid: usize) -> LinuxResult<TimeValue> {
+    match clockid {
+        CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => {
+            Ok(TimeValue::from_nanos(cputime_ns(clockid)))
+        },
+        _ => timekeeping::clock_get(clockid),
+    }
+}
+
+pub fn clock_gettime(clockid: usize, tp: usize) -> usize {
+    let t = match clock_get(clockid) {
+        Ok(t) => t,
+        Err(e) => return linux_err_from!(e),
+    };
+    if tp == 0 {
+        return linux_err!(EFAULT);
+    }
+    unsafe { *(tp as *mut Timespec) = Timespec::from_time(t) };
+    0
+}
+
+pub fn clock_getres(clockid: usize, res: usize) -> usize {
+    let t = match timekeeping::clock_getres(clockid) {
+        Ok(t) => t,
+        Err(e) => return linux_err_from!(e),
+    };
+    // A NULL res only checks the clock.
+    if res != 0 {
+        unsafe { *(res as *mut Timespec) = Timespec::from_time(t) };
+    }
+    0
+}
+
+/// Only CLOCK_REALTIME can be set, by a privileged task. The RTC follows
+/// it, for the time to hold across reboots.
+fn settime(t: TimeValue) -> LinuxResult {
+    if !task::current().cred.lock().is_privileged() {
+        return Err(LinuxError::EPERM);
+    }
+    timekeeping::do_settimeofday(t)?;
+    if let Err(e) = axdriver::rtc::set_time(t.as_nanos() as u64) {
+        debug!("rtc: not set: {:?}", e);
+    }
+    Ok(())
+}
+
+pub fn clock_settime(clockid: usize, tp: usize) -> usize {
+    if clockid != CLOCK_REALTIME {
+        return linux_err!(EINVAL);
+    }
+    if tp == 0 {
+        return linux_err!(EFAULT);
+    }
+    let ts = unsafe { *(tp as *const Timespec) };
+    match ts.to_time().and_then(settime) {
+        Ok(()) => 0,
+        Err(e) => linux_err_from!(e),
+    }
+}
+
+pub fn gettimeofday(tv: usize, tz: usize) -> usize {
+    if tv != 0 {
+        let now = timekeeping::ktime_get_real();
+        unsafe { *(tv as *mut Timeval) = Timeval::from_time(now) };
+    }
+    if tz != 0 {
+        unsafe {
+            *(tz as *mut Timezone) = Timezone {
+                tz_minuteswest: 0,
+                tz_dsttime: 0,
+            };
+        }
+    }
+    0
+}
+
+/// Sets the time from `tv`; the time zone in `tz` is ignored, as the
+/// kernel keeps UTC only.
+pub fn settimeofday(tv: usize, _tz: usize) -> usize {
+    if tv == 0 {
+        return 0;
+    }
+    let tv = unsafe { *(tv as *const Timeval) };
+    match tv.to_time().and_then(settime) {
+        Ok(()) => 0,
+        Err(e) => linux_err_from!(e),
+    }
+}
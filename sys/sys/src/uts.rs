@@ -0,0 +1,33 @@
+//! sethostname and setdomainname, on the UTS namespace of the caller.
+
+use axerrno::{linux_err, linux_err_from, LinuxResult};
+use nsproxy::{UtsNamespace, UTS_LEN};
+
+/// The name of `len` bytes at `name`, set by `set` if the caller is
+/// privileged.
+fn set_name(
+    name: usize, len: usize, set: fn(&UtsNamespace, &[u8]) -> LinuxResult
+) -> usize {
+    let current = task::current();
+    if !current.cred().is_privileged() {
+        return linux_err!(EPERM);
+    }
+    if len > UTS_LEN {
+        return linux_err!(EINVAL);
+    }
+    let name = unsafe { core::slice::from_raw_parts(name as *const u8, len) };
+    match set(&current.nsproxy.uts_ns, name) {
+        Ok(()) => 0,
+        Err(e) => linux_err_from!(e),
+    }
+}
+
+pub fn sethostname(name: usize, len: usize) -> usize {
+    info!("sethostname: len {}", len);
+    set_name(name, len, UtsNamespace::set_nodename)
+}
+
+pub fn setdomainname(name: usize, len: usize) -> usize {
+    info!("setdomainname: len {}", len);
+    set_name(name, len, UtsNamespace::set_domainname)
+}
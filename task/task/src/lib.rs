@@ -14,38 +14,63 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use axtype::{RLimit64, RLIM_NLIMITS};
-use axtype::{RLIMIT_DATA, RLIMIT_STACK, RLIMIT_CORE, RLIMIT_NOFILE};
+pub use axtype::Cred;
+use axtype::{RLIMIT_STACK, RLIMIT_CORE, RLIMIT_NOFILE, RLIMIT_MEMLOCK};
 use axhal::arch::TaskContext as ThreadStruct;
-use mm::MmStruct;
+use mm::{MmStruct, MmStructRef};
 use taskctx::switch_mm;
 use taskctx::SchedInfo;
 use taskctx::TaskState;
-use spinbase::SpinNoIrq;
+use mutex::RwSem;
 use spinpreempt::SpinLock;
 use fstree::FsStruct;
 use filetable::FileTable;
 use wait_queue::WaitQueue;
 use preempt_guard::NoPreempt;
+use nsproxy::{NsProxy, PidNamespace};
 use axconfig::TASK_STACK_SIZE;
 
-pub use crate::tid_map::{register_task, unregister_task, get_task};
+pub use crate::tid_map::{register_task, unregister_task, get_task, all_tasks, try_for_each_task};
 pub use taskctx::Tid;
 pub use taskctx::current_ctx;
 pub use taskctx::{TaskStack, THREAD_SIZE};
 pub use tid::alloc_tid;
+pub use ptrace::PtraceState;
 
 mod tid;
 mod tid_map;
+mod ptrace;
 
-const NSIG: usize = 64;
+pub const NSIG: usize = 64;
 
+pub const SIGHUP : usize = 1;
 pub const SIGINT : usize = 2;
-pub const SIGKILL: usize = 9;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL : usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
 pub const SIGBUS : usize = 7;
+pub const SIGFPE : usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
 pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
 pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
 pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
 pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG : usize = 23;
+pub const SIGXCPU: usize = 24;
+pub const SIGXFSZ: usize = 25;
+pub const SIGWINCH: usize = 28;
+pub const SIGSYS : usize = 31;
+/// Realtime signals are queued once per sending, others at most once.
+pub const SIGRTMIN: usize = 32;
 
 /*
  * SIGBUS si_codes
@@ -53,7 +78,7 @@ pub const SIGSTOP: usize = 19;
 //#define BUS_ADRALN  1   /* invalid address alignment */
 pub const BUS_ADRERR : usize =  2;  // non-existent physical address
 
-#[derive(Clone)]
+#[derive(Copy, Clone)]
 pub struct SigInfo {
     pub signo: i32,
     pub errno: i32,
@@ -71,6 +96,8 @@ pub const SA_NODEFER:   usize = 0x40000000;
 pub struct SigAction {
     pub handler: usize,
     pub flags: usize,
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub restorer: usize,
     pub mask: u64,
 }
 
@@ -100,32 +127,38 @@ impl SigHand {
     }
 }
 
-#[derive(Default)]
-pub struct Cred {
-    pub uid:    u32,    // real UID of the task
-    pub gid:    u32,    // real GID of the task
-    pub suid:   u32,    // saved UID of the task
-    pub sgid:   u32,    // saved GID of the task
-    pub euid:   u32,    // effective UID of the task
-    pub egid:   u32,    // effective GID of the task
-    pub fsuid:   u32,   // UID for filesystem
-    pub fsgid:   u32,   // GID for filesystem
-}
-
 pub struct TaskStruct {
-    pub mm: Option<Arc<SpinNoIrq<MmStruct>>>,
+    pub mm: Option<MmStructRef>,
     pub fs: Arc<SpinLock<FsStruct>>,
     pub filetable: Arc<SpinLock<FileTable>>,
     pub sigpending: SpinLock<SigPending>,
     pub sighand: Arc<SpinLock<SigHand>>,
-    pub rlim: [RLimit64; RLIM_NLIMITS],
+    /// Resource limits, shared by the threads of a process.
+    pub rlim: Arc<SpinLock<[RLimit64; RLIM_NLIMITS]>>,
     pub blocked: AtomicU64,
     pub sched_info: Arc<SchedInfo>,
     pub cred: Arc<SpinLock<Cred>>,
+    pub nsproxy: Arc<NsProxy>,
+    /// The pid namespace it has its pid in, for its whole life.
+    pub pid_ns: Arc<PidNamespace>,
 
     pub exit_state: AtomicUsize,
-    pub exit_code: AtomicU32,
+    /// Signal sent to the parent on exit, or -1 for threads, which are
+    /// reaped on exit.
+    pub exit_signal: i32,
+    /// Tid of the task that waits for it, init once that one has exited.
+    pub real_parent: AtomicUsize,
+    /// Wait status of a stop or continue not reported to the parent yet,
+    /// or 0 if none.
+    pub job_status: AtomicU32,
+    /// Process group and session, the same for all threads of a group.
+    pub pgid: AtomicUsize,
+    pub sid: AtomicUsize,
+    /// Controlling terminal of the session, set by the tty layer, or 0 if
+    /// none.
+    pub tty: AtomicUsize,
     pub vfork_done: Option<WaitQueue>,
+    pub ptrace: PtraceState,
 }
 
 unsafe impl Send for TaskStruct {}
@@ -139,14 +172,22 @@ impl TaskStruct {
             filetable: filetable::init_files(),
             sigpending: SpinLock::new(SigPending::new()),
             sighand: Arc::new(SpinLock::new(SigHand::new())),
-            rlim: rlimit_init(),
+            rlim: Arc::new(SpinLock::new(rlimit_init())),
             blocked: AtomicU64::new(0),
             sched_info: taskctx::init_thread(),
-            cred: Arc::new(SpinLock::new(Cred::default())),
+            cred: Arc::new(SpinLock::new(Cred::root())),
+            nsproxy: nsproxy::init_nsproxy(),
+            pid_ns: nsproxy::init_pid_ns(),
 
             exit_state: AtomicUsize::new(0),
-            exit_code: AtomicU32::new(0),
+            exit_signal: SIGCHLD as i32,
+            real_parent: AtomicUsize::new(0),
+            job_status: AtomicU32::new(0),
+            pgid: AtomicUsize::new(0),
+            sid: AtomicUsize::new(0),
+            tty: AtomicUsize::new(0),
             vfork_done: None,
+            ptrace: PtraceState::new(),
         }
     }
 
@@ -158,6 +199,17 @@ impl TaskStruct {
         self.cred.lock().fsgid
     }
 
+    /// A snapshot of its credentials for permission checks, so that the
+    /// lock isn't held across filesystem calls.
+    pub fn cred(&self) -> Cred {
+        self.cred.lock().clone()
+    }
+
+    /// Soft limit of `resource`.
+    pub fn rlimit(&self, resource: usize) -> u64 {
+        self.rlim.lock()[resource].rlim_cur
+    }
+
     pub fn tid(&self) -> Tid {
         self.sched_info.tid()
     }
@@ -166,15 +218,93 @@ impl TaskStruct {
         self.sched_info.tgid()
     }
 
+    /// Whether it's the init of its pid namespace, other than the root one.
+    pub fn is_child_reaper(&self) -> bool {
+        !self.pid_ns.is_root() && self.pid_ns.pid_nr(self.tgid()) == Some(1)
+    }
+
+    /// Whether it's the leader of its thread group.
+    pub fn is_group_leader(&self) -> bool {
+        self.sched_info.group_leader.is_none()
+    }
+
+    /// Tids of the tasks in its thread group, the leader first.
+    pub fn thread_group(&self) -> Vec<Tid> {
+        let leader = self.sched_info.group_leader.as_ref().unwrap_or(&self.sched_info);
+        let mut tids = alloc::vec![leader.tid()];
+        tids.extend(leader.siblings.lock().iter());
+        tids
+    }
+
+    pub fn pgid(&self) -> usize {
+        self.pgid.load(Ordering::Relaxed)
+    }
+
+    pub fn sid(&self) -> usize {
+        self.sid.load(Ordering::Relaxed)
+    }
+
+    /// Whether it leads its session, which then can't change its group.
+    pub fn is_session_leader(&self) -> bool {
+        self.sid() == self.tgid()
+    }
+
+    pub fn parent(&self) -> Option<Arc<TaskStruct>> {
+        get_task(self.real_parent.load(Ordering::Acquire))
+    }
+
+    /// Whether `sig` would be discarded on delivery, so that it needn't be
+    /// queued. SIGCONT is always queued, as it resumes a stopped task.
+    fn sig_ignored(&self, sig: usize) -> bool {
+        const SIG_DFL: usize = 0;
+        const SIG_IGN: usize = 1;
+        if (self.blocked.load(Ordering::Relaxed) & (1 << (sig - 1))) != 0 {
+            return false;
+        }
+        match self.sighand.lock().action[sig - 1].handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(sig, SIGCHLD | SIGURG | SIGWINCH),
+            _ => false,
+        }
+    }
+
+    /// Makes `info` pending on the task and wakes it up unless it sleeps
+    /// uninterruptibly. A non-realtime signal already pending is dropped,
+    /// and so is one the task ignores.
+    pub fn send_sig_info(&self, info: SigInfo) {
+        let sig = info.signo as usize;
+        assert!(sig > 0 && sig <= NSIG);
+        if self.sig_ignored(sig) {
+            return;
+        }
+        {
+            let mut pending = self.sigpending.lock();
+            let mask = 1u64 << (sig - 1);
+            if sig < SIGRTMIN && (pending.signal & mask) != 0 {
+                return;
+            }
+            pending.list.push(info);
+            pending.signal |= mask;
+            // Set under the lock, so that the task can't clear it in
+            // between without seeing the signal.
+            self.sched_info.set_tsk_thread_flag(taskctx::TIF_SIGPENDING);
+        }
+        // A running task has its next `block_current` return at once.
+        match self.sched_info.state() {
+            TaskState::Uninterruptible | TaskState::Zombie => {},
+            _ => { run_queue::wake_up_task(self.tid()); },
+        }
+    }
+
     pub fn pt_regs_addr(&self) -> usize {
         self.sched_info.pt_regs_addr()
     }
 
-    pub fn try_mm(&self) -> Option<Arc<SpinNoIrq<MmStruct>>> {
+    pub fn try_mm(&self) -> Option<MmStructRef> {
         self.mm.as_ref().and_then(|mm| Some(mm.clone()))
     }
 
-    pub fn mm(&self) -> Arc<SpinNoIrq<MmStruct>> {
+    pub fn mm(&self) -> MmStructRef {
         self.mm.as_ref().expect("NOT a user process.").clone()
     }
 
@@ -184,19 +314,29 @@ impl TaskStruct {
         //assert!(self.mm.is_none());
         let mm = MmStruct::new();
         let mm_id = mm.id();
-        self.mm.replace(Arc::new(SpinNoIrq::new(mm)));
+        // Taken before it is shared, as the mmap lock can't be under
+        // NoPreempt.
+        let pgd = mm.pgd();
+        self.mm.replace(Arc::new(RwSem::new(mm)));
         info!("================== mmid {}", mm_id);
         let mut ctx = taskctx::current_ctx();
         ctx.mm_id.store(mm_id, Ordering::Relaxed);
         ctx.active_mm_id.store(mm_id, Ordering::Relaxed);
-        ctx.as_ctx_mut().pgd = Some(self.mm().lock().pgd().clone());
-        switch_mm(0, mm_id, self.mm().lock().pgd());
+        ctx.as_ctx_mut().pgd = Some(pgd.clone());
+        switch_mm(0, mm_id, pgd);
     }
 
     pub fn dup_task_struct(&self) -> Self {
         info!("dup_task_struct ...");
         let task = Self::new();
         task.blocked.store(self.blocked.load(Ordering::Relaxed), Ordering::Relaxed);
+        task.pgid.store(self.pgid(), Ordering::Relaxed);
+        task.sid.store(self.sid(), Ordering::Relaxed);
+        task.tty.store(self.tty.load(Ordering::Relaxed), Ordering::Relaxed);
+        *task.cred.lock() = self.cred();
+        *task.rlim.lock() = *self.rlim.lock();
+        task.nsproxy = self.nsproxy.clone();
+        task.pid_ns = self.pid_ns.clone();
         task
     }
 
@@ -318,26 +458,49 @@ pub fn current() -> CurrentTask {
 pub fn yield_now() {
     let cur = current();
     let rq = run_queue::task_rq(&cur.sched_info);
-    rq.lock().resched(false);
+    rq.lock().yield_current();
 }
 
 pub fn activate(task: TaskRef) {
-    let rq = run_queue::task_rq(&task.sched_info);
-    rq.lock().activate_task(task.sched_info.clone());
+    run_queue::activate_task(task.sched_info.clone());
+}
+
+/// `tid` as the current task sees it, in its pid namespace, or 0 if it's
+/// not there, as pid_vnr in linux.
+pub fn pid_vnr(tid: Tid) -> usize {
+    current().pid_ns.pid_nr(tid).unwrap_or(0)
+}
+
+/// The tid of the task the current task sees as `nr`, if there is one.
+pub fn find_vpid(nr: usize) -> Option<Tid> {
+    current().pid_ns.find_tid(nr)
 }
 
 pub fn alloc_mm() {
-    let _ = NoPreempt::new();
+    let _guard = NoPreempt::new();
     let mut task = current();
     task.as_task_mut().alloc_mm();
 }
 
+/// Gives the current task a file table of its own, copied from the one it
+/// shares with CLONE_FILES, as exec does before it closes on exec.
+pub fn unshare_files() {
+    let _guard = NoPreempt::new();
+    let mut task = current();
+    let task = task.as_task_mut();
+    if Arc::strong_count(&task.filetable) > 1 {
+        let files = task.filetable.lock().clone();
+        task.filetable = Arc::new(SpinLock::new(files));
+    }
+}
+
 pub fn init(cpu_id: usize, dtb_pa: usize) {
     axconfig::init_once!();
     info!("Initialize schedule system ...");
 
     //run_queue::init(cpu_id, dtb_pa);
     fstree::init(cpu_id, dtb_pa);
+    nsproxy::init();
 
     let init_task = TaskStruct::new();
     init_task.set_state(TaskState::Running);
@@ -349,10 +512,11 @@ pub fn init(cpu_id: usize, dtb_pa: usize) {
 }
 
 fn rlimit_init() -> [RLimit64; RLIM_NLIMITS] {
-    let mut ret = [RLimit64::default(); RLIM_NLIMITS];
-    ret[RLIMIT_DATA] = RLimit64::new(u64::MAX, u64::MAX);
+    let mut ret = [RLimit64::infinity(); RLIM_NLIMITS];
     ret[RLIMIT_STACK] = RLimit64::new(TASK_STACK_SIZE as u64, u64::MAX);
-    ret[RLIMIT_CORE] = RLimit64::new(u64::MAX, u64::MAX);
+    // No core dumps unless asked for, by `ulimit -c`.
+    ret[RLIMIT_CORE] = RLimit64::new(0, u64::MAX);
     ret[RLIMIT_NOFILE] = RLimit64::new(0x400, 0x1000);
+    ret[RLIMIT_MEMLOCK] = RLimit64::new(0x800000, 0x800000);
     ret
 }
@@ -0,0 +1,43 @@
+//! What a task keeps of its tracer, and of the tasks it traces. The
+//! requests and the stops are in the ptrace crate.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize};
+use spinpreempt::SpinLock;
+
+use crate::{SigInfo, Tid};
+
+pub struct PtraceState {
+    /// Tgid of the process that traces it, or 0 if it isn't traced. Any
+    /// thread of the tracer may make requests and wait for it.
+    pub tracer: AtomicUsize,
+    /// PTRACE_O_* options set by the tracer.
+    pub options: AtomicUsize,
+    /// Wait status of a stop not reported to the tracer yet, or 0 if none.
+    pub status: AtomicU32,
+    /// Set while it is stopped for the tracer, which clears it to resume.
+    pub stopped: AtomicBool,
+    /// The signal the tracer resumes it with, or 0 for none.
+    pub resume_signo: AtomicUsize,
+    /// The value of PTRACE_GETEVENTMSG, as the pid of a new child.
+    pub message: AtomicUsize,
+    /// The signal of the last signal-delivery-stop, for PTRACE_GETSIGINFO.
+    pub siginfo: SpinLock<Option<SigInfo>>,
+    /// Tids of the tasks it traces, kept by the group leader only.
+    pub tracees: SpinLock<Vec<Tid>>,
+}
+
+impl PtraceState {
+    pub fn new() -> Self {
+        Self {
+            tracer: AtomicUsize::new(0),
+            options: AtomicUsize::new(0),
+            status: AtomicU32::new(0),
+            stopped: AtomicBool::new(false),
+            resume_signo: AtomicUsize::new(0),
+            message: AtomicUsize::new(0),
+            siginfo: SpinLock::new(None),
+            tracees: SpinLock::new(Vec::new()),
+        }
+    }
+}
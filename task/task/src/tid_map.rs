@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use spinpreempt::SpinLock;
 use crate::TaskRef;
 use crate::Tid;
@@ -14,6 +15,27 @@ pub fn register_task(task: TaskRef) {
     TID_MAP.lock().insert(tid, task);
 }
 
+/// Removes the task from the map, and frees its pids, which may be handed
+/// out again.
 pub fn unregister_task(tid: Tid) {
-    TID_MAP.lock().remove(&tid);
+    let task = TID_MAP.lock().remove(&tid);
+    if let Some(task) = task {
+        task.pid_ns.free_pid(tid);
+    }
+}
+
+/// All the tasks registered now, in the order of tid.
+pub fn all_tasks() -> Vec<TaskRef> {
+    TID_MAP.lock().values().cloned().collect()
+}
+
+/// Calls `f` on each task registered, in the order of tid, without
+/// allocating: for the crash dump. Returns false, without calling it, if
+/// the map is locked, as it may be by the panicking CPU.
+pub fn try_for_each_task(mut f: impl FnMut(&TaskRef)) -> bool {
+    let Some(map) = TID_MAP.try_lock() else {
+        return false;
+    };
+    map.values().for_each(|task| f(task));
+    true
 }
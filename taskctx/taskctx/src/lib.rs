@@ -6,11 +6,12 @@ extern crate log;
 extern crate alloc;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::any::Any;
 
 use core::ops::Deref;
 use core::mem::ManuallyDrop;
 use core::{alloc::Layout, cell::UnsafeCell, ptr::NonNull};
-use core::sync::atomic::{AtomicUsize, AtomicU8, AtomicBool, Ordering};
+use core::sync::atomic::{AtomicUsize, AtomicIsize, AtomicU8, AtomicU32, AtomicU64, AtomicBool, Ordering};
 use axhal::arch::TaskContext as ThreadStruct;
 use axhal::arch::TrapFrame;
 use axhal::trap::{TRAPFRAME_SIZE, STACK_ALIGN};
@@ -22,14 +23,69 @@ use lazy_init::LazyInit;
 
 pub const THREAD_SIZE: usize = 32 * PAGE_SIZE_4K;
 
+pub const TIF_SYSCALL_TRACE: usize  = 0;    // syscall trace active
 pub const TIF_SIGPENDING: usize     = 2;    // signal pending
+pub const TIF_SYSCALL_TRACEPOINT: usize = 5; // syscalls traced by strace
 pub const TIF_NOTIFY_SIGNAL: usize  = 9;    // signal notifications exist
 
+pub const _TIF_SYSCALL_TRACE: usize = 1 << TIF_SYSCALL_TRACE;
 pub const _TIF_SIGPENDING: usize = 1 << TIF_SIGPENDING;
+pub const _TIF_SYSCALL_TRACEPOINT: usize = 1 << TIF_SYSCALL_TRACEPOINT;
 pub const _TIF_NOTIFY_SIGNAL: usize = 1 << TIF_NOTIFY_SIGNAL;
 
+/// Whether a signal is pending for the task of `ctx`, which a sleep it's
+/// in or about to begin must give way to.
+#[inline]
+pub fn signal_pending(ctx: &SchedInfo) -> bool {
+    (ctx.flags.load(Ordering::Relaxed) & _TIF_SIGPENDING) != 0
+}
+
 pub type Tid = usize;
 
+/*
+ * Scheduling policies
+ */
+pub const SCHED_NORMAL: usize   = 0;
+pub const SCHED_FIFO: usize     = 1;
+pub const SCHED_RR: usize       = 2;
+pub const SCHED_BATCH: usize    = 3;
+pub const SCHED_IDLE: usize     = 5;
+
+pub const MIN_NICE: isize = -20;
+pub const MAX_NICE: isize = 19;
+/// Real-time priorities of SCHED_FIFO and SCHED_RR are in [1, MAX_RT_PRIO).
+pub use scheduler::MAX_RT_PRIO;
+
+/// Whether `policy` is one of the real-time policies.
+#[inline]
+pub fn rt_policy(policy: usize) -> bool {
+    policy == SCHED_FIFO || policy == SCHED_RR
+}
+
+/// Whether `priority` is in the valid range of `policy`.
+///
+/// It is the nice value for normal policies, and the real-time
+/// priority for SCHED_FIFO and SCHED_RR.
+pub fn valid_priority(policy: usize, priority: isize) -> bool {
+    match policy {
+        SCHED_NORMAL | SCHED_BATCH | SCHED_IDLE => (MIN_NICE..=MAX_NICE).contains(&priority),
+        SCHED_FIFO | SCHED_RR => (1..MAX_RT_PRIO).contains(&priority),
+        _ => false,
+    }
+}
+
+/// Whether parameters `(policy, priority)` are more urgent than
+/// `(other_policy, other_priority)`: real-time beats normal, then a higher
+/// real-time priority or a lower nice value wins.
+pub fn more_urgent(policy: usize, priority: isize, other_policy: usize, other_priority: isize) -> bool {
+    match (rt_policy(policy), rt_policy(other_policy)) {
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => priority > other_priority,
+        (false, false) => priority < other_priority,
+    }
+}
+
 pub struct TaskStack {
     ptr: NonNull<u8>,
     layout: Layout,
@@ -59,10 +115,16 @@ impl Drop for TaskStack {
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum TaskState {
+    /// Currently running on a CPU.
     Running = 1,
-    Ready = 2,
-    Blocked = 3,
-    Dead = 4,
+    /// Ready to run, waiting in a run queue.
+    Runnable = 2,
+    /// Sleeping, can be woken up by an event or a signal.
+    Interruptible = 3,
+    /// Sleeping, can only be woken up by the event it waits for.
+    Uninterruptible = 4,
+    /// Exited, waiting to be reaped.
+    Zombie = 5,
 }
 
 impl From<u8> for TaskState {
@@ -70,17 +132,33 @@ impl From<u8> for TaskState {
     fn from(state: u8) -> Self {
         match state {
             1 => Self::Running,
-            2 => Self::Ready,
-            3 => Self::Blocked,
-            4 => Self::Dead,
+            2 => Self::Runnable,
+            3 => Self::Interruptible,
+            4 => Self::Uninterruptible,
+            5 => Self::Zombie,
             _ => unreachable!(),
         }
     }
 }
 
+/// Scheduling statistics of a task.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedStats {
+    /// Time spent running on a CPU, in nanoseconds.
+    pub run_time_ns: u64,
+    /// Time spent ready in a run queue, waiting for a CPU, in nanoseconds.
+    pub wait_time_ns: u64,
+    /// Times the task has been switched out.
+    pub nr_switches: usize,
+    /// Switches out because the task blocked or exited.
+    pub nr_voluntary_switches: usize,
+}
+
 pub struct SchedInfo {
     tid:    Tid,
     tgid:   Tid,
+    /// Its tid in its own pid namespace, which is what it sees of itself.
+    vtid:   Tid,
 
     pub flags: AtomicUsize,
 
@@ -101,6 +179,43 @@ pub struct SchedInfo {
     pub kstack: Option<TaskStack>,
     state: AtomicU8,
     in_wait_queue: AtomicBool,
+    in_timer_list: AtomicBool,
+    wakeup_pending: AtomicBool,
+    exit_code: AtomicU32,
+
+    run_time_ns: AtomicU64,
+    wait_time_ns: AtomicU64,
+    nr_switches: AtomicUsize,
+    nr_voluntary_switches: AtomicUsize,
+    /// When it last started running, in nanoseconds.
+    last_arrival: AtomicU64,
+    /// When it was last queued, or 0 if it isn't waiting.
+    last_queued: AtomicU64,
+    /// When it last blocked, or 0 if it isn't sleeping.
+    last_sleep: AtomicU64,
+    /// CPU time spent in user and kernel mode, in nanoseconds.
+    utime_ns: AtomicU64,
+    stime_ns: AtomicU64,
+    /// When its CPU time was last charged.
+    acct_timestamp: AtomicU64,
+
+    policy: AtomicUsize,
+    priority: AtomicIsize,
+    /// Own policy and priority while running with inherited ones.
+    base_policy: AtomicUsize,
+    base_priority: AtomicIsize,
+    boosted: AtomicBool,
+    /// Sleeping locks held, which others may lend their priority to.
+    held_locks: AtomicUsize,
+
+    /// CPU whose run queue the task belongs to.
+    cpu: AtomicUsize,
+    /// Bitmask of CPUs the task may run on.
+    cpus_allowed: AtomicUsize,
+    group: AtomicUsize,
+    /// Scheduling entities the run queue keeps for the task, carried along
+    /// when it migrates.
+    sched_entity: SpinNoIrq<Option<Arc<dyn Any + Send + Sync>>>,
 
     need_resched: AtomicBool,
     preempt_disable_count: AtomicUsize,
@@ -117,6 +232,7 @@ impl SchedInfo {
         Self {
             tid: 0,
             tgid: 0,
+            vtid: 0,
 
             flags: AtomicUsize::new(0),
             real_parent: None,
@@ -134,8 +250,31 @@ impl SchedInfo {
 
             entry: None,
             kstack: Some(TaskStack::alloc(align_up_4k(THREAD_SIZE))),
-            state: AtomicU8::new(TaskState::Ready as u8),
+            state: AtomicU8::new(TaskState::Runnable as u8),
             in_wait_queue: AtomicBool::new(false),
+            in_timer_list: AtomicBool::new(false),
+            wakeup_pending: AtomicBool::new(false),
+            exit_code: AtomicU32::new(0),
+            run_time_ns: AtomicU64::new(0),
+            wait_time_ns: AtomicU64::new(0),
+            nr_switches: AtomicUsize::new(0),
+            nr_voluntary_switches: AtomicUsize::new(0),
+            last_arrival: AtomicU64::new(0),
+            last_queued: AtomicU64::new(0),
+            last_sleep: AtomicU64::new(0),
+            utime_ns: AtomicU64::new(0),
+            stime_ns: AtomicU64::new(0),
+            acct_timestamp: AtomicU64::new(0),
+            policy: AtomicUsize::new(SCHED_NORMAL),
+            priority: AtomicIsize::new(0),
+            base_policy: AtomicUsize::new(SCHED_NORMAL),
+            base_priority: AtomicIsize::new(0),
+            boosted: AtomicBool::new(false),
+            held_locks: AtomicUsize::new(0),
+            cpu: AtomicUsize::new(0),
+            cpus_allowed: AtomicUsize::new(usize::MAX),
+            group: AtomicUsize::new(0),
+            sched_entity: SpinNoIrq::new(None),
             need_resched: AtomicBool::new(false),
             preempt_disable_count: AtomicUsize::new(0),
 
@@ -145,6 +284,11 @@ impl SchedInfo {
 
     pub fn init_tid(&mut self, tid: Tid) {
         self.tid = tid;
+        self.vtid = tid;
+    }
+
+    pub fn init_vtid(&mut self, vtid: Tid) {
+        self.vtid = vtid;
     }
 
     pub fn init_tgid(&mut self, tgid: Tid) {
@@ -159,6 +303,10 @@ impl SchedInfo {
         self.tgid
     }
 
+    pub fn vtid(&self) -> Tid {
+        self.vtid
+    }
+
     #[inline]
     pub fn set_tsk_thread_flag(&self, flag: usize) {
         self.flags.fetch_or(1<<flag, Ordering::Relaxed);
@@ -170,7 +318,12 @@ impl SchedInfo {
     }
 
     #[inline]
-    pub(crate) fn state(&self) -> TaskState {
+    pub fn test_tsk_thread_flag(&self, flag: usize) -> bool {
+        (self.flags.load(Ordering::Relaxed) & (1<<flag)) != 0
+    }
+
+    #[inline]
+    pub fn state(&self) -> TaskState {
         self.state.load(Ordering::Acquire).into()
     }
 
@@ -185,13 +338,143 @@ impl SchedInfo {
     }
 
     #[inline]
-    pub fn is_ready(&self) -> bool {
-        matches!(self.state(), TaskState::Ready)
+    pub fn is_runnable(&self) -> bool {
+        matches!(self.state(), TaskState::Runnable)
     }
 
+    /// Sleeping in either `Interruptible` or `Uninterruptible` state.
     #[inline]
     pub fn is_blocked(&self) -> bool {
-        matches!(self.state(), TaskState::Blocked)
+        matches!(
+            self.state(),
+            TaskState::Interruptible | TaskState::Uninterruptible
+        )
+    }
+
+    #[inline]
+    pub fn is_interruptible(&self) -> bool {
+        matches!(self.state(), TaskState::Interruptible)
+    }
+
+    #[inline]
+    pub fn is_zombie(&self) -> bool {
+        matches!(self.state(), TaskState::Zombie)
+    }
+
+    /// Records a wakeup that arrived while the task wasn't blocked yet,
+    /// so that its next block returns at once.
+    #[inline]
+    pub fn set_wakeup_pending(&self, pending: bool) {
+        self.wakeup_pending.store(pending, Ordering::Release)
+    }
+
+    #[inline]
+    pub fn take_wakeup_pending(&self) -> bool {
+        self.wakeup_pending.swap(false, Ordering::AcqRel)
+    }
+
+    #[inline]
+    pub fn exit_code(&self) -> u32 {
+        self.exit_code.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_exit_code(&self, exit_code: u32) {
+        self.exit_code.store(exit_code, Ordering::Release)
+    }
+
+    pub fn sched_stats(&self) -> SchedStats {
+        SchedStats {
+            run_time_ns: self.run_time_ns.load(Ordering::Relaxed),
+            wait_time_ns: self.wait_time_ns.load(Ordering::Relaxed),
+            nr_switches: self.nr_switches.load(Ordering::Relaxed),
+            nr_voluntary_switches: self.nr_voluntary_switches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Marks the task as queued at `now`, starting its wait time.
+    #[inline]
+    pub fn stat_enqueue(&self, now: u64) {
+        self.last_queued.store(now, Ordering::Relaxed);
+    }
+
+    /// Accounts the time it waited, as it starts running at `now`.
+    pub fn stat_run_start(&self, now: u64) {
+        let queued = self.last_queued.swap(0, Ordering::Relaxed);
+        if queued != 0 {
+            self.wait_time_ns.fetch_add(now.saturating_sub(queued), Ordering::Relaxed);
+        }
+        self.last_arrival.store(now, Ordering::Relaxed);
+    }
+
+    /// Accounts the time it ran, as it stops running at `now`.
+    pub fn stat_run_stop(&self, now: u64) {
+        let arrival = self.last_arrival.load(Ordering::Relaxed);
+        self.run_time_ns.fetch_add(now.saturating_sub(arrival), Ordering::Relaxed);
+    }
+
+    /// Marks the task as blocked at `now`, starting its sleep time.
+    #[inline]
+    pub fn stat_block(&self, now: u64) {
+        self.last_sleep.store(now, Ordering::Relaxed);
+    }
+
+    /// Returns how long it slept until `now`, as it's woken up, or 0 if it
+    /// hasn't blocked since the last call.
+    pub fn take_sleep_ns(&self, now: u64) -> u64 {
+        let since = self.last_sleep.swap(0, Ordering::Relaxed);
+        if since == 0 {
+            return 0;
+        }
+        now.saturating_sub(since)
+    }
+
+    /// CPU time the task has spent in user mode, in nanoseconds.
+    pub fn utime_ns(&self) -> u64 {
+        self.utime_ns.load(Ordering::Relaxed)
+    }
+
+    /// CPU time the task has spent in kernel mode, in nanoseconds.
+    pub fn stime_ns(&self) -> u64 {
+        self.stime_ns.load(Ordering::Relaxed)
+    }
+
+    /// Charges the CPU time since it was last charged to user or kernel
+    /// mode, according to the mode the tick at `now` has interrupted.
+    pub fn acct_tick(&self, now: u64, user: bool) {
+        let last = self.acct_timestamp.swap(now, Ordering::Relaxed);
+        let delta = now.saturating_sub(last);
+        if user {
+            self.utime_ns.fetch_add(delta, Ordering::Relaxed);
+        } else {
+            self.stime_ns.fetch_add(delta, Ordering::Relaxed);
+        }
+    }
+
+    /// Starts charging CPU time, as the task is switched in at `now`.
+    #[inline]
+    pub fn acct_switch_in(&self, now: u64) {
+        self.acct_timestamp.store(now, Ordering::Relaxed);
+    }
+
+    /// Charges the CPU time since the last tick, as the task is switched
+    /// out at `now`. Switches happen in kernel mode.
+    #[inline]
+    pub fn acct_switch_out(&self, now: u64) {
+        self.acct_tick(now, false);
+    }
+
+    /// Counts a switch out of the task.
+    pub fn stat_switch_out(&self, voluntary: bool) {
+        self.nr_switches.fetch_add(1, Ordering::Relaxed);
+        if voluntary {
+            self.nr_voluntary_switches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub fn in_wait_queue(&self) -> bool {
+        self.in_wait_queue.load(Ordering::Acquire)
     }
 
     #[inline]
@@ -199,6 +482,130 @@ impl SchedInfo {
         self.in_wait_queue.store(in_wait_queue, Ordering::Release);
     }
 
+    #[inline]
+    pub fn in_timer_list(&self) -> bool {
+        self.in_timer_list.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_in_timer_list(&self, in_timer_list: bool) {
+        self.in_timer_list.store(in_timer_list, Ordering::Release);
+    }
+
+    /// Scheduling policy, one of the SCHED_* values.
+    #[inline]
+    pub fn policy(&self) -> usize {
+        self.policy.load(Ordering::Acquire)
+    }
+
+    /// Nice value, or real-time priority for real-time policies.
+    #[inline]
+    pub fn priority(&self) -> isize {
+        self.priority.load(Ordering::Acquire)
+    }
+
+    /// Sets policy and priority without checking them, the run queue is
+    /// in charge of validation and re-queueing. See `run_queue::AxRunQueue`.
+    pub fn set_sched_params(&self, policy: usize, priority: isize) {
+        self.policy.store(policy, Ordering::Release);
+        self.priority.store(priority, Ordering::Release);
+    }
+
+    /// Whether it runs with parameters inherited from a waiter.
+    #[inline]
+    pub fn is_boosted(&self) -> bool {
+        self.boosted.load(Ordering::Acquire)
+    }
+
+    /// Its own policy and priority, regardless of any inherited ones.
+    pub fn base_sched_params(&self) -> (usize, isize) {
+        if self.is_boosted() {
+            (self.base_policy.load(Ordering::Acquire), self.base_priority.load(Ordering::Acquire))
+        } else {
+            (self.policy(), self.priority())
+        }
+    }
+
+    /// Sets its own policy and priority while it's boosted, to be restored
+    /// by `end_boost`.
+    pub fn set_base_sched_params(&self, policy: usize, priority: isize) {
+        self.base_policy.store(policy, Ordering::Release);
+        self.base_priority.store(priority, Ordering::Release);
+    }
+
+    /// Saves its own parameters before inheriting others.
+    pub fn start_boost(&self) {
+        if !self.boosted.swap(true, Ordering::AcqRel) {
+            self.set_base_sched_params(self.policy(), self.priority());
+        }
+    }
+
+    /// Returns its own parameters to restore, if it has been boosted.
+    pub fn end_boost(&self) -> Option<(usize, isize)> {
+        self.boosted.swap(false, Ordering::AcqRel).then(|| {
+            (self.base_policy.load(Ordering::Acquire), self.base_priority.load(Ordering::Acquire))
+        })
+    }
+
+    #[inline]
+    pub fn lock_acquired(&self) {
+        self.held_locks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if it doesn't hold any lock anymore.
+    #[inline]
+    pub fn lock_released(&self) -> bool {
+        self.held_locks.fetch_sub(1, Ordering::Relaxed) == 1
+    }
+
+    /// The task group it belongs to, 0 for the root group.
+    #[inline]
+    pub fn group(&self) -> usize {
+        self.group.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_group(&self, group: usize) {
+        self.group.store(group, Ordering::Release)
+    }
+
+    #[inline]
+    pub fn sched_entity(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.sched_entity.lock().clone()
+    }
+
+    /// Attaches the scheduling entities, or detaches them with `None` once
+    /// the task is dead, since they refer back to it.
+    #[inline]
+    pub fn set_sched_entity(&self, se: Option<Arc<dyn Any + Send + Sync>>) {
+        *self.sched_entity.lock() = se;
+    }
+
+    #[inline]
+    pub fn cpu(&self) -> usize {
+        self.cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_cpu(&self, cpu: usize) {
+        self.cpu.store(cpu, Ordering::Release)
+    }
+
+    #[inline]
+    pub fn cpus_allowed(&self) -> usize {
+        self.cpus_allowed.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_cpus_allowed(&self, mask: usize) {
+        self.cpus_allowed.store(mask, Ordering::Release)
+    }
+
+    #[inline]
+    pub fn cpu_allowed(&self, cpu: usize) -> bool {
+        (self.cpus_allowed() & (1 << cpu)) != 0
+    }
+
     pub fn try_pgd(&self) -> Option<Arc<SpinNoIrq<PageTable>>> {
         self.pgd.as_ref().and_then(|pgd| Some(pgd.clone()))
     }
@@ -258,6 +665,11 @@ impl SchedInfo {
         self.preempt_disable_count.load(Ordering::Acquire) == current_disable_count
     }
 
+    #[inline]
+    pub fn preempt_count(&self) -> usize {
+        self.preempt_disable_count.load(Ordering::Acquire)
+    }
+
     #[inline]
     pub fn disable_preempt(&self) {
         self.preempt_disable_count.fetch_add(1, Ordering::Relaxed);
@@ -362,3 +774,16 @@ pub fn init(_cpu_id: usize, _dtb_pa: usize) {
 pub fn init_thread() -> Arc<SchedInfo> {
     INIT_THREAD.clone()
 }
+
+/// Creates the idle thread of a secondary CPU from the running context.
+pub fn init_secondary(cpu_id: usize) -> CtxRef {
+    let ctx = Arc::new(SchedInfo::new());
+    ctx.set_cpu(cpu_id);
+    ctx.set_cpus_allowed(1 << cpu_id);
+
+    let ptr = Arc::into_raw(ctx.clone());
+    unsafe {
+        axhal::cpu::set_current_task_ptr(ptr);
+    }
+    ctx
+}
@@ -32,6 +32,15 @@ TESTCASES="
     bprm_loader/rt_bprm_loader
     exec/rt_exec
     macrokernel/rt_macrokernel
+    sys/rt_sys
+    signal/rt_signal
+    ext2fs/rt_ext2fs
+    axdriver/rt_axdriver
+    axfs_devfs/rt_tty
+    socket/rt_socket
+    ptrace/rt_ptrace
+    coredump/rt_coredump
+    wait_queue/rt_wait_queue
 "
 
 PASSED=0
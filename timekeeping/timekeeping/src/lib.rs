@@ -0,0 +1,197 @@
+//! Timekeeping.
+//!
+//! CLOCK_MONOTONIC is the time since boot, read from the arch timer.
+//! CLOCK_BOOTTIME and CLOCK_MONOTONIC_RAW are the same clock, as the system
+//! never suspends and the timer is never slewed. CLOCK_REALTIME is that
+//! plus the time of boot since the Epoch, which is 0 until an RTC or
+//! settimeofday sets the clock. The coarse clocks give the time of the last
+//! tick, which [`update_wall_time`] records from the timer interrupt, so
+//! reading them does not touch the timer.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{current_time_nanos, ticks_to_nanos, TimeValue};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC};
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+pub const CLOCK_MONOTONIC_RAW: usize = 4;
+pub const CLOCK_REALTIME_COARSE: usize = 5;
+pub const CLOCK_MONOTONIC_COARSE: usize = 6;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+/// Interval of the periodic tick, in nanoseconds.
+const TICK_NANOS: u64 = NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// CLOCK_REALTIME minus CLOCK_MONOTONIC, in nanoseconds.
+static BOOT_OFFSET: AtomicU64 = AtomicU64::new(0);
+/// CLOCK_MONOTONIC at the last tick, in nanoseconds.
+static LAST_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// The time, as the C library sees it: `struct timespec` of 64-bit
+/// architectures.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+impl Timespec {
+    pub fn from_nanos(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NANOS_PER_SEC) as i64,
+            tv_nsec: (ns % NANOS_PER_SEC) as i64,
+        }
+    }
+
+    pub fn from_time(t: TimeValue) -> Self {
+        Self {
+            tv_sec: t.as_secs() as i64,
+            tv_nsec: t.subsec_nanos() as i64,
+        }
+    }
+
+    /// The time it tells, or EINVAL if it is negative or not normalized.
+    pub fn to_time(self) -> LinuxResult<TimeValue> {
+        if self.tv_sec < 0 || self.tv_nsec < 0 || self.tv_nsec >= NANOS_PER_SEC as i64 {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(TimeValue::new(self.tv_sec as u64, self.tv_nsec as u32))
+    }
+}
+
+/// `struct timeval`, with microseconds.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeval {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+}
+
+impl Timeval {
+    pub fn from_nanos(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NANOS_PER_SEC) as i64,
+            tv_usec: ((ns % NANOS_PER_SEC) / NANOS_PER_MICROS) as i64,
+        }
+    }
+
+    pub fn from_time(t: TimeValue) -> Self {
+        Self::from_nanos(time_to_nanos(t))
+    }
+
+    /// The time it tells, or EINVAL if it is negative or not normalized.
+    pub fn to_time(self) -> LinuxResult<TimeValue> {
+        let usec_per_sec = (NANOS_PER_SEC / NANOS_PER_MICROS) as i64;
+        if self.tv_sec < 0 || self.tv_usec < 0 || self.tv_usec >= usec_per_sec {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(TimeValue::new(self.tv_sec as u64, self.tv_usec as u32 * NANOS_PER_MICROS as u32))
+    }
+}
+
+/// Nanoseconds of `t`, saturated as they do not fit in 64 bits after
+/// about 584 years.
+pub fn time_to_nanos(t: TimeValue) -> u64 {
+    t.as_nanos().min(u64::MAX as u128) as u64
+}
+
+/// CLOCK_MONOTONIC.
+pub fn ktime_get() -> TimeValue {
+    TimeValue::from_nanos(ktime_get_ns())
+}
+
+pub fn ktime_get_ns() -> u64 {
+    current_time_nanos()
+}
+
+/// CLOCK_REALTIME.
+pub fn ktime_get_real() -> TimeValue {
+    TimeValue::from_nanos(ktime_get_real_ns())
+}
+
+pub fn ktime_get_real_ns() -> u64 {
+    BOOT_OFFSET.load(Ordering::Acquire) + ktime_get_ns()
+}
+
+/// CLOCK_MONOTONIC_COARSE: CLOCK_MONOTONIC at the last tick.
+pub fn ktime_get_coarse() -> TimeValue {
+    match LAST_TICK.load(Ordering::Relaxed) {
+        // Before the first tick.
+        0 => ktime_get(),
+        ns => TimeValue::from_nanos(ns),
+    }
+}
+
+/// CLOCK_REALTIME_COARSE: CLOCK_REALTIME at the last tick.
+pub fn ktime_get_real_coarse() -> TimeValue {
+    monotonic_to_real(ktime_get_coarse())
+}
+
+/// The time of boot since the Epoch.
+pub fn boot_time() -> TimeValue {
+    TimeValue::from_nanos(BOOT_OFFSET.load(Ordering::Acquire))
+}
+
+/// The CLOCK_REALTIME time of the CLOCK_MONOTONIC time `t`.
+pub fn monotonic_to_real(t: TimeValue) -> TimeValue {
+    boot_time() + t
+}
+
+/// The CLOCK_MONOTONIC time of the CLOCK_REALTIME time `t`, or 0 if `t` is
+/// before boot.
+pub fn real_to_monotonic(t: TimeValue) -> TimeValue {
+    t.saturating_sub(boot_time())
+}
+
+/// Sets CLOCK_REALTIME to `t`. Fails with EINVAL if `t` is before boot,
+/// which the monotonic clock already counts since the Epoch.
+pub fn do_settimeofday(t: TimeValue) -> LinuxResult {
+    let now = ktime_get_ns();
+    let offset = time_to_nanos(t)
+        .checked_sub(now)
+        .ok_or(LinuxError::EINVAL)?;
+    BOOT_OFFSET.store(offset, Ordering::Release);
+    vdso::update_vsyscall(&BOOT_OFFSET, &LAST_TICK);
+    info!("timekeeping: realtime set to {:?}", t);
+    Ok(())
+}
+
+/// Records the time of the tick, for the coarse clocks, and publishes it
+/// to the vDSO. Called from the timer interrupt on each periodic tick.
+pub fn update_wall_time() {
+    // All CPUs tick, the latest wins.
+    LAST_TICK.fetch_max(ktime_get_ns(), Ordering::Relaxed);
+    vdso::update_vsyscall(&BOOT_OFFSET, &LAST_TICK);
+}
+
+/// Reads the clock `clockid`, of those that are the same for all tasks.
+pub fn clock_get(clockid: usize) -> LinuxResult<TimeValue> {
+    match clockid {
+        CLOCK_REALTIME => Ok(ktime_get_real()),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME => Ok(ktime_get()),
+        CLOCK_REALTIME_COARSE => Ok(ktime_get_real_coarse()),
+        CLOCK_MONOTONIC_COARSE => Ok(ktime_get_coarse()),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Resolution of the clock `clockid`.
+pub fn clock_getres(clockid: usize) -> LinuxResult<TimeValue> {
+    match clockid {
+        CLOCK_REALTIME_COARSE | CLOCK_MONOTONIC_COARSE => Ok(TimeValue::from_nanos(TICK_NANOS)),
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME
+        | CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => {
+            Ok(TimeValue::from_nanos(ticks_to_nanos(1).max(1)))
+        },
+        _ => Err(LinuxError::EINVAL),
+    }
+}
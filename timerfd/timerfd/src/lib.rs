@@ -0,0 +1,314 @@
+//! Timer file descriptors.
+//!
+//! A [`TimerFd`] is a one-shot or periodic timer behind a file. Its
+//! expirations are counted from the clock whenever it is looked at, so an
+//! armed timer costs nothing until then: read() returns the count since
+//! the last read, sleeping on an alarm of the run queue for the next
+//! expiration while there is none, and poll finds it readable once it
+//! has fired. Deadlines are kept on CLOCK_MONOTONIC: an absolute one on
+//! CLOCK_REALTIME is converted when the timer is armed, and does not follow
+//! later settings of the clock.
+
+#![cfg_attr(not(test), no_std)]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfile::fops::File;
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsResult};
+use axfs_vfs::alloc_ino;
+use axhal::time::TimeValue;
+use axio::PollState;
+use axtype::{O_CLOEXEC, O_NONBLOCK};
+use capability::Cap;
+use mutex::Mutex;
+use spinbase::SpinNoIrq;
+use wait_queue::WaitQueue;
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+pub const TFD_CLOEXEC: usize = O_CLOEXEC as usize;
+pub const TFD_NONBLOCK: usize = O_NONBLOCK as usize;
+
+pub const TFD_TIMER_ABSTIME: usize = 1;
+/// Accepted, but a timer is not canceled when the realtime clock is set.
+pub const TFD_TIMER_CANCEL_ON_SET: usize = 2;
+
+const NSEC_PER_SEC: i64 = 1_000_000_000;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct KernelTimespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+impl KernelTimespec {
+    fn to_duration(self) -> LinuxResult<Duration> {
+        if self.tv_sec < 0 || self.tv_nsec < 0 || self.tv_nsec >= NSEC_PER_SEC {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Duration::new(self.tv_sec as u64, self.tv_nsec as u32))
+    }
+
+    fn from_duration(dur: Duration) -> Self {
+        Self {
+            tv_sec: dur.as_secs() as i64,
+            tv_nsec: dur.subsec_nanos() as i64,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct ITimerSpec {
+    it_interval: KernelTimespec,
+    it_value: KernelTimespec,
+}
+
+#[derive(Default)]
+struct TimerState {
+    /// The next expiration, if armed.
+    deadline: Option<TimeValue>,
+    /// Period of a periodic timer, or zero for a one-shot one.
+    interval: Duration,
+    /// Expirations not read yet.
+    ticks: u64,
+}
+
+impl TimerState {
+    /// Counts the expirations up to `now`.
+    fn expire(&mut self, now: TimeValue) {
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        if now < deadline {
+            return;
+        }
+        if self.interval.is_zero() {
+            self.ticks += 1;
+            self.deadline = None;
+            return;
+        }
+        let interval = self.interval.as_nanos();
+        let expired = (now - deadline).as_nanos() / interval + 1;
+        self.ticks += expired as u64;
+        self.deadline = Some(Duration::from_nanos((deadline.as_nanos() + interval * expired) as u64));
+    }
+
+    fn get(&self, now: TimeValue) -> ITimerSpec {
+        ITimerSpec {
+            it_interval: KernelTimespec::from_duration(self.interval),
+            it_value: KernelTimespec::from_duration(
+                self.deadline.map_or(Duration::ZERO, |deadline| deadline - now)
+            ),
+        }
+    }
+}
+
+pub struct TimerFd {
+    clockid: usize,
+    state: SpinNoIrq<TimerState>,
+    nonblock: AtomicBool,
+    /// Readers waiting for an expiration, which also wake when the timer
+    /// is set again.
+    waiters: WaitQueue,
+    ino: usize,
+    uid: u32,
+    gid: u32,
+}
+
+impl TimerFd {
+    pub fn new(clockid: usize, flags: usize, uid: u32, gid: u32) -> Self {
+        Self {
+            clockid,
+            state: SpinNoIrq::new(TimerState::default()),
+            nonblock: AtomicBool::new((flags & TFD_NONBLOCK) != 0),
+            waiters: WaitQueue::new(),
+            ino: alloc_ino(),
+            uid,
+            gid,
+        }
+    }
+
+    /// Returns the timer `node` is, if it is one.
+    pub fn from_node(node: &VfsNodeRef) -> Option<&Self> {
+        node.as_any().downcast_ref::<Self>()
+    }
+
+    /// Switches between blocking and nonblocking mode.
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    pub fn clockid(&self) -> usize {
+        self.clockid
+    }
+
+    /// Arms the timer to expire at `value`, or after it unless `abstime`,
+    /// and then every `interval` if that is not zero. A zero `value`
+    /// disarms it. Returns the setting it had.
+    fn settime(&self, value: Duration, interval: Duration, abstime: bool) -> ITimerSpec {
+        let now = axhal::time::current_time();
+        let old = {
+            let mut state = self.state.lock();
+            state.expire(now);
+            let old = state.get(now);
+            state.ticks = 0;
+            state.interval = interval;
+            state.deadline = if value.is_zero() {
+                None
+            } else if abstime && self.clockid == CLOCK_REALTIME {
+                Some(timekeeping::real_to_monotonic(value))
+            } else if abstime {
+                Some(value)
+            } else {
+                Some(now + value)
+            };
+            old
+        };
+        self.waiters.notify_all(false);
+        old
+    }
+
+    fn gettime(&self) -> ITimerSpec {
+        let now = axhal::time::current_time();
+        let mut state = self.state.lock();
+        state.expire(now);
+        state.get(now)
+    }
+
+    /// Takes the expirations counted so far, and when the timer fires
+    /// next if there are none.
+    fn take_ticks(&self) -> (u64, Option<TimeValue>) {
+        let mut state = self.state.lock();
+        state.expire(axhal::time::current_time());
+        (core::mem::take(&mut state.ticks), state.deadline)
+    }
+
+    /// Whether a reader sleeping till `deadline` has to look again, as the
+    /// timer has fired or been set since.
+    fn changed(&self, deadline: Option<TimeValue>) -> bool {
+        let mut state = self.state.lock();
+        state.expire(axhal::time::current_time());
+        state.ticks != 0 || state.deadline != deadline
+    }
+}
+
+impl VfsNodeOps for TimerFd {
+    fn get_ino(&self) -> usize {
+        self.ino
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_file(0, 0, self.uid, self.gid, 0o600))
+    }
+
+    fn read_at(&self, _pos: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.len() < 8 {
+            return Err(AxError::InvalidInput);
+        }
+        let ctx = taskctx::current_ctx();
+        let ticks = loop {
+            let (ticks, deadline) = self.take_ticks();
+            if ticks != 0 {
+                break ticks;
+            }
+            if self.nonblock.load(Ordering::Relaxed) {
+                return Err(AxError::WouldBlock);
+            }
+            // A disarmed timer sleeps until it is set. The alarm is
+            // canceled as the wait ends.
+            if let Some(deadline) = deadline {
+                run_queue::set_alarm_wakeup(deadline, ctx.as_ctx_ref().clone());
+            }
+            self.waiters.wait_until_interruptible(|| self.changed(deadline))?;
+        };
+        buf[..8].copy_from_slice(&ticks.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn poll(&self) -> VfsResult<PollState> {
+        let mut state = self.state.lock();
+        state.expire(axhal::time::current_time());
+        Ok(PollState {
+            readable: state.ticks != 0,
+            writable: false,
+        })
+    }
+
+    fn set_nonblock(&self, nonblock: bool) -> VfsResult {
+        TimerFd::set_nonblock(self, nonblock);
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+fn timerfd_lookup(fd: usize) -> LinuxResult<VfsNodeRef> {
+    let current = task::current();
+    let file = current.filetable.lock().get_file(fd)
+        .ok_or(LinuxError::EBADF)?;
+    let node = file.lock().get_node()?;
+    if TimerFd::from_node(&node).is_none() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(node)
+}
+
+/// Creates a timer on the clock `clockid`, disarmed.
+pub fn timerfd_create(clockid: usize, flags: usize) -> LinuxResult<usize> {
+    info!("timerfd_create: clock {} flags {:#x}", clockid, flags);
+    if clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC && clockid != CLOCK_BOOTTIME {
+        return Err(LinuxError::EINVAL);
+    }
+    if (flags & !(TFD_CLOEXEC | TFD_NONBLOCK)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let current = task::current();
+    let tfd = TimerFd::new(clockid, flags, current.fsuid(), current.fsgid());
+    let mut file = File::new(Arc::new(tfd), Cap::READ);
+    file.set_status_flags((flags & TFD_NONBLOCK) as i32);
+    fileops::install_file(Arc::new(Mutex::new(file)), flags & TFD_CLOEXEC)
+}
+
+/// Arms or disarms the timer `fd` as `new_value` tells, and writes the
+/// setting it had to `old_value` if it is not null.
+pub fn timerfd_settime(fd: usize, flags: usize, new_value: usize, old_value: usize) -> LinuxResult<usize> {
+    if (flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET)) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if new_value == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    let new = unsafe { *(new_value as *const ITimerSpec) };
+    let value = new.it_value.to_duration()?;
+    let interval = new.it_interval.to_duration()?;
+    debug!("timerfd_settime: fd {} value {:?} interval {:?} flags {:#x}", fd, value, interval, flags);
+
+    let node = timerfd_lookup(fd)?;
+    let timer = TimerFd::from_node(&node).unwrap();
+    let old = timer.settime(value, interval, (flags & TFD_TIMER_ABSTIME) != 0);
+    if old_value != 0 {
+        unsafe { *(old_value as *mut ITimerSpec) = old };
+    }
+    Ok(0)
+}
+
+/// Writes how long is left until the timer `fd` fires, and its period.
+pub fn timerfd_gettime(fd: usize, curr_value: usize) -> LinuxResult<usize> {
+    if curr_value == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    let node = timerfd_lookup(fd)?;
+    let timer = TimerFd::from_node(&node).unwrap();
+    unsafe { *(curr_value as *mut ITimerSpec) = timer.gettime() };
+    Ok(0)
+}
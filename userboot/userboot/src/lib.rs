@@ -12,35 +12,123 @@ use alloc::string::String;
 use axerrno::{LinuxError, LinuxResult};
 #[cfg(target_arch = "riscv64")]
 use axhal::mem::phys_to_virt;
-use axtype::DtbInfo;
 use fork::{user_mode_thread, CloneFlags};
+use spinbase::SpinNoIrq;
+
+/// The init given with `init=`, run in place of the default one.
+static EXECUTE_COMMAND: SpinNoIrq<Option<String>> = SpinNoIrq::new(None);
+
+fn init_setup(value: &str) -> LinuxResult {
+    if value.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    *EXECUTE_COMMAND.lock() = Some(value.into());
+    Ok(())
+}
+cmdline::kernel_param!("init", init_setup);
+
+/// `loglevel=`: the level to log at, as a number as Linux takes it, or a
+/// name as `AX_LOG` does.
+fn loglevel_setup(value: &str) -> LinuxResult {
+    let level = match value {
+        "0" | "1" | "2" | "3" => "error",
+        "4" => "warn",
+        "5" | "6" => "info",
+        "7" => "debug",
+        "off" | "error" | "warn" | "info" | "debug" | "trace" => value,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    axlog2::set_max_level(level);
+    Ok(())
+}
+cmdline::kernel_param!("loglevel", loglevel_setup);
+
+fn quiet_setup(_value: &str) -> LinuxResult {
+    axlog2::set_max_level("warn");
+    Ok(())
+}
+cmdline::kernel_param!("quiet", quiet_setup);
+
+fn debug_setup(_value: &str) -> LinuxResult {
+    axlog2::set_max_level("debug");
+    Ok(())
+}
+cmdline::kernel_param!("debug", debug_setup);
 
 pub fn init(cpu_id: usize, dtb_pa: usize) {
     axconfig::init_once!();
 
     axlog2::init(option_env!("AX_LOG").unwrap_or(""));
     axhal::arch_init_early(cpu_id);
+    axhal::rtconfig::init(dtb_pa);
     axalloc::init();
     page_table::init();
+    console_init(dtb_pa);
     axhal::platform_init();
+    cmdline::parse(&bootargs(dtb_pa));
+    random::init();
     task::init(cpu_id, dtb_pa);
     fileops::init(cpu_id, dtb_pa);
 }
 
+/// Moves the console to the UART of the `stdout-path` of the device tree,
+/// mapped by ioremap, which stays mapped.
+fn console_init(dtb_pa: usize) {
+    let dtb_va = if dtb_pa != 0 {
+        axhal::mem::phys_to_virt(dtb_pa.into()).as_usize()
+    } else {
+        0
+    };
+    axhal::console::init(dtb_va, |paddr, size| {
+        page_table::ioremap::ioremap(paddr, size)
+            .ok()
+            .map(|mem| mem.vaddr().as_usize())
+    });
+}
+
+/// The bootargs of /chosen in the device tree.
+fn bootargs(_dtb_pa: usize) -> String {
+    // Todo: for x86_64, we don't know how to get cmdline
+    // from qemu arg '-append="XX"'.
+    #[allow(unused_mut)]
+    let mut bootargs = String::new();
+    #[cfg(target_arch = "riscv64")]
+    {
+        let mut cb = |name: String,
+                      _addr_cells: usize,
+                      _size_cells: usize,
+                      props: Vec<(String, Vec<u8>)>| {
+            if name == "chosen" {
+                for prop in props {
+                    if prop.0 == "bootargs" {
+                        bootargs = String::from_utf8_lossy(&prop.1).into();
+                    }
+                }
+            }
+        };
+
+        let dtb_va = phys_to_virt(_dtb_pa.into());
+        axdtb::parse(dtb_va.into(), &mut cb);
+    }
+    bootargs
+}
+
 /// start_kernel
 pub fn start(_cpu_id: usize, dtb: usize) {
-    let dtb_info = setup_arch(dtb);
-    rest_init(dtb_info);
+    setup_arch(dtb);
+    rest_init();
 }
 
-fn setup_arch(dtb: usize) -> DtbInfo {
+fn setup_arch(dtb: usize) {
     parse_dtb(dtb)
 }
 
-fn parse_dtb(_dtb_pa: usize) -> DtbInfo {
+fn parse_dtb(_dtb_pa: usize) {
     #[cfg(target_arch = "riscv64")]
     {
-        let mut dtb_info = DtbInfo::new();
+        // Sstc is used only if all the harts have it.
+        let mut harts = 0;
+        let mut sstc_harts = 0;
         let mut cb = |name: String,
                       _addr_cells: usize,
                       _size_cells: usize,
@@ -48,42 +136,46 @@ fn parse_dtb(_dtb_pa: usize) -> DtbInfo {
             if name == "chosen" {
                 for prop in props {
                     match prop.0.as_str() {
-                        "bootargs" => {
-                            if let Ok(cmd) = core::str::from_utf8(&prop.1) {
-                                parse_cmdline(cmd, &mut dtb_info);
-                            }
-                        }
+                        "rng-seed" => random::add_bootloader_randomness(&prop.1),
+                        "kaslr-seed" => random::add_device_randomness(&prop.1),
                         _ => (),
                     }
                 }
+            } else if name.starts_with("cpu@") {
+                harts += 1;
+                if props.iter().any(|prop| has_sstc(&prop.0, &prop.1)) {
+                    sstc_harts += 1;
+                }
             }
         };
 
         let dtb_va = phys_to_virt(_dtb_pa.into());
         axdtb::parse(dtb_va.into(), &mut cb);
-        dtb_info
-    }
-    #[cfg(not(target_arch = "riscv64"))]
-    {
-        DtbInfo::new()
+        if harts > 0 && sstc_harts == harts {
+            info!("timer: Sstc");
+            axhal::time::enable_sstc();
+        }
     }
 }
 
-#[allow(dead_code)]
-fn parse_cmdline(cmd: &str, dtb_info: &mut DtbInfo) {
-    let cmd = cmd.trim_end_matches(char::from(0));
-    if cmd.len() > 0 {
-        assert!(cmd.starts_with("init="));
-        let cmd = cmd.strip_prefix("init=").unwrap();
-        dtb_info.set_init_cmd(cmd);
+/// Whether the property `name` of a cpu node tells that it has Sstc: its
+/// `riscv,isa` string, whose multi-letter extensions follow underscores,
+/// or its `riscv,isa-extensions` list.
+#[cfg(target_arch = "riscv64")]
+fn has_sstc(name: &str, value: &[u8]) -> bool {
+    let strings = || value.split(|c| *c == 0 || *c == b'_');
+    match name {
+        "riscv,isa" => strings().skip(1).any(|ext| ext.eq_ignore_ascii_case(b"sstc")),
+        "riscv,isa-extensions" => strings().any(|ext| ext == b"sstc"),
+        _ => false,
     }
 }
 
-fn rest_init(dtb_info: DtbInfo) {
+fn rest_init() {
     info!("rest_init ...");
     let tid = user_mode_thread(
         move || {
-            kernel_init(dtb_info);
+            kernel_init();
         },
         CloneFlags::CLONE_FS,
     );
@@ -103,13 +195,11 @@ fn schedule_preempt_disabled() {
 }
 
 fn cpu_startup_entry() {
-    loop {
-        task::yield_now();
-    }
+    run_queue::idle_loop()
 }
 
 /// Prepare for entering first user app.
-fn kernel_init(dtb_info: DtbInfo) {
+fn kernel_init() {
     let _ = kernel_init_freeable();
 
     /*
@@ -118,14 +208,14 @@ fn kernel_init(dtb_info: DtbInfo) {
      * The Bourne shell can be used instead of init if we are
      * trying to recover a really broken machine.
      */
-    if let Some(cmd) = dtb_info.get_init_cmd() {
-        run_init_process(cmd).unwrap_or_else(|_| panic!("Requested init {} failed.", cmd));
+    let execute_command = EXECUTE_COMMAND.lock().clone();
+    if let Some(cmd) = execute_command {
+        run_init_process(&cmd).unwrap_or_else(|_| panic!("Requested init {} failed.", cmd));
         return;
     }
 
-    // Todo: for x86_64, we don't know how to get cmdline
-    // from qemu arg '-append="XX"'.
-    // Just use environment.
+    // Without init= on the command line, as on x86_64, where it isn't
+    // read yet, use the environment it was built with.
     let init_cmd = env!("AX_INIT");
     if init_cmd.len() > 0 {
         info!("init_cmd: {}", init_cmd);
@@ -150,16 +240,25 @@ fn try_to_run_init_process(init_filename: &str) -> LinuxResult {
 fn run_init_process(init_filename: &str) -> LinuxResult {
     info!("run_init_process...");
 
-    let argv_init: Vec<String> = vec![init_filename.into()];
-    let envp_init: Vec<String> = vec![
+    let mut argv_init: Vec<String> = vec![init_filename.into()];
+    argv_init.extend_from_slice(cmdline::init_argv());
+    let mut envp_init: Vec<String> = vec![
         "HOME=/".into(), "TERM=linux".into(),
     ];
+    envp_init.extend_from_slice(cmdline::init_envp());
 
     exec::kernel_execve(init_filename, argv_init, envp_init)?;
     Ok(())
 }
 
 fn kernel_init_freeable() -> LinuxResult {
+    softirq::init();
+    workqueue::init();
+    watchdog::init();
+    kdump::init();
+    fork::kernel_thread(|| axdriver::bcache::flusher_loop(), None);
+    fileops::tty_init()?;
     fileops::console_on_rootfs()?;
-    fileops::loop_init()
+    fileops::loop_init()?;
+    pm::init()
 }
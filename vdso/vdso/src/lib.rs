@@ -0,0 +1,117 @@
+//! The vDSO: a shared object mapped into every process, whose functions
+//! read the clocks without a syscall.
+//!
+//! They read the timer, and the rest from the vvar page, which the kernel
+//! keeps up to date through [`update_vsyscall`] and which is mapped right
+//! below the image, where its code finds it. The image is a complete ELF
+//! file written in assembly, with the symbols of the Linux vDSO, and the C
+//! library finds it by `AT_SYSINFO_EHDR`. Only RISC-V has one: what its
+//! functions do not serve, as `getcpu`, they pass on to the syscall.
+
+#![no_std]
+#![feature(asm_const)]
+
+use core::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use axhal::mem::{virt_to_phys, PAGE_SIZE_4K};
+use axhal::time::NANOS_PER_SEC;
+use spinbase::SpinNoIrq;
+
+/// Offsets in the vvar page, which the code of the image uses.
+const VV_SEQ: usize = 0;
+const VV_NANOS_PER_TICK: usize = 8;
+const VV_BOOT_OFFSET: usize = 16;
+const VV_LAST_TICK: usize = 24;
+const VV_COARSE_RES: usize = 32;
+
+/// What the vDSO reads, under a sequence count that is odd while it is
+/// being updated, in the layout of the `VV_*` offsets.
+#[repr(C, align(4096))]
+struct VvarPage {
+    seq: AtomicU32,
+    _pad: u32,
+    nanos_per_tick: u64,
+    /// CLOCK_REALTIME minus CLOCK_MONOTONIC, in nanoseconds.
+    boot_offset: AtomicU64,
+    /// CLOCK_MONOTONIC at the last tick, in nanoseconds.
+    last_tick: AtomicU64,
+    /// Resolution of the coarse clocks, in nanoseconds.
+    coarse_res: u64,
+}
+
+static VVAR: VvarPage = VvarPage {
+    seq: AtomicU32::new(0),
+    _pad: 0,
+    nanos_per_tick: NANOS_PER_SEC / axconfig::TIMER_FREQUENCY as u64,
+    boot_offset: AtomicU64::new(0),
+    last_tick: AtomicU64::new(0),
+    coarse_res: NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64,
+};
+
+/// Serializes the writers of [`VVAR`].
+static VVAR_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+mod image {
+    use axhal::arch::sysno::*;
+
+    core::arch::global_asm!(
+        include_str!("riscv.S"),
+        vvar_size = const axhal::mem::PAGE_SIZE_4K,
+        vv_seq = const super::VV_SEQ,
+        vv_nanos_per_tick = const super::VV_NANOS_PER_TICK,
+        vv_boot_offset = const super::VV_BOOT_OFFSET,
+        vv_last_tick = const super::VV_LAST_TICK,
+        vv_coarse_res = const super::VV_COARSE_RES,
+        nr_clock_gettime = const LINUX_SYSCALL_CLOCK_GETTIME,
+        nr_clock_getres = const LINUX_SYSCALL_CLOCK_GETRES,
+        nr_gettimeofday = const LINUX_SYSCALL_GETTIMEOFDAY,
+        nr_getcpu = const LINUX_SYSCALL_GETCPU,
+        nr_rt_sigreturn = const LINUX_SYSCALL_RT_SIGRETURN,
+    );
+
+    extern "C" {
+        fn vdso_start();
+        fn vdso_end();
+    }
+
+    pub fn image() -> Option<&'static [u8]> {
+        let start = vdso_start as usize;
+        let len = vdso_end as usize - start;
+        Some(unsafe { core::slice::from_raw_parts(start as *const u8, len) })
+    }
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+mod image {
+    pub fn image() -> Option<&'static [u8]> {
+        None
+    }
+}
+
+/// The ELF image of the vDSO, page-aligned, if the architecture has one.
+pub use image::image;
+
+/// Bytes of the vvar page, which is mapped right below the image.
+pub const VVAR_SIZE: usize = PAGE_SIZE_4K;
+
+/// Physical address of the vvar page, to be mapped read-only.
+pub fn vvar_paddr() -> usize {
+    virt_to_phys((&VVAR as *const VvarPage as usize).into()).into()
+}
+
+/// Publishes the state of the clocks to the vDSO: CLOCK_REALTIME minus
+/// CLOCK_MONOTONIC, and CLOCK_MONOTONIC at the last tick, in nanoseconds.
+/// Called by timekeeping on each tick, and when the realtime clock is set.
+/// They are read under the lock, so the last one to publish publishes the
+/// latest.
+pub fn update_vsyscall(boot_offset: &AtomicU64, last_tick: &AtomicU64) {
+    let _guard = VVAR_LOCK.lock();
+    let boot_offset = boot_offset.load(Ordering::Acquire);
+    let last_tick = last_tick.load(Ordering::Relaxed);
+    let seq = VVAR.seq.load(Ordering::Relaxed);
+    VVAR.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+    fence(Ordering::Release);
+    VVAR.boot_offset.store(boot_offset, Ordering::Relaxed);
+    VVAR.last_tick.store(last_tick, Ordering::Relaxed);
+    VVAR.seq.store(seq.wrapping_add(2), Ordering::Release);
+}
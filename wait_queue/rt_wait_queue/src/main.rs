@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use wait_queue::WaitQueue;
+
+const ROUNDS: usize = 10000;
+
+/// Whose turn it is, by the parity of the round.
+static ROUND: AtomicUsize = AtomicUsize::new(0);
+static WQS: [WaitQueue; 2] = [WaitQueue::new(), WaitQueue::new()];
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb_pa: usize) {
+    axlog2::init("info");
+    info!("[rt_wait_queue]: ...");
+
+    fork::init(cpu_id, dtb_pa);
+
+    test_ping_pong();
+
+    info!("[rt_wait_queue]: ok!");
+    axhal::misc::terminate();
+}
+
+/// Two tasks hand the turn to each other, each waiting on its own queue
+/// till it is its turn, and notifying the other's. With more CPUs, they
+/// are on different ones, where a notify may come between the check of
+/// the condition and the queueing of the waiter: a wakeup lost there
+/// would hang them both.
+fn test_ping_pong() {
+    let other_cpu = if run_queue::cpu_present(1) && run_queue::cpu_online(1) { 1 } else { 0 };
+    info!("[rt_wait_queue]: ping-pong on cpus 0 and {}", other_cpu);
+    for (me, cpu) in [(0, 0), (1, other_cpu)] {
+        fork::kernel_thread(move || {
+            player(me);
+            if me == 1 {
+                DONE.store(true, Ordering::Release);
+            }
+            run_queue::exit_current(0);
+        }, Some(cpu));
+    }
+    while !DONE.load(Ordering::Acquire) {
+        task::yield_now();
+    }
+    assert_eq!(ROUND.load(Ordering::Acquire), ROUNDS);
+    info!("[rt_wait_queue]: ping-pong ok!");
+}
+
+fn player(me: usize) {
+    for _ in 0..ROUNDS / 2 {
+        WQS[me].wait_until(|| ROUND.load(Ordering::Acquire) % 2 == me);
+        ROUND.fetch_add(1, Ordering::AcqRel);
+        WQS[1 - me].notify_one(true);
+    }
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
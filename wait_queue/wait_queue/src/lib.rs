@@ -2,10 +2,12 @@
 
 extern crate alloc;
 use alloc::collections::VecDeque;
-use spinbase::SpinRaw;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use core::time::Duration;
+use spinbase::SpinNoIrq;
 
-use taskctx::CtxRef;
-use run_queue::AxRunQueue;
+use taskctx::{CtxRef, TaskState};
 
 /// A queue to store sleeping tasks.
 ///
@@ -30,21 +32,23 @@ use run_queue::AxRunQueue;
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueue {
-    queue: SpinRaw<VecDeque<CtxRef>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    /// Taken by notifiers without a run queue lock, so IRQs are disabled
+    /// with it.
+    queue: SpinNoIrq<VecDeque<CtxRef>>,
 }
 
 impl WaitQueue {
     /// Creates an empty wait queue.
     pub const fn new() -> Self {
         Self {
-            queue: SpinRaw::new(VecDeque::new()),
+            queue: SpinNoIrq::new(VecDeque::new()),
         }
     }
 
     /// Creates an empty wait queue with space for at least `capacity` elements.
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            queue: SpinRaw::new(VecDeque::with_capacity(capacity)),
+            queue: SpinNoIrq::new(VecDeque::with_capacity(capacity)),
         }
     }
 
@@ -52,35 +56,55 @@ impl WaitQueue {
         self.queue.lock().len()
     }
 
-    /*
-    fn cancel_events(&self, curr: CurrentTask) {
+    fn cancel_events(&self, curr: &CtxRef) {
         // A task can be wake up only one events (timer or `notify()`), remove
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
-            let _guard = kernel_guard::IrqSave::new();
-            self.queue.lock().retain(|t| !curr.ptr_eq(t));
+            // Lock the run queue to disable IRQs while touching `self.queue`.
+            let _rq = run_queue::task_rq(curr).lock();
+            self.queue.lock().retain(|t| !Arc::ptr_eq(curr, t));
             curr.set_in_wait_queue(false);
         }
-        #[cfg(feature = "irq")]
         if curr.in_timer_list() {
             // timeout was set but not triggered (wake up by `WaitQueue::notify()`)
-            crate::timers::cancel_alarm(curr.as_task_ref());
+            run_queue::cancel_alarm(curr);
         }
     }
-    */
+
+    /// Queues `curr` unless `condition` is true already, which it checks
+    /// under the lock of the queue, as Linux's `prepare_to_wait` does. A
+    /// notifier makes the condition true before it takes the lock, so it
+    /// either finds the task queued, or the task sees the condition.
+    ///
+    /// Returns whether `curr` is queued, and is to block. The run queue of
+    /// `curr` is locked, so a notifier wakes it only once it is blocked.
+    fn prepare_to_wait<F>(&self, curr: &CtxRef, condition: &F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let mut wq = self.queue.lock();
+        if condition() {
+            return false;
+        }
+        curr.set_in_wait_queue(true);
+        if !wq.iter().any(|t| Arc::ptr_eq(curr, t)) {
+            wq.push_back(curr.clone());
+        }
+        true
+    }
 
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
     pub fn wait(&self) {
         let curr = taskctx::current_ctx();
         let mut rq = run_queue::task_rq(&curr).lock();
-        rq.block_current(|task| {
+        rq.block_current(TaskState::Uninterruptible, |task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
-        //self.cancel_events(crate::current());
+        drop(rq);
+        self.cancel_events(curr.as_ctx_ref());
     }
 
     /// Blocks the current task and put it into the wait queue, until the given
@@ -92,40 +116,61 @@ impl WaitQueue {
     where
         F: Fn() -> bool,
     {
+        let curr = taskctx::current_ctx();
         loop {
-            let curr = taskctx::current_ctx();
             let mut rq = run_queue::task_rq(&curr).lock();
-            if condition() {
+            if !self.prepare_to_wait(curr.as_ctx_ref(), &condition) {
+                break;
+            }
+            rq.block_current(TaskState::Uninterruptible, |_| {});
+        }
+        self.cancel_events(curr.as_ctx_ref());
+    }
+
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, or a signal comes.
+    ///
+    /// Returns `EINTR` if a signal is pending while the condition is false.
+    pub fn wait_until_interruptible<F>(&self, condition: F) -> LinuxResult
+    where
+        F: Fn() -> bool,
+    {
+        let curr = taskctx::current_ctx();
+        let mut ret = Ok(());
+        loop {
+            let mut rq = run_queue::task_rq(&curr).lock();
+            if !self.prepare_to_wait(curr.as_ctx_ref(), &condition) {
+                break;
+            }
+            // Checked under the lock signal senders take to wake us up, so
+            // a signal can't slip in before we sleep.
+            if taskctx::signal_pending(&curr) {
+                ret = Err(LinuxError::EINTR);
                 break;
             }
-            rq.block_current(|task| {
-                task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
-            });
+            rq.block_current(TaskState::Interruptible, |_| {});
         }
-        //self.cancel_events(crate::current());
+        self.cancel_events(curr.as_ctx_ref());
+        ret
     }
 
-    /*
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
-    #[cfg(feature = "irq")]
-    pub fn wait_timeout(&self, dur: core::time::Duration) -> bool {
-        let curr = crate::current();
+    ///
+    /// Returns `true` if it's woken up by the timeout.
+    pub fn wait_timeout(&self, dur: Duration) -> bool {
+        let curr = taskctx::current_ctx();
         let deadline = axhal::time::current_time() + dur;
-        debug!(
-            "task wait_timeout: {} deadline={:?}",
-            curr.id_name(),
-            deadline
-        );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
-        RUN_QUEUE.lock().block_current(|task| {
+        run_queue::set_alarm_wakeup(deadline, curr.as_ctx_ref().clone());
+
+        let mut rq = run_queue::task_rq(&curr).lock();
+        rq.block_current(TaskState::Uninterruptible, |task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
+        drop(rq);
         let timeout = curr.in_wait_queue(); // still in the wait queue, must have timed out
-        self.cancel_events(curr);
+        self.cancel_events(curr.as_ctx_ref());
         timeout
     }
 
@@ -134,102 +179,77 @@ impl WaitQueue {
     ///
     /// Note that even other tasks notify this task, it will not wake up until
     /// the above conditions are met.
-    #[cfg(feature = "irq")]
-    pub fn wait_timeout_until<F>(&self, dur: core::time::Duration, condition: F) -> bool
+    ///
+    /// Returns `true` if the condition is still false at the deadline.
+    pub fn wait_timeout_until<F>(&self, dur: Duration, condition: F) -> bool
     where
         F: Fn() -> bool,
     {
-        let curr = crate::current();
+        let curr = taskctx::current_ctx();
         let deadline = axhal::time::current_time() + dur;
-        debug!(
-            "task wait_timeout: {}, deadline={:?}",
-            curr.id_name(),
-            deadline
-        );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
+        run_queue::set_alarm_wakeup(deadline, curr.as_ctx_ref().clone());
 
         let mut timeout = true;
         while axhal::time::current_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
-            if condition() {
+            let mut rq = run_queue::task_rq(&curr).lock();
+            if !self.prepare_to_wait(curr.as_ctx_ref(), &condition) {
                 timeout = false;
                 break;
             }
-            rq.block_current(|task| {
-                task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
-            });
+            rq.block_current(TaskState::Uninterruptible, |_| {});
         }
-        self.cancel_events(curr);
+        self.cancel_events(curr.as_ctx_ref());
         timeout
     }
-    */
 
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let curr = taskctx::current_ctx();
-        let mut rq = run_queue::task_rq(&curr).lock();
-        if !self.queue.lock().is_empty() {
-            self.notify_one_locked(resched, &mut rq)
-        } else {
-            false
-        }
+        let task = {
+            let mut wq = self.queue.lock();
+            let Some(task) = wq.pop_front() else {
+                return false;
+            };
+            task.set_in_wait_queue(false);
+            task
+        };
+        unblock(task, resched);
+        true
     }
 
-    /*
     /// Wakes all tasks in the wait queue.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
-        loop {
-            let mut rq = RUN_QUEUE.lock();
-            if let Some(task) = self.queue.lock().pop_front() {
-                task.set_in_wait_queue(false);
-                rq.unblock_task(task, resched);
-            } else {
-                break;
-            }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
-        }
+        // `self.queue` is unlocked before each wakeup.
+        while self.notify_one(resched) {}
     }
 
     /// Wake up the given task in the wait queue.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
-    pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
-        let mut wq = self.queue.lock();
-        if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(wq.remove(index).unwrap(), resched);
-            true
-        } else {
-            false
-        }
-    }
-    */
-
-    pub(crate) fn notify_one_locked(&self, resched: bool, rq: &mut AxRunQueue) -> bool {
-        if let Some(task) = self.queue.lock().pop_front() {
+    pub fn notify_task(&self, resched: bool, task: &CtxRef) -> bool {
+        let task = {
+            let mut wq = self.queue.lock();
+            let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) else {
+                return false;
+            };
             task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
-            true
-        } else {
-            false
-        }
+            wq.remove(index).unwrap()
+        };
+        unblock(task, resched);
+        true
     }
+}
 
-    /*
-    pub(crate) fn notify_all_locked(&self, resched: bool, rq: &mut AxRunQueue) {
-        while let Some(task) = self.queue.lock().pop_front() {
-            task.set_in_wait_queue(false);
-            rq.unblock_task(task, resched);
-        }
-    }
-    */
+/// Wakes up `task`, taken off a wait queue, under the lock of its own run
+/// queue: the task holds it from the check of its condition till it is
+/// switched out, so it is blocked by then, whichever CPU it's on.
+fn unblock(task: CtxRef, resched: bool) {
+    let mut rq = run_queue::task_rq(&task).lock();
+    rq.unblock_task(task, resched);
 }
@@ -0,0 +1,67 @@
+//! Hung task detector: reports the tasks that have slept uninterruptibly,
+//! without being switched in once, for longer than the timeout.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use taskctx::{TaskState, Tid};
+
+/// Seconds a task may sleep uninterruptibly, or 0 for no check.
+static HUNG_TASK_TIMEOUT: AtomicU64 = AtomicU64::new(120);
+
+/// Reports still to print, against a flood of them.
+static HUNG_TASK_WARNINGS: AtomicUsize = AtomicUsize::new(10);
+
+/// Sets the seconds a task may sleep uninterruptibly, or 0 for no check.
+pub fn set_hung_task_timeout(secs: u64) {
+    HUNG_TASK_TIMEOUT.store(secs, Ordering::Release);
+}
+
+pub fn hung_task_timeout() -> u64 {
+    HUNG_TASK_TIMEOUT.load(Ordering::Acquire)
+}
+
+/// Prints the stack of `task`, switched out.
+fn show_task(task: &task::TaskRef) {
+    let fp = unsafe { (*task.sched_info.ctx_mut_ptr()).frame_pointer() };
+    backtrace::print_frames(backtrace::Frames::from_fp(fp));
+}
+
+/// Checks the tasks against the switch counts they had at the last check,
+/// `last`, which is brought up to date.
+fn check_hung_uninterruptible_tasks(last: &mut BTreeMap<Tid, usize>, timeout: u64) {
+    let tasks = task::all_tasks();
+    let mut seen = BTreeMap::new();
+    for task in tasks.iter() {
+        if !matches!(task.sched_info.state(), TaskState::Uninterruptible) {
+            continue;
+        }
+        let switches = task.sched_info.sched_stats().nr_switches;
+        seen.insert(task.tid(), switches);
+        if last.get(&task.tid()) != Some(&switches) {
+            continue;
+        }
+        if HUNG_TASK_WARNINGS.load(Ordering::Acquire) == 0 {
+            continue;
+        }
+        HUNG_TASK_WARNINGS.fetch_sub(1, Ordering::AcqRel);
+        error!("INFO: task {} blocked for more than {} seconds.", task.tid(), timeout);
+        show_task(task);
+    }
+    *last = seen;
+}
+
+/// Runs as khungtaskd: checks the tasks once per timeout.
+pub(crate) fn khungtaskd() {
+    let mut last = BTreeMap::new();
+    loop {
+        let timeout = hung_task_timeout();
+        if timeout == 0 {
+            last.clear();
+            run_queue::sleep(Duration::from_secs(120));
+            continue;
+        }
+        run_queue::sleep(Duration::from_secs(timeout));
+        check_hung_uninterruptible_tasks(&mut last, timeout);
+    }
+}
@@ -0,0 +1,46 @@
+//! Software watchdog: detects the tasks hung in uninterruptible sleep,
+//! and the CPUs locked up in the kernel, and dumps their stacks, for the
+//! hangs that would be silent otherwise.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod hung_task;
+mod lockup;
+
+use axerrno::{LinuxError, LinuxResult};
+
+pub use hung_task::{hung_task_timeout, set_hung_task_timeout};
+pub use lockup::{set_watchdog_thresh, touch_softlockup_watchdog, watchdog_thresh};
+pub use lockup::watchdog_timer_interrupt;
+
+fn hung_task_timeout_setup(value: &str) -> LinuxResult {
+    set_hung_task_timeout(value.parse().map_err(|_| LinuxError::EINVAL)?);
+    Ok(())
+}
+cmdline::kernel_param!("hung_task_timeout_secs", hung_task_timeout_setup);
+
+fn watchdog_thresh_setup(value: &str) -> LinuxResult {
+    set_watchdog_thresh(value.parse().map_err(|_| LinuxError::EINVAL)?);
+    Ok(())
+}
+cmdline::kernel_param!("watchdog_thresh", watchdog_thresh_setup);
+
+/// `nowatchdog`: no lockup detection.
+fn nowatchdog_setup(_value: &str) -> LinuxResult {
+    set_watchdog_thresh(0);
+    Ok(())
+}
+cmdline::kernel_param!("nowatchdog", nowatchdog_setup);
+
+/// Starts khungtaskd, and a watchdog thread on each CPU brought up.
+pub fn init() {
+    info!("watchdog: hung task timeout {}s, threshold {}s", hung_task_timeout(), watchdog_thresh());
+    fork::kernel_thread(hung_task::khungtaskd, None);
+    for cpu in (0..axconfig::SMP).filter(|&cpu| run_queue::cpu_present(cpu)) {
+        fork::kernel_thread(move || lockup::watchdog_thread(cpu), Some(cpu));
+    }
+}
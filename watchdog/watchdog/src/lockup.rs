@@ -0,0 +1,143 @@
+//! Lockup detector.
+//!
+//! A soft lockup is a CPU that hasn't scheduled its watchdog thread for
+//! twice the threshold: it runs kernel code with preemption off. Its own
+//! timer interrupt tells, so it shows its stack.
+//!
+//! A hard lockup is a CPU that hasn't taken a timer interrupt for the
+//! threshold: it runs with interrupts off. Another CPU tells, so this
+//! needs SMP. Even with its tick stopped, an idle CPU takes one each
+//! second.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use spinbase::SpinNoIrq;
+
+/// Seconds of the threshold, or 0 for no detection.
+static WATCHDOG_THRESH: AtomicU64 = AtomicU64::new(10);
+
+/// When the watchdog thread of each CPU has last run, in nanoseconds, or 0
+/// while it hasn't started.
+static TOUCH_TS: [AtomicU64; axconfig::SMP] = {
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+    [ZERO; axconfig::SMP]
+};
+
+static SOFT_REPORTED: [AtomicBool; axconfig::SMP] = {
+    const FALSE: AtomicBool = AtomicBool::new(false);
+    [FALSE; axconfig::SMP]
+};
+
+/// Timer interrupts taken by each CPU.
+static INTERRUPTS: [AtomicUsize; axconfig::SMP] = {
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    [ZERO; axconfig::SMP]
+};
+
+/// The counts of `INTERRUPTS` at the last hard lockup check, and when it
+/// was done.
+struct HardCheck {
+    last_ns: u64,
+    saved: [usize; axconfig::SMP],
+    reported: [bool; axconfig::SMP],
+}
+
+static HARD_CHECK: SpinNoIrq<HardCheck> = SpinNoIrq::new(HardCheck {
+    last_ns: 0,
+    saved: [0; axconfig::SMP],
+    reported: [false; axconfig::SMP],
+});
+
+/// Sets the seconds of the threshold, or 0 for no detection.
+pub fn set_watchdog_thresh(secs: u64) {
+    WATCHDOG_THRESH.store(secs, Ordering::Release);
+}
+
+pub fn watchdog_thresh() -> u64 {
+    WATCHDOG_THRESH.load(Ordering::Acquire)
+}
+
+fn this_cpu() -> usize {
+    taskctx::current_ctx().cpu()
+}
+
+/// Tells the soft lockup detector that this CPU isn't stuck, for code
+/// that keeps preemption off on purpose, as long as it knows to be.
+pub fn touch_softlockup_watchdog() {
+    let cpu = this_cpu();
+    TOUCH_TS[cpu].store(axhal::time::current_time_nanos(), Ordering::Release);
+    SOFT_REPORTED[cpu].store(false, Ordering::Release);
+}
+
+/// Runs as the watchdog thread of `cpu`, bound to it.
+pub(crate) fn watchdog_thread(cpu: usize) {
+    assert_eq!(this_cpu(), cpu);
+    loop {
+        touch_softlockup_watchdog();
+        // Five times per soft lockup threshold.
+        let period = watchdog_thresh().max(1) * 2 * axhal::time::NANOS_PER_SEC / 5;
+        run_queue::sleep(Duration::from_nanos(period));
+    }
+}
+
+fn check_softlockup(cpu: usize, now: u64, thresh: u64) {
+    let touch = TOUCH_TS[cpu].load(Ordering::Acquire);
+    if touch == 0 || SOFT_REPORTED[cpu].load(Ordering::Acquire) {
+        return;
+    }
+    let stuck = now.saturating_sub(touch) / axhal::time::NANOS_PER_SEC;
+    if stuck < thresh * 2 {
+        return;
+    }
+    SOFT_REPORTED[cpu].store(true, Ordering::Release);
+    let curr = taskctx::current_ctx();
+    error!("BUG: soft lockup - CPU#{} stuck for {}s! [task {}]", cpu, stuck, curr.tid());
+    backtrace::print_backtrace();
+}
+
+fn check_hardlockup(cpu: usize, now: u64, thresh: u64) {
+    // One CPU at a time checks the others.
+    let Some(mut check) = HARD_CHECK.try_lock() else {
+        return;
+    };
+    if now.saturating_sub(check.last_ns) < thresh * axhal::time::NANOS_PER_SEC {
+        return;
+    }
+    let first = check.last_ns == 0;
+    check.last_ns = now;
+    for other in 0..axconfig::SMP {
+        let count = INTERRUPTS[other].load(Ordering::Acquire);
+        let stuck = count == check.saved[other];
+        check.saved[other] = count;
+        if other == cpu || first || !run_queue::cpu_online(other) {
+            check.reported[other] = false;
+            continue;
+        }
+        if !stuck {
+            check.reported[other] = false;
+        } else if !check.reported[other] {
+            check.reported[other] = true;
+            let running = task::all_tasks()
+                .iter()
+                .find(|t| t.sched_info.cpu() == other && t.sched_info.is_running())
+                .map(|t| t.tid());
+            error!("Watchdog detected hard LOCKUP on cpu {} [task {:?}]", other, running);
+        }
+    }
+}
+
+/// Counts a timer interrupt of this CPU, and checks for lockups. Called
+/// on each one.
+pub fn watchdog_timer_interrupt() {
+    let cpu = this_cpu();
+    INTERRUPTS[cpu].fetch_add(1, Ordering::AcqRel);
+    let thresh = watchdog_thresh();
+    if thresh == 0 {
+        return;
+    }
+    let now = axhal::time::current_time_nanos();
+    check_softlockup(cpu, now, thresh);
+    if axconfig::SMP > 1 {
+        check_hardlockup(cpu, now, thresh);
+    }
+}
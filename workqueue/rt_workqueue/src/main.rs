@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate axlog2;
+extern crate alloc;
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static DONE: AtomicBool = AtomicBool::new(false);
+
+/// Entry
+#[no_mangle]
+pub extern "Rust" fn runtime_main(cpu_id: usize, dtb: usize) {
+    init(cpu_id, dtb);
+    start(cpu_id, dtb);
+    panic!("Never reach here!");
+}
+
+pub fn init(cpu_id: usize, dtb: usize) {
+    axlog2::init("info");
+    fork::init(cpu_id, dtb);
+    workqueue::init();
+}
+
+pub fn start(_cpu_id: usize, _dtb: usize) {
+    // The boot task is the idle task, which mustn't block in `flush`.
+    fork::kernel_thread(test_workqueue, None);
+    while !DONE.load(Ordering::Acquire) {
+        task::yield_now();
+    }
+
+    info!("[rt_workqueue]: ok!");
+    axhal::misc::terminate();
+}
+
+fn test_workqueue() {
+    for _ in 0..4 {
+        workqueue::schedule_work(|| {
+            COUNT.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    workqueue::flush_scheduled_work();
+    assert_eq!(COUNT.load(Ordering::Relaxed), 4);
+
+    let wq = workqueue::system_unbound_wq();
+    wq.queue_work(|| {
+        COUNT.fetch_add(1, Ordering::Relaxed);
+    });
+    wq.flush();
+    assert_eq!(COUNT.load(Ordering::Relaxed), 5);
+
+    let id = workqueue::schedule_delayed_work(|| {
+        panic!("cancelled work is run!");
+    }, Duration::from_secs(3600));
+    assert!(workqueue::cancel_delayed_work(id));
+    assert!(!workqueue::cancel_delayed_work(id));
+
+    DONE.store(true, Ordering::Release);
+    run_queue::exit_current(0);
+}
+
+#[panic_handler]
+pub fn panic(info: &PanicInfo) -> ! {
+    error!("{}", info);
+    arch_boot::panic(info)
+}
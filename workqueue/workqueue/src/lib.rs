@@ -0,0 +1,310 @@
+//! Workqueues: deferring work to kernel threads.
+//!
+//! Interrupt handlers and filesystems queue closures here instead of doing
+//! heavy lifting in atomic context or spawning one-off tasks. Each queue
+//! is backed by pools of worker kthreads:
+//! - a bound queue has a pool per CPU, whose worker only runs on that CPU
+//! - an unbound queue has a single pool, whose workers run anywhere
+//!
+//! Delayed work is kept on a timer list by the `kdelayd` kthread, which
+//! queues it once its deadline has passed.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axhal::time::TimeValue;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use lazy_init::LazyInit;
+use spinbase::SpinNoIrq;
+use taskctx::{TaskState, Tid};
+use wait_queue::WaitQueue;
+
+const SMP: usize = axconfig::SMP;
+
+/// A piece of deferred work.
+pub type WorkFn = Box<dyn FnOnce() + Send>;
+
+/// Handle of a queued delayed work, to cancel it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DelayedWorkId(u64);
+
+struct PoolInner {
+    pending: VecDeque<WorkFn>,
+    /// Workers blocked waiting for work.
+    idle: Vec<Tid>,
+    /// Works being run right now.
+    nr_running: usize,
+}
+
+/// Worker kthreads and the works waiting for them.
+struct WorkerPool {
+    inner: SpinNoIrq<PoolInner>,
+    /// Tasks waiting for the pool to drain, see [`WorkQueue::flush`].
+    flush_wq: WaitQueue,
+}
+
+impl WorkerPool {
+    fn new() -> Self {
+        Self {
+            inner: SpinNoIrq::new(PoolInner {
+                pending: VecDeque::new(),
+                idle: Vec::new(),
+                nr_running: 0,
+            }),
+            flush_wq: WaitQueue::new(),
+        }
+    }
+
+    fn insert(&self, work: WorkFn) {
+        let idle = {
+            let mut inner = self.inner.lock();
+            inner.pending.push_back(work);
+            inner.idle.pop()
+        };
+        if let Some(tid) = idle {
+            run_queue::wake_up_task(tid);
+        }
+    }
+
+    fn is_drained(&self) -> bool {
+        let inner = self.inner.lock();
+        inner.pending.is_empty() && inner.nr_running == 0
+    }
+
+    fn worker_loop(&self) -> ! {
+        let tid = taskctx::current_ctx().tid();
+        loop {
+            let work = {
+                let mut inner = self.inner.lock();
+                let work = inner.pending.pop_front();
+                match work {
+                    Some(_) => inner.nr_running += 1,
+                    None if !inner.idle.contains(&tid) => inner.idle.push(tid),
+                    None => {}
+                }
+                work
+            };
+            match work {
+                Some(work) => {
+                    work();
+                    let drained = {
+                        let mut inner = self.inner.lock();
+                        inner.nr_running -= 1;
+                        inner.pending.is_empty() && inner.nr_running == 0
+                    };
+                    if drained {
+                        self.flush_wq.notify_all(false);
+                    }
+                }
+                None => run_queue::block_current(TaskState::Interruptible),
+            }
+        }
+    }
+}
+
+/// A queue of deferred work, see the [module documentation](self).
+pub struct WorkQueue {
+    name: &'static str,
+    bound: bool,
+    pools: Vec<Arc<WorkerPool>>,
+}
+
+impl WorkQueue {
+    /// Creates a queue with a worker per CPU.
+    pub fn new_bound(name: &'static str) -> Arc<Self> {
+        let pools = (0..SMP)
+            .map(|cpu| spawn_pool(name, Some(cpu), 1))
+            .collect();
+        Arc::new(Self { name, bound: true, pools })
+    }
+
+    /// Creates a queue with `max_active` workers that aren't bound to any CPU.
+    pub fn new_unbound(name: &'static str, max_active: usize) -> Arc<Self> {
+        assert!(max_active > 0);
+        let pools = alloc::vec![spawn_pool(name, None, max_active)];
+        Arc::new(Self { name, bound: false, pools })
+    }
+
+    /// Returns the name of this queue.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn pool(&self, cpu: usize) -> &WorkerPool {
+        if self.bound {
+            &self.pools[cpu]
+        } else {
+            &self.pools[0]
+        }
+    }
+
+    /// Queues `f` to run on the current CPU, or on any CPU if this queue
+    /// is unbound. It can be called in interrupt context.
+    pub fn queue_work<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let cpu = taskctx::current_ctx().cpu();
+        self.queue_work_on(cpu, f);
+    }
+
+    /// Queues `f` to run on `cpu`. `cpu` is ignored if this queue is unbound.
+    pub fn queue_work_on<F>(&self, cpu: usize, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.pool(cpu).insert(Box::new(f));
+    }
+
+    /// Queues `f` on this queue after `delay` has elapsed.
+    pub fn queue_delayed_work<F>(self: &Arc<Self>, f: F, delay: Duration) -> DelayedWorkId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let cpu = taskctx::current_ctx().cpu();
+        add_delayed(self.clone(), cpu, Box::new(f), delay)
+    }
+
+    /// Waits until all works queued on this queue have finished. Must not
+    /// be called from a work of this queue.
+    pub fn flush(&self) {
+        for pool in self.pools.iter() {
+            pool.flush_wq.wait_until(|| pool.is_drained());
+        }
+    }
+}
+
+fn spawn_pool(name: &'static str, cpu: Option<usize>, nr_workers: usize) -> Arc<WorkerPool> {
+    let pool = Arc::new(WorkerPool::new());
+    for _ in 0..nr_workers {
+        let worker = pool.clone();
+        let tid = fork::kernel_thread(move || worker.worker_loop(), cpu);
+        info!("workqueue {}: worker {} on cpu {:?}", name, tid, cpu);
+    }
+    pool
+}
+
+struct DelayedWork {
+    wq: Arc<WorkQueue>,
+    cpu: usize,
+    work: WorkFn,
+}
+
+/// Delayed works ordered by deadline, with a sequence number to tell apart
+/// works of the same deadline.
+static DELAYED: SpinNoIrq<BTreeMap<(TimeValue, u64), DelayedWork>> =
+    SpinNoIrq::new(BTreeMap::new());
+static DELAYED_SEQ: AtomicU64 = AtomicU64::new(0);
+/// Tid of `kdelayd`, 0 before `init`.
+static KDELAYD: AtomicUsize = AtomicUsize::new(0);
+
+fn add_delayed(wq: Arc<WorkQueue>, cpu: usize, work: WorkFn, delay: Duration) -> DelayedWorkId {
+    let deadline = axhal::time::current_time() + delay;
+    let seq = DELAYED_SEQ.fetch_add(1, Ordering::Relaxed);
+    let first = {
+        let mut delayed = DELAYED.lock();
+        delayed.insert((deadline, seq), DelayedWork { wq, cpu, work });
+        delayed.keys().next() == Some(&(deadline, seq))
+    };
+    // Let kdelayd rearm its alarm for the new earliest deadline.
+    let tid = KDELAYD.load(Ordering::Acquire);
+    if first && tid != 0 {
+        run_queue::wake_up_task(tid);
+    }
+    DelayedWorkId(seq)
+}
+
+/// Cancels a delayed work that hasn't been queued yet.
+///
+/// Returns `false` if it has already been queued or cancelled.
+pub fn cancel_delayed_work(id: DelayedWorkId) -> bool {
+    let mut delayed = DELAYED.lock();
+    let key = delayed.keys().find(|(_, seq)| *seq == id.0).copied();
+    key.and_then(|key| delayed.remove(&key)).is_some()
+}
+
+fn kdelayd_loop() -> ! {
+    let ctx = taskctx::current_ctx();
+    loop {
+        let now = axhal::time::current_time();
+        let (expired, next) = {
+            let mut delayed = DELAYED.lock();
+            let mut expired = Vec::new();
+            while let Some(entry) = delayed.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                expired.push(entry.remove());
+            }
+            (expired, delayed.keys().next().map(|(deadline, _)| *deadline))
+        };
+        for dw in expired {
+            dw.wq.queue_work_on(dw.cpu, dw.work);
+        }
+        if let Some(deadline) = next {
+            run_queue::set_alarm_wakeup(deadline, ctx.as_ctx_ref().clone());
+        }
+        run_queue::block_current(TaskState::Interruptible);
+        run_queue::cancel_alarm(ctx.as_ctx_ref());
+    }
+}
+
+static SYSTEM_WQ: LazyInit<Arc<WorkQueue>> = LazyInit::new();
+static SYSTEM_UNBOUND_WQ: LazyInit<Arc<WorkQueue>> = LazyInit::new();
+
+/// The queue of `schedule_work`, with a worker per CPU.
+pub fn system_wq() -> &'static Arc<WorkQueue> {
+    &SYSTEM_WQ
+}
+
+/// A queue whose workers aren't bound to any CPU, for long running works.
+pub fn system_unbound_wq() -> &'static Arc<WorkQueue> {
+    &SYSTEM_UNBOUND_WQ
+}
+
+/// Queues `f` on the system queue of the current CPU.
+pub fn schedule_work<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    system_wq().queue_work(f);
+}
+
+/// Queues `f` on the system queue of `cpu`.
+pub fn schedule_work_on<F>(cpu: usize, f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    system_wq().queue_work_on(cpu, f);
+}
+
+/// Queues `f` on the system queue after `delay` has elapsed.
+pub fn schedule_delayed_work<F>(f: F, delay: Duration) -> DelayedWorkId
+where
+    F: FnOnce() + Send + 'static,
+{
+    system_wq().queue_delayed_work(f, delay)
+}
+
+/// Waits until all works on the system queue have finished.
+pub fn flush_scheduled_work() {
+    system_wq().flush();
+}
+
+/// Creates the system queues and `kdelayd`. Worker kthreads are created
+/// by the calling task, so it must be a kernel task that can fork.
+pub fn init() {
+    info!("Initialize workqueues ...");
+    SYSTEM_WQ.init_by(WorkQueue::new_bound("events"));
+    SYSTEM_UNBOUND_WQ.init_by(WorkQueue::new_unbound("events_unbound", SMP));
+    let tid = fork::kernel_thread(|| kdelayd_loop(), None);
+    KDELAYD.store(tid, Ordering::Release);
+}